@@ -152,6 +152,7 @@ impl CodeGen {
                 out,
                 "#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]"
             )?;
+            writeln!(out, "{}", cg::SERDE_DERIVE)?;
             writeln!(out, "#[repr(u32)]")?;
             writeln!(out, "pub enum {} {{", rs_typ)?;
             for item in items {
@@ -210,7 +211,7 @@ impl CodeGen {
         writeln!(out, "        unsafe {{")?;
         writeln!(
             out,
-            "            *(wire_buf.as_mut_ptr() as *mut u32) = self.resource_id();"
+            "            std::ptr::write_unaligned(wire_buf.as_mut_ptr() as *mut u32, self.resource_id());"
         )?;
         writeln!(out, "        }}")?;
         writeln!(out, "        4")?;
@@ -232,6 +233,7 @@ impl CodeGen {
         if let Some(doc) = doc {
             doc.emit(out, 1)?;
         }
+        writeln!(out, "    {}", cg::SERDE_DERIVE_TRANSPARENT)?;
         writeln!(out, "    pub struct {}: u32 {{", rs_typ)?;
         for item in items {
             if let Some(text) = &item.2 {
@@ -210,40 +210,98 @@ impl CodeGen {
         }
         writeln!(out, "}}")?;
 
+        self.emit_error_sequence(out)?;
         self.emit_resolve_wire_error(out)?;
 
         Ok(())
     }
 
+    fn emit_error_sequence<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        writeln!(out)?;
+        writeln!(out, "impl Error {{")?;
+        writeln!(
+            out,
+            "{}/// The 16 bits sequence number of the request in error.",
+            cg::ind(1)
+        )?;
+        writeln!(out, "{}pub fn sequence(&self) -> u16 {{", cg::ind(1))?;
+        writeln!(
+            out,
+            "{}// SAFETY: the error is valid for the lifetime of `self`",
+            cg::ind(2)
+        )?;
+        writeln!(out, "{}unsafe {{ (*self.as_raw()).sequence }}", cg::ind(2))?;
+        writeln!(out, "{}}}", cg::ind(1))?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{}/// The sequence number of the request in error, widened to 32 bits by libxcb.",
+            cg::ind(1)
+        )?;
+        writeln!(out, "{}pub fn full_sequence(&self) -> u32 {{", cg::ind(1))?;
+        writeln!(
+            out,
+            "{}// SAFETY: the error is valid for the lifetime of `self`",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}unsafe {{ (*self.as_raw()).full_sequence }}",
+            cg::ind(2)
+        )?;
+        writeln!(out, "{}}}", cg::ind(1))?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{}fn as_raw(&self) -> *mut xcb_generic_error_t {{",
+            cg::ind(1)
+        )?;
+        writeln!(out, "{}match self {{", cg::ind(2))?;
+        for error in &self.errors {
+            writeln!(
+                out,
+                "{}Error::{}(err) => err.as_raw(),",
+                cg::ind(3),
+                error.variant
+            )?;
+        }
+        writeln!(out, "{}}}", cg::ind(2))?;
+        writeln!(out, "{}}}", cg::ind(1))?;
+        writeln!(out, "}}")?;
+
+        Ok(())
+    }
+
     fn emit_resolve_wire_error<O: Write>(&self, out: &mut O) -> io::Result<()> {
         writeln!(out)?;
         writeln!(out, "impl base::ResolveWireError for Error {{")?;
-        writeln!(out, "{}unsafe fn resolve_wire_error(first_error: u8, raw: *mut xcb_generic_error_t) -> Self {{", cg::ind(1))?;
+        writeln!(out, "{}unsafe fn resolve_wire_error(first_error: u8, raw: *mut xcb_generic_error_t) -> Option<Self> {{", cg::ind(1))?;
         writeln!(out, "{}debug_assert!(!raw.is_null());", cg::ind(2))?;
-        writeln!(out, "{}let error_code = (*raw).error_code;", cg::ind(2))?;
-        writeln!(out, "{}match error_code - first_error {{", cg::ind(2))?;
+        writeln!(
+            out,
+            "{}// SAFETY: the caller guarantees that `raw` is an error of this extension",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}let error_code = unsafe {{ (*raw).error_code }};",
+            cg::ind(2)
+        )?;
+        writeln!(out, "{}match error_code.wrapping_sub(first_error) {{", cg::ind(2))?;
         for error in &self.errors {
             if error.number < 0 {
                 continue;
             }
             writeln!(
                 out,
-                "{}{} => Error::{}({}::from_raw(raw)),",
+                "{}{} => Some(Error::{}(unsafe {{ {}::from_raw(raw) }})),",
                 cg::ind(3),
                 error.number,
                 error.variant,
                 error.rs_typ
             )?;
         }
-        writeln!(out, "{}_ => unreachable!(", cg::ind(3))?;
-        writeln!(
-            out,
-            "{}\"Could not resolve {} Error with error_code {{}} and first_error {{}}\",",
-            cg::ind(4),
-            self.xcb_mod
-        )?;
-        writeln!(out, "{}error_code, first_error", cg::ind(4))?;
-        writeln!(out, "{}),", cg::ind(3))?;
+        writeln!(out, "{}_ => None,", cg::ind(3))?;
         writeln!(out, "{}}}", cg::ind(2))?;
         writeln!(out, "{}}}", cg::ind(1))?;
         writeln!(out, "}}")?;
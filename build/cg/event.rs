@@ -1,6 +1,6 @@
 use super::r#struct::{make_field, ResolvedFields};
 use super::{CodeGen, Event, Field, UnionVariant, UnionVariantContent, WireSz};
-use crate::cg::r#struct::{enum_mask_qualified_rs_typ, RANDR_SUBCODES};
+use crate::cg::r#struct::{enum_mask_qualified_rs_typ, SerdeCtor, RANDR_SUBCODES};
 use crate::cg::{self, Expr, StructStyle, TypeInfo};
 use crate::cg::{util, QualifiedRsTyp};
 use crate::ir;
@@ -297,8 +297,17 @@ impl CodeGen {
 
             if !event.is_xge {
                 // we enable contruction of classic events to pass to SendEvent request
-                self.emit_event_new(out, event)?;
+                self.emit_event_new(out, event, None)?;
                 writeln!(out)?;
+                // the copies are type aliases of `event`, each with its own constructor
+                for copy in self
+                    .events
+                    .iter()
+                    .filter(|ev| ev.copy_from_rs_typ.as_deref() == Some(event.rs_typ.as_str()))
+                {
+                    self.emit_event_new(out, event, Some(copy))?;
+                    writeln!(out)?;
+                }
             }
 
             writeln!(
@@ -309,6 +318,17 @@ impl CodeGen {
             writeln!(out, "}}")?;
 
             self.emit_debug_impl(out, &event.rs_typ, &event.fields)?;
+            if self.is_serde_plain_data(&event.fields) {
+                // only the classic events have a constructor
+                let ctor = if event.is_xge {
+                    SerdeCtor::None
+                } else {
+                    SerdeCtor::EventNew {
+                        number: (self.xcb_mod != "xproto").then_some(event.number),
+                    }
+                };
+                self.emit_serde_impl(out, &event.rs_typ, &event.fields, ctor)?;
+            }
 
             self.emit_wired_impl(out, &event.rs_typ, event.is_xge, raw_typ)?;
 
@@ -388,7 +408,13 @@ impl CodeGen {
         Ok(())
     }
 
-    fn emit_event_new<O: Write>(&self, out: &mut O, event: &Event) -> io::Result<()> {
+    /// Emits the constructor of `event`, or of its `copy` which shares its fields.
+    fn emit_event_new<O: Write>(
+        &self,
+        out: &mut O,
+        event: &Event,
+        copy: Option<&Event>,
+    ) -> io::Result<()> {
         // only fixed size events, with size <= 32
         assert!(
             matches!(event.wire_sz, Expr::Value(sz) if sz <= 32),
@@ -397,11 +423,35 @@ impl CodeGen {
         );
 
         let need_event_base = self.xcb_mod != "xproto";
+        let fn_name = match copy {
+            Some(copy) => format!("new_{}", util::to_snake_case(&copy.variant)),
+            None => "new".to_string(),
+        };
         let fn_decl = if need_event_base {
-            "new(event_base: u8,"
+            format!("{}(event_base: u8,", fn_name)
         } else {
-            "new("
+            format!("{}(", fn_name)
         };
+        let number = copy.unwrap_or(event).number;
+        if let Some(copy) = copy {
+            writeln!(
+                out,
+                "    /// Builds a new `{}` event, ready to be sent with `SendEvent`.",
+                copy.variant
+            )?;
+        } else {
+            writeln!(
+                out,
+                "    /// Builds a new event, ready to be sent with `SendEvent`."
+            )?;
+        }
+        if need_event_base {
+            writeln!(out, "    ///")?;
+            writeln!(
+                out,
+                "    /// `event_base` is the first event of the extension, as returned by [get_extension_data]."
+            )?;
+        }
         writeln!(out, "    pub fn {}", fn_decl)?;
         // emit parameters
         for f in &event.fields {
@@ -448,8 +498,19 @@ impl CodeGen {
             }
         }
         writeln!(out, "    ) -> {} {{", event.rs_typ)?;
+        writeln!(
+            out,
+            "{}// SAFETY: the event is written in a zeroed allocation that it owns",
+            cg::ind(2)
+        )?;
         writeln!(out, "{}unsafe {{", cg::ind(2))?;
-        writeln!(out, "{}let ptr = libc::malloc(32) as *mut u8;", cg::ind(3),)?;
+        // zeroed for the padding bytes, and with room for the `full_sequence` of libxcb
+        writeln!(
+            out,
+            "{}let ptr = libc::calloc(1, std::mem::size_of::<xcb_generic_event_t>()) as *mut u8;",
+            cg::ind(3),
+        )?;
+        writeln!(out, "{}assert!(!ptr.is_null(), \"out of memory\");", cg::ind(3))?;
         writeln!(
             out,
             "{}let wire_buf = std::slice::from_raw_parts_mut(ptr, 32);",
@@ -457,14 +518,14 @@ impl CodeGen {
         )?;
         writeln!(out, "{}let mut wire_off = 0usize;", cg::ind(3))?;
         if need_event_base {
-            let expr = if event.number == 0 {
+            let expr = if number == 0 {
                 "event_base".to_string()
             } else {
-                format!("{}u8 + event_base", event.number)
+                format!("{}u8 + event_base", number)
             };
             writeln!(out, "{}let response_type = {};", cg::ind(3), expr)?;
         } else {
-            writeln!(out, "{}let response_type = {}u8;", cg::ind(3), event.number)?;
+            writeln!(out, "{}let response_type = {}u8;", cg::ind(3), number)?;
         }
         writeln!(out, "{}let sequence = 0u16;", cg::ind(3))?;
         if event.rs_typ == "ClientMessageEvent" {
@@ -622,7 +683,12 @@ impl CodeGen {
         writeln!(out, "{}debug_assert!(!raw.is_null());", cg::ind(2))?;
         writeln!(
             out,
-            "{}let response_type = (*raw).response_type & 0x7F;",
+            "{}// SAFETY: the caller guarantees that `raw` is an event of this extension",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}let response_type = unsafe {{ (*raw).response_type }} & 0x7F;",
             cg::ind(2)
         )?;
         writeln!(
@@ -642,7 +708,12 @@ impl CodeGen {
                 cg::ind(2)
             )?;
             writeln!(out, "{}let ptr = raw as *const u8;", cg::ind(2))?;
-            writeln!(out, "{}let xkb_type = *(ptr.add(1));", cg::ind(2))?;
+            writeln!(
+                out,
+                "{}// SAFETY: events are at least 32 bytes long",
+                cg::ind(2)
+            )?;
+            writeln!(out, "{}let xkb_type = unsafe {{ *(ptr.add(1)) }};", cg::ind(2))?;
             writeln!(out, "{}match xkb_type {{", cg::ind(2))?;
         } else {
             writeln!(out, "{}match response_type - first_event {{", cg::ind(2))?;
@@ -654,7 +725,7 @@ impl CodeGen {
 
             writeln!(
                 out,
-                "{}{} => Some(Event::{}({}::from_raw(raw))),",
+                "{}{} => Some(Event::{}(unsafe {{ {}::from_raw(raw) }})),",
                 cg::ind(3),
                 event.number,
                 event.variant,
@@ -679,10 +750,19 @@ impl CodeGen {
         writeln!(out, "{}debug_assert!(!raw.is_null());", cg::ind(2))?;
         writeln!(
             out,
-            "{}debug_assert!(((*raw).response_type & 0x7F) == XCB_GE_GENERIC);",
+            "{}// SAFETY: the caller guarantees that `raw` is a GE event of this extension",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}debug_assert!(unsafe {{ (*raw).response_type }} & 0x7F == XCB_GE_GENERIC);",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}let event_type = unsafe {{ (*raw).event_type }};",
             cg::ind(2)
         )?;
-        writeln!(out, "{}let event_type = (*raw).event_type;", cg::ind(2))?;
         writeln!(out, "{}match event_type {{", cg::ind(2))?;
         for event in &self.events {
             if !event.is_xge {
@@ -691,7 +771,7 @@ impl CodeGen {
 
             writeln!(
                 out,
-                "{}{} => Event::{}({}::from_raw(raw)),",
+                "{}{} => Event::{}(unsafe {{ {}::from_raw(raw) }}),",
                 cg::ind(3),
                 event.number,
                 event.variant,
@@ -766,7 +846,12 @@ impl CodeGen {
         if is_xge {
             writeln!(
                 out,
-                "{}32 + 4 * (*(ptr.add(4) as *const u32) as usize)",
+                "{}// SAFETY: the caller guarantees that `ptr` points to a GE event",
+                cg::ind(2)
+            )?;
+            writeln!(
+                out,
+                "{}32 + 4 * (unsafe {{ (ptr.add(4) as *const u32).read_unaligned() }} as usize)",
                 cg::ind(2)
             )?;
         } else {
@@ -782,21 +867,33 @@ impl CodeGen {
         )?;
         writeln!(
             out,
-            "{}let sz = Self::compute_wire_len(ptr, ());",
+            "{}// SAFETY: the caller guarantees that `ptr` points to an event of this type",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}let sz = unsafe {{ Self::compute_wire_len(ptr, ()) }};",
             cg::ind(2)
         )?;
         writeln!(out, "{}*offset += sz;", cg::ind(2))?;
+        // zeroed, and with room for the `full_sequence` of libxcb
         writeln!(
             out,
-            "{}let raw = libc::malloc(sz) as *mut {};",
+            "{}let raw = unsafe {{ libc::calloc(1, sz.max(std::mem::size_of::<{}>())) }} as *mut {};",
             cg::ind(2),
+            raw_typ,
             raw_typ
         )?;
+        writeln!(out, "{}assert!(!raw.is_null(), \"out of memory\");", cg::ind(2))?;
         writeln!(
             out,
-            "{}std::ptr::copy(ptr as *const {}, raw, sz);",
-            cg::ind(2),
-            raw_typ
+            "{}// SAFETY: `raw` is freshly allocated with at least `sz` bytes",
+            cg::ind(2)
+        )?;
+        writeln!(
+            out,
+            "{}unsafe {{ std::ptr::copy(ptr, raw as *mut u8, sz) }};",
+            cg::ind(2)
         )?;
         writeln!(out, "{}{} {{ raw }}", cg::ind(2), rs_typ)?;
         writeln!(out, "{}}}", cg::ind(1))?;
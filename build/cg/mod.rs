@@ -560,7 +560,7 @@ impl CodeGen {
             writeln!(out, "    unsafe {{")?;
             writeln!(
                 out,
-                "        xcb_prefetch_extension_data(conn.get_raw_conn(), &mut FFI_EXT as *mut _);"
+                "        xcb_prefetch_extension_data(conn.get_raw_conn(), std::ptr::addr_of_mut!(FFI_EXT));"
             )?;
             writeln!(out, "    }}")?;
             writeln!(out, "}}")?;
@@ -588,7 +588,7 @@ impl CodeGen {
             writeln!(out, "    unsafe {{")?;
             writeln!(
                 out,
-                "        let reply = xcb_get_extension_data(conn.get_raw_conn(), &mut FFI_EXT as *mut _);"
+                "        let reply = xcb_get_extension_data(conn.get_raw_conn(), std::ptr::addr_of_mut!(FFI_EXT));"
             )?;
             writeln!(
                 out,
@@ -1030,6 +1030,13 @@ fn mask_exceptions() -> Vec<RsTypException> {
     ]
 }
 
+/// The derives of the `serde` cargo feature, for the types that are plain data.
+const SERDE_DERIVE: &str =
+    "#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]";
+
+/// As [SERDE_DERIVE], for the types that wrap a single integer, serialized as the integer.
+const SERDE_DERIVE_TRANSPARENT: &str = "#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize), serde(transparent))]";
+
 fn ind(level: u32) -> &'static str {
     match level {
         0 => "",
@@ -1043,6 +1050,28 @@ fn ind(level: u32) -> &'static str {
     }
 }
 
+/// Emits `body`, the statements of an `unsafe fn` indented at `level`, in an `unsafe` block
+/// justified by `safety`.
+///
+/// The unsafe operations of the generated bodies all read at an offset of the wire data,
+/// bodies without offset are emitted as is.
+fn emit_unsafe_body<O: Write>(out: &mut O, level: u32, safety: &str, body: &[u8]) -> io::Result<()> {
+    let body = std::str::from_utf8(body).expect("generated code is UTF-8");
+    if !body.contains(".add(") {
+        return out.write_all(body.as_bytes());
+    }
+    writeln!(out, "{}// SAFETY: {}", ind(level), safety)?;
+    writeln!(out, "{}unsafe {{", ind(level))?;
+    for line in body.lines() {
+        if line.is_empty() {
+            writeln!(out)?;
+        } else {
+            writeln!(out, "    {}", line)?;
+        }
+    }
+    writeln!(out, "{}}}", ind(level))
+}
+
 fn rust_type_name(typ: &str) -> String {
     util::tit_cap(typ)
 }
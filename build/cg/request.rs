@@ -324,18 +324,19 @@ impl CodeGen {
         writeln!(out, "        (32 + self.length() * 4) as _")?;
         writeln!(out, "    }}")?;
 
-        for f in &reply.fields {
-            if let Field::Field {
-                struct_style: Some(StructStyle::DynBuf),
-                ..
-            }
-            | Field::List { .. } = f
-            {
-                let len_stmts =
-                    self.emit_compute_offset_and_get_stmts(out, reply_rs_typ, &reply.fields, None)?;
-                self.emit_compute_func(out, "compute_len", None, &len_stmts)?;
-                break;
-            }
+        let has_compute_len = reply.fields.iter().any(|f| {
+            matches!(
+                f,
+                Field::Field {
+                    struct_style: Some(StructStyle::DynBuf),
+                    ..
+                } | Field::List { .. }
+            )
+        });
+        if has_compute_len {
+            let len_stmts =
+                self.emit_compute_offset_and_get_stmts(out, reply_rs_typ, &reply.fields, None)?;
+            self.emit_compute_func(out, "compute_len", None, &len_stmts)?;
         }
         self.emit_struct_accessors(out, reply_rs_typ, &reply.fields)?;
 
@@ -346,7 +347,24 @@ impl CodeGen {
         writeln!(out)?;
         writeln!(out, "impl base::Reply for {} {{", reply_rs_typ)?;
         writeln!(out, "    unsafe fn from_raw(raw: *const u8) -> Self {{")?;
-        writeln!(out, "        Self {{ raw }}")?;
+        if has_compute_len {
+            // a corrupted count fails here rather than in an accessor reading past the reply
+            writeln!(out, "        let reply = Self {{ raw }};")?;
+            writeln!(out, "        debug_assert!(")?;
+            writeln!(
+                out,
+                "            unsafe {{ Self::compute_len(raw, ()) }} <= reply.wire_len(),"
+            )?;
+            writeln!(
+                out,
+                "            \"the lists of {}::{} overflow its length\",",
+                self.xcb_mod, reply_rs_typ
+            )?;
+            writeln!(out, "        );")?;
+            writeln!(out, "        reply")?;
+        } else {
+            writeln!(out, "        Self {{ raw }}")?;
+        }
         writeln!(out, "    }}")?;
         writeln!(out)?;
         writeln!(out, "    unsafe fn into_raw(self) -> *const u8 {{")?;
@@ -365,6 +383,36 @@ impl CodeGen {
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
 
+        // the file descriptors stored after the wire body are owned by a single reply
+        let has_fds = reply.fields.iter().any(|f| match f {
+            Field::Field { rs_typ, .. } | Field::List { rs_typ, .. } => rs_typ == "RawFd",
+            _ => false,
+        });
+        if !has_fds {
+            writeln!(out)?;
+            writeln!(out, "impl Clone for {} {{", reply_rs_typ)?;
+            writeln!(out, "    fn clone(&self) -> Self {{")?;
+            writeln!(out, "        let len = self.wire_len();")?;
+            writeln!(
+                out,
+                "        // SAFETY: the wire data of `self` is copied to a fresh allocation owned by the clone"
+            )?;
+            writeln!(out, "        unsafe {{")?;
+            writeln!(
+                out,
+                "            let raw = libc::malloc(len) as *mut u8;"
+            )?;
+            writeln!(out, "            assert!(!raw.is_null(), \"out of memory\");")?;
+            writeln!(
+                out,
+                "            std::ptr::copy_nonoverlapping(self.raw, raw, len);"
+            )?;
+            writeln!(out, "            Self {{ raw }}")?;
+            writeln!(out, "        }}")?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+        }
+
         writeln!(out)?;
         writeln!(
             out,
@@ -387,11 +435,15 @@ impl CodeGen {
         )?;
         writeln!(
             out,
-            "/// with [Connection::wait_for_reply](crate::Connection::wait_for_reply)"
+            "/// with [Connection::wait_for_reply](crate::Connection::wait_for_reply)."
+        )?;
+        writeln!(
+            out,
+            "/// The request is checked: its error is returned by `wait_for_reply`."
         )?;
         writeln!(out, "#[derive(Debug)]")?;
         writeln!(out, "pub struct {} {{", cookie_rs_typ)?;
-        writeln!(out, "    seq: u64,")?;
+        writeln!(out, "    seq: base::SequenceNumber,")?;
         writeln!(out, "}}")?;
 
         writeln!(out)?;
@@ -403,18 +455,26 @@ impl CodeGen {
             "/// This cookie can be used to get a [{}]",
             reply_rs_typ
         )?;
-        writeln!(out, "/// with [Connection::wait_for_reply_unchecked](crate::Connection::wait_for_reply_unchecked)")?;
+        writeln!(out, "/// with [Connection::wait_for_reply_unchecked](crate::Connection::wait_for_reply_unchecked).")?;
+        writeln!(
+            out,
+            "/// The request is unchecked: its error goes to the event loop, and"
+        )?;
+        writeln!(out, "/// `wait_for_reply_unchecked` returns `None`.")?;
         writeln!(out, "pub struct {}Unchecked {{", cookie_rs_typ)?;
-        writeln!(out, "    seq: u64,")?;
+        writeln!(out, "    seq: base::SequenceNumber,")?;
         writeln!(out, "}}")?;
 
         writeln!(out)?;
         writeln!(out, "impl base::Cookie for {} {{", cookie_rs_typ)?;
-        writeln!(out, "    unsafe fn from_sequence(seq: u64) -> Self {{")?;
+        writeln!(
+            out,
+            "    unsafe fn from_sequence(seq: base::SequenceNumber) -> Self {{"
+        )?;
         writeln!(out, "        {} {{ seq }}", cookie_rs_typ)?;
         writeln!(out, "    }}")?;
         writeln!(out)?;
-        writeln!(out, "    fn sequence(&self) -> u64 {{")?;
+        writeln!(out, "    fn sequence(&self) -> base::SequenceNumber {{")?;
         writeln!(out, "        self.seq")?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
@@ -438,11 +498,14 @@ impl CodeGen {
 
         writeln!(out)?;
         writeln!(out, "impl base::Cookie for {}Unchecked {{", cookie_rs_typ)?;
-        writeln!(out, "    unsafe fn from_sequence(seq: u64) -> Self {{")?;
+        writeln!(
+            out,
+            "    unsafe fn from_sequence(seq: base::SequenceNumber) -> Self {{"
+        )?;
         writeln!(out, "        {}Unchecked {{ seq }}", cookie_rs_typ)?;
         writeln!(out, "    }}")?;
         writeln!(out)?;
-        writeln!(out, "    fn sequence(&self) -> u64 {{")?;
+        writeln!(out, "    fn sequence(&self) -> base::SequenceNumber {{")?;
         writeln!(out, "        self.seq")?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
@@ -482,7 +545,7 @@ impl CodeGen {
                     )?;
                     writeln!(
                         out,
-                        "{}*(self.wire_ptr().add(self.wire_len()) as *const RawFd)",
+                        "{}(self.wire_ptr().add(self.wire_len()) as *const RawFd).read_unaligned()",
                         cg::ind(3)
                     )?;
                     writeln!(out, "{}}}", cg::ind(2))?;
@@ -662,11 +725,14 @@ impl CodeGen {
                     name,
                     module,
                     rs_typ,
+                    mask,
                     is_prop,
                     doc,
                     ..
                 } => {
-                    let q_rs_typ = (module, rs_typ).qualified_rs_typ();
+                    // a list of mask values is a list of the mask type, laid out as its bits
+                    let q_rs_typ =
+                        r#struct::enum_mask_qualified_rs_typ(module, rs_typ, &None, mask);
                     let typ = if *is_prop { "P" } else { &q_rs_typ };
                     if let Some(doc) = doc {
                         doc.emit(out, 1)?;
@@ -707,6 +773,7 @@ impl CodeGen {
         sends_event: bool,
     ) -> io::Result<()> {
         self.emit_raw_request(out, rs_typ, params, info, sends_event)?;
+        self.emit_request_len(out, rs_typ, info, sends_event)?;
 
         let (generic_decl, generic_use) = self.generic_decl_and_use(sends_event, info);
 
@@ -805,7 +872,7 @@ impl CodeGen {
         if self.xcb_mod == "xproto" {
             writeln!(out, "{}ext: std::ptr::null_mut(),", cg::ind(3))?;
         } else {
-            writeln!(out, "{}ext: (&mut FFI_EXT) as *mut _,", cg::ind(3))?;
+            writeln!(out, "{}ext: std::ptr::addr_of_mut!(FFI_EXT),", cg::ind(3))?;
         }
         writeln!(out, "{}opcode: {},", cg::ind(3), opcode)?;
         writeln!(
@@ -899,6 +966,105 @@ impl CodeGen {
         Ok(())
     }
 
+    /// Emits the `request_len` method of the requests with variable-length sections.
+    fn emit_request_len<O: Write>(
+        &self,
+        out: &mut O,
+        rs_typ: &str,
+        info: &RequestInfo,
+        sends_event: bool,
+    ) -> io::Result<()> {
+        if !info
+            .sections
+            .iter()
+            .any(|s| matches!(s, SerializeSection::Var(_)))
+        {
+            return Ok(());
+        }
+        let (generic_decl, generic_use) = self.generic_decl_and_use(sends_event, info);
+
+        writeln!(out)?;
+        writeln!(out, "impl{} {}{} {{", generic_decl, rs_typ, generic_use)?;
+        writeln!(
+            out,
+            "    /// The length of the request on the wire, in bytes."
+        )?;
+        writeln!(out, "    ///")?;
+        writeln!(
+            out,
+            "    /// See [Connection::fits_in_request](crate::Connection::fits_in_request)."
+        )?;
+        writeln!(out, "    pub fn request_len(&self) -> usize {{")?;
+        writeln!(out, "{}let mut len = 0usize;", cg::ind(2))?;
+        for (num, sect) in info.sections.iter().enumerate() {
+            let sect_len = match sect {
+                SerializeSection::Fixed(fields) => {
+                    format!("{}", self.request_fixed_fields_size(fields))
+                }
+                SerializeSection::Var(field) => self.req_var_len_expr(field),
+            };
+            writeln!(out, "{}let len{} = {};", cg::ind(2), num, sect_len)?;
+            writeln!(
+                out,
+                "{}len += len{} + base::align_pad(len{}, 4);",
+                cg::ind(2),
+                num,
+                num
+            )?;
+        }
+        writeln!(out, "{}len", cg::ind(2))?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    /// The expression of the length of a variable section, as serialized by
+    /// `emit_req_var_buf`.
+    fn req_var_len_expr(&self, field: &Field) -> String {
+        match field {
+            Field::Field {
+                name,
+                struct_style: Some(StructStyle::DynBuf),
+                ..
+            } => format!("self.{}.wire_len()", name),
+            Field::List {
+                name,
+                struct_style: Some(StructStyle::DynBuf),
+                ..
+            }
+            | Field::List {
+                name,
+                is_union: true,
+                ..
+            } => format!(
+                "self.{}.iter().map(|el| el.wire_len()).sum::<usize>()",
+                name
+            ),
+            Field::List { name, rs_typ, .. } if rs_typ == "char" => {
+                format!("self.{}.len()", name)
+            }
+            Field::List {
+                name,
+                module,
+                rs_typ,
+                mask,
+                is_prop,
+                ..
+            } => {
+                let typ_sz: Cow<str> = if *is_prop {
+                    Cow::Borrowed("P")
+                } else {
+                    Cow::Owned(r#struct::enum_mask_qualified_rs_typ(
+                        module, rs_typ, &None, mask,
+                    ))
+                };
+                format!("self.{}.len() * std::mem::size_of::<{}>()", name, typ_sz)
+            }
+            Field::Switch { name, .. } => format!("self.{}.wire_len()", name),
+            _ => unreachable!("{:#?}", field),
+        }
+    }
+
     fn emit_req_assertions<O: Write>(
         &self,
         out: &mut O,
@@ -1243,6 +1409,7 @@ impl CodeGen {
                 name,
                 module,
                 rs_typ,
+                mask,
                 is_prop,
                 ..
             } => {
@@ -1256,7 +1423,9 @@ impl CodeGen {
                 let typ_sz: Cow<str> = if *is_prop {
                     Cow::Borrowed("P")
                 } else {
-                    Cow::Owned((module, rs_typ).qualified_rs_typ())
+                    Cow::Owned(r#struct::enum_mask_qualified_rs_typ(
+                        module, rs_typ, &None, mask,
+                    ))
                 };
                 writeln!(
                     out,
@@ -3,15 +3,19 @@ use crate::cg::request::{field_is_fd, fieldref_get_value, request_fieldref_emitt
 use crate::cg::util;
 use crate::ir;
 
-use super::UnionTypeField;
 use super::{
     doc::DocField, CodeGen, Doc, Expr, Field, HasWireLayout, QualifiedRsTyp, RsTyp, StructStyle,
     TypeInfo, WireSz,
 };
+use super::{union, UnionTypeField};
 
 use std::borrow::Cow;
 use std::io::{self, Write};
 
+/// Justification of the `unsafe` blocks of the field accessors.
+const ACCESSOR_SAFETY: &str =
+    "the field is within the wire data of `self`, which is valid for the lifetime of `self`";
+
 /// struct of external parameters passed to functions such as compute_len
 /// This is very often `()` for which `None` is used, but sometimes an field external to struct is needed
 /// to compute the len of a list (i.e. <paramref> element types)
@@ -578,6 +582,18 @@ impl CodeGen {
             doc.emit(out, 0)?;
         }
         writeln!(out, "#[derive(Copy, Clone, Debug)]")?;
+        // serde implements its traits for the arrays of up to 32 elements
+        let serde_arrays = fields.iter().all(|f| match f {
+            Field::List {
+                rs_typ,
+                len_expr: Expr::Value(len),
+                ..
+            } => rs_typ == "char" || *len <= 32,
+            _ => true,
+        });
+        if serde_arrays {
+            writeln!(out, "{}", cg::SERDE_DERIVE)?;
+        }
         writeln!(out, "#[repr(C)]")?;
         writeln!(out, "pub struct {} {{", rs_typ)?;
         for f in fields {
@@ -634,6 +650,20 @@ impl CodeGen {
         }
         writeln!(out, "}}")?;
 
+        // lists of this struct are read in place in the wire data,
+        // which is aligned on 4 bytes
+        writeln!(out)?;
+        writeln!(
+            out,
+            "const _: () = assert!(std::mem::size_of::<{}>() == {});",
+            rs_typ, wire_sz
+        )?;
+        writeln!(
+            out,
+            "const _: () = assert!(std::mem::align_of::<{}>() <= 4);",
+            rs_typ
+        )?;
+
         self.emit_sizeof_test(out, rs_typ, wire_sz)?;
 
         writeln!(out)?;
@@ -651,7 +681,7 @@ impl CodeGen {
             rs_typ, wire_sz
         )?;
         writeln!(out, "        }};")?;
-        writeln!(out, "        wire_buf.copy_from_slice(me);")?;
+        writeln!(out, "        wire_buf[..{}].copy_from_slice(me);", wire_sz)?;
         writeln!(out, "        {}", wire_sz)?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
@@ -669,7 +699,16 @@ impl CodeGen {
             "    unsafe fn unserialize(ptr: *const u8, _params: (), offset: &mut usize) -> Self {{"
         )?;
         writeln!(out, "        *offset += {};", wire_sz)?;
-        writeln!(out, "        *(ptr as *const {})", rs_typ)?;
+        writeln!(
+            out,
+            "        // SAFETY: the caller guarantees that `ptr` points to {} readable bytes",
+            wire_sz
+        )?;
+        writeln!(
+            out,
+            "        unsafe {{ std::ptr::read_unaligned(ptr as *const {}) }}",
+            rs_typ
+        )?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
 
@@ -708,7 +747,12 @@ impl CodeGen {
         )?;
         writeln!(
             out,
-            "        &*(data.as_ref() as *const [u8] as *const {})",
+            "        // SAFETY: the struct is a transparent wrapper of its bytes, the caller"
+        )?;
+        writeln!(out, "        // guarantees that they are a valid wire representation")?;
+        writeln!(
+            out,
+            "        unsafe {{ &*(data.as_ref() as *const [u8] as *const {}) }}",
             rs_typ
         )?;
         writeln!(out, "    }}")?;
@@ -756,12 +800,33 @@ impl CodeGen {
             "    unsafe fn unserialize(ptr: *const u8, _params: (), offset: &mut usize) -> Self {{"
         )?;
         writeln!(out, "        *offset += {};", wire_sz)?;
-        writeln!(out, "        *(ptr as *const {})", rs_typ)?;
+        writeln!(
+            out,
+            "        // SAFETY: the caller guarantees that `ptr` points to {} readable bytes",
+            wire_sz
+        )?;
+        writeln!(
+            out,
+            "        unsafe {{ std::ptr::read_unaligned(ptr as *const {}) }}",
+            rs_typ
+        )?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
 
         self.emit_debug_impl(out, rs_typ, fields)?;
 
+        // the constructor takes references to the lists, omits the fields referred to,
+        // and is not emitted for the padding only structs
+        let ctor_params_owned = fields.iter().all(|f| match f {
+            Field::Field { is_fieldref, .. } => !is_fieldref,
+            Field::List { .. } => false,
+            _ => true,
+        });
+        let has_ctor = fields.iter().any(|f| !matches!(f, Field::Pad { .. }));
+        if ctor_params_owned && has_ctor && self.is_serde_plain_data(fields) {
+            self.emit_serde_impl(out, rs_typ, fields, SerdeCtor::New)?;
+        }
+
         Ok(())
     }
 
@@ -795,13 +860,18 @@ impl CodeGen {
         if params_struct.is_none() {
             writeln!(
                 out,
-                "        debug_assert_eq!(data.as_ref().len(), <&{} as base::WiredIn>::compute_wire_len(data.as_ref().as_ptr(), ()));",
+                "        debug_assert_eq!(data.as_ref().len(), unsafe {{ <&{} as base::WiredIn>::compute_wire_len(data.as_ref().as_ptr(), ()) }});",
                 rs_typ
             )?;
         }
         writeln!(
             out,
-            "        &*(data.as_ref() as *const [u8] as *const {})",
+            "        // SAFETY: the struct is a transparent wrapper of its bytes, the caller"
+        )?;
+        writeln!(out, "        // guarantees that they are a valid wire representation")?;
+        writeln!(
+            out,
+            "        unsafe {{ &*(data.as_ref() as *const [u8] as *const {}) }}",
             rs_typ
         )?;
         writeln!(out, "    }}")?;
@@ -845,13 +915,19 @@ impl CodeGen {
             "    unsafe fn unserialize(ptr: *const u8, params: {}, offset: &mut usize) -> Self {{",
             params_struct.rs_typ(),
         )?;
-        writeln!(out, "        let sz = Self::compute_wire_len(ptr, params);")?;
-        writeln!(out, "        *offset += sz;")?;
         writeln!(
             out,
-            "        let data = std::slice::from_raw_parts(ptr, sz);"
+            "        // SAFETY: the caller guarantees that `ptr` points to a valid wire representation"
         )?;
-        writeln!(out, "        {}::from_data(data)", rs_typ)?;
+        writeln!(out, "        unsafe {{")?;
+        writeln!(out, "            let sz = Self::compute_wire_len(ptr, params);")?;
+        writeln!(out, "            *offset += sz;")?;
+        writeln!(
+            out,
+            "            let data = std::slice::from_raw_parts(ptr, sz);"
+        )?;
+        writeln!(out, "            {}::from_data(data)", rs_typ)?;
+        writeln!(out, "        }}")?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
 
@@ -871,7 +947,7 @@ impl CodeGen {
         if params_struct.is_none() {
             writeln!(
                 out,
-                "        debug_assert_eq!(<&{}>::compute_wire_len(data.as_ptr(), ()), data.len());",
+                "        debug_assert_eq!(unsafe {{ <&{}>::compute_wire_len(data.as_ptr(), ()) }}, data.len());",
                 rs_typ
             )?;
         }
@@ -890,20 +966,31 @@ impl CodeGen {
             "    unsafe fn compute_wire_len(ptr: *const u8, params: {}) -> usize {{",
             params_struct.rs_typ(),
         )?;
-        writeln!(out, "    <&{}>::compute_wire_len(ptr, params)", rs_typ)?;
-        writeln!(out, "}}")?;
+        writeln!(out, "        // SAFETY: forwarded from the caller")?;
+        writeln!(
+            out,
+            "        unsafe {{ <&{}>::compute_wire_len(ptr, params) }}",
+            rs_typ
+        )?;
+        writeln!(out, "    }}")?;
         writeln!(
             out,
             "    unsafe fn unserialize(ptr: *const u8, params: {}, offset: &mut usize) -> Self {{",
             params_struct.rs_typ(),
         )?;
-        writeln!(out, "        let sz = Self::compute_wire_len(ptr, params);")?;
-        writeln!(out, "        *offset += sz;")?;
         writeln!(
             out,
-            "        let data = std::slice::from_raw_parts(ptr, sz);"
+            "        // SAFETY: the caller guarantees that `ptr` points to a valid wire representation"
         )?;
-        writeln!(out, "        {}Buf::from_data(data.to_vec())", rs_typ)?;
+        writeln!(out, "        unsafe {{")?;
+        writeln!(out, "            let sz = Self::compute_wire_len(ptr, params);")?;
+        writeln!(out, "            *offset += sz;")?;
+        writeln!(
+            out,
+            "            let data = std::slice::from_raw_parts(ptr, sz);"
+        )?;
+        writeln!(out, "            {}Buf::from_data(data.to_vec())", rs_typ)?;
+        writeln!(out, "        }}")?;
         writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
 
@@ -997,7 +1084,7 @@ impl CodeGen {
 
                 if *is_fieldref || *is_paramref {
                     stmts.push(format!(
-                        "let {} = *(ptr.add({}sz) as *const {});",
+                        "let {} = (ptr.add({}sz) as *const {}).read_unaligned();",
                         name, struct_offset, rs_typ
                     ));
                 }
@@ -1151,10 +1238,23 @@ impl CodeGen {
             }
             writeln!(out, "        }} = params;")?;
         }
-        for s in stmts {
-            writeln!(out, "        {}", s)?;
+        if stmts.iter().any(|s| s.contains("ptr")) {
+            writeln!(
+                out,
+                "        // SAFETY: the caller guarantees that `ptr` points to a valid wire representation"
+            )?;
+            writeln!(out, "        unsafe {{")?;
+            for s in stmts {
+                writeln!(out, "            {}", s)?;
+            }
+            writeln!(out, "            sz")?;
+            writeln!(out, "        }}")?;
+        } else {
+            for s in stmts {
+                writeln!(out, "        {}", s)?;
+            }
+            writeln!(out, "        sz")?;
         }
-        writeln!(out, "        sz")?;
         writeln!(out, "    }}")?;
         Ok(())
     }
@@ -1574,6 +1674,7 @@ impl CodeGen {
                         doc.emit(out, 1)?;
                     }
                     writeln!(out, "    pub fn {}(&self) -> bool {{", name)?;
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(
                         out,
                         "        let val = unsafe {{ *(self.wire_ptr().add({})) }};",
@@ -1602,6 +1703,7 @@ impl CodeGen {
                         doc.emit(out, 1)?;
                     }
                     writeln!(out, "    pub fn {}(&self) -> {} {{", name, ret_q_rs_typ)?;
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(out, "        unsafe {{")?;
                     writeln!(
                         out,
@@ -1613,7 +1715,7 @@ impl CodeGen {
                         "            let ptr = self.wire_ptr().add(offset) as *const {};",
                         q_rs_typ
                     )?;
-                    writeln!(out, "            let val = *ptr as u32;")?;
+                    writeln!(out, "            let val = ptr.read_unaligned() as u32;")?;
                     writeln!(
                         out,
                         "            std::mem::transmute::<u32, {}>(val)",
@@ -1638,6 +1740,7 @@ impl CodeGen {
                         doc.emit(out, 1)?;
                     }
                     writeln!(out, "    fn {}(&self) -> {} {{", name, q_rs_typ)?;
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(out, "        unsafe {{")?;
                     writeln!(
                         out,
@@ -1647,7 +1750,7 @@ impl CodeGen {
                     )?;
                     writeln!(
                         out,
-                        "{}let res_id = *(self.wire_ptr().add(offset) as *const u32);",
+                        "{}let res_id = (self.wire_ptr().add(offset) as *const u32).read_unaligned();",
                         cg::ind(3)
                     )?;
                     writeln!(out, "{}{}::Unknown(res_id)", cg::ind(3), q_rs_typ)?;
@@ -1689,6 +1792,7 @@ impl CodeGen {
                         name,
                         q_rs_typ
                     )?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(
                         out,
@@ -1702,7 +1806,9 @@ impl CodeGen {
                         cg::ind(3),
                         q_rs_typ
                     )?;
-                    writeln!(out, "            *ptr")?;
+                    // 64-bit fields are not always aligned (e.g. after the `full_sequence`
+                    // inserted by libxcb in the generic events)
+                    writeln!(out, "            ptr.read_unaligned()")?;
                     writeln!(out, "        }}")?;
                     writeln!(out, "    }}")?;
                 }
@@ -1732,6 +1838,7 @@ impl CodeGen {
                         doc.emit(out, 1)?;
                     }
                     writeln!(out, "    pub fn {}(&self) -> &{} {{", name, q_rs_typ)?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     if *need_compute_offset {
                         writeln!(
@@ -1785,6 +1892,7 @@ impl CodeGen {
                         doc.emit(out, 1)?;
                     }
                     writeln!(out, "    pub fn {}(&self) -> {} {{", name, q_rs_typ)?;
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(out, "        unsafe {{")?;
                     if *need_compute_offset {
                         writeln!(
@@ -1825,6 +1933,7 @@ impl CodeGen {
                         cg::ind(1),
                         name
                     )?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     // #154: format can be zero when len is zero
                     writeln!(
@@ -1885,6 +1994,7 @@ impl CodeGen {
                         len
                     )?;
 
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(
                         out,
@@ -1925,6 +2035,7 @@ impl CodeGen {
                         params
                     )?;
 
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(
                         out,
@@ -1968,6 +2079,7 @@ impl CodeGen {
                         name, q_rs_typ, len
                     )?;
 
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(out, "        unsafe {{")?;
                     writeln!(
                         out,
@@ -2008,6 +2120,7 @@ impl CodeGen {
                         name,
                         q_rs_typ
                     )?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(
                         out,
@@ -2054,6 +2167,7 @@ impl CodeGen {
                         "    pub fn {}(&self{}) -> &[{}] {{",
                         name, params, q_rs_typ
                     )?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(
                         out,
@@ -2099,6 +2213,7 @@ impl CodeGen {
                         name,
                         q_rs_typ
                     )?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(
                         out,
@@ -2133,6 +2248,7 @@ impl CodeGen {
                         name, params, q_rs_typ
                     )?;
 
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(out, "        unsafe {{")?;
                     writeln!(
                         out,
@@ -2181,6 +2297,7 @@ impl CodeGen {
                         "    pub fn {}(&self{}) -> {}Iterator {{",
                         name, params, q_rs_typ
                     )?;
+                    writeln!(out, "        // SAFETY: {}", ACCESSOR_SAFETY)?;
                     writeln!(out, "        unsafe {{")?;
                     if *need_compute_offset {
                         writeln!(
@@ -2280,6 +2397,7 @@ impl CodeGen {
                     let offset_expr = self.build_rs_expr(wire_off, "self.", "()", fields);
                     writeln!(out, "{}let params = {};", cg::ind(2), params_expr)?;
                     writeln!(out, "{}let mut offset = {};", cg::ind(2), offset_expr)?;
+                    writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
                     writeln!(out, "{}unsafe {{", cg::ind(2))?;
                     writeln!(out, "{}{}::unserialize(", cg::ind(3), impl_typ)?;
                     writeln!(
@@ -2318,6 +2436,7 @@ impl CodeGen {
                 "{}pub fn data(&self) -> ClientMessageData {{",
                 cg::ind(1)
             )?;
+            writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
             writeln!(out, "{}unsafe {{", cg::ind(2))?;
             writeln!(out, "{}match self.format() {{", cg::ind(3))?;
             for sz in [8, 16, 32] {
@@ -2365,6 +2484,7 @@ impl CodeGen {
         {
             writeln!(out)?;
             writeln!(out, "{}pub fn u(&self) -> NotifyData {{", cg::ind(1))?;
+            writeln!(out, "{}// SAFETY: {}", cg::ind(2), ACCESSOR_SAFETY)?;
             writeln!(out, "{}unsafe {{", cg::ind(2))?;
             writeln!(out, "{}match self.sub_code() {{", cg::ind(3))?;
 
@@ -2396,6 +2516,169 @@ impl CodeGen {
         }
     }
 
+    /// Whether the fields are plain data, that [CodeGen::emit_serde_impl] can serialize.
+    pub(super) fn is_serde_plain_data(&self, fields: &[Field]) -> bool {
+        fields.iter().all(|f| match f {
+            Field::Field {
+                module,
+                rs_typ,
+                is_union: true,
+                ..
+            } => self.union_is_plain_data(module.as_deref(), rs_typ),
+            Field::Field { struct_style, .. } => {
+                matches!(struct_style, None | Some(StructStyle::WireLayout))
+            }
+            Field::List {
+                len_expr: Expr::Value(len),
+                struct_style: None,
+                ..
+            } => *len <= 32,
+            Field::Pad { .. } => true,
+            _ => false,
+        })
+    }
+
+    fn union_is_plain_data(&self, module: Option<&str>, rs_typ: &str) -> bool {
+        let typinfos = match module {
+            Some(module) if module != self.xcb_mod => &self.get_depinfo(module).typinfos,
+            _ => &self.typinfos,
+        };
+        // the unions of xids are always plain data
+        !typinfos.values().any(|typinfo| {
+            matches!(typinfo, TypeInfo::Union { rs_typ: typ, variants, .. }
+                if typ == rs_typ && !union::is_plain_data(variants))
+        })
+    }
+
+    /// Emits the `serde` traits of a type made of plain data, that is serialized from its
+    /// accessors and deserialized with its constructor.
+    pub(super) fn emit_serde_impl<O: Write>(
+        &self,
+        out: &mut O,
+        rs_typ: &str,
+        fields: &[Field],
+        ctor: SerdeCtor,
+    ) -> io::Result<()> {
+        let serde_fields: Vec<&Field> = fields
+            .iter()
+            .filter(|f| !matches!(f, Field::Pad { .. }))
+            .collect();
+
+        writeln!(out)?;
+        writeln!(out, "#[cfg(feature = \"serde\")]")?;
+        writeln!(out, "impl serde::Serialize for {} {{", rs_typ)?;
+        writeln!(
+            out,
+            "{}fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {{",
+            cg::ind(1)
+        )?;
+        writeln!(out, "{}use serde::ser::SerializeStruct;", cg::ind(2))?;
+        writeln!(
+            out,
+            "{}let mut state = serializer.serialize_struct(\"{}\", {})?;",
+            cg::ind(2),
+            rs_typ,
+            serde_fields.len()
+        )?;
+        for f in &serde_fields {
+            let name = match f {
+                Field::Field { name, .. } | Field::List { name, .. } => name,
+                f => unreachable!("{:#?}", f),
+            };
+            writeln!(
+                out,
+                "{}state.serialize_field(\"{}\", &self.{}())?;",
+                cg::ind(2),
+                name.trim_start_matches("r#"),
+                name
+            )?;
+        }
+        writeln!(out, "{}state.end()", cg::ind(2))?;
+        writeln!(out, "{}}}", cg::ind(1))?;
+        writeln!(out, "}}")?;
+
+        let event_number = match ctor {
+            SerdeCtor::None => return Ok(()),
+            SerdeCtor::New => None,
+            SerdeCtor::EventNew { number } => number,
+        };
+        let is_event = matches!(ctor, SerdeCtor::EventNew { .. });
+
+        writeln!(out)?;
+        writeln!(out, "#[cfg(feature = \"serde\")]")?;
+        writeln!(out, "impl<'de> serde::Deserialize<'de> for {} {{", rs_typ)?;
+        writeln!(
+            out,
+            "{}fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {{",
+            cg::ind(1)
+        )?;
+        writeln!(out, "{}#[derive(serde::Deserialize)]", cg::ind(2))?;
+        writeln!(out, "{}struct Fields {{", cg::ind(2))?;
+        if event_number.is_some() {
+            writeln!(out, "{}response_type: u8,", cg::ind(3))?;
+        }
+        let mut params = Vec::new();
+        for f in &serde_fields {
+            match f {
+                // computed by the constructor of the events, ignored if present
+                Field::Field { name, .. }
+                    if is_event
+                        && matches!(
+                            name.as_str(),
+                            "response_type" | "sequence" | "format" | "sub_code"
+                        ) => {}
+                Field::Field {
+                    name,
+                    module,
+                    rs_typ,
+                    r#enum,
+                    mask,
+                    ..
+                } => {
+                    let q_rs_typ = enum_mask_qualified_rs_typ(module, rs_typ, r#enum, mask);
+                    writeln!(out, "{}{}: {},", cg::ind(3), name, q_rs_typ)?;
+                    params.push(name);
+                }
+                Field::List {
+                    name,
+                    module,
+                    rs_typ,
+                    len_expr: Expr::Value(len),
+                    ..
+                } => {
+                    let q_rs_typ = (module, rs_typ).qualified_rs_typ();
+                    writeln!(out, "{}{}: [{}; {}],", cg::ind(3), name, q_rs_typ, len)?;
+                    params.push(name);
+                }
+                f => unreachable!("{:#?}", f),
+            }
+        }
+        writeln!(out, "{}}}", cg::ind(2))?;
+        writeln!(
+            out,
+            "{}let fields = Fields::deserialize(deserializer)?;",
+            cg::ind(2)
+        )?;
+        writeln!(out, "{}Ok({}::new(", cg::ind(2), rs_typ)?;
+        if let Some(number) = event_number {
+            // the `event_base` of the extension events
+            writeln!(
+                out,
+                "{}(fields.response_type & 0x7f).wrapping_sub({}),",
+                cg::ind(3),
+                number
+            )?;
+        }
+        for name in &params {
+            writeln!(out, "{}fields.{},", cg::ind(3), name)?;
+        }
+        writeln!(out, "{}))", cg::ind(2))?;
+        writeln!(out, "{}}}", cg::ind(1))?;
+        writeln!(out, "}}")?;
+
+        Ok(())
+    }
+
     pub(super) fn emit_debug_impl<O: Write>(
         &self,
         out: &mut O,
@@ -2527,8 +2810,22 @@ impl CodeGen {
         writeln!(out, "            Some(res)")?;
         writeln!(out, "        }}}}")?;
         writeln!(out, "    }}")?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "    fn size_hint(&self) -> (usize, std::option::Option<usize>) {{"
+        )?;
+        writeln!(out, "        (self.rem, Some(self.rem))")?;
+        writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
 
+        writeln!(out)?;
+        writeln!(
+            out,
+            "impl<'a> ExactSizeIterator for {}Iterator<'a> {{}}",
+            rs_typ
+        )?;
+
         writeln!(out)?;
         writeln!(
             out,
@@ -2564,6 +2861,17 @@ struct FieldInfo {
     is_mask: bool,
 }
 
+/// How the `Deserialize` impl emitted by [CodeGen::emit_serde_impl] builds the value.
+pub(super) enum SerdeCtor {
+    /// No `Deserialize` impl.
+    None,
+    /// With `new`, from all the fields.
+    New,
+    /// With the `new` of an event, from the fields that it doesn't compute.
+    /// The `event_base` of an extension event is given by the serialized `response_type`.
+    EventNew { number: Option<i32> },
+}
+
 pub(super) const RANDR_SUBCODES: &[(&str, &str, &str)] = &[
     ("CrtcChange", "CrtcChange", "Cc"),
     ("OutputChange", "OutputChange", "Oc"),
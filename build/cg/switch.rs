@@ -395,6 +395,10 @@ impl CodeGen {
             "    unsafe fn unserialize(wire_data: *const u8, params: {}, out_offset: &mut usize) -> {} {{",
             params_struct.rs_typ, return_typ)?;
 
+        let fn_out = out;
+        let mut body = Vec::new();
+        let out = &mut body;
+
         writeln!(out, "{}let {}{{", cg::ind(2), params_struct.rs_typ)?;
         for p in &params_struct.params {
             writeln!(out, "{}{},", cg::ind(3), p)?;
@@ -440,7 +444,7 @@ impl CodeGen {
                         ..
                     } => {
                         let q_rs_typ = (&r#enum.0, &r#enum.1).qualified_rs_typ();
-                        writeln!(out, "{}let {} = std::mem::transmute::<_, {}>(*(wire_data.add(offset) as *const {}) as u32);", cg::ind(3), name, q_rs_typ, rs_typ)?;
+                        writeln!(out, "{}let {} = std::mem::transmute::<_, {}>((wire_data.add(offset) as *const {}).read_unaligned() as u32);", cg::ind(3), name, q_rs_typ, rs_typ)?;
                         writeln!(out, "{}offset += {};", cg::ind(3), sz)?;
                     }
                     Field::Field {
@@ -476,7 +480,7 @@ impl CodeGen {
                         let wire_typ = format!("u{}", *sz * 8);
                         writeln!(
                             out,
-                            "{}let {} = *(wire_data.add(offset) as *const {}) != 0;",
+                            "{}let {} = (wire_data.add(offset) as *const {}).read_unaligned() != 0;",
                             cg::ind(3),
                             name,
                             wire_typ
@@ -492,7 +496,7 @@ impl CodeGen {
                         let q_rs_typ = (module, rs_typ).qualified_rs_typ();
                         writeln!(
                             out,
-                            "{}let {} = *(wire_data.add(offset) as *const {});",
+                            "{}let {} = (wire_data.add(offset) as *const {}).read_unaligned();",
                             cg::ind(3),
                             name,
                             q_rs_typ
@@ -592,7 +596,7 @@ impl CodeGen {
                             self.build_rs_expr(len_expr, "", "", &[])
                         )?;
                         writeln!(out,
-                            "{}{}.push({}::from_bits(*(wire_data.add(offset) as *const {}) as u32).unwrap());",
+                            "{}{}.push({}::from_bits((wire_data.add(offset) as *const {}).read_unaligned() as u32).unwrap());",
                             cg::ind(4), name, q_rs_typ, rs_typ)?;
                         writeln!(
                             out,
@@ -761,7 +765,13 @@ impl CodeGen {
                 rs_typ
             )?;
         }
-        writeln!(out, "    }}")?;
+        cg::emit_unsafe_body(
+            fn_out,
+            2,
+            "the caller guarantees that `wire_data` points to a valid wire representation",
+            &body,
+        )?;
+        writeln!(fn_out, "    }}")?;
 
         Ok(())
     }
@@ -780,6 +790,10 @@ impl CodeGen {
             out,
             "    unsafe fn compute_wire_len(ptr: *const u8, params: Self::Params) -> usize {{"
         )?;
+
+        let fn_out = out;
+        let mut body = Vec::new();
+        let out = &mut body;
         writeln!(out, "{}let {} {{", cg::ind(2), params_struct.rs_typ)?;
         for p in &params_struct.params {
             writeln!(out, "{}{},", cg::ind(3), p)?;
@@ -832,7 +846,13 @@ impl CodeGen {
                 self.xcb_mod, rs_typ
             )?;
         }
-        writeln!(out, "    }}")?;
+        cg::emit_unsafe_body(
+            fn_out,
+            2,
+            "the caller guarantees that `ptr` points to a valid wire representation",
+            &body,
+        )?;
+        writeln!(fn_out, "    }}")?;
         Ok(())
     }
 
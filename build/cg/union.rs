@@ -198,6 +198,9 @@ impl CodeGen {
             "#[derive({}Debug)]",
             if impl_clone { "Clone, " } else { "" }
         )?;
+        if is_plain_data(variants) {
+            writeln!(out, "{}", cg::SERDE_DERIVE)?;
+        }
         writeln!(out, "pub enum {} {{", rs_typ)?;
         for v in variants {
             match &v.content {
@@ -395,11 +398,16 @@ impl CodeGen {
             "    unsafe fn compute_wire_len(_ptr: *const u8, _params: Self::Params) -> usize {{ {} }}", wire_sz
         )?;
             writeln!(out, "    unsafe fn unserialize(ptr: *const u8, _params: (), offset: &mut usize) -> Self {{")?;
-            writeln!(out, "        let sz = Self::compute_wire_len(ptr, ());")?;
+            writeln!(out, "        let sz = {};", wire_sz)?;
             writeln!(out, "        *offset += sz;")?;
             writeln!(
                 out,
-                "        Self::from_data(std::slice::from_raw_parts(ptr, sz))"
+                "        // SAFETY: the caller guarantees that `ptr` points to {} readable bytes",
+                wire_sz
+            )?;
+            writeln!(
+                out,
+                "        unsafe {{ Self::from_data(std::slice::from_raw_parts(ptr, sz)) }}"
             )?;
             writeln!(out, "    }}")?;
             writeln!(out, "}}")?;
@@ -445,6 +453,10 @@ impl CodeGen {
             rs_typ
         )?;
         writeln!(out, "        let r#type = wire_data[{}] as u32;", offset)?;
+
+        let fn_out = out;
+        let mut body = Vec::new();
+        let out = &mut body;
         writeln!(out, "        match r#type {{")?;
         for item in items {
             let variant = variants.iter().find(|v| v.variant == item.0).unwrap();
@@ -480,7 +492,7 @@ impl CodeGen {
                                     let q_rs_typ = (module, rs_typ).qualified_rs_typ();
                                     writeln!(
                                         out,
-                                        "                {}: *(wire_data.as_ptr().add({}) as *const {}),",
+                                        "                {}: (wire_data.as_ptr().add({}) as *const {}).read_unaligned(),",
                                         name,
                                         off,
                                         q_rs_typ,
@@ -517,8 +529,14 @@ impl CodeGen {
             self.xcb_mod, rs_typ
         )?;
         writeln!(out, "        }}")?;
-        writeln!(out, "    }}")?;
-        writeln!(out, "}}")?;
+        cg::emit_unsafe_body(
+            fn_out,
+            2,
+            "the caller guarantees that `wire_data` is a valid wire representation",
+            &body,
+        )?;
+        writeln!(fn_out, "    }}")?;
+        writeln!(fn_out, "}}")?;
 
         Ok(())
     }
@@ -530,3 +548,19 @@ fn is_type_field(f: &Field) -> bool {
         _ => false,
     }
 }
+
+/// Whether the variants of a union are primitives or small arrays of primitives,
+/// for which the `serde` traits are derived.
+pub(super) fn is_plain_data(variants: &[UnionVariant]) -> bool {
+    let is_primitive = |rs_typ: &str| {
+        matches!(
+            rs_typ,
+            "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64" | "f32" | "f64" | "bool"
+        )
+    };
+    variants.iter().all(|v| match &v.content {
+        UnionVariantContent::RsTyp(rs_typ) => is_primitive(rs_typ),
+        UnionVariantContent::Array(rs_typ, sz) => is_primitive(rs_typ) && *sz <= 32,
+        UnionVariantContent::Struct(_) => false,
+    })
+}
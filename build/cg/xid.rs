@@ -59,6 +59,7 @@ impl CodeGen {
             "#[derive(Copy, Clone, {}PartialEq, Eq, Hash, PartialOrd, Ord)]",
             dbg
         )?;
+        writeln!(out, "{}", cg::SERDE_DERIVE_TRANSPARENT)?;
         writeln!(out, "#[repr(C)]")?;
         writeln!(out, "pub struct {} {{", rs_typ)?;
         writeln!(out, "    res_id: u32,")?;
@@ -99,6 +100,7 @@ impl CodeGen {
 
         writeln!(out)?;
         writeln!(out, "#[derive(Copy, Clone, Debug)]")?;
+        writeln!(out, "{}", cg::SERDE_DERIVE)?;
         writeln!(out, "pub enum {} {{", rs_typ)?;
         writeln!(out, "    None,")?;
         if has_unknown {
@@ -0,0 +1,121 @@
+//! Runs `xcb::compositor::Basic` for a few seconds while a test window is created and
+//! destroyed a few times, and checks with the X-Resource extension that the compositor
+//! does not leak server resources.
+//!
+//! Run it in a nested X server without compositing manager, e.g.
+//! `Xephyr :1 & DISPLAY=:1 cargo run --example basic_compositor --features compositor,res`
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use xcb::{compositor, res, x, Extension, Xid};
+
+/// Returns the number of resources of the client of `conn`, by type name.
+fn resource_counts(conn: &xcb::Connection) -> xcb::Result<BTreeMap<String, u32>> {
+    let xid = conn.get_setup().resource_id_base();
+    let cookie = conn.send_request(&res::QueryClientResources { xid });
+    let reply = conn.wait_for_reply(cookie)?;
+
+    let mut counts = BTreeMap::new();
+    for ty in reply.types() {
+        let cookie = conn.send_request(&x::GetAtomName {
+            atom: ty.resource_type,
+        });
+        let name = conn.wait_for_reply(cookie)?.name().to_string();
+        counts.insert(name, ty.count);
+    }
+    Ok(counts)
+}
+
+/// Runs the compositor until `deadline`.
+fn run_until(
+    conn: &xcb::Connection,
+    compositor: &mut compositor::Basic,
+    deadline: Instant,
+) -> xcb::Result<()> {
+    while Instant::now() < deadline {
+        while let Some(event) = conn.poll_for_event()? {
+            compositor.handle_event(&event)?;
+        }
+        compositor.frame()?;
+        std::thread::sleep(Duration::from_millis(16));
+    }
+    Ok(())
+}
+
+fn main() -> xcb::Result<()> {
+    let (conn, screen_num) = xcb::Connection::connect_with_extensions(
+        None,
+        &[
+            Extension::Composite,
+            Extension::Damage,
+            Extension::XFixes,
+            Extension::Render,
+            Extension::Res,
+        ],
+        &[],
+    )?;
+    let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+    let root = screen.root();
+
+    let mut compositor = compositor::Basic::new(&conn, screen_num)?;
+    run_until(
+        &conn,
+        &mut compositor,
+        Instant::now() + Duration::from_secs(1),
+    )?;
+    let before = resource_counts(&conn)?;
+    println!("resources: {:?}", before);
+
+    for i in 0..5 {
+        // a window that is created, moved, resized and destroyed
+        let window: x::Window = conn.generate_id();
+        conn.send_request(&x::CreateWindow {
+            depth: x::COPY_FROM_PARENT as u8,
+            wid: window,
+            parent: root,
+            x: 50 * i,
+            y: 50 * i,
+            width: 200,
+            height: 150,
+            border_width: 2,
+            class: x::WindowClass::InputOutput,
+            visual: screen.root_visual(),
+            value_list: &[x::Cw::BackPixel(screen.white_pixel())],
+        });
+        conn.send_request(&x::MapWindow { window });
+        run_until(
+            &conn,
+            &mut compositor,
+            Instant::now() + Duration::from_millis(300),
+        )?;
+
+        conn.send_request(&x::ConfigureWindow {
+            window,
+            value_list: &[
+                x::ConfigWindow::X(100),
+                x::ConfigWindow::Width(300),
+                x::ConfigWindow::StackMode(x::StackMode::Below),
+            ],
+        });
+        run_until(
+            &conn,
+            &mut compositor,
+            Instant::now() + Duration::from_millis(300),
+        )?;
+
+        conn.send_request(&x::DestroyWindow { window });
+        run_until(
+            &conn,
+            &mut compositor,
+            Instant::now() + Duration::from_millis(300),
+        )?;
+        println!("window {:#x} destroyed", window.resource_id());
+    }
+
+    let after = resource_counts(&conn)?;
+    println!("resources: {:?}", after);
+    assert_eq!(before, after, "the compositor leaks resources");
+    println!("no leak");
+    Ok(())
+}
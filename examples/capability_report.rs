@@ -0,0 +1,14 @@
+//! Prints an `xdpyinfo`-like report of the capabilities of the server.
+//!
+//! Enable the `randr`, `render` and `xv` cargo features for the extension sections, e.g.
+//! `cargo run --example capability_report --features randr,xv`
+
+fn main() -> xcb::Result<()> {
+    let (conn, _) = xcb::Connection::connect(None)?;
+    let report = conn.capability_report();
+    print!("{}", report);
+    if !report.errors.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Embeds the window of a client into the window of another one with `xcb::embed::Embedder`,
+//! and checks that the embedded window survives when the embedder disconnects abruptly.
+//!
+//! Run it in a nested X server, e.g.
+//! `Xephyr :1 & DISPLAY=:1 cargo run --example embed_window`
+
+use xcb::{embed, x, Xid};
+
+fn create_window(conn: &xcb::Connection, screen: &x::Screen, x: i16, y: i16) -> x::Window {
+    let window: x::Window = conn.generate_id();
+    conn.send_request(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: screen.root(),
+        x,
+        y,
+        width: 200,
+        height: 150,
+        border_width: 0,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[x::Cw::BackPixel(screen.white_pixel())],
+    });
+    conn.send_request(&x::MapWindow { window });
+    window
+}
+
+fn parent(conn: &xcb::Connection, window: x::Window) -> xcb::Result<x::Window> {
+    let cookie = conn.send_request(&x::QueryTree { window });
+    Ok(conn.wait_for_reply(cookie)?.parent())
+}
+
+fn main() -> xcb::Result<()> {
+    // the client whose window is embedded
+    let (client, screen_num) = xcb::Connection::connect(None)?;
+    let screen = client.get_setup().roots().nth(screen_num as usize).unwrap();
+    let root = screen.root();
+    let foreign = create_window(&client, screen, 10, 20);
+    client.flush()?;
+
+    {
+        let (conn, _) = xcb::Connection::connect(None)?;
+        let container = create_window(&conn, screen, 300, 300);
+        let embedder = embed::Embedder::new(&conn, foreign, container, 5, 5)?;
+        assert_eq!(parent(&client, foreign)?, container);
+        println!(
+            "window {:#x} embedded in {:#x}",
+            foreign.resource_id(),
+            container.resource_id()
+        );
+
+        // simulate a crash: the connection is closed without dropping the embedder
+        std::mem::forget(embedder);
+    }
+
+    // the server processes the save-set of the embedder when its connection closes
+    let mut tries = 0;
+    while parent(&client, foreign)? != root {
+        tries += 1;
+        assert!(tries < 100, "the embedded window was not restored");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    println!(
+        "window {:#x} restored under the root",
+        foreign.resource_id()
+    );
+
+    // the foreign client destroys its window first
+    let (conn, _) = xcb::Connection::connect(None)?;
+    let container = create_window(&conn, screen, 300, 300);
+    let mut embedder = embed::Embedder::new(&conn, foreign, container, 5, 5)?;
+    client.send_and_check_request(&x::DestroyWindow { window: foreign })?;
+    loop {
+        let event = conn.wait_for_event()?;
+        if embedder.handle_event(&event) {
+            break;
+        }
+    }
+    assert!(embedder.is_destroyed());
+    drop(embedder);
+    conn.send_and_check_request(&x::NoOperation {})?;
+    assert!(conn.poll_for_event()?.is_none(), "unexpected error");
+    println!(
+        "window {:#x} destroyed by its client",
+        foreign.resource_id()
+    );
+    Ok(())
+}
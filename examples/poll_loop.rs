@@ -0,0 +1,93 @@
+//! Multiplexes the connection with a timer in a `poll` loop, without blocking in XCB.
+//!
+//! Prints the events of a window, and a tick every second, until a key is pressed.
+
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+use xcb::{x, Xid};
+
+const TICK: Duration = Duration::from_secs(1);
+
+/// Handles an event, returns `false` to exit.
+fn handle_event(event: xcb::Event) -> bool {
+    match event {
+        xcb::Event::X(x::Event::KeyPress(_)) => false,
+        event => {
+            println!("{:?}", event);
+            true
+        }
+    }
+}
+
+fn main() -> xcb::Result<()> {
+    let (conn, screen_num) = xcb::Connection::connect(None)?;
+    let setup = conn.get_setup();
+    let screen = setup.roots().nth(screen_num as usize).unwrap();
+
+    let window: x::Window = conn.generate_id();
+    conn.send_and_check_request(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: screen.root(),
+        x: 0,
+        y: 0,
+        width: 300,
+        height: 200,
+        border_width: 0,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[
+            x::Cw::BackPixel(screen.white_pixel()),
+            x::Cw::EventMask(
+                x::EventMask::EXPOSURE | x::EventMask::KEY_PRESS | x::EventMask::STRUCTURE_NOTIFY,
+            ),
+        ],
+    })?;
+    conn.send_and_check_request(&x::MapWindow { window })?;
+    println!(
+        "window {:#x} mapped, press a key to exit",
+        window.resource_id()
+    );
+
+    // the file descriptor stays owned by the connection
+    let mut fds = [libc::pollfd {
+        fd: conn.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    let mut next_tick = Instant::now() + TICK;
+
+    loop {
+        // the events already read, e.g. while waiting for a reply, don't make the fd readable
+        while let Some(event) = conn.poll_for_queued_event()? {
+            if !handle_event(event) {
+                return Ok(());
+            }
+        }
+        conn.flush()?;
+
+        let timeout = next_tick.saturating_duration_since(Instant::now());
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout.as_millis() as libc::c_int) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            panic!("poll failed: {}", err);
+        }
+
+        if fds[0].revents != 0 {
+            // reads from the connection, the other events read are queued
+            if let Some(event) = conn.poll_for_event()? {
+                if !handle_event(event) {
+                    return Ok(());
+                }
+            }
+        }
+
+        if Instant::now() >= next_tick {
+            println!("tick");
+            next_tick += TICK;
+        }
+    }
+}
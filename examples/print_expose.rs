@@ -0,0 +1,57 @@
+//! Maps a window and prints the `Expose` events it receives, until a key is pressed.
+//!
+//! The events are owned by the `xcb::Event` returned by `Connection::wait_for_event`
+//! and `Connection::poll_for_event`, and freed when it is dropped.
+
+use xcb::{x, Xid};
+
+fn main() -> xcb::Result<()> {
+    let (conn, screen_num) = xcb::Connection::connect(None)?;
+    let setup = conn.get_setup();
+    let screen = setup.roots().nth(screen_num as usize).unwrap();
+
+    let window: x::Window = conn.generate_id();
+    conn.send_and_check_request(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: screen.root(),
+        x: 0,
+        y: 0,
+        width: 300,
+        height: 200,
+        border_width: 0,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[
+            x::Cw::BackPixel(screen.white_pixel()),
+            x::Cw::EventMask(x::EventMask::EXPOSURE | x::EventMask::KEY_PRESS),
+        ],
+    })?;
+    conn.send_and_check_request(&x::MapWindow { window })?;
+    println!(
+        "window {:#x} mapped, press a key to exit",
+        window.resource_id()
+    );
+
+    loop {
+        // wait for the next event, then handle the ones already queued before waiting again
+        let mut event = Some(conn.wait_for_event()?);
+        while let Some(ev) = event {
+            match ev {
+                xcb::Event::X(x::Event::Expose(ev)) => {
+                    println!(
+                        "Expose: {}x{} at ({}, {}), {} more to come",
+                        ev.width(),
+                        ev.height(),
+                        ev.x(),
+                        ev.y(),
+                        ev.count()
+                    );
+                }
+                xcb::Event::X(x::Event::KeyPress(_)) => return Ok(()),
+                _ => {}
+            }
+            event = conn.poll_for_event()?;
+        }
+    }
+}
@@ -17,8 +17,8 @@ fn main() -> xcb::Result<()> {
             Err(xcb::Error::Connection(err)) => {
                 panic!("unexpected I/O error: {}", err);
             }
-            Err(xcb::Error::Protocol(err)) => {
-                panic!("unexpected protocol error: {:#?}", err);
+            Err(err) => {
+                panic!("unexpected error: {:#?}", err);
             }
             Ok(event) => event,
         };
@@ -0,0 +1,76 @@
+use xcb::{x, xinput};
+
+fn main() -> xcb::Result<()> {
+    let (conn, screen_num) =
+        xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Input], &[])?;
+
+    // Gesture events require XI 2.4
+    let version = conn.wait_for_reply(conn.send_request(&xinput::XiQueryVersion {
+        major_version: 2,
+        minor_version: 4,
+    }))?;
+    if (version.major_version(), version.minor_version()) < (2, 4) {
+        eprintln!(
+            "XI 2.4 is not supported by the server (got {}.{}), exiting",
+            version.major_version(),
+            version.minor_version()
+        );
+        return Ok(());
+    }
+
+    let setup = conn.get_setup();
+    let screen = setup.roots().nth(screen_num as usize).unwrap();
+    let window: x::Window = conn.generate_id();
+
+    conn.send_request(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: screen.root(),
+        x: 0,
+        y: 0,
+        width: 500,
+        height: 500,
+        border_width: 10,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[x::Cw::BackPixel(screen.white_pixel())],
+    });
+
+    conn.send_request(&x::MapWindow { window });
+
+    conn.send_request(&xinput::XiSelectEvents {
+        window,
+        masks: &[xinput::EventMaskBuf::new(
+            xinput::Device::AllMaster,
+            &[
+                xinput::XiEventMask::GESTURE_PINCH_BEGIN
+                    | xinput::XiEventMask::GESTURE_PINCH_UPDATE
+                    | xinput::XiEventMask::GESTURE_PINCH_END
+                    | xinput::XiEventMask::GESTURE_SWIPE_BEGIN
+                    | xinput::XiEventMask::GESTURE_SWIPE_UPDATE,
+                xinput::XiEventMask::GESTURE_SWIPE_END_WORD1,
+            ],
+        )],
+    });
+
+    conn.flush()?;
+
+    let mut recognizer = xinput::GestureRecognizer::new();
+
+    loop {
+        if let xcb::Event::Input(ev) = conn.wait_for_event()? {
+            if let Some(pinch) = recognizer.handle_event(&ev) {
+                println!("pinch: {:?}", pinch);
+            }
+            match ev {
+                xinput::Event::GestureSwipeUpdate(ev) => {
+                    println!("swipe update: {:?}", ev.delta_f64());
+                }
+                xinput::Event::GestureSwipeEnd(ev) => {
+                    println!("swipe end (cancelled: {})", ev.is_cancelled());
+                }
+                _ => {}
+            }
+        }
+    }
+}
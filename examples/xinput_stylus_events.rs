@@ -125,8 +125,8 @@ fn main() -> xcb::Result<()> {
                 // TODO, query valuator infos to ensure on which axis is the pressure
                 // This works for me with a Wacom One, but could be different with another device/config
                 println!("received stylus motion event");
-                println!("  event_x = {}", fp1616_to_f64(ev.event_x()));
-                println!("  event_y = {}", fp1616_to_f64(ev.event_y()));
+                println!("  event_x = {}", xinput::fp1616_to_f64(ev.event_x()));
+                println!("  event_y = {}", xinput::fp1616_to_f64(ev.event_y()));
                 println!("  pressure = {}", ev.axisvalues()[2].integral);
                 println!();
             }
@@ -142,7 +142,3 @@ fn main() -> xcb::Result<()> {
 
     //Ok(())
 }
-
-fn fp1616_to_f64(val: xinput::Fp1616) -> f64 {
-    (val as f64) / (u16::MAX as f64)
-}
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(reply) = xcb::dri2::ConnectReply::parse_from_bytes(data) {
+        // the Debug implementation goes through all the accessors
+        let _ = format!("{:?}", reply);
+    }
+});
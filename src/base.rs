@@ -3,6 +3,8 @@ use crate::event::{self, Event};
 use crate::ext::{Extension, ExtensionData};
 #[cfg(feature = "present")]
 use crate::present;
+use crate::queue::EventQueue;
+use crate::unchecked::{TaggedError, UncheckedTracker};
 use crate::x::{Atom, Keysym, Setup, Timestamp};
 #[cfg(feature = "xinput")]
 use crate::xinput;
@@ -24,6 +26,9 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 use std::ptr;
 use std::result;
 use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 /// A X resource trait
 pub trait Xid {
@@ -81,6 +86,27 @@ pub trait BaseEvent: Raw<xcb_generic_event_t> {
 
     /// The number associated to this event
     const NUMBER: u32;
+
+    /// The `response_type` of this event on the wire, without the `SendEvent` bit.
+    ///
+    /// `first_event` is the first event of the extension, as returned by its
+    /// `get_extension_data` or by [Connection::extension_data]. It is ignored for the
+    /// events of the main protocol.
+    fn event_code(first_event: u8) -> u8 {
+        match Self::EXTENSION {
+            Some(_) => first_event.wrapping_add(Self::NUMBER as u8),
+            None => Self::NUMBER as u8,
+        }
+    }
+
+    /// The sequence number of the last request processed by the server when the event
+    /// was generated, widened to 32 bits by libxcb.
+    ///
+    /// It is 0 for the events built by the client, e.g. for `SendEvent`.
+    fn full_sequence(&self) -> u32 {
+        // SAFETY: the events are allocated with the room of `xcb_generic_event_t`
+        unsafe { (*self.as_raw()).full_sequence }
+    }
 }
 
 /// A trait for GE_GENERIC events
@@ -112,6 +138,15 @@ pub trait BaseError: Raw<xcb_generic_error_t> {
 
     /// The number associated to this error
     const NUMBER: u32;
+
+    /// The sequence number of the request in error, widened to 32 bits by libxcb.
+    ///
+    /// It matches the lower 32 bits of the [sequence](Cookie::sequence) of the cookie of
+    /// the request.
+    fn full_sequence(&self) -> u32 {
+        // SAFETY: the errors are allocated by libxcb, with the room of `xcb_generic_error_t`
+        unsafe { (*self.as_raw()).full_sequence }
+    }
 }
 
 /// Trait for the resolution of raw wire event to a unified event enum.
@@ -155,19 +190,17 @@ pub(crate) trait ResolveWireGeEvent: Sized {
 ///
 /// `Self` is normally an enum of several event subtypes.
 /// See [crate::x::Error] and [crate::ProtocolError]
-pub(crate) trait ResolveWireError {
+pub(crate) trait ResolveWireError: Sized {
     /// Convert a pointer to `xcb_generic_error_t` to `Self`, inferring the correct subtype
-    /// using `response_type` field and `first_error`.
+    /// using `error_code` field and `first_error`.
     ///
-    /// # Panics
-    /// Panics if the error subtype cannot be resolved for `self`. That is,
-    /// `response_type` field must be checked beforehand to be in range with
-    /// `first_error`.
+    /// Returns `None` if `error_code` is not one of the errors of `Self`, in which case
+    /// the ownership of `error` stays with the caller.
     ///
     /// # Safety
     /// `err` must be a valid, non-null error obtained by `xcb_wait_for_reply`
     /// or similar function
-    unsafe fn resolve_wire_error(first_error: u8, error: *mut xcb_generic_error_t) -> Self;
+    unsafe fn resolve_wire_error(first_error: u8, error: *mut xcb_generic_error_t) -> Option<Self>;
 }
 
 /// Trait for types that can serialize themselves to the X wire.
@@ -224,7 +257,7 @@ macro_rules! impl_wired_simple {
             fn serialize(&self, wire_buf: &mut [u8]) -> usize {
                 debug_assert!(wire_buf.len() >= mem::size_of::<Self>());
                 unsafe {
-                    *(wire_buf.as_mut_ptr() as *mut Self) = *self;
+                    ptr::write_unaligned(wire_buf.as_mut_ptr() as *mut Self, *self);
                 }
                 mem::size_of::<Self>()
             }
@@ -243,7 +276,8 @@ macro_rules! impl_wired_simple {
                 offset: &mut usize,
             ) -> Self {
                 *offset += mem::size_of::<Self>();
-                *(ptr as *const Self)
+                // SAFETY: the caller guarantees that `ptr` points to a value of the wire
+                unsafe { ptr::read_unaligned(ptr as *const Self) }
             }
         }
     };
@@ -266,7 +300,7 @@ impl<T: Xid> WiredOut for T {
     fn serialize(&self, wire_buf: &mut [u8]) -> usize {
         debug_assert!(wire_buf.len() >= 4);
         unsafe {
-            *(wire_buf.as_mut_ptr() as *mut u32) = self.resource_id();
+            ptr::write_unaligned(wire_buf.as_mut_ptr() as *mut u32, self.resource_id());
         }
         4
     }
@@ -281,8 +315,11 @@ impl<T: XidNew> WiredIn for T {
 
     unsafe fn unserialize(ptr: *const u8, _params: Self::Params, offset: &mut usize) -> Self {
         *offset += 4;
-        let xid = *(ptr as *const u32);
-        T::new(xid)
+        // SAFETY: the caller guarantees that `ptr` points to a resource id of type `T`
+        unsafe {
+            let xid = ptr::read_unaligned(ptr as *const u32);
+            T::new(xid)
+        }
     }
 }
 
@@ -302,14 +339,72 @@ pub trait Reply {
     unsafe fn into_raw(self) -> *const u8;
 }
 
+/// The full sequence number of a request.
+///
+/// The X server numbers the requests with 16-bit sequence numbers that wrap around every
+/// 65536 requests. libxcb widens them, such that the sequence numbers of the cookies
+/// can be compared safely. The 16-bit sequence numbers of the events and errors are widened
+/// with [Connection::widen_sequence].
+pub type SequenceNumber = u64;
+
+/// Widens `seq`, a sequence number truncated to `bits` bits, to the first value that is equal
+/// or after `last`.
+///
+/// This is how libxcb widens the 16-bit sequence numbers received from the server.
+pub(crate) fn widen(last: SequenceNumber, seq: u64, bits: u32) -> SequenceNumber {
+    let mask = (1u64 << bits) - 1;
+    let full = (last & !mask) | (seq & mask);
+    if full < last {
+        full + (1 << bits)
+    } else {
+        full
+    }
+}
+
+/// Widens `seq16` to the sequence number closest to `last`.
+pub(crate) fn widen_sequence16(last: SequenceNumber, seq16: u16) -> SequenceNumber {
+    widen(last.saturating_sub(0x8000), seq16 as u64, 16)
+}
+
 /// General trait for cookies returned by requests.
+///
+/// The cookies are plain sequence numbers: dropping a cookie of a request with reply,
+/// or a checked cookie, before its reply or error is fetched leaves the reply or the error
+/// in the queue of libxcb until the connection is closed. Use [Cookie::discard] when the
+/// response of such a request is no longer needed.
 pub trait Cookie {
     /// # Safety
     /// `seq` must be a valid cookie for a given `Request` or `Reply`.
-    unsafe fn from_sequence(seq: u64) -> Self;
+    unsafe fn from_sequence(seq: SequenceNumber) -> Self;
 
     /// The raw sequence number associated with the cookie.
-    fn sequence(&self) -> u64;
+    fn sequence(&self) -> SequenceNumber;
+
+    /// Discards the reply and the error of the request, which must have been sent on `conn`.
+    ///
+    /// This does not block: the response is dropped by libxcb when it arrives.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use xcb::x;
+    /// use xcb::Cookie;
+    /// # fn main() -> xcb::Result<()> {
+    /// # let (conn, screen_num) = xcb::Connection::connect(None)?;
+    /// # let window = conn.get_setup().roots().nth(screen_num as usize).unwrap().root();
+    /// // speculatively requested, but not needed after all
+    /// let cookie = conn.send_request(&x::GetGeometry {
+    ///     drawable: x::Drawable::Window(window),
+    /// });
+    /// cookie.discard(&conn);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn discard(self, conn: &Connection)
+    where
+        Self: Sized,
+    {
+        conn.discard_reply(self);
+    }
 }
 
 /// A marker trait for a cookie that allows synchronized error checking.
@@ -346,36 +441,43 @@ pub unsafe trait CookieWithReplyUnchecked: Cookie {
 
 /// The default cookie type returned by void-requests.
 ///
+/// The errors of the request are delivered to the event loop. Use
+/// [Connection::send_request_checked] to check the request instead.
+///
 /// See [Connection::send_request]
 #[derive(Debug)]
 pub struct VoidCookie {
-    seq: u64,
+    seq: SequenceNumber,
 }
 
 impl Cookie for VoidCookie {
-    unsafe fn from_sequence(seq: u64) -> Self {
+    unsafe fn from_sequence(seq: SequenceNumber) -> Self {
         VoidCookie { seq }
     }
 
-    fn sequence(&self) -> u64 {
+    fn sequence(&self) -> SequenceNumber {
         self.seq
     }
 }
 
 /// The checked cookie type returned by void-requests.
 ///
+/// The cookie is consumed by [Connection::check_request], which waits for the request to
+/// be processed and returns its error, if any. Only checked cookies can be checked:
+/// the errors of the requests sent with [Connection::send_request] go to the event loop.
+///
 /// See [Connection::send_request_checked]
 #[derive(Debug)]
 pub struct VoidCookieChecked {
-    seq: u64,
+    seq: SequenceNumber,
 }
 
 impl Cookie for VoidCookieChecked {
-    unsafe fn from_sequence(seq: u64) -> Self {
+    unsafe fn from_sequence(seq: SequenceNumber) -> Self {
         VoidCookieChecked { seq }
     }
 
-    fn sequence(&self) -> u64 {
+    fn sequence(&self) -> SequenceNumber {
         self.seq
     }
 }
@@ -392,7 +494,7 @@ pub unsafe trait RawRequest {
     ///
     /// Send the request over the `conn` wire and return a cookie sequence fitting with the `checked` flag
     /// of `Self`
-    fn raw_request(&self, conn: &Connection, checked: bool) -> u64;
+    fn raw_request(&self, conn: &Connection, checked: bool) -> SequenceNumber;
 }
 
 /// Trait implemented by requests types.
@@ -438,6 +540,7 @@ pub enum EventQueueOwner {
 /// Container for authentication information to connect to the X server
 ///
 /// See [Connection::connect_to_display_with_auth_info] and [Connection::connect_to_fd].
+/// Binary data, such as the cookies of "MIT-MAGIC-COOKIE-1", is passed with [BinaryAuthInfo].
 #[derive(Copy, Clone, Debug)]
 pub struct AuthInfo<'a> {
     /// String containing the authentication protocol name,
@@ -447,56 +550,167 @@ pub struct AuthInfo<'a> {
     pub data: &'a str,
 }
 
+/// Authentication information with binary data, to connect to the X server
+///
+/// See [Connection::connect_to_display_with_binary_auth_info] and
+/// [Connection::connect_to_fd_with_binary_auth_info].
+#[derive(Copy, Clone, Debug)]
+pub struct BinaryAuthInfo<'a> {
+    /// String containing the authentication protocol name,
+    /// such as "MIT-MAGIC-COOKIE-1" or "XDM-AUTHORIZATION-1".
+    pub name: &'a str,
+    /// Data interpreted in a protocol specific manner, such as the 16 bytes
+    /// of a "MIT-MAGIC-COOKIE-1" cookie. It can contain any byte, including NUL.
+    pub data: &'a [u8],
+}
+
+impl<'a> From<AuthInfo<'a>> for BinaryAuthInfo<'a> {
+    fn from(auth_info: AuthInfo<'a>) -> BinaryAuthInfo<'a> {
+        BinaryAuthInfo {
+            name: auth_info.name,
+            data: auth_info.data.as_bytes(),
+        }
+    }
+}
+
+impl<'a> BinaryAuthInfo<'a> {
+    /// The FFI struct pointing to the name and data of `self`, that must outlive it.
+    fn to_ffi(self) -> xcb_auth_info_t {
+        // libxcb reads the name and data with their length, and never writes to them
+        xcb_auth_info_t {
+            namelen: self.name.len() as _,
+            name: self.name.as_ptr() as *mut _,
+            datalen: self.data.len() as _,
+            data: self.data.as_ptr() as *mut _,
+        }
+    }
+}
+
 /// Display info returned by [`parse_display`]
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DisplayInfo {
-    /// The hostname
-    host: String,
+    /// The hostname, without the brackets of IPv6 addresses.
+    ///
+    /// `None` for local displays such as `:0`.
+    pub host: Option<String>,
+    /// The protocol, such as `unix` or `tcp` in `unix/:0` or `tcp/host:0`
+    pub protocol: Option<String>,
     /// The display number
-    display: i32,
-    /// The screen number
-    screen: i32,
+    pub display: u16,
+    /// The screen number, if specified
+    pub screen: Option<u16>,
+}
+
+impl DisplayInfo {
+    /// Whether the display is reached through a local socket rather than the network.
+    ///
+    /// That is the case without host (e.g. `:0`), with the `unix` host (`unix:0`),
+    /// or with the `unix` protocol (`unix/:0`).
+    pub fn is_local(&self) -> bool {
+        matches!(
+            (self.protocol.as_deref(), self.host.as_deref()),
+            (Some("unix"), _) | (None, None) | (None, Some("unix"))
+        )
+    }
+
+    /// The path of the socket of a local display, by the `/tmp/.X11-unix/X<display>` convention.
+    ///
+    /// Returns `None` if the display is not [local](DisplayInfo::is_local).
+    /// The socket existing doesn't guarantee that a server listens on it.
+    pub fn socket_path(&self) -> Option<std::path::PathBuf> {
+        if self.is_local() {
+            Some(format!("/tmp/.X11-unix/X{}", self.display).into())
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats the display string back, such that [parse_display] returns the same info.
+impl Display for DisplayInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(protocol) = &self.protocol {
+            write!(f, "{}/", protocol)?;
+        }
+        match &self.host {
+            Some(host) if host.contains(':') => write!(f, "[{}]", host)?,
+            Some(host) => f.write_str(host)?,
+            None => {}
+        }
+        write!(f, ":{}", self.display)?;
+        if let Some(screen) = self.screen {
+            write!(f, ".{}", screen)?;
+        }
+        Ok(())
+    }
 }
 
 /// Parses a display string in the form documented by [X (7x)](https://linux.die.net/man/7/x).
 ///
+/// The form is `[protocol/][host]:display[.screen]`, where `host` can be an IPv6 address
+/// in brackets (e.g. `[::1]:0`).
 /// Returns `Some(DisplayInfo)` on success and `None` otherwise.
-/// Has no side effects on failure.
 ///
 /// If `name` empty, it uses the environment variable `DISPLAY`.
 ///
-/// If `name` does not contain a screen number, `DisplayInfo::screen` is set to `0`.
+/// # Example
+/// ```
+/// let info = xcb::parse_display("tcp/localhost:1.2").unwrap();
+/// assert_eq!(info.protocol.as_deref(), Some("tcp"));
+/// assert_eq!(info.host.as_deref(), Some("localhost"));
+/// assert_eq!((info.display, info.screen), (1, Some(2)));
+///
+/// let info = xcb::parse_display(":0").unwrap();
+/// assert_eq!(info.socket_path(), Some("/tmp/.X11-unix/X0".into()));
+/// ```
 pub fn parse_display(name: &str) -> Option<DisplayInfo> {
-    unsafe {
-        let name = CString::new(name).unwrap();
-        let mut hostp: *mut c_char = ptr::null_mut();
-        let mut display = 0i32;
-        let mut screen = 0i32;
-
-        let success = xcb_parse_display(
-            name.as_ptr(),
-            &mut hostp as *mut _,
-            &mut display as *mut _,
-            &mut screen as *mut _,
-        );
-
-        if success != 0 {
-            let host = CStr::from_ptr(hostp as *const _)
-                .to_str()
-                .unwrap()
-                .to_string();
-
-            libc::free(hostp as *mut _);
-
-            Some(DisplayInfo {
-                host,
-                display,
-                screen,
-            })
+    if name.is_empty() {
+        let name = std::env::var("DISPLAY").ok()?;
+        return if name.is_empty() {
+            None
         } else {
+            parse_display(&name)
+        };
+    }
+
+    let (protocol, name) = match name.rfind('/') {
+        Some(slash) => (Some(&name[..slash]), &name[slash + 1..]),
+        None => (None, name),
+    };
+    let colon = name.rfind(':')?;
+    let (host, number) = (&name[..colon], &name[colon + 1..]);
+
+    fn parse_number(s: &str) -> Option<u16> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
             None
+        } else {
+            s.parse().ok()
         }
     }
+    let (display, screen) = match number.find('.') {
+        Some(dot) => (
+            parse_number(&number[..dot])?,
+            Some(parse_number(&number[dot + 1..])?),
+        ),
+        None => (parse_number(number)?, None),
+    };
+
+    let host = if host.len() >= 2 && host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    };
+
+    Some(DisplayInfo {
+        host: if host.is_empty() {
+            None
+        } else {
+            Some(host.to_string())
+        },
+        protocol: protocol.map(String::from),
+        display,
+        screen,
+    })
 }
 
 /// A struct that serve as an identifier for internal special queue in XCB
@@ -518,7 +732,7 @@ impl SpecialEventId {
 }
 
 /// Error type that is returned by `Connection::has_error`.
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ConnError {
     /// xcb connection errors because of socket, pipe and other stream errors.
     Connection,
@@ -541,8 +755,8 @@ pub enum ConnError {
 }
 
 impl ConnError {
-    fn to_str(&self) -> &str {
-        match *self {
+    fn to_str(self) -> &'static str {
+        match self {
             ConnError::Connection => "Connection error, possible I/O error",
             ConnError::ClosedExtNotSupported => "Connection closed, X extension not supported",
             ConnError::ClosedMemInsufficient => "Connection closed, insufficient memory",
@@ -582,13 +796,159 @@ pub type ConnResult<T> = result::Result<T, ConnError>;
 /// The result type associated with [ProtocolError].
 pub type ProtocolResult<T> = result::Result<T, ProtocolError>;
 
+/// The context in which an error occurred.
+///
+/// Helpers that issue several requests attach a context to the errors they return,
+/// to tell which step of the sequence failed. See [Error::chain].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The operation that failed, usually the name of a request (e.g. `"GetGeometry"`).
+    pub operation: &'static str,
+    /// Details about the operation (e.g. `"for window 0x400001"`).
+    pub detail: String,
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.detail.is_empty() {
+            f.write_str(self.operation)
+        } else {
+            write!(f, "{} {}", self.operation, self.detail)
+        }
+    }
+}
+
+/// A property set with another type or format than the one expected by a helper.
+///
+/// Returned by the getters of [crate::icccm] and [crate::ewmh], which would otherwise
+/// return the property as unset. See [crate::x::PropertyValue].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PropertyError {
+    /// The name of the property (e.g. `"WM_HINTS"`).
+    pub property: &'static str,
+    /// The type the property is set with.
+    pub actual_type: Atom,
+    /// The format the property is set with: 8, 16 or 32.
+    pub actual_format: u8,
+}
+
+impl Display for PropertyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "property {} has unexpected type {:#x} and format {}",
+            self.property,
+            self.actual_type.resource_id(),
+            self.actual_format
+        )
+    }
+}
+
+impl std::error::Error for PropertyError {}
+
+/// A request or a feature that the version of an extension negotiated with the server
+/// does not support.
+///
+/// Returned eagerly by the helpers that know the negotiated version, instead of the
+/// protocol error the server would report later.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedError {
+    /// The name of the extension (e.g. `"RENDER"`).
+    pub extension: &'static str,
+    /// The request or feature (e.g. `"CreateLinearGradient"`).
+    pub feature: &'static str,
+    /// The version that introduced the feature, major and minor.
+    pub required: (u32, u32),
+    /// The version negotiated with the server, major and minor.
+    pub version: (u32, u32),
+}
+
+impl Display for UnsupportedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requires {} {}.{}, the server supports {}.{}",
+            self.feature,
+            self.extension,
+            self.required.0,
+            self.required.1,
+            self.version.0,
+            self.version.1
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedError {}
+
 /// The general error type for Rust-XCB.
+///
+/// New variants may be added in minor releases: matches on `Error` need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// I/O error issued from the connection.
     Connection(ConnError),
     /// A protocol related error issued by the X server.
     Protocol(ProtocolError),
+    /// A property that a helper could not interpret.
+    Property(PropertyError),
+    /// A request not supported by the negotiated version of an extension.
+    Unsupported(UnsupportedError),
+    /// A screen number that is not a screen of the display.
+    InvalidScreen(i32),
+    /// An error with the context in which it occurred.
+    ///
+    /// Only returned by helpers that issue several requests. See [Error::chain].
+    Context(Box<Error>, ErrorContext),
+}
+
+impl Error {
+    /// Wraps this error with the context `operation` and `detail`.
+    pub fn context<D: Into<String>>(self, operation: &'static str, detail: D) -> Error {
+        Error::Context(
+            Box::new(self),
+            ErrorContext {
+                operation,
+                detail: detail.into(),
+            },
+        )
+    }
+
+    /// Iterates over the contexts attached to this error, from the outermost to the innermost.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// # let (conn, screen_num) = xcb::Connection::connect(None)?;
+    /// # let window = conn.get_setup().roots().nth(screen_num as usize).unwrap().root();
+    /// if let Err(err) = xcb::x::window_summary(&conn, window) {
+    ///     for ctx in err.chain() {
+    ///         eprintln!("while running {}", ctx);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn chain(&self) -> impl Iterator<Item = &ErrorContext> {
+        let mut err = self;
+        std::iter::from_fn(move || match err {
+            Error::Context(inner, ctx) => {
+                err = inner;
+                Some(ctx)
+            }
+            _ => None,
+        })
+    }
+
+    /// The error at the origin of this error, without context.
+    ///
+    /// The returned error is never [Error::Context].
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::Context(inner, _) => inner.root_cause(),
+            err => err,
+        }
+    }
 }
 
 impl Display for Error {
@@ -596,6 +956,10 @@ impl Display for Error {
         match self {
             Error::Connection(_) => f.write_str("xcb connection error"),
             Error::Protocol(_) => f.write_str("xcb protocol error"),
+            Error::Property(err) => write!(f, "xcb {}", err),
+            Error::Unsupported(err) => write!(f, "xcb unsupported request: {}", err),
+            Error::InvalidScreen(screen) => write!(f, "xcb invalid screen {}", screen),
+            Error::Context(inner, ctx) => write!(f, "{}\ncaused by: {}", ctx, inner),
         }
     }
 }
@@ -605,10 +969,33 @@ impl std::error::Error for Error {
         match self {
             Error::Connection(err) => Some(err),
             Error::Protocol(err) => Some(err),
+            Error::Property(err) => Some(err),
+            Error::Unsupported(err) => Some(err),
+            Error::InvalidScreen(_) => None,
+            Error::Context(err, _) => Some(err.as_ref()),
         }
     }
 }
 
+/// Extension trait to attach an [ErrorContext] to the error of a result.
+pub trait ResultExt<T> {
+    /// Wraps the error, if any, with the context `operation` and the detail returned by `detail`.
+    fn context<D, F>(self, operation: &'static str, detail: F) -> Result<T>
+    where
+        D: Into<String>,
+        F: FnOnce() -> D;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for result::Result<T, E> {
+    fn context<D, F>(self, operation: &'static str, detail: F) -> Result<T>
+    where
+        D: Into<String>,
+        F: FnOnce() -> D,
+    {
+        self.map_err(|err| err.into().context(operation, detail()))
+    }
+}
+
 impl From<ConnError> for Error {
     fn from(err: ConnError) -> Error {
         Error::Connection(err)
@@ -621,19 +1008,49 @@ impl From<ProtocolError> for Error {
     }
 }
 
+impl From<PropertyError> for Error {
+    fn from(err: PropertyError) -> Error {
+        Error::Property(err)
+    }
+}
+
+impl From<UnsupportedError> for Error {
+    fn from(err: UnsupportedError) -> Error {
+        Error::Unsupported(err)
+    }
+}
+
 /// The general result type for Rust-XCB.
 pub type Result<T> = result::Result<T, Error>;
 
+/// The maximum length of a request without the extended length of BIG-REQUESTS, in bytes.
+const CORE_MAX_REQUEST_LEN: usize = 0xffff * 4;
+
 /// `Connection` is the central object of XCB.
 ///
 /// It handles all communications with the X server.
 /// It dispatches the requests, receives the replies, poll/wait the events.
 /// It also resolves the errors and events from X server.
 ///
-/// `Connection` is thread safe.
+/// # Threading model
+///
+/// `Connection` is `Send` and `Sync`: it can be moved to another thread, or shared between
+/// threads behind an `Arc` or a reference. libxcb serializes the access to the connection
+/// with its own lock, and lets a thread block in [Connection::wait_for_event] or
+/// [Connection::wait_for_reply] while other threads send requests and wait for their
+/// replies. The state kept by the crate on the side is behind mutexes and atomics.
+///
+/// Each event is delivered to one thread only: when several threads wait for events, the
+/// event loop should run in a single thread. Cookies are plain sequence numbers, and the
+/// replies and events own their data: they can be sent to other threads as well.
+///
+/// With the `xlib_xcb` feature, the Xlib functions called on the display from several
+/// threads additionally require `XInitThreads` to be called first.
 ///
 /// It internally wraps an `xcb_connection_t` object and
-/// will call `xcb_disconnect` when the `Connection` goes out of scope.
+/// will call `xcb_disconnect` when the `Connection` goes out of scope,
+/// unless the connection is borrowed from another library
+/// (see [Connection::from_borrowed_raw_conn]).
 pub struct Connection {
     c: *mut xcb_connection_t,
 
@@ -642,6 +1059,25 @@ pub struct Connection {
 
     ext_data: Vec<ExtensionData>,
 
+    // Display name and authentication used to open the connection.
+    // Only known if the connection was opened by name.
+    display_name: Option<String>,
+    auth_info: Option<(String, Vec<u8>)>,
+
+    // Unchecked requests tracked with `track_unchecked`.
+    unchecked: Mutex<UncheckedTracker>,
+
+    // Bounded event queue, see `set_event_queue_limit`.
+    queue: Mutex<EventQueue>,
+
+    // Code of the fatal error of the connection once observed (0 until then), and whether
+    // it was acknowledged with `take_error`.
+    error: AtomicI32,
+    error_taken: AtomicBool,
+
+    // Whether `xcb_disconnect` is called when the connection is dropped.
+    owned: bool,
+
     // Following field is used to handle the
     // rare (if existing) cases of multiple connections
     // per application.
@@ -651,7 +1087,11 @@ pub struct Connection {
     dbg_atom_names: bool,
 }
 
+// SAFETY: libxcb is thread safe: every function of the `xcb_connection_t` API takes the
+// lock of the connection. The connection is not bound to the thread that opened it.
 unsafe impl Send for Connection {}
+// SAFETY: as above, and the state of the crate mutated through `&Connection` is behind
+// `Mutex` and atomics. The blocking calls to libxcb are made without holding these locks.
 unsafe impl Sync for Connection {}
 
 impl Connection {
@@ -663,6 +1103,13 @@ impl Connection {
     ///
     /// If no screen is preferred, the second member of the tuple is set to 0.
     ///
+    /// The connection is checked right after it is opened, and the errors are reported as:
+    ///  - [ConnError::ClosedParseErr] if `display_name` can't be parsed, or if it is `None`
+    ///    and `DISPLAY` is not set.
+    ///  - [ConnError::ClosedInvalidScreen] if the server has no screen of the display.
+    ///  - [ConnError::Connection] if the server can't be reached, or if it refuses the
+    ///    connection (e.g. failed authentication). libxcb does not tell these cases apart.
+    ///
     /// # Example
     /// ```no_run
     /// fn main() -> xcb::Result<()> {
@@ -684,6 +1131,7 @@ impl Connection {
     /// the resolution of events and errors in these extensions.
     ///
     /// If no screen is preferred, the second member of the tuple is set to 0.
+    /// The connection errors are the ones of [Connection::connect].
     ///
     /// # Panics
     /// Panics if one of the mandatory extension is not present.
@@ -713,7 +1161,10 @@ impl Connection {
 
             check_connection_error(conn)?;
 
-            let conn = Self::from_raw_conn_and_extensions(conn, mandatory, optional);
+            let mut conn = Self::from_raw_conn_and_extensions(conn, mandatory, optional);
+            conn.display_name = resolve_display_name(display_name);
+            #[cfg(feature = "log")]
+            crate::logging::connection_opened(conn.display_name(), screen_num as i32);
             conn.has_error().map(|_| (conn, screen_num as i32))
         }
     }
@@ -780,6 +1231,13 @@ impl Connection {
     /// Connects to an X server, given the open socket fd and the
     /// `auth_info`. The file descriptor `fd` is bidirectionally connected to an X server.
     /// If the connection should be unauthenticated, `auth_info` must be `None`.
+    ///
+    /// The connection takes ownership of `fd`: libxcb closes it when the connection is
+    /// dropped. `fd` must not be closed by the caller, even if an error is returned,
+    /// as libxcb closes it when the connection setup fails.
+    ///
+    /// As the connection is not opened by name, it has no [display name](Connection::display_name)
+    /// and its preferred screen is unknown.
     pub fn connect_to_fd(fd: RawFd, auth_info: Option<AuthInfo<'_>>) -> ConnResult<Self> {
         Self::connect_to_fd_with_extensions(fd, auth_info, &[], &[])
     }
@@ -801,22 +1259,31 @@ impl Connection {
         mandatory: &[Extension],
         optional: &[Extension],
     ) -> ConnResult<Self> {
-        let mut auth_info = auth_info.map(|auth_info| {
-            let auth_name = CString::new(auth_info.name).unwrap();
-            let auth_data = CString::new(auth_info.data).unwrap();
-
-            let auth_info = xcb_auth_info_t {
-                namelen: auth_name.as_bytes().len() as _,
-                name: auth_name.as_ptr() as *mut _,
-                datalen: auth_data.as_bytes().len() as _,
-                data: auth_data.as_ptr() as *mut _,
-            };
-            // return the strings too otherwise they would drop
-            (auth_info, auth_name, auth_data)
-        });
+        Self::connect_to_fd_with_binary_auth_info(
+            fd,
+            auth_info.map(BinaryAuthInfo::from),
+            mandatory,
+            optional,
+        )
+    }
 
+    /// Connects to the X server with an open socket file descriptor and optional binary
+    /// authentification info.
+    ///
+    /// This is [Connection::connect_to_fd_with_extensions] with authentication data that
+    /// is not a string.
+    ///
+    /// # Panics
+    /// Panics if one of the mandatory extension is not present.
+    pub fn connect_to_fd_with_binary_auth_info(
+        fd: RawFd,
+        auth_info: Option<BinaryAuthInfo<'_>>,
+        mandatory: &[Extension],
+        optional: &[Extension],
+    ) -> ConnResult<Self> {
+        let mut auth_info = auth_info.map(BinaryAuthInfo::to_ffi);
         let ai_ptr = if let Some(auth_info) = auth_info.as_mut() {
-            &mut auth_info.0 as *mut _
+            auth_info as *mut _
         } else {
             ptr::null_mut()
         };
@@ -836,6 +1303,9 @@ impl Connection {
     /// Connects to the X server specified by `display_name`, using the
     /// authorization `auth_info`. If a particular screen on that server, it is
     /// returned in the second tuple member, which is otherwise set to `0`.
+    /// The connection errors are the ones of [Connection::connect].
+    ///
+    /// See [Connection::connect_to_display_with_binary_auth_info] for binary data.
     pub fn connect_to_display_with_auth_info(
         display_name: Option<&str>,
         auth_info: AuthInfo<'_>,
@@ -859,8 +1329,51 @@ impl Connection {
         auth_info: AuthInfo<'_>,
         mandatory: &[Extension],
         optional: &[Extension],
+    ) -> ConnResult<(Connection, i32)> {
+        Self::connect_to_display_with_binary_auth_info(
+            display_name,
+            auth_info.into(),
+            mandatory,
+            optional,
+        )
+    }
+
+    /// Connects to the X server, using a binary authorization information.
+    ///
+    /// This is [Connection::connect_to_display_with_auth_info_and_extensions] with
+    /// authentication data that is not a string.
+    ///
+    /// # Example
+    /// ```no_run
+    /// fn main() -> xcb::Result<()> {
+    ///     // a cookie obtained out of band, e.g. with `xauth list`
+    ///     let cookie = [0x3c, 0x00, 0xa1, 0x5e, 0x0d, 0x1b, 0x2f, 0x7a,
+    ///                   0x91, 0x44, 0xe0, 0x06, 0x5b, 0xc8, 0x12, 0xf3];
+    ///     let auth_info = xcb::BinaryAuthInfo {
+    ///         name: "MIT-MAGIC-COOKIE-1",
+    ///         data: &cookie,
+    ///     };
+    ///     let (conn, screen) = xcb::Connection::connect_to_display_with_binary_auth_info(
+    ///         Some(":1"),
+    ///         auth_info,
+    ///         &[],
+    ///         &[],
+    ///     )?;
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if one of the mandatory extension is not present.
+    pub fn connect_to_display_with_binary_auth_info(
+        display_name: Option<&str>,
+        auth_info: BinaryAuthInfo<'_>,
+        mandatory: &[Extension],
+        optional: &[Extension],
     ) -> ConnResult<(Connection, i32)> {
         let mut screen_num: c_int = 0;
+        let resolved_name = resolve_display_name(display_name);
+        let resolved_auth = (auth_info.name.to_string(), auth_info.data.to_vec());
         let display_name = display_name.map(|s| CString::new(s).unwrap());
 
         unsafe {
@@ -870,16 +1383,7 @@ impl Connection {
                 ptr::null()
             };
 
-            let auth_name = CString::new(auth_info.name).unwrap();
-            let auth_data = CString::new(auth_info.data).unwrap();
-
-            let mut auth_info = xcb_auth_info_t {
-                namelen: auth_name.as_bytes().len() as _,
-                name: auth_name.as_ptr() as *mut _,
-                datalen: auth_data.as_bytes().len() as _,
-                data: auth_data.as_ptr() as *mut _,
-            };
-
+            let mut auth_info = auth_info.to_ffi();
             let conn = xcb_connect_to_display_with_auth_info(
                 display_name,
                 &mut auth_info as *mut _,
@@ -888,17 +1392,62 @@ impl Connection {
 
             check_connection_error(conn)?;
 
-            let conn = Self::from_raw_conn_and_extensions(conn, mandatory, optional);
+            let mut conn = Self::from_raw_conn_and_extensions(conn, mandatory, optional);
+            conn.display_name = resolved_name;
+            conn.auth_info = Some(resolved_auth);
+            #[cfg(feature = "log")]
+            crate::logging::connection_opened(conn.display_name(), screen_num as i32);
             conn.has_error().map(|_| (conn, screen_num as i32))
         }
     }
 
     /// builds a new Connection object from an available connection
     ///
+    /// The returned `Connection` takes ownership of `conn` and disconnects it when dropped.
+    /// Use [Connection::from_borrowed_raw_conn] for a connection owned by another library.
+    ///
     /// # Safety
     /// The `conn` pointer must point to a valid `xcb_connection_t`
     pub unsafe fn from_raw_conn(conn: *mut xcb_connection_t) -> Connection {
-        Self::from_raw_conn_and_extensions(conn, &[], &[])
+        // SAFETY: forwarded from the caller
+        unsafe { Self::from_raw_conn_and_extensions(conn, &[], &[]) }
+    }
+
+    /// Builds a new `Connection` object from a connection owned by another library
+    /// (e.g. the one of an SDL, GLFW or cairo window), to send requests on it.
+    ///
+    /// The returned `Connection` does not call `xcb_disconnect` when dropped.
+    ///
+    /// # Safety
+    /// The `conn` pointer must point to a valid `xcb_connection_t`, which must not be
+    /// disconnected before the returned `Connection` is dropped.
+    pub unsafe fn from_borrowed_raw_conn(conn: *mut xcb_connection_t) -> Connection {
+        // SAFETY: forwarded from the caller
+        unsafe { Self::from_borrowed_raw_conn_and_extensions(conn, &[], &[]) }
+    }
+
+    /// Builds a new `Connection` object from a connection owned by another library
+    /// and cache the extension data.
+    ///
+    /// See [Connection::from_raw_conn_and_extensions] for the extensions, and
+    /// [Connection::from_borrowed_raw_conn] for the ownership of `conn`.
+    ///
+    /// # Panics
+    /// Panics if the connection is null or in error state.
+    /// Panics if one of the mandatory extension is not present.
+    ///
+    /// # Safety
+    /// The `conn` pointer must point to a valid `xcb_connection_t`, which must not be
+    /// disconnected before the returned `Connection` is dropped.
+    pub unsafe fn from_borrowed_raw_conn_and_extensions(
+        conn: *mut xcb_connection_t,
+        mandatory: &[Extension],
+        optional: &[Extension],
+    ) -> Connection {
+        // SAFETY: forwarded from the caller
+        let mut conn = unsafe { Self::from_raw_conn_and_extensions(conn, mandatory, optional) };
+        conn.owned = false;
+        conn
     }
 
     /// Builds a new `Connection` object from an available connection and cache the extension data
@@ -918,10 +1467,13 @@ impl Connection {
         optional: &[Extension],
     ) -> Connection {
         assert!(!conn.is_null());
-        assert!(check_connection_error(conn).is_ok());
+        // SAFETY: the caller guarantees that `conn` is a valid connection
+        assert!(unsafe { check_connection_error(conn) }.is_ok());
 
         #[cfg(feature = "debug_atom_names")]
-        let dbg_atom_names = {
+        // SAFETY: `DAN_CONN` is registered by the first connection and cleared when it is
+        // dropped; the debug atom names are a debugging aid and not synchronized
+        let dbg_atom_names = unsafe {
             if dan::DAN_CONN.is_null() {
                 dan::DAN_CONN = conn;
                 true
@@ -934,13 +1486,30 @@ impl Connection {
 
         #[cfg(not(feature = "xlib_xcb"))]
         #[cfg(not(feature = "debug_atom_names"))]
-        return Connection { c: conn, ext_data };
+        return Connection {
+            c: conn,
+            ext_data,
+            display_name: None,
+            auth_info: None,
+            unchecked: Default::default(),
+            queue: Default::default(),
+            error: AtomicI32::new(0),
+            error_taken: AtomicBool::new(false),
+            owned: true,
+        };
 
         #[cfg(not(feature = "xlib_xcb"))]
         #[cfg(feature = "debug_atom_names")]
         return Connection {
             c: conn,
             ext_data,
+            display_name: None,
+            auth_info: None,
+            unchecked: Default::default(),
+            queue: Default::default(),
+            error: AtomicI32::new(0),
+            error_taken: AtomicBool::new(false),
+            owned: true,
             dbg_atom_names,
         };
 
@@ -950,6 +1519,13 @@ impl Connection {
             c: conn,
             dpy: ptr::null_mut(),
             ext_data,
+            display_name: None,
+            auth_info: None,
+            unchecked: Default::default(),
+            queue: Default::default(),
+            error: AtomicI32::new(0),
+            error_taken: AtomicBool::new(false),
+            owned: true,
         };
 
         #[cfg(feature = "xlib_xcb")]
@@ -958,6 +1534,13 @@ impl Connection {
             c: conn,
             dpy: ptr::null_mut(),
             ext_data,
+            display_name: None,
+            auth_info: None,
+            unchecked: Default::default(),
+            queue: Default::default(),
+            error: AtomicI32::new(0),
+            error_taken: AtomicBool::new(false),
+            owned: true,
             dbg_atom_names,
         };
     }
@@ -973,7 +1556,8 @@ impl Connection {
     /// The `dpy` pointer must be a pointer to a valid `xlib::Display`
     #[cfg(feature = "xlib_xcb")]
     pub unsafe fn from_xlib_display(dpy: *mut xlib::Display) -> Connection {
-        Self::from_xlib_display_and_extensions(dpy, &[], &[])
+        // SAFETY: forwarded from the caller
+        unsafe { Self::from_xlib_display_and_extensions(dpy, &[], &[]) }
     }
 
     /// Initialize a new `Connection` from an existing Xlib display.
@@ -998,12 +1582,16 @@ impl Connection {
         optional: &[Extension],
     ) -> Connection {
         assert!(!dpy.is_null(), "attempt connect with null display");
-        let c = XGetXCBConnection(dpy);
+        // SAFETY: the caller guarantees that `dpy` is a valid display
+        let c = unsafe { XGetXCBConnection(dpy) };
 
-        assert!(check_connection_error(c).is_ok());
+        // SAFETY: `c` is the valid connection of `dpy`
+        assert!(unsafe { check_connection_error(c) }.is_ok());
 
         #[cfg(feature = "debug_atom_names")]
-        let dbg_atom_names = {
+        // SAFETY: `DAN_CONN` is registered by the first connection and cleared when it is
+        // dropped; the debug atom names are a debugging aid and not synchronized
+        let dbg_atom_names = unsafe {
             if dan::DAN_CONN.is_null() {
                 dan::DAN_CONN = c;
                 true
@@ -1014,16 +1602,107 @@ impl Connection {
 
         let ext_data = cache_extensions_data(c, mandatory, optional);
 
+        let display_name = {
+            // SAFETY: `dpy` is a valid display
+            let name = unsafe { xlib::XDisplayString(dpy) };
+            if name.is_null() {
+                None
+            } else {
+                // SAFETY: the name is a C string owned by `dpy`
+                unsafe { CStr::from_ptr(name) }
+                    .to_str()
+                    .ok()
+                    .map(String::from)
+            }
+        };
+
         #[cfg(feature = "debug_atom_names")]
         return Connection {
             c,
             dpy,
             ext_data,
+            display_name,
+            auth_info: None,
+            unchecked: Default::default(),
+            queue: Default::default(),
+            error: AtomicI32::new(0),
+            error_taken: AtomicBool::new(false),
+            owned: true,
             dbg_atom_names,
         };
 
         #[cfg(not(feature = "debug_atom_names"))]
-        return Connection { c, dpy, ext_data };
+        return Connection {
+            c,
+            dpy,
+            ext_data,
+            display_name,
+            auth_info: None,
+            unchecked: Default::default(),
+            queue: Default::default(),
+            error: AtomicI32::new(0),
+            error_taken: AtomicBool::new(false),
+            owned: true,
+        };
+    }
+
+    /// Initialize a new `Connection` from an Xlib display owned by the application, to
+    /// send requests and receive events on the XCB connection of the display.
+    ///
+    /// The returned `Connection` does not call `xlib::XCloseDisplay` when dropped.
+    /// Xlib owns the event queue until [Connection::set_event_queue_owner] is called.
+    ///
+    /// This function is behind the `xlib_xcb` cargo feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use x11::xlib;
+    /// # fn main() -> xcb::Result<()> {
+    /// let dpy = unsafe { xlib::XOpenDisplay(std::ptr::null()) };
+    /// // e.g. glXCreateContext(dpy, ...) with Xlib
+    /// {
+    ///     let conn = unsafe { xcb::Connection::from_borrowed_xlib_display(dpy) };
+    ///     conn.set_event_queue_owner(xcb::EventQueueOwner::Xcb);
+    ///     // requests and events through `conn`
+    /// }
+    /// unsafe { xlib::XCloseDisplay(dpy) };
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Safety
+    /// The `dpy` pointer must be a pointer to a valid `xlib::Display`, which must not be
+    /// closed before the returned `Connection` is dropped.
+    #[cfg(feature = "xlib_xcb")]
+    pub unsafe fn from_borrowed_xlib_display(dpy: *mut xlib::Display) -> Connection {
+        // SAFETY: forwarded from the caller
+        unsafe { Self::from_borrowed_xlib_display_and_extensions(dpy, &[], &[]) }
+    }
+
+    /// Initialize a new `Connection` from an Xlib display owned by the application and cache
+    /// the extension data.
+    ///
+    /// See [Connection::from_xlib_display_and_extensions] for the extensions, and
+    /// [Connection::from_borrowed_xlib_display] for the ownership of `dpy`.
+    ///
+    /// This function is behind the `xlib_xcb` cargo feature.
+    ///
+    /// # Panics
+    /// Panics if the connection is null or in error state.
+    ///
+    /// # Safety
+    /// The `dpy` pointer must be a pointer to a valid `xlib::Display`, which must not be
+    /// closed before the returned `Connection` is dropped.
+    #[cfg(feature = "xlib_xcb")]
+    pub unsafe fn from_borrowed_xlib_display_and_extensions(
+        dpy: *mut xlib::Display,
+        mandatory: &[Extension],
+        optional: &[Extension],
+    ) -> Connection {
+        // SAFETY: forwarded from the caller
+        let mut conn = unsafe { Self::from_xlib_display_and_extensions(dpy, mandatory, optional) };
+        conn.owned = false;
+        conn
     }
 
     /// Get the extensions activated for this connection.
@@ -1046,12 +1725,105 @@ impl Connection {
         self.ext_data.iter().map(|eed| eed.ext)
     }
 
+    /// The data of `ext`, if it is one of the active extensions of this connection.
+    pub fn extension_data(&self, ext: Extension) -> Option<&ExtensionData> {
+        self.ext_data.iter().find(|eed| eed.ext == ext)
+    }
+
+    /// Prefetches the data of `ext`, such that [Connection::get_extension_data] does not
+    /// block on a round trip to the server.
+    pub fn prefetch_extension_data(&self, ext: Extension) {
+        crate::ext::prefetch_extension_data(self.c, ext);
+    }
+
+    /// Fetches the data of `ext` from the server, or `None` if the extension is not present
+    /// or the connection is shut down.
+    ///
+    /// Unlike [Connection::extension_data], this works for any extension, not only the
+    /// ones passed to the constructor of the connection. The events and errors of such
+    /// an extension are not resolved by the connection: they are returned as
+    /// [Event::Unknown](crate::Event::Unknown) and [ProtocolError::Unknown], to be
+    /// identified with the `first_event` and `first_error` of the returned data.
+    /// libxcb caches the data: it is fetched once per extension and per connection.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// # #[cfg(feature = "xv")] {
+    /// use xcb::{xv, BaseEvent, Extension};
+    ///
+    /// let (conn, _) = xcb::Connection::connect(None)?;
+    /// conn.prefetch_extension_data(Extension::Xv);
+    /// // other requests are sent while the reply is on its way
+    /// if let Some(data) = conn.get_extension_data(Extension::Xv) {
+    ///     let video_notify = data.first_event + xv::VideoNotifyEvent::NUMBER as u8;
+    ///     # let _ = video_notify;
+    /// }
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_extension_data(&self, ext: Extension) -> Option<ExtensionData> {
+        crate::ext::fetch_extension_data(self.c, ext)
+    }
+
+    /// The name of the display this connection was opened with.
+    ///
+    /// If the connection was opened without display name, this is the value that
+    /// the `DISPLAY` environment variable had at connection time.
+    /// Returns `None` if the connection was not opened by name (e.g. with [Connection::connect_to_fd]
+    /// or [Connection::from_raw_conn]).
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    /// Opens a new connection to the same display as `self`.
+    ///
+    /// The new connection uses the same authentication information as `self` (if any was
+    /// provided) and caches data for the same extensions.
+    /// The preferred screen of the new connection is discarded.
+    ///
+    /// Returns [ConnError::ClosedParseErr], as libxcb when no display can be determined, if
+    /// the display name of `self` is unknown.
+    pub(crate) fn reconnect(&self) -> ConnResult<Connection> {
+        let display_name = self
+            .display_name
+            .as_deref()
+            .ok_or(ConnError::ClosedParseErr)?;
+        let extensions: Vec<Extension> = self.active_extensions().collect();
+
+        let (conn, _) = if let Some((name, data)) = &self.auth_info {
+            Self::connect_to_display_with_binary_auth_info(
+                Some(display_name),
+                BinaryAuthInfo { name, data },
+                &extensions,
+                &[],
+            )?
+        } else {
+            Self::connect_with_extensions(Some(display_name), &extensions, &[])?
+        };
+        Ok(conn)
+    }
+
     /// Returns the inner ffi `xcb_connection_t` pointer
     pub fn get_raw_conn(&self) -> *mut xcb_connection_t {
         self.c
     }
 
+    /// Whether this object disconnects the inner ffi `xcb_connection_t` when dropped.
+    ///
+    /// Returns `false` for the connections built with [Connection::from_borrowed_raw_conn]
+    /// or `Connection::from_borrowed_xlib_display`.
+    pub fn owns_raw_conn(&self) -> bool {
+        self.owned
+    }
+
     /// Consumes this object, returning the inner ffi `xcb_connection_t` pointer
+    ///
+    /// The caller becomes responsible for calling `xcb_disconnect` on the returned pointer,
+    /// unless this object was built with [Connection::from_borrowed_raw_conn].
+    ///
+    /// This object is forgotten, not dropped: nothing is sent on the connection.
     pub fn into_raw_conn(self) -> *mut xcb_connection_t {
         let c = self.c;
         mem::forget(self);
@@ -1095,10 +1867,50 @@ impl Connection {
     /// Note that this length is measured in four-byte units, making the
     /// theoretical maximum lengths roughly 256kB without BIG-REQUESTS and
     /// 16GB with.
+    ///
+    /// libxcb sends the [crate::bigreq::Enable] request itself, the first time the length
+    /// is prefetched or fetched, and then uses the extended length for the large requests.
+    /// It must not be sent by the application: libxcb would not know that BIG-REQUESTS is
+    /// enabled, and would still limit the requests to the length of the setup data.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// let (conn, _) = xcb::Connection::connect(None)?;
+    /// // neither call blocks: BIG-REQUESTS is queried and enabled in the background
+    /// xcb::bigreq::prefetch_extension_data(&conn);
+    /// conn.prefetch_maximum_request_length();
+    ///
+    /// // the rows of 1920 YUY2 pixels that fit in an `xv::PutImage` request, after its
+    /// // header of 40 bytes and the 4 bytes of the extended length
+    /// let max_len = conn.get_maximum_request_length() as usize * 4;
+    /// let rows = (max_len - 44) / (1920 * 2);
+    /// # let _ = rows;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn get_maximum_request_length(&self) -> u32 {
         unsafe { xcb_get_maximum_request_length(self.c) }
     }
 
+    /// Whether a request of `len` bytes is accepted by the server.
+    ///
+    /// `len` is typically given by the `request_len` method of the requests with
+    /// variable-length data (e.g. [crate::x::ChangeProperty::request_len]), to split the data
+    /// in several requests before libxcb shuts the connection down for an oversized request.
+    /// The 4 bytes of the extended length of BIG-REQUESTS are accounted for.
+    ///
+    /// This function may block like [Connection::get_maximum_request_length].
+    pub fn fits_in_request(&self, len: usize) -> bool {
+        let max = self.get_maximum_request_length() as usize * 4;
+        let len = if len > CORE_MAX_REQUEST_LEN {
+            len + 4
+        } else {
+            len
+        };
+        len <= max
+    }
+
     /// Prefetch the maximum request length without blocking.
     ///
     /// Without blocking, does as much work as possible toward computing
@@ -1145,17 +1957,53 @@ impl Connection {
     /// The main difference between `flush` and `check_request` is that `flush` will not report protocol errors.
     /// If a protocol error is emitted by an unchecked void request, it will be reported through the event loop.
     ///
+    /// Returns the error of [Connection::has_error] if the connection is shut down.
+    ///
     /// See also: [wait_for_event](Connection::wait_for_event), [check_request](Connection::check_request),
     /// [send_and_check_request](Connection::send_and_check_request).
     pub fn flush(&self) -> ConnResult<()> {
+        self.poisoned()?;
         unsafe {
             let ret = xcb_flush(self.c);
             if ret > 0 {
                 Ok(())
             } else {
-                self.has_error()?;
-                unreachable!()
+                // the connection is shut down
+                self.has_error().and(Err(ConnError::Connection))
+            }
+        }
+    }
+
+    pub(crate) fn unchecked_tracker(&self) -> MutexGuard<'_, UncheckedTracker> {
+        self.unchecked.lock().unwrap()
+    }
+
+    pub(crate) fn event_queue(&self) -> MutexGuard<'_, EventQueue> {
+        self.queue.lock().unwrap()
+    }
+
+    /// Queues `ev` for [Connection::poll_for_error] if it is the error of a request tracked
+    /// with [Connection::track_unchecked], in which case `true` is returned.
+    pub(crate) unsafe fn intercept_tracked_error(&self, ev: *mut xcb_generic_event_t) -> bool {
+        if ev.is_null() {
+            return false;
+        }
+        let mut tracker = self.unchecked_tracker();
+        // SAFETY: the caller guarantees that `ev` is an event or an error returned by XCB
+        let seq = unsafe { (*ev).full_sequence } as SequenceNumber;
+        // observed also without tracked requests, for `widen_sequence`
+        if !unsafe { is_error(ev) } || tracker.is_empty() {
+            tracker.observe(seq);
+            return false;
+        }
+        match tracker.take(seq) {
+            Some(tag) => {
+                // SAFETY: `ev` is an error, which ownership is passed on
+                let error = unsafe { error::resolve_error(ev as *mut _, &self.ext_data) };
+                tracker.push_error(TaggedError { tag, error });
+                true
             }
+            None => false,
         }
     }
 
@@ -1163,31 +2011,61 @@ impl Connection {
         if ev.is_null() {
             self.has_error()?;
             panic!("xcb_wait_for_event returned null with I/O error");
-        } else if is_error(ev) {
-            Err(error::resolve_error(ev as *mut _, &self.ext_data).into())
-        } else {
-            Ok(event::resolve_event(ev, &self.ext_data))
+        }
+        // SAFETY: the caller passes on the ownership of `ev`, returned by XCB
+        unsafe {
+            if is_error(ev) {
+                Err(error::resolve_error(ev as *mut _, &self.ext_data).into())
+            } else {
+                Ok(event::resolve_event(ev, &self.ext_data))
+            }
         }
     }
 
     /// Resolve an xcb_generic_event_t pointer into an Event.
+    ///
+    /// The event functions of XCB also return the errors of the unchecked requests, with a
+    /// `response_type` of 0: they are resolved with [Connection::resolve_error].
     /// # Safety
-    /// The caller is repsonsible to ensure that the `ev` pointer is not NULL.
+    /// The caller is repsonsible to ensure that the `ev` pointer is not NULL,
+    /// and is not an error.
     /// The ownership of the pointer is effectively transferred to the
     /// returned Event and it will be destroyed when the Event is
     /// dropped.
     pub unsafe fn resolve_event(&self, ev: &mut xcb_generic_event_t) -> Event {
-        event::resolve_event(ev, &self.ext_data)
+        debug_assert!(ev.response_type != 0, "resolve_event called with an error");
+        // SAFETY: forwarded from the caller
+        unsafe { event::resolve_event(ev, &self.ext_data) }
+    }
+
+    /// Resolve an xcb_generic_error_t pointer into a [ProtocolError].
+    ///
+    /// These are the errors returned by the reply functions of XCB, and the events with a
+    /// `response_type` of 0 returned by its event functions, for the unchecked requests.
+    /// The sequence number of the request in error is given by
+    /// [ProtocolError::full_sequence].
+    /// # Safety
+    /// The caller is repsonsible to ensure that the `err` pointer is not NULL.
+    /// The ownership of the pointer is effectively transferred to the
+    /// returned error and it will be destroyed when the error is
+    /// dropped.
+    pub unsafe fn resolve_error(&self, err: &mut xcb_generic_error_t) -> ProtocolError {
+        // SAFETY: forwarded from the caller
+        unsafe { error::resolve_error(err, &self.ext_data) }
     }
 
     unsafe fn handle_poll_for_event(&self, ev: *mut xcb_generic_event_t) -> Result<Option<Event>> {
         if ev.is_null() {
             self.has_error()?;
-            Ok(None)
-        } else if is_error(ev) {
-            Err(error::resolve_error(ev as *mut _, &self.ext_data).into())
-        } else {
-            Ok(Some(event::resolve_event(ev, &self.ext_data)))
+            return Ok(None);
+        }
+        // SAFETY: the caller passes on the ownership of `ev`, returned by XCB
+        unsafe {
+            if is_error(ev) {
+                Err(error::resolve_error(ev as *mut _, &self.ext_data).into())
+            } else {
+                Ok(Some(event::resolve_event(ev, &self.ext_data)))
+            }
         }
     }
 
@@ -1208,8 +2086,8 @@ impl Connection {
     ///                 // may be this particular error is fine?
     ///                 continue;
     ///             }
-    ///             Err(xcb::Error::Protocol(err)) => {
-    ///                 panic!("unexpected protocol error: {:#?}", err);
+    ///             Err(err) => {
+    ///                 panic!("unexpected error: {:#?}", err);
     ///             }
     ///             Ok(event) => event,
     ///         };
@@ -1227,8 +2105,16 @@ impl Connection {
     /// ```
     pub fn wait_for_event(&self) -> Result<Event> {
         unsafe {
-            let ev = xcb_wait_for_event(self.c);
-            self.handle_wait_for_event(ev)
+            if self.event_queue().is_active() {
+                let ev = self.wait_for_bounded_event();
+                return self.handle_wait_for_event(ev);
+            }
+            loop {
+                let ev = xcb_wait_for_event(self.c);
+                if !self.intercept_tracked_error(ev) {
+                    break self.handle_wait_for_event(ev);
+                }
+            }
         }
     }
 
@@ -1241,8 +2127,16 @@ impl Connection {
     /// shut down when this function returns.
     pub fn poll_for_event(&self) -> Result<Option<Event>> {
         unsafe {
-            let ev = xcb_poll_for_event(self.c);
-            self.handle_poll_for_event(ev)
+            if self.event_queue().is_active() {
+                let ev = self.poll_for_bounded_event(true);
+                return self.handle_poll_for_event(ev);
+            }
+            loop {
+                let ev = xcb_poll_for_event(self.c);
+                if !self.intercept_tracked_error(ev) {
+                    break self.handle_poll_for_event(ev);
+                }
+            }
         }
     }
 
@@ -1256,9 +2150,24 @@ impl Connection {
     /// interesting events have already been read from the connection. For
     /// example, callers might use [Connection::wait_for_reply] and be interested
     /// only of events that preceded a specific reply.
+    ///
+    /// It is also the way to drain the events in a loop that polls the file descriptor
+    /// of the connection (see [Connection::as_raw_fd]): once the descriptor is readable,
+    /// call [Connection::poll_for_event] once to read from it, then this function until
+    /// it returns `None` to handle the other events read, without more system calls.
+    /// The ownership of the returned events is the same as with [Connection::poll_for_event].
+    /// See the `poll_loop` example.
     pub fn poll_for_queued_event(&self) -> ProtocolResult<Option<Event>> {
         unsafe {
-            let ev = xcb_poll_for_queued_event(self.c);
+            let ev = if self.event_queue().is_active() {
+                self.poll_for_bounded_event(false)
+            } else {
+                let mut ev = xcb_poll_for_queued_event(self.c);
+                while self.intercept_tracked_error(ev) {
+                    ev = xcb_poll_for_queued_event(self.c);
+                }
+                ev
+            };
             if ev.is_null() {
                 Ok(None)
             } else if is_error(ev) {
@@ -1269,6 +2178,88 @@ impl Connection {
         }
     }
 
+    /// Waits for the next event or error from the server for at most `timeout`.
+    ///
+    /// Returns `Ok(None)` if nothing was received in time. The events already read from
+    /// the connection, e.g. while waiting for a reply, are returned without reading from
+    /// it. Otherwise the requests are flushed and the file descriptor of the connection is
+    /// polled until an event is complete or the timeout expires, such that partial reads
+    /// and interrupted system calls don't end the wait early.
+    ///
+    /// The events read by another thread while this one waits (e.g. in
+    /// [Connection::wait_for_reply]) are only returned at the end of the wait: the event
+    /// loop should run in a single thread.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::time::{Duration, Instant};
+    /// # fn main() -> xcb::Result<()> {
+    /// # let (conn, _) = xcb::Connection::connect(None)?;
+    /// let frame = Duration::from_millis(16);
+    /// loop {
+    ///     let deadline = Instant::now() + frame;
+    ///     while let Some(event) =
+    ///         conn.wait_for_event_timeout(deadline.saturating_duration_since(Instant::now()))?
+    ///     {
+    ///         // handle the event
+    ///         # let _ = event;
+    ///     }
+    ///     // render the frame
+    /// }
+    /// # }
+    /// ```
+    pub fn wait_for_event_timeout(&self, timeout: Duration) -> Result<Option<Event>> {
+        let deadline = Instant::now().checked_add(timeout);
+        // the events already read don't make the file descriptor readable
+        if let Some(event) = self.poll_for_queued_event()? {
+            return Ok(Some(event));
+        }
+        self.flush()?;
+        loop {
+            if let Some(event) = self.poll_for_event()? {
+                return Ok(Some(event));
+            }
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            // rounded up, not to wake up before the deadline
+            let millis = remaining
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(c_int::MAX as u128);
+            let mut fd = libc::pollfd {
+                fd: self.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let res = unsafe { libc::poll(&mut fd, 1, millis as c_int) };
+            if res < 0 && std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted
+            {
+                return Err(Error::Connection(ConnError::Connection));
+            }
+            // a readable descriptor may hold a partial event, or replies only
+        }
+    }
+
+    /// Widens the 16-bit sequence number of an event or an error to a full [SequenceNumber].
+    ///
+    /// The sequence number is reconstructed relative to the last sequence number seen
+    /// by the event functions ([Connection::wait_for_event], [Connection::poll_for_event]...),
+    /// with the algorithm libxcb uses for the responses of the server. The result is within
+    /// 32768 requests before or 32767 requests after the last seen sequence number, which
+    /// covers the events that were already returned, and the errors of the checked requests
+    /// that are still pending.
+    ///
+    /// The result can be compared with the sequence numbers of the cookies, which is not safe
+    /// with 16-bit sequence numbers after 65536 requests.
+    pub fn widen_sequence(&self, seq16: u16) -> SequenceNumber {
+        widen_sequence16(self.unchecked_tracker().last_read(), seq16)
+    }
+
     /// Start listening for a special event.
     ///
     /// Effectively creates an internal special queue for this event
@@ -1280,9 +2271,9 @@ impl Connection {
         unsafe {
             let ext: *mut xcb_extension_t = match XGE::EXTENSION {
                 #[cfg(feature = "xinput")]
-                Extension::Input => &mut xinput::FFI_EXT as *mut _,
+                Extension::Input => ptr::addr_of_mut!(xinput::FFI_EXT),
                 #[cfg(feature = "present")]
-                Extension::Present => &mut present::FFI_EXT as *mut _,
+                Extension::Present => ptr::addr_of_mut!(present::FFI_EXT),
                 _ => unreachable!("only Input and Present have XGE events"),
             };
 
@@ -1327,7 +2318,7 @@ impl Connection {
     /// and the error has already arrived).
     ///
     /// This function will not block even if the reply is not yet available.
-    fn discard_reply<C: Cookie>(&self, cookie: C) {
+    pub(crate) fn discard_reply<C: Cookie>(&self, cookie: C) {
         unsafe {
             xcb_discard_reply64(self.c, cookie.sequence());
         }
@@ -1341,10 +2332,41 @@ impl Connection {
     /// - a list of available visuals,
     /// - a list of available screens,
     /// - the server's maximum request length (in the absence of the
-    /// BIG-REQUESTS extension),
+    ///   BIG-REQUESTS extension),
     /// - and other assorted information.
     ///
     /// See the X protocol specification for more details.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// // the preferred screen is the one of the display name, e.g. 1 for ":0.1"
+    /// let (conn, screen_num) = xcb::Connection::connect(None)?;
+    /// let setup = conn.get_setup();
+    /// println!(
+    ///     "{} (protocol {}.{})",
+    ///     setup.vendor(),
+    ///     setup.protocol_major_version(),
+    ///     setup.protocol_minor_version()
+    /// );
+    ///
+    /// let screen = setup.roots().nth(screen_num as usize).unwrap();
+    /// println!(
+    ///     "root {:?}: {}x{}, depth {}, visual {:#x}, white {:#x}, black {:#x}",
+    ///     screen.root(),
+    ///     screen.width_in_pixels(),
+    ///     screen.height_in_pixels(),
+    ///     screen.root_depth(),
+    ///     screen.root_visual(),
+    ///     screen.white_pixel(),
+    ///     screen.black_pixel()
+    /// );
+    /// for depth in screen.allowed_depths() {
+    ///     println!("depth {}: {} visuals", depth.depth(), depth.visuals().len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn get_setup(&self) -> &Setup {
         unsafe {
             let ptr = xcb_get_setup(self.c);
@@ -1360,8 +2382,45 @@ impl Connection {
     /// are unrecoverable. When such an error occurs, the
     /// connection is shut down and further operations on the
     /// connection have no effect.
+    ///
+    /// The returned error tells why the connection was shut down, e.g.
+    /// [ConnError::Connection] if the server closed it. A shut down connection cannot
+    /// recover: a long-running client has to open a new one.
+    /// [Connection::flush] returns the same error.
+    ///
+    /// Once the error is observed, here or by a method failing because of it, the connection
+    /// is marked as poisoned: [Connection::flush] and the `wait_for_reply` methods then
+    /// return the error without calling into libxcb.
     pub fn has_error(&self) -> ConnResult<()> {
-        unsafe { check_connection_error(self.c) }
+        let code = match self.error.load(Ordering::Relaxed) {
+            0 => unsafe { xcb_connection_has_error(self.c) },
+            code => code,
+        };
+        if code != 0 {
+            self.error.store(code, Ordering::Relaxed);
+        }
+        conn_result(code)
+    }
+
+    /// The error of the connection if it is known to be poisoned, see [Connection::has_error].
+    fn poisoned(&self) -> ConnResult<()> {
+        conn_result(self.error.load(Ordering::Relaxed))
+    }
+
+    /// Returns the error that shut down the connection, the first time it is called after
+    /// the shutdown.
+    ///
+    /// Unlike [Connection::has_error], which keeps returning the error, the error is
+    /// acknowledged and `None` is returned by the subsequent calls. This lets a client
+    /// that observes the connection from several places handle the shutdown only once.
+    /// The connection stays shut down.
+    pub fn take_error(&self) -> Option<ConnError> {
+        let err = self.has_error().err()?;
+        if self.error_taken.swap(true, Ordering::Relaxed) {
+            None
+        } else {
+            Some(err)
+        }
     }
 
     /// Send a request to the X server.
@@ -1413,7 +2472,10 @@ impl Connection {
     where
         R: Request,
     {
-        unsafe { R::Cookie::from_sequence(req.raw_request(self, !R::IS_VOID)) }
+        let seq = req.raw_request(self, !R::IS_VOID);
+        #[cfg(feature = "log")]
+        crate::logging::request_sent::<R>(seq);
+        unsafe { R::Cookie::from_sequence(seq) }
     }
 
     /// Send a checked request to the X server.
@@ -1436,7 +2498,10 @@ impl Connection {
     where
         R: RequestWithoutReply,
     {
-        unsafe { VoidCookieChecked::from_sequence(req.raw_request(self, true)) }
+        let seq = req.raw_request(self, true);
+        #[cfg(feature = "log")]
+        crate::logging::request_sent::<R>(seq);
+        unsafe { VoidCookieChecked::from_sequence(seq) }
     }
 
     /// Send an unchecked request to the X server.
@@ -1464,7 +2529,10 @@ impl Connection {
     where
         R: RequestWithReply,
     {
-        unsafe { R::CookieUnchecked::from_sequence(req.raw_request(self, false)) }
+        let seq = req.raw_request(self, false);
+        #[cfg(feature = "log")]
+        crate::logging::request_sent::<R>(seq);
+        unsafe { R::CookieUnchecked::from_sequence(seq) }
     }
 
     /// Check a checked request for errors.
@@ -1479,6 +2547,9 @@ impl Connection {
     /// sequence number will advance beyond that provided in cookie; this is a
     /// convenience to avoid races in determining whether the sync is needed.
     ///
+    /// If the connection is shut down, the connection error is returned instead, as no
+    /// protocol error can be received anymore: see [Connection::has_error].
+    ///
     /// # Example
     /// ```no_run
     /// # use xcb::x;
@@ -1489,17 +2560,19 @@ impl Connection {
     /// #   Ok(())
     /// # }
     /// ```
-    pub fn check_request(&self, cookie: VoidCookieChecked) -> ProtocolResult<()> {
+    pub fn check_request(&self, cookie: VoidCookieChecked) -> Result<()> {
+        self.poisoned()?;
         let cookie = xcb_void_cookie_t {
             seq: cookie.sequence() as u32,
         };
         let error = unsafe { xcb_request_check(self.c, cookie) };
         if error.is_null() {
+            self.has_error()?;
             Ok(())
         } else {
             unsafe {
                 let res = error::resolve_error(error, &self.ext_data);
-                Err(res)
+                Err(Error::Protocol(res))
             }
         }
     }
@@ -1523,7 +2596,7 @@ impl Connection {
     /// #   Ok(())
     /// # }
     /// ```
-    pub fn send_and_check_request<R>(&self, req: &R) -> ProtocolResult<()>
+    pub fn send_and_check_request<R>(&self, req: &R) -> Result<()>
     where
         R: RequestWithoutReply,
     {
@@ -1551,6 +2624,7 @@ impl Connection {
     where
         C: CookieWithReplyChecked,
     {
+        self.poisoned()?;
         unsafe {
             let mut error: *mut xcb_generic_error_t = std::ptr::null_mut();
             let reply = xcb_wait_for_reply64(self.c, cookie.sequence(), &mut error as *mut _);
@@ -1592,6 +2666,7 @@ impl Connection {
     where
         C: CookieWithReplyUnchecked,
     {
+        self.poisoned()?;
         unsafe {
             let reply = xcb_wait_for_reply64(self.c, cookie.sequence(), ptr::null_mut());
             if reply.is_null() {
@@ -1630,6 +2705,15 @@ impl AsRef<Connection> for Connection {
     }
 }
 
+/// The file descriptor of the socket of the connection, to register in an event loop
+/// (`poll`, `epoll`, `mio`...).
+///
+/// The file descriptor is owned by the connection: it must not be closed, nor read from
+/// or written to. When it is readable, [Connection::poll_for_event] reads the available
+/// data and returns the first event, and [Connection::poll_for_queued_event] returns the
+/// others. Events can also be queued while waiting for a reply, so the queue should be
+/// drained before waiting for the file descriptor to become readable again.
+/// See the `poll_loop` example.
 impl AsRawFd for Connection {
     fn as_raw_fd(&self) -> RawFd {
         unsafe { xcb_get_file_descriptor(self.c) }
@@ -1646,6 +2730,9 @@ unsafe impl as_raw_xcb_connection::AsRawXcbConnection for Connection {
 
 impl Drop for Connection {
     fn drop(&mut self) {
+        #[cfg(feature = "log")]
+        crate::logging::connection_closed(self.display_name());
+
         #[cfg(feature = "debug_atom_names")]
         if self.dbg_atom_names {
             unsafe {
@@ -1653,6 +2740,10 @@ impl Drop for Connection {
             }
         }
 
+        if !self.owned {
+            return;
+        }
+
         #[cfg(not(feature = "xlib_xcb"))]
         unsafe {
             xcb_disconnect(self.c);
@@ -1709,8 +2800,21 @@ mod dan {
     }
 }
 
+fn resolve_display_name(display_name: Option<&str>) -> Option<String> {
+    match display_name {
+        Some(name) if !name.is_empty() => Some(name.to_string()),
+        _ => std::env::var("DISPLAY").ok(),
+    }
+}
+
 unsafe fn check_connection_error(conn: *mut xcb_connection_t) -> ConnResult<()> {
-    match xcb_connection_has_error(conn) {
+    // SAFETY: the caller guarantees that `conn` is a valid connection
+    conn_result(unsafe { xcb_connection_has_error(conn) })
+}
+
+/// The result of the error `code` of `xcb_connection_has_error`.
+fn conn_result(code: c_int) -> ConnResult<()> {
+    match code {
         0 => Ok(()),
         XCB_CONN_ERROR => Err(ConnError::Connection),
         XCB_CONN_CLOSED_EXT_NOTSUPPORTED => Err(ConnError::ClosedExtNotSupported),
@@ -1719,13 +2823,15 @@ unsafe fn check_connection_error(conn: *mut xcb_connection_t) -> ConnResult<()>
         XCB_CONN_CLOSED_PARSE_ERR => Err(ConnError::ClosedParseErr),
         XCB_CONN_CLOSED_INVALID_SCREEN => Err(ConnError::ClosedInvalidScreen),
         XCB_CONN_CLOSED_FDPASSING_FAILED => Err(ConnError::ClosedFdPassingFailed),
-        code => unreachable!("unexpected error code from XCB: {}", code),
+        // a code added by a later libxcb
+        _ => Err(ConnError::Connection),
     }
 }
 
 unsafe fn is_error(ev: *mut xcb_generic_event_t) -> bool {
     debug_assert!(!ev.is_null());
-    (*ev).response_type == 0
+    // SAFETY: the caller guarantees that `ev` is an event or an error returned by XCB
+    unsafe { (*ev).response_type == 0 }
 }
 
 bitflags! {
@@ -0,0 +1,252 @@
+//! Pipelined queries of many windows.
+//!
+//! Window managers and task bars query the windows returned by `x::QueryTree`, which
+//! may be destroyed by their clients at any time during the scan. The queries here capture
+//! the errors of each request, such that a destroyed window does not abort the whole scan.
+
+use crate::base::{Connection, Error, ProtocolResult, Result};
+use crate::error::ProtocolError;
+use crate::x;
+
+/// Maximum property length requested by [window_scan], in 32-bit units.
+const MAX_PROP_LEN: u32 = 0x10000;
+
+/// The replies of the requests of a window scanned by [window_scan].
+///
+/// The requests that failed for another reason than the destruction of the window
+/// (e.g. `BadAtom` for an invalid property) have their error in place of the reply.
+#[derive(Debug)]
+pub struct WindowScan {
+    pub window: x::Window,
+    pub attributes: ProtocolResult<x::GetWindowAttributesReply>,
+    pub geometry: ProtocolResult<x::GetGeometryReply>,
+    /// The properties, in the order they were requested.
+    ///
+    /// The values longer than 256 KiB are truncated (see `x::GetPropertyReply::bytes_after`).
+    pub properties: Vec<(x::Atom, ProtocolResult<x::GetPropertyReply>)>,
+}
+
+/// The result of the scan of a window.
+#[derive(Debug)]
+pub enum WindowScanResult {
+    /// The window does not exist, or was destroyed during the scan: a request
+    /// failed with `BadWindow` or `BadDrawable`.
+    Gone(x::Window),
+    /// The window exists.
+    Found(WindowScan),
+}
+
+impl WindowScanResult {
+    /// The window that was scanned.
+    pub fn window(&self) -> x::Window {
+        match self {
+            WindowScanResult::Gone(window) => *window,
+            WindowScanResult::Found(scan) => scan.window,
+        }
+    }
+
+    /// Whether the window was destroyed before or during the scan.
+    pub fn is_gone(&self) -> bool {
+        matches!(self, WindowScanResult::Gone(_))
+    }
+}
+
+/// Fetches the attributes, the geometry and the properties `props` of each of `windows`.
+///
+/// All the requests are pipelined, such that the scan takes a single round trip.
+/// Each request is checked individually: the windows that are destroyed before or
+/// during the scan are reported as [WindowScanResult::Gone], without affecting the others.
+/// The results are in the order of `windows`.
+///
+/// The properties are requested with `x::ATOM_ANY`.
+/// Only a connection error aborts the scan.
+pub fn window_scan(
+    conn: &Connection,
+    windows: &[x::Window],
+    props: &[x::Atom],
+) -> Result<Vec<WindowScanResult>> {
+    let cookies: Vec<_> = windows
+        .iter()
+        .map(|&window| {
+            let attributes = conn.send_request(&x::GetWindowAttributes { window });
+            let geometry = conn.send_request(&x::GetGeometry {
+                drawable: x::Drawable::Window(window),
+            });
+            let properties: Vec<_> = props
+                .iter()
+                .map(|&property| {
+                    conn.send_request(&x::GetProperty {
+                        delete: false,
+                        window,
+                        property,
+                        r#type: x::ATOM_ANY,
+                        long_offset: 0,
+                        long_length: MAX_PROP_LEN,
+                    })
+                })
+                .collect();
+            (window, attributes, geometry, properties)
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(windows.len());
+    for (window, attributes, geometry, properties) in cookies {
+        // all the replies are collected, even once the window is known to be gone
+        let attributes = capture(conn.wait_for_reply(attributes))?;
+        let geometry = capture(conn.wait_for_reply(geometry))?;
+        let mut gone = is_gone(&attributes) || is_gone(&geometry);
+        let mut props_replies = Vec::with_capacity(properties.len());
+        for (&property, cookie) in props.iter().zip(properties) {
+            let reply = capture(conn.wait_for_reply(cookie))?;
+            gone |= is_gone(&reply);
+            props_replies.push((property, reply));
+        }
+
+        results.push(if gone {
+            WindowScanResult::Gone(window)
+        } else {
+            WindowScanResult::Found(WindowScan {
+                window,
+                attributes,
+                geometry,
+                properties: props_replies,
+            })
+        });
+    }
+    Ok(results)
+}
+
+/// Captures the protocol error of a request, a connection error aborts the scan.
+fn capture<T>(res: Result<T>) -> Result<ProtocolResult<T>> {
+    match res {
+        Ok(reply) => Ok(Ok(reply)),
+        Err(Error::Protocol(err)) => Ok(Err(err)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether the request failed because the window does not exist.
+fn is_gone<T>(res: &ProtocolResult<T>) -> bool {
+    matches!(
+        res,
+        Err(ProtocolError::X(
+            x::Error::Window(_) | x::Error::Drawable(_),
+            _
+        ))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_scan_destroyed_window() {
+        use crate::mock;
+        use crate::{Xid, XidNew};
+        use std::collections::HashSet;
+        use std::sync::{Arc, Mutex};
+
+        const BAD_WINDOW: u8 = 3;
+        const BAD_ATOM: u8 = 5;
+        const BAD_DRAWABLE: u8 = 9;
+        const INVALID_ATOM: u32 = 999;
+
+        let windows = [0x200001, 0x200002, 0x200003];
+        let live = Arc::new(Mutex::new(
+            windows.iter().copied().collect::<HashSet<u32>>(),
+        ));
+
+        // the second client destroys the windows
+        let destroyer = {
+            let live = live.clone();
+            mock::connect(move |req| match req.opcode {
+                // DestroyWindow
+                4 => {
+                    live.lock().unwrap().remove(&req.u32_at(4));
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+
+        let conn = mock::connect(move |req| {
+            // the window or drawable of the scan requests
+            let window = req.data.get(4..8).map_or(0, |_| req.u32_at(4));
+            let exists = live.lock().unwrap().contains(&window);
+            match req.opcode {
+                // GetWindowAttributes
+                3 if exists => {
+                    let resp = vec![mock::reply(req.seq, 0, &[0; 36])];
+                    if window == 0x200002 {
+                        // destroyed once its first request is answered
+                        destroyer
+                            .send_and_check_request(&x::DestroyWindow {
+                                window: unsafe { x::Window::new(window) },
+                            })
+                            .unwrap();
+                    }
+                    resp
+                }
+                3 => vec![mock::error(req.seq, BAD_WINDOW, window, 3)],
+                // GetGeometry
+                14 if exists => {
+                    let mut body = vec![0u8; 24];
+                    body[8..10].copy_from_slice(&(window as u16).to_ne_bytes());
+                    vec![mock::reply(req.seq, 24, &body)]
+                }
+                14 => vec![mock::error(req.seq, BAD_DRAWABLE, window, 14)],
+                // GetProperty
+                20 if !exists => vec![mock::error(req.seq, BAD_WINDOW, window, 20)],
+                20 if req.u32_at(8) == INVALID_ATOM => {
+                    vec![mock::error(req.seq, BAD_ATOM, INVALID_ATOM, 20)]
+                }
+                20 => {
+                    let mut body = vec![0u8; 24];
+                    body[0..4].copy_from_slice(&x::ATOM_STRING.resource_id().to_ne_bytes());
+                    body[8..12].copy_from_slice(&4u32.to_ne_bytes());
+                    body.extend_from_slice(b"name");
+                    vec![mock::reply(req.seq, 8, &body)]
+                }
+                _ => mock::default_response(req),
+            }
+        });
+
+        let windows: Vec<_> = windows
+            .iter()
+            .map(|&w| unsafe { x::Window::new(w) })
+            .collect();
+        let invalid_atom = unsafe { x::Atom::new(INVALID_ATOM) };
+        let results = window_scan(&conn, &windows, &[x::ATOM_WM_NAME, invalid_atom]).unwrap();
+
+        assert_eq!(results.len(), 3);
+        for (result, window) in results.iter().zip(&windows) {
+            assert_eq!(result.window(), *window);
+        }
+        assert!(!results[0].is_gone());
+        assert!(results[1].is_gone());
+        assert!(!results[2].is_gone());
+
+        for result in [&results[0], &results[2]] {
+            let scan = match result {
+                WindowScanResult::Found(scan) => scan,
+                WindowScanResult::Gone(window) => panic!("{:?} is gone", window),
+            };
+            assert!(scan.attributes.is_ok());
+            let width = scan.geometry.as_ref().unwrap().width();
+            assert_eq!(width as u32, scan.window.resource_id() & 0xffff);
+            // the partial results
+            assert_eq!(scan.properties.len(), 2);
+            assert_eq!(scan.properties[0].0, x::ATOM_WM_NAME);
+            assert_eq!(
+                scan.properties[0].1.as_ref().unwrap().value::<u8>(),
+                b"name"
+            );
+            assert_eq!(scan.properties[1].0, invalid_atom);
+            assert!(matches!(
+                scan.properties[1].1,
+                Err(ProtocolError::X(x::Error::Atom(_), _))
+            ));
+        }
+    }
+}
@@ -0,0 +1,1333 @@
+//! A minimal reference compositing manager built on the Composite, Damage, XFixes and Render
+//! extensions.
+//!
+//! [Basic] redirects the top-level windows of a screen and paints them, bottom to top,
+//! onto the composite overlay window. It shows the bookkeeping every compositor has to do
+//! (stacking order, window pixmaps, pictures and damage objects) and can serve as a
+//! starting point for more elaborate compositors.
+//!
+//! This module is only available with the `compositor` cargo feature.
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::error::ProtocolError;
+use crate::event::Event;
+use crate::ext::Extension;
+use crate::{composite, damage, render, x, xfixes};
+
+use std::collections::HashMap;
+
+/// Geometry of a window, as reported by `ConfigureNotify`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Geometry {
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    border_width: u16,
+}
+
+impl From<&x::CreateNotifyEvent> for Geometry {
+    fn from(ev: &x::CreateNotifyEvent) -> Geometry {
+        Geometry {
+            x: ev.x(),
+            y: ev.y(),
+            width: ev.width(),
+            height: ev.height(),
+            border_width: ev.border_width(),
+        }
+    }
+}
+
+impl From<&x::ConfigureNotifyEvent> for Geometry {
+    fn from(ev: &x::ConfigureNotifyEvent) -> Geometry {
+        Geometry {
+            x: ev.x(),
+            y: ev.y(),
+            width: ev.width(),
+            height: ev.height(),
+            border_width: ev.border_width(),
+        }
+    }
+}
+
+impl From<&x::GetGeometryReply> for Geometry {
+    fn from(reply: &x::GetGeometryReply) -> Geometry {
+        Geometry {
+            x: reply.x(),
+            y: reply.y(),
+            width: reply.width(),
+            height: reply.height(),
+            border_width: reply.border_width(),
+        }
+    }
+}
+
+impl Geometry {
+    /// The outer size of the window (i.e. of its pixmap), border included.
+    fn outer_size(&self) -> (u16, u16) {
+        (
+            self.width + 2 * self.border_width,
+            self.height + 2 * self.border_width,
+        )
+    }
+
+    fn outer_rect(&self) -> x::Rectangle {
+        let (width, height) = self.outer_size();
+        x::Rectangle {
+            x: self.x,
+            y: self.y,
+            width,
+            height,
+        }
+    }
+}
+
+/// How a window is painted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    /// Never painted.
+    InputOnly,
+    InputOutput {
+        format: render::Pictformat,
+        op: render::PictOp,
+    },
+}
+
+/// The pixmap of a mapped window and the picture it is painted from.
+#[derive(Debug)]
+struct Paint {
+    pixmap: x::Pixmap,
+    picture: render::Picture,
+}
+
+#[derive(Debug)]
+struct Client {
+    window: x::Window,
+    geometry: Geometry,
+    mapped: bool,
+    /// `None` until the window is mapped for the first time.
+    kind: Option<Kind>,
+    /// Created when the window is mapped for the first time and kept until the window
+    /// is destroyed, at which point the server frees it.
+    damage: Option<damage::Damage>,
+    /// Only set while the window is mapped.
+    paint: Option<Paint>,
+}
+
+impl Client {
+    fn new(window: x::Window, geometry: Geometry) -> Client {
+        Client {
+            window,
+            geometry,
+            mapped: false,
+            kind: None,
+            damage: None,
+            paint: None,
+        }
+    }
+}
+
+/// The top-level windows, in stacking order from bottom to top.
+#[derive(Debug, Default)]
+struct Stack {
+    clients: Vec<Client>,
+}
+
+impl Stack {
+    fn position(&self, window: x::Window) -> Option<usize> {
+        self.clients.iter().position(|c| c.window == window)
+    }
+
+    fn get_mut(&mut self, window: x::Window) -> Option<&mut Client> {
+        self.clients.iter_mut().find(|c| c.window == window)
+    }
+
+    fn by_damage(&self, damage: damage::Damage) -> Option<&Client> {
+        self.clients.iter().find(|c| c.damage == Some(damage))
+    }
+
+    /// Places `client` above `sibling`, at the bottom if `sibling` is `x::WINDOW_NONE`,
+    /// and on top if `sibling` is unknown.
+    fn insert(&mut self, client: Client, sibling: x::Window) {
+        let pos = if sibling.is_none() {
+            0
+        } else {
+            self.position(sibling)
+                .map_or(self.clients.len(), |pos| pos + 1)
+        };
+        self.clients.insert(pos, client);
+    }
+
+    fn push_top(&mut self, client: Client) {
+        self.clients.push(client);
+    }
+
+    fn restack(&mut self, window: x::Window, sibling: x::Window) {
+        if let Some(client) = self.remove(window) {
+            self.insert(client, sibling);
+        }
+    }
+
+    fn remove(&mut self, window: x::Window) -> Option<Client> {
+        self.position(window).map(|pos| self.clients.remove(pos))
+    }
+}
+
+/// Whether `err` is caused by a window that was unmapped or destroyed in the meantime.
+fn is_gone(err: &ProtocolError) -> bool {
+    matches!(
+        err,
+        ProtocolError::X(
+            x::Error::Window(_) | x::Error::Match(_) | x::Error::Drawable(_),
+            _
+        )
+    )
+}
+
+fn query_versions(conn: &Connection) -> Result<()> {
+    let composite = conn.send_request(&composite::QueryVersion {
+        client_major_version: 0,
+        client_minor_version: 4,
+    });
+    let damage = conn.send_request(&damage::QueryVersion {
+        client_major_version: 1,
+        client_minor_version: 1,
+    });
+    let xfixes = conn.send_request(&xfixes::QueryVersion {
+        client_major_version: 5,
+        client_minor_version: 0,
+    });
+    let render = conn.send_request(&render::QueryVersion {
+        client_major_version: 0,
+        client_minor_version: 11,
+    });
+    conn.wait_for_reply(composite)
+        .context("QueryVersion", || composite::XNAME)?;
+    conn.wait_for_reply(damage)
+        .context("QueryVersion", || damage::XNAME)?;
+    conn.wait_for_reply(xfixes)
+        .context("QueryVersion", || xfixes::XNAME)?;
+    conn.wait_for_reply(render)
+        .context("QueryVersion", || render::XNAME)?;
+    Ok(())
+}
+
+/// Returns how to paint the windows of each visual.
+fn query_formats(conn: &Connection) -> Result<HashMap<x::Visualid, Kind>> {
+    let cookie = conn.send_request(&render::QueryPictFormats {});
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("QueryPictFormats", || render::XNAME)?;
+
+    let ops: HashMap<_, _> = reply
+        .formats()
+        .iter()
+        .map(|info| {
+            let op = if info.direct().alpha_mask != 0 {
+                render::PictOp::Over
+            } else {
+                render::PictOp::Src
+            };
+            (info.id(), op)
+        })
+        .collect();
+    let mut formats = HashMap::new();
+    for screen in reply.screens() {
+        for depth in screen.depths() {
+            for visual in depth.visuals() {
+                if let Some(op) = ops.get(&visual.format) {
+                    let kind = Kind::InputOutput {
+                        format: visual.format,
+                        op: *op,
+                    };
+                    formats.insert(visual.visual, kind);
+                }
+            }
+        }
+    }
+    Ok(formats)
+}
+
+/// A basic compositing manager for one screen.
+///
+/// On creation, [Basic] redirects the children of the root window with
+/// `composite::Redirect::Manual`, and paints them on the composite overlay window each
+/// time [Basic::frame] is called. The windows are tracked with the
+/// `SubstructureNotify` events of the root window and their content with damage events,
+/// which must be passed to [Basic::handle_event].
+///
+/// Windows are painted through an off-screen buffer to avoid flickering. Windows with
+/// an alpha channel are blended with `render::PictOp::Over`, the others are copied.
+/// Changes of the size of the screen are not handled.
+///
+/// All the server resources are freed when the window they belong to is unmapped or
+/// destroyed, and when [Basic] is dropped, which also stops the redirection.
+///
+/// The connection must be opened with the Composite, Damage, XFixes and Render
+/// extensions (see [Connection::connect_with_extensions]), such that damage events
+/// are resolved. Only one compositing manager can run on a screen: to cooperate with
+/// other compositors, acquire the `_NET_WM_CM` selection beforehand
+/// (see [crate::managers::acquire]).
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// use xcb::Extension;
+///
+/// let (conn, screen_num) = xcb::Connection::connect_with_extensions(
+///     None,
+///     &[Extension::Composite, Extension::Damage, Extension::XFixes, Extension::Render],
+///     &[],
+/// )?;
+/// let mut compositor = xcb::compositor::Basic::new(&conn, screen_num)?;
+/// loop {
+///     let event = conn.wait_for_event()?;
+///     compositor.handle_event(&event)?;
+///     while let Some(event) = conn.poll_for_queued_event()? {
+///         compositor.handle_event(&event)?;
+///     }
+///     compositor.frame()?;
+/// }
+/// # }
+/// ```
+pub struct Basic<'a> {
+    conn: &'a Connection,
+    root: x::Window,
+    width: u16,
+    height: u16,
+    overlay: x::Window,
+    overlay_picture: render::Picture,
+    buffer: x::Pixmap,
+    buffer_picture: render::Picture,
+    /// The painting parameters of the render format of each visual.
+    formats: HashMap<x::Visualid, Kind>,
+    /// The damaged area of the screen, repainted by the next frame.
+    damaged: xfixes::Region,
+    scratch: xfixes::Region,
+    dirty: bool,
+    background: render::Color,
+    stack: Stack,
+}
+
+impl<'a> std::fmt::Debug for Basic<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Basic")
+            .field("root", &self.root)
+            .field("overlay", &self.overlay)
+            .field("dirty", &self.dirty)
+            .field("stack", &self.stack)
+            .finish()
+    }
+}
+
+impl<'a> Basic<'a> {
+    /// Starts compositing the screen `screen_num`.
+    ///
+    /// The windows that already exist are picked up. The server is grabbed while they
+    /// are inspected.
+    ///
+    /// Fails if another compositing manager redirects the screen (`BadAccess` error
+    /// of `RedirectSubwindows`).
+    ///
+    /// # Panics
+    /// Panics if the connection does not have the Composite, Damage, XFixes or
+    /// Render extension, or if `screen_num` does not exist.
+    pub fn new(conn: &'a Connection, screen_num: i32) -> Result<Basic<'a>> {
+        for ext in [
+            Extension::Composite,
+            Extension::Damage,
+            Extension::XFixes,
+            Extension::Render,
+        ] {
+            assert!(
+                conn.active_extensions().any(|e| e == ext),
+                "compositor::Basic requires the {:?} extension",
+                ext
+            );
+        }
+
+        let screen = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .unwrap_or_else(|| panic!("no screen {}", screen_num));
+        let (root, width, height) = (
+            screen.root(),
+            screen.width_in_pixels(),
+            screen.height_in_pixels(),
+        );
+        let (root_visual, root_depth) = (screen.root_visual(), screen.root_depth());
+
+        query_versions(conn)?;
+        let formats = query_formats(conn)?;
+
+        conn.send_request(&x::GrabServer {});
+        let mut compositor = Self::init(
+            conn,
+            root,
+            (width, height),
+            formats,
+            root_visual,
+            root_depth,
+        );
+        let res = compositor.as_mut().map_or(Ok(()), |c| c.scan());
+        conn.send_request(&x::UngrabServer {});
+        conn.flush()?;
+        res?;
+        compositor
+    }
+
+    fn init(
+        conn: &'a Connection,
+        root: x::Window,
+        (width, height): (u16, u16),
+        formats: HashMap<x::Visualid, Kind>,
+        root_visual: x::Visualid,
+        root_depth: u8,
+    ) -> Result<Basic<'a>> {
+        conn.send_request(&x::ChangeWindowAttributes {
+            window: root,
+            value_list: &[x::Cw::EventMask(x::EventMask::SUBSTRUCTURE_NOTIFY)],
+        });
+        conn.send_and_check_request(&composite::RedirectSubwindows {
+            window: root,
+            update: composite::Redirect::Manual,
+        })
+        .context("RedirectSubwindows", || "root window")?;
+
+        let cookie = conn.send_request(&composite::GetOverlayWindow { window: root });
+        let overlay = match conn.wait_for_reply(cookie) {
+            Ok(reply) => reply.overlay_win(),
+            Err(err) => {
+                conn.send_request(&composite::UnredirectSubwindows {
+                    window: root,
+                    update: composite::Redirect::Manual,
+                });
+                return Err(err).context("GetOverlayWindow", || composite::XNAME);
+            }
+        };
+        conn.send_request(&x::ChangeWindowAttributes {
+            window: overlay,
+            value_list: &[x::Cw::EventMask(x::EventMask::EXPOSURE)],
+        });
+
+        // let input go through the overlay window
+        let empty: xfixes::Region = conn.generate_id();
+        conn.send_request(&xfixes::CreateRegion {
+            region: empty,
+            rectangles: &[],
+        });
+        conn.send_request(&xfixes::SetWindowShapeRegion {
+            dest: overlay,
+            dest_kind: crate::shape::Sk::Input,
+            x_offset: 0,
+            y_offset: 0,
+            region: empty,
+        });
+        conn.send_request(&xfixes::DestroyRegion { region: empty });
+
+        let root_format = match formats.get(&root_visual) {
+            Some(Kind::InputOutput { format, .. }) => *format,
+            _ => panic!("Render reports no format for the root visual"),
+        };
+        let overlay_picture = conn.generate_id();
+        conn.send_request(&render::CreatePicture {
+            pid: overlay_picture,
+            drawable: x::Drawable::Window(overlay),
+            format: root_format,
+            value_list: &[],
+        });
+        let buffer = conn.generate_id();
+        conn.send_request(&x::CreatePixmap {
+            depth: root_depth,
+            pid: buffer,
+            drawable: x::Drawable::Window(root),
+            width,
+            height,
+        });
+        let buffer_picture = conn.generate_id();
+        conn.send_request(&render::CreatePicture {
+            pid: buffer_picture,
+            drawable: x::Drawable::Pixmap(buffer),
+            format: root_format,
+            value_list: &[],
+        });
+
+        let damaged = conn.generate_id();
+        conn.send_request(&xfixes::CreateRegion {
+            region: damaged,
+            rectangles: &[x::Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }],
+        });
+        let scratch = conn.generate_id();
+        conn.send_request(&xfixes::CreateRegion {
+            region: scratch,
+            rectangles: &[],
+        });
+
+        Ok(Basic {
+            conn,
+            root,
+            width,
+            height,
+            overlay,
+            overlay_picture,
+            buffer,
+            buffer_picture,
+            formats,
+            damaged,
+            scratch,
+            dirty: true,
+            background: render::Color {
+                red: 0x3000,
+                green: 0x3000,
+                blue: 0x3000,
+                alpha: 0xffff,
+            },
+            stack: Stack::default(),
+        })
+    }
+
+    /// Picks up the existing children of the root window.
+    fn scan(&mut self) -> Result<()> {
+        let cookie = self.conn.send_request(&x::QueryTree { window: self.root });
+        let tree = self
+            .conn
+            .wait_for_reply(cookie)
+            .context("QueryTree", || "root window")?;
+
+        let cookies: Vec<_> = tree
+            .children()
+            .iter()
+            .filter(|w| **w != self.overlay)
+            .map(|&window| {
+                (
+                    window,
+                    self.conn.send_request(&x::GetWindowAttributes { window }),
+                    self.conn.send_request(&x::GetGeometry {
+                        drawable: x::Drawable::Window(window),
+                    }),
+                )
+            })
+            .collect();
+        for (window, attributes, geometry) in cookies {
+            let attributes = self.conn.wait_for_reply(attributes);
+            let geometry = self.conn.wait_for_reply(geometry);
+            let (attributes, geometry) = match attributes.and_then(|a| geometry.map(|g| (a, g))) {
+                Ok(replies) => replies,
+                // destroyed before the server was grabbed
+                Err(crate::Error::Protocol(err)) if is_gone(&err) => continue,
+                Err(err) => {
+                    return Err(err).context("GetWindowAttributes", || {
+                        format!("window {:#x}", window.resource_id())
+                    })
+                }
+            };
+            let mut client = Client::new(window, Geometry::from(&geometry));
+            client.kind = Some(self.kind(attributes.class(), attributes.visual()));
+            self.stack.push_top(client);
+            if attributes.map_state() != x::MapState::Unmapped {
+                self.map(window)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn kind(&self, class: x::WindowClass, visual: x::Visualid) -> Kind {
+        match class {
+            x::WindowClass::InputOnly => Kind::InputOnly,
+            _ => self
+                .formats
+                .get(&visual)
+                .copied()
+                .unwrap_or(Kind::InputOnly),
+        }
+    }
+
+    /// The composite overlay window, on which the screen is painted.
+    pub fn overlay(&self) -> x::Window {
+        self.overlay
+    }
+
+    /// The tracked top-level windows, in stacking order from bottom to top.
+    pub fn windows(&self) -> impl Iterator<Item = x::Window> + '_ {
+        self.stack.clients.iter().map(|c| c.window)
+    }
+
+    /// Sets the color painted where no window is mapped.
+    pub fn set_background(&mut self, color: render::Color) {
+        self.background = color;
+        self.damage_rect(x::Rectangle {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        });
+    }
+
+    /// Whether part of the screen has to be repainted by [Basic::frame].
+    pub fn needs_frame(&self) -> bool {
+        self.dirty
+    }
+
+    /// Updates the state of the compositor with `event`.
+    ///
+    /// Returns whether the event was of interest to the compositor. Other events are ignored.
+    /// Mapping a new window costs a round trip to the server.
+    pub fn handle_event(&mut self, event: &Event) -> Result<bool> {
+        match event {
+            Event::X(x::Event::CreateNotify(ev)) if ev.parent() == self.root => {
+                self.create(ev.window(), Geometry::from(ev));
+            }
+            Event::X(x::Event::ConfigureNotify(ev)) if ev.event() == self.root => {
+                self.configure(ev.window(), Geometry::from(ev), ev.above_sibling())?;
+            }
+            Event::X(x::Event::MapNotify(ev)) if ev.event() == self.root => {
+                self.map(ev.window())?;
+            }
+            Event::X(x::Event::UnmapNotify(ev)) if ev.event() == self.root => {
+                self.unmap(ev.window());
+            }
+            Event::X(x::Event::DestroyNotify(ev)) if ev.event() == self.root => {
+                // the server destroys the damage object with the window
+                if let Some(client) = self.stack.remove(ev.window()) {
+                    if client.mapped {
+                        self.damage_rect(client.geometry.outer_rect());
+                    }
+                    self.free_paint(client.paint);
+                }
+            }
+            Event::X(x::Event::ReparentNotify(ev)) if ev.event() == self.root => {
+                self.reparent(ev.window(), ev.parent())?;
+            }
+            Event::X(x::Event::Expose(ev)) if ev.window() == self.overlay => {
+                self.damage_rect(x::Rectangle {
+                    x: ev.x() as i16,
+                    y: ev.y() as i16,
+                    width: ev.width(),
+                    height: ev.height(),
+                });
+            }
+            Event::Damage(damage::Event::Notify(ev)) => self.repair(ev.damage()),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn create(&mut self, window: x::Window, geometry: Geometry) {
+        if window != self.overlay && self.stack.position(window).is_none() {
+            self.stack.push_top(Client::new(window, geometry));
+        }
+    }
+
+    fn configure(
+        &mut self,
+        window: x::Window,
+        geometry: Geometry,
+        sibling: x::Window,
+    ) -> Result<()> {
+        let client = match self.stack.get_mut(window) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        let old = std::mem::replace(&mut client.geometry, geometry);
+        let resized = old.outer_size() != geometry.outer_size();
+        let mapped = client.mapped;
+        // a resized window has a new pixmap
+        let paint = if resized { client.paint.take() } else { None };
+        self.free_paint(paint);
+        self.stack.restack(window, sibling);
+
+        if mapped {
+            self.damage_rect(old.outer_rect());
+            if resized {
+                self.map(window)?;
+            } else {
+                self.damage_rect(geometry.outer_rect());
+            }
+        }
+        Ok(())
+    }
+
+    fn map(&mut self, window: x::Window) -> Result<()> {
+        let conn = self.conn;
+        let detail = || format!("window {:#x}", window.resource_id());
+
+        let kind = match self.stack.get_mut(window) {
+            Some(client) => {
+                client.mapped = true;
+                client.kind
+            }
+            None => return Ok(()),
+        };
+        let kind = match kind {
+            Some(kind) => kind,
+            None => {
+                let cookie = conn.send_request(&x::GetWindowAttributes { window });
+                match conn.wait_for_reply(cookie) {
+                    Ok(attributes) => self.kind(attributes.class(), attributes.visual()),
+                    Err(crate::Error::Protocol(err)) if is_gone(&err) => return Ok(()),
+                    Err(err) => return Err(err).context("GetWindowAttributes", detail),
+                }
+            }
+        };
+        self.stack.get_mut(window).unwrap().kind = Some(kind);
+        let format = match kind {
+            Kind::InputOnly => return Ok(()),
+            Kind::InputOutput { format, .. } => format,
+        };
+
+        let pixmap = conn.generate_id();
+        let cookie = conn.send_request_checked(&composite::NameWindowPixmap { window, pixmap });
+        match conn.check_request(cookie) {
+            Ok(()) => {}
+            // unmapped or destroyed in the meantime
+            Err(err) if is_gone(&err) => return Ok(()),
+            Err(err) => return Err(err).context("NameWindowPixmap", detail),
+        }
+        let picture = conn.generate_id();
+        conn.send_request(&render::CreatePicture {
+            pid: picture,
+            drawable: x::Drawable::Pixmap(pixmap),
+            format,
+            value_list: &[],
+        });
+
+        let client = self.stack.get_mut(window).unwrap();
+        if client.damage.is_none() {
+            let damage = conn.generate_id();
+            conn.send_request(&damage::Create {
+                damage,
+                drawable: x::Drawable::Window(window),
+                level: damage::ReportLevel::NonEmpty,
+            });
+            client.damage = Some(damage);
+        }
+        client.paint = Some(Paint { pixmap, picture });
+        let rect = client.geometry.outer_rect();
+        self.damage_rect(rect);
+        Ok(())
+    }
+
+    fn unmap(&mut self, window: x::Window) {
+        let client = match self.stack.get_mut(window) {
+            Some(client) => client,
+            None => return,
+        };
+        let paint = client.paint.take();
+        let rect = client.geometry.outer_rect();
+        if std::mem::replace(&mut client.mapped, false) {
+            self.damage_rect(rect);
+        }
+        self.free_paint(paint);
+    }
+
+    fn reparent(&mut self, window: x::Window, parent: x::Window) -> Result<()> {
+        if parent != self.root {
+            if let Some(client) = self.stack.remove(window) {
+                if client.mapped {
+                    self.damage_rect(client.geometry.outer_rect());
+                }
+                self.free_paint(client.paint);
+                if let Some(damage) = client.damage {
+                    self.conn.send_request(&damage::Destroy { damage });
+                }
+            }
+            return Ok(());
+        }
+
+        // the size is not part of the event
+        let cookie = self.conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        });
+        match self.conn.wait_for_reply(cookie) {
+            Ok(geometry) => {
+                self.create(window, Geometry::from(&geometry));
+                Ok(())
+            }
+            Err(crate::Error::Protocol(err)) if is_gone(&err) => Ok(()),
+            Err(err) => Err(err).context("GetGeometry", || {
+                format!("window {:#x}", window.resource_id())
+            }),
+        }
+    }
+
+    /// Collects the damage of a window into the damaged area of the screen.
+    fn repair(&mut self, damage: damage::Damage) {
+        let client = match self.stack.by_damage(damage) {
+            Some(client) => client,
+            None => return,
+        };
+        if !client.mapped {
+            self.conn.send_request(&damage::Subtract {
+                damage,
+                repair: xfixes::Region::none(),
+                parts: xfixes::Region::none(),
+            });
+            return;
+        }
+        let geometry = client.geometry;
+        self.conn.send_request(&damage::Subtract {
+            damage,
+            repair: xfixes::Region::none(),
+            parts: self.scratch,
+        });
+        // the damage is relative to the origin of the window, inside the border
+        self.conn.send_request(&xfixes::TranslateRegion {
+            region: self.scratch,
+            dx: geometry.x + geometry.border_width as i16,
+            dy: geometry.y + geometry.border_width as i16,
+        });
+        self.conn.send_request(&xfixes::UnionRegion {
+            source1: self.damaged,
+            source2: self.scratch,
+            destination: self.damaged,
+        });
+        self.dirty = true;
+    }
+
+    fn damage_rect(&mut self, rect: x::Rectangle) {
+        self.conn.send_request(&xfixes::SetRegion {
+            region: self.scratch,
+            rectangles: &[rect],
+        });
+        self.conn.send_request(&xfixes::UnionRegion {
+            source1: self.damaged,
+            source2: self.scratch,
+            destination: self.damaged,
+        });
+        self.dirty = true;
+    }
+
+    fn free_paint(&self, paint: Option<Paint>) {
+        if let Some(paint) = paint {
+            self.conn.send_request(&render::FreePicture {
+                picture: paint.picture,
+            });
+            self.conn.send_request(&x::FreePixmap {
+                pixmap: paint.pixmap,
+            });
+        }
+    }
+
+    /// Repaints the damaged area of the screen, if any, and flushes the connection.
+    pub fn frame(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let conn = self.conn;
+        let screen = x::Rectangle {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+
+        conn.send_request(&xfixes::SetPictureClipRegion {
+            picture: self.buffer_picture,
+            region: self.damaged,
+            x_origin: 0,
+            y_origin: 0,
+        });
+        conn.send_request(&render::FillRectangles {
+            op: render::PictOp::Src,
+            dst: self.buffer_picture,
+            color: self.background,
+            rects: &[screen],
+        });
+        for client in &self.stack.clients {
+            let (paint, op) = match (&client.paint, client.kind) {
+                (Some(paint), Some(Kind::InputOutput { op, .. })) if client.mapped => (paint, op),
+                _ => continue,
+            };
+            let (width, height) = client.geometry.outer_size();
+            conn.send_request(&render::Composite {
+                op,
+                src: paint.picture,
+                mask: render::Picture::none(),
+                dst: self.buffer_picture,
+                src_x: 0,
+                src_y: 0,
+                mask_x: 0,
+                mask_y: 0,
+                dst_x: client.geometry.x,
+                dst_y: client.geometry.y,
+                width,
+                height,
+            });
+        }
+
+        conn.send_request(&xfixes::SetPictureClipRegion {
+            picture: self.overlay_picture,
+            region: self.damaged,
+            x_origin: 0,
+            y_origin: 0,
+        });
+        conn.send_request(&render::Composite {
+            op: render::PictOp::Src,
+            src: self.buffer_picture,
+            mask: render::Picture::none(),
+            dst: self.overlay_picture,
+            src_x: 0,
+            src_y: 0,
+            mask_x: 0,
+            mask_y: 0,
+            dst_x: 0,
+            dst_y: 0,
+            width: self.width,
+            height: self.height,
+        });
+        conn.send_request(&xfixes::SetRegion {
+            region: self.damaged,
+            rectangles: &[],
+        });
+        self.dirty = false;
+        conn.flush()?;
+        Ok(())
+    }
+}
+
+impl<'a> Drop for Basic<'a> {
+    fn drop(&mut self) {
+        let conn = self.conn;
+        for client in std::mem::take(&mut self.stack.clients) {
+            self.free_paint(client.paint);
+            if let Some(damage) = client.damage {
+                conn.send_request(&damage::Destroy { damage });
+            }
+        }
+        conn.send_request(&render::FreePicture {
+            picture: self.overlay_picture,
+        });
+        conn.send_request(&render::FreePicture {
+            picture: self.buffer_picture,
+        });
+        conn.send_request(&x::FreePixmap {
+            pixmap: self.buffer,
+        });
+        conn.send_request(&xfixes::DestroyRegion {
+            region: self.damaged,
+        });
+        conn.send_request(&xfixes::DestroyRegion {
+            region: self.scratch,
+        });
+        conn.send_request(&composite::ReleaseOverlayWindow { window: self.root });
+        conn.send_request(&composite::UnredirectSubwindows {
+            window: self.root,
+            update: composite::Redirect::Manual,
+        });
+        let _ = conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32) -> x::Window {
+        use crate::XidNew;
+        unsafe { x::Window::new(id) }
+    }
+
+    fn geometry(x: i16, y: i16, width: u16, height: u16) -> Geometry {
+        Geometry {
+            x,
+            y,
+            width,
+            height,
+            border_width: 1,
+        }
+    }
+
+    #[test]
+    fn test_stack() {
+        let mut stack = Stack::default();
+        let ids = |stack: &Stack| {
+            stack
+                .clients
+                .iter()
+                .map(|c| c.window.resource_id())
+                .collect::<Vec<_>>()
+        };
+        for id in 1..=3 {
+            stack.push_top(Client::new(window(id), geometry(0, 0, 10, 10)));
+        }
+        assert_eq!(ids(&stack), [1, 2, 3]);
+
+        stack.restack(window(1), window(3));
+        assert_eq!(ids(&stack), [2, 3, 1]);
+        stack.restack(window(1), x::WINDOW_NONE);
+        assert_eq!(ids(&stack), [1, 2, 3]);
+        stack.restack(window(3), window(1));
+        assert_eq!(ids(&stack), [1, 3, 2]);
+        // unknown sibling: on top
+        stack.restack(window(1), window(42));
+        assert_eq!(ids(&stack), [3, 2, 1]);
+        // unknown window
+        stack.restack(window(42), window(1));
+        assert_eq!(ids(&stack), [3, 2, 1]);
+
+        assert_eq!(stack.remove(window(2)).unwrap().window, window(2));
+        assert!(stack.remove(window(2)).is_none());
+        assert_eq!(ids(&stack), [3, 1]);
+    }
+
+    #[test]
+    fn test_geometry() {
+        let geometry = geometry(10, -5, 100, 50);
+        assert_eq!(geometry.outer_size(), (102, 52));
+        let rect = geometry.outer_rect();
+        assert_eq!((rect.x, rect.y, rect.width, rect.height), (10, -5, 102, 52));
+    }
+
+    #[test]
+    fn test_basic_resources() {
+        use crate::mock::{self, MockRequest};
+        use std::collections::HashSet;
+        use std::sync::{Arc, Mutex};
+
+        const COMPOSITE: u8 = 140;
+        const DAMAGE: u8 = 141;
+        const XFIXES: u8 = 142;
+        const RENDER: u8 = 143;
+        const DAMAGE_EVENT: u8 = 90;
+        const OVERLAY: u32 = 0x300;
+        const RGB: u32 = 0x50;
+        const ARGB: u32 = 0x51;
+
+        #[derive(Default)]
+        struct Server {
+            /// The resources created by the compositor and not freed yet.
+            live: HashSet<u32>,
+            /// The sources of the `Composite` requests.
+            composited: Vec<u32>,
+            /// The windows of the `NameWindowPixmap` requests.
+            named: Vec<u32>,
+            /// The damage object of each window.
+            damages: HashMap<u32, u32>,
+            /// The picture created on each window pixmap.
+            pictures: HashMap<u32, u32>,
+            pixmaps: HashMap<u32, u32>,
+            redirected: bool,
+            overlay_released: bool,
+        }
+
+        fn pict_formats() -> Vec<u8> {
+            let mut body = Vec::new();
+            // formats, screens, depths, visuals, subpixels
+            for v in [2u32, 1, 2, 2, 0, 0] {
+                body.extend_from_slice(&v.to_ne_bytes());
+            }
+            for (id, depth, alpha_mask) in [(RGB, 24u8, 0u16), (ARGB, 32, 0xff)] {
+                body.extend_from_slice(&id.to_ne_bytes());
+                body.extend_from_slice(&[1, depth, 0, 0]);
+                for v in [16u16, 0xff, 8, 0xff, 0, 0xff, 24, alpha_mask] {
+                    body.extend_from_slice(&v.to_ne_bytes());
+                }
+                body.extend_from_slice(&0u32.to_ne_bytes());
+            }
+            // one screen with the root visual (0x21) and an ARGB visual (0x22)
+            body.extend_from_slice(&2u32.to_ne_bytes());
+            body.extend_from_slice(&RGB.to_ne_bytes());
+            for (depth, visual, format) in [(24u8, 0x21u32, RGB), (32, 0x22, ARGB)] {
+                body.extend_from_slice(&[depth, 0]);
+                body.extend_from_slice(&1u16.to_ne_bytes());
+                body.extend_from_slice(&[0; 4]);
+                body.extend_from_slice(&visual.to_ne_bytes());
+                body.extend_from_slice(&format.to_ne_bytes());
+            }
+            body
+        }
+
+        // children of the root: 0x400 (mapped), 0x500 (mapped, input only), 0x600 (unmapped, ARGB)
+        fn window_attributes(req: &MockRequest) -> Vec<u8> {
+            let window = req.u32_at(4);
+            let (visual, class, map_state) = match window {
+                0x400 => (0x21u32, 1u16, 2u8),
+                0x500 => (0, 2, 2),
+                _ => (0x22, 1, 0),
+            };
+            let mut body = vec![0u8; 36];
+            body[0..4].copy_from_slice(&visual.to_ne_bytes());
+            body[4..6].copy_from_slice(&class.to_ne_bytes());
+            body[18] = map_state;
+            mock::reply(req.seq, 0, &body)
+        }
+
+        fn handle(server: &mut Server, req: &MockRequest) -> Vec<Vec<u8>> {
+            let minor = req.data[1];
+            match req.opcode {
+                98 => {
+                    let (major, first_event) = match mock::extension_name(req) {
+                        b"Composite" => (COMPOSITE, 0),
+                        b"DAMAGE" => (DAMAGE, DAMAGE_EVENT),
+                        b"XFIXES" => (XFIXES, 100),
+                        b"RENDER" => (RENDER, 0),
+                        _ => return vec![mock::reply(req.seq, 0, &[])],
+                    };
+                    vec![mock::query_extension_reply(req, major, first_event, 0)]
+                }
+                // GetWindowAttributes
+                3 => vec![window_attributes(req)],
+                // GetGeometry
+                14 => {
+                    let mut body = vec![0u8; 16];
+                    body[0..4].copy_from_slice(&mock::ROOT.to_ne_bytes());
+                    body[8..10].copy_from_slice(&100u16.to_ne_bytes());
+                    body[10..12].copy_from_slice(&50u16.to_ne_bytes());
+                    body[12..14].copy_from_slice(&1u16.to_ne_bytes());
+                    vec![mock::reply(req.seq, 24, &body)]
+                }
+                // QueryTree
+                15 => {
+                    let mut body = vec![0u8; 24];
+                    body[0..4].copy_from_slice(&mock::ROOT.to_ne_bytes());
+                    body[8..10].copy_from_slice(&4u16.to_ne_bytes());
+                    for child in [0x400u32, OVERLAY, 0x500, 0x600] {
+                        body.extend_from_slice(&child.to_ne_bytes());
+                    }
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                // CreatePixmap
+                53 => {
+                    server.live.insert(req.u32_at(4));
+                    vec![]
+                }
+                // FreePixmap
+                54 => {
+                    assert!(server.live.remove(&req.u32_at(4)));
+                    vec![]
+                }
+                COMPOSITE | DAMAGE | XFIXES | RENDER if minor == 0 => {
+                    vec![mock::reply(req.seq, 0, &[])]
+                }
+                COMPOSITE => match minor {
+                    2 => {
+                        server.redirected = true;
+                        vec![]
+                    }
+                    4 => {
+                        server.redirected = false;
+                        vec![]
+                    }
+                    6 => {
+                        server.named.push(req.u32_at(4));
+                        server.pixmaps.insert(req.u32_at(8), req.u32_at(4));
+                        server.live.insert(req.u32_at(8));
+                        vec![]
+                    }
+                    7 => vec![mock::reply(req.seq, 0, &OVERLAY.to_ne_bytes())],
+                    8 => {
+                        server.overlay_released = true;
+                        vec![]
+                    }
+                    _ => vec![],
+                },
+                DAMAGE => {
+                    match minor {
+                        1 => {
+                            server.live.insert(req.u32_at(4));
+                            server.damages.insert(req.u32_at(8), req.u32_at(4));
+                        }
+                        2 => assert!(server.live.remove(&req.u32_at(4))),
+                        _ => {}
+                    }
+                    vec![]
+                }
+                XFIXES => {
+                    match minor {
+                        5 => assert!(server.live.insert(req.u32_at(4))),
+                        10 => assert!(server.live.remove(&req.u32_at(4))),
+                        _ => {}
+                    }
+                    vec![]
+                }
+                RENDER => match minor {
+                    1 => vec![mock::reply(req.seq, 0, &pict_formats())],
+                    4 => {
+                        server.live.insert(req.u32_at(4));
+                        server.pictures.insert(req.u32_at(8), req.u32_at(4));
+                        vec![]
+                    }
+                    7 => {
+                        assert!(server.live.remove(&req.u32_at(4)));
+                        vec![]
+                    }
+                    8 => {
+                        server.composited.push(req.u32_at(8));
+                        vec![]
+                    }
+                    _ => vec![],
+                },
+                _ => mock::default_response(req),
+            }
+        }
+
+        let server = Arc::new(Mutex::new(Server::default()));
+        let conn = {
+            let server = server.clone();
+            mock::connect_with_extensions(
+                move |req| handle(&mut server.lock().unwrap(), req),
+                &[
+                    Extension::Composite,
+                    Extension::Damage,
+                    Extension::XFixes,
+                    Extension::Render,
+                ],
+            )
+        };
+        let root = window(mock::ROOT);
+        // returns the picture of the pixmap of `window`
+        let picture = |window: u32| {
+            let server = server.lock().unwrap();
+            let pixmap = server
+                .pixmaps
+                .iter()
+                .filter(|(pixmap, w)| **w == window && server.live.contains(pixmap))
+                .map(|(pixmap, _)| *pixmap)
+                .next()
+                .unwrap();
+            server.pictures[&pixmap]
+        };
+        let sync = || {
+            conn.send_and_check_request(&x::NoOperation {}).unwrap();
+        };
+
+        let mut compositor = Basic::new(&conn, 0).unwrap();
+        assert!(server.lock().unwrap().redirected);
+        assert_eq!(compositor.overlay(), window(OVERLAY));
+        assert_eq!(
+            compositor.windows().collect::<Vec<_>>(),
+            [window(0x400), window(0x500), window(0x600)]
+        );
+        // 2 regions, the overlay picture, the buffer and its picture,
+        // and the damage, pixmap and picture of 0x400
+        sync();
+        assert_eq!(server.lock().unwrap().live.len(), 8);
+        assert_eq!(server.lock().unwrap().named, [0x400]);
+
+        assert!(compositor.needs_frame());
+        compositor.frame().unwrap();
+        assert!(!compositor.needs_frame());
+        sync();
+        let buffer_picture = compositor.buffer_picture.resource_id();
+        let expected = [picture(0x400), buffer_picture];
+        assert_eq!(server.lock().unwrap().composited, expected);
+
+        // map 0x600 and raise 0x400 on top of it
+        let map = x::MapNotifyEvent::new(root, window(0x600), false);
+        assert!(compositor
+            .handle_event(&Event::X(x::Event::MapNotify(map)))
+            .unwrap());
+        let configure = x::ConfigureNotifyEvent::new(
+            root,
+            window(0x400),
+            window(0x600),
+            0,
+            0,
+            100,
+            50,
+            1,
+            false,
+        );
+        assert!(compositor
+            .handle_event(&Event::X(x::Event::ConfigureNotify(configure)))
+            .unwrap());
+        assert_eq!(
+            compositor.windows().collect::<Vec<_>>(),
+            [window(0x500), window(0x600), window(0x400)]
+        );
+        server.lock().unwrap().composited.clear();
+        compositor.frame().unwrap();
+        sync();
+        let expected = [picture(0x600), picture(0x400), buffer_picture];
+        assert_eq!(server.lock().unwrap().composited, expected);
+        assert_eq!(server.lock().unwrap().live.len(), 11);
+
+        // resizing 0x600 replaces its pixmap and picture
+        let configure = x::ConfigureNotifyEvent::new(
+            root,
+            window(0x600),
+            window(0x500),
+            0,
+            0,
+            200,
+            50,
+            1,
+            false,
+        );
+        compositor
+            .handle_event(&Event::X(x::Event::ConfigureNotify(configure)))
+            .unwrap();
+        sync();
+        assert_eq!(server.lock().unwrap().named, [0x400, 0x600, 0x600]);
+        assert_eq!(server.lock().unwrap().live.len(), 11);
+
+        // damage of 0x600
+        let damage_0x600 = server.lock().unwrap().damages[&0x600];
+        let damage = damage::NotifyEvent::new(
+            DAMAGE_EVENT,
+            damage::ReportLevel::NonEmpty,
+            x::Drawable::Window(window(0x600)),
+            unsafe { crate::XidNew::new(damage_0x600) },
+            0,
+            x::Rectangle {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10,
+            },
+            x::Rectangle {
+                x: 0,
+                y: 0,
+                width: 200,
+                height: 50,
+            },
+        );
+        compositor.frame().unwrap();
+        assert!(!compositor.needs_frame());
+        assert!(compositor
+            .handle_event(&Event::Damage(damage::Event::Notify(damage)))
+            .unwrap());
+        assert!(compositor.needs_frame());
+
+        // unmapping and destroying 0x400 frees its resources
+        let unmap = x::UnmapNotifyEvent::new(root, window(0x400), false);
+        compositor
+            .handle_event(&Event::X(x::Event::UnmapNotify(unmap)))
+            .unwrap();
+        let destroy = x::DestroyNotifyEvent::new(root, window(0x400));
+        compositor
+            .handle_event(&Event::X(x::Event::DestroyNotify(destroy)))
+            .unwrap();
+        {
+            // the server frees the damage object with the window
+            let mut server = server.lock().unwrap();
+            let damage = server.damages[&0x400];
+            server.live.remove(&damage);
+        }
+        sync();
+        assert_eq!(server.lock().unwrap().live.len(), 8);
+        assert_eq!(
+            compositor.windows().collect::<Vec<_>>(),
+            [window(0x500), window(0x600)]
+        );
+
+        // events of other windows are ignored
+        let map = x::MapNotifyEvent::new(window(0x600), window(0x601), false);
+        assert!(!compositor
+            .handle_event(&Event::X(x::Event::MapNotify(map)))
+            .unwrap());
+
+        drop(compositor);
+        sync();
+        let server = server.lock().unwrap();
+        assert!(server.live.is_empty(), "leaked {:x?}", server.live);
+        assert!(!server.redirected);
+        assert!(server.overlay_released);
+    }
+}
@@ -0,0 +1,1005 @@
+//! Loading of the cursors of the Xcursor themes, as libxcb-cursor does.
+//!
+//! [CursorContext] looks up the cursors by name (e.g. `"left_ptr"` or `"watch"`) in the
+//! cursor theme of the user and creates them with the RENDER extension, animated if the
+//! theme has several frames. The theme and the size are found as Xlib does: in the
+//! `XCURSOR_THEME` and `XCURSOR_SIZE` environment variables, then in the `Xcursor.theme`
+//! and `Xcursor.size` resources of the `RESOURCE_MANAGER` property.
+//!
+//! Without RENDER on the connection, or if the theme has no such cursor, the cursor is
+//! created from the glyph of the same name in the core `cursor` font.
+//!
+//! This module is only available with the `render` cargo feature.
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::ext::Extension;
+use crate::render;
+use crate::x;
+
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// The version of RENDER that introduced `CreateCursor`.
+const CURSOR_VERSION: (u32, u32) = (0, 5);
+
+/// The version of RENDER that introduced `CreateAnimCursor`.
+const ANIM_CURSOR_VERSION: (u32, u32) = (0, 8);
+
+/// The search path of the themes if `XCURSOR_PATH` is not set. `~` is the home directory.
+const DEFAULT_PATH: &str = "~/.local/share/icons:~/.icons:/usr/share/icons:/usr/share/pixmaps";
+
+/// `"Xcur"` in little endian.
+const FILE_MAGIC: u32 = 0x7275_6358;
+
+/// The chunk type of the images.
+const IMAGE_TYPE: u32 = 0xfffd_0002;
+
+/// The length of the header of an image chunk, in bytes.
+const IMAGE_HEADER_LEN: u32 = 36;
+
+/// The largest width and height of an image.
+const IMAGE_MAX_SIZE: u32 = 0x7fff;
+
+/// The glyphs of the core `cursor` font, in the order of their index (the glyph of a
+/// cursor is twice its index, and its mask is the next glyph).
+const CORE_CURSORS: [&str; 77] = [
+    "X_cursor",
+    "arrow",
+    "based_arrow_down",
+    "based_arrow_up",
+    "boat",
+    "bogosity",
+    "bottom_left_corner",
+    "bottom_right_corner",
+    "bottom_side",
+    "bottom_tee",
+    "box_spiral",
+    "center_ptr",
+    "circle",
+    "clock",
+    "coffee_mug",
+    "cross",
+    "cross_reverse",
+    "crosshair",
+    "diamond_cross",
+    "dot",
+    "dotbox",
+    "double_arrow",
+    "draft_large",
+    "draft_small",
+    "draped_box",
+    "exchange",
+    "fleur",
+    "gobbler",
+    "gumby",
+    "hand1",
+    "hand2",
+    "heart",
+    "icon",
+    "iron_cross",
+    "left_ptr",
+    "left_side",
+    "left_tee",
+    "leftbutton",
+    "ll_angle",
+    "lr_angle",
+    "man",
+    "middlebutton",
+    "mouse",
+    "pencil",
+    "pirate",
+    "plus",
+    "question_arrow",
+    "right_ptr",
+    "right_side",
+    "right_tee",
+    "rightbutton",
+    "rtl_logo",
+    "sailboat",
+    "sb_down_arrow",
+    "sb_h_double_arrow",
+    "sb_left_arrow",
+    "sb_right_arrow",
+    "sb_up_arrow",
+    "sb_v_double_arrow",
+    "shuttle",
+    "sizing",
+    "spider",
+    "spraycan",
+    "star",
+    "target",
+    "tcross",
+    "top_left_arrow",
+    "top_left_corner",
+    "top_right_corner",
+    "top_side",
+    "top_tee",
+    "trek",
+    "ul_angle",
+    "umbrella",
+    "ur_angle",
+    "watch",
+    "xterm",
+];
+
+/// Error returned by [CursorContext::load_cursor].
+#[derive(Debug)]
+pub enum CursorError {
+    /// The cursor is neither in the theme nor in the core `cursor` font.
+    NotFound(String),
+    /// A request failed.
+    Xcb(crate::Error),
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::NotFound(name) => write!(f, "cursor {:?} not found", name),
+            CursorError::Xcb(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CursorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CursorError::NotFound(_) => None,
+            CursorError::Xcb(err) => Some(err),
+        }
+    }
+}
+
+impl From<crate::Error> for CursorError {
+    fn from(err: crate::Error) -> CursorError {
+        CursorError::Xcb(err)
+    }
+}
+
+/// An image of a cursor file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Image {
+    width: u32,
+    height: u32,
+    xhot: u32,
+    yhot: u32,
+    /// The delay before the next frame of an animated cursor, in milliseconds.
+    delay: u32,
+    /// The pixels in premultiplied ARGB format, row by row.
+    argb: Vec<u32>,
+}
+
+fn u32_at(data: &[u8], offset: u32) -> Option<u32> {
+    let offset = offset as usize;
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Parses the image chunk at `position` of a cursor file, of nominal `size`.
+fn parse_image(data: &[u8], position: u32, size: u32) -> Option<Image> {
+    let field = |i: u32| u32_at(data, position.checked_add(4 * i)?);
+    let header = field(0)?;
+    if header < IMAGE_HEADER_LEN || field(1)? != IMAGE_TYPE || field(2)? != size {
+        return None;
+    }
+    let (width, height) = (field(4)?, field(5)?);
+    if width == 0 || height == 0 || width > IMAGE_MAX_SIZE || height > IMAGE_MAX_SIZE {
+        return None;
+    }
+    let pixels = position.checked_add(header)? as usize;
+    let len = (width * height) as usize * 4;
+    let bytes = data.get(pixels..pixels.checked_add(len)?)?;
+    Some(Image {
+        width,
+        height,
+        xhot: field(6)?.min(width),
+        yhot: field(7)?.min(height),
+        delay: field(8)?,
+        argb: bytes
+            .chunks_exact(4)
+            .map(|px| u32::from_le_bytes([px[0], px[1], px[2], px[3]]))
+            .collect(),
+    })
+}
+
+/// Parses the frames of the nominal size closest to `size` of a cursor file.
+///
+/// Returns `None` if the file is not a valid cursor file or has no image.
+fn parse_images(data: &[u8], size: u32) -> Option<Vec<Image>> {
+    if u32_at(data, 0)? != FILE_MAGIC {
+        return None;
+    }
+    let (header, ntoc) = (u32_at(data, 4)?, u32_at(data, 12)?);
+    let toc: Vec<(u32, u32, u32)> = (0..ntoc)
+        .map(|i| {
+            let entry = header.checked_add(i.checked_mul(12)?)?;
+            Some((
+                u32_at(data, entry)?,
+                u32_at(data, entry + 4)?,
+                u32_at(data, entry + 8)?,
+            ))
+        })
+        .collect::<Option<_>>()?;
+    let best = toc
+        .iter()
+        .filter(|entry| entry.0 == IMAGE_TYPE)
+        .map(|entry| entry.1)
+        .min_by_key(|nominal| (*nominal as i64 - size as i64).abs())?;
+    toc.iter()
+        .filter(|entry| entry.0 == IMAGE_TYPE && entry.1 == best)
+        .map(|entry| parse_image(data, entry.2, best))
+        .collect()
+}
+
+/// The value of the resource `name` in the resource database `resources`, in the format
+/// of the `RESOURCE_MANAGER` property (one `name: value` per line).
+fn resource<'r>(resources: &'r str, name: &str) -> Option<&'r str> {
+    resources.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        Some(value.trim()).filter(|_| key.trim() == name)
+    })
+}
+
+/// The size of the cursors: the one of `XCURSOR_SIZE`, of `Xcursor.size`, derived from
+/// `Xft.dpi` or from the size of the screen, in this order.
+fn cursor_size(
+    env: &dyn Fn(&str) -> Option<String>,
+    resources: &str,
+    screen_size: (u16, u16),
+) -> u32 {
+    let positive = |size: u32| Some(size).filter(|size| *size > 0);
+    env("XCURSOR_SIZE")
+        .and_then(|size| positive(size.trim().parse().ok()?))
+        .or_else(|| positive(resource(resources, "Xcursor.size")?.parse().ok()?))
+        .or_else(|| {
+            let dpi: f64 = resource(resources, "Xft.dpi")?.parse().ok()?;
+            positive((dpi * 16.0 / 72.0) as u32)
+        })
+        .unwrap_or_else(|| (screen_size.0.min(screen_size.1) as u32 / 48).max(1))
+}
+
+/// The directories of the themes: the ones of `XCURSOR_PATH` or the default ones.
+fn search_path(env: &dyn Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let home = env("HOME");
+    env("XCURSOR_PATH")
+        .unwrap_or_else(|| DEFAULT_PATH.to_string())
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .filter_map(|dir| match dir.strip_prefix("~/") {
+            Some(rest) => Some(PathBuf::from(home.as_ref()?).join(rest)),
+            None => Some(PathBuf::from(dir)),
+        })
+        .collect()
+}
+
+/// The themes inherited by a theme, in the `Inherits` key of its `index.theme`.
+fn parse_inherits(index: &str) -> Vec<String> {
+    let mut in_section = false;
+    for line in index.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_section = line == "[Icon Theme]";
+        } else if let Some((key, value)) = line.split_once('=') {
+            if in_section && key.trim() == "Inherits" {
+                return value
+                    .split([',', ';'])
+                    .map(str::trim)
+                    .filter(|theme| !theme.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        }
+    }
+    Vec::new()
+}
+
+/// The cursor file `name` of `theme` or of the themes it inherits, in the directories of
+/// `path`.
+///
+/// `visited` holds the themes already searched, to stop on inheritance cycles.
+fn find_in_theme(
+    path: &[PathBuf],
+    theme: &str,
+    name: &str,
+    visited: &mut Vec<String>,
+) -> Option<Vec<u8>> {
+    if visited.iter().any(|t| t == theme) {
+        return None;
+    }
+    visited.push(theme.to_string());
+    if let Some(data) = path
+        .iter()
+        .find_map(|dir| fs::read(dir.join(theme).join("cursors").join(name)).ok())
+    {
+        return Some(data);
+    }
+    path.iter()
+        .find_map(|dir| fs::read_to_string(dir.join(theme).join("index.theme")).ok())
+        .map(|index| parse_inherits(&index))
+        .unwrap_or_default()
+        .iter()
+        .find_map(|inherited| find_in_theme(path, inherited, name, visited))
+}
+
+/// The RENDER formats and version to create the cursors.
+#[derive(Copy, Clone, Debug)]
+struct RenderCursors {
+    format: render::Pictformat,
+    animated: bool,
+}
+
+/// Creates the cursors of the cursor theme of the user.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use xcb::{cursor::CursorContext, x};
+///
+/// let (conn, screen_num) =
+///     xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Render], &[])?;
+/// let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+/// let ctx = CursorContext::new(&conn, screen)?;
+/// let cursor = ctx.load_cursor("watch")?;
+/// conn.send_and_check_request(&x::ChangeWindowAttributes {
+///     window: screen.root(),
+///     value_list: &[x::Cw::Cursor(cursor)],
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CursorContext<'c> {
+    conn: &'c Connection,
+    root: x::Window,
+    theme: Option<String>,
+    size: u32,
+    path: Vec<PathBuf>,
+    render: Option<RenderCursors>,
+    /// The core `cursor` font, opened on first use.
+    cursor_font: Cell<Option<x::Font>>,
+}
+
+impl<'c> CursorContext<'c> {
+    /// Finds the theme and the size of the cursors of `screen`, and the support of RENDER
+    /// cursors by the server.
+    ///
+    /// RENDER is only used if it is active on the connection (see
+    /// [Connection::active_extensions]).
+    ///
+    /// # Panics
+    /// Panics if `screen` is not a screen of the connection.
+    pub fn new(conn: &'c Connection, screen: &x::Screen) -> Result<CursorContext<'c>> {
+        let setup = conn.get_setup();
+        let screen_num = setup
+            .roots()
+            .position(|s| s.root() == screen.root())
+            .expect("the screen is not a screen of the connection");
+        // the resources of all the screens are on the first one
+        let first_root = setup.roots().next().unwrap().root();
+        let resources =
+            x::get_full_property(conn, first_root, x::ATOM_RESOURCE_MANAGER, x::ATOM_STRING)?;
+        let resources = resources
+            .expect_data8("RESOURCE_MANAGER")?
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+
+        let render = if conn.extension_data(Extension::Render).is_some() {
+            query_render(conn, screen_num as i32)?
+        } else {
+            None
+        };
+
+        let env = |name: &str| std::env::var(name).ok();
+        let theme = env("XCURSOR_THEME")
+            .or_else(|| resource(&resources, "Xcursor.theme").map(String::from))
+            .filter(|theme| !theme.is_empty());
+        Ok(CursorContext {
+            conn,
+            root: screen.root(),
+            theme,
+            size: cursor_size(
+                &env,
+                &resources,
+                (screen.width_in_pixels(), screen.height_in_pixels()),
+            ),
+            path: search_path(&env),
+            render,
+            cursor_font: Cell::new(None),
+        })
+    }
+
+    /// The name of the cursor theme, or `None` for the `default` theme.
+    pub fn theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
+    /// The nominal size of the cursors, in pixels.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Creates the cursor `name`.
+    ///
+    /// The cursor is searched in the theme, the themes it inherits, then in the `default`
+    /// theme, and the images of the size closest to [CursorContext::size] are used.
+    /// The theme `core` and servers without RENDER 0.5 get the glyph of the core `cursor`
+    /// font.
+    ///
+    /// The cursor is owned by the caller, who frees it with `x::FreeCursor`.
+    pub fn load_cursor(&self, name: &str) -> std::result::Result<x::Cursor, CursorError> {
+        if let Some(render) = self.render {
+            if let Some(images) = self.find_images(name) {
+                return Ok(self.create_render_cursor(render, name, &images)?);
+            }
+        }
+        match CORE_CURSORS.iter().position(|c| *c == name) {
+            Some(index) => Ok(self.create_glyph_cursor(name, index as u16 * 2)?),
+            None => Err(CursorError::NotFound(name.to_string())),
+        }
+    }
+
+    /// The images of the cursor `name` in the theme, if it has a valid cursor file.
+    fn find_images(&self, name: &str) -> Option<Vec<Image>> {
+        let theme = self.theme.as_deref().unwrap_or("default");
+        if theme == "core" {
+            return None;
+        }
+        let mut visited = Vec::new();
+        let data = find_in_theme(&self.path, theme, name, &mut visited)
+            .or_else(|| find_in_theme(&self.path, "default", name, &mut visited))?;
+        parse_images(&data, self.size)
+    }
+
+    fn create_render_cursor(
+        &self,
+        render: RenderCursors,
+        name: &str,
+        images: &[Image],
+    ) -> Result<x::Cursor> {
+        let images = if render.animated {
+            images
+        } else {
+            &images[..1]
+        };
+        let mut frames = Vec::with_capacity(images.len());
+        for image in images {
+            match self.create_frame(render.format, name, image) {
+                Ok(frame) => frames.push(frame),
+                Err(err) => {
+                    self.free_cursors(&frames);
+                    return Err(err);
+                }
+            }
+        }
+        if frames.len() == 1 {
+            return Ok(frames[0]);
+        }
+
+        let cid = self.conn.generate_id();
+        let elts: Vec<_> = frames
+            .iter()
+            .zip(images)
+            .map(|(&cursor, image)| render::Animcursorelt {
+                cursor,
+                delay: image.delay,
+            })
+            .collect();
+        let cookie = self.conn.send_request_checked(&render::CreateAnimCursor {
+            cid,
+            cursors: &elts,
+        });
+        // the animated cursor holds the references to its frames
+        self.free_cursors(&frames);
+        self.conn
+            .check_request(cookie)
+            .context("CreateAnimCursor", || name.to_string())?;
+        Ok(cid)
+    }
+
+    /// Creates a cursor of `image` from a picture of a pixmap of depth 32.
+    fn create_frame(
+        &self,
+        format: render::Pictformat,
+        name: &str,
+        image: &Image,
+    ) -> Result<x::Cursor> {
+        let conn = self.conn;
+        let (width, height) = (image.width as u16, image.height as u16);
+        let data: Vec<u8> = match conn.get_setup().image_byte_order() {
+            x::ImageOrder::LsbFirst => image.argb.iter().flat_map(|px| px.to_le_bytes()).collect(),
+            x::ImageOrder::MsbFirst => image.argb.iter().flat_map(|px| px.to_be_bytes()).collect(),
+        };
+
+        let pixmap: x::Pixmap = conn.generate_id();
+        let create_pixmap = conn.send_request_checked(&x::CreatePixmap {
+            depth: 32,
+            pid: pixmap,
+            drawable: x::Drawable::Window(self.root),
+            width,
+            height,
+        });
+        let gc: x::Gcontext = conn.generate_id();
+        let create_gc = conn.send_request_checked(&x::CreateGc {
+            cid: gc,
+            drawable: x::Drawable::Pixmap(pixmap),
+            value_list: &[],
+        });
+        let put_image = x::put_image_chunked(
+            conn,
+            &x::PutImage {
+                format: x::ImageFormat::ZPixmap,
+                drawable: x::Drawable::Pixmap(pixmap),
+                gc,
+                width,
+                height,
+                dst_x: 0,
+                dst_y: 0,
+                left_pad: 0,
+                depth: 32,
+                data: &data,
+            },
+        );
+        let picture: render::Picture = conn.generate_id();
+        let create_picture = conn.send_request_checked(&render::CreatePicture {
+            pid: picture,
+            drawable: x::Drawable::Pixmap(pixmap),
+            format,
+            value_list: &[],
+        });
+        let cid: x::Cursor = conn.generate_id();
+        let create_cursor = conn.send_request_checked(&render::CreateCursor {
+            cid,
+            source: picture,
+            x: image.xhot as u16,
+            y: image.yhot as u16,
+        });
+        conn.send_request(&render::FreePicture { picture });
+        conn.send_request(&x::FreeGc { gc });
+        conn.send_request(&x::FreePixmap { pixmap });
+
+        let detail = || format!("{} of {}x{}", name, width, height);
+        conn.check_request(create_pixmap)
+            .context("CreatePixmap", detail)?;
+        conn.check_request(create_gc).context("CreateGC", detail)?;
+        put_image?;
+        conn.check_request(create_picture)
+            .context("CreatePicture", detail)?;
+        conn.check_request(create_cursor)
+            .context("CreateCursor", detail)?;
+        Ok(cid)
+    }
+
+    fn free_cursors(&self, cursors: &[x::Cursor]) {
+        for &cursor in cursors {
+            self.conn.send_request(&x::FreeCursor { cursor });
+        }
+    }
+
+    /// Creates a black cursor on white of the `glyph` of the core `cursor` font.
+    fn create_glyph_cursor(&self, name: &str, glyph: u16) -> Result<x::Cursor> {
+        let font = match self.cursor_font.get() {
+            Some(font) => font,
+            None => {
+                let font = self.conn.generate_id();
+                self.conn
+                    .send_and_check_request(&x::OpenFont {
+                        fid: font,
+                        name: b"cursor",
+                    })
+                    .context("OpenFont", || "cursor")?;
+                self.cursor_font.set(Some(font));
+                font
+            }
+        };
+        let cid = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&x::CreateGlyphCursor {
+                cid,
+                source_font: font,
+                mask_font: font,
+                source_char: glyph,
+                mask_char: glyph + 1,
+                fore_red: 0,
+                fore_green: 0,
+                fore_blue: 0,
+                back_red: 0xffff,
+                back_green: 0xffff,
+                back_blue: 0xffff,
+            })
+            .context("CreateGlyphCursor", || name.to_string())?;
+        Ok(cid)
+    }
+}
+
+impl<'c> Drop for CursorContext<'c> {
+    fn drop(&mut self) {
+        if let Some(font) = self.cursor_font.get() {
+            self.conn.send_request(&x::CloseFont { font });
+        }
+    }
+}
+
+/// The support of the cursors by the RENDER extension of the server, `None` before 0.5 or
+/// without ARGB format.
+fn query_render(conn: &Connection, screen_num: i32) -> Result<Option<RenderCursors>> {
+    let handle = render::Handle::new(conn, screen_num)?;
+    if handle.version() < CURSOR_VERSION {
+        return Ok(None);
+    }
+    let cookie = conn.send_request(&render::QueryPictFormats {});
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("QueryPictFormats", || render::XNAME)?;
+    Ok(
+        crate::surface::pick_format(reply.formats(), None, 32).map(|format| RenderCursors {
+            format,
+            animated: handle.version() >= ANIM_CURSOR_VERSION,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::WiredOut;
+    use crate::mock;
+    use crate::XidNew;
+
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    const RENDER_OPCODE: u8 = 139;
+    const ARGB: u32 = 0x31;
+
+    /// The reply to `QueryPictFormats`: a single ARGB format.
+    fn query_pict_formats_reply(seq: u16) -> Vec<u8> {
+        let format = render::Pictforminfo::new(
+            unsafe { render::Pictformat::new(ARGB) },
+            render::PictType::Direct,
+            32,
+            render::Directformat {
+                red_shift: 16,
+                red_mask: 0xff,
+                green_shift: 8,
+                green_mask: 0xff,
+                blue_shift: 0,
+                blue_mask: 0xff,
+                alpha_shift: 24,
+                alpha_mask: 0xff,
+            },
+            x::Colormap::none(),
+        );
+        let mut body = vec![0u8; 24];
+        body[0..4].copy_from_slice(&1u32.to_ne_bytes());
+        let mut buf = [0u8; 28];
+        format.serialize(&mut buf);
+        body.extend_from_slice(&buf);
+        mock::reply(seq, 0, &body)
+    }
+
+    /// Connects to a mock server with RENDER `version` if any, that records the requests
+    /// that create and free the cursors and their resources.
+    fn mock_connection(version: Option<(u32, u32)>, log: Arc<Mutex<Vec<String>>>) -> Connection {
+        let handler = move |req: &mock::MockRequest| {
+            let mut log = log.lock().unwrap();
+            match req.opcode {
+                98 => match mock::extension_name(req) {
+                    b"RENDER" => vec![mock::query_extension_reply(req, RENDER_OPCODE, 0, 140)],
+                    _ => vec![mock::reply(req.seq, 0, &[])],
+                },
+                // GetProperty: the resources of the user
+                20 => {
+                    assert_eq!(req.u32_at(8), x::ATOM_RESOURCE_MANAGER.resource_id());
+                    let resources = b"Xcursor.theme:\tmain\nXcursor.size:\t24\n";
+                    let mut body = vec![0u8; 24];
+                    body[0..4].copy_from_slice(&x::ATOM_STRING.resource_id().to_ne_bytes());
+                    body[8..12].copy_from_slice(&(resources.len() as u32).to_ne_bytes());
+                    body.extend_from_slice(resources);
+                    vec![mock::reply(req.seq, 8, &body)]
+                }
+                // OpenFont
+                45 => {
+                    let len = u16::from_ne_bytes([req.data[8], req.data[9]]) as usize;
+                    log.push(format!(
+                        "OpenFont {}",
+                        String::from_utf8_lossy(&req.data[12..12 + len])
+                    ));
+                    vec![]
+                }
+                46 => {
+                    log.push("CloseFont".to_string());
+                    vec![]
+                }
+                53 => {
+                    let (width, height) = (
+                        u16::from_ne_bytes([req.data[12], req.data[13]]),
+                        u16::from_ne_bytes([req.data[14], req.data[15]]),
+                    );
+                    log.push(format!("CreatePixmap {} {}x{}", req.data[1], width, height));
+                    vec![]
+                }
+                72 => {
+                    let pixels: Vec<u32> = req.data[24..]
+                        .chunks_exact(4)
+                        .map(|px| u32::from_le_bytes([px[0], px[1], px[2], px[3]]))
+                        .collect();
+                    log.push(format!("PutImage {:?}", pixels));
+                    vec![]
+                }
+                94 => {
+                    let glyph = u16::from_ne_bytes([req.data[16], req.data[17]]);
+                    let mask = u16::from_ne_bytes([req.data[18], req.data[19]]);
+                    log.push(format!("CreateGlyphCursor {} {}", glyph, mask));
+                    vec![]
+                }
+                95 => {
+                    log.push("FreeCursor".to_string());
+                    vec![]
+                }
+                RENDER_OPCODE => match req.data[1] {
+                    // QueryVersion
+                    0 => {
+                        let version = version.unwrap();
+                        let mut body = vec![0u8; 8];
+                        body[0..4].copy_from_slice(&version.0.to_ne_bytes());
+                        body[4..8].copy_from_slice(&version.1.to_ne_bytes());
+                        vec![mock::reply(req.seq, 0, &body)]
+                    }
+                    1 => vec![query_pict_formats_reply(req.seq)],
+                    4 => {
+                        assert_eq!(req.u32_at(12), ARGB);
+                        vec![]
+                    }
+                    27 => {
+                        let (x, y) = (
+                            u16::from_ne_bytes([req.data[12], req.data[13]]),
+                            u16::from_ne_bytes([req.data[14], req.data[15]]),
+                        );
+                        log.push(format!("CreateCursor {},{}", x, y));
+                        vec![]
+                    }
+                    31 => {
+                        let delays: Vec<u32> = (0..(req.data.len() - 8) / 8)
+                            .map(|i| req.u32_at(12 + 8 * i))
+                            .collect();
+                        log.push(format!("CreateAnimCursor {:?}", delays));
+                        vec![]
+                    }
+                    _ => vec![],
+                },
+                _ => mock::default_response(req),
+            }
+        };
+        match version {
+            Some(_) => mock::connect_with_extensions(handler, &[crate::Extension::Render]),
+            None => mock::connect(handler),
+        }
+    }
+
+    /// The themes `main`, that inherits `base` where `watch` is animated, and `default`
+    /// with `left_ptr`.
+    fn write_themes(dir: &Path) {
+        let cursors = |theme: &str| {
+            let cursors = dir.join(theme).join("cursors");
+            fs::create_dir_all(&cursors).unwrap();
+            cursors
+        };
+        cursors("main");
+        fs::write(
+            dir.join("main").join("index.theme"),
+            "[Icon Theme]\nInherits=base\n",
+        )
+        .unwrap();
+        fs::write(
+            cursors("base").join("watch"),
+            cursor_file(&[(24, 2, 1, 100), (24, 2, 1, 200), (48, 4, 2, 100)]),
+        )
+        .unwrap();
+        fs::write(
+            cursors("default").join("left_ptr"),
+            cursor_file(&[(32, 1, 2, 0)]),
+        )
+        .unwrap();
+    }
+
+    fn context<'c>(conn: &'c Connection, dir: &Path) -> CursorContext<'c> {
+        let screen = conn.get_setup().roots().next().unwrap();
+        let mut ctx = CursorContext::new(conn, screen).unwrap();
+        if std::env::var_os("XCURSOR_THEME").is_none() {
+            assert_eq!(ctx.theme(), Some("main"));
+        }
+        if std::env::var_os("XCURSOR_SIZE").is_none() {
+            assert_eq!(ctx.size(), 24);
+        }
+        ctx.theme = Some("main".to_string());
+        ctx.size = 24;
+        ctx.path = vec![dir.join("none"), dir.to_path_buf()];
+        ctx
+    }
+
+    #[test]
+    fn test_load_cursor() {
+        let dir = std::env::temp_dir().join(format!("xcb-cursor-{}", std::process::id()));
+        write_themes(&dir);
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(Some((0, 11)), log.clone());
+        let ctx = context(&conn, &dir);
+        let take = || std::mem::take(&mut *log.lock().unwrap());
+
+        // animated, from the inherited theme
+        ctx.load_cursor("watch").unwrap();
+        assert_eq!(
+            take(),
+            [
+                "CreatePixmap 32 2x1",
+                "PutImage [0, 1]",
+                "CreateCursor 1,1",
+                "CreatePixmap 32 2x1",
+                "PutImage [0, 1]",
+                "CreateCursor 1,1",
+                "CreateAnimCursor [100, 200]",
+                "FreeCursor",
+                "FreeCursor",
+            ]
+        );
+
+        // from the default theme, of another size
+        ctx.load_cursor("left_ptr").unwrap();
+        assert_eq!(
+            take(),
+            ["CreatePixmap 32 1x2", "PutImage [0, 1]", "CreateCursor 1,2"]
+        );
+
+        // from the cursor font, opened once
+        ctx.load_cursor("xterm").unwrap();
+        ctx.load_cursor("X_cursor").unwrap();
+        assert_eq!(
+            take(),
+            [
+                "OpenFont cursor",
+                "CreateGlyphCursor 152 153",
+                "CreateGlyphCursor 0 1"
+            ]
+        );
+
+        match ctx.load_cursor("no_such_cursor") {
+            Err(CursorError::NotFound(name)) => assert_eq!(name, "no_such_cursor"),
+            res => panic!("unexpected result {:?}", res),
+        }
+        drop(ctx);
+        let cookie = conn.send_request(&x::GetInputFocus {});
+        conn.wait_for_reply(cookie).unwrap();
+        assert_eq!(take(), ["CloseFont"]);
+
+        // the first frame without CreateAnimCursor
+        let conn = mock_connection(Some((0, 5)), log.clone());
+        let ctx = context(&conn, &dir);
+        ctx.load_cursor("watch").unwrap();
+        assert_eq!(
+            take(),
+            ["CreatePixmap 32 2x1", "PutImage [0, 1]", "CreateCursor 1,1"]
+        );
+
+        // the cursor font without RENDER or before 0.5
+        for version in [None, Some((0, 4))] {
+            let conn = mock_connection(version, log.clone());
+            let ctx = context(&conn, &dir);
+            ctx.load_cursor("left_ptr").unwrap();
+            assert_eq!(take(), ["OpenFont cursor", "CreateGlyphCursor 68 69"]);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A cursor file with an image of each `(nominal size, width, height, delay)`, whose
+    /// pixels are their index.
+    fn cursor_file(images: &[(u32, u32, u32, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut push = |v: u32| data.extend_from_slice(&v.to_le_bytes());
+        push(FILE_MAGIC);
+        push(16);
+        push(0x1_0000);
+        push(images.len() as u32);
+        let mut position = 16 + 12 * images.len() as u32;
+        for &(size, width, height, _) in images {
+            push(IMAGE_TYPE);
+            push(size);
+            push(position);
+            position += IMAGE_HEADER_LEN + 4 * width * height;
+        }
+        for &(size, width, height, delay) in images {
+            for v in [
+                IMAGE_HEADER_LEN,
+                IMAGE_TYPE,
+                size,
+                1,
+                width,
+                height,
+                1,
+                2,
+                delay,
+            ] {
+                push(v);
+            }
+            for px in 0..width * height {
+                push(px);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_images() {
+        let data = cursor_file(&[(24, 2, 2, 50), (32, 3, 3, 50), (24, 2, 2, 70)]);
+
+        let images = parse_images(&data, 22).unwrap();
+        assert_eq!(
+            images,
+            vec![
+                Image {
+                    width: 2,
+                    height: 2,
+                    xhot: 1,
+                    yhot: 2,
+                    delay: 50,
+                    argb: vec![0, 1, 2, 3],
+                },
+                Image {
+                    width: 2,
+                    height: 2,
+                    xhot: 1,
+                    yhot: 2,
+                    delay: 70,
+                    argb: vec![0, 1, 2, 3],
+                },
+            ]
+        );
+        let images = parse_images(&data, 48).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].width, images[0].argb.len()), (3, 9));
+
+        // truncated, not a cursor file, without image
+        assert_eq!(parse_images(&data[..data.len() - 1], 24), None);
+        assert_eq!(parse_images(b"#define cursor_width 16", 24), None);
+        assert_eq!(parse_images(&cursor_file(&[]), 24), None);
+    }
+
+    #[test]
+    fn test_cursor_settings() {
+        let resources = "Xft.dpi:\t144\nXcursor.theme:\tAdwaita\n*background:\tblack\n";
+        assert_eq!(resource(resources, "Xcursor.theme"), Some("Adwaita"));
+        assert_eq!(resource(resources, "Xcursor.size"), None);
+        assert_eq!(resource(resources, "background"), None);
+
+        let no_env = |_: &str| None;
+        let size_env = |name: &str| Some("48".to_string()).filter(|_| name == "XCURSOR_SIZE");
+        assert_eq!(cursor_size(&size_env, resources, (1920, 1080)), 48);
+        assert_eq!(cursor_size(&no_env, "Xcursor.size: 32", (1920, 1080)), 32);
+        assert_eq!(cursor_size(&no_env, resources, (1920, 1080)), 32);
+        assert_eq!(cursor_size(&no_env, "", (1920, 1080)), 22);
+
+        let env = |name: &str| match name {
+            "HOME" => Some("/home/user".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            search_path(&env),
+            [
+                "/home/user/.local/share/icons",
+                "/home/user/.icons",
+                "/usr/share/icons",
+                "/usr/share/pixmaps",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+        let env =
+            |name: &str| Some("/icons::~/icons".to_string()).filter(|_| name == "XCURSOR_PATH");
+        assert_eq!(search_path(&env), vec![PathBuf::from("/icons")]);
+
+        let index = "[Icon Theme]\nName=Main\nInherits = base; other,\n[Other]\nInherits=none\n";
+        assert_eq!(parse_inherits(index), vec!["base", "other"]);
+        assert!(parse_inherits("[Other]\nInherits=none\n").is_empty());
+    }
+}
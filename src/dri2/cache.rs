@@ -0,0 +1,399 @@
+//! Caching of the buffers of a DRI2 drawable across `InvalidateBuffers` events.
+
+use super::{Attachment, Dri2Buffer, Event, GetBuffers, InvalidateBuffersEvent};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+use std::collections::BTreeMap;
+
+/// Caches the buffers of a DRI2 drawable.
+///
+/// When a window is resized rapidly, the server sends `InvalidateBuffers` events faster
+/// than a client can re-fetch the buffers, and fetching them for every event makes the
+/// client fall further and further behind. `BufferCache` instead marks the buffers as dirty
+/// when it is notified of an invalidation with [BufferCache::handle_event], and issues
+/// `GetBuffers` only when the buffers are requested with [BufferCache::buffers].
+/// Any number of invalidations between two calls costs a single round trip.
+///
+/// The drawable must have been created with `dri2::CreateDrawable`.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # use xcb::{dri2, x};
+/// # let (conn, _) = xcb::Connection::connect(None)?;
+/// # let window: x::Window = conn.generate_id();
+/// let drawable = x::Drawable::Window(window);
+/// conn.send_and_check_request(&dri2::CreateDrawable { drawable })?;
+/// let mut cache = dri2::BufferCache::new(&conn, drawable);
+/// loop {
+///     while let Some(event) = conn.poll_for_event()? {
+///         cache.handle_event(&event);
+///     }
+///     let generation = cache.generation();
+///     let buffers = cache.buffers()?;
+///     if let Some(back) = buffers.get(&dri2::Attachment::BufferBackLeft) {
+///         // render into back.name()
+///     }
+///     # break;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct BufferCache<'a> {
+    conn: &'a Connection,
+    drawable: x::Drawable,
+    attachments: Vec<Attachment>,
+    buffers: BTreeMap<Attachment, Dri2Buffer>,
+    width: u32,
+    height: u32,
+    dirty: bool,
+    invalidations: u32,
+    generation: u64,
+}
+
+impl<'a> std::fmt::Debug for BufferCache<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferCache")
+            .field("drawable", &self.drawable)
+            .field("attachments", &self.attachments)
+            .field("buffers", &self.buffers)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("dirty", &self.dirty)
+            .field("invalidations", &self.invalidations)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+impl<'a> BufferCache<'a> {
+    /// Caches the back left buffer of `drawable`.
+    ///
+    /// No request is sent before the first call to [BufferCache::buffers].
+    pub fn new(conn: &'a Connection, drawable: x::Drawable) -> BufferCache<'a> {
+        BufferCache {
+            conn,
+            drawable,
+            attachments: vec![Attachment::BufferBackLeft],
+            buffers: BTreeMap::new(),
+            width: 0,
+            height: 0,
+            dirty: true,
+            invalidations: 0,
+            generation: 0,
+        }
+    }
+
+    /// Sets the attachments requested with `GetBuffers`, and marks the buffers as dirty.
+    pub fn set_attachments(&mut self, attachments: &[Attachment]) {
+        self.attachments = attachments.to_vec();
+        self.dirty = true;
+    }
+
+    /// The cached drawable.
+    pub fn drawable(&self) -> x::Drawable {
+        self.drawable
+    }
+
+    /// Marks the buffers as dirty if `event` invalidates them.
+    ///
+    /// Returns `true` if `event` is an `InvalidateBuffers` event for the cached drawable.
+    pub fn handle_event(&mut self, event: &crate::Event) -> bool {
+        match event {
+            crate::Event::Dri2(Event::InvalidateBuffers(ev)) => self.handle_invalidate(ev),
+            _ => false,
+        }
+    }
+
+    /// Marks the buffers as dirty if `ev` is for the cached drawable, in which case `true`
+    /// is returned.
+    pub fn handle_invalidate(&mut self, ev: &InvalidateBuffersEvent) -> bool {
+        if ev.drawable().resource_id() != self.drawable.resource_id() {
+            return false;
+        }
+        self.dirty = true;
+        self.invalidations += 1;
+        true
+    }
+
+    /// Whether the next call to [BufferCache::buffers] will issue `GetBuffers`.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The number of invalidations received since the buffers were last fetched.
+    pub fn pending_invalidations(&self) -> u32 {
+        self.invalidations
+    }
+
+    /// The size of the drawable reported by the last `GetBuffers` reply.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Incremented each time the buffers are re-fetched and their size or names changed.
+    ///
+    /// Renderers can compare the generation before and after a frame to detect that
+    /// the buffers were reallocated while the frame was drawn.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the buffers of the drawable, by attachment.
+    ///
+    /// `GetBuffers` is issued only if the buffers were invalidated since they were last
+    /// fetched, otherwise the cached buffers are returned. Attachments that the server
+    /// could not allocate are missing from the returned map.
+    pub fn buffers(&mut self) -> Result<&BTreeMap<Attachment, Dri2Buffer>> {
+        if self.dirty {
+            self.fetch()?;
+        }
+        Ok(&self.buffers)
+    }
+
+    fn fetch(&mut self) -> Result<()> {
+        let attachments: Vec<u32> = self.attachments.iter().map(|a| *a as u32).collect();
+        let cookie = self.conn.send_request(&GetBuffers {
+            drawable: self.drawable,
+            count: attachments.len() as u32,
+            attachments: &attachments,
+        });
+        let reply = self
+            .conn
+            .wait_for_reply(cookie)
+            .context("dri2::GetBuffers", || {
+                format!("drawable {:#x}", self.drawable.resource_id())
+            })?;
+
+        let buffers: BTreeMap<_, _> = reply
+            .buffers()
+            .iter()
+            .map(|buf| (buf.attachment(), *buf))
+            .collect();
+        let names = |buffers: &BTreeMap<Attachment, Dri2Buffer>| {
+            buffers
+                .iter()
+                .map(|(att, buf)| (*att, buf.name()))
+                .collect::<Vec<_>>()
+        };
+        if (reply.width(), reply.height()) != (self.width, self.height)
+            || names(&buffers) != names(&self.buffers)
+        {
+            self.generation += 1;
+        }
+
+        self.buffers = buffers;
+        self.width = reply.width();
+        self.height = reply.height();
+        self.dirty = false;
+        self.invalidations = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRI2_OPCODE: u8 = 140;
+
+    const DRI2_FIRST_EVENT: u8 = 90;
+
+    /// An `InvalidateBuffers` event as sent by the server after the request of sequence `seq`.
+    fn invalidate_buffers(seq: u16, drawable: u32) -> Vec<u8> {
+        let mut ev = vec![DRI2_FIRST_EVENT + 1, 0];
+        ev.extend_from_slice(&seq.to_ne_bytes());
+        ev.extend_from_slice(&drawable.to_ne_bytes());
+        ev.resize(32, 0);
+        ev
+    }
+
+    /// A `GetBuffers` reply with a back left buffer of name `name`.
+    fn get_buffers_reply(seq: u16, width: u32, height: u32, name: u32) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&width.to_ne_bytes());
+        body.extend_from_slice(&height.to_ne_bytes());
+        body.extend_from_slice(&1u32.to_ne_bytes());
+        body.resize(24, 0);
+        for v in [Attachment::BufferBackLeft as u32, name, width * 4, 4, 0] {
+            body.extend_from_slice(&v.to_ne_bytes());
+        }
+        crate::mock::reply(seq, 0, &body)
+    }
+
+    fn connect<F>(get_buffers: F) -> Connection
+    where
+        F: FnMut(&crate::mock::MockRequest) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        use crate::mock;
+
+        let mut get_buffers = get_buffers;
+        mock::connect_with_extensions(
+            move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(
+                    req,
+                    DRI2_OPCODE,
+                    DRI2_FIRST_EVENT,
+                    150,
+                )],
+                DRI2_OPCODE if req.data[1] == 5 => get_buffers(req),
+                _ => mock::default_response(req),
+            },
+            &[crate::Extension::Dri2],
+        )
+    }
+
+    fn window(id: u32) -> x::Drawable {
+        use crate::XidNew;
+
+        x::Drawable::Window(unsafe { x::Window::new(id) })
+    }
+
+    #[test]
+    fn test_buffer_cache_coalescing() {
+        use crate::XidNew;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let requests = Arc::new(AtomicU32::new(0));
+        let conn = {
+            let requests = requests.clone();
+            connect(move |req| {
+                assert_eq!(req.u32_at(4), 0x200);
+                let n = requests.fetch_add(1, Ordering::SeqCst);
+                // the size and the name change after the second request
+                let (size, name) = if n < 2 { (100, 7) } else { (200, 8) };
+                vec![get_buffers_reply(req.seq, size, size, name)]
+            })
+        };
+        let ev = |drawable| {
+            let ev = InvalidateBuffersEvent::new(DRI2_FIRST_EVENT, unsafe {
+                x::Drawable::Window(x::Window::new(drawable))
+            });
+            crate::Event::Dri2(Event::InvalidateBuffers(ev))
+        };
+
+        let mut cache = BufferCache::new(&conn, window(0x200));
+        assert!(cache.is_dirty());
+        assert_eq!(cache.generation(), 0);
+
+        let buffers = cache.buffers().unwrap();
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(buffers[&Attachment::BufferBackLeft].name(), 7);
+        assert!(!cache.is_dirty());
+        assert_eq!(cache.size(), (100, 100));
+        assert_eq!(cache.generation(), 1);
+
+        // clean: no request
+        cache.buffers().unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // other drawables and other events are ignored
+        assert!(!cache.handle_event(&ev(0x300)));
+        assert!(!cache.handle_event(&crate::Event::X(x::Event::MapNotify(
+            x::MapNotifyEvent::new(
+                unsafe { x::Window::new(0x200) },
+                unsafe { x::Window::new(0x200) },
+                false
+            )
+        ))));
+        assert!(!cache.is_dirty());
+
+        // the invalidations are coalesced
+        for _ in 0..10 {
+            assert!(cache.handle_event(&ev(0x200)));
+        }
+        assert!(cache.is_dirty());
+        assert_eq!(cache.pending_invalidations(), 10);
+        cache.buffers().unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(!cache.is_dirty());
+        assert_eq!(cache.pending_invalidations(), 0);
+        // same size and names
+        assert_eq!(cache.generation(), 1);
+
+        cache.handle_event(&ev(0x200));
+        let buffers = cache.buffers().unwrap();
+        assert_eq!(buffers[&Attachment::BufferBackLeft].name(), 8);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.size(), (200, 200));
+        assert_eq!(cache.generation(), 2);
+
+        // changing the attachments invalidates the buffers
+        cache.set_attachments(&[Attachment::BufferBackLeft, Attachment::BufferFrontLeft]);
+        assert!(cache.is_dirty());
+        cache.buffers().unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_buffer_cache_resize_storm() {
+        use crate::mock;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        const FRAMES: u32 = 20;
+        const EVENTS_PER_RESIZE: usize = 5;
+
+        let requests = Arc::new(AtomicU32::new(0));
+        let width = Arc::new(AtomicU32::new(100));
+        let conn = {
+            let (requests, width) = (requests.clone(), width.clone());
+            mock::connect_with_extensions(
+                move |req| match req.opcode {
+                    98 => vec![mock::query_extension_reply(
+                        req,
+                        DRI2_OPCODE,
+                        DRI2_FIRST_EVENT,
+                        150,
+                    )],
+                    // ConfigureWindow: the server invalidates the buffers several times
+                    12 => {
+                        width.store(req.u32_at(12), Ordering::SeqCst);
+                        vec![invalidate_buffers(req.seq, req.u32_at(4)); EVENTS_PER_RESIZE]
+                    }
+                    DRI2_OPCODE if req.data[1] == 5 => {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        let width = width.load(Ordering::SeqCst);
+                        vec![get_buffers_reply(req.seq, width, 100, width)]
+                    }
+                    _ => mock::default_response(req),
+                },
+                &[crate::Extension::Dri2],
+            )
+        };
+
+        let mut cache = BufferCache::new(&conn, window(0x200));
+        cache.buffers().unwrap();
+        let mut invalidations = 0;
+        for frame in 0..FRAMES {
+            let window = unsafe { <x::Window as crate::XidNew>::new(0x200) };
+            // several resizes per frame
+            for i in 0..3 {
+                conn.send_request(&x::ConfigureWindow {
+                    window,
+                    value_list: &[x::ConfigWindow::Width(101 + 3 * frame + i)],
+                });
+            }
+            // wait for the events that precede the reply
+            conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+                .unwrap();
+            while let Some(event) = conn.poll_for_queued_event().unwrap() {
+                if cache.handle_event(&event) {
+                    invalidations += 1;
+                }
+            }
+            let buffers = cache.buffers().unwrap();
+            assert_eq!(
+                buffers[&Attachment::BufferBackLeft].name(),
+                101 + 3 * frame + 2
+            );
+        }
+
+        assert_eq!(invalidations, FRAMES as usize * 3 * EVENTS_PER_RESIZE);
+        // one request per frame, plus the initial one
+        assert_eq!(requests.load(Ordering::SeqCst), FRAMES + 1);
+        assert_eq!(cache.generation() as u32, FRAMES + 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Scheduling of the swaps of a DRI2 drawable.
+
+use super::{BufferSwapCompleteEvent, Event, SwapBuffers};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::swap::{widen_serial, Backend, Scheduler, SwapComplete};
+use crate::x;
+
+use std::collections::VecDeque;
+
+fn hi_lo(hi: u32, lo: u32) -> u64 {
+    (hi as u64) << 32 | lo as u64
+}
+
+/// Schedules the swaps of a DRI2 drawable with `dri2::SwapBuffers`.
+///
+/// The serials of the swaps are the swap buffer counts returned by the server.
+/// The drawable must have been created with `dri2::CreateDrawable`.
+pub struct Dri2Scheduler<'a> {
+    conn: &'a Connection,
+    drawable: x::Drawable,
+    pending: VecDeque<u64>,
+    completions: Vec<SwapComplete>,
+}
+
+impl<'a> std::fmt::Debug for Dri2Scheduler<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dri2Scheduler")
+            .field("drawable", &self.drawable)
+            .field("pending", &self.pending)
+            .field("completions", &self.completions)
+            .finish()
+    }
+}
+
+impl<'a> Dri2Scheduler<'a> {
+    /// Schedules the swaps of `drawable`.
+    pub fn new(conn: &'a Connection, drawable: x::Drawable) -> Dri2Scheduler<'a> {
+        Dri2Scheduler {
+            conn,
+            drawable,
+            pending: VecDeque::new(),
+            completions: Vec::new(),
+        }
+    }
+
+    /// The drawable of the swaps.
+    pub fn drawable(&self) -> x::Drawable {
+        self.drawable
+    }
+
+    /// Records the completion of a swap if `ev` is for the drawable, in which case `true`
+    /// is returned.
+    pub fn handle_swap_complete(&mut self, ev: &BufferSwapCompleteEvent) -> bool {
+        if ev.drawable().resource_id() != self.drawable.resource_id() {
+            return false;
+        }
+        let last = self.pending.back().copied().unwrap_or(0);
+        let serial = widen_serial(last, ev.sbc());
+        while self.pending.front().is_some_and(|s| *s <= serial) {
+            self.pending.pop_front();
+        }
+        self.completions.push(SwapComplete {
+            serial,
+            ust: hi_lo(ev.ust_hi(), ev.ust_lo()),
+            msc: hi_lo(ev.msc_hi(), ev.msc_lo()),
+        });
+        true
+    }
+}
+
+impl<'a> Scheduler for Dri2Scheduler<'a> {
+    fn backend(&self) -> Backend {
+        Backend::Dri2
+    }
+
+    fn request_swap(&mut self, target_msc: u64) -> Result<u64> {
+        let cookie = self.conn.send_request(&SwapBuffers {
+            drawable: self.drawable,
+            target_msc_hi: (target_msc >> 32) as u32,
+            target_msc_lo: target_msc as u32,
+            divisor_hi: 0,
+            divisor_lo: 0,
+            remainder_hi: 0,
+            remainder_lo: 0,
+        });
+        let reply = self
+            .conn
+            .wait_for_reply(cookie)
+            .context("SwapBuffers", || {
+                format!("of drawable {}", self.drawable.resource_id())
+            })?;
+        let serial = hi_lo(reply.swap_hi(), reply.swap_lo());
+        self.pending.push_back(serial);
+        Ok(serial)
+    }
+
+    fn handle_event(&mut self, event: &crate::Event) -> bool {
+        match event {
+            crate::Event::Dri2(Event::BufferSwapComplete(ev)) => self.handle_swap_complete(ev),
+            _ => false,
+        }
+    }
+
+    fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn poll_completions(&mut self) -> Vec<SwapComplete> {
+        std::mem::take(&mut self.completions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRI2_OPCODE: u8 = 140;
+
+    const DRI2_FIRST_EVENT: u8 = 80;
+
+    fn connect() -> Connection {
+        use crate::mock;
+
+        // the swap count of the drawable before the first swap
+        let mut sbc = 0xffff_fffeu64;
+        mock::connect_with_extensions(
+            move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(
+                    req,
+                    DRI2_OPCODE,
+                    DRI2_FIRST_EVENT,
+                    150,
+                )],
+                // SwapBuffers
+                DRI2_OPCODE if req.data[1] == 8 => {
+                    sbc += 1;
+                    let mut body = ((sbc >> 32) as u32).to_ne_bytes().to_vec();
+                    body.extend_from_slice(&(sbc as u32).to_ne_bytes());
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                _ => mock::default_response(req),
+            },
+            &[crate::Extension::Dri2],
+        )
+    }
+
+    fn swap_complete(drawable: u32, ust: u64, msc: u64, sbc: u32) -> crate::Event {
+        use crate::XidNew;
+
+        crate::Event::Dri2(Event::BufferSwapComplete(BufferSwapCompleteEvent::new(
+            DRI2_FIRST_EVENT,
+            crate::dri2::EventType::FlipComplete,
+            x::Drawable::Window(unsafe { x::Window::new(drawable) }),
+            (ust >> 32) as u32,
+            ust as u32,
+            (msc >> 32) as u32,
+            msc as u32,
+            sbc,
+        )))
+    }
+
+    #[test]
+    fn test_dri2_scheduler() {
+        use crate::XidNew;
+
+        let conn = connect();
+        let drawable = x::Drawable::Window(unsafe { x::Window::new(0x0120_0001) });
+        let mut scheduler = Dri2Scheduler::new(&conn, drawable);
+        assert_eq!(scheduler.backend(), Backend::Dri2);
+
+        // the swap count crosses 2^32, which the events only have the lower 32 bits of
+        assert_eq!(scheduler.request_swap(0).unwrap(), 0xffff_ffff);
+        assert_eq!(scheduler.request_swap(1001).unwrap(), 0x1_0000_0000);
+        assert_eq!(scheduler.request_swap(1002).unwrap(), 0x1_0000_0001);
+        assert_eq!(scheduler.pending(), 3);
+
+        assert!(!scheduler.handle_event(&swap_complete(0x0120_0002, 5, 1000, 0xffff_ffff)));
+        assert!(scheduler.handle_event(&swap_complete(
+            0x0120_0001,
+            0x1_0000_0010,
+            1000,
+            0xffff_ffff
+        )));
+        assert!(scheduler.handle_event(&swap_complete(0x0120_0001, 0x1_0000_4120, 1001, 0)));
+        assert_eq!(scheduler.pending(), 1);
+        assert_eq!(
+            scheduler.poll_completions(),
+            vec![
+                SwapComplete {
+                    serial: 0xffff_ffff,
+                    ust: 0x1_0000_0010,
+                    msc: 1000,
+                },
+                SwapComplete {
+                    serial: 0x1_0000_0000,
+                    ust: 0x1_0000_4120,
+                    msc: 1001,
+                },
+            ]
+        );
+        assert!(scheduler.poll_completions().is_empty());
+
+        // a missed swap is completed by the next one
+        assert_eq!(scheduler.request_swap(1003).unwrap(), 0x1_0000_0002);
+        assert!(scheduler.handle_event(&swap_complete(0x0120_0001, 0x1_0000_c350, 1003, 2)));
+        assert_eq!(scheduler.pending(), 0);
+        assert_eq!(
+            scheduler.poll_completions(),
+            vec![SwapComplete {
+                serial: 0x1_0000_0002,
+                ust: 0x1_0000_c350,
+                msc: 1003,
+            }]
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! Embedding of the windows of other clients (system trays, XEmbed).
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Embedded,
+    /// The window was reparented out of the embedder by another client.
+    Released,
+    /// The window was destroyed.
+    Destroyed,
+}
+
+/// Embeds the window of another client into a window of ours.
+///
+/// [Embedder::new] selects `StructureNotify` on the foreign window, adds it to the save-set
+/// of the client and reparents it into the embedder window. If the client crashes,
+/// the server reparents the foreign window back to the root window (see [x::change_save_set]).
+///
+/// [Embedder::handle_event] is meant to be called from the event loop with every event:
+/// it tracks the destruction of the foreign window, or its reparenting out of the embedder.
+///
+/// When the `Embedder` is dropped while the window is embedded, the window is reparented
+/// back to the root window where it was before embedding, then removed from the save-set,
+/// and its events are deselected. The window is in the save-set until it is out of the
+/// embedder, such that it survives a crash in the middle of the cleanup.
+/// If the foreign client destroyed the window first, the server has already removed it from
+/// the save-set and nothing is sent on drop. The `DestroyNotify` event may not be processed yet
+/// when the `Embedder` is dropped: the cleanup requests are checked and their errors
+/// discarded, such that they don't reach the event loop.
+pub struct Embedder<'a> {
+    conn: &'a Connection,
+    window: x::Window,
+    embedder: x::Window,
+    root: x::Window,
+    origin: (i16, i16),
+    state: State,
+}
+
+impl<'a> std::fmt::Debug for Embedder<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Embedder")
+            .field("window", &self.window)
+            .field("embedder", &self.embedder)
+            .field("root", &self.root)
+            .field("origin", &self.origin)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<'a> Embedder<'a> {
+    /// Reparents `window`, created by another client, into `embedder` at (`x`, `y`).
+    ///
+    /// Errors are returned with the name of the failed request as context
+    /// (see [crate::Error::chain]). On error, the requests that succeeded are undone.
+    pub fn new(
+        conn: &'a Connection,
+        window: x::Window,
+        embedder: x::Window,
+        x: i16,
+        y: i16,
+    ) -> Result<Embedder<'a>> {
+        let detail = || format!("for window {:#x}", window.resource_id());
+
+        let tree = conn.send_request(&x::QueryTree { window });
+        let geometry = conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        });
+        let tree = conn.wait_for_reply(tree).context("QueryTree", detail)?;
+        let geometry = conn
+            .wait_for_reply(geometry)
+            .context("GetGeometry", detail)?;
+        let root = tree.root();
+        let origin = conn.send_request(&x::TranslateCoordinates {
+            src_window: tree.parent(),
+            dst_window: root,
+            src_x: geometry.x(),
+            src_y: geometry.y(),
+        });
+        let origin = conn
+            .wait_for_reply(origin)
+            .context("TranslateCoordinates", detail)?;
+
+        let mut embedded = Embedder {
+            conn,
+            window,
+            embedder,
+            root,
+            origin: (origin.dst_x(), origin.dst_y()),
+            // such that a failure is undone by drop
+            state: State::Released,
+        };
+
+        // events are selected first such that the destruction of the window can't be missed
+        conn.send_and_check_request(&x::ChangeWindowAttributes {
+            window,
+            value_list: &[x::Cw::EventMask(x::EventMask::STRUCTURE_NOTIFY)],
+        })
+        .context("ChangeWindowAttributes", detail)?;
+        x::change_save_set(conn, x::SetMode::Insert, window)?;
+        conn.send_and_check_request(&x::ReparentWindow {
+            window,
+            parent: embedder,
+            x,
+            y,
+        })
+        .context("ReparentWindow", detail)?;
+
+        embedded.state = State::Embedded;
+        Ok(embedded)
+    }
+
+    /// The foreign window.
+    pub fn window(&self) -> x::Window {
+        self.window
+    }
+
+    /// The window into which the foreign window is embedded.
+    pub fn embedder(&self) -> x::Window {
+        self.embedder
+    }
+
+    /// Whether the foreign window is still in the embedder.
+    pub fn is_embedded(&self) -> bool {
+        self.state == State::Embedded
+    }
+
+    /// Whether the foreign window was destroyed.
+    pub fn is_destroyed(&self) -> bool {
+        self.state == State::Destroyed
+    }
+
+    /// Updates the state of the foreign window with `event`.
+    ///
+    /// Returns `true` if `event` is the destruction of the foreign window, or its reparenting
+    /// out of the embedder.
+    pub fn handle_event(&mut self, event: &crate::Event) -> bool {
+        match event {
+            crate::Event::X(x::Event::DestroyNotify(ev)) if ev.window() == self.window => {
+                self.state = State::Destroyed;
+                true
+            }
+            crate::Event::X(x::Event::ReparentNotify(ev))
+                if ev.window() == self.window
+                    && ev.parent() != self.embedder
+                    && self.state == State::Embedded =>
+            {
+                self.state = State::Released;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Drop for Embedder<'a> {
+    fn drop(&mut self) {
+        let conn = self.conn;
+        let window = self.window;
+        match self.state {
+            State::Destroyed => return,
+            State::Embedded => conn.discard_reply(conn.send_request_checked(&x::ReparentWindow {
+                window,
+                parent: self.root,
+                x: self.origin.0,
+                y: self.origin.1,
+            })),
+            State::Released => {}
+        }
+        conn.discard_reply(conn.send_request_checked(&x::ChangeSaveSet {
+            mode: x::SetMode::Delete,
+            window,
+        }));
+        conn.discard_reply(conn.send_request_checked(&x::ChangeWindowAttributes {
+            window,
+            value_list: &[x::Cw::EventMask(x::EventMask::NO_EVENT)],
+        }));
+        let _ = conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32) -> x::Window {
+        use crate::XidNew;
+        unsafe { x::Window::new(id) }
+    }
+
+    /// The requests recorded by the mock server of [connect]: opcode, window and data.
+    type Requests = std::sync::Arc<std::sync::Mutex<Vec<(u8, u32, Vec<u8>)>>>;
+
+    /// A mock server where the foreign window 0x300 is at (10, 20) in 0x150, itself at
+    /// (100, 200) in the root window.
+    ///
+    /// The requests in `fail` get a `Window` error.
+    fn connect(fail: &'static [u8]) -> (Connection, Requests) {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let requests = requests.clone();
+            mock::connect(move |req| {
+                if [2, 6, 7].contains(&req.opcode) {
+                    requests
+                        .lock()
+                        .unwrap()
+                        .push((req.opcode, req.u32_at(4), req.data.clone()));
+                }
+                if fail.contains(&req.opcode) {
+                    return vec![mock::error(req.seq, 3, req.u32_at(4), req.opcode)];
+                }
+                match req.opcode {
+                    // GetGeometry
+                    14 => {
+                        let mut body = mock::ROOT.to_ne_bytes().to_vec();
+                        for v in [10i16, 20, 50, 50, 1] {
+                            body.extend_from_slice(&v.to_ne_bytes());
+                        }
+                        vec![mock::reply(req.seq, 24, &body)]
+                    }
+                    // QueryTree
+                    15 => {
+                        let mut body = mock::ROOT.to_ne_bytes().to_vec();
+                        body.extend_from_slice(&0x150u32.to_ne_bytes());
+                        vec![mock::reply(req.seq, 0, &body)]
+                    }
+                    // TranslateCoordinates
+                    40 => {
+                        let mut body = 0u32.to_ne_bytes().to_vec();
+                        body.extend_from_slice(&(req.u32_at(12) as i16 + 100).to_ne_bytes());
+                        body.extend_from_slice(
+                            &((req.u32_at(12) >> 16) as i16 + 200).to_ne_bytes(),
+                        );
+                        vec![mock::reply(req.seq, 1, &body)]
+                    }
+                    _ => mock::default_response(req),
+                }
+            })
+        };
+        (conn, requests)
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    fn take_requests(requests: &Requests) -> Vec<(u8, u32, Vec<u8>)> {
+        std::mem::take(&mut *requests.lock().unwrap())
+    }
+
+    #[test]
+    fn test_embed_and_drop() {
+        let (conn, requests) = connect(&[]);
+        let mut embedder = Embedder::new(&conn, window(0x300), window(0x200), 5, 6).unwrap();
+        assert!(embedder.is_embedded());
+
+        let requests_new = take_requests(&requests);
+        assert_eq!(
+            requests_new.iter().map(|r| (r.0, r.1)).collect::<Vec<_>>(),
+            [(2, 0x300), (6, 0x300), (7, 0x300)]
+        );
+        // StructureNotify selected first
+        assert_eq!(u32_at(&requests_new[0].2, 8), 0x800);
+        assert_eq!(
+            u32_at(&requests_new[0].2, 12),
+            x::EventMask::STRUCTURE_NOTIFY.bits()
+        );
+        // inserted in the save-set
+        assert_eq!(requests_new[1].2[1], 0);
+        // reparented into the embedder
+        assert_eq!(u32_at(&requests_new[2].2, 8), 0x200);
+        assert_eq!(&requests_new[2].2[12..16], &[5, 0, 6, 0]);
+
+        // unrelated events
+        assert!(
+            !embedder.handle_event(&crate::Event::X(x::Event::DestroyNotify(
+                x::DestroyNotifyEvent::new(window(0x200), window(0x400))
+            )))
+        );
+        // our own reparenting
+        assert!(
+            !embedder.handle_event(&crate::Event::X(x::Event::ReparentNotify(
+                x::ReparentNotifyEvent::new(
+                    window(0x300),
+                    window(0x300),
+                    window(0x200),
+                    5,
+                    6,
+                    false
+                )
+            )))
+        );
+        assert!(embedder.is_embedded());
+
+        drop(embedder);
+        conn.send_and_check_request(&x::NoOperation {}).unwrap();
+        let requests = take_requests(&requests);
+        assert_eq!(
+            requests.iter().map(|r| (r.0, r.1)).collect::<Vec<_>>(),
+            [(7, 0x300), (6, 0x300), (2, 0x300)]
+        );
+        // reparented to the root where it was: (100 + 10, 200 + 20)
+        assert_eq!(u32_at(&requests[0].2, 8), crate::mock::ROOT);
+        assert_eq!(&requests[0].2[12..16], &[110, 0, 220, 0]);
+        // removed from the save-set
+        assert_eq!(requests[1].2[1], 1);
+        // events deselected
+        assert_eq!(u32_at(&requests[2].2, 12), 0);
+    }
+
+    #[test]
+    fn test_embedded_window_destroyed() {
+        let (conn, requests) = connect(&[]);
+        let mut embedder = Embedder::new(&conn, window(0x300), window(0x200), 0, 0).unwrap();
+        take_requests(&requests);
+
+        assert!(
+            embedder.handle_event(&crate::Event::X(x::Event::DestroyNotify(
+                x::DestroyNotifyEvent::new(window(0x300), window(0x300))
+            )))
+        );
+        assert!(embedder.is_destroyed());
+        assert!(!embedder.is_embedded());
+
+        // nothing to undo
+        drop(embedder);
+        conn.send_and_check_request(&x::NoOperation {}).unwrap();
+        assert!(take_requests(&requests).is_empty());
+    }
+
+    #[test]
+    fn test_embedded_window_released() {
+        let (conn, requests) = connect(&[]);
+        let mut embedder = Embedder::new(&conn, window(0x300), window(0x200), 0, 0).unwrap();
+        take_requests(&requests);
+
+        // the foreign client moved its window out
+        assert!(
+            embedder.handle_event(&crate::Event::X(x::Event::ReparentNotify(
+                x::ReparentNotifyEvent::new(
+                    window(0x300),
+                    window(0x300),
+                    window(crate::mock::ROOT),
+                    0,
+                    0,
+                    false
+                )
+            )))
+        );
+        assert!(!embedder.is_embedded());
+        assert!(!embedder.is_destroyed());
+
+        drop(embedder);
+        conn.send_and_check_request(&x::NoOperation {}).unwrap();
+        let requests = take_requests(&requests);
+        assert_eq!(requests.iter().map(|r| r.0).collect::<Vec<_>>(), [6, 2]);
+    }
+
+    #[test]
+    fn test_embed_failure() {
+        // the foreign window is destroyed before it is reparented
+        let (conn, requests) = connect(&[7]);
+        let err = Embedder::new(&conn, window(0x300), window(0x200), 0, 0).unwrap_err();
+        assert!(matches!(
+            err.root_cause(),
+            crate::Error::Protocol(crate::ProtocolError::X(x::Error::Window(_), _))
+        ));
+        assert!(format!("{}", err).contains("ReparentWindow"));
+
+        // the save-set insertion and the event selection are undone, and the errors of
+        // the cleanup don't reach the event loop
+        conn.send_and_check_request(&x::NoOperation {}).unwrap();
+        let requests = take_requests(&requests);
+        assert_eq!(
+            requests.iter().map(|r| r.0).collect::<Vec<_>>(),
+            [2, 6, 7, 6, 2]
+        );
+        assert!(conn.poll_for_queued_event().unwrap().is_none());
+    }
+}
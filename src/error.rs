@@ -1,6 +1,7 @@
 use crate::base::ResolveWireError;
 use crate::ext::{Extension, ExtensionData};
 use crate::ffi::*;
+use crate::raw::{generic_error, GenericError};
 use crate::x;
 use std::mem;
 
@@ -13,6 +14,9 @@ use crate::glx;
 #[cfg(feature = "randr")]
 use crate::randr;
 
+#[cfg(feature = "record")]
+use crate::record;
+
 #[cfg(feature = "render")]
 use crate::render;
 
@@ -43,7 +47,10 @@ use crate::xv;
 /// A protocol error issued from the X server
 ///
 /// The second member is the name of the request that emitted the error (if any).
+///
+/// New variants may be added in minor releases: matches on `ProtocolError` need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ProtocolError {
     /// The error is from the core X protocol.
     X(x::Error, Option<&'static str>),
@@ -60,6 +67,10 @@ pub enum ProtocolError {
     /// The error is issued from the `RANDR` extension.
     RandR(randr::Error, Option<&'static str>),
 
+    #[cfg(feature = "record")]
+    /// The error is issued from the `RECORD` extension.
+    Record(record::Error, Option<&'static str>),
+
     #[cfg(feature = "render")]
     /// The error is issued from the `RENDER` extension.
     Render(render::Error, Option<&'static str>),
@@ -95,6 +106,83 @@ pub enum ProtocolError {
     #[cfg(feature = "xv")]
     /// The error is issued from the `XVideo` extension.
     Xv(xv::Error, Option<&'static str>),
+
+    /// The error was not recognized, it was likely issued from a disabled extension.
+    Unknown(GenericError, Option<&'static str>),
+}
+
+impl ProtocolError {
+    /// The 16 bits sequence number of the request in error.
+    pub fn sequence(&self) -> u16 {
+        match self {
+            ProtocolError::X(err, _) => err.sequence(),
+            #[cfg(feature = "damage")]
+            ProtocolError::Damage(err, _) => err.sequence(),
+            #[cfg(feature = "glx")]
+            ProtocolError::Glx(err, _) => err.sequence(),
+            #[cfg(feature = "randr")]
+            ProtocolError::RandR(err, _) => err.sequence(),
+            #[cfg(feature = "record")]
+            ProtocolError::Record(err, _) => err.sequence(),
+            #[cfg(feature = "render")]
+            ProtocolError::Render(err, _) => err.sequence(),
+            #[cfg(feature = "shm")]
+            ProtocolError::Shm(err, _) => err.sequence(),
+            #[cfg(feature = "sync")]
+            ProtocolError::Sync(err, _) => err.sequence(),
+            #[cfg(feature = "xf86vidmode")]
+            ProtocolError::Xf86VidMode(err, _) => err.sequence(),
+            #[cfg(feature = "xfixes")]
+            ProtocolError::XFixes(err, _) => err.sequence(),
+            #[cfg(feature = "xinput")]
+            ProtocolError::Input(err, _) => err.sequence(),
+            #[cfg(feature = "xkb")]
+            ProtocolError::Xkb(err, _) => err.sequence(),
+            #[cfg(feature = "xprint")]
+            ProtocolError::XPrint(err, _) => err.sequence(),
+            #[cfg(feature = "xv")]
+            ProtocolError::Xv(err, _) => err.sequence(),
+            ProtocolError::Unknown(err, _) => err.sequence(),
+        }
+    }
+
+    /// The sequence number of the request in error, widened to 32 bits by libxcb.
+    ///
+    /// It matches the lower 32 bits of the [sequence](crate::Cookie::sequence) of the
+    /// cookie of the request, which is how the errors of unchecked requests, delivered to
+    /// the event loop, are attributed to their request.
+    pub fn full_sequence(&self) -> u32 {
+        match self {
+            ProtocolError::X(err, _) => err.full_sequence(),
+            #[cfg(feature = "damage")]
+            ProtocolError::Damage(err, _) => err.full_sequence(),
+            #[cfg(feature = "glx")]
+            ProtocolError::Glx(err, _) => err.full_sequence(),
+            #[cfg(feature = "randr")]
+            ProtocolError::RandR(err, _) => err.full_sequence(),
+            #[cfg(feature = "record")]
+            ProtocolError::Record(err, _) => err.full_sequence(),
+            #[cfg(feature = "render")]
+            ProtocolError::Render(err, _) => err.full_sequence(),
+            #[cfg(feature = "shm")]
+            ProtocolError::Shm(err, _) => err.full_sequence(),
+            #[cfg(feature = "sync")]
+            ProtocolError::Sync(err, _) => err.full_sequence(),
+            #[cfg(feature = "xf86vidmode")]
+            ProtocolError::Xf86VidMode(err, _) => err.full_sequence(),
+            #[cfg(feature = "xfixes")]
+            ProtocolError::XFixes(err, _) => err.full_sequence(),
+            #[cfg(feature = "xinput")]
+            ProtocolError::Input(err, _) => err.full_sequence(),
+            #[cfg(feature = "xkb")]
+            ProtocolError::Xkb(err, _) => err.full_sequence(),
+            #[cfg(feature = "xprint")]
+            ProtocolError::XPrint(err, _) => err.full_sequence(),
+            #[cfg(feature = "xv")]
+            ProtocolError::Xv(err, _) => err.full_sequence(),
+            ProtocolError::Unknown(err, _) => err.full_sequence(),
+        }
+    }
 }
 
 impl std::fmt::Display for ProtocolError {
@@ -111,9 +199,29 @@ pub(crate) unsafe fn resolve_error(
 ) -> ProtocolError {
     debug_assert!(!error.is_null());
 
-    let error_code = (*error).error_code;
-    let major_code = (*error).major_code;
-    let minor_code = (*error).minor_code;
+    // SAFETY: the caller guarantees that `error` is an error returned by XCB
+    #[cfg(feature = "log")]
+    let (error_code, seq) = unsafe { ((*error).error_code, (*error).full_sequence) };
+    // SAFETY: forwarded from the caller
+    let (err, _emitted_by) = unsafe { resolve_wire_protocol_error(error, extension_data) };
+    #[cfg(feature = "log")]
+    crate::logging::protocol_error(&err, _emitted_by, error_code, seq);
+    err
+}
+
+/// Resolves `error`, and returns it with the name of the request that emitted it.
+unsafe fn resolve_wire_protocol_error(
+    error: *mut xcb_generic_error_t,
+    extension_data: &[ExtensionData],
+) -> (ProtocolError, Option<&'static str>) {
+    // SAFETY: the caller guarantees that `error` is an error returned by XCB
+    let (error_code, major_code, minor_code) = unsafe {
+        (
+            (*error).error_code,
+            (*error).major_code,
+            (*error).minor_code,
+        )
+    };
 
     let (best, emitting_ext) = {
         let mut best: Option<&ExtensionData> = None;
@@ -228,79 +336,76 @@ pub(crate) unsafe fn resolve_error(
         crate::x::request_name(major_code as u16)
     };
 
-    if let Some(ext_data) = best {
-        match ext_data.ext {
-            #[cfg(feature = "damage")]
-            Extension::Damage => ProtocolError::Damage(
-                damage::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
-
-            #[cfg(feature = "glx")]
-            Extension::Glx => ProtocolError::Glx(
-                glx::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
-
-            #[cfg(feature = "randr")]
-            Extension::RandR => ProtocolError::RandR(
-                randr::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
-
-            #[cfg(feature = "shm")]
-            Extension::Shm => ProtocolError::Shm(
-                shm::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
+    // SAFETY: the caller guarantees that `error` is an error returned by XCB
+    let err = unsafe {
+        if let Some(ext_data) = best {
+            match ext_data.ext {
+                #[cfg(feature = "damage")]
+                Extension::Damage => damage::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Damage(err, emitted_by)),
+
+                #[cfg(feature = "glx")]
+                Extension::Glx => glx::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Glx(err, emitted_by)),
+
+                #[cfg(feature = "randr")]
+                Extension::RandR => randr::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::RandR(err, emitted_by)),
+
+                #[cfg(feature = "record")]
+                Extension::Record => record::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Record(err, emitted_by)),
+
+                #[cfg(feature = "render")]
+                Extension::Render => render::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Render(err, emitted_by)),
+
+                #[cfg(feature = "shm")]
+                Extension::Shm => shm::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Shm(err, emitted_by)),
+
+                #[cfg(feature = "sync")]
+                Extension::Sync => sync::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Sync(err, emitted_by)),
+
+                #[cfg(feature = "xf86vidmode")]
+                Extension::Xf86VidMode => {
+                    xf86vidmode::Error::resolve_wire_error(ext_data.first_error, error)
+                        .map(|err| ProtocolError::Xf86VidMode(err, emitted_by))
+                }
 
-            #[cfg(feature = "sync")]
-            Extension::Sync => ProtocolError::Sync(
-                sync::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
+                #[cfg(feature = "xfixes")]
+                Extension::XFixes => xfixes::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::XFixes(err, emitted_by)),
 
-            #[cfg(feature = "xf86vidmode")]
-            Extension::Xf86VidMode => ProtocolError::Xf86VidMode(
-                xf86vidmode::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
+                #[cfg(feature = "xinput")]
+                Extension::Input => xinput::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Input(err, emitted_by)),
 
-            #[cfg(feature = "xfixes")]
-            Extension::XFixes => ProtocolError::XFixes(
-                xfixes::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
+                #[cfg(feature = "xkb")]
+                Extension::Xkb => xkb::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Xkb(err, emitted_by)),
 
-            #[cfg(feature = "xinput")]
-            Extension::Input => ProtocolError::Input(
-                xinput::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
+                #[cfg(feature = "xprint")]
+                Extension::XPrint => xprint::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::XPrint(err, emitted_by)),
 
-            #[cfg(feature = "xkb")]
-            Extension::Xkb => ProtocolError::Xkb(
-                xkb::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
+                #[cfg(feature = "xv")]
+                Extension::Xv => xv::Error::resolve_wire_error(ext_data.first_error, error)
+                    .map(|err| ProtocolError::Xv(err, emitted_by)),
 
-            #[cfg(feature = "xprint")]
-            Extension::XPrint => ProtocolError::XPrint(
-                xprint::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
-
-            #[cfg(feature = "xv")]
-            Extension::Xv => ProtocolError::Xv(
-                xv::Error::resolve_wire_error(ext_data.first_error, error),
-                emitted_by,
-            ),
-
-            _ => unreachable!("Could not match extension event"),
+                _ => None,
+            }
+        } else {
+            x::Error::resolve_wire_error(0, error).map(|err| ProtocolError::X(err, emitted_by))
         }
-    } else {
-        ProtocolError::X(x::Error::resolve_wire_error(0, error), emitted_by)
-    }
+    };
+    // the error is of an extension that is not cached by the connection, or that has no
+    // error type in this crate
+    // SAFETY: `error` was not resolved, its ownership is passed on
+    let err =
+        err.unwrap_or_else(|| ProtocolError::Unknown(unsafe { generic_error(error) }, emitted_by));
+    (err, emitted_by)
 }
 
 #[cfg(all(feature = "xinput", feature = "xkb", feature = "screensaver"))]
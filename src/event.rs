@@ -146,6 +146,30 @@ impl Event {
             Self::Unknown(e) => e.as_raw(),
         }
     }
+
+    /// The server time of the events of the core protocol that carry one: the key, button,
+    /// motion, crossing, property and selection events.
+    ///
+    /// This is the timestamp to pass to the requests triggered by the event, e.g.
+    /// `x::SetInputFocus` or `x::SetSelectionOwner`, rather than [x::CURRENT_TIME], which
+    /// the server replaces by the time it processes the request.
+    pub fn time(&self) -> Option<x::Timestamp> {
+        match self {
+            Self::X(x::Event::KeyPress(ev)) | Self::X(x::Event::KeyRelease(ev)) => Some(ev.time()),
+            Self::X(x::Event::ButtonPress(ev)) | Self::X(x::Event::ButtonRelease(ev)) => {
+                Some(ev.time())
+            }
+            Self::X(x::Event::MotionNotify(ev)) => Some(ev.time()),
+            Self::X(x::Event::EnterNotify(ev)) | Self::X(x::Event::LeaveNotify(ev)) => {
+                Some(ev.time())
+            }
+            Self::X(x::Event::PropertyNotify(ev)) => Some(ev.time()),
+            Self::X(x::Event::SelectionClear(ev)) => Some(ev.time()),
+            Self::X(x::Event::SelectionRequest(ev)) => Some(ev.time()),
+            Self::X(x::Event::SelectionNotify(ev)) => Some(ev.time()),
+            _ => None,
+        }
+    }
 }
 
 /// an event was not recognized as part of the core protocol or any enabled extension
@@ -178,6 +202,23 @@ impl UnknownEvent {
     pub fn full_sequence(&self) -> u32 {
         unsafe { (*self.raw).full_sequence }
     }
+
+    /// Converts the event to `T` if its `response_type` is the one of `T`.
+    ///
+    /// `first_event` is the first event of the extension of `T`, as returned by its
+    /// `get_extension_data`. It is ignored for the events of the main protocol.
+    /// The event is given back if it is not a `T`.
+    ///
+    /// This is of use for the events of the extensions that were not cached by the
+    /// connection, which are not resolved by [Event].
+    pub fn cast_event<T: BaseEvent>(self, first_event: u8) -> Result<T, UnknownEvent> {
+        if self.response_type() & 0x7F != T::event_code(first_event) {
+            return Err(self);
+        }
+        // SAFETY: `T` is the event type of the response type, and takes the ownership
+        // of the allocation
+        Ok(unsafe { T::from_raw(self.into_raw()) })
+    }
 }
 
 impl std::fmt::Debug for UnknownEvent {
@@ -192,25 +233,36 @@ impl Drop for UnknownEvent {
     }
 }
 
+// SAFETY: like the generated events, the event owns its allocation and is immutable
+unsafe impl Send for UnknownEvent {}
+unsafe impl Sync for UnknownEvent {}
+
 pub(crate) unsafe fn resolve_event(
     event: *mut xcb_generic_event_t,
     extension_data: &[ExtensionData],
 ) -> Event {
-    let response_type = (*event).response_type & 0x7F;
+    // SAFETY: the caller guarantees that `event` is an event returned by XCB, and passes on
+    // its ownership to the resolved event
+    let response_type = unsafe { (*event).response_type } & 0x7F;
 
     if response_type == XCB_GE_GENERIC {
         let event = event as *mut xcb_ge_generic_event_t;
-        let extension = (*event).extension;
+        // SAFETY: GE events are at least 32 bytes long
+        let extension = unsafe { (*event).extension };
         for ext in extension_data {
             if ext.major_opcode == extension {
                 match ext.ext {
                     #[cfg(feature = "present")]
                     Extension::Present => {
-                        return Event::Present(present::Event::resolve_wire_ge_event(event));
+                        return Event::Present(unsafe {
+                            present::Event::resolve_wire_ge_event(event)
+                        });
                     }
                     #[cfg(feature = "xinput")]
                     Extension::Input => {
-                        return Event::Input(xinput::Event::resolve_wire_ge_event(event));
+                        return Event::Input(unsafe {
+                            xinput::Event::resolve_wire_ge_event(event)
+                        });
                     }
                     _ => panic!("could not resolve Generic Event extension"),
                 }
@@ -224,98 +276,108 @@ pub(crate) unsafe fn resolve_event(
                 #[cfg(feature = "damage")]
                 Extension::Damage => {
                     return Event::Damage(
-                        damage::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { damage::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "dri2")]
                 Extension::Dri2 => {
                     return Event::Dri2(
-                        dri2::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { dri2::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "glx")]
                 Extension::Glx => {
                     return Event::Glx(
-                        glx::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { glx::Event::resolve_wire_event(data.first_event, event) }.unwrap(),
                     );
                 }
 
                 #[cfg(feature = "present")]
                 Extension::Present => {
                     return Event::Present(
-                        present::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { present::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "randr")]
                 Extension::RandR => {
                     return Event::RandR(
-                        randr::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { randr::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "screensaver")]
                 Extension::ScreenSaver => {
                     return Event::ScreenSaver(
-                        screensaver::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { screensaver::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "shape")]
                 Extension::Shape => {
                     return Event::Shape(
-                        shape::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { shape::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "shm")]
                 Extension::Shm => {
                     return Event::Shm(
-                        shm::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { shm::Event::resolve_wire_event(data.first_event, event) }.unwrap(),
                     );
                 }
 
                 #[cfg(feature = "sync")]
                 Extension::Sync => {
                     return Event::Sync(
-                        sync::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { sync::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "xfixes")]
                 Extension::XFixes => {
                     return Event::XFixes(
-                        xfixes::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { xfixes::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "xinput")]
                 Extension::Input => {
                     return Event::Input(
-                        xinput::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { xinput::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "xkb")]
                 Extension::Xkb => {
                     return Event::Xkb(
-                        xkb::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { xkb::Event::resolve_wire_event(data.first_event, event) }.unwrap(),
                     );
                 }
 
                 #[cfg(feature = "xprint")]
                 Extension::XPrint => {
                     return Event::XPrint(
-                        xprint::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { xprint::Event::resolve_wire_event(data.first_event, event) }
+                            .unwrap(),
                     );
                 }
 
                 #[cfg(feature = "xv")]
                 Extension::Xv => {
                     return Event::Xv(
-                        xv::Event::resolve_wire_event(data.first_event, event).unwrap(),
+                        unsafe { xv::Event::resolve_wire_event(data.first_event, event) }.unwrap(),
                     );
                 }
 
@@ -324,12 +386,12 @@ pub(crate) unsafe fn resolve_event(
         }
     }
 
-    x::Event::resolve_wire_event(0, event)
+    unsafe { x::Event::resolve_wire_event(0, event) }
         .map(Event::X)
         .unwrap_or_else(|| {
             // SAFETY the event type is checked above and the function panicked if it was
             // not a basic event (XCB_GE_GENERIC)
-            let unknown = UnknownEvent::from_raw(event);
+            let unknown = unsafe { UnknownEvent::from_raw(event) };
             Event::Unknown(unknown)
         })
 }
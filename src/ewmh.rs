@@ -0,0 +1,1092 @@
+//! Helpers for the properties of the
+//! [Extended Window Manager Hints](https://specifications.freedesktop.org/wm-spec/latest/).
+//!
+//! [EwmhConnection] interns the atoms of the EWMH once, and gets and sets the common
+//! properties of the root and client windows with their types. The `_NET_WM_ICON`
+//! helpers only need a [Connection].
+
+use crate::base::{Connection, Result, ResultExt, Xid, XidNew};
+use crate::x;
+
+/// An icon of the `_NET_WM_ICON` property.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IconData {
+    pub width: u32,
+    pub height: u32,
+    /// The pixels in ARGB format (alpha in the most significant byte), row by row,
+    /// left to right and top to bottom.
+    pub argb: Vec<u32>,
+}
+
+/// Encodes icons in the `_NET_WM_ICON` format: for each icon, the width, the height
+/// and the pixels.
+///
+/// # Panics
+/// Panics if the length of the pixels of an icon is not `width * height`.
+pub fn encode_wm_icon(icons: &[IconData]) -> Vec<u32> {
+    let len = icons.iter().map(|icon| 2 + icon.argb.len()).sum();
+    let mut data = Vec::with_capacity(len);
+    for icon in icons {
+        assert_eq!(
+            Some(icon.argb.len() as u64),
+            (icon.width as u64).checked_mul(icon.height as u64),
+            "icon of {}x{} has {} pixels",
+            icon.width,
+            icon.height,
+            icon.argb.len()
+        );
+        data.push(icon.width);
+        data.push(icon.height);
+        data.extend_from_slice(&icon.argb);
+    }
+    data
+}
+
+/// Decodes the icons of a `_NET_WM_ICON` property.
+///
+/// Icons with a null size are skipped. A truncated trailing icon
+/// (as set by some buggy clients) is ignored.
+pub fn parse_wm_icon(mut data: &[u32]) -> Vec<IconData> {
+    let mut icons = Vec::new();
+    while data.len() >= 2 {
+        let (width, height) = (data[0], data[1]);
+        let len = (width as u64) * (height as u64);
+        if len > (data.len() - 2) as u64 {
+            break;
+        }
+        let (argb, rest) = data[2..].split_at(len as usize);
+        if len != 0 {
+            icons.push(IconData {
+                width,
+                height,
+                argb: argb.to_vec(),
+            });
+        }
+        data = rest;
+    }
+    icons
+}
+
+fn net_wm_icon_atom(conn: &Connection) -> Result<x::Atom> {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: false,
+        name: b"_NET_WM_ICON",
+    });
+    Ok(conn
+        .wait_for_reply(cookie)
+        .context("InternAtom", || "_NET_WM_ICON")?
+        .atom())
+}
+
+/// Sets the `_NET_WM_ICON` property of `window`.
+///
+/// Multiple sizes of the same icon can be given, the window manager or the task bar
+/// chooses the most appropriate one.
+/// If the property does not fit in a single request, it is set with several
+/// `ChangeProperty` requests (see [Connection::get_maximum_request_length]). An empty
+/// `icons` deletes the property.
+///
+/// # Panics
+/// Panics if the length of the pixels of an icon is not `width * height`.
+pub fn set_wm_icon(conn: &Connection, window: x::Window, icons: &[IconData]) -> Result<()> {
+    let data = encode_wm_icon(icons);
+    let property = net_wm_icon_atom(conn)?;
+    let detail = || format!("_NET_WM_ICON of window {:#x}", window.resource_id());
+
+    if data.is_empty() {
+        return conn
+            .send_and_check_request(&x::DeleteProperty { window, property })
+            .context("DeleteProperty", detail);
+    }
+
+    let change_property = |mode, data| x::ChangeProperty {
+        mode,
+        window,
+        property,
+        r#type: x::ATOM_CARDINAL,
+        data,
+    };
+    let chunk_len =
+        if conn.fits_in_request(change_property(x::PropMode::Replace, &data).request_len()) {
+            data.len()
+        } else {
+            let header = change_property(x::PropMode::Replace, &[]).request_len();
+            let mut chunk_len = (conn.get_maximum_request_length() as usize * 4 - header) / 4;
+            // the extended length of BIG-REQUESTS takes 4 bytes
+            if !conn.fits_in_request(header + chunk_len * 4) {
+                chunk_len -= 1;
+            }
+            chunk_len
+        };
+    let cookies: Vec<_> = data
+        .chunks(chunk_len)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mode = if i == 0 {
+                x::PropMode::Replace
+            } else {
+                x::PropMode::Append
+            };
+            conn.send_request_checked(&change_property(mode, chunk))
+        })
+        .collect();
+    for cookie in cookies {
+        conn.check_request(cookie)
+            .context("ChangeProperty", detail)?;
+    }
+    Ok(())
+}
+
+/// Fetches the icons of the `_NET_WM_ICON` property of `window`.
+///
+/// Returns an empty list if the property is not set, and a
+/// [PropertyError](crate::PropertyError) if it is set with another type than `CARDINAL`
+/// or another format than 32.
+/// See [parse_wm_icon] for the handling of malformed properties.
+pub fn get_wm_icon(conn: &Connection, window: x::Window) -> Result<Vec<IconData>> {
+    let property = net_wm_icon_atom(conn)?;
+    let value = x::get_full_property(conn, window, property, x::ATOM_CARDINAL)?;
+    Ok(value
+        .expect_data32("_NET_WM_ICON")?
+        .map(|data| parse_wm_icon(&data))
+        .unwrap_or_default())
+}
+
+macro_rules! ewmh_atoms {
+    ($($field:ident => $name:literal,)*) => {
+        /// The atoms of the EWMH, interned by [EwmhConnection::new].
+        #[derive(Copy, Clone, Debug)]
+        pub struct EwmhAtoms {
+            $(
+                #[doc = concat!("`", $name, "`")]
+                pub $field: x::Atom,
+            )*
+        }
+
+        impl EwmhAtoms {
+            fn intern_all(conn: &Connection) -> Result<EwmhAtoms> {
+                let mut atoms = x::intern_atoms(conn, &[$($name,)*], false)?.into_iter();
+                Ok(EwmhAtoms {
+                    $($field: atoms.next().unwrap(),)*
+                })
+            }
+        }
+    };
+}
+
+ewmh_atoms! {
+    utf8_string => "UTF8_STRING",
+    net_supported => "_NET_SUPPORTED",
+    net_client_list => "_NET_CLIENT_LIST",
+    net_client_list_stacking => "_NET_CLIENT_LIST_STACKING",
+    net_number_of_desktops => "_NET_NUMBER_OF_DESKTOPS",
+    net_current_desktop => "_NET_CURRENT_DESKTOP",
+    net_desktop_names => "_NET_DESKTOP_NAMES",
+    net_active_window => "_NET_ACTIVE_WINDOW",
+    net_workarea => "_NET_WORKAREA",
+    net_supporting_wm_check => "_NET_SUPPORTING_WM_CHECK",
+    net_close_window => "_NET_CLOSE_WINDOW",
+    net_wm_name => "_NET_WM_NAME",
+    net_wm_visible_name => "_NET_WM_VISIBLE_NAME",
+    net_wm_desktop => "_NET_WM_DESKTOP",
+    net_wm_window_type => "_NET_WM_WINDOW_TYPE",
+    net_wm_state => "_NET_WM_STATE",
+    net_wm_strut => "_NET_WM_STRUT",
+    net_wm_strut_partial => "_NET_WM_STRUT_PARTIAL",
+    net_wm_icon => "_NET_WM_ICON",
+    net_wm_pid => "_NET_WM_PID",
+    net_wm_window_type_desktop => "_NET_WM_WINDOW_TYPE_DESKTOP",
+    net_wm_window_type_dock => "_NET_WM_WINDOW_TYPE_DOCK",
+    net_wm_window_type_toolbar => "_NET_WM_WINDOW_TYPE_TOOLBAR",
+    net_wm_window_type_menu => "_NET_WM_WINDOW_TYPE_MENU",
+    net_wm_window_type_utility => "_NET_WM_WINDOW_TYPE_UTILITY",
+    net_wm_window_type_splash => "_NET_WM_WINDOW_TYPE_SPLASH",
+    net_wm_window_type_dialog => "_NET_WM_WINDOW_TYPE_DIALOG",
+    net_wm_window_type_dropdown_menu => "_NET_WM_WINDOW_TYPE_DROPDOWN_MENU",
+    net_wm_window_type_popup_menu => "_NET_WM_WINDOW_TYPE_POPUP_MENU",
+    net_wm_window_type_tooltip => "_NET_WM_WINDOW_TYPE_TOOLTIP",
+    net_wm_window_type_notification => "_NET_WM_WINDOW_TYPE_NOTIFICATION",
+    net_wm_window_type_combo => "_NET_WM_WINDOW_TYPE_COMBO",
+    net_wm_window_type_dnd => "_NET_WM_WINDOW_TYPE_DND",
+    net_wm_window_type_normal => "_NET_WM_WINDOW_TYPE_NORMAL",
+    net_wm_state_modal => "_NET_WM_STATE_MODAL",
+    net_wm_state_sticky => "_NET_WM_STATE_STICKY",
+    net_wm_state_maximized_vert => "_NET_WM_STATE_MAXIMIZED_VERT",
+    net_wm_state_maximized_horz => "_NET_WM_STATE_MAXIMIZED_HORZ",
+    net_wm_state_shaded => "_NET_WM_STATE_SHADED",
+    net_wm_state_skip_taskbar => "_NET_WM_STATE_SKIP_TASKBAR",
+    net_wm_state_skip_pager => "_NET_WM_STATE_SKIP_PAGER",
+    net_wm_state_hidden => "_NET_WM_STATE_HIDDEN",
+    net_wm_state_fullscreen => "_NET_WM_STATE_FULLSCREEN",
+    net_wm_state_above => "_NET_WM_STATE_ABOVE",
+    net_wm_state_below => "_NET_WM_STATE_BELOW",
+    net_wm_state_demands_attention => "_NET_WM_STATE_DEMANDS_ATTENTION",
+    net_wm_state_focused => "_NET_WM_STATE_FOCUSED",
+}
+
+/// The change of the states of a window requested with [EwmhConnection::request_wm_state].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WmStateAction {
+    Remove = 0,
+    Add = 1,
+    Toggle = 2,
+}
+
+/// Who sends the requests to the window manager, which may treat them differently.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SourceIndication {
+    /// A regular application, on behalf of itself.
+    Application = 1,
+    /// A pager or a task bar, on behalf of the user.
+    Pager = 2,
+}
+
+/// The space reserved at the edges of the screen by a window, such as a panel, in
+/// the `_NET_WM_STRUT` property.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Strut {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+/// The space reserved by a window along a part of the edges of the screen, in
+/// the `_NET_WM_STRUT_PARTIAL` property.
+///
+/// The ranges are the coordinates of the first and of the last pixel along the edge.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StrutPartial {
+    pub strut: Strut,
+    pub left_start_y: u32,
+    pub left_end_y: u32,
+    pub right_start_y: u32,
+    pub right_end_y: u32,
+    pub top_start_x: u32,
+    pub top_end_x: u32,
+    pub bottom_start_x: u32,
+    pub bottom_end_x: u32,
+}
+
+/// A connection with the atoms of the EWMH, to get and set their properties and to send
+/// their requests to the window manager.
+///
+/// The properties of the root windows are set by the window manager, and those of the
+/// client windows by the clients. The clients ask the window manager to change them with
+/// the `request_*` methods, which send client messages to the root window.
+pub struct EwmhConnection<'c> {
+    conn: &'c Connection,
+    atoms: EwmhAtoms,
+    source: SourceIndication,
+}
+
+impl<'c> EwmhConnection<'c> {
+    /// Interns all the atoms of [EwmhAtoms] in a single round trip.
+    ///
+    /// The requests are sent as a [SourceIndication::Application].
+    pub fn new(conn: &'c Connection) -> Result<EwmhConnection<'c>> {
+        Ok(EwmhConnection {
+            conn,
+            atoms: EwmhAtoms::intern_all(conn)?,
+            source: SourceIndication::Application,
+        })
+    }
+
+    /// The connection, to send the other requests.
+    pub fn connection(&self) -> &'c Connection {
+        self.conn
+    }
+
+    /// The interned atoms.
+    pub fn atoms(&self) -> &EwmhAtoms {
+        &self.atoms
+    }
+
+    /// Sets who sends the requests to the window manager.
+    pub fn set_source_indication(&mut self, source: SourceIndication) {
+        self.source = source;
+    }
+
+    fn root(&self, screen: i32) -> x::Window {
+        self.conn
+            .get_setup()
+            .roots()
+            .nth(screen as usize)
+            .unwrap_or_else(|| panic!("no screen {}", screen))
+            .root()
+    }
+
+    fn get_data32(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+        r#type: x::Atom,
+        name: &'static str,
+    ) -> Result<Option<Vec<u32>>> {
+        x::get_full_property(self.conn, window, property, r#type)?.expect_data32(name)
+    }
+
+    fn get_list<T: XidNew>(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+        r#type: x::Atom,
+        name: &'static str,
+    ) -> Result<Vec<T>> {
+        Ok(self
+            .get_data32(window, property, r#type, name)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| unsafe { T::new(id) })
+            .collect())
+    }
+
+    fn get_cardinal(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+        name: &'static str,
+    ) -> Result<Option<u32>> {
+        Ok(self
+            .get_data32(window, property, x::ATOM_CARDINAL, name)?
+            .and_then(|data| data.first().copied()))
+    }
+
+    fn set_property<P: x::PropEl>(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+        r#type: x::Atom,
+        data: &[P],
+        name: &str,
+    ) -> Result<()> {
+        self.conn
+            .send_and_check_request(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window,
+                property,
+                r#type,
+                data,
+            })
+            .context("ChangeProperty", || {
+                format!("{} of window {:#x}", name, window.resource_id())
+            })
+    }
+
+    /// Sends the client message `message_type` about `window` to the root window of
+    /// `screen`, where the window manager receives it.
+    fn send_request(
+        &self,
+        screen: i32,
+        window: x::Window,
+        message_type: x::Atom,
+        data: [u32; 5],
+        name: &str,
+    ) -> Result<()> {
+        let event =
+            x::ClientMessageEvent::new(window, message_type, x::ClientMessageData::Data32(data));
+        self.conn
+            .send_and_check_request(&x::SendEvent {
+                propagate: false,
+                destination: x::SendEventDest::Window(self.root(screen)),
+                event_mask: x::EventMask::SUBSTRUCTURE_REDIRECT | x::EventMask::SUBSTRUCTURE_NOTIFY,
+                event: &event,
+            })
+            .context("SendEvent", || {
+                format!("{} of window {:#x}", name, window.resource_id())
+            })
+    }
+
+    /// The hints supported by the window manager of `screen` (`_NET_SUPPORTED`).
+    pub fn get_supported(&self, screen: i32) -> Result<Vec<x::Atom>> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.get_list(root, atoms.net_supported, x::ATOM_ATOM, "_NET_SUPPORTED")
+    }
+
+    /// Sets the hints supported by the window manager of `screen`.
+    pub fn set_supported(&self, screen: i32, hints: &[x::Atom]) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.set_property(
+            root,
+            atoms.net_supported,
+            x::ATOM_ATOM,
+            hints,
+            "_NET_SUPPORTED",
+        )
+    }
+
+    /// The windows managed by the window manager of `screen`, in mapping order
+    /// (`_NET_CLIENT_LIST`).
+    pub fn get_client_list(&self, screen: i32) -> Result<Vec<x::Window>> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.get_list(
+            root,
+            atoms.net_client_list,
+            x::ATOM_WINDOW,
+            "_NET_CLIENT_LIST",
+        )
+    }
+
+    /// Sets the windows managed by the window manager of `screen`.
+    pub fn set_client_list(&self, screen: i32, windows: &[x::Window]) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.set_property(
+            root,
+            atoms.net_client_list,
+            x::ATOM_WINDOW,
+            windows,
+            "_NET_CLIENT_LIST",
+        )
+    }
+
+    /// The number of desktops of `screen` (`_NET_NUMBER_OF_DESKTOPS`).
+    pub fn get_number_of_desktops(&self, screen: i32) -> Result<Option<u32>> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.get_cardinal(
+            root,
+            atoms.net_number_of_desktops,
+            "_NET_NUMBER_OF_DESKTOPS",
+        )
+    }
+
+    /// Sets the number of desktops of `screen`.
+    pub fn set_number_of_desktops(&self, screen: i32, number: u32) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.set_property(
+            root,
+            atoms.net_number_of_desktops,
+            x::ATOM_CARDINAL,
+            &[number],
+            "_NET_NUMBER_OF_DESKTOPS",
+        )
+    }
+
+    /// Asks the window manager to change the number of desktops of `screen`.
+    pub fn request_number_of_desktops(&self, screen: i32, number: u32) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.send_request(
+            screen,
+            root,
+            atoms.net_number_of_desktops,
+            [number, 0, 0, 0, 0],
+            "_NET_NUMBER_OF_DESKTOPS",
+        )
+    }
+
+    /// The index of the current desktop of `screen`, from 0 (`_NET_CURRENT_DESKTOP`).
+    pub fn get_current_desktop(&self, screen: i32) -> Result<Option<u32>> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.get_cardinal(root, atoms.net_current_desktop, "_NET_CURRENT_DESKTOP")
+    }
+
+    /// Sets the index of the current desktop of `screen`.
+    pub fn set_current_desktop(&self, screen: i32, desktop: u32) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.set_property(
+            root,
+            atoms.net_current_desktop,
+            x::ATOM_CARDINAL,
+            &[desktop],
+            "_NET_CURRENT_DESKTOP",
+        )
+    }
+
+    /// Asks the window manager to switch `screen` to `desktop`. `time` is the timestamp of
+    /// the event that triggered the switch.
+    pub fn request_current_desktop(
+        &self,
+        screen: i32,
+        desktop: u32,
+        time: x::Timestamp,
+    ) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.send_request(
+            screen,
+            root,
+            atoms.net_current_desktop,
+            [desktop, time, 0, 0, 0],
+            "_NET_CURRENT_DESKTOP",
+        )
+    }
+
+    /// The window that has the focus on `screen` (`_NET_ACTIVE_WINDOW`), or `None` if the
+    /// property is not set or if no window has the focus.
+    pub fn get_active_window(&self, screen: i32) -> Result<Option<x::Window>> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        let windows: Vec<x::Window> = self.get_list(
+            root,
+            atoms.net_active_window,
+            x::ATOM_WINDOW,
+            "_NET_ACTIVE_WINDOW",
+        )?;
+        Ok(windows.first().copied().filter(|w| !w.is_none()))
+    }
+
+    /// Sets the window that has the focus on `screen`, or `x::WINDOW_NONE`.
+    pub fn set_active_window(&self, screen: i32, window: x::Window) -> Result<()> {
+        let (root, atoms) = (self.root(screen), &self.atoms);
+        self.set_property(
+            root,
+            atoms.net_active_window,
+            x::ATOM_WINDOW,
+            &[window],
+            "_NET_ACTIVE_WINDOW",
+        )
+    }
+
+    /// Asks the window manager to activate `window`: to switch to its desktop, raise it
+    /// and give it the focus.
+    ///
+    /// `time` is the timestamp of the event that triggered the request, and `current` the
+    /// active window of the client, or `x::WINDOW_NONE`.
+    pub fn request_active_window(
+        &self,
+        screen: i32,
+        window: x::Window,
+        time: x::Timestamp,
+        current: x::Window,
+    ) -> Result<()> {
+        self.send_request(
+            screen,
+            window,
+            self.atoms.net_active_window,
+            [self.source as u32, time, current.resource_id(), 0, 0],
+            "_NET_ACTIVE_WINDOW",
+        )
+    }
+
+    /// The title of `window` in UTF-8 (`_NET_WM_NAME`).
+    ///
+    /// The invalid UTF-8 sequences are replaced with `U+FFFD`.
+    pub fn get_wm_name(&self, window: x::Window) -> Result<Option<String>> {
+        let value = x::get_full_property(
+            self.conn,
+            window,
+            self.atoms.net_wm_name,
+            self.atoms.utf8_string,
+        )?;
+        Ok(value
+            .expect_data8("_NET_WM_NAME")?
+            .map(|name| String::from_utf8_lossy(name).into_owned()))
+    }
+
+    /// Sets the title of `window`.
+    pub fn set_wm_name(&self, window: x::Window, name: &str) -> Result<()> {
+        self.set_property(
+            window,
+            self.atoms.net_wm_name,
+            self.atoms.utf8_string,
+            name.as_bytes(),
+            "_NET_WM_NAME",
+        )
+    }
+
+    /// The index of the desktop of `window` (`_NET_WM_DESKTOP`), `0xFFFFFFFF` if it is on
+    /// all the desktops.
+    pub fn get_wm_desktop(&self, window: x::Window) -> Result<Option<u32>> {
+        self.get_cardinal(window, self.atoms.net_wm_desktop, "_NET_WM_DESKTOP")
+    }
+
+    /// Sets the desktop of `window`, before it is mapped.
+    pub fn set_wm_desktop(&self, window: x::Window, desktop: u32) -> Result<()> {
+        self.set_property(
+            window,
+            self.atoms.net_wm_desktop,
+            x::ATOM_CARDINAL,
+            &[desktop],
+            "_NET_WM_DESKTOP",
+        )
+    }
+
+    /// Asks the window manager to move the mapped `window` to `desktop`.
+    pub fn request_wm_desktop(&self, screen: i32, window: x::Window, desktop: u32) -> Result<()> {
+        self.send_request(
+            screen,
+            window,
+            self.atoms.net_wm_desktop,
+            [desktop, self.source as u32, 0, 0, 0],
+            "_NET_WM_DESKTOP",
+        )
+    }
+
+    /// The functional types of `window`, by order of preference (`_NET_WM_WINDOW_TYPE`).
+    pub fn get_wm_window_type(&self, window: x::Window) -> Result<Vec<x::Atom>> {
+        self.get_list(
+            window,
+            self.atoms.net_wm_window_type,
+            x::ATOM_ATOM,
+            "_NET_WM_WINDOW_TYPE",
+        )
+    }
+
+    /// Sets the functional types of `window`, before it is mapped.
+    pub fn set_wm_window_type(&self, window: x::Window, types: &[x::Atom]) -> Result<()> {
+        self.set_property(
+            window,
+            self.atoms.net_wm_window_type,
+            x::ATOM_ATOM,
+            types,
+            "_NET_WM_WINDOW_TYPE",
+        )
+    }
+
+    /// The states of `window`, such as `_NET_WM_STATE_FULLSCREEN` (`_NET_WM_STATE`).
+    pub fn get_wm_state(&self, window: x::Window) -> Result<Vec<x::Atom>> {
+        self.get_list(
+            window,
+            self.atoms.net_wm_state,
+            x::ATOM_ATOM,
+            "_NET_WM_STATE",
+        )
+    }
+
+    /// Sets the states of `window`, before it is mapped.
+    pub fn set_wm_state(&self, window: x::Window, states: &[x::Atom]) -> Result<()> {
+        self.set_property(
+            window,
+            self.atoms.net_wm_state,
+            x::ATOM_ATOM,
+            states,
+            "_NET_WM_STATE",
+        )
+    }
+
+    /// Asks the window manager to change the state `first` of the mapped `window`, and the
+    /// state `second` (or `x::ATOM_NONE`), such as the two maximized states.
+    pub fn request_wm_state(
+        &self,
+        screen: i32,
+        window: x::Window,
+        action: WmStateAction,
+        first: x::Atom,
+        second: x::Atom,
+    ) -> Result<()> {
+        self.send_request(
+            screen,
+            window,
+            self.atoms.net_wm_state,
+            [
+                action as u32,
+                first.resource_id(),
+                second.resource_id(),
+                self.source as u32,
+                0,
+            ],
+            "_NET_WM_STATE",
+        )
+    }
+
+    /// The process id of the client of `window` (`_NET_WM_PID`).
+    pub fn get_wm_pid(&self, window: x::Window) -> Result<Option<u32>> {
+        self.get_cardinal(window, self.atoms.net_wm_pid, "_NET_WM_PID")
+    }
+
+    /// Sets the process id of the client of `window`.
+    pub fn set_wm_pid(&self, window: x::Window, pid: u32) -> Result<()> {
+        self.set_property(
+            window,
+            self.atoms.net_wm_pid,
+            x::ATOM_CARDINAL,
+            &[pid],
+            "_NET_WM_PID",
+        )
+    }
+
+    /// The space reserved by `window` at the edges of the screen (`_NET_WM_STRUT`).
+    pub fn get_wm_strut(&self, window: x::Window) -> Result<Option<Strut>> {
+        let data = self.get_data32(
+            window,
+            self.atoms.net_wm_strut,
+            x::ATOM_CARDINAL,
+            "_NET_WM_STRUT",
+        )?;
+        Ok(data.and_then(|data| match data[..] {
+            [left, right, top, bottom, ..] => Some(Strut {
+                left,
+                right,
+                top,
+                bottom,
+            }),
+            _ => None,
+        }))
+    }
+
+    /// Sets the space reserved by `window` at the edges of the screen.
+    ///
+    /// The window managers that support `_NET_WM_STRUT_PARTIAL` ignore this property if
+    /// it is set.
+    pub fn set_wm_strut(&self, window: x::Window, strut: &Strut) -> Result<()> {
+        self.set_property(
+            window,
+            self.atoms.net_wm_strut,
+            x::ATOM_CARDINAL,
+            &[strut.left, strut.right, strut.top, strut.bottom],
+            "_NET_WM_STRUT",
+        )
+    }
+
+    /// The space reserved by `window` along parts of the edges of the screen
+    /// (`_NET_WM_STRUT_PARTIAL`).
+    pub fn get_wm_strut_partial(&self, window: x::Window) -> Result<Option<StrutPartial>> {
+        let data = self.get_data32(
+            window,
+            self.atoms.net_wm_strut_partial,
+            x::ATOM_CARDINAL,
+            "_NET_WM_STRUT_PARTIAL",
+        )?;
+        Ok(data.and_then(|data| match data[..] {
+            [left, right, top, bottom, left_start_y, left_end_y, right_start_y, right_end_y, top_start_x, top_end_x, bottom_start_x, bottom_end_x, ..] => {
+                Some(StrutPartial {
+                    strut: Strut {
+                        left,
+                        right,
+                        top,
+                        bottom,
+                    },
+                    left_start_y,
+                    left_end_y,
+                    right_start_y,
+                    right_end_y,
+                    top_start_x,
+                    top_end_x,
+                    bottom_start_x,
+                    bottom_end_x,
+                })
+            }
+            _ => None,
+        }))
+    }
+
+    /// Sets the space reserved by `window` along parts of the edges of the screen.
+    ///
+    /// The clients also set `_NET_WM_STRUT` (see [EwmhConnection::set_wm_strut]) for the
+    /// older window managers.
+    pub fn set_wm_strut_partial(&self, window: x::Window, strut: &StrutPartial) -> Result<()> {
+        let data = [
+            strut.strut.left,
+            strut.strut.right,
+            strut.strut.top,
+            strut.strut.bottom,
+            strut.left_start_y,
+            strut.left_end_y,
+            strut.right_start_y,
+            strut.right_end_y,
+            strut.top_start_x,
+            strut.top_end_x,
+            strut.bottom_start_x,
+            strut.bottom_end_x,
+        ];
+        self.set_property(
+            window,
+            self.atoms.net_wm_strut_partial,
+            x::ATOM_CARDINAL,
+            &data,
+            "_NET_WM_STRUT_PARTIAL",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icon(width: u32, height: u32) -> IconData {
+        IconData {
+            width,
+            height,
+            argb: (0..width * height).map(|i| 0xff00_0000 | i).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_wm_icon() {
+        let icons = vec![icon(16, 16), icon(2, 3)];
+        let data = encode_wm_icon(&icons);
+        assert_eq!(data.len(), 2 + 256 + 2 + 6);
+        assert_eq!(&data[..2], [16, 16]);
+        assert_eq!(parse_wm_icon(&data), icons);
+
+        // truncated trailing icon
+        assert_eq!(parse_wm_icon(&data[..data.len() - 1]), &icons[..1]);
+        assert_eq!(parse_wm_icon(&data[..259]), &icons[..1]);
+        // size that overflows
+        assert_eq!(parse_wm_icon(&[u32::MAX, u32::MAX, 0, 0]), []);
+        // null size is skipped
+        let mut data = vec![0, 4];
+        data.extend(encode_wm_icon(&icons[1..]));
+        assert_eq!(parse_wm_icon(&data), &icons[1..]);
+        assert_eq!(parse_wm_icon(&[]), []);
+        assert_eq!(parse_wm_icon(&[1]), []);
+    }
+
+    #[test]
+    #[should_panic(expected = "icon of 2x2 has 3 pixels")]
+    fn test_encode_wm_icon_mismatch() {
+        encode_wm_icon(&[IconData {
+            width: 2,
+            height: 2,
+            argb: vec![0; 3],
+        }]);
+    }
+
+    #[test]
+    fn test_wm_icon_round_trip() {
+        use crate::mock;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        const NET_WM_ICON: u32 = 400;
+
+        // `requests` records the length in 32-bit units of the ChangeProperty requests
+        let prop = Arc::new(Mutex::new(Vec::<u32>::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let (prop, requests) = (prop.clone(), requests.clone());
+            mock::connect(move |req| match req.opcode {
+                // QueryExtension(BIG-REQUESTS): not present
+                98 => vec![mock::reply(req.seq, 0, &[])],
+                16 => vec![mock::reply(req.seq, 0, &NET_WM_ICON.to_ne_bytes())],
+                // ChangeProperty
+                18 => {
+                    assert_eq!(req.u32_at(8), NET_WM_ICON);
+                    assert_eq!(req.u32_at(12), 6); // CARDINAL
+                    assert_eq!(req.data[16], 32);
+                    let len = req.u32_at(20) as usize;
+                    let mut prop = prop.lock().unwrap();
+                    if req.data[1] == 0 {
+                        prop.clear();
+                    }
+                    prop.extend((0..len).map(|i| req.u32_at(24 + 4 * i)));
+                    requests.lock().unwrap().push(req.data.len() / 4);
+                    vec![]
+                }
+                // GetProperty
+                20 => {
+                    let prop = prop.lock().unwrap();
+                    let offset = (req.u32_at(16) as usize).min(prop.len());
+                    let len = (req.u32_at(20) as usize).min(prop.len() - offset);
+                    let mut body = vec![0u8; 24];
+                    body[0..4].copy_from_slice(&6u32.to_ne_bytes());
+                    let after = (prop.len() - offset - len) * 4;
+                    body[4..8].copy_from_slice(&(after as u32).to_ne_bytes());
+                    body[8..12].copy_from_slice(&(len as u32).to_ne_bytes());
+                    for v in &prop[offset..offset + len] {
+                        body.extend_from_slice(&v.to_ne_bytes());
+                    }
+                    vec![mock::reply(req.seq, 32, &body)]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let window = unsafe { x::Window::new(0x200) };
+
+        let icons = vec![icon(48, 48), icon(16, 16)];
+        set_wm_icon(&conn, window, &icons).unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(get_wm_icon(&conn, window).unwrap(), icons);
+
+        // 256x256 does not fit in the maximum request length of the mock server (0xffff)
+        requests.lock().unwrap().clear();
+        let icons = vec![icon(256, 256), icon(16, 16)];
+        set_wm_icon(&conn, window, &icons).unwrap();
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|len| *len <= 0xffff));
+        assert_eq!(get_wm_icon(&conn, window).unwrap(), icons);
+    }
+
+    #[test]
+    fn test_ewmh_connection() {
+        use crate::mock;
+        use std::collections::HashMap;
+        use std::sync::{Arc, Mutex};
+
+        // the atoms are numbered from 400 in the order they are interned, and the properties
+        // are stored by window and atom with their type, format and bytes
+        let atoms = Arc::new(Mutex::new(HashMap::<Vec<u8>, u32>::new()));
+        let props = Arc::new(Mutex::new(HashMap::<(u32, u32), (u32, u8, Vec<u8>)>::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let (atoms, props, events) = (atoms.clone(), props.clone(), events.clone());
+            mock::connect(move |req| match req.opcode {
+                // InternAtom
+                16 => {
+                    let len = u16::from_ne_bytes([req.data[4], req.data[5]]) as usize;
+                    let mut atoms = atoms.lock().unwrap();
+                    let next = 400 + atoms.len() as u32;
+                    let atom = *atoms.entry(req.data[8..8 + len].to_vec()).or_insert(next);
+                    vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+                }
+                // ChangeProperty
+                18 => {
+                    let format = req.data[16];
+                    let len = req.u32_at(20) as usize * format as usize / 8;
+                    props.lock().unwrap().insert(
+                        (req.u32_at(4), req.u32_at(8)),
+                        (req.u32_at(12), format, req.data[24..24 + len].to_vec()),
+                    );
+                    vec![]
+                }
+                // GetProperty
+                20 => {
+                    let props = props.lock().unwrap();
+                    let (r#type, format, data) = props
+                        .get(&(req.u32_at(4), req.u32_at(8)))
+                        .cloned()
+                        .unwrap_or((0, 0, Vec::new()));
+                    let mut body = vec![0u8; 24];
+                    body[0..4].copy_from_slice(&r#type.to_ne_bytes());
+                    let len = if format == 0 {
+                        0
+                    } else {
+                        data.len() * 8 / format as usize
+                    };
+                    body[8..12].copy_from_slice(&(len as u32).to_ne_bytes());
+                    body.extend_from_slice(&data);
+                    vec![mock::reply(req.seq, format, &body)]
+                }
+                // SendEvent
+                25 => {
+                    events.lock().unwrap().push(req.data.to_vec());
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let u32_at = |data: &[u8], off: usize| {
+            u32::from_ne_bytes([data[off], data[off + 1], data[off + 2], data[off + 3]])
+        };
+
+        let mut ewmh = EwmhConnection::new(&conn).unwrap();
+        let a = *ewmh.atoms();
+        assert_eq!(
+            atoms.lock().unwrap()[&b"_NET_WM_NAME"[..]],
+            a.net_wm_name.resource_id()
+        );
+        assert_ne!(a.net_wm_state, a.net_wm_state_fullscreen);
+
+        let window = unsafe { x::Window::new(0x200001) };
+        let other = unsafe { x::Window::new(0x200002) };
+
+        // root window properties
+        assert_eq!(ewmh.get_supported(0).unwrap(), vec![]);
+        assert_eq!(ewmh.get_active_window(0).unwrap(), None);
+        ewmh.set_supported(0, &[a.net_wm_name, a.net_wm_state])
+            .unwrap();
+        assert_eq!(
+            ewmh.get_supported(0).unwrap(),
+            vec![a.net_wm_name, a.net_wm_state]
+        );
+        ewmh.set_client_list(0, &[window, other]).unwrap();
+        assert_eq!(ewmh.get_client_list(0).unwrap(), vec![window, other]);
+        ewmh.set_number_of_desktops(0, 4).unwrap();
+        ewmh.set_current_desktop(0, 2).unwrap();
+        assert_eq!(ewmh.get_number_of_desktops(0).unwrap(), Some(4));
+        assert_eq!(ewmh.get_current_desktop(0).unwrap(), Some(2));
+        ewmh.set_active_window(0, x::WINDOW_NONE).unwrap();
+        assert_eq!(ewmh.get_active_window(0).unwrap(), None);
+        ewmh.set_active_window(0, window).unwrap();
+        assert_eq!(ewmh.get_active_window(0).unwrap(), Some(window));
+
+        // client window properties
+        assert_eq!(ewmh.get_wm_name(window).unwrap(), None);
+        ewmh.set_wm_name(window, "Ünïcödé").unwrap();
+        assert_eq!(
+            ewmh.get_wm_name(window).unwrap().as_deref(),
+            Some("Ünïcödé")
+        );
+        ewmh.set_wm_desktop(window, 0xFFFFFFFF).unwrap();
+        assert_eq!(ewmh.get_wm_desktop(window).unwrap(), Some(0xFFFFFFFF));
+        ewmh.set_wm_window_type(window, &[a.net_wm_window_type_dialog])
+            .unwrap();
+        assert_eq!(
+            ewmh.get_wm_window_type(window).unwrap(),
+            vec![a.net_wm_window_type_dialog]
+        );
+        ewmh.set_wm_state(window, &[a.net_wm_state_above, a.net_wm_state_sticky])
+            .unwrap();
+        assert_eq!(
+            ewmh.get_wm_state(window).unwrap(),
+            vec![a.net_wm_state_above, a.net_wm_state_sticky]
+        );
+        ewmh.set_wm_pid(window, 1234).unwrap();
+        assert_eq!(ewmh.get_wm_pid(window).unwrap(), Some(1234));
+        let strut = StrutPartial {
+            strut: Strut {
+                top: 24,
+                ..Default::default()
+            },
+            top_start_x: 0,
+            top_end_x: 1919,
+            ..Default::default()
+        };
+        ewmh.set_wm_strut(window, &strut.strut).unwrap();
+        ewmh.set_wm_strut_partial(window, &strut).unwrap();
+        assert_eq!(ewmh.get_wm_strut(window).unwrap(), Some(strut.strut));
+        assert_eq!(ewmh.get_wm_strut_partial(window).unwrap(), Some(strut));
+        assert_eq!(ewmh.get_wm_strut_partial(other).unwrap(), None);
+
+        // a property set with another format is an error
+        ewmh.set_property(
+            window,
+            a.net_wm_pid,
+            x::ATOM_CARDINAL,
+            &[1u8],
+            "_NET_WM_PID",
+        )
+        .unwrap();
+        assert!(ewmh.get_wm_pid(window).is_err());
+
+        // requests to the window manager
+        ewmh.set_source_indication(SourceIndication::Pager);
+        ewmh.request_current_desktop(0, 1, 5678).unwrap();
+        ewmh.request_active_window(0, other, 5678, window).unwrap();
+        ewmh.request_wm_state(
+            0,
+            window,
+            WmStateAction::Toggle,
+            a.net_wm_state_maximized_vert,
+            a.net_wm_state_maximized_horz,
+        )
+        .unwrap();
+        let events = events.lock().unwrap();
+        let sent: Vec<_> = events
+            .iter()
+            .map(|req| {
+                assert_eq!(u32_at(req, 4), mock::ROOT); // destination
+                assert_eq!(u32_at(req, 8), 0x180000); // SUBSTRUCTURE_NOTIFY | SUBSTRUCTURE_REDIRECT
+                let event = &req[12..];
+                assert_eq!(event[0], 33); // ClientMessage
+                assert_eq!(event[1], 32); // format
+                let data: Vec<_> = (0..5).map(|i| u32_at(event, 12 + 4 * i)).collect();
+                (u32_at(event, 4), u32_at(event, 8), data)
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                (
+                    mock::ROOT,
+                    a.net_current_desktop.resource_id(),
+                    vec![1, 5678, 0, 0, 0]
+                ),
+                (
+                    0x200002,
+                    a.net_active_window.resource_id(),
+                    vec![2, 5678, 0x200001, 0, 0]
+                ),
+                (
+                    0x200001,
+                    a.net_wm_state.resource_id(),
+                    vec![
+                        2,
+                        a.net_wm_state_maximized_vert.resource_id(),
+                        a.net_wm_state_maximized_horz.resource_id(),
+                        2,
+                        0
+                    ]
+                ),
+            ]
+        );
+    }
+}
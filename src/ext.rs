@@ -6,6 +6,7 @@ use crate::x;
 
 use std::fmt;
 use std::mem;
+use std::ptr;
 
 /// Refers to a X protocol extension.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -269,155 +270,165 @@ pub struct ExtensionData {
     pub first_error: u8,
 }
 
+/// Prefetches the data of `ext`, see [fetch_extension_data].
+pub(crate) fn prefetch_extension_data(conn: *mut xcb_connection_t, ext: Extension) {
+    // SAFETY: `conn` is a valid connection and the extension id a static of the crate
+    unsafe {
+        xcb_prefetch_extension_data(conn, get_extension_id(ext));
+    }
+}
+
+/// Fetches the data of `ext`, or `None` if the extension is not present on the server
+/// (or if the connection is shut down).
+pub(crate) fn fetch_extension_data(
+    conn: *mut xcb_connection_t,
+    ext: Extension,
+) -> Option<ExtensionData> {
+    // SAFETY: `conn` is a valid connection and the extension id a static of the crate.
+    // The reply is owned by libxcb.
+    unsafe {
+        let raw = xcb_get_extension_data(conn, get_extension_id(ext));
+        if raw.is_null() {
+            return None;
+        }
+        let reply = x::QueryExtensionReply::from_raw(raw);
+        let data = reply.present().then(|| ExtensionData {
+            ext,
+            major_opcode: reply.major_opcode(),
+            first_event: reply.first_event(),
+            first_error: reply.first_error(),
+        });
+        mem::forget(reply);
+        data
+    }
+}
+
 pub(crate) fn cache_extensions_data(
     conn: *mut xcb_connection_t,
     mandatory: &[Extension],
     optional: &[Extension],
 ) -> Vec<ExtensionData> {
-    unsafe {
-        for ext in mandatory {
-            let ext_id = get_extension_id(*ext);
-            xcb_prefetch_extension_data(conn, ext_id as *mut _);
-        }
-        for ext in optional {
-            let ext_id = get_extension_id(*ext);
-            xcb_prefetch_extension_data(conn, ext_id as *mut _);
-        }
+    for ext in mandatory.iter().chain(optional) {
+        prefetch_extension_data(conn, *ext);
+    }
 
-        let mut ext_data = Vec::new();
-
-        for ext in mandatory {
-            let ext_id = get_extension_id(*ext);
-            let raw = xcb_get_extension_data(conn, ext_id as *mut _);
-            let reply = x::QueryExtensionReply::from_raw(raw);
-
-            assert!(
-                reply.present(),
-                "mandatory extension {} is not present on this system",
-                ext
-            );
-            ext_data.push(ExtensionData {
-                ext: *ext,
-                major_opcode: reply.major_opcode(),
-                first_event: reply.first_event(),
-                first_error: reply.first_error(),
-            });
-            mem::forget(reply);
-        }
+    let mut ext_data = Vec::new();
 
-        for ext in optional {
-            let ext_id = get_extension_id(*ext);
-            let raw = xcb_get_extension_data(conn, ext_id as *mut _);
-            let reply = x::QueryExtensionReply::from_raw(raw);
-
-            if !reply.present() {
-                mem::forget(reply);
-                continue;
-            }
-
-            ext_data.push(ExtensionData {
-                ext: *ext,
-                major_opcode: reply.major_opcode(),
-                first_event: reply.first_event(),
-                first_error: reply.first_error(),
-            });
-            mem::forget(reply);
+    for ext in mandatory {
+        let data = fetch_extension_data(conn, *ext);
+        assert!(
+            data.is_some(),
+            "mandatory extension {} is not present on this system",
+            ext
+        );
+        ext_data.extend(data);
+    }
+
+    for ext in optional {
+        let data = fetch_extension_data(conn, *ext);
+        #[cfg(feature = "log")]
+        if data.is_none() {
+            crate::logging::extension_missing(*ext);
         }
+        ext_data.extend(data);
+    }
 
-        // we sort by event in reverse order to optimize the event algo
-        ext_data.sort_by(|a, b| b.first_event.cmp(&a.first_event));
+    #[cfg(feature = "log")]
+    ext_data.iter().for_each(crate::logging::extension_found);
 
-        ext_data
-    }
+    // we sort by event in reverse order to optimize the event algo
+    ext_data.sort_by(|a, b| b.first_event.cmp(&a.first_event));
+
+    ext_data
 }
 
-unsafe fn get_extension_id(ext: Extension) -> &'static mut xcb_extension_t {
+fn get_extension_id(ext: Extension) -> *mut xcb_extension_t {
     match ext {
-        Extension::BigRequests => &mut crate::bigreq::FFI_EXT,
-        Extension::XcMisc => &mut crate::xc_misc::FFI_EXT,
+        Extension::BigRequests => ptr::addr_of_mut!(crate::bigreq::FFI_EXT),
+        Extension::XcMisc => ptr::addr_of_mut!(crate::xc_misc::FFI_EXT),
 
         #[cfg(feature = "composite")]
-        Extension::Composite => &mut crate::composite::FFI_EXT,
+        Extension::Composite => ptr::addr_of_mut!(crate::composite::FFI_EXT),
 
         #[cfg(feature = "damage")]
-        Extension::Damage => &mut crate::damage::FFI_EXT,
+        Extension::Damage => ptr::addr_of_mut!(crate::damage::FFI_EXT),
 
         #[cfg(feature = "dpms")]
-        Extension::Dpms => &mut crate::dpms::FFI_EXT,
+        Extension::Dpms => ptr::addr_of_mut!(crate::dpms::FFI_EXT),
 
         #[cfg(feature = "dri2")]
-        Extension::Dri2 => &mut crate::dri2::FFI_EXT,
+        Extension::Dri2 => ptr::addr_of_mut!(crate::dri2::FFI_EXT),
 
         #[cfg(feature = "dri3")]
-        Extension::Dri3 => &mut crate::dri3::FFI_EXT,
+        Extension::Dri3 => ptr::addr_of_mut!(crate::dri3::FFI_EXT),
 
         #[cfg(feature = "ge")]
-        Extension::GenericEvent => &mut crate::ge::FFI_EXT,
+        Extension::GenericEvent => ptr::addr_of_mut!(crate::ge::FFI_EXT),
 
         #[cfg(feature = "glx")]
-        Extension::Glx => &mut crate::glx::FFI_EXT,
+        Extension::Glx => ptr::addr_of_mut!(crate::glx::FFI_EXT),
 
         #[cfg(feature = "present")]
-        Extension::Present => &mut crate::present::FFI_EXT,
+        Extension::Present => ptr::addr_of_mut!(crate::present::FFI_EXT),
 
         #[cfg(feature = "randr")]
-        Extension::RandR => &mut crate::randr::FFI_EXT,
+        Extension::RandR => ptr::addr_of_mut!(crate::randr::FFI_EXT),
 
         #[cfg(feature = "record")]
-        Extension::Record => &mut crate::record::FFI_EXT,
+        Extension::Record => ptr::addr_of_mut!(crate::record::FFI_EXT),
 
         #[cfg(feature = "render")]
-        Extension::Render => &mut crate::render::FFI_EXT,
+        Extension::Render => ptr::addr_of_mut!(crate::render::FFI_EXT),
 
         #[cfg(feature = "res")]
-        Extension::Res => &mut crate::res::FFI_EXT,
+        Extension::Res => ptr::addr_of_mut!(crate::res::FFI_EXT),
 
         #[cfg(feature = "screensaver")]
-        Extension::ScreenSaver => &mut crate::screensaver::FFI_EXT,
+        Extension::ScreenSaver => ptr::addr_of_mut!(crate::screensaver::FFI_EXT),
 
         #[cfg(feature = "shape")]
-        Extension::Shape => &mut crate::shape::FFI_EXT,
+        Extension::Shape => ptr::addr_of_mut!(crate::shape::FFI_EXT),
 
         #[cfg(feature = "shm")]
-        Extension::Shm => &mut crate::shm::FFI_EXT,
+        Extension::Shm => ptr::addr_of_mut!(crate::shm::FFI_EXT),
 
         #[cfg(feature = "sync")]
-        Extension::Sync => &mut crate::sync::FFI_EXT,
+        Extension::Sync => ptr::addr_of_mut!(crate::sync::FFI_EXT),
 
         #[cfg(feature = "xevie")]
-        Extension::Xevie => &mut crate::xevie::FFI_EXT,
+        Extension::Xevie => ptr::addr_of_mut!(crate::xevie::FFI_EXT),
 
         #[cfg(feature = "xf86dri")]
-        Extension::Xf86Dri => &mut crate::xf86dri::FFI_EXT,
+        Extension::Xf86Dri => ptr::addr_of_mut!(crate::xf86dri::FFI_EXT),
 
         #[cfg(feature = "xf86vidmode")]
-        Extension::Xf86VidMode => &mut crate::xf86vidmode::FFI_EXT,
+        Extension::Xf86VidMode => ptr::addr_of_mut!(crate::xf86vidmode::FFI_EXT),
 
         #[cfg(feature = "xfixes")]
-        Extension::XFixes => &mut crate::xfixes::FFI_EXT,
+        Extension::XFixes => ptr::addr_of_mut!(crate::xfixes::FFI_EXT),
 
         #[cfg(feature = "xinerama")]
-        Extension::Xinerama => &mut crate::xinerama::FFI_EXT,
+        Extension::Xinerama => ptr::addr_of_mut!(crate::xinerama::FFI_EXT),
 
         #[cfg(feature = "xinput")]
-        Extension::Input => &mut crate::xinput::FFI_EXT,
+        Extension::Input => ptr::addr_of_mut!(crate::xinput::FFI_EXT),
 
         #[cfg(feature = "xkb")]
-        Extension::Xkb => &mut crate::xkb::FFI_EXT,
+        Extension::Xkb => ptr::addr_of_mut!(crate::xkb::FFI_EXT),
 
         #[cfg(feature = "xprint")]
-        Extension::XPrint => &mut crate::xprint::FFI_EXT,
+        Extension::XPrint => ptr::addr_of_mut!(crate::xprint::FFI_EXT),
 
         #[cfg(feature = "xselinux")]
-        Extension::SeLinux => &mut crate::xselinux::FFI_EXT,
+        Extension::SeLinux => ptr::addr_of_mut!(crate::xselinux::FFI_EXT),
 
         #[cfg(feature = "xtest")]
-        Extension::Test => &mut crate::xtest::FFI_EXT,
+        Extension::Test => ptr::addr_of_mut!(crate::xtest::FFI_EXT),
 
         #[cfg(feature = "xv")]
-        Extension::Xv => &mut crate::xv::FFI_EXT,
+        Extension::Xv => ptr::addr_of_mut!(crate::xv::FFI_EXT),
 
         #[cfg(feature = "xvmc")]
-        Extension::XvMc => &mut crate::xvmc::FFI_EXT,
+        Extension::XvMc => ptr::addr_of_mut!(crate::xvmc::FFI_EXT),
     }
 }
@@ -0,0 +1,556 @@
+//! Tracking of the keyboard focus of top-level windows.
+//!
+//! The `FocusIn` and `FocusOut` events received by a top-level window don't all mean that
+//! the window gains or loses the focus: their mode tells whether they are caused by a grab,
+//! and their detail where the focus comes from or goes to. [focus_change] implements the
+//! decision table used by the toolkits, and [FocusTracker] applies it to the events
+//! of a window.
+
+use crate::event::Event;
+use crate::x;
+
+use std::time::{Duration, Instant};
+
+/// Bit of the `same_screen_focus` field of `EnterNotify` and `LeaveNotify`
+/// set when the event window is the focus window or an inferior of it.
+const CROSSING_FOCUS: u8 = 0x01;
+
+/// How a focus event changes the focus state of a top-level window. See [focus_change].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FocusChange {
+    /// The new state of the focus of the window (or one of its inferiors).
+    pub window_focus: Option<bool>,
+    /// The new state of the focus received through the pointer, when the focus is
+    /// `PointerRoot` (or an ancestor of the window) and the pointer is in the window.
+    pub pointer_focus: Option<bool>,
+}
+
+/// Returns how a `FocusIn` (`focus_in` is `true`) or `FocusOut` event of `mode` and `detail`,
+/// received by a top-level window, changes its focus state.
+///
+/// `has_pointer` is whether the pointer is in the window.
+///
+/// - The events caused by keyboard grabs (`Grab` and `Ungrab` modes) are transients
+///   and are ignored.
+/// - With the `Ancestor` and `Virtual` details, the focus moves between the window and one
+///   of its ancestors (e.g. the root window). If the pointer is in the window, the window was
+///   (`FocusOut`) or was not anymore (`FocusIn`) receiving the keystrokes through the pointer.
+/// - With the `Nonlinear` and `NonlinearVirtual` details, the focus moves between the window
+///   and an unrelated window.
+/// - With the `Pointer` detail, the focus is `PointerRoot` and the pointer is in the window.
+/// - With the `Inferior` detail, the focus moves within the window. The `PointerRoot` and
+///   `None` details are for the root window only.
+pub fn focus_change(
+    focus_in: bool,
+    mode: x::NotifyMode,
+    detail: x::NotifyDetail,
+    has_pointer: bool,
+) -> FocusChange {
+    let mut change = FocusChange::default();
+    if matches!(mode, x::NotifyMode::Grab | x::NotifyMode::Ungrab) {
+        return change;
+    }
+    match detail {
+        x::NotifyDetail::Ancestor | x::NotifyDetail::Virtual => {
+            if has_pointer {
+                change.pointer_focus = Some(!focus_in);
+            }
+            change.window_focus = Some(focus_in);
+        }
+        x::NotifyDetail::Nonlinear | x::NotifyDetail::NonlinearVirtual => {
+            change.window_focus = Some(focus_in);
+        }
+        x::NotifyDetail::Pointer => change.pointer_focus = Some(focus_in),
+        x::NotifyDetail::Inferior | x::NotifyDetail::PointerRoot | x::NotifyDetail::None => {}
+    }
+    change
+}
+
+/// Tracks whether a top-level window has the keyboard focus.
+///
+/// [FocusTracker::handle_event] is meant to be called from the event loop with every event.
+/// The window must select `FocusChange` and `EnterWindow | LeaveWindow` events.
+///
+/// Window managers often move the focus out of a window and back in a row (e.g. when they
+/// reparent or restack it). With [FocusTracker::set_debounce], the loss of the focus is only
+/// reported if the focus doesn't come back within the duration, by [FocusTracker::tick].
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, _) = xcb::Connection::connect(None)?;
+/// # let window: xcb::x::Window = conn.generate_id();
+/// let mut tracker = xcb::focus::FocusTracker::new(window);
+/// loop {
+///     let event = conn.wait_for_event()?;
+///     if let Some(focused) = tracker.handle_event(&event) {
+///         println!("focused: {}", focused);
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FocusTracker {
+    window: x::Window,
+    has_pointer: bool,
+    window_focus: bool,
+    pointer_focus: bool,
+    focused: bool,
+    debounce: Option<Duration>,
+    // when the focus was lost, while the loss is not reported yet
+    lost_at: Option<Instant>,
+}
+
+impl FocusTracker {
+    /// Tracks the focus of `window`, initially unfocused.
+    pub fn new(window: x::Window) -> FocusTracker {
+        FocusTracker {
+            window,
+            has_pointer: false,
+            window_focus: false,
+            pointer_focus: false,
+            focused: false,
+            debounce: None,
+            lost_at: None,
+        }
+    }
+
+    /// The tracked window.
+    pub fn window(&self) -> x::Window {
+        self.window
+    }
+
+    /// Whether the window has the focus, as last reported.
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Delays the report of the loss of the focus by `debounce`, or reports it immediately
+    /// with `None` (the default).
+    pub fn set_debounce(&mut self, debounce: Option<Duration>) {
+        self.debounce = debounce;
+    }
+
+    /// Updates the focus state with `event`, received now.
+    ///
+    /// Returns the new focus state if it changed.
+    pub fn handle_event(&mut self, event: &Event) -> Option<bool> {
+        self.handle_event_at(event, Instant::now())
+    }
+
+    /// Updates the focus state with `event`, received at `now`.
+    ///
+    /// Returns the new focus state if it changed.
+    pub fn handle_event_at(&mut self, event: &Event, now: Instant) -> Option<bool> {
+        let change = match event {
+            Event::X(x::Event::FocusIn(ev)) if ev.event() == self.window => {
+                focus_change(true, ev.mode(), ev.detail(), self.has_pointer)
+            }
+            Event::X(x::Event::FocusOut(ev)) if ev.event() == self.window => {
+                focus_change(false, ev.mode(), ev.detail(), self.has_pointer)
+            }
+            Event::X(x::Event::EnterNotify(ev)) if ev.event() == self.window => {
+                self.crossing(true, ev.detail(), ev.same_screen_focus())
+            }
+            Event::X(x::Event::LeaveNotify(ev)) if ev.event() == self.window => {
+                self.crossing(false, ev.detail(), ev.same_screen_focus())
+            }
+            _ => return None,
+        };
+        if let Some(focus) = change.window_focus {
+            self.window_focus = focus;
+        }
+        if let Some(focus) = change.pointer_focus {
+            self.pointer_focus = focus;
+        }
+        self.update(now)
+    }
+
+    fn crossing(&mut self, enter: bool, detail: x::NotifyDetail, flags: u8) -> FocusChange {
+        let mut change = FocusChange::default();
+        if detail == x::NotifyDetail::Inferior {
+            return change;
+        }
+        self.has_pointer = enter;
+        // the focus is an ancestor of the window
+        if flags & CROSSING_FOCUS != 0 && !self.window_focus {
+            change.pointer_focus = Some(enter);
+        }
+        change
+    }
+
+    fn update(&mut self, now: Instant) -> Option<bool> {
+        let focused = self.window_focus || self.pointer_focus;
+        if focused == self.focused {
+            // the focus came back before the loss was reported
+            self.lost_at = None;
+            return None;
+        }
+        match (focused, self.debounce) {
+            (false, Some(_)) => {
+                self.lost_at.get_or_insert(now);
+                None
+            }
+            _ => {
+                self.focused = focused;
+                Some(focused)
+            }
+        }
+    }
+
+    /// When [FocusTracker::tick] should be called to report the loss of the focus.
+    pub fn deadline(&self) -> Option<Instant> {
+        match (self.lost_at, self.debounce) {
+            (Some(lost_at), Some(debounce)) => Some(lost_at + debounce),
+            _ => None,
+        }
+    }
+
+    /// Reports the loss of the focus if the debounce duration elapsed at `now`
+    /// without the focus coming back.
+    pub fn tick(&mut self, now: Instant) -> Option<bool> {
+        match self.deadline() {
+            Some(deadline) if now >= deadline => {
+                self.lost_at = None;
+                self.focused = false;
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn focus_event(
+        focus_in: bool,
+        window: x::Window,
+        mode: x::NotifyMode,
+        detail: x::NotifyDetail,
+    ) -> Event {
+        if focus_in {
+            Event::X(x::Event::FocusIn(x::FocusInEvent::new(
+                detail, window, mode,
+            )))
+        } else {
+            Event::X(x::Event::FocusOut(x::FocusOutEvent::new_focus_out(
+                detail, window, mode,
+            )))
+        }
+    }
+
+    fn window(id: u32) -> x::Window {
+        use crate::XidNew;
+        unsafe { x::Window::new(id) }
+    }
+
+    #[test]
+    fn test_focus_change() {
+        use x::NotifyDetail::*;
+        use x::NotifyMode::*;
+
+        let details = [
+            Ancestor,
+            Virtual,
+            Inferior,
+            Nonlinear,
+            NonlinearVirtual,
+            Pointer,
+            PointerRoot,
+            None,
+        ];
+        // (detail, has_pointer) -> (window_focus, pointer_focus) of a FocusIn
+        let expected = |detail, has_pointer| match (detail, has_pointer) {
+            (Ancestor | Virtual, false) => (Some(true), Option::None),
+            (Ancestor | Virtual, true) => (Some(true), Some(false)),
+            (Nonlinear | NonlinearVirtual, _) => (Some(true), Option::None),
+            (Pointer, _) => (Option::None, Some(true)),
+            (Inferior | PointerRoot | None, _) => (Option::None, Option::None),
+        };
+        let negate = |focus: Option<bool>| focus.map(|f| !f);
+
+        for mode in [Normal, Grab, Ungrab, WhileGrabbed] {
+            for detail in details {
+                for has_pointer in [false, true] {
+                    let focus_in = focus_change(true, mode, detail, has_pointer);
+                    let focus_out = focus_change(false, mode, detail, has_pointer);
+                    if matches!(mode, Grab | Ungrab) {
+                        assert_eq!(focus_in, FocusChange::default());
+                        assert_eq!(focus_out, FocusChange::default());
+                        continue;
+                    }
+                    let (window_focus, pointer_focus) = expected(detail, has_pointer);
+                    assert_eq!(
+                        focus_in,
+                        FocusChange {
+                            window_focus,
+                            pointer_focus
+                        },
+                        "FocusIn {:?} {:?} {}",
+                        mode,
+                        detail,
+                        has_pointer
+                    );
+                    assert_eq!(
+                        focus_out,
+                        FocusChange {
+                            window_focus: negate(window_focus),
+                            pointer_focus: negate(pointer_focus),
+                        },
+                        "FocusOut {:?} {:?} {}",
+                        mode,
+                        detail,
+                        has_pointer
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tracker() {
+        use x::NotifyDetail::*;
+        use x::NotifyMode::*;
+
+        let w = window(0x200);
+        let mut tracker = FocusTracker::new(w);
+        let now = Instant::now();
+
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(true, w, Normal, Nonlinear), now),
+            Some(true)
+        );
+        // focus moved to a child window, and grabs
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, Normal, Inferior), now),
+            Option::None
+        );
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, Grab, Nonlinear), now),
+            Option::None
+        );
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(true, w, Ungrab, Nonlinear), now),
+            Option::None
+        );
+        // events of other windows
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, window(0x300), Normal, Nonlinear), now),
+            Option::None
+        );
+        assert!(tracker.is_focused());
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, WhileGrabbed, Nonlinear), now),
+            Some(false)
+        );
+
+        // PointerRoot focus, with the pointer entering the window
+        let enter = Event::X(x::Event::EnterNotify(x::EnterNotifyEvent::new(
+            Ancestor,
+            x::CURRENT_TIME,
+            window(crate::mock::ROOT),
+            w,
+            x::WINDOW_NONE,
+            0,
+            0,
+            0,
+            0,
+            x::KeyButMask::empty(),
+            Normal,
+            CROSSING_FOCUS | 0x02,
+        )));
+        assert_eq!(tracker.handle_event_at(&enter, now), Some(true));
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, Normal, Pointer), now),
+            Some(false)
+        );
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(true, w, Normal, Pointer), now),
+            Some(true)
+        );
+        // the focus moves from the root window to the window, with the pointer in it
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(true, w, Normal, Ancestor), now),
+            Option::None
+        );
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, Normal, Ancestor), now),
+            Option::None
+        );
+        assert!(tracker.is_focused());
+    }
+
+    #[test]
+    fn test_debounce() {
+        use x::NotifyDetail::*;
+        use x::NotifyMode::*;
+
+        let w = window(0x200);
+        let mut tracker = FocusTracker::new(w);
+        tracker.set_debounce(Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(true, w, Normal, Nonlinear), at(0)),
+            Some(true)
+        );
+        // flicker hidden
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, Normal, Nonlinear), at(10)),
+            Option::None
+        );
+        assert_eq!(tracker.deadline(), Some(at(60)));
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(true, w, Normal, Nonlinear), at(20)),
+            Option::None
+        );
+        assert_eq!(tracker.deadline(), Option::None);
+        assert_eq!(tracker.tick(at(100)), Option::None);
+        assert!(tracker.is_focused());
+
+        // actual loss
+        assert_eq!(
+            tracker.handle_event_at(&focus_event(false, w, Normal, Nonlinear), at(100)),
+            Option::None
+        );
+        assert_eq!(tracker.tick(at(149)), Option::None);
+        assert_eq!(tracker.tick(at(150)), Some(false));
+        assert_eq!(tracker.tick(at(200)), Option::None);
+        assert!(!tracker.is_focused());
+    }
+
+    /// Two windows 0x200 and 0x300 swap the focus with `SetInputFocus`, and a keyboard grab
+    /// happens in between.
+    #[test]
+    fn test_set_input_focus() {
+        use crate::{mock, Xid};
+        use std::sync::{Arc, Mutex};
+
+        fn event(
+            code: u8,
+            seq: u16,
+            detail: x::NotifyDetail,
+            window: u32,
+            mode: x::NotifyMode,
+        ) -> Vec<u8> {
+            let mut ev = vec![0u8; 32];
+            ev[0] = code;
+            ev[1] = detail as u8;
+            ev[2..4].copy_from_slice(&seq.to_ne_bytes());
+            ev[4..8].copy_from_slice(&window.to_ne_bytes());
+            ev[8] = mode as u8;
+            ev
+        }
+        const FOCUS_IN: u8 = 9;
+        const FOCUS_OUT: u8 = 10;
+
+        let focus = Arc::new(Mutex::new(0x200u32));
+        let conn = mock::connect(move |req| {
+            let mut focus = focus.lock().unwrap();
+            match req.opcode {
+                // GrabKeyboard
+                31 => vec![
+                    event(
+                        FOCUS_OUT,
+                        req.seq,
+                        x::NotifyDetail::Nonlinear,
+                        *focus,
+                        x::NotifyMode::Grab,
+                    ),
+                    event(
+                        FOCUS_IN,
+                        req.seq,
+                        x::NotifyDetail::Nonlinear,
+                        req.u32_at(4),
+                        x::NotifyMode::Grab,
+                    ),
+                    mock::reply(req.seq, 0, &[]),
+                ],
+                // UngrabKeyboard
+                32 => vec![
+                    event(
+                        FOCUS_OUT,
+                        req.seq,
+                        x::NotifyDetail::Nonlinear,
+                        0x300,
+                        x::NotifyMode::Ungrab,
+                    ),
+                    event(
+                        FOCUS_IN,
+                        req.seq,
+                        x::NotifyDetail::Nonlinear,
+                        *focus,
+                        x::NotifyMode::Ungrab,
+                    ),
+                ],
+                // SetInputFocus
+                42 => {
+                    let old = std::mem::replace(&mut *focus, req.u32_at(4));
+                    vec![
+                        event(
+                            FOCUS_OUT,
+                            req.seq,
+                            x::NotifyDetail::Nonlinear,
+                            old,
+                            x::NotifyMode::Normal,
+                        ),
+                        event(
+                            FOCUS_IN,
+                            req.seq,
+                            x::NotifyDetail::Nonlinear,
+                            *focus,
+                            x::NotifyMode::Normal,
+                        ),
+                    ]
+                }
+                _ => mock::default_response(req),
+            }
+        });
+
+        let mut trackers = [
+            FocusTracker::new(window(0x200)),
+            FocusTracker::new(window(0x300)),
+        ];
+        let mut dispatch = || {
+            conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+                .unwrap();
+            let mut changes = Vec::new();
+            while let Some(event) = conn.poll_for_queued_event().unwrap() {
+                for tracker in trackers.iter_mut() {
+                    if let Some(focused) = tracker.handle_event(&event) {
+                        changes.push((tracker.window().resource_id(), focused));
+                    }
+                }
+            }
+            changes
+        };
+
+        let set_focus = |id| {
+            conn.send_request(&x::SetInputFocus {
+                revert_to: x::InputFocus::Parent,
+                focus: window(id),
+                time: x::CURRENT_TIME,
+            });
+        };
+        set_focus(0x300);
+        assert_eq!(dispatch(), [(0x300, true)]);
+
+        conn.send_request(&x::GrabKeyboard {
+            owner_events: false,
+            grab_window: window(0x300),
+            time: x::CURRENT_TIME,
+            pointer_mode: x::GrabMode::Async,
+            keyboard_mode: x::GrabMode::Async,
+        });
+        conn.send_request(&x::UngrabKeyboard {
+            time: x::CURRENT_TIME,
+        });
+        assert_eq!(dispatch(), []);
+
+        set_focus(0x200);
+        assert_eq!(dispatch(), [(0x300, false), (0x200, true)]);
+    }
+}
@@ -0,0 +1,528 @@
+//! Length-checked constructors of replies from untrusted bytes.
+//!
+//! Replies received through [crate::Connection] are trusted: their accessors do not check
+//! the bounds of the lists. The `parse_from_bytes` constructors of this module validate
+//! the header, the total length and the bounds of every list before wrapping the data,
+//! such that all the accessors of the returned reply are sound.
+//! They are intended for fuzzing and are only available with the `fuzzing` cargo feature.
+
+#[cfg(feature = "dri2")]
+use crate::dri2;
+#[cfg(feature = "xv")]
+use crate::xv;
+use crate::{base::Reply, x};
+
+use std::convert::TryInto;
+use std::fmt;
+
+/// Size of the fixed part of all replies.
+const HEADER_LEN: usize = 32;
+
+/// Error returned by the `parse_from_bytes` constructors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The data is shorter than the fixed part of a reply.
+    TooShort(usize),
+    /// The first byte is not the reply response type (1).
+    NotAReply(u8),
+    /// The size announced by the `length` field does not match the size of the data.
+    LengthMismatch { expected: usize, actual: usize },
+    /// The named list does not fit in the reply.
+    ListOutOfBounds(&'static str),
+    /// The named field has a value that is not allowed by the protocol.
+    InvalidValue { field: &'static str, value: u32 },
+    /// The size computed from the named length field overflows the address space
+    /// (e.g. on 32-bit targets).
+    ReplyTooLarge(&'static str),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort(len) => write!(f, "reply too short: {} bytes", len),
+            ParseError::NotAReply(rt) => write!(f, "not a reply: response type {}", rt),
+            ParseError::LengthMismatch { expected, actual } => write!(
+                f,
+                "reply length mismatch: expected {} bytes, got {}",
+                expected, actual
+            ),
+            ParseError::ListOutOfBounds(field) => write!(f, "list {} is out of bounds", field),
+            ParseError::InvalidValue { field, value } => {
+                write!(f, "invalid value {} for field {}", value, field)
+            }
+            ParseError::ReplyTooLarge(field) => {
+                write!(f, "the size of {} overflows the address space", field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = std::result::Result<T, ParseError>;
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Checks the response type and the length of the reply.
+fn check_header(data: &[u8]) -> ParseResult<()> {
+    if data.len() < HEADER_LEN {
+        return Err(ParseError::TooShort(data.len()));
+    }
+    if data[0] != 1 {
+        return Err(ParseError::NotAReply(data[0]));
+    }
+    // a slice can't be larger than isize::MAX bytes
+    let expected = (u32_at(data, 4) as usize)
+        .checked_mul(4)
+        .and_then(|len| len.checked_add(HEADER_LEN))
+        .filter(|len| *len <= isize::MAX as usize)
+        .ok_or(ParseError::ReplyTooLarge("length"))?;
+    if expected != data.len() {
+        return Err(ParseError::LengthMismatch {
+            expected,
+            actual: data.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that `count` elements of `elem_size` bytes fit in `data` from `offset`.
+///
+/// Returns the offset of the end of the list.
+fn check_list(
+    data: &[u8],
+    offset: usize,
+    count: usize,
+    elem_size: usize,
+    field: &'static str,
+) -> ParseResult<usize> {
+    let end = count
+        .checked_mul(elem_size)
+        .and_then(|len| len.checked_add(offset))
+        .ok_or(ParseError::ReplyTooLarge(field))?;
+    if end > data.len() {
+        return Err(ParseError::ListOutOfBounds(field));
+    }
+    Ok(end)
+}
+
+/// Copies `data` into a `malloc` allocation owned by the reply.
+///
+/// # Safety
+/// `data` must have been validated as a wire representation of `R`.
+unsafe fn wrap<R: Reply>(data: &[u8]) -> R {
+    // SAFETY: `malloc` has no precondition
+    let raw = unsafe { libc::malloc(data.len()) } as *mut u8;
+    assert!(!raw.is_null(), "out of memory");
+    // SAFETY: `raw` is a fresh allocation of `data.len()` bytes, passed on to the reply,
+    // and the caller guarantees that `data` is a valid reply
+    unsafe {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), raw, data.len());
+        R::from_raw(raw)
+    }
+}
+
+impl x::GetPropertyReply {
+    /// Builds a reply from untrusted bytes, checking that the value fits in the reply
+    /// and that the format is 0, 8, 16 or 32.
+    pub fn parse_from_bytes(data: &[u8]) -> ParseResult<Self> {
+        check_header(data)?;
+        let format = data[1];
+        if ![0, 8, 16, 32].contains(&format) {
+            return Err(ParseError::InvalidValue {
+                field: "format",
+                value: format as u32,
+            });
+        }
+        let value_len = u32_at(data, 16) as usize;
+        check_list(data, HEADER_LEN, value_len, format as usize / 8, "value")?;
+        Ok(unsafe { wrap(data) })
+    }
+}
+
+#[cfg(feature = "xv")]
+impl xv::QueryAdaptorsReply {
+    /// Builds a reply from untrusted bytes, checking the bounds of each adaptor,
+    /// including its name and formats.
+    pub fn parse_from_bytes(data: &[u8]) -> ParseResult<Self> {
+        check_header(data)?;
+        let num_adaptors = u16_at(data, 8) as usize;
+        let mut offset = HEADER_LEN;
+        for _ in 0..num_adaptors {
+            let fixed_end = check_list(data, offset, 1, 12, "info")?;
+            let name_size = u16_at(data, offset + 4) as usize;
+            let num_formats = u16_at(data, offset + 8) as usize;
+            let name_end = check_list(data, fixed_end, name_size, 1, "info.name")?;
+            let pad = crate::base::align_pad(name_end - offset, 4);
+            let formats = check_list(data, name_end, pad, 1, "info.pad")?;
+            offset = check_list(data, formats, num_formats, 8, "info.formats")?;
+        }
+        Ok(unsafe { wrap(data) })
+    }
+}
+
+#[cfg(feature = "xv")]
+impl xv::QueryImageAttributesReply {
+    /// Builds a reply from untrusted bytes, checking that the pitches and offsets fit in the reply.
+    pub fn parse_from_bytes(data: &[u8]) -> ParseResult<Self> {
+        check_header(data)?;
+        let num_planes = u32_at(data, 8) as usize;
+        let pitches_end = check_list(data, HEADER_LEN, num_planes, 4, "pitches")?;
+        check_list(data, pitches_end, num_planes, 4, "offsets")?;
+        Ok(unsafe { wrap(data) })
+    }
+}
+
+#[cfg(feature = "dri2")]
+impl dri2::GetBuffersReply {
+    /// Builds a reply from untrusted bytes, checking that the buffers fit in the reply
+    /// and that their attachment is valid.
+    pub fn parse_from_bytes(data: &[u8]) -> ParseResult<Self> {
+        check_header(data)?;
+        let count = u32_at(data, 16) as usize;
+        check_list(data, HEADER_LEN, count, 20, "buffers")?;
+        for i in 0..count {
+            let attachment = u32_at(data, HEADER_LEN + i * 20);
+            if attachment > dri2::Attachment::BufferHiz as u32 {
+                return Err(ParseError::InvalidValue {
+                    field: "buffers.attachment",
+                    value: attachment,
+                });
+            }
+        }
+        Ok(unsafe { wrap(data) })
+    }
+}
+
+#[cfg(feature = "dri2")]
+impl dri2::ConnectReply {
+    /// Builds a reply from untrusted bytes, checking that the driver and device names
+    /// fit in the reply.
+    pub fn parse_from_bytes(data: &[u8]) -> ParseResult<Self> {
+        check_header(data)?;
+        let driver_name_len = u32_at(data, 8) as usize;
+        let device_name_len = u32_at(data, 12) as usize;
+        let driver_name_end = check_list(data, HEADER_LEN, driver_name_len, 1, "driver_name")?;
+        let aligned = check_list(
+            data,
+            driver_name_end,
+            crate::base::align_pad(driver_name_len, 4),
+            1,
+            "alignment_pad",
+        )?;
+        check_list(data, aligned, device_name_len, 1, "device_name")?;
+        Ok(unsafe { wrap(data) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply_bytes(byte1: u8, fields: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; HEADER_LEN];
+        data[0] = 1;
+        data[1] = byte1;
+        data[8..8 + fields.len()].copy_from_slice(fields);
+        data.extend_from_slice(body);
+        data.resize(data.len() + crate::base::align_pad(data.len(), 4), 0);
+        let length = ((data.len() - HEADER_LEN) / 4) as u32;
+        data[4..8].copy_from_slice(&length.to_ne_bytes());
+        data
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let data = reply_bytes(8, &[], &[]);
+        assert!(x::GetPropertyReply::parse_from_bytes(&data).is_ok());
+
+        assert_eq!(
+            x::GetPropertyReply::parse_from_bytes(&data[..31]).unwrap_err(),
+            ParseError::TooShort(31)
+        );
+
+        let mut error = data.clone();
+        error[0] = 0;
+        assert_eq!(
+            x::GetPropertyReply::parse_from_bytes(&error).unwrap_err(),
+            ParseError::NotAReply(0)
+        );
+
+        let mut long = data.clone();
+        long[4..8].copy_from_slice(&u32::MAX.to_ne_bytes());
+        #[cfg(target_pointer_width = "64")]
+        assert!(matches!(
+            x::GetPropertyReply::parse_from_bytes(&long).unwrap_err(),
+            ParseError::LengthMismatch { actual: 32, .. }
+        ));
+        // the byte length doesn't fit in 32 bits
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(
+            x::GetPropertyReply::parse_from_bytes(&long).unwrap_err(),
+            ParseError::ReplyTooLarge("length")
+        );
+    }
+
+    #[test]
+    fn test_parse_get_property() {
+        let mut fields = [0u8; 12];
+        fields[0..4].copy_from_slice(&31u32.to_ne_bytes()); // STRING
+        fields[8..12].copy_from_slice(&5u32.to_ne_bytes());
+        let data = reply_bytes(8, &fields, b"hello");
+        let reply = x::GetPropertyReply::parse_from_bytes(&data).unwrap();
+        assert_eq!(reply.value::<u8>(), b"hello");
+
+        // 5 items of 32 bits do not fit in 8 bytes
+        let data = reply_bytes(32, &fields, b"hello");
+        assert_eq!(
+            x::GetPropertyReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::ListOutOfBounds("value")
+        );
+
+        let data = reply_bytes(7, &fields, b"hello");
+        assert_eq!(
+            x::GetPropertyReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::InvalidValue {
+                field: "format",
+                value: 7
+            }
+        );
+    }
+
+    #[cfg(feature = "xv")]
+    #[test]
+    fn test_parse_query_adaptors() {
+        let adaptor = |name: &str, num_formats: u16| {
+            let mut info = Vec::new();
+            info.extend_from_slice(&0x40u32.to_ne_bytes());
+            info.extend_from_slice(&(name.len() as u16).to_ne_bytes());
+            info.extend_from_slice(&16u16.to_ne_bytes());
+            info.extend_from_slice(&num_formats.to_ne_bytes());
+            info.extend_from_slice(&[0x13, 0]);
+            info.extend_from_slice(name.as_bytes());
+            info.resize(info.len() + crate::base::align_pad(info.len(), 4), 0);
+            for _ in 0..num_formats {
+                info.extend_from_slice(&0x21u32.to_ne_bytes());
+                info.extend_from_slice(&[24, 0, 0, 0]);
+            }
+            info
+        };
+
+        let mut body = adaptor("Intel(R) Textured Video", 1);
+        body.extend(adaptor("Sprite", 2));
+        let data = reply_bytes(0, &2u16.to_ne_bytes(), &body);
+        let reply = xv::QueryAdaptorsReply::parse_from_bytes(&data).unwrap();
+        let names: Vec<_> = reply
+            .info()
+            .map(|i| i.name().to_utf8().into_owned())
+            .collect();
+        assert_eq!(names, ["Intel(R) Textured Video", "Sprite"]);
+        assert_eq!(reply.info().nth(1).unwrap().formats().len(), 2);
+
+        // announces a third adaptor that is not there
+        let data = reply_bytes(0, &3u16.to_ne_bytes(), &body);
+        assert_eq!(
+            xv::QueryAdaptorsReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::ListOutOfBounds("info")
+        );
+
+        // truncated formats
+        let body = adaptor("Sprite", 2);
+        let data = reply_bytes(0, &1u16.to_ne_bytes(), &body[..body.len() - 8]);
+        assert_eq!(
+            xv::QueryAdaptorsReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::ListOutOfBounds("info.formats")
+        );
+    }
+
+    #[cfg(feature = "xv")]
+    #[test]
+    fn test_parse_query_image_attributes() {
+        let mut fields = [0u8; 12];
+        fields[0..4].copy_from_slice(&3u32.to_ne_bytes());
+        let mut body = Vec::new();
+        for v in [320u32, 160, 160, 0, 76800, 96000] {
+            body.extend_from_slice(&v.to_ne_bytes());
+        }
+        let data = reply_bytes(0, &fields, &body);
+        let reply = xv::QueryImageAttributesReply::parse_from_bytes(&data).unwrap();
+        assert_eq!(reply.pitches(), [320, 160, 160]);
+        assert_eq!(reply.offsets(), [0, 76800, 96000]);
+
+        let data = reply_bytes(0, &fields, &body[..16]);
+        assert_eq!(
+            xv::QueryImageAttributesReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::ListOutOfBounds("offsets")
+        );
+    }
+
+    #[cfg(feature = "dri2")]
+    #[test]
+    fn test_parse_get_buffers() {
+        let mut fields = [0u8; 12];
+        fields[8..12].copy_from_slice(&1u32.to_ne_bytes());
+        let mut body = Vec::new();
+        for v in [1u32, 7, 5120, 4, 0] {
+            body.extend_from_slice(&v.to_ne_bytes());
+        }
+        let data = reply_bytes(0, &fields, &body);
+        let reply = dri2::GetBuffersReply::parse_from_bytes(&data).unwrap();
+        assert_eq!(
+            reply.buffers()[0].attachment(),
+            dri2::Attachment::BufferBackLeft
+        );
+
+        body[0..4].copy_from_slice(&11u32.to_ne_bytes());
+        let data = reply_bytes(0, &fields, &body);
+        assert_eq!(
+            dri2::GetBuffersReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::InvalidValue {
+                field: "buffers.attachment",
+                value: 11
+            }
+        );
+
+        fields[8..12].copy_from_slice(&2u32.to_ne_bytes());
+        let data = reply_bytes(0, &fields, &body);
+        assert_eq!(
+            dri2::GetBuffersReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::ListOutOfBounds("buffers")
+        );
+    }
+
+    #[cfg(feature = "dri2")]
+    #[test]
+    fn test_parse_connect() {
+        let mut fields = [0u8; 8];
+        fields[0..4].copy_from_slice(&5u32.to_ne_bytes());
+        fields[4..8].copy_from_slice(&19u32.to_ne_bytes());
+        let data = reply_bytes(0, &fields, b"iris\0\0\0\0/dev/dri/renderD128");
+        let reply = dri2::ConnectReply::parse_from_bytes(&data).unwrap();
+        assert_eq!(reply.driver_name().as_bytes(), b"iris\0");
+        assert_eq!(reply.device_name().as_bytes(), b"/dev/dri/renderD128");
+
+        // the device name overflows the reply
+        fields[4..8].copy_from_slice(&40u32.to_ne_bytes());
+        let data = reply_bytes(0, &fields, b"iris\0\0\0\0/dev/dri/renderD128");
+        assert_eq!(
+            dri2::ConnectReply::parse_from_bytes(&data).unwrap_err(),
+            ParseError::ListOutOfBounds("device_name")
+        );
+    }
+
+    #[test]
+    fn test_parse_fuzz_seeds() {
+        use std::path::Path;
+
+        fn check(target: &str, parse: fn(&[u8]) -> bool) {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fuzz/corpus")
+                .join(target);
+            // the fuzz directory is not part of the published crate
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => return,
+            };
+            for entry in entries {
+                let path = entry.unwrap().path();
+                let data = std::fs::read(&path).unwrap();
+                assert!(parse(&data), "invalid seed {}", path.display());
+            }
+        }
+
+        check("get_property_reply", |data| {
+            x::GetPropertyReply::parse_from_bytes(data).is_ok()
+        });
+        #[cfg(feature = "xv")]
+        check("xv_query_adaptors_reply", |data| {
+            xv::QueryAdaptorsReply::parse_from_bytes(data).is_ok()
+        });
+        #[cfg(feature = "xv")]
+        check("xv_query_image_attributes_reply", |data| {
+            xv::QueryImageAttributesReply::parse_from_bytes(data).is_ok()
+        });
+        #[cfg(feature = "dri2")]
+        check("dri2_get_buffers_reply", |data| {
+            dri2::GetBuffersReply::parse_from_bytes(data).is_ok()
+        });
+        #[cfg(feature = "dri2")]
+        check("dri2_connect_reply", |data| {
+            dri2::ConnectReply::parse_from_bytes(data).is_ok()
+        });
+    }
+
+    #[test]
+    fn test_check_list_overflow() {
+        let data = [0u8; 64];
+        assert_eq!(check_list(&data, 32, 8, 4, "list"), Ok(64));
+        assert_eq!(
+            check_list(&data, 32, 9, 4, "list"),
+            Err(ParseError::ListOutOfBounds("list"))
+        );
+        assert_eq!(
+            check_list(&data, 32, usize::MAX / 2, 4, "list"),
+            Err(ParseError::ReplyTooLarge("list"))
+        );
+        assert_eq!(
+            check_list(&data, usize::MAX - 2, 1, 4, "list"),
+            Err(ParseError::ReplyTooLarge("list"))
+        );
+        // counts read from 32-bit fields
+        let err = check_list(&data, 32, u32::MAX as usize, 20, "list").unwrap_err();
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(err, ParseError::ListOutOfBounds("list"));
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(err, ParseError::ReplyTooLarge("list"));
+    }
+
+    #[test]
+    fn test_parse_lengths_near_u32_max() {
+        // GetProperty: value_len
+        for value_len in [u32::MAX, u32::MAX - 3, 0x4000_0000] {
+            let mut fields = [0u8; 12];
+            fields[8..12].copy_from_slice(&value_len.to_ne_bytes());
+            let data = reply_bytes(32, &fields, &[0; 16]);
+            assert!(matches!(
+                x::GetPropertyReply::parse_from_bytes(&data).unwrap_err(),
+                ParseError::ListOutOfBounds("value") | ParseError::ReplyTooLarge("value")
+            ));
+        }
+
+        #[cfg(feature = "xv")]
+        for num_planes in [u32::MAX, 0x4000_0000] {
+            let mut fields = [0u8; 12];
+            fields[0..4].copy_from_slice(&num_planes.to_ne_bytes());
+            let data = reply_bytes(0, &fields, &[0; 24]);
+            assert!(matches!(
+                xv::QueryImageAttributesReply::parse_from_bytes(&data).unwrap_err(),
+                ParseError::ListOutOfBounds("pitches") | ParseError::ReplyTooLarge("pitches")
+            ));
+        }
+
+        #[cfg(feature = "dri2")]
+        for count in [u32::MAX, 0x0ccc_cccd] {
+            let mut fields = [0u8; 12];
+            fields[8..12].copy_from_slice(&count.to_ne_bytes());
+            let data = reply_bytes(0, &fields, &[0; 20]);
+            assert!(matches!(
+                dri2::GetBuffersReply::parse_from_bytes(&data).unwrap_err(),
+                ParseError::ListOutOfBounds("buffers") | ParseError::ReplyTooLarge("buffers")
+            ));
+        }
+
+        #[cfg(feature = "dri2")]
+        {
+            // the padding of the driver name overflows
+            let mut fields = [0u8; 8];
+            fields[0..4].copy_from_slice(&u32::MAX.to_ne_bytes());
+            let data = reply_bytes(0, &fields, &[0; 8]);
+            assert!(dri2::ConnectReply::parse_from_bytes(&data).is_err());
+        }
+    }
+}
@@ -0,0 +1,903 @@
+//! Helpers for the client to window manager protocol of the
+//! [ICCCM](https://x.org/releases/X11R7.6/doc/xorg-docs/specs/ICCCM/icccm.html).
+//!
+//! The state of a top-level window is owned by the window manager: clients request
+//! transitions with [iconify], [withdraw] or by mapping the window, and learn their actual
+//! state from the `WM_STATE` property (see [get_wm_state]) that the window manager updates.
+//!
+//! The properties set by the clients are typed: [WmHints] for `WM_HINTS`, [SizeHints] for
+//! `WM_NORMAL_HINTS`, and the atoms of `WM_PROTOCOLS`, with which the window manager asks
+//! a client to close its window (see [delete_window]).
+
+use crate::base::{Connection, Error, Result, ResultExt, Xid, XidNew};
+use crate::x;
+
+use std::convert::TryFrom;
+
+/// Flags of the `WM_HINTS` property.
+const INPUT_HINT: u32 = 1 << 0;
+const STATE_HINT: u32 = 1 << 1;
+const ICON_PIXMAP_HINT: u32 = 1 << 2;
+const ICON_WINDOW_HINT: u32 = 1 << 3;
+const ICON_POSITION_HINT: u32 = 1 << 4;
+const ICON_MASK_HINT: u32 = 1 << 5;
+const WINDOW_GROUP_HINT: u32 = 1 << 6;
+const URGENCY_HINT: u32 = 1 << 8;
+
+/// Length of the `WM_HINTS` property, in 32-bit units.
+const WM_HINTS_LEN: u32 = 9;
+
+/// Flags of the `WM_SIZE_HINTS` type.
+const US_POSITION: u32 = 1 << 0;
+const US_SIZE: u32 = 1 << 1;
+const P_POSITION: u32 = 1 << 2;
+const P_SIZE: u32 = 1 << 3;
+const P_MIN_SIZE: u32 = 1 << 4;
+const P_MAX_SIZE: u32 = 1 << 5;
+const P_RESIZE_INC: u32 = 1 << 6;
+const P_ASPECT: u32 = 1 << 7;
+const P_BASE_SIZE: u32 = 1 << 8;
+const P_WIN_GRAVITY: u32 = 1 << 9;
+
+/// Length of the `WM_SIZE_HINTS` type, in 32-bit units.
+const WM_SIZE_HINTS_LEN: u32 = 18;
+
+/// The state of a top-level window.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WindowState {
+    /// Neither the window nor its icon is visible.
+    Withdrawn = 0,
+    /// The window is visible.
+    Normal = 1,
+    /// The window is replaced by its icon.
+    Iconic = 3,
+}
+
+impl WindowState {
+    /// Returns the state of the `WM_STATE` and `WM_HINTS` encoding, if valid.
+    pub fn from_u32(value: u32) -> Option<WindowState> {
+        match value {
+            0 => Some(WindowState::Withdrawn),
+            1 => Some(WindowState::Normal),
+            3 => Some(WindowState::Iconic),
+            _ => None,
+        }
+    }
+}
+
+/// The content of the `WM_STATE` property, set by the window manager.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WmState {
+    pub state: WindowState,
+    /// The icon window of the window manager, or `x::WINDOW_NONE`.
+    pub icon: x::Window,
+}
+
+impl WmState {
+    /// Parses the raw value of a `WM_STATE` property (the state and the icon window).
+    ///
+    /// Returns `None` if `data` is too short or if the state is invalid.
+    pub fn parse(data: &[u32]) -> Option<WmState> {
+        match data {
+            [state, icon, ..] => Some(WmState {
+                state: WindowState::from_u32(*state)?,
+                icon: unsafe { x::Window::new(*icon) },
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The content of the `WM_HINTS` property.
+///
+/// The fields are `None` when the corresponding flag is not set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WmHints {
+    /// Whether the client relies on the window manager to get the input focus.
+    pub input: Option<bool>,
+    /// The state of the window when it is first mapped.
+    pub initial_state: Option<WindowState>,
+    pub icon_pixmap: Option<x::Pixmap>,
+    pub icon_window: Option<x::Window>,
+    pub icon_position: Option<(i32, i32)>,
+    pub icon_mask: Option<x::Pixmap>,
+    pub window_group: Option<x::Window>,
+    pub urgent: bool,
+}
+
+impl WmHints {
+    /// Parses the raw value of a `WM_HINTS` property.
+    ///
+    /// The fields missing from a short property (as set by some old clients) are `None`.
+    /// An invalid initial state is ignored.
+    pub fn parse(data: &[u32]) -> WmHints {
+        let flags = data.first().copied().unwrap_or(0);
+        let field = |flag: u32, index: usize| {
+            if flags & flag != 0 {
+                data.get(index).copied()
+            } else {
+                None
+            }
+        };
+        WmHints {
+            input: field(INPUT_HINT, 1).map(|input| input != 0),
+            initial_state: field(STATE_HINT, 2).and_then(WindowState::from_u32),
+            icon_pixmap: field(ICON_PIXMAP_HINT, 3).map(|p| unsafe { x::Pixmap::new(p) }),
+            icon_window: field(ICON_WINDOW_HINT, 4).map(|w| unsafe { x::Window::new(w) }),
+            icon_position: field(ICON_POSITION_HINT, 5)
+                .and_then(|x| Some((x as i32, field(ICON_POSITION_HINT, 6)? as i32))),
+            icon_mask: field(ICON_MASK_HINT, 7).map(|p| unsafe { x::Pixmap::new(p) }),
+            window_group: field(WINDOW_GROUP_HINT, 8).map(|w| unsafe { x::Window::new(w) }),
+            urgent: flags & URGENCY_HINT != 0,
+        }
+    }
+
+    /// Encodes the hints in the `WM_HINTS` format.
+    pub fn encode(&self) -> [u32; WM_HINTS_LEN as usize] {
+        let mut data = [0u32; WM_HINTS_LEN as usize];
+        let mut set = |flag: u32, index: usize, value: Option<u32>| {
+            if let Some(value) = value {
+                data[0] |= flag;
+                data[index] = value;
+            }
+        };
+        set(INPUT_HINT, 1, self.input.map(u32::from));
+        set(STATE_HINT, 2, self.initial_state.map(|s| s as u32));
+        set(
+            ICON_PIXMAP_HINT,
+            3,
+            self.icon_pixmap.map(|p| p.resource_id()),
+        );
+        set(
+            ICON_WINDOW_HINT,
+            4,
+            self.icon_window.map(|w| w.resource_id()),
+        );
+        set(
+            ICON_POSITION_HINT,
+            5,
+            self.icon_position.map(|(x, _)| x as u32),
+        );
+        set(
+            ICON_POSITION_HINT,
+            6,
+            self.icon_position.map(|(_, y)| y as u32),
+        );
+        set(ICON_MASK_HINT, 7, self.icon_mask.map(|p| p.resource_id()));
+        set(
+            WINDOW_GROUP_HINT,
+            8,
+            self.window_group.map(|w| w.resource_id()),
+        );
+        if self.urgent {
+            data[0] |= URGENCY_HINT;
+        }
+        data
+    }
+}
+
+/// The content of the `WM_NORMAL_HINTS` property, of type `WM_SIZE_HINTS`.
+///
+/// The fields are `None` when the corresponding flag is not set. The sizes are in pixels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SizeHints {
+    /// The position of the window. Obsolete, the position of the window is the one it is
+    /// configured with, but still set by the toolkits with the flag of [SizeHints::user_position].
+    pub position: Option<(i32, i32)>,
+    /// The size of the window. Obsolete, as `position`, with the flag of
+    /// [SizeHints::user_size].
+    pub size: Option<(i32, i32)>,
+    /// Whether `position` is specified by the user (`USPosition`), rather than by the
+    /// program (`PPosition`).
+    pub user_position: bool,
+    /// Whether `size` is specified by the user (`USSize`), rather than by the program
+    /// (`PSize`).
+    pub user_size: bool,
+    pub min_size: Option<(i32, i32)>,
+    pub max_size: Option<(i32, i32)>,
+    /// The increments of the width and of the height above the base size.
+    pub size_increment: Option<(i32, i32)>,
+    /// The minimum and maximum aspect ratios, as numerators and denominators.
+    pub aspect: Option<((i32, i32), (i32, i32))>,
+    /// The size to which the increments are added. The minimum size is used in its absence.
+    pub base_size: Option<(i32, i32)>,
+    /// The corner of the window that stays in place when it is reparented by the window
+    /// manager.
+    pub win_gravity: Option<x::Gravity>,
+}
+
+impl SizeHints {
+    /// Parses the raw value of a `WM_SIZE_HINTS` property.
+    ///
+    /// The fields missing from a short property (such as the 15 fields of the clients that
+    /// predate the base size and gravity) are `None`. An invalid gravity is ignored.
+    pub fn parse(data: &[u32]) -> SizeHints {
+        let flags = data.first().copied().unwrap_or(0);
+        let pair = |flag: u32, index: usize| {
+            if flags & flag != 0 {
+                Some((*data.get(index)? as i32, *data.get(index + 1)? as i32))
+            } else {
+                None
+            }
+        };
+        SizeHints {
+            position: pair(US_POSITION | P_POSITION, 1),
+            size: pair(US_SIZE | P_SIZE, 3),
+            user_position: flags & US_POSITION != 0,
+            user_size: flags & US_SIZE != 0,
+            min_size: pair(P_MIN_SIZE, 5),
+            max_size: pair(P_MAX_SIZE, 7),
+            size_increment: pair(P_RESIZE_INC, 9),
+            aspect: pair(P_ASPECT, 11).and_then(|min| Some((min, pair(P_ASPECT, 13)?))),
+            base_size: pair(P_BASE_SIZE, 15),
+            win_gravity: data
+                .get(17)
+                .filter(|_| flags & P_WIN_GRAVITY != 0)
+                .and_then(|gravity| gravity_from_u32(*gravity)),
+        }
+    }
+
+    /// Encodes the hints in the `WM_SIZE_HINTS` format.
+    pub fn encode(&self) -> [u32; WM_SIZE_HINTS_LEN as usize] {
+        let mut data = [0u32; WM_SIZE_HINTS_LEN as usize];
+        let mut set = |flag: u32, index: usize, value: Option<(i32, i32)>| {
+            if let Some((a, b)) = value {
+                data[0] |= flag;
+                data[index] = a as u32;
+                data[index + 1] = b as u32;
+            }
+        };
+        let position_flag = if self.user_position {
+            US_POSITION
+        } else {
+            P_POSITION
+        };
+        let size_flag = if self.user_size { US_SIZE } else { P_SIZE };
+        set(position_flag, 1, self.position);
+        set(size_flag, 3, self.size);
+        set(P_MIN_SIZE, 5, self.min_size);
+        set(P_MAX_SIZE, 7, self.max_size);
+        set(P_RESIZE_INC, 9, self.size_increment);
+        set(P_ASPECT, 11, self.aspect.map(|(min, _)| min));
+        set(P_ASPECT, 13, self.aspect.map(|(_, max)| max));
+        set(P_BASE_SIZE, 15, self.base_size);
+        if let Some(gravity) = self.win_gravity {
+            data[0] |= P_WIN_GRAVITY;
+            data[17] = gravity as u32;
+        }
+        data
+    }
+}
+
+fn gravity_from_u32(value: u32) -> Option<x::Gravity> {
+    Some(match value {
+        1 => x::Gravity::NorthWest,
+        2 => x::Gravity::North,
+        3 => x::Gravity::NorthEast,
+        4 => x::Gravity::West,
+        5 => x::Gravity::Center,
+        6 => x::Gravity::East,
+        7 => x::Gravity::SouthWest,
+        8 => x::Gravity::South,
+        9 => x::Gravity::SouthEast,
+        10 => x::Gravity::Static,
+        _ => return None,
+    })
+}
+
+fn root_of(conn: &Connection, screen: i32) -> Result<x::Window> {
+    usize::try_from(screen)
+        .ok()
+        .and_then(|screen| conn.get_setup().roots().nth(screen))
+        .map(|screen| screen.root())
+        .ok_or(Error::InvalidScreen(screen))
+}
+
+/// Fetches the `WM_HINTS` property of `window`.
+///
+/// Returns `None` if the property is not set, and a [PropertyError](crate::PropertyError)
+/// if it is set with another type than `WM_HINTS` or another format than 32.
+pub fn get_wm_hints(conn: &Connection, window: x::Window) -> Result<Option<WmHints>> {
+    let value = x::get_full_property(conn, window, x::ATOM_WM_HINTS, x::ATOM_WM_HINTS)?;
+    Ok(value
+        .expect_data32("WM_HINTS")?
+        .map(|data| WmHints::parse(&data)))
+}
+
+/// Sets the `WM_HINTS` property of `window`.
+pub fn set_wm_hints(conn: &Connection, window: x::Window, hints: &WmHints) -> Result<()> {
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: x::ATOM_WM_HINTS,
+        r#type: x::ATOM_WM_HINTS,
+        data: &hints.encode(),
+    })
+    .context("ChangeProperty", || {
+        format!("WM_HINTS of window {:#x}", window.resource_id())
+    })
+}
+
+/// Sets the initial state hint of `window` (`WM_HINTS.initial_state`), keeping
+/// its other hints.
+///
+/// The hint is read by the window manager when the window is mapped from the withdrawn
+/// state, e.g. to start a window iconified. Use [iconify] to change the state of a window
+/// that is already mapped.
+pub fn set_wm_state_hint(conn: &Connection, window: x::Window, state: WindowState) -> Result<()> {
+    let hints = WmHints {
+        initial_state: Some(state),
+        ..get_wm_hints(conn, window)?.unwrap_or_default()
+    };
+    set_wm_hints(conn, window, &hints)
+}
+
+/// Fetches the `WM_NORMAL_HINTS` property of `window`.
+///
+/// Returns `None` if the property is not set, and a [PropertyError](crate::PropertyError)
+/// if it is set with another type than `WM_SIZE_HINTS` or another format than 32.
+pub fn get_wm_normal_hints(conn: &Connection, window: x::Window) -> Result<Option<SizeHints>> {
+    let value = x::get_full_property(conn, window, x::ATOM_WM_NORMAL_HINTS, x::ATOM_WM_SIZE_HINTS)?;
+    Ok(value
+        .expect_data32("WM_NORMAL_HINTS")?
+        .map(|data| SizeHints::parse(&data)))
+}
+
+/// Sets the `WM_NORMAL_HINTS` property of `window`.
+pub fn set_wm_normal_hints(conn: &Connection, window: x::Window, hints: &SizeHints) -> Result<()> {
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: x::ATOM_WM_NORMAL_HINTS,
+        r#type: x::ATOM_WM_SIZE_HINTS,
+        data: &hints.encode(),
+    })
+    .context("ChangeProperty", || {
+        format!("WM_NORMAL_HINTS of window {:#x}", window.resource_id())
+    })
+}
+
+/// Fetches the `WM_PROTOCOLS` property of `window`: the atoms of the protocols that the
+/// client takes part in, such as `WM_DELETE_WINDOW` and `WM_TAKE_FOCUS`.
+///
+/// Returns `None` if the property is not set, and a [PropertyError](crate::PropertyError)
+/// if it is set with another type than `ATOM` or another format than 32.
+pub fn get_wm_protocols(conn: &Connection, window: x::Window) -> Result<Option<Vec<x::Atom>>> {
+    let wm_protocols = x::intern_atoms(conn, &["WM_PROTOCOLS"], true)?[0];
+    if wm_protocols == x::ATOM_NONE {
+        // no client ever set it
+        return Ok(None);
+    }
+    let value = x::get_full_property(conn, window, wm_protocols, x::ATOM_ATOM)?;
+    Ok(value.expect_data32("WM_PROTOCOLS")?.map(|data| {
+        data.into_iter()
+            .map(|a| unsafe { x::Atom::new(a) })
+            .collect()
+    }))
+}
+
+/// Sets the `WM_PROTOCOLS` property of `window`.
+pub fn set_wm_protocols(conn: &Connection, window: x::Window, protocols: &[x::Atom]) -> Result<()> {
+    let wm_protocols = x::intern_atoms(conn, &["WM_PROTOCOLS"], false)?[0];
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: wm_protocols,
+        r#type: x::ATOM_ATOM,
+        data: protocols,
+    })
+    .context("ChangeProperty", || {
+        format!("WM_PROTOCOLS of window {:#x}", window.resource_id())
+    })
+}
+
+/// Asks the client of the top-level `window` to delete it, with the `WM_DELETE_WINDOW`
+/// protocol.
+///
+/// Returns `false` without sending anything if the client doesn't take part in the
+/// protocol (see [get_wm_protocols]): the window manager then typically destroys the
+/// window or kills the client with `x::KillClient`. `time` is the timestamp of the event
+/// that triggered the deletion, rather than `x::CURRENT_TIME`.
+pub fn delete_window(conn: &Connection, window: x::Window, time: x::Timestamp) -> Result<bool> {
+    let atoms = x::intern_atoms(conn, &["WM_DELETE_WINDOW", "WM_PROTOCOLS"], false)?;
+    let (wm_delete_window, wm_protocols) = (atoms[0], atoms[1]);
+    let protocols = get_wm_protocols(conn, window)?.unwrap_or_default();
+    if !protocols.contains(&wm_delete_window) {
+        return Ok(false);
+    }
+    let event = x::ClientMessageEvent::new(
+        window,
+        wm_protocols,
+        x::ClientMessageData::Data32([wm_delete_window.resource_id(), time, 0, 0, 0]),
+    );
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(window),
+        event_mask: x::EventMask::NO_EVENT,
+        event: &event,
+    })
+    .context("SendEvent", || {
+        format!("WM_DELETE_WINDOW of window {:#x}", window.resource_id())
+    })?;
+    Ok(true)
+}
+
+/// Fetches the `WM_STATE` property of `window`, set by the window manager on the
+/// top-level windows it manages.
+///
+/// Returns `None` if the property is not set or is malformed, and a
+/// [PropertyError](crate::PropertyError) if it is set with another type than `WM_STATE`
+/// or another format than 32.
+pub fn get_wm_state(conn: &Connection, window: x::Window) -> Result<Option<WmState>> {
+    let wm_state = x::intern_atoms(conn, &["WM_STATE"], true)?[0];
+    if wm_state == x::ATOM_NONE {
+        // no window manager ever set it
+        return Ok(None);
+    }
+    let value = x::get_full_property(conn, window, wm_state, wm_state)?;
+    Ok(value
+        .expect_data32("WM_STATE")?
+        .and_then(|data| WmState::parse(&data)))
+}
+
+/// Fetches the `WM_COLORMAP_WINDOWS` property of the top-level `window`: the subwindows
+/// whose colormaps the window manager installs when `window` has the focus, by decreasing
+/// priority.
+///
+/// Returns `None` if the property is not set, and a [PropertyError](crate::PropertyError)
+/// if it is set with another type than `WINDOW` or another format than 32.
+pub fn get_wm_colormap_windows(
+    conn: &Connection,
+    window: x::Window,
+) -> Result<Option<Vec<x::Window>>> {
+    let wm_colormap_windows = x::intern_atoms(conn, &["WM_COLORMAP_WINDOWS"], true)?[0];
+    if wm_colormap_windows == x::ATOM_NONE {
+        // no client ever set it
+        return Ok(None);
+    }
+    let value = x::get_full_property(conn, window, wm_colormap_windows, x::ATOM_WINDOW)?;
+    Ok(value.expect_data32("WM_COLORMAP_WINDOWS")?.map(|data| {
+        data.into_iter()
+            .map(|w| unsafe { x::Window::new(w) })
+            .collect()
+    }))
+}
+
+/// Sets the `WM_COLORMAP_WINDOWS` property of the top-level `window`.
+///
+/// `windows` are the subwindows of `window` whose colormap attribute differs from the one
+/// of `window` (see [x::set_window_colormap]), by decreasing priority. The window
+/// manager places `window` first if it is not listed.
+pub fn set_wm_colormap_windows(
+    conn: &Connection,
+    window: x::Window,
+    windows: &[x::Window],
+) -> Result<()> {
+    let wm_colormap_windows = x::intern_atoms(conn, &["WM_COLORMAP_WINDOWS"], false)?[0];
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: wm_colormap_windows,
+        r#type: x::ATOM_WINDOW,
+        data: windows,
+    })
+    .context("ChangeProperty", || {
+        format!("WM_COLORMAP_WINDOWS of window {:#x}", window.resource_id())
+    })
+}
+
+/// Asks the window manager to iconify the mapped top-level `window` of `screen`.
+///
+/// This sends the `WM_CHANGE_STATE` client message to the root window. The window manager
+/// updates `WM_STATE` once the window is iconified (see [get_wm_state]).
+///
+/// Returns [Error::InvalidScreen] if `screen` is not a screen of the display.
+pub fn iconify(conn: &Connection, screen: i32, window: x::Window) -> Result<()> {
+    let root = root_of(conn, screen)?;
+    let wm_change_state = x::intern_atoms(conn, &["WM_CHANGE_STATE"], false)?[0];
+    let event = x::ClientMessageEvent::new(
+        window,
+        wm_change_state,
+        x::ClientMessageData::Data32([WindowState::Iconic as u32, 0, 0, 0, 0]),
+    );
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(root),
+        event_mask: x::EventMask::SUBSTRUCTURE_REDIRECT | x::EventMask::SUBSTRUCTURE_NOTIFY,
+        event: &event,
+    })
+    .context("SendEvent", || {
+        format!("WM_CHANGE_STATE of window {:#x}", window.resource_id())
+    })
+}
+
+/// Withdraws the top-level `window` of `screen`.
+///
+/// The window is unmapped, and a synthetic `UnmapNotify` is sent to the root window,
+/// as required by the ICCCM for the window manager to notice the transition of an
+/// iconified window (which is already unmapped). The window is withdrawn once the window
+/// manager removes or updates its `WM_STATE` property (see [get_wm_state]).
+///
+/// Returns [Error::InvalidScreen] if `screen` is not a screen of the display.
+pub fn withdraw(conn: &Connection, screen: i32, window: x::Window) -> Result<()> {
+    let root = root_of(conn, screen)?;
+    let detail = || format!("window {:#x}", window.resource_id());
+
+    conn.send_and_check_request(&x::UnmapWindow { window })
+        .context("UnmapWindow", detail)?;
+    let event = x::UnmapNotifyEvent::new(root, window, false);
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(root),
+        event_mask: x::EventMask::SUBSTRUCTURE_REDIRECT | x::EventMask::SUBSTRUCTURE_NOTIFY,
+        event: &event,
+    })
+    .context("SendEvent", || {
+        format!(
+            "synthetic UnmapNotify of window {:#x}",
+            window.resource_id()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wm_hints() {
+        let hints = WmHints {
+            input: Some(true),
+            initial_state: Some(WindowState::Iconic),
+            icon_position: Some((-10, 20)),
+            window_group: Some(unsafe { x::Window::new(0x400) }),
+            urgent: true,
+            ..Default::default()
+        };
+        let data = hints.encode();
+        assert_eq!(data, [0x153, 1, 3, 0, 0, (-10i32) as u32, 20, 0, 0x400]);
+        assert_eq!(WmHints::parse(&data), hints);
+
+        // short property of old clients, invalid state
+        let hints = WmHints::parse(&[0x07, 0, 2, 0x500]);
+        assert_eq!(hints.input, Some(false));
+        assert_eq!(hints.initial_state, None);
+        assert_eq!(hints.icon_pixmap, Some(unsafe { x::Pixmap::new(0x500) }));
+        assert_eq!(WmHints::parse(&[0x1f, 0, 0, 0, 0, 5]).icon_position, None);
+        assert_eq!(WmHints::parse(&[]), WmHints::default());
+    }
+
+    #[test]
+    fn test_wm_state_parse() {
+        assert_eq!(
+            WmState::parse(&[3, 0x500]),
+            Some(WmState {
+                state: WindowState::Iconic,
+                icon: unsafe { x::Window::new(0x500) },
+            })
+        );
+        assert_eq!(WmState::parse(&[1]), None);
+        assert_eq!(WmState::parse(&[2, 0]), None);
+    }
+
+    #[test]
+    fn test_withdraw() {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(requests.clone(), None);
+        let window = unsafe { x::Window::new(0x200001) };
+
+        withdraw(&conn, 0, window).unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0][0], 10); // UnmapWindow
+        assert_eq!(u32_at(&requests[0], 4), 0x200001);
+
+        let req = &requests[1];
+        assert_eq!(req[0], 25); // SendEvent
+        assert_eq!(req[1], 0); // propagate
+        assert_eq!(req.len(), 44);
+        assert_eq!(u32_at(req, 4), mock::ROOT);
+        // SubstructureNotify | SubstructureRedirect
+        assert_eq!(u32_at(req, 8), 0x0018_0000);
+
+        let ev = &req[12..];
+        assert_eq!(ev[0], 18); // UnmapNotify
+        assert_eq!(u32_at(ev, 4), mock::ROOT); // event
+        assert_eq!(u32_at(ev, 8), 0x200001); // window
+        assert_eq!(ev[12], 0); // from_configure
+    }
+
+    #[test]
+    fn test_iconify() {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(requests.clone(), None);
+        let window = unsafe { x::Window::new(0x200001) };
+
+        // the screen is checked before any request is sent
+        assert!(matches!(
+            iconify(&conn, 1, window),
+            Err(Error::InvalidScreen(1))
+        ));
+        assert!(matches!(
+            withdraw(&conn, -1, window),
+            Err(Error::InvalidScreen(-1))
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+
+        iconify(&conn, 0, window).unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let req = &requests[0];
+        assert_eq!(u32_at(req, 4), mock::ROOT);
+        assert_eq!(u32_at(req, 8), 0x0018_0000);
+
+        let ev = &req[12..];
+        assert_eq!(ev[0], 33); // ClientMessage
+        assert_eq!(ev[1], 32); // format
+        assert_eq!(u32_at(ev, 4), 0x200001);
+        assert_eq!(u32_at(ev, 8), 301); // WM_CHANGE_STATE
+        assert_eq!(u32_at(ev, 12), 3); // IconicState
+    }
+
+    #[test]
+    fn test_wm_state_and_hints() {
+        use std::sync::{Arc, Mutex};
+
+        let window = unsafe { x::Window::new(0x200001) };
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let conn = mock_connection(requests.clone(), Some(vec![1, 0]));
+        assert_eq!(
+            get_wm_state(&conn, window).unwrap(),
+            Some(WmState {
+                state: WindowState::Normal,
+                icon: x::WINDOW_NONE,
+            })
+        );
+
+        // the initial state hint keeps the other hints
+        assert_eq!(get_wm_hints(&conn, window).unwrap(), None);
+        set_wm_state_hint(&conn, window, WindowState::Iconic).unwrap();
+        let hints = WmHints {
+            input: Some(true),
+            ..get_wm_hints(&conn, window).unwrap().unwrap()
+        };
+        assert_eq!(hints.initial_state, Some(WindowState::Iconic));
+        set_wm_hints(&conn, window, &hints).unwrap();
+        set_wm_state_hint(&conn, window, WindowState::Normal).unwrap();
+        assert_eq!(
+            get_wm_hints(&conn, window).unwrap(),
+            Some(WmHints {
+                input: Some(true),
+                initial_state: Some(WindowState::Normal),
+                ..Default::default()
+            })
+        );
+
+        // no window manager
+        let conn = mock_connection(requests, None);
+        assert_eq!(get_wm_state(&conn, window).unwrap(), None);
+    }
+
+    #[test]
+    fn test_wm_hints_type_mismatch() {
+        use std::sync::{Arc, Mutex};
+
+        let window = unsafe { x::Window::new(0x200001) };
+        let conn = mock_connection(Arc::new(Mutex::new(Vec::new())), None);
+
+        conn.send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window,
+            property: x::ATOM_WM_HINTS,
+            r#type: x::ATOM_CARDINAL,
+            data: &WmHints::default().encode(),
+        })
+        .unwrap();
+
+        let err = get_wm_hints(&conn, window).unwrap_err();
+        match err.root_cause() {
+            crate::Error::Property(err) => {
+                assert_eq!(err.property, "WM_HINTS");
+                assert_eq!(err.actual_type, x::ATOM_CARDINAL);
+                assert_eq!(err.actual_format, 32);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        // the hints are not silently reset
+        assert!(set_wm_state_hint(&conn, window, WindowState::Iconic).is_err());
+    }
+
+    #[test]
+    fn test_wm_colormap_windows() {
+        use std::sync::{Arc, Mutex};
+
+        let window = unsafe { x::Window::new(0x200001) };
+        let subwindows = unsafe { [x::Window::new(0x200002), x::Window::new(0x200003)] };
+        let conn = mock_connection(Arc::new(Mutex::new(Vec::new())), None);
+
+        assert_eq!(get_wm_colormap_windows(&conn, window).unwrap(), None);
+        set_wm_colormap_windows(&conn, window, &subwindows).unwrap();
+        assert_eq!(
+            get_wm_colormap_windows(&conn, window).unwrap(),
+            Some(subwindows.to_vec())
+        );
+        set_wm_colormap_windows(&conn, window, &[]).unwrap();
+        assert_eq!(
+            get_wm_colormap_windows(&conn, window).unwrap(),
+            Some(vec![])
+        );
+    }
+
+    /// Connects to a mock server that records the `SendEvent` and `UnmapWindow` requests,
+    /// stores the properties and their type, and returns `wm_state` as the `WM_STATE` property.
+    fn mock_connection(
+        requests: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+        wm_state: Option<Vec<u32>>,
+    ) -> Connection {
+        use crate::mock;
+
+        const WM_STATE: u32 = 300;
+        const WM_CHANGE_STATE: u32 = 301;
+        const WM_COLORMAP_WINDOWS: u32 = 302;
+        const WM_PROTOCOLS: u32 = 303;
+        const WM_DELETE_WINDOW: u32 = 304;
+
+        let mut properties = std::collections::HashMap::<u32, (u32, Vec<u32>)>::new();
+        mock::connect(move |req| match req.opcode {
+            // InternAtom
+            16 => {
+                let len = u16::from_ne_bytes([req.data[4], req.data[5]]) as usize;
+                let atom = match &req.data[8..8 + len] {
+                    b"WM_STATE" if wm_state.is_some() => WM_STATE,
+                    b"WM_STATE" => 0,
+                    b"WM_CHANGE_STATE" => WM_CHANGE_STATE,
+                    b"WM_COLORMAP_WINDOWS" => WM_COLORMAP_WINDOWS,
+                    b"WM_PROTOCOLS" => WM_PROTOCOLS,
+                    b"WM_DELETE_WINDOW" => WM_DELETE_WINDOW,
+                    name => panic!("unexpected atom {:?}", name),
+                };
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            // ChangeProperty
+            18 => {
+                let len = req.u32_at(20) as usize;
+                properties.insert(
+                    req.u32_at(8),
+                    (
+                        req.u32_at(12),
+                        (0..len).map(|i| req.u32_at(24 + 4 * i)).collect(),
+                    ),
+                );
+                vec![]
+            }
+            // GetProperty
+            20 => {
+                let (r#type, mut value) = match req.u32_at(8) {
+                    WM_STATE => (WM_STATE, wm_state.clone().unwrap()),
+                    property => properties.get(&property).cloned().unwrap_or((0, vec![])),
+                };
+                let mut body = vec![0u8; 24];
+                body[0..4].copy_from_slice(&r#type.to_ne_bytes());
+                if r#type != 0 && r#type != req.u32_at(12) {
+                    // another type than requested: the size, but no value
+                    body[4..8].copy_from_slice(&(value.len() as u32 * 4).to_ne_bytes());
+                    value.clear();
+                }
+                body[8..12].copy_from_slice(&(value.len() as u32).to_ne_bytes());
+                for v in value {
+                    body.extend_from_slice(&v.to_ne_bytes());
+                }
+                vec![mock::reply(req.seq, 32, &body)]
+            }
+            // UnmapWindow, SendEvent
+            10 | 25 => {
+                requests.lock().unwrap().push(req.data.clone());
+                vec![]
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        use std::convert::TryInto;
+        u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_size_hints() {
+        // as set by GTK: program position, minimum and base size, gravity
+        let data = [
+            0x314, 0, 0, 0, 0, 200, 100, 0, 0, 0, 0, 0, 0, 0, 0, 180, 80, 1,
+        ];
+        let hints = SizeHints::parse(&data);
+        assert_eq!(
+            hints,
+            SizeHints {
+                position: Some((0, 0)),
+                min_size: Some((200, 100)),
+                base_size: Some((180, 80)),
+                win_gravity: Some(x::Gravity::NorthWest),
+                ..Default::default()
+            }
+        );
+        assert_eq!(hints.encode(), data);
+
+        let hints = SizeHints {
+            position: Some((-10, 20)),
+            user_position: true,
+            size: Some((640, 480)),
+            max_size: Some((1920, 1080)),
+            size_increment: Some((8, 16)),
+            aspect: Some(((4, 3), (16, 9))),
+            win_gravity: Some(x::Gravity::Static),
+            ..Default::default()
+        };
+        let data = hints.encode();
+        assert_eq!(data[0], 0x2e9);
+        assert_eq!(SizeHints::parse(&data), hints);
+
+        // short property of old clients, invalid gravity
+        let hints = SizeHints::parse(&[0x3f0, 0, 0, 0, 0, 10, 10, 20, 20, 1, 1, 1, 1, 2, 1]);
+        assert_eq!(hints.max_size, Some((20, 20)));
+        assert_eq!(hints.aspect, Some(((1, 1), (2, 1))));
+        assert_eq!(hints.base_size, None);
+        assert_eq!(hints.win_gravity, None);
+        let mut data = SizeHints::default().encode();
+        data[0] = P_WIN_GRAVITY;
+        assert_eq!(SizeHints::parse(&data).win_gravity, None);
+        assert_eq!(SizeHints::parse(&[]), SizeHints::default());
+    }
+
+    #[test]
+    fn test_wm_normal_hints_and_protocols() {
+        use std::sync::{Arc, Mutex};
+
+        let window = unsafe { x::Window::new(0x200001) };
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(requests.clone(), None);
+
+        assert_eq!(get_wm_normal_hints(&conn, window).unwrap(), None);
+        let hints = SizeHints {
+            min_size: Some((200, 100)),
+            win_gravity: Some(x::Gravity::Center),
+            ..Default::default()
+        };
+        set_wm_normal_hints(&conn, window, &hints).unwrap();
+        assert_eq!(get_wm_normal_hints(&conn, window).unwrap(), Some(hints));
+
+        // the client doesn't take part in WM_DELETE_WINDOW
+        assert_eq!(get_wm_protocols(&conn, window).unwrap(), None);
+        assert!(!delete_window(&conn, window, 1234).unwrap());
+        assert!(requests.lock().unwrap().is_empty());
+
+        let (wm_delete_window, wm_take_focus) = unsafe { (x::Atom::new(304), x::Atom::new(305)) };
+        set_wm_protocols(&conn, window, &[wm_take_focus, wm_delete_window]).unwrap();
+        assert_eq!(
+            get_wm_protocols(&conn, window).unwrap(),
+            Some(vec![wm_take_focus, wm_delete_window])
+        );
+        assert!(delete_window(&conn, window, 1234).unwrap());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let send_event = &requests[0];
+        assert_eq!(send_event[0], 25);
+        assert_eq!(u32_at(send_event, 4), 0x200001); // destination
+        assert_eq!(u32_at(send_event, 8), 0); // no event mask
+        let event = &send_event[12..];
+        assert_eq!(event[0], 33); // ClientMessage
+        assert_eq!(event[1], 32); // format
+        assert_eq!(u32_at(event, 4), 0x200001);
+        assert_eq!(u32_at(event, 8), 303); // WM_PROTOCOLS
+        assert_eq!(u32_at(event, 12), 304); // WM_DELETE_WINDOW
+        assert_eq!(u32_at(event, 16), 1234);
+    }
+}
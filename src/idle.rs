@@ -0,0 +1,273 @@
+#[cfg(feature = "screensaver")]
+use crate::base::ResultExt;
+use crate::base::{Connection, Result};
+#[cfg(feature = "screensaver")]
+use crate::screensaver;
+use crate::worker::ConnectionPair;
+use crate::x;
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The period at which the screen saver is reset by an [IdleInhibitor] that uses
+/// [IdleInhibitMethod::ResetLoop].
+pub const IDLE_RESET_PERIOD: Duration = Duration::from_secs(30);
+
+/// The method used by an [IdleInhibitor] to prevent the screen from blanking.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdleInhibitMethod {
+    /// The screen saver is suspended with the `MIT-SCREEN-SAVER` extension (version 1.1 or later).
+    Suspend,
+    /// The screen saver is reset with `x::ForceScreenSaver` every [IDLE_RESET_PERIOD],
+    /// from a background thread that owns a secondary connection.
+    ResetLoop,
+}
+
+enum Method {
+    Suspend,
+    ResetLoop {
+        stop: Sender<()>,
+        thread: Option<JoinHandle<()>>,
+    },
+}
+
+/// Prevents the screen from blanking as long as it is alive.
+///
+/// See [Connection::inhibit_idle].
+pub struct IdleInhibitor<'a> {
+    conn: &'a Connection,
+    reason: String,
+    method: Method,
+}
+
+impl<'a> IdleInhibitor<'a> {
+    /// The reason given to [Connection::inhibit_idle].
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The method used to prevent blanking.
+    pub fn method(&self) -> IdleInhibitMethod {
+        match self.method {
+            Method::Suspend => IdleInhibitMethod::Suspend,
+            Method::ResetLoop { .. } => IdleInhibitMethod::ResetLoop,
+        }
+    }
+}
+
+impl<'a> std::fmt::Debug for IdleInhibitor<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdleInhibitor")
+            .field("reason", &self.reason)
+            .field("method", &self.method())
+            .finish()
+    }
+}
+
+impl<'a> Drop for IdleInhibitor<'a> {
+    fn drop(&mut self) {
+        match &mut self.method {
+            Method::Suspend => {
+                #[cfg(feature = "screensaver")]
+                {
+                    self.conn.send_request(&screensaver::Suspend { suspend: 0 });
+                    let _ = self.conn.flush();
+                }
+            }
+            Method::ResetLoop { stop, thread } => {
+                let _ = stop.send(());
+                if let Some(thread) = thread.take() {
+                    let _ = thread.join();
+                }
+            }
+        }
+    }
+}
+
+impl Connection {
+    /// Prevents the screen saver (and DPMS) from blanking the screen, e.g. during media playback.
+    ///
+    /// If the `screensaver` cargo feature is enabled and the server supports
+    /// `MIT-SCREEN-SAVER` 1.1 or later, the screen saver is suspended with
+    /// `screensaver::Suspend`. Suspension also disables DPMS.
+    ///
+    /// Otherwise, a background thread opens a new connection to the same display
+    /// (see [Connection::display_name]) and resets the screen saver every [IDLE_RESET_PERIOD]
+    /// with `x::ForceScreenSaver`. The X.org server also resets the DPMS timers on
+    /// `ForceScreenSaver`.
+    ///
+    /// Everything is undone when the returned [IdleInhibitor] is dropped.
+    /// `reason` is for debugging purpose only, it is not sent to the server.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// # let (conn, _) = xcb::Connection::connect(None)?;
+    /// let inhibitor = conn.inhibit_idle("video playback")?;
+    /// // [...] play the video
+    /// drop(inhibitor);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn inhibit_idle(&self, reason: &str) -> Result<IdleInhibitor<'_>> {
+        let method = if has_screensaver_suspend(self)? {
+            suspend(self)?
+        } else {
+            let conn = ConnectionPair::clone_display(self)?;
+            reset_loop(conn, IDLE_RESET_PERIOD)
+        };
+
+        Ok(IdleInhibitor {
+            conn: self,
+            reason: reason.to_string(),
+            method,
+        })
+    }
+}
+
+#[cfg(feature = "screensaver")]
+fn has_screensaver_suspend(conn: &Connection) -> Result<bool> {
+    let cookie = conn.send_request(&x::QueryExtension {
+        name: screensaver::XNAME.as_bytes(),
+    });
+    let present = conn
+        .wait_for_reply(cookie)
+        .context("QueryExtension", || screensaver::XNAME)?
+        .present();
+    if !present {
+        return Ok(false);
+    }
+
+    let cookie = conn.send_request(&screensaver::QueryVersion {
+        client_major_version: 1,
+        client_minor_version: 1,
+    });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("QueryVersion", || screensaver::XNAME)?;
+    Ok((reply.server_major_version(), reply.server_minor_version()) >= (1, 1))
+}
+
+#[cfg(not(feature = "screensaver"))]
+fn has_screensaver_suspend(_conn: &Connection) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(feature = "screensaver")]
+fn suspend(conn: &Connection) -> Result<Method> {
+    conn.send_and_check_request(&screensaver::Suspend { suspend: 1 })
+        .context("Suspend", || screensaver::XNAME)?;
+    Ok(Method::Suspend)
+}
+
+#[cfg(not(feature = "screensaver"))]
+fn suspend(_conn: &Connection) -> Result<Method> {
+    unreachable!()
+}
+
+fn reset_loop(conn: Connection, period: Duration) -> Method {
+    let (stop, stopped) = mpsc::channel();
+    let thread = thread::spawn(move || loop {
+        conn.send_request(&x::ForceScreenSaver {
+            mode: x::ScreenSaver::Reset,
+        });
+        if conn.flush().is_err() {
+            break;
+        }
+        match stopped.recv_timeout(period) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    });
+    Method::ResetLoop {
+        stop,
+        thread: Some(thread),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "screensaver")]
+    #[test]
+    fn test_inhibit_idle_suspend() {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        let suspends = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let suspends = suspends.clone();
+            mock::connect(move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(req, 141, 90, 160)],
+                // screensaver::QueryVersion
+                141 if req.data[1] == 0 => {
+                    let mut body = [0u8; 4];
+                    body[0..2].copy_from_slice(&1u16.to_ne_bytes());
+                    body[2..4].copy_from_slice(&1u16.to_ne_bytes());
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                // screensaver::Suspend
+                141 if req.data[1] == 5 => {
+                    suspends.lock().unwrap().push(req.u32_at(4));
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+
+        let inhibitor = conn.inhibit_idle("test").unwrap();
+        assert_eq!(inhibitor.method(), IdleInhibitMethod::Suspend);
+        assert_eq!(inhibitor.reason(), "test");
+        assert_eq!(*suspends.lock().unwrap(), [1]);
+
+        drop(inhibitor);
+        // round trip to make sure that the server processed the requests
+        conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+            .unwrap();
+        assert_eq!(*suspends.lock().unwrap(), [1, 0]);
+    }
+
+    #[test]
+    fn test_inhibit_idle_reset_loop() {
+        use crate::mock;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // the extension is reported absent, and the fallback fails because the mock
+        // connection has no display name
+        let conn = mock::connect(|req| match req.opcode {
+            98 => vec![mock::reply(req.seq, 0, &[])],
+            _ => mock::default_response(req),
+        });
+        let err = conn.inhibit_idle("test").unwrap_err();
+        assert_eq!(err.chain().next().unwrap().operation, "connect");
+
+        let resets = Arc::new(AtomicUsize::new(0));
+        let secondary = {
+            let resets = resets.clone();
+            mock::connect(move |req| {
+                if req.opcode == 115 && req.data[1] == 0 {
+                    resets.fetch_add(1, Ordering::SeqCst);
+                }
+                mock::default_response(req)
+            })
+        };
+
+        let inhibitor = IdleInhibitor {
+            conn: &conn,
+            reason: "test".to_string(),
+            method: reset_loop(secondary, Duration::from_millis(10)),
+        };
+        assert_eq!(inhibitor.method(), IdleInhibitMethod::ResetLoop);
+        thread::sleep(Duration::from_millis(100));
+        assert!(resets.load(Ordering::SeqCst) >= 2);
+
+        drop(inhibitor);
+        thread::sleep(Duration::from_millis(50));
+        let count = resets.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(resets.load(Ordering::SeqCst), count);
+    }
+}
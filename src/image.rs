@@ -0,0 +1,458 @@
+//! Client-side images of the core protocol, as libxcb-image.
+//!
+//! [Image] holds the pixels of an image in the layout the server expects in `PutImage`
+//! and returns in `GetImage`: the bits per pixel and the scanline padding of the pixmap
+//! format of its depth, in the image byte order of the server. It is uploaded in as many
+//! requests as needed with [Image::put], and composed from 8 bits RGBA pixels with
+//! [Image::from_rgba].
+//!
+//! See the `xv` module for the images of the X Video extension.
+
+use crate::base::{Connection, Result, ResultExt};
+use crate::surface::stride;
+use crate::x;
+
+/// An image in the layout of the server, with owned data.
+///
+/// The `ZPixmap` images have the bits per pixel and the scanline pad of the pixmap format
+/// of their depth. The `XYPixmap` images have a bitmap of each plane, most significant
+/// first, and the `XYBitmap` images a single bitmap, with the scanline pad of the bitmaps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    width: u16,
+    height: u16,
+    depth: u8,
+    format: x::ImageFormat,
+    bits_per_pixel: u8,
+    byte_order: x::ImageOrder,
+    stride: usize,
+    data: Vec<u8>,
+}
+
+/// The bits per pixel, the stride and the length of the data of an image.
+fn layout(
+    setup: &x::Setup,
+    width: u16,
+    height: u16,
+    depth: u8,
+    format: x::ImageFormat,
+) -> (u8, usize, usize) {
+    let (bits_per_pixel, scanline_pad, planes) = match format {
+        x::ImageFormat::ZPixmap => {
+            let format = setup
+                .pixmap_formats()
+                .iter()
+                .find(|f| f.depth() == depth)
+                .unwrap_or_else(|| panic!("no pixmap format of depth {}", depth));
+            (format.bits_per_pixel(), format.scanline_pad(), 1)
+        }
+        x::ImageFormat::XyPixmap => (1, setup.bitmap_format_scanline_pad(), depth as usize),
+        x::ImageFormat::XyBitmap => {
+            assert_eq!(depth, 1, "XYBitmap image of depth {}", depth);
+            (1, setup.bitmap_format_scanline_pad(), 1)
+        }
+    };
+    let stride = stride(width, bits_per_pixel as usize, scanline_pad as usize);
+    (bits_per_pixel, stride, stride * height as usize * planes)
+}
+
+impl Image {
+    /// Creates a blank image of `width` by `height` pixels of `depth`, in `format`.
+    ///
+    /// # Panics
+    /// Panics if the server has no pixmap format of `depth` for `ImageFormat::ZPixmap`,
+    /// or if `depth` is not 1 for `ImageFormat::XyBitmap`.
+    pub fn new(
+        setup: &x::Setup,
+        width: u16,
+        height: u16,
+        depth: u8,
+        format: x::ImageFormat,
+    ) -> Image {
+        let len = layout(setup, width, height, depth, format).2;
+        Image::from_raw(setup, width, height, depth, format, vec![0; len])
+    }
+
+    /// Creates an image of `data`, in the layout of the server and in its image byte order.
+    ///
+    /// `data` may be longer than the image, the rest is ignored.
+    ///
+    /// # Panics
+    /// Panics as [Image::new], and if `data` is shorter than the image.
+    pub fn from_raw(
+        setup: &x::Setup,
+        width: u16,
+        height: u16,
+        depth: u8,
+        format: x::ImageFormat,
+        mut data: Vec<u8>,
+    ) -> Image {
+        let (bits_per_pixel, stride, len) = layout(setup, width, height, depth, format);
+        assert!(
+            data.len() >= len,
+            "image data of {} bytes, {} expected",
+            data.len(),
+            len
+        );
+        data.truncate(len);
+        Image {
+            width,
+            height,
+            depth,
+            format,
+            bits_per_pixel,
+            byte_order: setup.image_byte_order(),
+            stride,
+            data,
+        }
+    }
+
+    /// Creates a `ZPixmap` image of `depth` from pixels of 8 bits red, green, blue and
+    /// alpha, row by row.
+    ///
+    /// The channels are scaled to the masks of `visual`, which should be a `TrueColor` or
+    /// `DirectColor` visual of `depth`. The alpha is stored in the bits of the depth that
+    /// are out of the masks, such as the most significant byte of the ARGB visuals, and
+    /// dropped if there are none. The pixels are not premultiplied.
+    ///
+    /// # Panics
+    /// Panics as [Image::new], if the bits per pixel of `depth` are not 8, 16, 24 or 32,
+    /// or if `rgba` is shorter than `width * height * 4` bytes.
+    pub fn from_rgba(
+        setup: &x::Setup,
+        visual: &x::Visualtype,
+        depth: u8,
+        width: u16,
+        height: u16,
+        rgba: &[u8],
+    ) -> Image {
+        let len = width as usize * height as usize * 4;
+        assert!(
+            rgba.len() >= len,
+            "RGBA data of {} bytes, {} expected",
+            rgba.len(),
+            len
+        );
+        let format = x::PixelFormat::from_visual(visual);
+        let depth_mask = if depth >= 32 {
+            u32::MAX
+        } else {
+            (1 << depth) - 1
+        };
+        let alpha = x::Channel::from_mask(
+            depth_mask & !(visual.red_mask() | visual.green_mask() | visual.blue_mask()),
+        );
+
+        let mut image = Image::new(setup, width, height, depth, x::ImageFormat::ZPixmap);
+        let scale = |value: u8| value as u16 * 0x101;
+        for (i, px) in rgba[..len].chunks_exact(4).enumerate() {
+            let pixel = format.encode(scale(px[0]), scale(px[1]), scale(px[2]))
+                | alpha.encode(scale(px[3]));
+            let (x, y) = (i % width as usize, i / width as usize);
+            image.put_pixel(x as u16, y as u16, pixel);
+        }
+        image
+    }
+
+    /// Fetches the `rect` area of `drawable` in a `ZPixmap` image, of all its planes.
+    ///
+    /// The area must be within the drawable, and within its parent for a window.
+    pub fn get(conn: &Connection, drawable: x::Drawable, rect: x::Rectangle) -> Result<Image> {
+        let cookie = conn.send_request(&x::GetImage {
+            format: x::ImageFormat::ZPixmap,
+            drawable,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            plane_mask: u32::MAX,
+        });
+        let reply = conn.wait_for_reply(cookie).context("GetImage", || {
+            format!(
+                "{}x{}+{}+{} of {:?}",
+                rect.width, rect.height, rect.x, rect.y, drawable
+            )
+        })?;
+        Ok(Image::from_raw(
+            conn.get_setup(),
+            rect.width,
+            rect.height,
+            reply.depth(),
+            x::ImageFormat::ZPixmap,
+            reply.data().to_vec(),
+        ))
+    }
+
+    /// Uploads the image to `drawable` with `gc`, with its top left corner at `x`, `y`.
+    ///
+    /// The image is sent in as many `PutImage` requests as needed (see
+    /// [x::put_image_chunked]), converted to the image byte order of the server if needed.
+    pub fn put(
+        &self,
+        conn: &Connection,
+        drawable: x::Drawable,
+        gc: x::Gcontext,
+        x: i16,
+        y: i16,
+    ) -> Result<()> {
+        let server_order = conn.get_setup().image_byte_order();
+        let converted;
+        let image = if self.byte_order == server_order {
+            self
+        } else {
+            let mut image = self.clone();
+            image.convert_byte_order(server_order);
+            converted = image;
+            &converted
+        };
+        x::put_image_chunked(
+            conn,
+            &x::PutImage {
+                format: image.format,
+                drawable,
+                gc,
+                width: image.width,
+                height: image.height,
+                dst_x: x,
+                dst_y: y,
+                left_pad: 0,
+                depth: image.depth,
+                data: &image.data,
+            },
+        )
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn format(&self) -> x::ImageFormat {
+        self.format
+    }
+
+    /// The bits per pixel, 1 for the `XYPixmap` and `XYBitmap` images.
+    pub fn bits_per_pixel(&self) -> u8 {
+        self.bits_per_pixel
+    }
+
+    /// The length of a row in bytes, padded to the scanline pad.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The byte order of the pixels of a `ZPixmap` image.
+    pub fn byte_order(&self) -> x::ImageOrder {
+        self.byte_order
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The bytes of the pixel at `x`, `y`.
+    ///
+    /// # Panics
+    /// Panics if the image is not a `ZPixmap` image of 8, 16, 24 or 32 bits per pixel,
+    /// or if the pixel is out of the image.
+    fn pixel_range(&self, x: u16, y: u16) -> std::ops::Range<usize> {
+        assert!(
+            self.format == x::ImageFormat::ZPixmap
+                && matches!(self.bits_per_pixel, 8 | 16 | 24 | 32),
+            "pixel access to a {:?} image of {} bits per pixel",
+            self.format,
+            self.bits_per_pixel
+        );
+        assert!(
+            x < self.width && y < self.height,
+            "pixel {},{} out of a {}x{} image",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let bytes = self.bits_per_pixel as usize / 8;
+        let offset = y as usize * self.stride + x as usize * bytes;
+        offset..offset + bytes
+    }
+
+    /// The pixel at `x`, `y` of a `ZPixmap` image.
+    ///
+    /// # Panics
+    /// Panics if the image is not a `ZPixmap` image of 8, 16, 24 or 32 bits per pixel,
+    /// or if the pixel is out of the image.
+    pub fn get_pixel(&self, x: u16, y: u16) -> u32 {
+        let bytes = &self.data[self.pixel_range(x, y)];
+        match self.byte_order {
+            x::ImageOrder::LsbFirst => bytes.iter().rev().fold(0, |px, b| px << 8 | *b as u32),
+            x::ImageOrder::MsbFirst => bytes.iter().fold(0, |px, b| px << 8 | *b as u32),
+        }
+    }
+
+    /// Sets the pixel at `x`, `y` of a `ZPixmap` image.
+    ///
+    /// # Panics
+    /// Panics as [Image::get_pixel].
+    pub fn put_pixel(&mut self, x: u16, y: u16, pixel: u32) {
+        let range = self.pixel_range(x, y);
+        let len = range.len();
+        let bytes = &mut self.data[range];
+        let le = pixel.to_le_bytes();
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = match self.byte_order {
+                x::ImageOrder::LsbFirst => le[i],
+                x::ImageOrder::MsbFirst => le[len - 1 - i],
+            };
+        }
+    }
+
+    /// Converts the pixels of a `ZPixmap` image to the byte order `order`.
+    ///
+    /// The images of the other formats and of 8 bits per pixel or less are unchanged.
+    pub fn convert_byte_order(&mut self, order: x::ImageOrder) {
+        if order == self.byte_order {
+            return;
+        }
+        self.byte_order = order;
+        let bytes = self.bits_per_pixel as usize / 8;
+        if self.format != x::ImageFormat::ZPixmap || bytes < 2 {
+            return;
+        }
+        let row_len = self.width as usize * bytes;
+        for row in self.data.chunks_exact_mut(self.stride) {
+            for px in row[..row_len].chunks_exact_mut(bytes) {
+                px.reverse();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_layout() {
+        use crate::mock;
+
+        // the mock server has the pixmap formats of depth 1, 24 and 32 with a scanline pad
+        // of 32 bits, in LSB first
+        let conn = mock::connect(mock::default_response);
+        let setup = conn.get_setup();
+
+        let image = Image::new(setup, 3, 2, 24, x::ImageFormat::ZPixmap);
+        assert_eq!((image.bits_per_pixel(), image.stride()), (32, 12));
+        assert_eq!(image.data().len(), 24);
+        let image = Image::new(setup, 33, 2, 1, x::ImageFormat::ZPixmap);
+        assert_eq!((image.bits_per_pixel(), image.stride()), (1, 8));
+        let image = Image::new(setup, 33, 2, 24, x::ImageFormat::XyPixmap);
+        assert_eq!((image.bits_per_pixel(), image.stride()), (1, 8));
+        assert_eq!(image.data().len(), 8 * 2 * 24);
+
+        let mut image = Image::from_raw(setup, 2, 1, 32, x::ImageFormat::ZPixmap, vec![7; 10]);
+        assert_eq!(image.data().len(), 8);
+        image.put_pixel(1, 0, 0x8011_2233);
+        assert_eq!(image.get_pixel(1, 0), 0x8011_2233);
+        assert_eq!(image.data()[4..], [0x33, 0x22, 0x11, 0x80]);
+
+        image.convert_byte_order(x::ImageOrder::MsbFirst);
+        assert_eq!(image.byte_order(), x::ImageOrder::MsbFirst);
+        assert_eq!(image.get_pixel(1, 0), 0x8011_2233);
+        assert_eq!(image.get_pixel(0, 0), 0x0707_0707);
+        assert_eq!(image.data()[4..], [0x80, 0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    #[should_panic(expected = "image data of 20 bytes, 24 expected")]
+    fn test_image_from_raw_short() {
+        use crate::mock;
+
+        let conn = mock::connect(mock::default_response);
+        Image::from_raw(
+            conn.get_setup(),
+            3,
+            2,
+            24,
+            x::ImageFormat::ZPixmap,
+            vec![0; 20],
+        );
+    }
+
+    #[test]
+    fn test_image_put_get() {
+        use crate::mock;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        // the data of the PutImage requests
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let puts = puts.clone();
+            mock::connect(move |req| match req.opcode {
+                98 => vec![mock::reply(req.seq, 0, &[])],
+                // PutImage
+                72 => {
+                    puts.lock().unwrap().push(req.data[24..].to_vec());
+                    vec![]
+                }
+                // GetImage: a 2x1 area of depth 24
+                73 => {
+                    assert_eq!(req.data[1], 2); // ZPixmap
+                    let mut body = vec![0u8; 24];
+                    body.extend_from_slice(&[1, 2, 3, 0, 4, 5, 6, 0]);
+                    vec![mock::reply(req.seq, 24, &body)]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let setup = conn.get_setup();
+        let visual = x::Visualtype::new(
+            0x21,
+            x::VisualClass::TrueColor,
+            8,
+            256,
+            0xff_0000,
+            0x00_ff00,
+            0x00_00ff,
+        );
+        let drawable = x::Drawable::Window(unsafe { x::Window::new(mock::ROOT) });
+        let gc = unsafe { x::Gcontext::new(0x200001) };
+
+        let image = Image::get(
+            &conn,
+            drawable,
+            x::Rectangle {
+                x: 0,
+                y: 0,
+                width: 2,
+                height: 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(image.depth(), 24);
+        assert_eq!(image.get_pixel(1, 0), 0x060504);
+
+        // converted back to the byte order of the server
+        let mut image =
+            Image::from_rgba(setup, &visual, 24, 2, 1, &[0xff, 0, 0x80, 0xff, 0, 0, 0, 0]);
+        assert_eq!(image.get_pixel(0, 0), 0xff0080);
+        image.convert_byte_order(x::ImageOrder::MsbFirst);
+        image.put(&conn, drawable, gc, 0, 0).unwrap();
+        assert_eq!(*puts.lock().unwrap(), [vec![0x80, 0, 0xff, 0, 0, 0, 0, 0]]);
+    }
+}
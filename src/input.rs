@@ -0,0 +1,635 @@
+//! Classification of the core pointer and keyboard input events.
+//!
+//! [ClickDetector] counts the successive presses of a pointer button into single, double and
+//! triple clicks. [RepeatFilter] tells the key presses generated by the keyboard auto-repeat
+//! from the real ones. Both only look at the events they are given and never send requests.
+//!
+//! The timestamps of the server are in milliseconds and wrap around after about 49.7 days.
+//! They are compared with [ServerTime].
+
+use crate::event::Event;
+use crate::x;
+
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+/// A timestamp of the server, in milliseconds.
+///
+/// The server time wraps around to 0 after `u32::MAX`. Two times are compared by their
+/// difference, which is correct as long as they are less than about 24.8 days apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServerTime(pub x::Timestamp);
+
+impl ServerTime {
+    /// The milliseconds elapsed from `earlier` to `self`, negative if `earlier` is later
+    /// than `self`.
+    pub fn millis_since(self, earlier: ServerTime) -> i32 {
+        self.0.wrapping_sub(earlier.0) as i32
+    }
+
+    /// The duration elapsed from `earlier` to `self`, or `None` if `earlier` is later
+    /// than `self`.
+    pub fn duration_since(self, earlier: ServerTime) -> Option<Duration> {
+        let millis = self.millis_since(earlier);
+        if millis < 0 {
+            None
+        } else {
+            Some(Duration::from_millis(millis as u64))
+        }
+    }
+
+    /// Whether `self` is strictly later than `other`.
+    pub fn is_after(self, other: ServerTime) -> bool {
+        self.millis_since(other) > 0
+    }
+}
+
+impl From<x::Timestamp> for ServerTime {
+    fn from(time: x::Timestamp) -> ServerTime {
+        ServerTime(time)
+    }
+}
+
+/// The kind of click of a button press. See [ClickDetector].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClickKind {
+    /// The first press of a series.
+    Single,
+    /// The second press of a series.
+    Double,
+    /// The third press of a series.
+    Triple,
+}
+
+/// A button press classified by [ClickDetector].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Click {
+    /// The pressed button.
+    pub button: x::Button,
+    /// The window of the event.
+    pub window: x::Window,
+    /// The time of the press.
+    pub time: ServerTime,
+    /// The position of the press, relative to the root window.
+    pub root_x: i16,
+    /// The position of the press, relative to the root window.
+    pub root_y: i16,
+    /// The kind of click.
+    pub kind: ClickKind,
+}
+
+/// Counts the successive presses of a pointer button into single, double and triple clicks.
+///
+/// A press continues the series of the previous press if it is of the same button, in the
+/// same window, no later than the interval after the previous press and within the distance
+/// of the first press of the series. Any other press starts a new series, as does the press
+/// following a triple click.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, _) = xcb::Connection::connect(None)?;
+/// let mut clicks = xcb::input::ClickDetector::new();
+/// loop {
+///     let event = conn.wait_for_event()?;
+///     if let Some(click) = clicks.handle_event(&event) {
+///         println!("{:?} click of button {}", click.kind, click.button);
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClickDetector {
+    interval: Duration,
+    distance: u16,
+    // the position of the first press of the current series
+    origin: Option<(i16, i16)>,
+    last: Option<Click>,
+}
+
+impl Default for ClickDetector {
+    fn default() -> ClickDetector {
+        ClickDetector::new()
+    }
+}
+
+impl ClickDetector {
+    /// The default maximum interval between two clicks of a series.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(400);
+
+    /// The default maximum distance, in pixels, between the clicks of a series.
+    pub const DEFAULT_DISTANCE: u16 = 5;
+
+    /// Builds a detector with the default interval and distance.
+    pub fn new() -> ClickDetector {
+        ClickDetector {
+            interval: ClickDetector::DEFAULT_INTERVAL,
+            distance: ClickDetector::DEFAULT_DISTANCE,
+            origin: None,
+            last: None,
+        }
+    }
+
+    /// Sets the maximum interval between two clicks of a series.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Sets the maximum distance, in pixels and along each axis, between the first click
+    /// of a series and the following ones.
+    pub fn set_distance(&mut self, distance: u16) {
+        self.distance = distance;
+    }
+
+    /// The last classified click.
+    pub fn last_click(&self) -> Option<&Click> {
+        self.last.as_ref()
+    }
+
+    /// Forgets the current series: the next press is a single click.
+    pub fn reset(&mut self) {
+        self.origin = None;
+        self.last = None;
+    }
+
+    /// Classifies `event` if it is a `ButtonPress` event.
+    pub fn handle_event(&mut self, event: &Event) -> Option<Click> {
+        match event {
+            Event::X(x::Event::ButtonPress(ev)) => Some(self.handle_press(ev)),
+            _ => None,
+        }
+    }
+
+    /// Classifies the button press `ev`.
+    pub fn handle_press(&mut self, ev: &x::ButtonPressEvent) -> Click {
+        self.press(
+            ev.detail(),
+            ev.event(),
+            ServerTime(ev.time()),
+            ev.root_x(),
+            ev.root_y(),
+        )
+    }
+
+    fn press(
+        &mut self,
+        button: x::Button,
+        window: x::Window,
+        time: ServerTime,
+        root_x: i16,
+        root_y: i16,
+    ) -> Click {
+        let kind = match (&self.last, self.origin) {
+            (Some(last), Some((x0, y0)))
+                if last.button == button
+                    && last.window == window
+                    && self.within_interval(last.time, time)
+                    && self.within_distance(x0, y0, root_x, root_y) =>
+            {
+                match last.kind {
+                    ClickKind::Single => ClickKind::Double,
+                    ClickKind::Double => ClickKind::Triple,
+                    ClickKind::Triple => ClickKind::Single,
+                }
+            }
+            _ => ClickKind::Single,
+        };
+        if kind == ClickKind::Single {
+            self.origin = Some((root_x, root_y));
+        }
+        let click = Click {
+            button,
+            window,
+            time,
+            root_x,
+            root_y,
+            kind,
+        };
+        self.last = Some(click);
+        click
+    }
+
+    fn within_interval(&self, last: ServerTime, time: ServerTime) -> bool {
+        time.duration_since(last)
+            .is_some_and(|elapsed| elapsed <= self.interval)
+    }
+
+    fn within_distance(&self, x0: i16, y0: i16, x: i16, y: i16) -> bool {
+        let dx = (x as i32 - x0 as i32).unsigned_abs();
+        let dy = (y as i32 - y0 as i32).unsigned_abs();
+        dx <= self.distance as u32 && dy <= self.distance as u32
+    }
+}
+
+/// An event passed through [RepeatFilter].
+#[derive(Debug)]
+pub enum Filtered {
+    /// An event that is not an auto-repeat, including the real key presses and releases.
+    Event(Event),
+    /// A key press generated by the auto-repeat of the keyboard.
+    Repeat(x::KeyPressEvent),
+}
+
+/// Tells the key presses generated by the keyboard auto-repeat from the real ones.
+///
+/// Unless the client enables the detectable auto-repeat of XKB, the server reports each
+/// repeat of a held key as a `KeyRelease` followed by a `KeyPress` of the same key with
+/// the same timestamp. The filter holds back each `KeyRelease` until the next event:
+/// if it is the matching `KeyPress`, both are replaced by a single [Filtered::Repeat].
+/// With the detectable auto-repeat, the repeats are `KeyPress` events of a key that is
+/// already pressed, and are also reported as [Filtered::Repeat].
+///
+/// The server sends the two events of a repeat together: a `KeyRelease` that is the last
+/// queued event is a real release, and must be released with [RepeatFilter::flush].
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, _) = xcb::Connection::connect(None)?;
+/// use xcb::input::{Filtered, RepeatFilter};
+///
+/// let mut filter = RepeatFilter::new();
+/// loop {
+///     filter.push(conn.wait_for_event()?);
+///     while let Some(event) = conn.poll_for_queued_event()? {
+///         filter.push(event);
+///     }
+///     filter.flush();
+///     while let Some(filtered) = filter.pop() {
+///         match filtered {
+///             Filtered::Repeat(ev) => println!("repeat of key {}", ev.detail()),
+///             Filtered::Event(event) => println!("{:?}", event),
+///         }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct RepeatFilter {
+    // the release held back until the next event
+    held: Option<x::KeyReleaseEvent>,
+    pressed: HashSet<x::Keycode>,
+    output: VecDeque<Filtered>,
+}
+
+impl RepeatFilter {
+    /// Builds a filter with no key pressed.
+    pub fn new() -> RepeatFilter {
+        RepeatFilter::default()
+    }
+
+    /// Whether `keycode` is pressed according to the filtered events.
+    pub fn is_pressed(&self, keycode: x::Keycode) -> bool {
+        self.pressed.contains(&keycode)
+    }
+
+    /// Whether a `KeyRelease` is held back, waiting for the next event.
+    pub fn is_holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Filters `event`. The filtered events are returned by [RepeatFilter::pop].
+    pub fn push(&mut self, event: Event) {
+        match event {
+            Event::X(x::Event::KeyPress(press)) => {
+                if let Some(release) = self.held.take() {
+                    if is_repeat(&release, &press) {
+                        self.output.push_back(Filtered::Repeat(press));
+                        return;
+                    }
+                    self.release(release);
+                }
+                if self.pressed.insert(press.detail()) {
+                    self.output
+                        .push_back(Filtered::Event(Event::X(x::Event::KeyPress(press))));
+                } else {
+                    self.output.push_back(Filtered::Repeat(press));
+                }
+            }
+            Event::X(x::Event::KeyRelease(release)) => {
+                self.flush();
+                self.held = Some(release);
+            }
+            event => {
+                self.flush();
+                self.output.push_back(Filtered::Event(event));
+            }
+        }
+    }
+
+    /// Releases the held back `KeyRelease`, if any, as a real release.
+    pub fn flush(&mut self) {
+        if let Some(release) = self.held.take() {
+            self.release(release);
+        }
+    }
+
+    /// Returns the next filtered event.
+    pub fn pop(&mut self) -> Option<Filtered> {
+        self.output.pop_front()
+    }
+
+    fn release(&mut self, release: x::KeyReleaseEvent) {
+        self.pressed.remove(&release.detail());
+        self.output
+            .push_back(Filtered::Event(Event::X(x::Event::KeyRelease(release))));
+    }
+}
+
+/// Whether `press` is the auto-repeat following `release`.
+fn is_repeat(release: &x::KeyReleaseEvent, press: &x::KeyPressEvent) -> bool {
+    release.detail() == press.detail()
+        && release.time() == press.time()
+        && release.event() == press.event()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Key {
+        Press(u8, u32),
+        Release(u8, u32),
+        Repeat(u8, u32),
+        Other,
+    }
+
+    fn drain(filter: &mut RepeatFilter) -> Vec<Key> {
+        std::iter::from_fn(|| filter.pop())
+            .map(|filtered| match filtered {
+                Filtered::Repeat(ev) => Key::Repeat(ev.detail(), ev.time()),
+                Filtered::Event(Event::X(x::Event::KeyPress(ev))) => {
+                    Key::Press(ev.detail(), ev.time())
+                }
+                Filtered::Event(Event::X(x::Event::KeyRelease(ev))) => {
+                    Key::Release(ev.detail(), ev.time())
+                }
+                Filtered::Event(_) => Key::Other,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_server_time() {
+        let t = ServerTime(1000);
+        assert_eq!(ServerTime(1250).millis_since(t), 250);
+        assert_eq!(ServerTime(750).millis_since(t), -250);
+        assert!(ServerTime(1001).is_after(t));
+        assert!(!t.is_after(t));
+        assert_eq!(
+            ServerTime(1250).duration_since(t),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(ServerTime(750).duration_since(t), None);
+
+        // across the wrap around
+        let before = ServerTime(u32::MAX - 99);
+        let after = ServerTime(100);
+        assert_eq!(after.millis_since(before), 200);
+        assert_eq!(before.millis_since(after), -200);
+        assert!(after.is_after(before));
+        assert_eq!(
+            after.duration_since(before),
+            Some(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn test_clicks() {
+        let mut clicks = ClickDetector::new();
+
+        let series = [
+            (1000, 50, 50, ClickKind::Single),
+            (1200, 51, 49, ClickKind::Double),
+            (1600, 52, 50, ClickKind::Triple),
+            // the series restarts after a triple click
+            (1700, 52, 50, ClickKind::Single),
+            (1800, 52, 50, ClickKind::Double),
+        ];
+        for (time, root_x, root_y, kind) in series {
+            let click = clicks.handle_event(&button_press(1, 1, time, root_x, root_y));
+            assert_eq!(click.map(|click| click.kind), Some(kind));
+        }
+
+        assert!(clicks.handle_event(&key_event(true, 38, 1850)).is_none());
+    }
+
+    #[test]
+    fn test_clicks_reset() {
+        let mut clicks = ClickDetector::new();
+        let mut kind = |event: Event| clicks.handle_event(&event).unwrap().kind;
+
+        // too late
+        assert_eq!(kind(button_press(1, 1, 1000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 1401, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 1801, 50, 50)), ClickKind::Double);
+
+        // too far, along any axis
+        assert_eq!(kind(button_press(1, 1, 3000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 3100, 56, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 3200, 56, 44)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 3300, 51, 40)), ClickKind::Double);
+
+        // the distance is from the first click of the series
+        assert_eq!(kind(button_press(1, 1, 5000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 5100, 55, 50)), ClickKind::Double);
+        assert_eq!(kind(button_press(1, 1, 5200, 60, 50)), ClickKind::Single);
+
+        // another button
+        assert_eq!(kind(button_press(1, 1, 7000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(3, 1, 7100, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 7200, 50, 50)), ClickKind::Single);
+
+        // another window
+        assert_eq!(kind(button_press(1, 1, 9000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 2, 9100, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 2, 9200, 50, 50)), ClickKind::Double);
+
+        // time going backwards
+        assert_eq!(kind(button_press(1, 1, 11000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 10900, 50, 50)), ClickKind::Single);
+    }
+
+    #[test]
+    fn test_clicks_settings() {
+        let mut clicks = ClickDetector::new();
+        clicks.set_interval(Duration::from_millis(100));
+        clicks.set_distance(0);
+        let mut kind = |event: Event| clicks.handle_event(&event).unwrap().kind;
+
+        assert_eq!(kind(button_press(1, 1, 1000, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 1100, 50, 50)), ClickKind::Double);
+        assert_eq!(kind(button_press(1, 1, 1201, 50, 50)), ClickKind::Single);
+        assert_eq!(kind(button_press(1, 1, 1250, 50, 51)), ClickKind::Single);
+
+        // across the wrap around of the server time
+        assert_eq!(
+            kind(button_press(1, 1, u32::MAX - 20, 50, 50)),
+            ClickKind::Single
+        );
+        assert_eq!(kind(button_press(1, 1, 30, 50, 50)), ClickKind::Double);
+
+        clicks.reset();
+        assert!(clicks.last_click().is_none());
+        let click = clicks
+            .handle_event(&button_press(2, 3, 60, 40, 30))
+            .unwrap();
+        assert_eq!(click.kind, ClickKind::Single);
+        assert_eq!(click.button, 2);
+        assert_eq!(click.window, window(3));
+        assert_eq!(click.time, ServerTime(60));
+        assert_eq!((click.root_x, click.root_y), (40, 30));
+        assert_eq!(clicks.last_click(), Some(&click));
+    }
+
+    #[test]
+    fn test_repeat_filter() {
+        use Key::*;
+
+        let mut filter = RepeatFilter::new();
+
+        // a held key: the repeats are release and press pairs with equal timestamps
+        filter.push(key_event(true, 38, 1000));
+        assert!(filter.is_pressed(38));
+        filter.push(key_event(false, 38, 1500));
+        assert!(filter.is_holding());
+        assert_eq!(drain(&mut filter), vec![Press(38, 1000)]);
+        filter.push(key_event(true, 38, 1500));
+        filter.push(key_event(false, 38, 1530));
+        filter.push(key_event(true, 38, 1530));
+        filter.push(key_event(false, 38, 1560));
+        filter.push(key_event(true, 38, 1560));
+        assert!(!filter.is_holding());
+        // the real release is the last event
+        filter.push(key_event(false, 38, 1575));
+        filter.flush();
+        assert!(!filter.is_pressed(38));
+        assert_eq!(
+            drain(&mut filter),
+            vec![
+                Repeat(38, 1500),
+                Repeat(38, 1530),
+                Repeat(38, 1560),
+                Release(38, 1575)
+            ]
+        );
+
+        // a quick press after a release is not a repeat
+        filter.push(key_event(true, 38, 2000));
+        filter.push(key_event(false, 38, 2080));
+        filter.push(key_event(true, 38, 2081));
+        assert_eq!(
+            drain(&mut filter),
+            vec![Press(38, 2000), Release(38, 2080), Press(38, 2081)]
+        );
+
+        // nor is the press of another key with the same timestamp
+        filter.push(key_event(false, 38, 2100));
+        filter.push(key_event(true, 40, 2100));
+        assert!(!filter.is_pressed(38));
+        assert!(filter.is_pressed(40));
+        assert_eq!(drain(&mut filter), vec![Release(38, 2100), Press(40, 2100)]);
+    }
+
+    #[test]
+    fn test_repeat_filter_other_events() {
+        use Key::*;
+
+        let mut filter = RepeatFilter::new();
+
+        // any other event releases the held release
+        filter.push(key_event(true, 50, 100));
+        filter.push(key_event(false, 50, 200));
+        filter.push(button_press(1, 1, 200, 10, 10));
+        assert!(!filter.is_holding());
+        assert_eq!(
+            drain(&mut filter),
+            vec![Press(50, 100), Release(50, 200), Other]
+        );
+
+        // and so does another release
+        filter.push(key_event(true, 50, 300));
+        filter.push(key_event(true, 51, 310));
+        filter.push(key_event(false, 50, 400));
+        filter.push(key_event(false, 51, 400));
+        filter.flush();
+        assert_eq!(
+            drain(&mut filter),
+            vec![
+                Press(50, 300),
+                Press(51, 310),
+                Release(50, 400),
+                Release(51, 400)
+            ]
+        );
+
+        // detectable auto-repeat: presses of a pressed key
+        filter.push(key_event(true, 52, 500));
+        filter.push(key_event(true, 52, 1000));
+        filter.push(key_event(true, 52, 1030));
+        filter.push(key_event(false, 52, 1040));
+        filter.flush();
+        assert_eq!(
+            drain(&mut filter),
+            vec![
+                Press(52, 500),
+                Repeat(52, 1000),
+                Repeat(52, 1030),
+                Release(52, 1040)
+            ]
+        );
+    }
+
+    fn window(id: u32) -> x::Window {
+        use crate::XidNew;
+        unsafe { x::Window::new(id) }
+    }
+
+    fn button_press(button: u8, window_id: u32, time: u32, root_x: i16, root_y: i16) -> Event {
+        Event::X(x::Event::ButtonPress(x::ButtonPressEvent::new(
+            button,
+            time,
+            window(crate::mock::ROOT),
+            window(window_id),
+            x::WINDOW_NONE,
+            root_x,
+            root_y,
+            root_x,
+            root_y,
+            x::KeyButMask::empty(),
+            true,
+        )))
+    }
+
+    fn key_event(press: bool, keycode: u8, time: u32) -> Event {
+        let ev = x::KeyPressEvent::new(
+            keycode,
+            time,
+            window(crate::mock::ROOT),
+            window(0x0120_0001),
+            x::WINDOW_NONE,
+            10,
+            10,
+            10,
+            10,
+            x::KeyButMask::empty(),
+            true,
+        );
+        if press {
+            Event::X(x::Event::KeyPress(ev))
+        } else {
+            Event::X(x::Event::KeyRelease(ev))
+        }
+    }
+
+    #[test]
+    fn test_event_time() {
+        assert_eq!(button_press(1, 0x0120_0001, 1234, 0, 0).time(), Some(1234));
+        assert_eq!(key_event(false, 50, 4321).time(), Some(4321));
+
+        // the structure events carry no time
+        let map = x::MapNotifyEvent::new(window(crate::mock::ROOT), window(0x0120_0001), false);
+        assert_eq!(Event::X(x::Event::MapNotify(map)).time(), None);
+    }
+}
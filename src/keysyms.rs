@@ -0,0 +1,401 @@
+//! Translation between keycodes and keysyms, as done by the `xcb-keysyms` library.
+//!
+//! [KeySymbols] caches the keyboard mapping of the server, and its modifier mapping to
+//! apply the rules of the core protocol that select the keysym of a key event from its
+//! modifiers: `Shift`, `Lock`, `Num_Lock` and `Mode_switch`. The cache is refreshed with
+//! the `MappingNotify` events, which are always sent to all clients.
+
+use crate::base::{Connection, Result, ResultExt};
+use crate::event::Event;
+use crate::x::{self, Keycode, Keysym, NO_SYMBOL};
+
+const XK_MODE_SWITCH: Keysym = 0xff7e;
+const XK_NUM_LOCK: Keysym = 0xff7f;
+const XK_CAPS_LOCK: Keysym = 0xffe5;
+const XK_SHIFT_LOCK: Keysym = 0xffe6;
+
+/// Whether `keysym` is on the keypad, where `Num_Lock` selects the second keysym.
+fn is_keypad(keysym: Keysym) -> bool {
+    // `KP_Space` to `KP_Equal`, and the keypad keysyms of the vendors
+    (0xff80..=0xffbd).contains(&keysym) || (0x1100_0000..=0x1100_ffff).contains(&keysym)
+}
+
+/// The lowercase and uppercase keysyms of `keysym`.
+///
+/// The case of the Latin-1 and of the Unicode keysyms is converted, the other keysyms
+/// are returned unchanged.
+pub fn convert_case(keysym: Keysym) -> (Keysym, Keysym) {
+    match keysym {
+        0x41..=0x5a | 0xc0..=0xd6 | 0xd8..=0xde => (keysym + 0x20, keysym),
+        0x61..=0x7a | 0xe0..=0xf6 | 0xf8..=0xfe => (keysym, keysym - 0x20),
+        0x0100_0100..=0x0110_ffff => match char::from_u32(keysym - 0x0100_0000) {
+            Some(ch) => (
+                single_char_keysym(ch.to_lowercase(), keysym),
+                single_char_keysym(ch.to_uppercase(), keysym),
+            ),
+            None => (keysym, keysym),
+        },
+        _ => (keysym, keysym),
+    }
+}
+
+/// The keysym of the case conversion `chars`, or `keysym` if it gives several characters
+/// (such as `ß` to `SS`).
+fn single_char_keysym(mut chars: impl Iterator<Item = char>, keysym: Keysym) -> Keysym {
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => unicode_keysym(ch),
+        _ => keysym,
+    }
+}
+
+/// The keysym of `ch`: its code point in Latin-1, and offset by `0x0100_0000` beyond.
+fn unicode_keysym(ch: char) -> Keysym {
+    match ch as u32 {
+        code @ 0x20..=0x7e | code @ 0xa0..=0xff => code,
+        code => code | 0x0100_0000,
+    }
+}
+
+/// How the `Lock` modifier selects the keysyms.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LockMode {
+    /// No `Caps_Lock` or `Shift_Lock` key is in the `Lock` modifier: it is ignored.
+    None,
+    /// Uppercase the alphabetic keysyms.
+    CapsLock,
+    /// Acts as `Shift`.
+    ShiftLock,
+}
+
+/// The keyboard mapping of the server, and the role of its modifiers.
+///
+/// The mappings are fetched with `x::GetKeyboardMapping` and `x::GetModifierMapping` by
+/// [KeySymbols::new], and again by [KeySymbols::refresh] after a `MappingNotify` event.
+pub struct KeySymbols<'c> {
+    conn: &'c Connection,
+    min_keycode: Keycode,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<Keysym>,
+    num_lock: x::KeyButMask,
+    mode_switch: x::KeyButMask,
+    lock: LockMode,
+}
+
+impl<'c> KeySymbols<'c> {
+    /// Fetches the keyboard and modifier mappings of the server.
+    pub fn new(conn: &'c Connection) -> Result<KeySymbols<'c>> {
+        let mut key_symbols = KeySymbols {
+            conn,
+            min_keycode: 0,
+            keysyms_per_keycode: 0,
+            keysyms: Vec::new(),
+            num_lock: x::KeyButMask::empty(),
+            mode_switch: x::KeyButMask::empty(),
+            lock: LockMode::None,
+        };
+        key_symbols.fetch()?;
+        Ok(key_symbols)
+    }
+
+    fn fetch(&mut self) -> Result<()> {
+        let setup = self.conn.get_setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode(), setup.max_keycode());
+        let keyboard_cookie = self.conn.send_request(&x::GetKeyboardMapping {
+            first_keycode: min_keycode,
+            count: max_keycode - min_keycode + 1,
+        });
+        let modifier_cookie = self.conn.send_request(&x::GetModifierMapping {});
+
+        let reply = self
+            .conn
+            .wait_for_reply(keyboard_cookie)
+            .context("GetKeyboardMapping", String::new)?;
+        self.min_keycode = min_keycode;
+        self.keysyms_per_keycode = reply.keysyms_per_keycode() as usize;
+        self.keysyms = reply.keysyms().to_vec();
+
+        let reply = self
+            .conn
+            .wait_for_reply(modifier_cookie)
+            .context("GetModifierMapping", String::new)?;
+        let mut num_lock = x::KeyButMask::empty();
+        let mut mode_switch = x::KeyButMask::empty();
+        let mut lock = LockMode::None;
+        let keycodes = reply.keycodes();
+        let keycodes_per_modifier = keycodes.len() / 8;
+        if keycodes_per_modifier > 0 {
+            for (modifier, keycodes) in keycodes.chunks(keycodes_per_modifier).enumerate() {
+                let mask = x::KeyButMask::from_bits_truncate(1 << modifier);
+                for &keycode in keycodes.iter().filter(|&&keycode| keycode != 0) {
+                    for &keysym in self.keysyms_of(keycode) {
+                        match keysym {
+                            XK_NUM_LOCK => num_lock |= mask,
+                            XK_MODE_SWITCH => mode_switch |= mask,
+                            XK_CAPS_LOCK if mask == x::KeyButMask::LOCK => {
+                                lock = LockMode::CapsLock;
+                            }
+                            XK_SHIFT_LOCK
+                                if mask == x::KeyButMask::LOCK && lock == LockMode::None =>
+                            {
+                                lock = LockMode::ShiftLock;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        self.num_lock = num_lock;
+        self.mode_switch = mode_switch;
+        self.lock = lock;
+        Ok(())
+    }
+
+    /// The keysyms of `keycode` in the mapping, without the normalization of
+    /// [KeySymbols::get_keysym].
+    fn keysyms_of(&self, keycode: Keycode) -> &[Keysym] {
+        let per = self.keysyms_per_keycode;
+        match keycode.checked_sub(self.min_keycode) {
+            Some(index) => {
+                let start = index as usize * per;
+                self.keysyms.get(start..start + per).unwrap_or(&[])
+            }
+            None => &[],
+        }
+    }
+
+    /// Fetches the mappings again if `event` notifies a change of the keyboard or of the
+    /// modifier mapping, and returns whether it did.
+    pub fn refresh(&mut self, event: &x::MappingNotifyEvent) -> Result<bool> {
+        match event.request() {
+            x::Mapping::Keyboard | x::Mapping::Modifier => {
+                self.fetch()?;
+                Ok(true)
+            }
+            x::Mapping::Pointer => Ok(false),
+        }
+    }
+
+    /// As [KeySymbols::refresh], if `event` is a `MappingNotify` event.
+    pub fn handle_event(&mut self, event: &Event) -> Result<bool> {
+        match event {
+            Event::X(x::Event::MappingNotify(ev)) => self.refresh(ev),
+            _ => Ok(false),
+        }
+    }
+
+    /// The keysym of `keycode` in the column `col` of the mapping, or `NO_SYMBOL`.
+    ///
+    /// The columns 0 and 1 are the group 1 of the key, the columns 2 and 3 its group 2.
+    /// As in the core protocol, a group with a single alphabetic keysym has its lowercase
+    /// and uppercase keysyms in its two columns, and an empty group 2 is the group 1.
+    pub fn get_keysym(&self, keycode: Keycode, col: usize) -> Keysym {
+        let keysyms = self.keysyms_of(keycode);
+        let mut per = keysyms.len();
+        let mut col = col;
+        if col >= per {
+            return NO_SYMBOL;
+        }
+        if col < 4 {
+            if col > 1 {
+                while per > 2 && keysyms[per - 1] == NO_SYMBOL {
+                    per -= 1;
+                }
+                if per < 3 {
+                    col -= 2;
+                }
+            }
+            if per <= (col | 1) || keysyms[col | 1] == NO_SYMBOL {
+                let (lower, upper) = convert_case(keysyms[col & !1]);
+                return if col & 1 == 0 {
+                    lower
+                } else if upper == lower {
+                    NO_SYMBOL
+                } else {
+                    upper
+                };
+            }
+        }
+        keysyms[col]
+    }
+
+    /// The keycodes that have `keysym` in any column of the mapping.
+    pub fn get_keycode(&self, keysym: Keysym) -> impl Iterator<Item = Keycode> + '_ {
+        let keycodes = self.keysyms.len() / self.keysyms_per_keycode.max(1);
+        (0..keycodes)
+            .map(move |i| self.min_keycode + i as u8)
+            .filter(move |&keycode| {
+                (0..self.keysyms_per_keycode).any(|col| self.get_keysym(keycode, col) == keysym)
+            })
+    }
+
+    /// The keysym of a key event, selected by its modifiers.
+    ///
+    /// `Mode_switch` selects the group 2 of the key. In the group, the first keysym is
+    /// selected without modifier, and the second one with `Shift`, or with `Lock` if it is
+    /// a `Shift_Lock`. A `Caps_Lock` uppercases the selected keysym. On the keypad,
+    /// `Num_Lock` inverts the effect of `Shift`.
+    pub fn press_lookup_keysym(&self, event: &x::KeyPressEvent) -> Keysym {
+        let state = event.state();
+        let col = if state.intersects(self.mode_switch) {
+            2
+        } else {
+            0
+        };
+        let keycode = event.detail();
+        let first = self.get_keysym(keycode, col);
+        let second = match self.get_keysym(keycode, col + 1) {
+            NO_SYMBOL => first,
+            keysym => keysym,
+        };
+
+        let shift = state.contains(x::KeyButMask::SHIFT);
+        let lock = if state.contains(x::KeyButMask::LOCK) {
+            self.lock
+        } else {
+            LockMode::None
+        };
+        if state.intersects(self.num_lock) && is_keypad(second) {
+            if shift || lock == LockMode::ShiftLock {
+                first
+            } else {
+                second
+            }
+        } else {
+            match (shift, lock) {
+                (false, LockMode::None) => first,
+                (false, LockMode::CapsLock) => convert_case(first).1,
+                (true, LockMode::CapsLock) => convert_case(second).1,
+                (true, _) | (false, LockMode::ShiftLock) => second,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_symbols() {
+        use crate::{mock, XidNew};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        const XK_KP_END: Keysym = 0xff9c;
+        const XK_KP_1: Keysym = 0xffb1;
+
+        // the keysyms of the keycodes from 8, 4 per keycode, and the modifiers
+        let keyboard: &[[Keysym; 4]] = &[
+            [0x61, 0, 0, 0],            // 8: a
+            [0x31, 0x21, 0, 0],         // 9: 1 exclam
+            [XK_KP_END, XK_KP_1, 0, 0], // 10
+            [XK_NUM_LOCK, 0, 0, 0],     // 11
+            [XK_MODE_SWITCH, 0, 0, 0],  // 12
+            [XK_CAPS_LOCK, 0, 0, 0],    // 13
+            [0x65, 0x45, 0xe9, 0xc9],   // 14: e E eacute Eacute
+            [0x61, 0x41, 0, 0],         // 15: a A
+            [0x0100_0439, 0, 0, 0],     // 16: Cyrillic short i
+        ];
+        // Shift, Lock, Control, Mod1, Mod2, Mod3, Mod4, Mod5
+        let modifiers = [0u8, 13, 0, 0, 11, 0, 0, 12];
+        let swapped = Arc::new(AtomicBool::new(false));
+        let conn = {
+            let swapped = swapped.clone();
+            mock::connect(move |req| match req.opcode {
+                // GetKeyboardMapping
+                101 => {
+                    assert_eq!(req.data[4], 8);
+                    let mut body = vec![0u8; 24];
+                    for i in 0..req.data[5] as usize {
+                        let mut keysyms = keyboard.get(i).copied().unwrap_or_default();
+                        if i == 0 && swapped.load(Ordering::SeqCst) {
+                            keysyms[0] = 0x62; // b
+                        }
+                        for keysym in keysyms {
+                            body.extend_from_slice(&keysym.to_ne_bytes());
+                        }
+                    }
+                    vec![mock::reply(req.seq, 4, &body)]
+                }
+                // GetModifierMapping
+                119 => {
+                    let mut body = vec![0u8; 24];
+                    body.extend_from_slice(&modifiers);
+                    vec![mock::reply(req.seq, 1, &body)]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let mut key_symbols = KeySymbols::new(&conn).unwrap();
+
+        assert_eq!(key_symbols.get_keysym(8, 0), 0x61);
+        assert_eq!(key_symbols.get_keysym(8, 1), 0x41);
+        // the empty group 2 is the group 1
+        assert_eq!(key_symbols.get_keysym(8, 2), 0x61);
+        assert_eq!(key_symbols.get_keysym(8, 3), 0x41);
+        assert_eq!(key_symbols.get_keysym(9, 1), 0x21);
+        assert_eq!(key_symbols.get_keysym(14, 3), 0xc9);
+        assert_eq!(key_symbols.get_keysym(16, 1), 0x0100_0419);
+        assert_eq!(key_symbols.get_keysym(9, 4), NO_SYMBOL);
+        assert_eq!(key_symbols.get_keysym(7, 0), NO_SYMBOL);
+        assert_eq!(key_symbols.get_keysym(200, 0), NO_SYMBOL);
+
+        assert_eq!(key_symbols.get_keycode(0x41).collect::<Vec<_>>(), [8, 15]);
+        assert_eq!(key_symbols.get_keycode(0xe9).collect::<Vec<_>>(), [14]);
+        assert_eq!(key_symbols.get_keycode(0x1234).count(), 0);
+
+        let press = |keycode: Keycode, state: x::KeyButMask| {
+            let window = unsafe { x::Window::new(mock::ROOT) };
+            let ev = x::KeyPressEvent::new(
+                keycode,
+                0,
+                window,
+                window,
+                x::WINDOW_NONE,
+                0,
+                0,
+                0,
+                0,
+                state,
+                true,
+            );
+            key_symbols.press_lookup_keysym(&ev)
+        };
+        let none = x::KeyButMask::empty();
+        let shift = x::KeyButMask::SHIFT;
+        let caps_lock = x::KeyButMask::LOCK;
+        let num_lock = x::KeyButMask::MOD2;
+        let mode_switch = x::KeyButMask::MOD5;
+        assert_eq!(press(8, none), 0x61);
+        assert_eq!(press(8, shift), 0x41);
+        assert_eq!(press(8, caps_lock), 0x41);
+        assert_eq!(press(9, none), 0x31);
+        assert_eq!(press(9, shift), 0x21);
+        // Caps_Lock only uppercases
+        assert_eq!(press(9, caps_lock), 0x31);
+        assert_eq!(press(10, none), XK_KP_END);
+        assert_eq!(press(10, num_lock), XK_KP_1);
+        assert_eq!(press(10, num_lock | shift), XK_KP_END);
+        assert_eq!(press(14, mode_switch), 0xe9);
+        assert_eq!(press(14, mode_switch | shift), 0xc9);
+        assert_eq!(press(14, mode_switch | caps_lock), 0xc9);
+        // no group 2
+        assert_eq!(press(9, mode_switch | shift), 0x21);
+        assert_eq!(press(16, shift), 0x0100_0419);
+
+        // the mappings are fetched again after a change of the keyboard mapping
+        swapped.store(true, Ordering::SeqCst);
+        let pointer = x::MappingNotifyEvent::new(x::Mapping::Pointer, 0, 0);
+        assert!(!key_symbols.refresh(&pointer).unwrap());
+        assert_eq!(key_symbols.get_keysym(8, 0), 0x61);
+        let keyboard = Event::X(x::Event::MappingNotify(x::MappingNotifyEvent::new(
+            x::Mapping::Keyboard,
+            8,
+            1,
+        )));
+        assert!(key_symbols.handle_event(&keyboard).unwrap());
+        assert_eq!(key_symbols.get_keysym(8, 0), 0x62);
+        assert_eq!(key_symbols.get_keysym(8, 1), 0x42);
+    }
+}
@@ -23,6 +23,10 @@ pub enum Lat1Error {
 /// expected to be Latin-1 encoded.
 /// Although the X strings are Latin-1, in reality ASCII can be
 /// expected without too much risk, hence all the ASCII related functions.
+/// Some names sent by the server are not ASCII though, such as the names of the
+/// Xv adaptors of some drivers that contain `®` (`0xAE`). [Lat1Str::to_utf8] and
+/// the `Display` implementation decode any string, and [Lat1Str::as_bytes]
+/// returns the bytes as sent. The ASCII functions fail or panic on such strings.
 ///
 /// This does not account for strings passed as raw bytes
 /// to [x::ChangeProperty](crate::x::ChangeProperty) (e.g. to set a window title).
@@ -109,6 +113,7 @@ impl Lat1Str {
     ///
     /// # Panics
     /// This function panics if the string contains non-ASCII chars.
+    /// [Lat1Str::to_utf8] converts any string.
     pub fn as_ascii(&self) -> &str {
         self.try_as_ascii().unwrap()
     }
@@ -119,7 +124,8 @@ impl Lat1Str {
     /// If the string contains non-ASCII characters, the returned string will be
     /// invalid UTF-8.
     pub unsafe fn as_ascii_unchecked(&self) -> &str {
-        str::from_utf8_unchecked(&self.data)
+        // SAFETY: the caller guarantees that the string is ASCII
+        unsafe { str::from_utf8_unchecked(&self.data) }
     }
 
     /// Returns the string converted to UTF-8.
@@ -242,6 +248,66 @@ impl<const N: usize> fmt::Debug for Lat1StrF<N> {
     }
 }
 
+/// The Latin-1 strings are serialized as UTF-8 strings.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Lat1Str {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_utf8())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Lat1String {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for Lat1StrF<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+/// Converts `str` to Latin-1, failing on the characters beyond `U+00FF`.
+#[cfg(feature = "serde")]
+fn deserialize_lat1<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    use serde::de::Error;
+    use std::convert::TryFrom;
+    let str = <std::borrow::Cow<'de, str> as serde::Deserialize>::deserialize(deserializer)?;
+    str.chars()
+        .map(|c| u8::try_from(c).map_err(|_| D::Error::custom(format!("{:?} is not Latin-1", c))))
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Lat1String {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_lat1(deserializer).map(|data| Lat1String { data })
+    }
+}
+
+/// The strings shorter than `N` are padded with zeros.
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for Lat1StrF<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let bytes = deserialize_lat1(deserializer)?;
+        if bytes.len() > N {
+            return Err(D::Error::invalid_length(
+                bytes.len(),
+                &"a string of at most N characters",
+            ));
+        }
+        let mut data = [0u8; N];
+        data[..bytes.len()].copy_from_slice(&bytes);
+        Ok(Lat1StrF { data })
+    }
+}
+
 #[test]
 fn test_latin_str() {
     let utf8 = "Mon frère est là.";
@@ -30,6 +30,8 @@
  * DEALINGS IN THE SOFTWARE.
  */
 
+#![deny(unsafe_op_in_unsafe_fn)]
+#![deny(non_snake_case)]
 #![allow(dead_code)]
 #![allow(unused_imports)]
 #![allow(unused_parens)]
@@ -261,6 +263,15 @@
 //! have side effects (`x::GetAtomName` requests) which can sometimes not be desirable.
 //! The feature should therefore only be activated when needed.
 //!
+//! ## `log`
+//!
+//! This feature emits records through the [`log`](https://docs.rs/log) crate facade:
+//! the opening and closing of connections and the extensions found (`xcb::connection` target,
+//! debug level), the requests sent with their sequence number (`xcb::request`, trace level),
+//! the protocol errors (`xcb::error`, error level) and the events dropped by a bounded
+//! event queue (`xcb::queue`, debug level).
+//! Nothing is formatted unless the level of the record is enabled.
+//!
 //! ## Extension features
 //!
 //! The following X extensions are activated by a cargo feature:
@@ -295,24 +306,101 @@
 //! | `TEST`                        | `xtest`       |
 //! | `XVideo`                      | `xv`          |
 //! | `XVideo-MotionCompensation`   | `xvmc`        |
+//!
+//! No extension is activated by default: the core protocol (the `x` module), `BIG-REQUESTS`
+//! and `XC-MISC` are always available.
+//! The dependencies between extensions are feature dependencies: e.g. `xv` activates `shm`.
+//! The `full` feature activates all the extensions.
+//! Only the modules and the C symbols of the activated extensions are compiled.
 
 mod base;
 mod error;
 mod event;
 mod ext;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
+mod idle;
 mod lat1_str;
+#[cfg(feature = "log")]
+mod logging;
+#[cfg(test)]
+mod mock;
+mod power;
+mod queue;
+mod raw;
+mod report;
+mod unchecked;
+mod worker;
+
+/// The misuses prevented by the API, checked as `compile_fail` doctests.
+#[cfg(doctest)]
+#[doc = include_str!("../tests/api/compile_fail.md")]
+struct ApiMisuse;
 
 pub use base::*;
 pub use error::*;
 pub use event::*;
 pub use ext::*;
+#[cfg(feature = "fuzzing")]
+pub use fuzzing::*;
+pub use idle::*;
 pub use lat1_str::*;
+pub use power::*;
+pub use queue::*;
+pub use raw::*;
+pub use report::*;
+pub use unchecked::*;
+pub use worker::*;
 
 pub mod x {
     //! The core X protocol definitions
 
     pub use super::xproto::*;
-}
+
+    mod atom;
+    mod colormap;
+    mod gc;
+    mod grab;
+    mod image;
+    mod pixel;
+    mod pointer;
+    mod points;
+    mod property;
+    mod window;
+
+    pub use atom::*;
+    pub use colormap::*;
+    pub use gc::*;
+    pub use grab::*;
+    pub use image::*;
+    pub use pixel::*;
+    pub use pointer::*;
+    pub use points::*;
+    pub use property::*;
+    pub use window::*;
+}
+
+pub mod bulk;
+#[cfg(feature = "compositor")]
+pub mod compositor;
+#[cfg(feature = "render")]
+pub mod cursor;
+pub mod embed;
+pub mod ewmh;
+pub mod focus;
+pub mod icccm;
+pub mod image;
+pub mod input;
+pub mod keysyms;
+pub mod managers;
+pub mod redraw;
+pub mod reparent;
+pub mod startup;
+pub mod surface;
+#[cfg(any(feature = "dri2", feature = "present"))]
+pub mod swap;
+pub mod text;
+pub mod xdnd;
 
 pub mod ffi {
     //! Module for Foreign Function Interface bindings.
@@ -514,6 +602,12 @@ pub mod dri2 {
     //! Accessible with the `dri2` cargo feature.
     #![allow(clippy::too_many_arguments)]
     include!(concat!(env!("OUT_DIR"), "/dri2.rs"));
+
+    mod cache;
+    mod swap;
+
+    pub use cache::*;
+    pub use swap::*;
 }
 
 #[cfg(feature = "dri3")]
@@ -552,6 +646,7 @@ pub mod xinput {
     #![allow(clippy::too_many_arguments)]
 
     #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum Device {
         All,
         AllMaster,
@@ -583,7 +678,7 @@ pub mod xinput {
         fn serialize(&self, wire_buf: &mut [u8]) -> usize {
             assert!(wire_buf.len() >= 2);
             unsafe {
-                *(wire_buf.as_mut_ptr() as *mut u16) = self.id();
+                std::ptr::write_unaligned(wire_buf.as_mut_ptr() as *mut u16, self.id());
             }
             2
         }
@@ -596,12 +691,19 @@ pub mod xinput {
         }
         unsafe fn unserialize(ptr: *const u8, params: Self::Params, offset: &mut usize) -> Self {
             *offset = 2;
-            let id = *(ptr as *const u16);
+            // SAFETY: the caller guarantees that `ptr` points to 2 readable bytes
+            let id = unsafe { std::ptr::read_unaligned(ptr as *const u16) };
             Device::from_id(id)
         }
     }
 
     include!(concat!(env!("OUT_DIR"), "/xinput.rs"));
+
+    mod fixed;
+    mod gesture;
+
+    pub use fixed::*;
+    pub use gesture::*;
 }
 
 #[cfg(feature = "present")]
@@ -611,6 +713,10 @@ pub mod present {
     //! Accessible with the `present` cargo feature.
     #![allow(clippy::unit_arg)]
     include!(concat!(env!("OUT_DIR"), "/present.rs"));
+
+    mod swap;
+
+    pub use swap::*;
 }
 
 #[cfg(feature = "randr")]
@@ -621,6 +727,8 @@ pub mod randr {
     #![allow(clippy::unit_arg)]
     #![allow(clippy::too_many_arguments)]
     include!(concat!(env!("OUT_DIR"), "/randr.rs"));
+
+    mod mode;
 }
 
 #[cfg(feature = "record")]
@@ -641,6 +749,10 @@ pub mod render {
     #![allow(unused_variables)]
     #![allow(clippy::unit_arg)]
     include!(concat!(env!("OUT_DIR"), "/render.rs"));
+
+    mod handle;
+
+    pub use handle::*;
 }
 
 #[cfg(feature = "res")]
@@ -705,6 +817,10 @@ pub mod xtest {
     //!
     //! Accessible with the `xtest` cargo feature.
     include!(concat!(env!("OUT_DIR"), "/xtest.rs"));
+
+    mod script;
+
+    pub use script::*;
 }
 
 #[cfg(feature = "xprint")]
@@ -777,6 +893,18 @@ pub mod xv {
     #![allow(clippy::unit_arg)]
     #![allow(clippy::too_many_arguments)]
     include!(concat!(env!("OUT_DIR"), "/xv.rs"));
+
+    mod attributes;
+    mod colorkey;
+    mod present;
+    mod stats;
+    mod upload;
+
+    pub use attributes::*;
+    pub use colorkey::*;
+    pub use present::*;
+    pub use stats::*;
+    pub use upload::UploadStrategy;
 }
 
 #[cfg(feature = "xvmc")]
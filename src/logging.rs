@@ -0,0 +1,168 @@
+//! Records emitted through the `log` crate facade, with the `log` feature.
+//!
+//! The call sites are all behind `#[cfg(feature = "log")]`, and the records that need some
+//! work before formatting are guarded by `log_enabled!`.
+
+use crate::error::ProtocolError;
+use crate::ext::{Extension, ExtensionData};
+
+use log::{debug, error, log_enabled, trace, Level};
+
+const CONNECTION: &str = "xcb::connection";
+const REQUEST: &str = "xcb::request";
+const ERROR: &str = "xcb::error";
+const QUEUE: &str = "xcb::queue";
+
+pub(crate) fn connection_opened(display_name: Option<&str>, screen: i32) {
+    debug!(
+        target: CONNECTION,
+        "connected to display {:?}, screen {}",
+        display_name.unwrap_or(""),
+        screen
+    );
+}
+
+pub(crate) fn connection_closed(display_name: Option<&str>) {
+    match display_name {
+        Some(name) => debug!(target: CONNECTION, "closing connection to display {:?}", name),
+        None => debug!(target: CONNECTION, "closing connection"),
+    }
+}
+
+pub(crate) fn extension_found(data: &ExtensionData) {
+    debug!(
+        target: CONNECTION,
+        "extension {} present: major opcode {}, first event {}, first error {}",
+        data.ext,
+        data.major_opcode,
+        data.first_event,
+        data.first_error
+    );
+}
+
+pub(crate) fn extension_missing(ext: Extension) {
+    debug!(target: CONNECTION, "optional extension {} not present", ext);
+}
+
+pub(crate) fn extension_version(name: &str, major: u32, minor: u32) {
+    debug!(
+        target: CONNECTION,
+        "extension {} negotiated version {}.{}", name, major, minor
+    );
+}
+
+/// Traces the request `R` sent with sequence number `seq`.
+pub(crate) fn request_sent<R>(seq: u64) {
+    if log_enabled!(target: REQUEST, Level::Trace) {
+        // named like in the errors, e.g. `x::MapWindow` for `xcb::xproto::MapWindow`
+        let name = std::any::type_name::<R>();
+        let name = name.strip_prefix("xcb::").unwrap_or(name);
+        match name.strip_prefix("xproto::") {
+            Some(name) => trace!(target: REQUEST, "x::{} (sequence {})", name, seq),
+            None => trace!(target: REQUEST, "{} (sequence {})", name, seq),
+        }
+    }
+}
+
+/// Records the error of code `error_code`, resolved to `err`, for `request` of sequence `seq`.
+pub(crate) fn protocol_error(err: &ProtocolError, request: Option<&str>, error_code: u8, seq: u32) {
+    let request = request.unwrap_or("unknown request");
+    error!(
+        target: ERROR,
+        "{} (sequence {}) failed with error code {}: {:?}", request, seq, error_code, err
+    );
+}
+
+pub(crate) fn event_dropped(code: u8) {
+    debug!(
+        target: QUEUE,
+        "event queue full: dropped event of code {}", code
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Record = (Level, String, String);
+
+    thread_local! {
+        static RECORDS: std::cell::RefCell<Vec<Record>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    /// Logger capturing the records of the xcb targets in the thread that emits them,
+    /// such that the tests running in parallel don't see each other's records.
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.target().starts_with("xcb::")
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            if self.enabled(record.metadata()) {
+                RECORDS.with(|records| {
+                    records.borrow_mut().push((
+                        record.level(),
+                        record.target().to_string(),
+                        record.args().to_string(),
+                    ))
+                });
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Runs `f` and returns the records it emitted.
+    fn capture(f: impl FnOnce()) -> Vec<Record> {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&Capture).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        RECORDS.with(|records| records.borrow_mut().clear());
+        f();
+        RECORDS.with(|records| std::mem::take(&mut *records.borrow_mut()))
+    }
+
+    #[test]
+    fn test_failed_checked_request() {
+        use crate::{mock, x, XidNew};
+
+        let records = capture(|| {
+            let conn = mock::connect(|req| match req.opcode {
+                // MapWindow
+                8 => vec![mock::error(req.seq, 3, req.u32_at(4), req.opcode)],
+                _ => mock::default_response(req),
+            });
+            let window = unsafe { x::Window::new(0x400) };
+            assert!(conn
+                .send_and_check_request(&x::MapWindow { window })
+                .is_err());
+        });
+
+        let errors: Vec<_> = records.iter().filter(|r| r.0 == Level::Error).collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].1, ERROR);
+        assert!(errors[0]
+            .2
+            .starts_with("x::MapWindow (sequence 1) failed with error code 3"));
+
+        assert!(records
+            .iter()
+            .any(|r| r.0 == Level::Trace && r.1 == REQUEST && r.2 == "x::MapWindow (sequence 1)"));
+        assert_eq!(records.last().unwrap().2, "closing connection");
+    }
+
+    #[test]
+    fn test_event_dropped() {
+        let records = capture(|| {
+            let conn = crate::queue::flood_connection();
+            conn.set_event_queue_limit(10, crate::OverflowPolicy::DropOldest);
+            crate::queue::flood(&conn);
+        });
+        let dropped = records.iter().filter(|r| r.1 == QUEUE).count();
+        assert_eq!(dropped, crate::queue::FLOOD_LEN - 8);
+    }
+}
@@ -0,0 +1,373 @@
+//! Discovery and acquisition of manager selections.
+//!
+//! Services such as window managers (`WM_S<n>`), compositing managers (`_NET_WM_CM_S<n>`)
+//! or system trays (`_NET_SYSTEM_TRAY_S<n>`) advertise themselves by owning a selection
+//! whose name is suffixed by the screen number, as described in the
+//! [ICCCM](https://x.org/releases/X11R7.6/doc/xorg-docs/specs/ICCCM/icccm.html#manager_selections).
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+use std::fmt;
+
+fn selection_owner(conn: &Connection, selection: x::Atom, name: &str) -> Result<x::Window> {
+    let cookie = conn.send_request(&x::GetSelectionOwner { selection });
+    Ok(conn
+        .wait_for_reply(cookie)
+        .context("GetSelectionOwner", || name.to_string())?
+        .owner())
+}
+
+fn selection_name(screen: i32, base_name: &str) -> String {
+    format!("{}_S{}", base_name, screen)
+}
+
+/// Returns the owner of the manager selection `base_name` of `screen`, if any.
+///
+/// `base_name` is the name of the selection without the screen suffix (e.g. `"_NET_WM_CM"`).
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// let (conn, screen_num) = xcb::Connection::connect(None)?;
+/// if xcb::managers::find(&conn, screen_num, "_NET_WM_CM")?.is_some() {
+///     println!("a compositing manager is running");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn find(conn: &Connection, screen: i32, base_name: &str) -> Result<Option<x::Window>> {
+    let name = selection_name(screen, base_name);
+    let selection = x::intern_atoms(conn, &[&name], false)?[0];
+    let owner = selection_owner(conn, selection, &name)?;
+    Ok(if owner.is_none() { None } else { Some(owner) })
+}
+
+/// Error returned by [acquire].
+#[derive(Debug)]
+pub enum AcquireError {
+    /// The selection is owned by another window.
+    AlreadyOwned(x::Window),
+    /// A request failed.
+    Xcb(crate::Error),
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcquireError::AlreadyOwned(owner) => write!(
+                f,
+                "manager selection already owned by window {:#x}",
+                owner.resource_id()
+            ),
+            AcquireError::Xcb(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AcquireError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AcquireError::AlreadyOwned(_) => None,
+            AcquireError::Xcb(err) => Some(err),
+        }
+    }
+}
+
+impl From<crate::Error> for AcquireError {
+    fn from(err: crate::Error) -> AcquireError {
+        AcquireError::Xcb(err)
+    }
+}
+
+/// A manager selection owned by this client, released when dropped.
+pub struct ManagerGuard<'a> {
+    conn: &'a Connection,
+    selection: x::Atom,
+    window: x::Window,
+    timestamp: x::Timestamp,
+}
+
+impl<'a> ManagerGuard<'a> {
+    /// The selection atom (e.g. `_NET_WM_CM_S0`).
+    pub fn selection(&self) -> x::Atom {
+        self.selection
+    }
+
+    /// The window that owns the selection.
+    pub fn window(&self) -> x::Window {
+        self.window
+    }
+
+    /// The timestamp of the acquisition.
+    pub fn timestamp(&self) -> x::Timestamp {
+        self.timestamp
+    }
+}
+
+impl<'a> fmt::Debug for ManagerGuard<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManagerGuard")
+            .field("selection", &self.selection)
+            .field("window", &self.window)
+            .field("timestamp", &self.timestamp)
+            .finish()
+    }
+}
+
+impl<'a> Drop for ManagerGuard<'a> {
+    fn drop(&mut self) {
+        // The acquisition timestamp is used such that the server ignores the release
+        // if another client acquired the selection in between.
+        self.conn.send_request(&x::SetSelectionOwner {
+            owner: x::Window::none(),
+            selection: self.selection,
+            time: self.timestamp,
+        });
+        let _ = self.conn.flush();
+    }
+}
+
+/// Acquires the manager selection `base_name` of `screen` for `window`.
+///
+/// The ICCCM acquisition protocol is followed:
+///  - the selection must not be owned already, otherwise [AcquireError::AlreadyOwned] is
+///    returned with the current owner,
+///  - `SetSelectionOwner` is sent, and the ownership is verified with `GetSelectionOwner` as
+///    another client may have acquired the selection in between,
+///  - the new manager is announced with a `MANAGER` client message sent to the root window
+///    of `screen`.
+///
+/// `timestamp` must be a real timestamp (e.g. from a `PropertyNotify` event), and not
+/// `x::CURRENT_TIME`, as required by the ICCCM.
+/// The selection is released when the returned [ManagerGuard] is dropped. The caller is
+/// responsible for answering the `SelectionClear` event sent to `window` when another
+/// client takes over the selection.
+pub fn acquire<'a>(
+    conn: &'a Connection,
+    screen: i32,
+    base_name: &str,
+    window: x::Window,
+    timestamp: x::Timestamp,
+) -> std::result::Result<ManagerGuard<'a>, AcquireError> {
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap_or_else(|| panic!("no screen {}", screen))
+        .root();
+    let name = selection_name(screen, base_name);
+
+    let atoms = x::intern_atoms(conn, &[&name, "MANAGER"], false)?;
+    let (selection, manager) = (atoms[0], atoms[1]);
+
+    let owner = selection_owner(conn, selection, &name)?;
+    if !owner.is_none() {
+        return Err(AcquireError::AlreadyOwned(owner));
+    }
+
+    conn.send_request(&x::SetSelectionOwner {
+        owner: window,
+        selection,
+        time: timestamp,
+    });
+    let owner = selection_owner(conn, selection, &name)?;
+    if owner != window {
+        return Err(AcquireError::AlreadyOwned(owner));
+    }
+    let guard = ManagerGuard {
+        conn,
+        selection,
+        window,
+        timestamp,
+    };
+
+    let event = x::ClientMessageEvent::new(
+        root,
+        manager,
+        x::ClientMessageData::Data32([
+            timestamp,
+            selection.resource_id(),
+            window.resource_id(),
+            0,
+            0,
+        ]),
+    );
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(root),
+        event_mask: x::EventMask::STRUCTURE_NOTIFY,
+        event: &event,
+    })
+    .context("SendEvent", || format!("MANAGER announce of {}", name))?;
+
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MockSelections {
+        atoms: Vec<String>,
+        /// owner and last change time of the selections, by atom
+        owners: std::collections::HashMap<u32, (u32, u32)>,
+        /// the client messages sent to the root window
+        announces: Vec<Vec<u8>>,
+        /// a window that acquires the selections right after any SetSelectionOwner
+        rival: Option<u32>,
+    }
+
+    /// Connects to a mock server whose selections are shared with the other connections
+    /// of `state`, similarly to a real server.
+    fn mock_connection(state: std::sync::Arc<std::sync::Mutex<MockSelections>>) -> Connection {
+        use crate::mock;
+        use std::convert::TryInto;
+
+        fn set_owner(state: &mut MockSelections, owner: u32, selection: u32, time: u32) {
+            let last = state.owners.get(&selection).map_or(0, |(_, t)| *t);
+            // the change is ignored if older than the last change
+            if time >= last {
+                state.owners.insert(selection, (owner, time));
+            }
+        }
+
+        mock::connect(move |req| {
+            let mut state = state.lock().unwrap();
+            match req.opcode {
+                16 => {
+                    let len = u16::from_ne_bytes(req.data[4..6].try_into().unwrap()) as usize;
+                    let name = std::str::from_utf8(&req.data[8..8 + len]).unwrap();
+                    let index = match state.atoms.iter().position(|a| a == name) {
+                        Some(index) => index,
+                        None => {
+                            state.atoms.push(name.to_string());
+                            state.atoms.len() - 1
+                        }
+                    };
+                    let atom = 300 + index as u32;
+                    vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+                }
+                // SetSelectionOwner
+                22 => {
+                    let (owner, selection, time) = (req.u32_at(4), req.u32_at(8), req.u32_at(12));
+                    set_owner(&mut state, owner, selection, time);
+                    if let Some(rival) = state.rival {
+                        set_owner(&mut state, rival, selection, time + 1);
+                    }
+                    vec![]
+                }
+                // GetSelectionOwner
+                23 => {
+                    let owner = state.owners.get(&req.u32_at(4)).map_or(0, |(o, _)| *o);
+                    vec![mock::reply(req.seq, 0, &owner.to_ne_bytes())]
+                }
+                // SendEvent
+                25 => {
+                    assert_eq!(req.data[1], 0); // propagate
+                    assert_eq!(req.u32_at(4), mock::ROOT);
+                    assert_eq!(req.u32_at(8), x::EventMask::STRUCTURE_NOTIFY.bits());
+                    state.announces.push(req.data[12..44].to_vec());
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            }
+        })
+    }
+
+    #[test]
+    fn test_acquire_and_announce() {
+        use crate::XidNew;
+        use std::convert::TryInto;
+        use std::sync::{Arc, Mutex};
+
+        let state = Arc::new(Mutex::new(MockSelections::default()));
+        let conn = mock_connection(state.clone());
+        let window = unsafe { x::Window::new(0x200001) };
+
+        assert_eq!(find(&conn, 0, "_NET_WM_CM").unwrap(), None);
+
+        let guard = acquire(&conn, 0, "_NET_WM_CM", window, 1000).unwrap();
+        assert_eq!(find(&conn, 0, "_NET_WM_CM").unwrap(), Some(window));
+
+        {
+            let state = state.lock().unwrap();
+            // _NET_WM_CM_S0 was interned first by `find`
+            assert_eq!(state.atoms, ["_NET_WM_CM_S0", "MANAGER"]);
+            assert_eq!(state.announces.len(), 1);
+
+            let ev = &state.announces[0];
+            let u32_at =
+                |offset: usize| u32::from_ne_bytes(ev[offset..offset + 4].try_into().unwrap());
+            assert_eq!(ev[0], 33); // ClientMessage
+            assert_eq!(ev[1], 32); // format
+            assert_eq!(u32_at(4), crate::mock::ROOT);
+            assert_eq!(u32_at(8), 301); // MANAGER
+            assert_eq!(u32_at(12), 1000); // timestamp
+            assert_eq!(u32_at(16), guard.selection().resource_id());
+            assert_eq!(u32_at(20), 0x200001);
+            assert_eq!((u32_at(24), u32_at(28)), (0, 0));
+        }
+
+        drop(guard);
+        assert_eq!(find(&conn, 0, "_NET_WM_CM").unwrap(), None);
+    }
+
+    #[test]
+    fn test_acquire_two_connections() {
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let state = Arc::new(Mutex::new(MockSelections::default()));
+        let first = mock_connection(state.clone());
+        let second = mock_connection(state.clone());
+        let first_window = unsafe { x::Window::new(0x200001) };
+        let second_window = unsafe { x::Window::new(0x400001) };
+
+        let guard = acquire(&first, 0, "WM", first_window, 1000).unwrap();
+        match acquire(&second, 0, "WM", second_window, 2000) {
+            Err(AcquireError::AlreadyOwned(owner)) => assert_eq!(owner, first_window),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(find(&second, 0, "WM").unwrap(), Some(first_window));
+
+        // released on drop, the second connection can acquire
+        drop(guard);
+        first
+            .wait_for_reply(first.send_request(&x::GetInputFocus {}))
+            .unwrap();
+        let guard = acquire(&second, 0, "WM", second_window, 2000).unwrap();
+        assert_eq!(find(&first, 0, "WM").unwrap(), Some(second_window));
+        assert_eq!(state.lock().unwrap().announces.len(), 2);
+
+        // a stale release (with an older timestamp) does not steal the selection
+        first.send_request(&x::SetSelectionOwner {
+            owner: x::Window::none(),
+            selection: guard.selection(),
+            time: 1000,
+        });
+        assert_eq!(find(&first, 0, "WM").unwrap(), Some(second_window));
+    }
+
+    #[test]
+    fn test_acquire_race() {
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        // another client acquires the selection between SetSelectionOwner and the verification
+        let state = Arc::new(Mutex::new(MockSelections {
+            rival: Some(0x600001),
+            ..Default::default()
+        }));
+        let conn = mock_connection(state.clone());
+        let window = unsafe { x::Window::new(0x200001) };
+
+        match acquire(&conn, 0, "_NET_SYSTEM_TRAY", window, 1000) {
+            Err(AcquireError::AlreadyOwned(owner)) => assert_eq!(owner.resource_id(), 0x600001),
+            other => panic!("unexpected {:?}", other),
+        }
+        // the lost acquisition is not announced
+        assert!(state.lock().unwrap().announces.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+//! A minimal in-process X server used to unit-test code that needs a `Connection`.
+//!
+//! The server speaks just enough of the protocol for libxcb to accept the connection:
+//! it answers the setup with a single screen, and then hands each request
+//! to a user supplied handler that returns the raw responses (replies, errors or events)
+//! to write back to the client.
+
+use crate::base::{align_pad, Connection};
+use crate::ext::Extension;
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::os::unix::io::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+/// A request received by the mock server.
+#[derive(Debug)]
+pub(crate) struct MockRequest {
+    /// The sequence number of the request (as computed by the server).
+    pub seq: u16,
+    /// The major opcode.
+    pub opcode: u8,
+    /// The complete request data, including the 4 bytes header (8 bytes for the requests
+    /// sent with BIG-REQUESTS).
+    pub data: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_ne_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+}
+
+/// Default handling of the requests that libxcb issues on its own behalf.
+///
+/// `GetInputFocus` is used by libxcb to synchronize checked void requests.
+pub(crate) fn default_response(req: &MockRequest) -> Vec<Vec<u8>> {
+    match req.opcode {
+        43 => vec![reply(req.seq, 0, &[])],
+        _ => vec![],
+    }
+}
+
+/// Builds a reply to the request of sequence `seq`.
+///
+/// `body` is the data following the 8 bytes header, it is padded to 24 bytes minimum
+/// and to a multiple of 4 bytes.
+pub(crate) fn reply(seq: u16, data: u8, body: &[u8]) -> Vec<u8> {
+    let mut body = body.to_vec();
+    if body.len() < 24 {
+        body.resize(24, 0);
+    }
+    body.resize(body.len() + align_pad(body.len(), 4), 0);
+    let length = (body.len() - 24) / 4;
+
+    let mut buf = vec![1, data];
+    buf.extend_from_slice(&seq.to_ne_bytes());
+    buf.extend_from_slice(&(length as u32).to_ne_bytes());
+    buf.extend_from_slice(&body);
+    buf
+}
+
+/// Builds a reply to `QueryExtension` that reports the extension as present.
+pub(crate) fn query_extension_reply(
+    req: &MockRequest,
+    major_opcode: u8,
+    first_event: u8,
+    first_error: u8,
+) -> Vec<u8> {
+    reply(req.seq, 0, &[1, major_opcode, first_event, first_error])
+}
+
+/// Returns the extension name of a `QueryExtension` request.
+pub(crate) fn extension_name(req: &MockRequest) -> &[u8] {
+    let len = u16::from_ne_bytes([req.data[4], req.data[5]]) as usize;
+    &req.data[8..8 + len]
+}
+
+/// Builds an error for the request of sequence `seq`.
+pub(crate) fn error(seq: u16, code: u8, bad_value: u32, major_opcode: u8) -> Vec<u8> {
+    let mut buf = vec![0, code];
+    buf.extend_from_slice(&seq.to_ne_bytes());
+    buf.extend_from_slice(&bad_value.to_ne_bytes());
+    buf.extend_from_slice(&0u16.to_ne_bytes());
+    buf.push(major_opcode);
+    buf.resize(32, 0);
+    buf
+}
+
+/// Connects to a mock server that handles the requests with `handler`.
+///
+/// The server runs in its own thread, which exits when the connection is dropped.
+pub(crate) fn connect<F>(handler: F) -> Connection
+where
+    F: FnMut(&MockRequest) -> Vec<Vec<u8>> + Send + 'static,
+{
+    Connection::connect_to_fd(serve(handler).into_raw_fd(), None).unwrap()
+}
+
+/// Like [connect], and caches the data of the mandatory `extensions`.
+///
+/// `handler` must report the extensions as present in response to the `QueryExtension`
+/// requests issued by libxcb during the connection (see [extension_name]).
+pub(crate) fn connect_with_extensions<F>(handler: F, extensions: &[Extension]) -> Connection
+where
+    F: FnMut(&MockRequest) -> Vec<Vec<u8>> + Send + 'static,
+{
+    let fd = serve(handler).into_raw_fd();
+    Connection::connect_to_fd_with_extensions(fd, None, extensions, &[]).unwrap()
+}
+
+fn serve<F>(mut handler: F) -> UnixStream
+where
+    F: FnMut(&MockRequest) -> Vec<Vec<u8>> + Send + 'static,
+{
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    thread::spawn(move || {
+        if setup(&mut server).is_err() {
+            return;
+        }
+        let mut seq = 0u16;
+        loop {
+            let mut header = [0u8; 4];
+            if server.read_exact(&mut header).is_err() {
+                return;
+            }
+            let mut data = header.to_vec();
+            let mut len = u16::from_ne_bytes([header[2], header[3]]) as usize * 4;
+            if len == 0 {
+                // BIG-REQUESTS: the length follows the header
+                let mut big_len = [0u8; 4];
+                if server.read_exact(&mut big_len).is_err() {
+                    return;
+                }
+                data.extend_from_slice(&big_len);
+                len = u32::from_ne_bytes(big_len) as usize * 4;
+            }
+            let read = data.len();
+            data.resize(len.max(read), 0);
+            if server.read_exact(&mut data[read..]).is_err() {
+                return;
+            }
+            seq = seq.wrapping_add(1);
+            let req = MockRequest {
+                seq,
+                opcode: header[0],
+                data,
+            };
+            for resp in handler(&req) {
+                if server.write_all(&resp).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    client
+}
+
+/// The root window of the only screen of the mock server.
+pub(crate) const ROOT: u32 = 0x100;
+
+/// A 1920x1080 screen without any depth.
+fn screen() -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&ROOT.to_ne_bytes());
+    buf.extend_from_slice(&0x20u32.to_ne_bytes()); // default colormap
+    buf.extend_from_slice(&0x00ff_ffffu32.to_ne_bytes()); // white pixel
+    buf.extend_from_slice(&0u32.to_ne_bytes()); // black pixel
+    buf.extend_from_slice(&0u32.to_ne_bytes()); // current input masks
+    for v in [1920u16, 1080, 508, 286, 1, 1] {
+        buf.extend_from_slice(&v.to_ne_bytes()); // size in pixels and mm, min and max maps
+    }
+    buf.extend_from_slice(&0x21u32.to_ne_bytes()); // root visual
+    buf.extend_from_slice(&[0, 0, 24, 0]); // backing stores, save unders, root depth, depths
+    buf
+}
+
+fn setup(server: &mut UnixStream) -> std::io::Result<()> {
+    let mut req = [0u8; 12];
+    server.read_exact(&mut req)?;
+    let name_len = u16::from_ne_bytes([req[6], req[7]]) as usize;
+    let data_len = u16::from_ne_bytes([req[8], req[9]]) as usize;
+    let auth_len = name_len + align_pad(name_len, 4) + data_len + align_pad(data_len, 4);
+    let mut auth = vec![0u8; auth_len];
+    server.read_exact(&mut auth)?;
+
+    // success, protocol 11.0, 24 words of additional data
+    let mut buf = vec![1, 0];
+    buf.extend_from_slice(&11u16.to_ne_bytes());
+    buf.extend_from_slice(&0u16.to_ne_bytes());
+    buf.extend_from_slice(&24u16.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes()); // release number
+    buf.extend_from_slice(&0x0020_0000u32.to_ne_bytes()); // resource id base
+    buf.extend_from_slice(&0x001f_ffffu32.to_ne_bytes()); // resource id mask
+    buf.extend_from_slice(&0u32.to_ne_bytes()); // motion buffer size
+    buf.extend_from_slice(&0u16.to_ne_bytes()); // vendor len
+    buf.extend_from_slice(&0xffffu16.to_ne_bytes()); // maximum request length
+    buf.extend_from_slice(&[1, 3, 0, 0, 32, 32, 8, 255]); // roots, formats, orders, keycodes
+    buf.extend_from_slice(&[0; 4]);
+    for (depth, bits_per_pixel) in [(1, 1), (24, 32), (32, 32)] {
+        // the pixmap formats, with a scanline pad of 32 bits
+        buf.extend_from_slice(&[depth, bits_per_pixel, 32, 0, 0, 0, 0, 0]);
+    }
+    buf.extend_from_slice(&screen());
+    server.write_all(&buf)
+}
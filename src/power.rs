@@ -0,0 +1,557 @@
+//! Snapshot of the screen saver and display power management state.
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+#[cfg(feature = "dpms")]
+use crate::dpms;
+use crate::raw::ExtensionInfo;
+#[cfg(feature = "screensaver")]
+use crate::screensaver;
+use crate::x;
+
+use std::fmt;
+
+/// A core protocol preference, such as `x::Blanking` or `x::Exposures`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Preference {
+    No,
+    Yes,
+    /// The server default.
+    Default,
+}
+
+impl fmt::Display for Preference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Preference::No => "no",
+            Preference::Yes => "yes",
+            Preference::Default => "default",
+        })
+    }
+}
+
+/// The screen saver settings of the core protocol, as returned by `x::GetScreenSaver`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScreenSaverSettings {
+    /// Seconds of inactivity before the screen saver activates, 0 if disabled.
+    pub timeout: u16,
+    /// Seconds between the changes of the screen saver pattern.
+    pub interval: u16,
+    pub prefer_blanking: Preference,
+    pub allow_exposures: Preference,
+}
+
+/// The state of the screen saver of the `MIT-SCREEN-SAVER` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScreenSaverState {
+    Off,
+    On,
+    Cycle,
+    Disabled,
+}
+
+/// The kind of screen saver of the `MIT-SCREEN-SAVER` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScreenSaverKind {
+    Blanked,
+    Internal,
+    External,
+}
+
+/// The state of the screen saver, as returned by `screensaver::QueryInfo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScreenSaverInfo {
+    pub state: ScreenSaverState,
+    pub kind: ScreenSaverKind,
+    /// Milliseconds until the screen saver activates if it is off,
+    /// or since it activated if it is on.
+    pub ms_until_or_since: u32,
+    pub ms_since_user_input: u32,
+}
+
+/// A DPMS power level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PowerLevel {
+    On,
+    Standby,
+    Suspend,
+    Off,
+}
+
+/// The state of the `DPMS` extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DpmsInfo {
+    /// Whether the monitor supports power management.
+    pub capable: bool,
+    /// Whether power management is enabled.
+    pub enabled: bool,
+    pub power_level: PowerLevel,
+    /// Seconds of inactivity before entering standby mode, 0 if disabled.
+    pub standby_timeout: u16,
+    /// Seconds of inactivity before entering suspend mode, 0 if disabled.
+    pub suspend_timeout: u16,
+    /// Seconds of inactivity before turning the monitor off, 0 if disabled.
+    pub off_timeout: u16,
+}
+
+/// The EWMH state relevant to idle inhibition.
+///
+/// Many window managers and desktop environments inhibit the screen saver while
+/// the active window is full screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NetWmState {
+    /// The `_NET_ACTIVE_WINDOW` property of the root window, if set and not `None`.
+    pub active_window: Option<u32>,
+    /// Whether `_NET_WM_STATE_FULLSCREEN` is in the `_NET_WM_STATE` of the active window.
+    pub active_window_fullscreen: bool,
+}
+
+/// A snapshot of the screen saver and display power management state, as returned by
+/// [Connection::display_power_snapshot].
+///
+/// Each section other than the core one is `None` if the extension is not present
+/// on the server, or if the cargo feature of the extension is not active.
+/// With the `serde` cargo feature, the snapshot is serializable.
+///
+/// The [fmt::Display] implementation formats a report meant for diagnostics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerSnapshot {
+    pub screen_saver: ScreenSaverSettings,
+    /// From the `MIT-SCREEN-SAVER` extension (`screensaver` cargo feature).
+    pub screen_saver_info: Option<ScreenSaverInfo>,
+    /// From the `DPMS` extension (`dpms` cargo feature).
+    pub dpms: Option<DpmsInfo>,
+    /// `None` if the window manager doesn't support `_NET_ACTIVE_WINDOW`.
+    pub net_wm: Option<NetWmState>,
+}
+
+fn seconds(secs: u16) -> String {
+    if secs == 0 {
+        "disabled".to_string()
+    } else {
+        format!("{} s", secs)
+    }
+}
+
+impl fmt::Display for PowerSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ss = &self.screen_saver;
+        writeln!(f, "core screen saver:")?;
+        writeln!(f, "  timeout: {}", seconds(ss.timeout))?;
+        writeln!(f, "  interval: {}", seconds(ss.interval))?;
+        writeln!(f, "  prefer blanking: {}", ss.prefer_blanking)?;
+        writeln!(f, "  allow exposures: {}", ss.allow_exposures)?;
+
+        match &self.screen_saver_info {
+            Some(info) => {
+                writeln!(f, "MIT-SCREEN-SAVER:")?;
+                writeln!(f, "  state: {:?}", info.state)?;
+                writeln!(f, "  kind: {:?}", info.kind)?;
+                let until_or_since = if info.state == ScreenSaverState::On {
+                    "since activation"
+                } else {
+                    "until activation"
+                };
+                writeln!(f, "  {}: {} ms", until_or_since, info.ms_until_or_since)?;
+                writeln!(f, "  since user input: {} ms", info.ms_since_user_input)?;
+            }
+            None => writeln!(f, "MIT-SCREEN-SAVER: not available")?,
+        }
+
+        match &self.dpms {
+            Some(dpms) => {
+                writeln!(f, "DPMS:")?;
+                writeln!(f, "  capable: {}", dpms.capable)?;
+                writeln!(f, "  enabled: {}", dpms.enabled)?;
+                writeln!(f, "  power level: {:?}", dpms.power_level)?;
+                writeln!(f, "  standby timeout: {}", seconds(dpms.standby_timeout))?;
+                writeln!(f, "  suspend timeout: {}", seconds(dpms.suspend_timeout))?;
+                writeln!(f, "  off timeout: {}", seconds(dpms.off_timeout))?;
+            }
+            None => writeln!(f, "DPMS: not available")?,
+        }
+
+        match &self.net_wm {
+            Some(net_wm) => {
+                writeln!(f, "window manager:")?;
+                match net_wm.active_window {
+                    Some(window) => writeln!(f, "  active window: {:#x}", window)?,
+                    None => writeln!(f, "  active window: none")?,
+                }
+                write!(
+                    f,
+                    "  active window fullscreen: {}",
+                    net_wm.active_window_fullscreen
+                )
+            }
+            None => write!(f, "window manager: _NET_ACTIVE_WINDOW not supported"),
+        }
+    }
+}
+
+fn screen_saver_settings(reply: &x::GetScreenSaverReply) -> ScreenSaverSettings {
+    ScreenSaverSettings {
+        timeout: reply.timeout(),
+        interval: reply.interval(),
+        prefer_blanking: match reply.prefer_blanking() {
+            x::Blanking::NotPreferred => Preference::No,
+            x::Blanking::Preferred => Preference::Yes,
+            x::Blanking::Default => Preference::Default,
+        },
+        allow_exposures: match reply.allow_exposures() {
+            x::Exposures::NotAllowed => Preference::No,
+            x::Exposures::Allowed => Preference::Yes,
+            x::Exposures::Default => Preference::Default,
+        },
+    }
+}
+
+#[cfg(feature = "screensaver")]
+fn screen_saver_info(conn: &Connection, root: x::Window) -> Result<Option<ScreenSaverInfo>> {
+    if ExtensionInfo::query(conn, screensaver::XNAME)?.is_none() {
+        return Ok(None);
+    }
+    let cookie = conn.send_request(&screensaver::QueryInfo {
+        drawable: x::Drawable::Window(root),
+    });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("QueryInfo", || screensaver::XNAME)?;
+    Ok(Some(ScreenSaverInfo {
+        state: match reply.state() {
+            0 => ScreenSaverState::Off,
+            1 => ScreenSaverState::On,
+            2 => ScreenSaverState::Cycle,
+            _ => ScreenSaverState::Disabled,
+        },
+        kind: match reply.kind() {
+            screensaver::Kind::Blanked => ScreenSaverKind::Blanked,
+            screensaver::Kind::Internal => ScreenSaverKind::Internal,
+            screensaver::Kind::External => ScreenSaverKind::External,
+        },
+        ms_until_or_since: reply.ms_until_server(),
+        ms_since_user_input: reply.ms_since_user_input(),
+    }))
+}
+
+#[cfg(not(feature = "screensaver"))]
+fn screen_saver_info(_conn: &Connection, _root: x::Window) -> Result<Option<ScreenSaverInfo>> {
+    Ok(None)
+}
+
+#[cfg(feature = "dpms")]
+fn dpms_info(conn: &Connection) -> Result<Option<DpmsInfo>> {
+    if ExtensionInfo::query(conn, dpms::XNAME)?.is_none() {
+        return Ok(None);
+    }
+    let capable = conn.send_request(&dpms::Capable {});
+    let timeouts = conn.send_request(&dpms::GetTimeouts {});
+    let info = conn.send_request(&dpms::Info {});
+    let capable = conn
+        .wait_for_reply(capable)
+        .context("Capable", || dpms::XNAME)?;
+    let timeouts = conn
+        .wait_for_reply(timeouts)
+        .context("GetTimeouts", || dpms::XNAME)?;
+    let info = conn.wait_for_reply(info).context("Info", || dpms::XNAME)?;
+    Ok(Some(DpmsInfo {
+        capable: capable.capable(),
+        enabled: info.state(),
+        power_level: match info.power_level() {
+            dpms::DpmsMode::On => PowerLevel::On,
+            dpms::DpmsMode::Standby => PowerLevel::Standby,
+            dpms::DpmsMode::Suspend => PowerLevel::Suspend,
+            dpms::DpmsMode::Off => PowerLevel::Off,
+        },
+        standby_timeout: timeouts.standby_timeout(),
+        suspend_timeout: timeouts.suspend_timeout(),
+        off_timeout: timeouts.off_timeout(),
+    }))
+}
+
+#[cfg(not(feature = "dpms"))]
+fn dpms_info(_conn: &Connection) -> Result<Option<DpmsInfo>> {
+    Ok(None)
+}
+
+fn net_wm_state(conn: &Connection, root: x::Window) -> Result<Option<NetWmState>> {
+    let intern = |name: &'static str| {
+        let cookie = conn.send_request(&x::InternAtom {
+            only_if_exists: true,
+            name: name.as_bytes(),
+        });
+        (name, cookie)
+    };
+    let atoms = vec![
+        intern("_NET_ACTIVE_WINDOW"),
+        intern("_NET_WM_STATE"),
+        intern("_NET_WM_STATE_FULLSCREEN"),
+    ];
+    let mut values = [x::ATOM_NONE; 3];
+    for ((name, cookie), atom) in atoms.into_iter().zip(values.iter_mut()) {
+        *atom = conn
+            .wait_for_reply(cookie)
+            .context("InternAtom", || name)?
+            .atom();
+    }
+    let [active_window, wm_state, fullscreen] = values;
+    if active_window == x::ATOM_NONE {
+        return Ok(None);
+    }
+
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window: root,
+        property: active_window,
+        r#type: x::ATOM_WINDOW,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("GetProperty", || "_NET_ACTIVE_WINDOW")?;
+    let active_window = if reply.format() == 32 {
+        reply.value::<x::Window>().first().copied()
+    } else {
+        None
+    }
+    .filter(|w| !w.is_none());
+
+    let active_window_fullscreen = match active_window {
+        Some(window) if wm_state != x::ATOM_NONE && fullscreen != x::ATOM_NONE => {
+            let cookie = conn.send_request(&x::GetProperty {
+                delete: false,
+                window,
+                property: wm_state,
+                r#type: x::ATOM_ATOM,
+                long_offset: 0,
+                long_length: 64,
+            });
+            match conn.wait_for_reply(cookie) {
+                Ok(reply) => reply.format() == 32 && reply.value::<x::Atom>().contains(&fullscreen),
+                // the window was destroyed in the meantime
+                Err(crate::Error::Protocol(crate::ProtocolError::X(x::Error::Window(_), _))) => {
+                    false
+                }
+                Err(err) => return Err(err).context("GetProperty", || "_NET_WM_STATE"),
+            }
+        }
+        _ => false,
+    };
+
+    Ok(Some(NetWmState {
+        active_window: active_window.map(|w| w.resource_id()),
+        active_window_fullscreen,
+    }))
+}
+
+impl Connection {
+    /// Gathers the screen saver, blanking and display power management state of the server,
+    /// for the screen `screen_num`.
+    ///
+    /// The core screen saver settings are always present. The extension sections are
+    /// queried only if the extension is present on the server, otherwise they are `None`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// let (conn, screen_num) = xcb::Connection::connect(None)?;
+    /// let snapshot = conn.display_power_snapshot(screen_num)?;
+    /// println!("{}", snapshot);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `screen_num` is not a screen of the display.
+    pub fn display_power_snapshot(&self, screen_num: i32) -> Result<PowerSnapshot> {
+        let root = self
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .unwrap_or_else(|| panic!("no screen {}", screen_num))
+            .root();
+
+        let cookie = self.send_request(&x::GetScreenSaver {});
+        let reply = self
+            .wait_for_reply(cookie)
+            .context("GetScreenSaver", String::new)?;
+
+        Ok(PowerSnapshot {
+            screen_saver: screen_saver_settings(&reply),
+            screen_saver_info: screen_saver_info(self, root)?,
+            dpms: dpms_info(self)?,
+            net_wm: net_wm_state(self, root)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mock server with the screen saver settings of the X.Org server, and the extensions
+    /// in `extensions` (`"DPMS"` with opcode 140 and `"MIT-SCREEN-SAVER"` with opcode 141).
+    ///
+    /// If `active_window` is not 0, it is the `_NET_ACTIVE_WINDOW`, and it is full screen.
+    fn connect(extensions: &'static [&'static [u8]], active_window: u32) -> Connection {
+        use crate::mock;
+
+        const NET_ACTIVE_WINDOW: u32 = 500;
+        const NET_WM_STATE: u32 = 501;
+        const NET_WM_STATE_FULLSCREEN: u32 = 502;
+
+        mock::connect(move |req| match req.opcode {
+            // InternAtom: the atoms exist only with a window manager
+            16 => {
+                let len = u16::from_ne_bytes([req.data[4], req.data[5]]) as usize;
+                let atom = match (active_window, &req.data[8..8 + len]) {
+                    (0, _) => 0,
+                    (_, b"_NET_ACTIVE_WINDOW") => NET_ACTIVE_WINDOW,
+                    (_, b"_NET_WM_STATE") => NET_WM_STATE,
+                    (_, b"_NET_WM_STATE_FULLSCREEN") => NET_WM_STATE_FULLSCREEN,
+                    _ => 0,
+                };
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            // GetProperty
+            20 => {
+                let (ty, value) = match req.u32_at(8) {
+                    NET_ACTIVE_WINDOW => (x::ATOM_WINDOW.resource_id(), active_window),
+                    NET_WM_STATE => (x::ATOM_ATOM.resource_id(), NET_WM_STATE_FULLSCREEN),
+                    _ => unreachable!(),
+                };
+                let mut body = ty.to_ne_bytes().to_vec();
+                body.extend_from_slice(&0u32.to_ne_bytes());
+                body.extend_from_slice(&1u32.to_ne_bytes());
+                body.resize(24, 0);
+                body.extend_from_slice(&value.to_ne_bytes());
+                vec![mock::reply(req.seq, 32, &body)]
+            }
+            98 => match mock::extension_name(req) {
+                b"DPMS" if extensions.contains(&&b"DPMS"[..]) => {
+                    vec![mock::query_extension_reply(req, 140, 0, 0)]
+                }
+                b"MIT-SCREEN-SAVER" if extensions.contains(&&b"MIT-SCREEN-SAVER"[..]) => {
+                    vec![mock::query_extension_reply(req, 141, 90, 0)]
+                }
+                _ => vec![mock::reply(req.seq, 0, &[])],
+            },
+            // GetScreenSaver
+            108 => {
+                let mut body = Vec::new();
+                body.extend_from_slice(&600u16.to_ne_bytes());
+                body.extend_from_slice(&600u16.to_ne_bytes());
+                body.extend_from_slice(&[1, 1]);
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            // dpms::Capable, dpms::GetTimeouts and dpms::Info
+            140 => match req.data[1] {
+                1 => vec![mock::reply(req.seq, 0, &[1])],
+                2 => {
+                    let mut body = Vec::new();
+                    for v in [600u16, 0, 1200] {
+                        body.extend_from_slice(&v.to_ne_bytes());
+                    }
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                7 => {
+                    let mut body = 0u16.to_ne_bytes().to_vec();
+                    body.push(1);
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                _ => unreachable!(),
+            },
+            // screensaver::QueryInfo
+            141 if req.data[1] == 1 => {
+                let mut body = Vec::new();
+                for v in [0x400u32, 5000, 1000, 0] {
+                    body.extend_from_slice(&v.to_ne_bytes());
+                }
+                // kind: External
+                body.push(2);
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    #[test]
+    fn test_power_snapshot_core_only() {
+        let conn = connect(&[], 0);
+        let snapshot = conn.display_power_snapshot(0).unwrap();
+        assert_eq!(
+            snapshot.screen_saver,
+            ScreenSaverSettings {
+                timeout: 600,
+                interval: 600,
+                prefer_blanking: Preference::Yes,
+                allow_exposures: Preference::Yes,
+            }
+        );
+        assert_eq!(snapshot.screen_saver_info, None);
+        assert_eq!(snapshot.dpms, None);
+        assert_eq!(snapshot.net_wm, None);
+
+        let report = snapshot.to_string();
+        assert!(report.contains("timeout: 600 s"));
+        assert!(report.contains("DPMS: not available"));
+        assert!(report.contains("MIT-SCREEN-SAVER: not available"));
+    }
+
+    #[test]
+    fn test_power_snapshot_full() {
+        let conn = connect(&[b"DPMS", b"MIT-SCREEN-SAVER"], 0x600);
+        let snapshot = conn.display_power_snapshot(0).unwrap();
+
+        #[cfg(feature = "dpms")]
+        assert_eq!(
+            snapshot.dpms,
+            Some(DpmsInfo {
+                capable: true,
+                enabled: true,
+                power_level: PowerLevel::On,
+                standby_timeout: 600,
+                suspend_timeout: 0,
+                off_timeout: 1200,
+            })
+        );
+        #[cfg(not(feature = "dpms"))]
+        assert_eq!(snapshot.dpms, None);
+
+        #[cfg(feature = "screensaver")]
+        assert_eq!(
+            snapshot.screen_saver_info,
+            Some(ScreenSaverInfo {
+                state: ScreenSaverState::Off,
+                kind: ScreenSaverKind::External,
+                ms_until_or_since: 5000,
+                ms_since_user_input: 1000,
+            })
+        );
+        #[cfg(not(feature = "screensaver"))]
+        assert_eq!(snapshot.screen_saver_info, None);
+
+        assert_eq!(
+            snapshot.net_wm,
+            Some(NetWmState {
+                active_window: Some(0x600),
+                active_window_fullscreen: true,
+            })
+        );
+
+        let report = snapshot.to_string();
+        assert!(report.contains("active window: 0x600"));
+        assert!(report.contains("active window fullscreen: true"));
+        #[cfg(feature = "dpms")]
+        assert!(report.contains("suspend timeout: disabled"));
+    }
+}
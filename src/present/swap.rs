@@ -0,0 +1,329 @@
+//! Scheduling of the presentation of pixmaps to a window.
+
+use super::{CompleteKind, CompleteNotifyEvent, Event, EventMask, EventXid, Pixmap, SelectInput};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::swap::{widen_serial, Backend, Scheduler, SwapComplete};
+use crate::{randr, sync, x, xfixes};
+
+use std::collections::VecDeque;
+
+/// Schedules the presentation of pixmaps to a window with `present::Pixmap`.
+///
+/// Each swap presents the pixmap set with [Scheduler::set_pixmap] to the whole window.
+/// The serials of the swaps are counted by the scheduler, from 1.
+pub struct PresentScheduler<'a> {
+    conn: &'a Connection,
+    window: x::Window,
+    eid: EventXid,
+    pixmap: x::Pixmap,
+    serial: u64,
+    pending: VecDeque<u64>,
+    completions: Vec<SwapComplete>,
+}
+
+impl<'a> std::fmt::Debug for PresentScheduler<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PresentScheduler")
+            .field("window", &self.window)
+            .field("eid", &self.eid)
+            .field("pixmap", &self.pixmap)
+            .field("serial", &self.serial)
+            .field("pending", &self.pending)
+            .field("completions", &self.completions)
+            .finish()
+    }
+}
+
+impl<'a> PresentScheduler<'a> {
+    /// Schedules the presentations to `window`, and selects its `CompleteNotify` events.
+    pub fn new(conn: &'a Connection, window: x::Window) -> Result<PresentScheduler<'a>> {
+        let eid = conn.generate_id();
+        conn.send_and_check_request(&SelectInput {
+            eid,
+            window,
+            event_mask: EventMask::COMPLETE_NOTIFY,
+        })
+        .context("SelectInput", || {
+            format!("CompleteNotify of window {}", window.resource_id())
+        })?;
+        Ok(PresentScheduler {
+            conn,
+            window,
+            eid,
+            pixmap: x::Pixmap::none(),
+            serial: 0,
+            pending: VecDeque::new(),
+            completions: Vec::new(),
+        })
+    }
+
+    /// The window of the presentations.
+    pub fn window(&self) -> x::Window {
+        self.window
+    }
+
+    /// The pixmap presented by the next swaps.
+    pub fn pixmap(&self) -> x::Pixmap {
+        self.pixmap
+    }
+
+    /// Records the completion of a swap if `ev` reports the presentation of a pixmap
+    /// by the scheduler, in which case `true` is returned.
+    ///
+    /// Skipped presentations are also completions: they were replaced by a later one
+    /// before being shown.
+    pub fn handle_complete_notify(&mut self, ev: &CompleteNotifyEvent) -> bool {
+        if ev.event().resource_id() != self.eid.resource_id()
+            || ev.window() != self.window
+            || ev.kind() != CompleteKind::Pixmap
+        {
+            return false;
+        }
+        let serial = widen_serial(self.serial, ev.serial());
+        while self.pending.front().is_some_and(|s| *s <= serial) {
+            self.pending.pop_front();
+        }
+        self.completions.push(SwapComplete {
+            serial,
+            ust: ev.ust(),
+            msc: ev.msc(),
+        });
+        true
+    }
+}
+
+impl<'a> Scheduler for PresentScheduler<'a> {
+    fn backend(&self) -> Backend {
+        Backend::Present
+    }
+
+    fn set_pixmap(&mut self, pixmap: x::Pixmap) {
+        self.pixmap = pixmap;
+    }
+
+    fn request_swap(&mut self, target_msc: u64) -> Result<u64> {
+        let serial = self.serial + 1;
+        self.conn
+            .send_and_check_request(&Pixmap {
+                window: self.window,
+                pixmap: self.pixmap,
+                serial: serial as u32,
+                valid: xfixes::Region::none(),
+                update: xfixes::Region::none(),
+                x_off: 0,
+                y_off: 0,
+                target_crtc: randr::Crtc::none(),
+                wait_fence: sync::Fence::none(),
+                idle_fence: sync::Fence::none(),
+                options: 0,
+                target_msc,
+                divisor: 0,
+                remainder: 0,
+                notifies: &[],
+            })
+            .context("PresentPixmap", || {
+                format!(
+                    "pixmap {} to window {}",
+                    self.pixmap.resource_id(),
+                    self.window.resource_id()
+                )
+            })?;
+        self.serial = serial;
+        self.pending.push_back(serial);
+        Ok(serial)
+    }
+
+    fn handle_event(&mut self, event: &crate::Event) -> bool {
+        match event {
+            crate::Event::Present(Event::CompleteNotify(ev)) => self.handle_complete_notify(ev),
+            _ => false,
+        }
+    }
+
+    fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn poll_completions(&mut self) -> Vec<SwapComplete> {
+        std::mem::take(&mut self.completions)
+    }
+}
+
+impl<'a> Drop for PresentScheduler<'a> {
+    fn drop(&mut self) {
+        // frees the event id
+        self.conn.send_request(&SelectInput {
+            eid: self.eid,
+            window: self.window,
+            event_mask: EventMask::NO_EVENT,
+        });
+        let _ = self.conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESENT_OPCODE: u8 = 141;
+
+    const WINDOW: u32 = 0x0120_0001;
+
+    fn connect(requests: std::sync::Arc<std::sync::Mutex<Vec<(u8, u32)>>>) -> Connection {
+        use crate::mock;
+
+        mock::connect_with_extensions(
+            move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(req, PRESENT_OPCODE, 0, 160)],
+                // SelectInput and Pixmap: the minor opcode and the serial or event mask
+                PRESENT_OPCODE => {
+                    requests.lock().unwrap().push((req.data[1], req.u32_at(12)));
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            },
+            &[crate::Extension::Present],
+        )
+    }
+
+    /// A `CompleteNotify` event, laid out like libxcb does, with `full_sequence` inserted
+    /// after the first 32 bytes.
+    fn complete_notify(
+        eid: u32,
+        kind: CompleteKind,
+        serial: u32,
+        ust: u64,
+        msc: u64,
+    ) -> crate::Event {
+        use crate::base::Raw;
+
+        let mut wire = [0u8; 44];
+        wire[0] = 35;
+        wire[1] = PRESENT_OPCODE;
+        wire[4..8].copy_from_slice(&2u32.to_ne_bytes());
+        wire[8..10].copy_from_slice(&1u16.to_ne_bytes());
+        wire[10] = kind as u8;
+        wire[11] = crate::present::CompleteMode::Flip as u8;
+        wire[12..16].copy_from_slice(&eid.to_ne_bytes());
+        wire[16..20].copy_from_slice(&WINDOW.to_ne_bytes());
+        wire[20..24].copy_from_slice(&serial.to_ne_bytes());
+        wire[24..32].copy_from_slice(&ust.to_ne_bytes());
+        wire[36..44].copy_from_slice(&msc.to_ne_bytes());
+        unsafe {
+            let raw = libc::malloc(wire.len()) as *mut u8;
+            std::ptr::copy_nonoverlapping(wire.as_ptr(), raw, wire.len());
+            crate::Event::Present(Event::CompleteNotify(CompleteNotifyEvent::from_raw(
+                raw as *mut _,
+            )))
+        }
+    }
+
+    #[test]
+    fn test_present_scheduler() {
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = connect(requests.clone());
+        let window = unsafe { x::Window::new(WINDOW) };
+        let mut scheduler = PresentScheduler::new(&conn, window).unwrap();
+        let eid = scheduler.eid.resource_id();
+        assert_eq!(scheduler.backend(), Backend::Present);
+
+        scheduler.set_pixmap(unsafe { x::Pixmap::new(0x0120_0010) });
+        assert_eq!(scheduler.request_swap(0).unwrap(), 1);
+        assert_eq!(scheduler.request_swap(501).unwrap(), 2);
+        assert_eq!(scheduler.request_swap(502).unwrap(), 3);
+        assert_eq!(scheduler.pending(), 3);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                (3, EventMask::COMPLETE_NOTIFY.bits()),
+                (1, 1),
+                (1, 2),
+                (1, 3)
+            ]
+        );
+
+        // the notifications of the other event ids and of NotifyMSC are not completions
+        assert!(!scheduler.handle_event(&complete_notify(
+            eid + 1,
+            CompleteKind::Pixmap,
+            1,
+            10,
+            500
+        )));
+        assert!(!scheduler.handle_event(&complete_notify(
+            eid,
+            CompleteKind::NotifyMsc,
+            1,
+            10,
+            500
+        )));
+        assert!(scheduler.handle_event(&complete_notify(
+            eid,
+            CompleteKind::Pixmap,
+            1,
+            16_000,
+            500
+        )));
+        // the second presentation is skipped, the third completes both
+        assert!(scheduler.handle_event(&complete_notify(
+            eid,
+            CompleteKind::Pixmap,
+            3,
+            32_000,
+            502
+        )));
+        assert_eq!(scheduler.pending(), 0);
+        assert_eq!(
+            scheduler.poll_completions(),
+            vec![
+                SwapComplete {
+                    serial: 1,
+                    ust: 16_000,
+                    msc: 500,
+                },
+                SwapComplete {
+                    serial: 3,
+                    ust: 32_000,
+                    msc: 502,
+                },
+            ]
+        );
+        assert!(scheduler.poll_completions().is_empty());
+    }
+
+    #[test]
+    fn test_present_serial_wrap() {
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = connect(requests.clone());
+        let window = unsafe { x::Window::new(WINDOW) };
+        let mut scheduler = PresentScheduler::new(&conn, window).unwrap();
+        let eid = scheduler.eid.resource_id();
+
+        // the serials sent to the server are truncated to 32 bits
+        scheduler.serial = 0xffff_fffe;
+        assert_eq!(scheduler.request_swap(0).unwrap(), 0xffff_ffff);
+        assert_eq!(scheduler.request_swap(0).unwrap(), 0x1_0000_0000);
+        assert_eq!(requests.lock().unwrap()[1..], [(1, 0xffff_ffff), (1, 0)]);
+
+        assert!(scheduler.handle_event(&complete_notify(
+            eid,
+            CompleteKind::Pixmap,
+            0xffff_ffff,
+            1,
+            1
+        )));
+        assert!(scheduler.handle_event(&complete_notify(eid, CompleteKind::Pixmap, 0, 2, 2)));
+        let serials: Vec<_> = scheduler
+            .poll_completions()
+            .iter()
+            .map(|c| c.serial)
+            .collect();
+        assert_eq!(serials, [0xffff_ffff, 0x1_0000_0000]);
+    }
+}
@@ -0,0 +1,556 @@
+#[cfg(feature = "damage")]
+use crate::base::BaseEvent;
+use crate::base::Connection;
+use crate::event::Event;
+#[cfg(feature = "damage")]
+use crate::ext::Extension;
+use crate::ffi::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::MutexGuard;
+
+/// What to do when the event queue of a connection is full.
+/// See [Connection::set_event_queue_limit].
+///
+/// Only the events that are superseded by the next ones of their kind can be dropped:
+/// `MotionNotify`, the `Notify` events of the DAMAGE extension (with the `damage` cargo
+/// feature) and the events listed with [Connection::set_droppable_event_kinds].
+/// The other events and the errors are never dropped, even when the queue is full.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drops the oldest event that can be dropped to make room for the new one.
+    DropOldest,
+    /// Drops the new event.
+    ///
+    /// The events whose code (the response type without the synthetic bit) is in
+    /// `keep_kinds` are never dropped: the oldest event not in `keep_kinds` is dropped
+    /// instead to make room for them.
+    DropNewest { keep_kinds: Vec<u8> },
+    /// Leaves the events in the queue of libxcb until there is room in the queue.
+    ///
+    /// Events are not read from the connection while the queue is full, which eventually
+    /// blocks the server from sending more events to this client.
+    /// Note that libxcb still reads the events that precede the replies waited for with
+    /// [Connection::wait_for_reply].
+    Block,
+}
+
+/// Statistics of the event queue of a connection, as returned by
+/// [Connection::event_queue_stats].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventQueueStats {
+    /// The number of events in the queue.
+    pub depth: usize,
+    /// The maximum number of events that were in the queue at once.
+    pub high_water: usize,
+    /// The number of dropped events, by event code.
+    pub dropped: HashMap<u8, u64>,
+}
+
+impl EventQueueStats {
+    /// The total number of dropped events.
+    pub fn total_dropped(&self) -> u64 {
+        self.dropped.values().sum()
+    }
+}
+
+const MOTION_NOTIFY: u8 = 6;
+
+/// Returns the event code of `ev` (0 for errors).
+unsafe fn event_code(ev: *mut xcb_generic_event_t) -> u8 {
+    // SAFETY: the caller guarantees that `ev` is an event or an error returned by XCB
+    unsafe { (*ev).response_type & 0x7f }
+}
+
+/// Bounded queue of the events read from libxcb.
+///
+/// The events are owned by the queue until they are popped.
+#[derive(Default)]
+pub(crate) struct EventQueue {
+    limit: Option<(usize, OverflowPolicy)>,
+    /// The code of the DAMAGE `Notify` event, if the extension is active.
+    damage_notify: Option<u8>,
+    /// The codes of the events that can be dropped in addition to the motions.
+    drop_kinds: Vec<u8>,
+    events: VecDeque<*mut xcb_generic_event_t>,
+    stats: EventQueueStats,
+}
+
+// the events are exclusively owned by the queue
+unsafe impl Send for EventQueue {}
+
+impl Drop for EventQueue {
+    fn drop(&mut self) {
+        for ev in self.events.drain(..) {
+            unsafe { libc::free(ev as *mut _) };
+        }
+    }
+}
+
+impl EventQueue {
+    /// Whether the events must be read from this queue rather than from libxcb: a limit is
+    /// set, or events were left in the queue by [Connection::take_queued_events].
+    pub(crate) fn is_active(&self) -> bool {
+        self.limit.is_some() || !self.events.is_empty()
+    }
+
+    /// Whether no more events should be moved from the queue of libxcb.
+    fn is_blocked(&self) -> bool {
+        match &self.limit {
+            Some((max, OverflowPolicy::Block)) => self.events.len() >= *max,
+            _ => false,
+        }
+    }
+
+    fn is_droppable(&self, code: u8) -> bool {
+        code == MOTION_NOTIFY || self.damage_notify == Some(code) || self.drop_kinds.contains(&code)
+    }
+
+    /// Drops the oldest event that can be dropped and is not in `keep_kinds`.
+    unsafe fn drop_oldest(&mut self, keep_kinds: &[u8]) -> bool {
+        let pos = self.events.iter().position(|ev| {
+            // SAFETY: the queued events are returned by XCB
+            let code = unsafe { event_code(*ev) };
+            self.is_droppable(code) && !keep_kinds.contains(&code)
+        });
+        match pos {
+            Some(pos) => {
+                let ev = self.events.remove(pos).unwrap();
+                // SAFETY: `ev` is no longer queued
+                unsafe { self.discard(ev) };
+                true
+            }
+            None => false,
+        }
+    }
+
+    unsafe fn discard(&mut self, ev: *mut xcb_generic_event_t) {
+        // SAFETY: the caller passes on the ownership of `ev`, returned by XCB
+        let code = unsafe { event_code(ev) };
+        #[cfg(feature = "log")]
+        crate::logging::event_dropped(code);
+        *self.stats.dropped.entry(code).or_insert(0) += 1;
+        // SAFETY: the events are allocated by XCB with `malloc`
+        unsafe { libc::free(ev as *mut _) };
+    }
+
+    /// Queues `ev`, applying the overflow policy if the queue is full.
+    ///
+    /// The queue exceeds its limit if no event can be dropped.
+    unsafe fn push(&mut self, ev: *mut xcb_generic_event_t) {
+        if let Some((max, policy)) = &self.limit {
+            if self.events.len() >= *max {
+                // SAFETY: the caller passes on the ownership of `ev`, returned by XCB, which
+                // is either discarded or queued
+                let code = unsafe { event_code(ev) };
+                match policy.clone() {
+                    OverflowPolicy::DropOldest => {
+                        if !unsafe { self.drop_oldest(&[]) } && self.is_droppable(code) {
+                            unsafe { self.discard(ev) };
+                            return;
+                        }
+                    }
+                    OverflowPolicy::DropNewest { keep_kinds } => {
+                        if self.is_droppable(code) && !keep_kinds.contains(&code) {
+                            unsafe { self.discard(ev) };
+                            return;
+                        }
+                        unsafe { self.drop_oldest(&keep_kinds) };
+                    }
+                    OverflowPolicy::Block => {}
+                }
+            }
+        }
+        self.events.push_back(ev);
+        self.stats.high_water = self.stats.high_water.max(self.events.len());
+    }
+
+    fn pop(&mut self) -> Option<*mut xcb_generic_event_t> {
+        self.events.pop_front()
+    }
+}
+
+impl Connection {
+    /// Bounds the number of events queued by the connection to `max_events`.
+    ///
+    /// Without limit, a stalled consumer lets the event queue of libxcb grow indefinitely
+    /// (e.g. with motion events while a frame takes too long to render).
+    /// With a limit, the events are moved from the queue of libxcb to a queue of the
+    /// connection by [Connection::wait_for_event], [Connection::poll_for_event] and
+    /// [Connection::poll_for_queued_event], and `policy` is applied when this queue is full.
+    /// See [OverflowPolicy] for the events that are never dropped.
+    ///
+    /// Use [Connection::event_queue_stats] to monitor the queue.
+    ///
+    /// # Panics
+    /// Panics if `max_events` is 0.
+    pub fn set_event_queue_limit(&self, max_events: usize, policy: OverflowPolicy) {
+        assert!(max_events > 0, "the event queue limit must be positive");
+        let mut queue = self.event_queue();
+        #[cfg(feature = "damage")]
+        {
+            queue.damage_notify = self.extension_data(Extension::Damage).map(|data| {
+                data.first_event + <crate::damage::NotifyEvent as BaseEvent>::NUMBER as u8
+            });
+        }
+        queue.limit = Some((max_events, policy));
+    }
+
+    /// Lets the events whose code is in `kinds` be dropped when the event queue is full,
+    /// in addition to the events listed by [OverflowPolicy].
+    ///
+    /// The code of an event is its response type without the synthetic bit; the code of an
+    /// extension event starts at the `first_event` of the extension
+    /// (see [Connection::extension_data]).
+    pub fn set_droppable_event_kinds(&self, kinds: &[u8]) {
+        self.event_queue().drop_kinds = kinds.to_vec();
+    }
+
+    /// Returns the statistics of the event queue.
+    ///
+    /// The statistics are only collected once a limit is set with
+    /// [Connection::set_event_queue_limit].
+    pub fn event_queue_stats(&self) -> EventQueueStats {
+        let queue = self.event_queue();
+        EventQueueStats {
+            depth: queue.events.len(),
+            ..queue.stats.clone()
+        }
+    }
+
+    /// Moves the events from the queue of libxcb to `queue`, without reading
+    /// from the connection.
+    unsafe fn drain_xcb_queue(&self, queue: &mut MutexGuard<'_, EventQueue>) {
+        while !queue.is_blocked() {
+            // SAFETY: the connection is valid, and the returned event is owned by the queue
+            // unless it is intercepted
+            unsafe {
+                let ev = xcb_poll_for_queued_event(self.get_raw_conn());
+                if ev.is_null() {
+                    break;
+                }
+                if !self.intercept_tracked_error(ev) {
+                    queue.push(ev);
+                }
+            }
+        }
+    }
+
+    /// Takes out of the event queue the events, already read from the connection, for which
+    /// `take` returns `true`, and returns them in order.
+    ///
+    /// `take` is given the 32 bytes of each event on the wire. Errors are never taken.
+    /// The other events are left in the queue of the connection, in order, and will be
+    /// returned by the event functions before the events that are still in libxcb.
+    pub(crate) fn take_queued_events(&self, mut take: impl FnMut(&[u8]) -> bool) -> Vec<Event> {
+        let mut taken = Vec::new();
+        {
+            let mut queue = self.event_queue();
+            unsafe { self.drain_xcb_queue(&mut queue) };
+            let mut kept = VecDeque::with_capacity(queue.events.len());
+            for ev in queue.events.drain(..) {
+                let bytes = unsafe { std::slice::from_raw_parts(ev as *const u8, 32) };
+                if unsafe { event_code(ev) } != 0 && take(bytes) {
+                    taken.push(ev);
+                } else {
+                    kept.push_back(ev);
+                }
+            }
+            queue.events = kept;
+        }
+        taken
+            .into_iter()
+            .map(|ev| unsafe { self.resolve_event(&mut *ev) })
+            .collect()
+    }
+
+    /// Returns the next event of the bounded queue, and blocks until one is received
+    /// if the queue is empty. Returns null on I/O error.
+    pub(crate) unsafe fn wait_for_bounded_event(&self) -> *mut xcb_generic_event_t {
+        loop {
+            {
+                let mut queue = self.event_queue();
+                // SAFETY: the lock of the queue is held
+                unsafe { self.drain_xcb_queue(&mut queue) };
+                if let Some(ev) = queue.pop() {
+                    return ev;
+                }
+            }
+            // SAFETY: the connection is valid, and the returned event is owned by the queue
+            // unless it is intercepted
+            unsafe {
+                let ev = xcb_wait_for_event(self.get_raw_conn());
+                if ev.is_null() {
+                    return ev;
+                }
+                if !self.intercept_tracked_error(ev) {
+                    self.event_queue().push(ev);
+                }
+            }
+        }
+    }
+
+    /// Returns the next event of the bounded queue, or null if there is none.
+    ///
+    /// If `read` is true and the queue is empty, the events available on the connection
+    /// are read.
+    pub(crate) unsafe fn poll_for_bounded_event(&self, read: bool) -> *mut xcb_generic_event_t {
+        loop {
+            {
+                let mut queue = self.event_queue();
+                // SAFETY: the lock of the queue is held
+                unsafe { self.drain_xcb_queue(&mut queue) };
+                if let Some(ev) = queue.pop() {
+                    return ev;
+                }
+            }
+            if !read {
+                return std::ptr::null_mut();
+            }
+            // SAFETY: the connection is valid, and the returned event is owned by the queue
+            // unless it is intercepted
+            unsafe {
+                let ev = xcb_poll_for_event(self.get_raw_conn());
+                if ev.is_null() {
+                    return ev;
+                }
+                if !self.intercept_tracked_error(ev) {
+                    self.event_queue().push(ev);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) const FLOOD_LEN: usize = 100;
+
+/// Connects to a mock server that replies to `GetInputFocus` after a flood of `FLOOD_LEN`
+/// `MotionNotify` events, with a `ConfigureNotify` and an event of code 90 in the middle.
+///
+/// The `root_x` field of the motion events is their index in the flood.
+#[cfg(test)]
+pub(crate) fn flood_connection() -> Connection {
+    crate::mock::connect(|req| flood_response(req, MOTION_NOTIFY))
+}
+
+#[cfg(test)]
+fn flood_event(code: u8, seq: u16, x: i16) -> Vec<u8> {
+    let mut ev = vec![0u8; 32];
+    ev[0] = code;
+    ev[2..4].copy_from_slice(&seq.to_ne_bytes());
+    ev[20..22].copy_from_slice(&x.to_ne_bytes());
+    ev
+}
+
+/// The response of the server of [flood_connection], with a flood of events of `code`.
+#[cfg(test)]
+fn flood_response(req: &crate::mock::MockRequest, code: u8) -> Vec<Vec<u8>> {
+    use crate::mock;
+
+    match req.opcode {
+        43 => {
+            let mut resp: Vec<_> = (0..FLOOD_LEN as i16)
+                .map(|i| flood_event(code, req.seq, i))
+                .collect();
+            resp.insert(50, flood_event(22, req.seq, 0));
+            resp.insert(60, flood_event(90, req.seq, 0));
+            resp.push(mock::reply(req.seq, 0, &[]));
+            resp
+        }
+        _ => mock::default_response(req),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn flood(conn: &Connection) -> Vec<String> {
+    use crate::x;
+
+    conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+        .unwrap();
+    let mut events = Vec::new();
+    while let Some(ev) = conn.poll_for_event().unwrap() {
+        events.push(match ev {
+            crate::Event::X(x::Event::MotionNotify(ev)) => format!("M{}", ev.root_x()),
+            crate::Event::X(x::Event::ConfigureNotify(_)) => "C".to_string(),
+            _ => "U".to_string(),
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motions(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("M{}", i)).collect()
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let conn = flood_connection();
+        assert_eq!(flood(&conn).len(), FLOOD_LEN + 2);
+        assert_eq!(conn.event_queue_stats(), EventQueueStats::default());
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let conn = flood_connection();
+        conn.set_event_queue_limit(10, OverflowPolicy::DropOldest);
+
+        let mut expected = vec!["C".to_string(), "U".to_string()];
+        expected.extend(motions(FLOOD_LEN - 8..FLOOD_LEN));
+        assert_eq!(flood(&conn), expected);
+
+        let stats = conn.event_queue_stats();
+        assert_eq!(stats.depth, 0);
+        assert_eq!(stats.high_water, 10);
+        assert_eq!(stats.dropped[&MOTION_NOTIFY], FLOOD_LEN as u64 - 8);
+        assert_eq!(stats.total_dropped(), FLOOD_LEN as u64 - 8);
+
+        // the event 90 is dropped once listed
+        let conn = flood_connection();
+        conn.set_event_queue_limit(10, OverflowPolicy::DropOldest);
+        conn.set_droppable_event_kinds(&[90]);
+
+        let mut expected = vec!["C".to_string()];
+        expected.extend(motions(FLOOD_LEN - 9..FLOOD_LEN));
+        assert_eq!(flood(&conn), expected);
+        let stats = conn.event_queue_stats();
+        assert_eq!(stats.dropped[&MOTION_NOTIFY], FLOOD_LEN as u64 - 9);
+        assert_eq!(stats.dropped[&90], 1);
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let conn = flood_connection();
+        conn.set_event_queue_limit(10, OverflowPolicy::DropNewest { keep_kinds: vec![] });
+
+        // the ConfigureNotify and the event 90 take the place of the oldest motions
+        let mut expected = motions(2..10);
+        expected.push("C".to_string());
+        expected.push("U".to_string());
+        assert_eq!(flood(&conn), expected);
+        let stats = conn.event_queue_stats();
+        assert_eq!(stats.dropped[&MOTION_NOTIFY], FLOOD_LEN as u64 - 8);
+        assert_eq!(stats.dropped.get(&90), None);
+
+        let conn = flood_connection();
+        conn.set_event_queue_limit(10, OverflowPolicy::DropNewest { keep_kinds: vec![] });
+        conn.set_droppable_event_kinds(&[90]);
+        let mut expected = motions(1..10);
+        expected.push("C".to_string());
+        assert_eq!(flood(&conn), expected);
+        assert_eq!(conn.event_queue_stats().dropped[&90], 1);
+
+        let conn = flood_connection();
+        conn.set_droppable_event_kinds(&[90]);
+        conn.set_event_queue_limit(
+            10,
+            OverflowPolicy::DropNewest {
+                keep_kinds: vec![90],
+            },
+        );
+        let mut expected = motions(2..10);
+        expected.push("C".to_string());
+        expected.push("U".to_string());
+        assert_eq!(flood(&conn), expected);
+        let stats = conn.event_queue_stats();
+        assert_eq!(stats.dropped.get(&90), None);
+        assert_eq!(stats.high_water, 10);
+    }
+
+    #[test]
+    fn test_extension_events_kept() {
+        // a flood of extension events, none of which can be dropped
+        let conn = crate::mock::connect(|req| flood_response(req, 100));
+        conn.set_event_queue_limit(10, OverflowPolicy::DropOldest);
+        assert_eq!(flood(&conn).len(), FLOOD_LEN + 2);
+
+        let stats = conn.event_queue_stats();
+        assert_eq!(stats.high_water, FLOOD_LEN + 2);
+        assert_eq!(stats.total_dropped(), 0);
+
+        let conn = crate::mock::connect(|req| flood_response(req, 100));
+        conn.set_event_queue_limit(10, OverflowPolicy::DropNewest { keep_kinds: vec![] });
+        assert_eq!(flood(&conn).len(), FLOOD_LEN + 2);
+        assert_eq!(conn.event_queue_stats().total_dropped(), 0);
+    }
+
+    #[cfg(feature = "damage")]
+    #[test]
+    fn test_drop_damage_notify() {
+        use crate::mock;
+
+        // DAMAGE with its first event at 100
+        let conn = mock::connect_with_extensions(
+            |req| match req.opcode {
+                98 if mock::extension_name(req) == b"DAMAGE" => {
+                    vec![mock::query_extension_reply(req, 140, 100, 150)]
+                }
+                _ => flood_response(req, 100),
+            },
+            &[Extension::Damage],
+        );
+        conn.set_event_queue_limit(10, OverflowPolicy::DropOldest);
+
+        let events = flood(&conn);
+        assert_eq!(events.len(), 10);
+        assert_eq!(&events[..2], &["C".to_string(), "U".to_string()]);
+        assert_eq!(conn.event_queue_stats().dropped[&100], FLOOD_LEN as u64 - 8);
+    }
+
+    #[test]
+    fn test_block() {
+        use crate::x;
+
+        let conn = flood_connection();
+        conn.set_event_queue_limit(10, OverflowPolicy::Block);
+
+        conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+            .unwrap();
+        assert!(matches!(
+            conn.wait_for_event().unwrap(),
+            crate::Event::X(x::Event::MotionNotify(_))
+        ));
+        assert_eq!(conn.event_queue_stats().depth, 9);
+
+        let mut count = 1;
+        while conn.poll_for_queued_event().unwrap().is_some() {
+            count += 1;
+            assert!(conn.event_queue_stats().depth <= 10);
+        }
+        assert_eq!(count, FLOOD_LEN + 2);
+        let stats = conn.event_queue_stats();
+        assert_eq!(stats.high_water, 10);
+        assert_eq!(stats.total_dropped(), 0);
+    }
+
+    #[test]
+    fn test_take_queued_events() {
+        use crate::x;
+
+        let conn = flood_connection();
+        conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+            .unwrap();
+        let taken = conn.take_queued_events(|ev| ev[0] == 22);
+        assert_eq!(taken.len(), 1);
+        assert!(matches!(
+            taken[0],
+            crate::Event::X(x::Event::ConfigureNotify(_))
+        ));
+
+        // the other events are left in order, without limit
+        let mut count = 0;
+        while let Some(ev) = conn.poll_for_queued_event().unwrap() {
+            if count == 59 {
+                assert!(!matches!(ev, crate::Event::X(x::Event::MotionNotify(_))));
+            } else {
+                assert!(matches!(ev, crate::Event::X(x::Event::MotionNotify(_))));
+            }
+            count += 1;
+        }
+        assert_eq!(count, FLOOD_LEN + 1);
+    }
+}
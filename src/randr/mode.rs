@@ -0,0 +1,187 @@
+//! Refresh rate and modeline of the video modes.
+
+use super::{ModeFlag, ModeInfo};
+
+use std::fmt;
+
+/// The names of the flags printed by `xrandr`, in its order.
+const FLAG_NAMES: [(ModeFlag, &str); 9] = [
+    (ModeFlag::HSYNC_POSITIVE, "+HSync"),
+    (ModeFlag::HSYNC_NEGATIVE, "-HSync"),
+    (ModeFlag::VSYNC_POSITIVE, "+VSync"),
+    (ModeFlag::VSYNC_NEGATIVE, "-VSync"),
+    (ModeFlag::INTERLACE, "Interlace"),
+    (ModeFlag::DOUBLE_SCAN, "DoubleScan"),
+    (ModeFlag::CSYNC, "CSync"),
+    (ModeFlag::CSYNC_POSITIVE, "+CSync"),
+    (ModeFlag::CSYNC_NEGATIVE, "-CSync"),
+];
+
+impl ModeInfo {
+    /// Whether the mode is interlaced.
+    pub fn is_interlaced(&self) -> bool {
+        self.mode_flags.contains(ModeFlag::INTERLACE)
+    }
+
+    /// Whether each line of the mode is scanned twice.
+    pub fn is_doublescan(&self) -> bool {
+        self.mode_flags.contains(ModeFlag::DOUBLE_SCAN)
+    }
+
+    /// The vertical refresh rate of the mode in Hz, computed like `xrandr` does.
+    ///
+    /// The number of scanned lines is doubled for doublescan modes, and halved for
+    /// interlaced modes, of which the rate is thus the field rate.
+    /// Returns `0.0` if the totals are zero.
+    pub fn refresh_hz(&self) -> f64 {
+        let mut vtotal = self.vtotal as f64;
+        if self.is_doublescan() {
+            vtotal *= 2.0;
+        }
+        if self.is_interlaced() {
+            vtotal /= 2.0;
+        }
+        if self.htotal == 0 || vtotal == 0.0 {
+            return 0.0;
+        }
+        self.dot_clock as f64 / (self.htotal as f64 * vtotal)
+    }
+}
+
+/// Prints the timings like a modeline of `xrandr --newmode`, without the name:
+/// the dot clock in MHz, the horizontal and vertical timings, and the flags.
+///
+/// E.g. `148.50  1920 2008 2052 2200  1080 1084 1089 1125 +HSync +VSync`.
+impl fmt::Display for ModeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2}  {} {} {} {}  {} {} {} {}",
+            self.dot_clock as f64 / 1_000_000.0,
+            self.width,
+            self.hsync_start,
+            self.hsync_end,
+            self.htotal,
+            self.height,
+            self.vsync_start,
+            self.vsync_end,
+            self.vtotal
+        )?;
+        for (flag, name) in FLAG_NAMES {
+            if self.mode_flags.contains(flag) {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(dot_clock_khz: u32, h: [u16; 4], v: [u16; 4], mode_flags: ModeFlag) -> ModeInfo {
+        ModeInfo {
+            id: 0x48,
+            width: h[0],
+            height: v[0],
+            dot_clock: dot_clock_khz * 1000,
+            hsync_start: h[1],
+            hsync_end: h[2],
+            htotal: h[3],
+            hskew: 0,
+            vsync_start: v[1],
+            vsync_end: v[2],
+            vtotal: v[3],
+            name_len: 0,
+            mode_flags,
+        }
+    }
+
+    #[test]
+    fn test_refresh_hz() {
+        let rate = |mode: &ModeInfo| format!("{:.2}", mode.refresh_hz());
+
+        let hd = mode(
+            148_500,
+            [1920, 2008, 2052, 2200],
+            [1080, 1084, 1089, 1125],
+            ModeFlag::HSYNC_POSITIVE | ModeFlag::VSYNC_POSITIVE,
+        );
+        assert!(!hd.is_interlaced() && !hd.is_doublescan());
+        assert_eq!(rate(&hd), "60.00");
+
+        // 1080i: the rate is the field rate
+        let interlaced = mode(
+            74_250,
+            [1920, 2008, 2052, 2200],
+            [1080, 1084, 1094, 1125],
+            ModeFlag::HSYNC_POSITIVE | ModeFlag::VSYNC_POSITIVE | ModeFlag::INTERLACE,
+        );
+        assert!(interlaced.is_interlaced());
+        assert_eq!(rate(&interlaced), "60.00");
+
+        let doublescan = mode(
+            12_588,
+            [320, 336, 384, 400],
+            [240, 245, 246, 262],
+            ModeFlag::HSYNC_NEGATIVE | ModeFlag::VSYNC_NEGATIVE | ModeFlag::DOUBLE_SCAN,
+        );
+        assert!(doublescan.is_doublescan());
+        assert_eq!(rate(&doublescan), "60.06");
+
+        let sxga = mode(
+            108_000,
+            [1280, 1296, 1440, 1688],
+            [1024, 1025, 1028, 1066],
+            ModeFlag::HSYNC_POSITIVE | ModeFlag::VSYNC_POSITIVE,
+        );
+        assert_eq!(rate(&sxga), "60.02");
+
+        assert_eq!(mode(0, [0; 4], [0; 4], ModeFlag::empty()).refresh_hz(), 0.0);
+        // the doubled total doesn't overflow
+        let tall = mode(
+            100_000,
+            [100, 0, 0, 1000],
+            [0, 0, 0, 50_000],
+            ModeFlag::DOUBLE_SCAN,
+        );
+        assert_eq!(rate(&tall), "1.00");
+    }
+
+    #[test]
+    fn test_modeline() {
+        let hd = mode(
+            148_500,
+            [1920, 2008, 2052, 2200],
+            [1080, 1084, 1089, 1125],
+            ModeFlag::HSYNC_POSITIVE | ModeFlag::VSYNC_POSITIVE,
+        );
+        assert_eq!(
+            hd.to_string(),
+            "148.50  1920 2008 2052 2200  1080 1084 1089 1125 +HSync +VSync"
+        );
+
+        let interlaced = mode(
+            74_250,
+            [1920, 2008, 2052, 2200],
+            [1080, 1084, 1094, 1125],
+            ModeFlag::INTERLACE | ModeFlag::VSYNC_POSITIVE | ModeFlag::HSYNC_POSITIVE,
+        );
+        assert_eq!(
+            interlaced.to_string(),
+            "74.25  1920 2008 2052 2200  1080 1084 1094 1125 +HSync +VSync Interlace"
+        );
+
+        let doublescan = mode(
+            12_588,
+            [320, 336, 384, 400],
+            [240, 245, 246, 262],
+            ModeFlag::DOUBLE_SCAN | ModeFlag::HSKEW_PRESENT,
+        );
+        assert_eq!(
+            doublescan.to_string(),
+            "12.59  320 336 384 400  240 245 246 262 DoubleScan"
+        );
+    }
+}
@@ -0,0 +1,488 @@
+//! Requests encoded by hand, for the extensions that are not modeled by the crate.
+
+use crate::base::{align_pad, Connection, RequestFlags, Result, ResultExt, SequenceNumber};
+use crate::ext::ExtensionData;
+use crate::ffi::*;
+use crate::x;
+
+use libc::iovec;
+use std::fmt;
+
+/// The runtime data of an extension, as reported by `x::QueryExtension`.
+///
+/// Unlike [ExtensionData], the extension doesn't need to be known by the crate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionInfo {
+    pub major_opcode: u8,
+    pub first_event: u8,
+    pub first_error: u8,
+}
+
+impl ExtensionInfo {
+    /// Queries the extension `name` (e.g. `"NV-GLX"`).
+    ///
+    /// Returns `None` if the extension is not present on the server.
+    pub fn query(conn: &Connection, name: &str) -> Result<Option<ExtensionInfo>> {
+        let cookie = conn.send_request(&x::QueryExtension {
+            name: name.as_bytes(),
+        });
+        let reply = conn
+            .wait_for_reply(cookie)
+            .context("QueryExtension", || name.to_string())?;
+        Ok(if reply.present() {
+            Some(ExtensionInfo {
+                major_opcode: reply.major_opcode(),
+                first_event: reply.first_event(),
+                first_error: reply.first_error(),
+            })
+        } else {
+            None
+        })
+    }
+}
+
+impl From<&ExtensionData> for ExtensionInfo {
+    fn from(data: &ExtensionData) -> ExtensionInfo {
+        ExtensionInfo {
+            major_opcode: data.major_opcode,
+            first_event: data.first_event,
+            first_error: data.first_error,
+        }
+    }
+}
+
+/// An error returned by the server in response to a raw request.
+///
+/// Raw requests are usually sent to extensions that the crate doesn't know, so their errors
+/// are not resolved: they are returned as [crate::ProtocolError::Unknown].
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct GenericError {
+    data: [u8; 32],
+    full_sequence: u32,
+}
+
+impl GenericError {
+    /// The error code. Extension errors start at [ExtensionInfo::first_error].
+    pub fn error_code(&self) -> u8 {
+        self.data[1]
+    }
+
+    /// The 16 bits sequence number of the request in error.
+    pub fn sequence(&self) -> u16 {
+        u16::from_ne_bytes([self.data[2], self.data[3]])
+    }
+
+    /// The sequence number of the request in error, widened to 32 bits by libxcb.
+    pub fn full_sequence(&self) -> u32 {
+        self.full_sequence
+    }
+
+    /// The bad resource id or value, if relevant for the error code.
+    pub fn resource_id(&self) -> u32 {
+        u32::from_ne_bytes([self.data[4], self.data[5], self.data[6], self.data[7]])
+    }
+
+    /// The minor opcode of the request in error.
+    pub fn minor_code(&self) -> u16 {
+        u16::from_ne_bytes([self.data[8], self.data[9]])
+    }
+
+    /// The major opcode of the request in error.
+    pub fn major_code(&self) -> u8 {
+        self.data[10]
+    }
+
+    /// The complete error, as sent by the server.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.data
+    }
+}
+
+impl fmt::Debug for GenericError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GenericError")
+            .field("error_code", &self.error_code())
+            .field("sequence", &self.sequence())
+            .field("resource_id", &self.resource_id())
+            .field("minor_code", &self.minor_code())
+            .field("major_code", &self.major_code())
+            .finish()
+    }
+}
+
+impl fmt::Display for GenericError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "X error {} for request {}.{} (resource {:#x})",
+            self.error_code(),
+            self.major_code(),
+            self.minor_code(),
+            self.resource_id()
+        )
+    }
+}
+
+impl std::error::Error for GenericError {}
+
+/// Takes ownership of `error` (allocated by libxcb).
+pub(crate) unsafe fn generic_error(error: *mut xcb_generic_error_t) -> GenericError {
+    let mut data = [0u8; 32];
+    // SAFETY: the caller passes on the ownership of `error`, a 32 bytes error allocated by libxcb
+    // followed by its full sequence number
+    let full_sequence = unsafe {
+        std::ptr::copy_nonoverlapping(error as *const u8, data.as_mut_ptr(), 32);
+        let full_sequence = (*error).full_sequence;
+        libc::free(error as *mut _);
+        full_sequence
+    };
+    GenericError {
+        data,
+        full_sequence,
+    }
+}
+
+/// Takes ownership of `error` (allocated by libxcb), which is not resolved.
+unsafe fn unknown_error(error: *mut xcb_generic_error_t) -> crate::Error {
+    // SAFETY: forwarded from the caller
+    let error = unsafe { generic_error(error) };
+    crate::Error::Protocol(crate::ProtocolError::Unknown(error, None))
+}
+
+/// The cookie of a request sent with [Connection::send_raw_request] or
+/// [Connection::send_raw_core_request].
+///
+/// Raw requests are always checked: their error is reported to the cookie, not to the event
+/// loop. The reply or error of a cookie dropped without being consumed is discarded.
+pub struct RawCookie<'a> {
+    conn: &'a Connection,
+    seq: u64,
+    wants_reply: bool,
+}
+
+impl<'a> fmt::Debug for RawCookie<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawCookie")
+            .field("seq", &self.seq)
+            .field("wants_reply", &self.wants_reply)
+            .finish()
+    }
+}
+
+impl<'a> RawCookie<'a> {
+    /// The sequence number of the request.
+    pub fn sequence(&self) -> SequenceNumber {
+        self.seq
+    }
+
+    /// Waits for the reply of the request, which is returned complete (header included).
+    ///
+    /// The error of the server is returned as [crate::ProtocolError::Unknown].
+    ///
+    /// # Panics
+    /// Panics if the request was sent with `wants_reply == false`.
+    pub fn get_reply_raw(self) -> Result<Vec<u8>> {
+        assert!(self.wants_reply, "get_reply_raw on a request without reply");
+        let conn = self.conn;
+        let seq = self.seq;
+        std::mem::forget(self);
+        unsafe {
+            let mut error: *mut xcb_generic_error_t = std::ptr::null_mut();
+            let reply = xcb_wait_for_reply64(conn.get_raw_conn(), seq, &mut error as *mut _);
+            match (reply.is_null(), error.is_null()) {
+                (true, true) => {
+                    conn.has_error()?;
+                    unreachable!("xcb_wait_for_reply64 returned null without I/O error");
+                }
+                (true, false) => Err(unknown_error(error)),
+                (false, true) => {
+                    let reply = reply as *mut u8;
+                    let length = *(reply.add(4) as *const u32) as usize;
+                    let data = std::slice::from_raw_parts(reply, 32 + 4 * length).to_vec();
+                    libc::free(reply as *mut _);
+                    Ok(data)
+                }
+                (false, false) => unreachable!("xcb_wait_for_reply64 returned two pointers"),
+            }
+        }
+    }
+
+    /// Waits until the request is processed, and returns its error, if any.
+    ///
+    /// The reply of a request with reply is discarded. The error of the server is returned
+    /// as [crate::ProtocolError::Unknown].
+    pub fn request_check(self) -> Result<()> {
+        if self.wants_reply {
+            return self.get_reply_raw().map(|_| ());
+        }
+        let conn = self.conn;
+        let seq = self.seq;
+        std::mem::forget(self);
+        unsafe {
+            let error = xcb_request_check(conn.get_raw_conn(), xcb_void_cookie_t { seq: seq as _ });
+            if error.is_null() {
+                conn.has_error()?;
+                Ok(())
+            } else {
+                Err(unknown_error(error))
+            }
+        }
+    }
+}
+
+impl<'a> Drop for RawCookie<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            xcb_discard_reply64(self.conn.get_raw_conn(), self.seq);
+        }
+    }
+}
+
+impl Connection {
+    /// Sends a request of the extension `ext` encoded by hand.
+    ///
+    /// `body` is the request without its 4 bytes header (opcodes and length), which is
+    /// built from `ext` and `minor_opcode`: the body is padded to 4 bytes, its length
+    /// is computed by libxcb and sent with BIG-REQUESTS if needed.
+    ///
+    /// This is meant for the extensions that the crate doesn't model (see
+    /// [ExtensionInfo::query]); the requests of known extensions should be sent
+    /// with [Connection::send_request].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// # let (conn, _) = xcb::Connection::connect(None)?;
+    /// // the QueryVersion request (minor opcode 0) of an extension
+    /// if let Some(ext) = xcb::ExtensionInfo::query(&conn, "SOME-EXTENSION")? {
+    ///     let reply = conn
+    ///         .send_raw_request(&ext, 0, &[1, 0, 0, 0, 0, 0, 0, 0], true)
+    ///         .get_reply_raw()?;
+    ///     println!("reply: {:?}", &reply[8..16]);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_raw_request(
+        &self,
+        ext: &ExtensionInfo,
+        minor_opcode: u8,
+        body: &[u8],
+        wants_reply: bool,
+    ) -> RawCookie<'_> {
+        self.send_raw(ext.major_opcode, minor_opcode, body, wants_reply)
+    }
+
+    /// Sends a core request encoded by hand.
+    ///
+    /// Same as [Connection::send_raw_request], except that the header is built from the
+    /// core `opcode` and from `data`, the second byte of the header, which some core
+    /// requests use for a field (e.g. `only_if_exists` of `InternAtom`) and the others
+    /// leave unused.
+    pub fn send_raw_core_request(
+        &self,
+        opcode: u8,
+        data: u8,
+        body: &[u8],
+        wants_reply: bool,
+    ) -> RawCookie<'_> {
+        self.send_raw(opcode, data, body, wants_reply)
+    }
+
+    fn send_raw(&self, opcode: u8, data: u8, body: &[u8], wants_reply: bool) -> RawCookie<'_> {
+        let len = 4 + body.len();
+        let mut buf = Vec::with_capacity(len + align_pad(len, 4));
+        buf.extend_from_slice(&[0, data, 0, 0]);
+        buf.extend_from_slice(body);
+        buf.resize(len + align_pad(len, 4), 0);
+
+        // libxcb writes the major opcode and the length in the header, and reserves
+        // the sections before the request for the prefix of BIG-REQUESTS.
+        let mut sections = [iovec {
+            iov_base: std::ptr::null_mut(),
+            iov_len: 0,
+        }; 3];
+        sections[2].iov_base = buf.as_mut_ptr() as *mut _;
+        sections[2].iov_len = buf.len();
+
+        let request = xcb_protocol_request_t {
+            count: 1,
+            ext: std::ptr::null_mut(),
+            opcode,
+            isvoid: !wants_reply as u8,
+        };
+        let seq = unsafe {
+            xcb_send_request64(
+                self.get_raw_conn(),
+                RequestFlags::CHECKED.bits() as _,
+                sections.as_mut_ptr().add(2),
+                &request as *const _,
+            )
+        };
+        RawCookie {
+            conn: self,
+            seq,
+            wants_reply,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIGREQ_OPCODE: u8 = 133;
+
+    /// A mock server with the BIG-REQUESTS extension, and an extension `RAW-TEST` whose
+    /// requests are recorded (opcode 134, first error 160).
+    ///
+    /// Minor 0 requests get a reply that echoes the request, minor 1 requests an error.
+    /// The core `InternAtom` requests are recorded as well, and get the atom `0x42`.
+    fn connect_raw(requests: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>) -> Connection {
+        use crate::mock;
+
+        mock::connect(move |req| match req.opcode {
+            98 => match mock::extension_name(req) {
+                b"BIG-REQUESTS" => vec![mock::query_extension_reply(req, BIGREQ_OPCODE, 0, 0)],
+                b"RAW-TEST" => vec![mock::query_extension_reply(req, 134, 0, 160)],
+                _ => vec![mock::reply(req.seq, 0, &[])],
+            },
+            // BigRequests::Enable
+            BIGREQ_OPCODE => vec![mock::reply(req.seq, 0, &0x0040_0000u32.to_ne_bytes())],
+            // InternAtom
+            16 => {
+                requests.lock().unwrap().push(req.data.clone());
+                vec![mock::reply(req.seq, 0, &0x42u32.to_ne_bytes())]
+            }
+            134 => {
+                requests.lock().unwrap().push(req.data.clone());
+                match req.data[1] {
+                    0 => {
+                        let mut body = vec![0u8; 24];
+                        body.extend_from_slice(&req.data[..4]);
+                        body.extend_from_slice(req.data.get(4..8).unwrap_or(&[0; 4]));
+                        vec![mock::reply(req.seq, 7, &body)]
+                    }
+                    _ => vec![mock::error(req.seq, 160, 0xdead, 134)],
+                }
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    #[test]
+    fn test_raw_bigreq_enable() {
+        use std::sync::{Arc, Mutex};
+
+        let conn = connect_raw(Arc::new(Mutex::new(Vec::new())));
+
+        let cookie = conn.send_request(&crate::bigreq::Enable {});
+        let expected = conn.wait_for_reply(cookie).unwrap();
+
+        let ext = ExtensionInfo::query(&conn, "BIG-REQUESTS")
+            .unwrap()
+            .unwrap();
+        assert_eq!(ext.major_opcode, BIGREQ_OPCODE);
+        let cookie = conn.send_raw_request(&ext, 0, &[], true);
+        let seq = cookie.sequence();
+        let reply = cookie.get_reply_raw().unwrap();
+        assert_eq!(reply.len(), 32);
+        assert_eq!(reply[0], 1);
+        assert_eq!(
+            u32::from_ne_bytes([reply[8], reply[9], reply[10], reply[11]]),
+            expected.maximum_request_length()
+        );
+        assert_eq!(u16::from_ne_bytes([reply[2], reply[3]]), seq as u16);
+
+        assert_eq!(ExtensionInfo::query(&conn, "NV-GLX").unwrap(), None);
+    }
+
+    #[test]
+    fn test_raw_request_encoding() {
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = connect_raw(requests.clone());
+        let ext = ExtensionInfo::query(&conn, "RAW-TEST").unwrap().unwrap();
+
+        // padded to 4 bytes, length in 4 bytes units
+        let reply = conn
+            .send_raw_request(&ext, 0, &[1, 2, 3, 4, 5], true)
+            .get_reply_raw()
+            .unwrap();
+        assert_eq!(reply.len(), 40);
+        assert_eq!(reply[1], 7);
+        assert_eq!(&reply[32..40], &[134, 0, 3, 0, 1, 2, 3, 4]);
+        assert_eq!(
+            requests.lock().unwrap()[0],
+            [134, 0, 3, 0, 1, 2, 3, 4, 5, 0, 0, 0]
+        );
+
+        // void request with an error
+        let err = match conn
+            .send_raw_request(&ext, 1, &[0; 4], false)
+            .request_check()
+        {
+            Err(crate::Error::Protocol(crate::ProtocolError::Unknown(err, None))) => err,
+            res => panic!("unexpected result: {:?}", res),
+        };
+        assert_eq!(err.error_code(), 160);
+        assert_eq!(err.major_code(), 134);
+        assert_eq!(err.resource_id(), 0xdead);
+
+        // the error of a dropped cookie doesn't reach the event loop
+        drop(conn.send_raw_request(&ext, 1, &[], false));
+        assert!(conn
+            .send_raw_request(&ext, 0, &[], true)
+            .request_check()
+            .is_ok());
+        assert!(conn.poll_for_queued_event().unwrap().is_none());
+        assert_eq!(requests.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_raw_big_request() {
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = connect_raw(requests.clone());
+        let ext = ExtensionInfo::query(&conn, "RAW-TEST").unwrap().unwrap();
+
+        // more than the 65535 units allowed without BIG-REQUESTS
+        let body: Vec<u8> = (0..70_000u32 * 4).map(|i| i as u8).collect();
+        conn.send_raw_request(&ext, 0, &body, true)
+            .get_reply_raw()
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let req = &requests[0];
+        assert_eq!(&req[..4], &[134, 0, 0, 0]);
+        assert_eq!(
+            u32::from_ne_bytes([req[4], req[5], req[6], req[7]]),
+            70_000 + 2
+        );
+        assert_eq!(&req[8..], &body[..]);
+    }
+
+    #[test]
+    fn test_raw_core_request() {
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = connect_raw(requests.clone());
+
+        // InternAtom with only_if_exists in the data byte of the header
+        let reply = conn
+            .send_raw_core_request(16, 1, &[3, 0, 0, 0, b'F', b'O', b'O'], true)
+            .get_reply_raw()
+            .unwrap();
+        assert_eq!(
+            u32::from_ne_bytes([reply[8], reply[9], reply[10], reply[11]]),
+            0x42
+        );
+        assert_eq!(
+            requests.lock().unwrap()[0],
+            [16, 1, 3, 0, 3, 0, 0, 0, b'F', b'O', b'O', 0]
+        );
+    }
+}
@@ -0,0 +1,560 @@
+//! Coalescing of `Expose` events into redraw regions.
+
+use crate::event::Event;
+use crate::x;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A rectangle with exclusive bottom-right corner.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Bounds {
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+}
+
+impl Bounds {
+    fn from_rectangle(rect: &x::Rectangle) -> Bounds {
+        Bounds {
+            x1: rect.x as i32,
+            y1: rect.y as i32,
+            x2: rect.x as i32 + rect.width as i32,
+            y2: rect.y as i32 + rect.height as i32,
+        }
+    }
+
+    fn to_rectangle(self) -> x::Rectangle {
+        x::Rectangle {
+            x: self.x1 as i16,
+            y: self.y1 as i16,
+            width: (self.x2 - self.x1) as u16,
+            height: (self.y2 - self.y1) as u16,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.x1 >= self.x2 || self.y1 >= self.y2
+    }
+
+    fn intersects(&self, other: &Bounds) -> bool {
+        self.x1 < other.x2 && other.x1 < self.x2 && self.y1 < other.y2 && other.y1 < self.y2
+    }
+
+    /// The parts of `self` outside of `other`, as up to 4 disjoint boxes.
+    fn subtract(&self, other: &Bounds, out: &mut Vec<Bounds>) {
+        if !self.intersects(other) {
+            out.push(*self);
+            return;
+        }
+        let (y1, y2) = (self.y1.max(other.y1), self.y2.min(other.y2));
+        let pieces = [
+            // above, below, then left and right of the intersection
+            Bounds { y2: y1, ..*self },
+            Bounds { y1: y2, ..*self },
+            Bounds {
+                y1,
+                y2,
+                x2: other.x1,
+                ..*self
+            },
+            Bounds {
+                y1,
+                y2,
+                x1: other.x2,
+                ..*self
+            },
+        ];
+        out.extend(pieces.iter().filter(|b| !b.is_empty()));
+    }
+
+    /// The box covering exactly `self` and `other`, if there is one.
+    fn merge(&self, other: &Bounds) -> Option<Bounds> {
+        if self.x1 == other.x1
+            && self.x2 == other.x2
+            && (self.y2 == other.y1 || other.y2 == self.y1)
+        {
+            Some(Bounds {
+                y1: self.y1.min(other.y1),
+                y2: self.y2.max(other.y2),
+                ..*self
+            })
+        } else if self.y1 == other.y1
+            && self.y2 == other.y2
+            && (self.x2 == other.x1 || other.x2 == self.x1)
+        {
+            Some(Bounds {
+                x1: self.x1.min(other.x1),
+                x2: self.x2.max(other.x2),
+                ..*self
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// A client-side region: a set of pixels stored as disjoint rectangles.
+///
+/// Adjacent rectangles that form a rectangle together are merged, such that
+/// the exposure of a window in slices yields a single rectangle.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegionData {
+    boxes: Vec<Bounds>,
+}
+
+impl RegionData {
+    /// Creates an empty region.
+    pub fn new() -> RegionData {
+        RegionData::default()
+    }
+
+    /// Creates the union of `rectangles`.
+    pub fn from_rectangles(rectangles: &[x::Rectangle]) -> RegionData {
+        let mut region = RegionData::new();
+        for rect in rectangles {
+            region.add_rectangle(rect);
+        }
+        region
+    }
+
+    /// Whether the region contains no pixel.
+    pub fn is_empty(&self) -> bool {
+        self.boxes.is_empty()
+    }
+
+    /// Adds the pixels of `rect` to the region.
+    pub fn add_rectangle(&mut self, rect: &x::Rectangle) {
+        let rect = Bounds::from_rectangle(rect);
+        if rect.is_empty() {
+            return;
+        }
+        let mut pieces = vec![rect];
+        let mut rest = Vec::new();
+        for existing in &self.boxes {
+            for piece in pieces.drain(..) {
+                piece.subtract(existing, &mut rest);
+            }
+            std::mem::swap(&mut pieces, &mut rest);
+        }
+        self.boxes.extend(pieces);
+        self.coalesce();
+    }
+
+    /// Adds the pixels of `other` to the region.
+    pub fn union(&mut self, other: &RegionData) {
+        for b in &other.boxes {
+            self.add_rectangle(&b.to_rectangle());
+        }
+    }
+
+    /// Merges the rectangles that form a rectangle together, until none does.
+    fn coalesce(&mut self) {
+        let mut merged = true;
+        while merged {
+            merged = false;
+            'search: for i in 0..self.boxes.len() {
+                for j in i + 1..self.boxes.len() {
+                    if let Some(b) = self.boxes[i].merge(&self.boxes[j]) {
+                        self.boxes[i] = b;
+                        self.boxes.swap_remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The disjoint rectangles of the region, e.g. for `x::SetClipRectangles`
+    /// or `xfixes::CreateRegion`.
+    pub fn rectangles(&self) -> Vec<x::Rectangle> {
+        self.boxes.iter().map(|b| b.to_rectangle()).collect()
+    }
+
+    /// The smallest rectangle containing the region, or `None` if it is empty.
+    pub fn extents(&self) -> Option<x::Rectangle> {
+        let first = *self.boxes.first()?;
+        let extents = self.boxes.iter().fold(first, |e, b| Bounds {
+            x1: e.x1.min(b.x1),
+            y1: e.y1.min(b.y1),
+            x2: e.x2.max(b.x2),
+            y2: e.y2.max(b.y2),
+        });
+        Some(extents.to_rectangle())
+    }
+
+    /// The number of pixels of the region.
+    pub fn area(&self) -> u64 {
+        self.boxes
+            .iter()
+            .map(|b| (b.x2 - b.x1) as u64 * (b.y2 - b.y1) as u64)
+            .sum()
+    }
+
+    /// Whether the pixel at `x`, `y` is in the region.
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        self.boxes
+            .iter()
+            .any(|b| b.x1 <= x && x < b.x2 && b.y1 <= y && y < b.y2)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Exposure {
+    /// The series being received.
+    series: RegionData,
+    /// The complete series, waiting for a tick.
+    held: RegionData,
+    /// The complete series, ready to be redrawn.
+    ready: RegionData,
+}
+
+/// Accumulates the `Expose` events of windows into one redraw region per series.
+///
+/// The server sends the exposure of a window as a series of `Expose` events, whose `count`
+/// field is the number of events that follow in the series. [ExposeCollector::handle_event]
+/// is meant to be called from the event loop with every event: the rectangles of a series
+/// are merged, and the region is [ready](ExposeCollector::ready) when the last event
+/// of the series (with a `count` of 0) is received.
+///
+/// Optionally, the redraws can be capped to a frame rate with
+/// [ExposeCollector::set_min_interval]: the complete series are then held until
+/// the next [ExposeCollector::tick] that happens at least the interval after the previous one,
+/// and the series completed in between are merged into one redraw.
+///
+/// # Example
+/// ```no_run
+/// # fn redraw(_: xcb::x::Window, _: &[xcb::x::Rectangle]) {}
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, _) = xcb::Connection::connect(None)?;
+/// let mut collector = xcb::redraw::ExposeCollector::new();
+/// loop {
+///     let event = conn.wait_for_event()?;
+///     collector.handle_event(&event);
+///     for window in collector.ready_windows() {
+///         let region = collector.ready(window).unwrap();
+///         redraw(window, &region.rectangles());
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ExposeCollector {
+    windows: HashMap<x::Window, Exposure>,
+    min_interval: Option<Duration>,
+    last_tick: Option<Instant>,
+}
+
+impl ExposeCollector {
+    /// Creates a collector that yields the regions as soon as their series is complete.
+    pub fn new() -> ExposeCollector {
+        ExposeCollector::default()
+    }
+
+    /// Sets the minimum interval between two releases of the complete series, or `None` to release
+    /// them without waiting for [ExposeCollector::tick].
+    ///
+    /// The series that are already complete are released when the cap is removed.
+    pub fn set_min_interval(&mut self, min_interval: Option<Duration>) {
+        self.min_interval = min_interval;
+        if min_interval.is_none() {
+            self.release();
+        }
+    }
+
+    /// Accumulates `event` if it is an `Expose` event, and returns whether it was.
+    ///
+    /// `DestroyNotify` events discard the exposures of the destroyed window.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::X(x::Event::Expose(ev)) => {
+                let exposure = self.windows.entry(ev.window()).or_default();
+                exposure.series.add_rectangle(&x::Rectangle {
+                    x: ev.x() as i16,
+                    y: ev.y() as i16,
+                    width: ev.width(),
+                    height: ev.height(),
+                });
+                if ev.count() == 0 {
+                    let series = std::mem::take(&mut exposure.series);
+                    if self.min_interval.is_some() {
+                        exposure.held.union(&series);
+                    } else {
+                        exposure.ready.union(&series);
+                    }
+                }
+                true
+            }
+            Event::X(x::Event::DestroyNotify(ev)) => {
+                self.windows.remove(&ev.window());
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Releases the held series if the minimum interval elapsed since the previous release.
+    ///
+    /// Meant to be called once per frame. Returns whether the series were released.
+    /// Always releases without minimum interval.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let due = match (self.min_interval, self.last_tick) {
+            (Some(interval), Some(last)) => now.saturating_duration_since(last) >= interval,
+            _ => true,
+        };
+        if due {
+            self.last_tick = Some(now);
+            self.release();
+        }
+        due
+    }
+
+    fn release(&mut self) {
+        for exposure in self.windows.values_mut() {
+            let held = std::mem::take(&mut exposure.held);
+            exposure.ready.union(&held);
+        }
+    }
+
+    /// Takes the region to redraw in `window`, if a series is complete since the last call.
+    pub fn ready(&mut self, window: x::Window) -> Option<RegionData> {
+        let exposure = self.windows.get_mut(&window)?;
+        if exposure.ready.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut exposure.ready))
+        }
+    }
+
+    /// The windows that have a region ready to redraw.
+    pub fn ready_windows(&self) -> Vec<x::Window> {
+        self.windows
+            .iter()
+            .filter(|(_, exposure)| !exposure.ready.is_empty())
+            .map(|(window, _)| *window)
+            .collect()
+    }
+
+    /// Whether `window` has a series of `Expose` events being received.
+    pub fn is_pending(&self, window: x::Window) -> bool {
+        self.windows
+            .get(&window)
+            .is_some_and(|exposure| !exposure.series.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i16, y: i16, width: u16, height: u16) -> x::Rectangle {
+        x::Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn sorted(region: &RegionData) -> Vec<(i16, i16, u16, u16)> {
+        let mut rects: Vec<_> = region
+            .rectangles()
+            .iter()
+            .map(|r| (r.x, r.y, r.width, r.height))
+            .collect();
+        rects.sort_unstable();
+        rects
+    }
+
+    fn expose(window: u32, x: u16, y: u16, width: u16, height: u16, count: u16) -> Event {
+        use crate::XidNew;
+
+        let window = unsafe { x::Window::new(window) };
+        Event::X(x::Event::Expose(x::ExposeEvent::new(
+            window, x, y, width, height, count,
+        )))
+    }
+
+    #[test]
+    fn test_region_union() {
+        let mut region = RegionData::new();
+        assert!(region.is_empty());
+        assert!(region.extents().is_none());
+
+        region.add_rectangle(&rect(0, 0, 10, 10));
+        // contained
+        region.add_rectangle(&rect(2, 2, 5, 5));
+        assert_eq!(sorted(&region), [(0, 0, 10, 10)]);
+        // empty
+        region.add_rectangle(&rect(20, 20, 0, 5));
+        assert_eq!(sorted(&region), [(0, 0, 10, 10)]);
+
+        // overlapping: the new pixels are added as disjoint rectangles
+        region.add_rectangle(&rect(5, 5, 10, 10));
+        assert_eq!(region.area(), 100 + 100 - 25);
+        assert_eq!(
+            sorted(&region),
+            [(0, 0, 10, 10), (5, 10, 10, 5), (10, 5, 5, 5)]
+        );
+        let extents = region.extents().unwrap();
+        assert_eq!(
+            (extents.x, extents.y, extents.width, extents.height),
+            (0, 0, 15, 15)
+        );
+        assert!(region.contains(12, 7));
+        assert!(!region.contains(12, 2));
+        assert!(!region.contains(15, 7));
+
+        // adjacent slices are merged
+        let region =
+            RegionData::from_rectangles(&[rect(0, 0, 10, 4), rect(0, 8, 10, 2), rect(0, 4, 10, 4)]);
+        assert_eq!(sorted(&region), [(0, 0, 10, 10)]);
+        let region = RegionData::from_rectangles(&[rect(0, 0, 4, 10), rect(4, 0, 6, 10)]);
+        assert_eq!(sorted(&region), [(0, 0, 10, 10)]);
+
+        // a hole is filled
+        let mut ring = RegionData::from_rectangles(&[
+            rect(0, 0, 9, 3),
+            rect(0, 6, 9, 3),
+            rect(0, 3, 3, 3),
+            rect(6, 3, 3, 3),
+        ]);
+        assert_eq!(ring.area(), 81 - 9);
+        assert!(!ring.contains(4, 4));
+        ring.union(&RegionData::from_rectangles(&[rect(3, 3, 3, 3)]));
+        assert_eq!(sorted(&ring), [(0, 0, 9, 9)]);
+    }
+
+    #[test]
+    fn test_expose_series() {
+        use crate::XidNew;
+
+        let window = unsafe { x::Window::new(0x200) };
+        let mut collector = ExposeCollector::new();
+
+        assert!(collector.handle_event(&expose(0x200, 0, 0, 10, 10, 2)));
+        assert!(collector.handle_event(&expose(0x200, 5, 5, 10, 10, 1)));
+        assert!(collector.is_pending(window));
+        assert!(collector.ready(window).is_none());
+        assert!(collector.ready_windows().is_empty());
+
+        // another window, interleaved
+        assert!(collector.handle_event(&expose(0x300, 0, 0, 1, 1, 0)));
+
+        assert!(collector.handle_event(&expose(0x200, 0, 10, 5, 5, 0)));
+        assert!(!collector.is_pending(window));
+        let mut windows = collector.ready_windows();
+        windows.sort();
+        assert_eq!(windows, [window, unsafe { x::Window::new(0x300) }]);
+
+        let region = collector.ready(window).unwrap();
+        assert_eq!(region.area(), 100 + 100 - 25 + 25);
+        assert_eq!(
+            sorted(&region),
+            [(0, 0, 10, 10), (0, 10, 15, 5), (10, 5, 5, 5)]
+        );
+        // yielded once per series
+        assert!(collector.ready(window).is_none());
+
+        // a second series before the first is consumed is merged with it
+        collector.handle_event(&expose(0x200, 0, 0, 5, 5, 0));
+        collector.handle_event(&expose(0x200, 5, 0, 5, 5, 0));
+        assert_eq!(sorted(&collector.ready(window).unwrap()), [(0, 0, 10, 5)]);
+
+        // destroyed windows are forgotten
+        collector.handle_event(&expose(0x200, 0, 0, 5, 5, 1));
+        let destroy = x::DestroyNotifyEvent::new(window, window);
+        assert!(!collector.handle_event(&Event::X(x::Event::DestroyNotify(destroy))));
+        assert!(!collector.is_pending(window));
+        collector.handle_event(&expose(0x200, 0, 10, 5, 5, 0));
+        assert_eq!(sorted(&collector.ready(window).unwrap()), [(0, 10, 5, 5)]);
+    }
+
+    #[test]
+    fn test_expose_frame_cap() {
+        use crate::XidNew;
+
+        let window = unsafe { x::Window::new(0x200) };
+        let mut collector = ExposeCollector::new();
+        collector.set_min_interval(Some(Duration::from_millis(16)));
+        let start = Instant::now();
+
+        assert!(collector.tick(start));
+        collector.handle_event(&expose(0x200, 0, 0, 10, 10, 0));
+        assert!(collector.ready(window).is_none());
+
+        // too early
+        assert!(!collector.tick(start + Duration::from_millis(10)));
+        assert!(collector.ready(window).is_none());
+        collector.handle_event(&expose(0x200, 10, 0, 10, 10, 0));
+
+        // both series are merged into one redraw
+        assert!(collector.tick(start + Duration::from_millis(16)));
+        assert_eq!(sorted(&collector.ready(window).unwrap()), [(0, 0, 20, 10)]);
+        assert!(collector.ready(window).is_none());
+
+        // removing the cap releases the held series
+        collector.handle_event(&expose(0x200, 0, 0, 1, 1, 0));
+        collector.set_min_interval(None);
+        assert_eq!(sorted(&collector.ready(window).unwrap()), [(0, 0, 1, 1)]);
+        collector.handle_event(&expose(0x200, 0, 0, 2, 2, 0));
+        assert_eq!(sorted(&collector.ready(window).unwrap()), [(0, 0, 2, 2)]);
+    }
+
+    #[test]
+    fn test_expose_obscured_window() {
+        use crate::mock;
+        use crate::XidNew;
+
+        /// An `Expose` event as sent by the server after the request of sequence `seq`.
+        fn expose_event(seq: u16, window: u32, rect: [u16; 4], count: u16) -> Vec<u8> {
+            let mut ev = vec![12, 0];
+            ev.extend_from_slice(&seq.to_ne_bytes());
+            ev.extend_from_slice(&window.to_ne_bytes());
+            for v in rect.iter().chain(&[count]) {
+                ev.extend_from_slice(&v.to_ne_bytes());
+            }
+            ev.resize(32, 0);
+            ev
+        }
+
+        // the window 0x300 covers the area 20,20 60x40 of the window 0x200
+        let conn = mock::connect(|req| match req.opcode {
+            // ConfigureWindow of 0x300: it moves away and reveals its area of 0x200
+            // in 3 bands, the middle one in 2 parts
+            12 if req.u32_at(4) == 0x300 => vec![
+                expose_event(req.seq, 0x200, [20, 20, 60, 10], 3),
+                expose_event(req.seq, 0x200, [20, 30, 25, 20], 2),
+                expose_event(req.seq, 0x200, [45, 30, 35, 20], 1),
+                expose_event(req.seq, 0x200, [20, 50, 60, 10], 0),
+            ],
+            _ => mock::default_response(req),
+        });
+
+        let window = unsafe { x::Window::new(0x200) };
+        let obscuring = unsafe { x::Window::new(0x300) };
+        conn.send_request(&x::ConfigureWindow {
+            window: obscuring,
+            value_list: &[x::ConfigWindow::X(200)],
+        });
+        // wait for the events that precede the reply
+        conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+            .unwrap();
+
+        let mut collector = ExposeCollector::new();
+        let mut redraws = Vec::new();
+        while let Some(event) = conn.poll_for_queued_event().unwrap() {
+            collector.handle_event(&event);
+            for window in collector.ready_windows() {
+                redraws.push((window, collector.ready(window).unwrap()));
+            }
+        }
+
+        assert_eq!(redraws.len(), 1);
+        assert_eq!(redraws[0].0, window);
+        assert_eq!(sorted(&redraws[0].1), [(20, 20, 60, 40)]);
+    }
+}
@@ -0,0 +1,410 @@
+//! Negotiated version and capabilities of the RENDER extension.
+
+use super::{
+    Color, CreateConicalGradient, CreateLinearGradient, CreateRadialGradient, CreateSolidFill,
+    Fixed, PictOp, Picture, Pointfix, QueryFilters, QueryVersion, XNAME,
+};
+use crate::base::{Connection, Result, ResultExt, UnsupportedError, Xid};
+use crate::x;
+
+use std::cell::RefCell;
+
+/// The latest version of the RENDER protocol known to the crate.
+const CLIENT_VERSION: (u32, u32) = (0, 11);
+
+/// The version that introduced the image filters (`SetPictureFilter`, `QueryFilters`).
+const FILTERS_VERSION: (u32, u32) = (0, 6);
+
+/// The version that introduced the solid fills and the gradients.
+const GRADIENTS_VERSION: (u32, u32) = (0, 10);
+
+impl PictOp {
+    /// The version of the RENDER protocol that introduced the operator.
+    ///
+    /// The Porter-Duff operators are part of the initial protocol, the disjoint and conjoint
+    /// operators were added in 0.2, and the PDF blend modes (`Multiply` and above) in 0.11.
+    pub fn required_version(self) -> (u32, u32) {
+        if self <= PictOp::Saturate {
+            (0, 0)
+        } else if self <= PictOp::ConjointXor {
+            (0, 2)
+        } else {
+            (0, 11)
+        }
+    }
+}
+
+/// The RENDER extension, as negotiated with the server.
+///
+/// The handle tells which operators and features the server supports, and creates
+/// the source pictures that require a recent version, failing with an
+/// [UnsupportedError] if the server is too old instead of with a protocol error.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # use xcb::render;
+/// let (conn, screen_num) =
+///     xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Render], &[])?;
+/// let render = render::Handle::new(&conn, screen_num)?;
+/// let op = if render.supports_op(render::PictOp::Multiply) {
+///     render::PictOp::Multiply
+/// } else {
+///     render::PictOp::Over
+/// };
+/// # Ok(())
+/// # }
+/// ```
+pub struct Handle<'a> {
+    conn: &'a Connection,
+    root: x::Window,
+    version: (u32, u32),
+    /// The filters of the screen, queried on first use.
+    filters: RefCell<Option<Vec<String>>>,
+}
+
+impl<'a> std::fmt::Debug for Handle<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle")
+            .field("root", &self.root)
+            .field("version", &self.version)
+            .field("filters", &self.filters)
+            .finish()
+    }
+}
+
+impl<'a> Handle<'a> {
+    /// Negotiates the version of the extension, for the screen `screen`.
+    ///
+    /// # Panics
+    /// Panics if `screen` is not a screen of the connection.
+    pub fn new(conn: &'a Connection, screen: i32) -> Result<Handle<'a>> {
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen as usize)
+            .unwrap_or_else(|| panic!("no screen {}", screen))
+            .root();
+        let cookie = conn.send_request(&QueryVersion {
+            client_major_version: CLIENT_VERSION.0,
+            client_minor_version: CLIENT_VERSION.1,
+        });
+        let reply = conn
+            .wait_for_reply(cookie)
+            .context("QueryVersion", || XNAME)?;
+        Ok(Handle {
+            conn,
+            root,
+            // the server replies with its own version if it is older
+            version: CLIENT_VERSION.min((reply.major_version(), reply.minor_version())),
+            filters: RefCell::new(None),
+        })
+    }
+
+    /// The negotiated version, major and minor.
+    pub fn version(&self) -> (u32, u32) {
+        self.version
+    }
+
+    /// Whether the server supports the compositing operator `op`.
+    pub fn supports_op(&self, op: PictOp) -> bool {
+        self.version >= op.required_version()
+    }
+
+    /// Whether the server supports the solid fill and gradient pictures.
+    pub fn supports_gradients(&self) -> bool {
+        self.version >= GRADIENTS_VERSION
+    }
+
+    /// Whether the server supports all the image filters `names` (e.g. `"bilinear"`)
+    /// on the screen of the handle.
+    ///
+    /// The filters are queried with `QueryFilters` on the first call, and cached.
+    pub fn supports_filters(&self, names: &[&str]) -> Result<bool> {
+        if self.version < FILTERS_VERSION {
+            return Ok(false);
+        }
+        let mut filters = self.filters.borrow_mut();
+        if filters.is_none() {
+            let cookie = self.conn.send_request(&QueryFilters {
+                drawable: x::Drawable::Window(self.root),
+            });
+            let reply = self
+                .conn
+                .wait_for_reply(cookie)
+                .context("QueryFilters", || {
+                    format!("of root {:#x}", self.root.resource_id())
+                })?;
+            *filters = Some(
+                reply
+                    .filters()
+                    .map(|filter| filter.name().to_utf8().into_owned())
+                    .collect(),
+            );
+        }
+        let filters = filters.as_ref().unwrap();
+        Ok(names
+            .iter()
+            .all(|name| filters.iter().any(|filter| filter == name)))
+    }
+
+    /// Creates a picture filled with `color`.
+    pub fn create_solid_fill(&self, color: Color) -> Result<Picture> {
+        self.require("CreateSolidFill", GRADIENTS_VERSION)?;
+        let picture = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&CreateSolidFill { picture, color })
+            .context("CreateSolidFill", String::new)?;
+        Ok(picture)
+    }
+
+    /// Creates a linear gradient from `p1` to `p2`, with the colors `colors` at the
+    /// positions `stops` (from 0 to 1).
+    ///
+    /// # Panics
+    /// Panics if `stops` and `colors` have different lengths.
+    pub fn create_linear_gradient(
+        &self,
+        p1: Pointfix,
+        p2: Pointfix,
+        stops: &[Fixed],
+        colors: &[Color],
+    ) -> Result<Picture> {
+        assert_eq!(stops.len(), colors.len(), "a color for each stop");
+        self.require("CreateLinearGradient", GRADIENTS_VERSION)?;
+        let picture = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&CreateLinearGradient {
+                picture,
+                p1,
+                p2,
+                stops,
+                colors,
+            })
+            .context("CreateLinearGradient", String::new)?;
+        Ok(picture)
+    }
+
+    /// Creates a radial gradient from the `inner` circle to the `outer` circle, each given
+    /// as its center and radius, with the colors `colors` at the positions `stops` (from 0 to 1).
+    ///
+    /// # Panics
+    /// Panics if `stops` and `colors` have different lengths.
+    pub fn create_radial_gradient(
+        &self,
+        inner: (Pointfix, Fixed),
+        outer: (Pointfix, Fixed),
+        stops: &[Fixed],
+        colors: &[Color],
+    ) -> Result<Picture> {
+        assert_eq!(stops.len(), colors.len(), "a color for each stop");
+        self.require("CreateRadialGradient", GRADIENTS_VERSION)?;
+        let picture = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&CreateRadialGradient {
+                picture,
+                inner: inner.0,
+                outer: outer.0,
+                inner_radius: inner.1,
+                outer_radius: outer.1,
+                stops,
+                colors,
+            })
+            .context("CreateRadialGradient", String::new)?;
+        Ok(picture)
+    }
+
+    /// Creates a conical gradient around `center`, starting at `angle` (in degrees), with
+    /// the colors `colors` at the positions `stops` (from 0 to 1).
+    ///
+    /// # Panics
+    /// Panics if `stops` and `colors` have different lengths.
+    pub fn create_conical_gradient(
+        &self,
+        center: Pointfix,
+        angle: Fixed,
+        stops: &[Fixed],
+        colors: &[Color],
+    ) -> Result<Picture> {
+        assert_eq!(stops.len(), colors.len(), "a color for each stop");
+        self.require("CreateConicalGradient", GRADIENTS_VERSION)?;
+        let picture = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&CreateConicalGradient {
+                picture,
+                center,
+                angle,
+                stops,
+                colors,
+            })
+            .context("CreateConicalGradient", String::new)?;
+        Ok(picture)
+    }
+
+    fn require(&self, feature: &'static str, required: (u32, u32)) -> Result<()> {
+        if self.version >= required {
+            Ok(())
+        } else {
+            Err(UnsupportedError {
+                extension: XNAME,
+                feature,
+                required,
+                version: self.version,
+            }
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_required_version() {
+        assert_eq!(PictOp::Clear.required_version(), (0, 0));
+        assert_eq!(PictOp::Over.required_version(), (0, 0));
+        assert_eq!(PictOp::Saturate.required_version(), (0, 0));
+        assert_eq!(PictOp::DisjointClear.required_version(), (0, 2));
+        assert_eq!(PictOp::DisjointXor.required_version(), (0, 2));
+        assert_eq!(PictOp::ConjointClear.required_version(), (0, 2));
+        assert_eq!(PictOp::ConjointXor.required_version(), (0, 2));
+        assert_eq!(PictOp::Multiply.required_version(), (0, 11));
+        assert_eq!(PictOp::HslLuminosity.required_version(), (0, 11));
+        // no operator requires a version newer than the one the crate negotiates
+        for op in [PictOp::Clear, PictOp::ConjointXor, PictOp::HslLuminosity] {
+            assert!(op.required_version() <= CLIENT_VERSION);
+        }
+    }
+
+    /// Connects to a mock server of RENDER `version`, that counts the requests of `opcode`.
+    fn mock_connection(
+        version: (u32, u32),
+        opcode: u8,
+        count: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    ) -> Connection {
+        use crate::mock;
+        use std::sync::atomic::Ordering;
+
+        const RENDER_OPCODE: u8 = 139;
+
+        mock::connect_with_extensions(
+            move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(req, RENDER_OPCODE, 0, 140)],
+                RENDER_OPCODE => {
+                    if req.data[1] == opcode {
+                        count.fetch_add(1, Ordering::SeqCst);
+                    }
+                    match req.data[1] {
+                        // QueryVersion
+                        0 => {
+                            let mut body = vec![0u8; 8];
+                            body[0..4].copy_from_slice(&version.0.to_ne_bytes());
+                            body[4..8].copy_from_slice(&version.1.to_ne_bytes());
+                            vec![mock::reply(req.seq, 0, &body)]
+                        }
+                        // QueryFilters
+                        29 => {
+                            let filters: &[&[u8]] = &[b"nearest", b"bilinear", b"fast"];
+                            let mut body = vec![0u8; 24];
+                            body[4..8].copy_from_slice(&(filters.len() as u32).to_ne_bytes());
+                            for filter in filters {
+                                body.push(filter.len() as u8);
+                                body.extend_from_slice(filter);
+                            }
+                            vec![mock::reply(req.seq, 0, &body)]
+                        }
+                        _ => vec![],
+                    }
+                }
+                _ => mock::default_response(req),
+            },
+            &[crate::Extension::Render],
+        )
+    }
+
+    #[test]
+    fn test_old_server() {
+        use crate::Error;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // CreateLinearGradient
+        let count = Arc::new(AtomicUsize::new(0));
+        let conn = mock_connection((0, 5), 34, count.clone());
+        let render = Handle::new(&conn, 0).unwrap();
+        assert_eq!(render.version(), (0, 5));
+
+        assert!(render.supports_op(PictOp::Over));
+        assert!(render.supports_op(PictOp::DisjointOver));
+        assert!(!render.supports_op(PictOp::Multiply));
+        assert!(!render.supports_gradients());
+        assert!(!render.supports_filters(&["bilinear"]).unwrap());
+
+        let color = Color {
+            red: 0xffff,
+            green: 0,
+            blue: 0,
+            alpha: 0xffff,
+        };
+        let p = |x| Pointfix { x, y: 0 };
+        let err = render
+            .create_linear_gradient(p(0), p(100 << 16), &[0, 1 << 16], &[color, color])
+            .unwrap_err();
+        match err {
+            Error::Unsupported(err) => {
+                assert_eq!(
+                    err,
+                    UnsupportedError {
+                        extension: "RENDER",
+                        feature: "CreateLinearGradient",
+                        required: (0, 10),
+                        version: (0, 5),
+                    }
+                );
+                assert_eq!(
+                    err.to_string(),
+                    "CreateLinearGradient requires RENDER 0.10, the server supports 0.5"
+                );
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert!(matches!(
+            render.create_solid_fill(color),
+            Err(Error::Unsupported(_))
+        ));
+        // the request is not sent
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_recent_server() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // QueryFilters
+        let count = Arc::new(AtomicUsize::new(0));
+        // the server is more recent than the crate
+        let conn = mock_connection((0, 12), 29, count.clone());
+        let render = Handle::new(&conn, 0).unwrap();
+        assert_eq!(render.version(), CLIENT_VERSION);
+
+        assert!(render.supports_op(PictOp::HslLuminosity));
+        assert!(render.supports_gradients());
+        assert!(render.supports_filters(&["bilinear", "nearest"]).unwrap());
+        assert!(render.supports_filters(&[]).unwrap());
+        assert!(!render
+            .supports_filters(&["bilinear", "convolution"])
+            .unwrap());
+        // the filters are queried once
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let color = Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: 0xffff,
+        };
+        assert!(render.create_solid_fill(color).is_ok());
+    }
+}
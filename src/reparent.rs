@@ -0,0 +1,458 @@
+//! Reparenting with a report of the resulting events.
+//!
+//! When a window is reparented, its position is the one of the outer corner of its border,
+//! relative to the new parent, and the events of the reparenting arrive in the event queue
+//! after the events that were generated in the old parent. [safe_reparent] takes the events
+//! of the reparenting out of the queue and returns them with the geometry of the window
+//! before and after, such that the event loop is left with the other events only.
+
+use crate::base::{Connection, Cookie, Result, ResultExt, Xid};
+use crate::x;
+
+const UNMAP_NOTIFY: u8 = 18;
+const MAP_NOTIFY: u8 = 19;
+const REPARENT_NOTIFY: u8 = 21;
+
+/// The geometry of a window relative to its parent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WindowGeometry {
+    /// The position of the outer corner of the border.
+    pub x: i16,
+    pub y: i16,
+    /// The size of the window, without the border.
+    pub width: u16,
+    pub height: u16,
+    pub border_width: u16,
+}
+
+impl WindowGeometry {
+    fn from_reply(reply: &x::GetGeometryReply) -> WindowGeometry {
+        WindowGeometry {
+            x: reply.x(),
+            y: reply.y(),
+            width: reply.width(),
+            height: reply.height(),
+            border_width: reply.border_width(),
+        }
+    }
+
+    /// The position of the origin of the window (inside the border) relative to its parent.
+    pub fn content_origin(&self) -> (i32, i32) {
+        let border = self.border_width as i32;
+        (self.x as i32 + border, self.y as i32 + border)
+    }
+}
+
+/// An event generated by a `ReparentWindow` request.
+///
+/// `event` is the window that received the event: the reparented window itself,
+/// or one of its parents if `SubstructureNotify` is selected on them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReparentEvent {
+    /// The window was mapped, and is unmapped before the reparenting.
+    Unmap { event: x::Window },
+    /// The window is reparented into `parent`, at (`x`, `y`).
+    Reparent {
+        event: x::Window,
+        parent: x::Window,
+        x: i16,
+        y: i16,
+    },
+    /// The window was mapped, and is mapped again after the reparenting.
+    Map { event: x::Window },
+}
+
+impl ReparentEvent {
+    /// Classifies `event` if it concerns `window`.
+    ///
+    /// Returns `None` for the events that can't be generated by the reparenting of `window`.
+    pub fn classify(event: &crate::Event, window: x::Window) -> Option<ReparentEvent> {
+        match event {
+            crate::Event::X(x::Event::UnmapNotify(ev)) if ev.window() == window => {
+                Some(ReparentEvent::Unmap { event: ev.event() })
+            }
+            crate::Event::X(x::Event::ReparentNotify(ev)) if ev.window() == window => {
+                Some(ReparentEvent::Reparent {
+                    event: ev.event(),
+                    parent: ev.parent(),
+                    x: ev.x(),
+                    y: ev.y(),
+                })
+            }
+            crate::Event::X(x::Event::MapNotify(ev)) if ev.window() == window => {
+                Some(ReparentEvent::Map { event: ev.event() })
+            }
+            _ => None,
+        }
+    }
+
+    /// The window that received the event.
+    pub fn event_window(&self) -> x::Window {
+        match *self {
+            ReparentEvent::Unmap { event }
+            | ReparentEvent::Reparent { event, .. }
+            | ReparentEvent::Map { event } => event,
+        }
+    }
+}
+
+/// Whether the raw event `ev` is generated for `window` by the request of sequence `sequence`.
+fn is_reparent_event(ev: &[u8], window: x::Window, sequence: u16) -> bool {
+    let code = ev[0] & 0x7f;
+    let seq = u16::from_ne_bytes([ev[2], ev[3]]);
+    // the three events have the reparented window at the same offset
+    let win = u32::from_ne_bytes([ev[8], ev[9], ev[10], ev[11]]);
+    matches!(code, UNMAP_NOTIFY | MAP_NOTIFY | REPARENT_NOTIFY)
+        && seq == sequence
+        && win == window.resource_id()
+}
+
+/// The result of [safe_reparent].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReparentReceipt {
+    pub window: x::Window,
+    pub old_parent: x::Window,
+    pub new_parent: x::Window,
+    /// Whether the window was mapped before the reparenting.
+    pub was_mapped: bool,
+    /// The geometry relative to the old parent.
+    pub before: WindowGeometry,
+    /// The geometry relative to the new parent.
+    pub after: WindowGeometry,
+    /// The events generated by the reparenting, in order.
+    pub events: Vec<ReparentEvent>,
+}
+
+impl ReparentReceipt {
+    /// The events received by the reparented window itself.
+    pub fn window_events(&self) -> impl Iterator<Item = &ReparentEvent> {
+        let window = self.window;
+        self.events
+            .iter()
+            .filter(move |ev| ev.event_window() == window)
+    }
+
+    /// Whether the events received by the window are the ones specified by the protocol:
+    /// `UnmapNotify`, `ReparentNotify` and `MapNotify` for a mapped window,
+    /// `ReparentNotify` only otherwise.
+    pub fn matches_protocol(&self) -> bool {
+        let events: Vec<_> = self.window_events().collect();
+        match events.as_slice() {
+            [ReparentEvent::Unmap { .. }, ReparentEvent::Reparent { parent, .. }, ReparentEvent::Map { .. }] => {
+                self.was_mapped && *parent == self.new_parent
+            }
+            [ReparentEvent::Reparent { parent, .. }] => {
+                !self.was_mapped && *parent == self.new_parent
+            }
+            _ => false,
+        }
+    }
+
+    /// The offset to add to the coordinates relative to the origin of the old parent, to make
+    /// them relative to the origin of the new parent.
+    pub fn offset(&self) -> (i32, i32) {
+        let (bx, by) = self.before.content_origin();
+        let (ax, ay) = self.after.content_origin();
+        (ax - bx, ay - by)
+    }
+}
+
+/// Reparents `window` into `new_parent` at (`x`, `y`), and returns the resulting events.
+///
+/// The reparenting is done while the server is grabbed, with `StructureNotify` temporarily
+/// selected on `window`. The events generated by the reparenting are then taken out of the
+/// event queue and returned in the receipt, while the other events (including the ones
+/// generated for `window` before the reparenting) are left in the queue, in order.
+///
+/// Errors are returned with the name of the failed request as context
+/// (see [crate::Error::chain]).
+pub fn safe_reparent(
+    conn: &Connection,
+    window: x::Window,
+    new_parent: x::Window,
+    x: i16,
+    y: i16,
+) -> Result<ReparentReceipt> {
+    let detail = || format!("for window {:#x}", window.resource_id());
+
+    let tree = conn.send_request(&x::QueryTree { window });
+    let geometry = conn.send_request(&x::GetGeometry {
+        drawable: x::Drawable::Window(window),
+    });
+    let attributes = conn.send_request(&x::GetWindowAttributes { window });
+    let tree = conn.wait_for_reply(tree).context("QueryTree", detail)?;
+    let before = conn
+        .wait_for_reply(geometry)
+        .context("GetGeometry", detail)?;
+    let attributes = conn
+        .wait_for_reply(attributes)
+        .context("GetWindowAttributes", detail)?;
+    let event_mask = attributes.your_event_mask();
+    let select = !event_mask.contains(x::EventMask::STRUCTURE_NOTIFY);
+
+    conn.send_request(&x::GrabServer {});
+    if select {
+        conn.send_request(&x::ChangeWindowAttributes {
+            window,
+            value_list: &[x::Cw::EventMask(
+                event_mask | x::EventMask::STRUCTURE_NOTIFY,
+            )],
+        });
+    }
+    let reparent = conn.send_request_checked(&x::ReparentWindow {
+        window,
+        parent: new_parent,
+        x,
+        y,
+    });
+    if select {
+        conn.send_request(&x::ChangeWindowAttributes {
+            window,
+            value_list: &[x::Cw::EventMask(event_mask)],
+        });
+    }
+    conn.send_request(&x::UngrabServer {});
+    let sequence = reparent.sequence() as u16;
+    // the events of the reparenting precede the reply of the check
+    conn.check_request(reparent)
+        .context("ReparentWindow", detail)?;
+    let after = conn.send_request(&x::GetGeometry {
+        drawable: x::Drawable::Window(window),
+    });
+    let after = conn.wait_for_reply(after).context("GetGeometry", detail)?;
+
+    let events = conn
+        .take_queued_events(|ev| is_reparent_event(ev, window, sequence))
+        .iter()
+        .filter_map(|ev| ReparentEvent::classify(ev, window))
+        .collect();
+
+    Ok(ReparentReceipt {
+        window,
+        old_parent: tree.parent(),
+        new_parent,
+        was_mapped: attributes.map_state() != x::MapState::Unmapped,
+        before: WindowGeometry::from_reply(&before),
+        after: WindowGeometry::from_reply(&after),
+        events,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32) -> x::Window {
+        use crate::XidNew;
+        unsafe { x::Window::new(id) }
+    }
+
+    fn geometry(x: i16, y: i16, border_width: u16) -> WindowGeometry {
+        WindowGeometry {
+            x,
+            y,
+            width: 100,
+            height: 50,
+            border_width,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let w = window(0x200);
+        let unmap = crate::Event::X(x::Event::UnmapNotify(x::UnmapNotifyEvent::new(
+            window(0x150),
+            w,
+            false,
+        )));
+        assert_eq!(
+            ReparentEvent::classify(&unmap, w),
+            Some(ReparentEvent::Unmap {
+                event: window(0x150)
+            })
+        );
+        assert_eq!(ReparentEvent::classify(&unmap, window(0x201)), None);
+
+        let reparent = crate::Event::X(x::Event::ReparentNotify(x::ReparentNotifyEvent::new(
+            w,
+            w,
+            window(0x300),
+            5,
+            6,
+            false,
+        )));
+        assert_eq!(
+            ReparentEvent::classify(&reparent, w),
+            Some(ReparentEvent::Reparent {
+                event: w,
+                parent: window(0x300),
+                x: 5,
+                y: 6
+            })
+        );
+
+        let destroy = crate::Event::X(x::Event::DestroyNotify(x::DestroyNotifyEvent::new(w, w)));
+        assert_eq!(ReparentEvent::classify(&destroy, w), None);
+    }
+
+    #[test]
+    fn test_is_reparent_event() {
+        let mut ev = [0u8; 32];
+        ev[0] = REPARENT_NOTIFY | 0x80;
+        ev[2..4].copy_from_slice(&7u16.to_ne_bytes());
+        ev[8..12].copy_from_slice(&0x200u32.to_ne_bytes());
+        assert!(is_reparent_event(&ev, window(0x200), 7));
+        // generated by another request
+        assert!(!is_reparent_event(&ev, window(0x200), 6));
+        assert!(!is_reparent_event(&ev, window(0x201), 7));
+        // ConfigureNotify
+        ev[0] = 22;
+        assert!(!is_reparent_event(&ev, window(0x200), 7));
+    }
+
+    #[test]
+    fn test_receipt() {
+        let (w, old, new) = (window(0x200), window(0x150), window(0x300));
+        let mut receipt = ReparentReceipt {
+            window: w,
+            old_parent: old,
+            new_parent: new,
+            was_mapped: true,
+            before: geometry(10, 20, 2),
+            after: geometry(5, 6, 2),
+            events: vec![
+                ReparentEvent::Unmap { event: w },
+                ReparentEvent::Unmap { event: old },
+                ReparentEvent::Reparent {
+                    event: w,
+                    parent: new,
+                    x: 5,
+                    y: 6,
+                },
+                ReparentEvent::Map { event: w },
+            ],
+        };
+        assert_eq!(receipt.window_events().count(), 3);
+        assert!(receipt.matches_protocol());
+        assert_eq!(receipt.after.content_origin(), (7, 8));
+        assert_eq!(receipt.offset(), (-5, -14));
+
+        receipt.was_mapped = false;
+        assert!(!receipt.matches_protocol());
+        receipt
+            .events
+            .retain(|ev| matches!(ev, ReparentEvent::Reparent { .. }));
+        assert!(receipt.matches_protocol());
+        receipt.new_parent = old;
+        assert!(!receipt.matches_protocol());
+    }
+
+    /// The window 0x200, mapped at (10, 20) in the root window with a border of 2, is reparented
+    /// into 0x300. A `ConfigureNotify` for 0x200 precedes the reparenting.
+    #[test]
+    fn test_safe_reparent() {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        fn event(code: u8, seq: u16, fields: &[u32]) -> Vec<u8> {
+            let mut ev = vec![0u8; 32];
+            ev[0] = code;
+            ev[2..4].copy_from_slice(&seq.to_ne_bytes());
+            for (i, f) in fields.iter().enumerate() {
+                ev[4 + 4 * i..8 + 4 * i].copy_from_slice(&f.to_ne_bytes());
+            }
+            ev
+        }
+
+        let masks = Arc::new(Mutex::new(Vec::new()));
+        let reparented = Arc::new(Mutex::new(false));
+        let conn = {
+            let masks = masks.clone();
+            mock::connect(move |req| match req.opcode {
+                // ChangeWindowAttributes
+                2 => {
+                    masks.lock().unwrap().push(req.u32_at(12));
+                    vec![]
+                }
+                // GetWindowAttributes: viewable, KeyPress selected
+                3 => {
+                    let mut body = vec![0u8; 36];
+                    body[18] = 2;
+                    body[28..32].copy_from_slice(&x::EventMask::KEY_PRESS.bits().to_ne_bytes());
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                // ReparentWindow
+                7 => {
+                    *reparented.lock().unwrap() = true;
+                    let (w, parent) = (req.u32_at(4), req.u32_at(8));
+                    let pos = req.u32_at(12);
+                    vec![
+                        event(UNMAP_NOTIFY, req.seq, &[w, w]),
+                        event(REPARENT_NOTIFY, req.seq, &[w, w, parent, pos]),
+                        event(MAP_NOTIFY, req.seq, &[w, w]),
+                    ]
+                }
+                // GetGeometry
+                14 => {
+                    let mut body = mock::ROOT.to_ne_bytes().to_vec();
+                    let (x, y) = if *reparented.lock().unwrap() {
+                        (5i16, 6i16)
+                    } else {
+                        (10, 20)
+                    };
+                    for v in [x, y, 100, 50, 2] {
+                        body.extend_from_slice(&v.to_ne_bytes());
+                    }
+                    vec![mock::reply(req.seq, 24, &body)]
+                }
+                // QueryTree
+                15 => {
+                    let mut body = mock::ROOT.to_ne_bytes().to_vec();
+                    body.extend_from_slice(&mock::ROOT.to_ne_bytes());
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                // GrabServer: a stale event generated before the reparenting
+                36 => vec![event(22, req.seq, &[0x200, 0x200])],
+                _ => mock::default_response(req),
+            })
+        };
+
+        let receipt = safe_reparent(&conn, window(0x200), window(0x300), 5, 6).unwrap();
+        assert_eq!(receipt.old_parent, window(mock::ROOT));
+        assert!(receipt.was_mapped);
+        assert_eq!(receipt.before, geometry(10, 20, 2));
+        assert_eq!(receipt.after, geometry(5, 6, 2));
+        assert_eq!(
+            receipt.events,
+            [
+                ReparentEvent::Unmap {
+                    event: window(0x200)
+                },
+                ReparentEvent::Reparent {
+                    event: window(0x200),
+                    parent: window(0x300),
+                    x: 5,
+                    y: 6
+                },
+                ReparentEvent::Map {
+                    event: window(0x200)
+                },
+            ]
+        );
+        assert!(receipt.matches_protocol());
+
+        // StructureNotify selected during the reparenting, then the mask restored
+        let key_press = x::EventMask::KEY_PRESS.bits();
+        assert_eq!(
+            *masks.lock().unwrap(),
+            [key_press | x::EventMask::STRUCTURE_NOTIFY.bits(), key_press]
+        );
+
+        // the stale event is left to the event loop
+        assert!(matches!(
+            conn.poll_for_queued_event().unwrap(),
+            Some(crate::Event::X(x::Event::ConfigureNotify(_)))
+        ));
+        assert!(conn.poll_for_queued_event().unwrap().is_none());
+    }
+}
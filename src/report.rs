@@ -0,0 +1,1091 @@
+//! Report of the capabilities of the server, in the spirit of `xdpyinfo`.
+
+use crate::base::{Connection, Result, ResultExt};
+use crate::x;
+
+use std::fmt;
+
+/// An extension advertised by the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerExtension {
+    pub name: String,
+    pub major_opcode: u8,
+    pub first_event: u8,
+    pub first_error: u8,
+}
+
+/// The version of an extension, negotiated with the version of the bindings of this crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionVersion {
+    pub name: String,
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// A visual of a screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VisualSummary {
+    pub id: u32,
+    pub depth: u8,
+    /// The visual class, such as `"TrueColor"`.
+    pub class: String,
+    pub bits_per_rgb_value: u8,
+    pub colormap_entries: u16,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+}
+
+/// A screen, as described in the setup of the connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScreenSummary {
+    pub number: usize,
+    pub root: u32,
+    pub width: u16,
+    pub height: u16,
+    pub width_mm: u16,
+    pub height_mm: u16,
+    pub root_depth: u8,
+    pub root_visual: u32,
+    pub visuals: Vec<VisualSummary>,
+}
+
+/// A pixmap format of the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PixmapFormatSummary {
+    pub depth: u8,
+    pub bits_per_pixel: u8,
+    pub scanline_pad: u8,
+}
+
+/// An adaptor of the `XVideo` extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct XvAdaptorSummary {
+    pub screen: usize,
+    pub name: String,
+    pub base_port: u32,
+    pub num_ports: u16,
+    /// The capabilities of the adaptor, among `input`, `output`, `video`, `still` and `image`.
+    pub kinds: Vec<String>,
+    /// The FourCC codes of the image formats of the adaptor, empty if it is not an image adaptor.
+    pub image_formats: Vec<String>,
+}
+
+/// A mode of a RandR output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RandrModeSummary {
+    pub name: String,
+    pub width: u16,
+    pub height: u16,
+    /// The refresh rate in mHz, 0 if unknown.
+    pub refresh_millihertz: u32,
+    /// Whether the mode is a preferred mode of the output.
+    pub preferred: bool,
+}
+
+/// An output of the RandR extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RandrOutputSummary {
+    pub screen: usize,
+    pub name: String,
+    /// `"connected"`, `"disconnected"` or `"unknown"`.
+    pub connection: String,
+    /// The CRTC driving the output, if any.
+    pub crtc: Option<u32>,
+    pub mm_width: u32,
+    pub mm_height: u32,
+    pub modes: Vec<RandrModeSummary>,
+}
+
+/// A picture format of the RENDER extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PictFormatSummary {
+    pub id: u32,
+    pub depth: u8,
+    /// Whether the format is direct (the masks are meaningful), or indexed.
+    pub direct: bool,
+    pub red_mask: u16,
+    pub green_mask: u16,
+    pub blue_mask: u16,
+    pub alpha_mask: u16,
+}
+
+/// A section of a [CapabilityReport] that could not be gathered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionError {
+    /// The section, such as `"extensions"` or an extension name.
+    pub section: String,
+    pub error: String,
+}
+
+/// The capabilities of the server, returned by [Connection::capability_report].
+///
+/// The sections of the extensions whose cargo feature is not enabled are empty.
+/// Formatting the report with `Display` gives an `xdpyinfo`-like dump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapabilityReport {
+    pub vendor: String,
+    pub release_number: u32,
+    pub protocol_major_version: u16,
+    pub protocol_minor_version: u16,
+    /// The maximum length of a request, in bytes.
+    pub maximum_request_length: u32,
+    pub extensions: Vec<ServerExtension>,
+    pub versions: Vec<ExtensionVersion>,
+    pub pixmap_formats: Vec<PixmapFormatSummary>,
+    pub screens: Vec<ScreenSummary>,
+    pub xv_adaptors: Vec<XvAdaptorSummary>,
+    pub randr_outputs: Vec<RandrOutputSummary>,
+    pub render_formats: Vec<PictFormatSummary>,
+    /// The sections that failed. The report is otherwise complete.
+    pub errors: Vec<SectionError>,
+}
+
+impl CapabilityReport {
+    /// The negotiated version of the extension `name`, if it is present and supported.
+    pub fn version(&self, name: &str) -> Option<(u32, u32)> {
+        self.versions
+            .iter()
+            .find(|v| v.name == name)
+            .map(|v| (v.major, v.minor))
+    }
+
+    fn push_error(&mut self, section: &str, err: crate::Error) {
+        self.errors.push(SectionError {
+            section: section.to_string(),
+            error: err.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "vendor string:    {}", self.vendor)?;
+        writeln!(f, "vendor release number:    {}", self.release_number)?;
+        writeln!(
+            f,
+            "version number:    {}.{}",
+            self.protocol_major_version, self.protocol_minor_version
+        )?;
+        writeln!(
+            f,
+            "maximum request size:  {} bytes",
+            self.maximum_request_length
+        )?;
+        writeln!(f, "number of extensions:    {}", self.extensions.len())?;
+        for ext in &self.extensions {
+            write!(f, "    {} (opcode {}", ext.name, ext.major_opcode)?;
+            if let Some(v) = self.versions.iter().find(|v| v.name == ext.name) {
+                write!(f, ", version {}.{}", v.major, v.minor)?;
+            }
+            writeln!(f, ")")?;
+        }
+        writeln!(
+            f,
+            "number of supported pixmap formats:    {}",
+            self.pixmap_formats.len()
+        )?;
+        for format in &self.pixmap_formats {
+            writeln!(
+                f,
+                "    depth {}, bits_per_pixel {}, scanline_pad {}",
+                format.depth, format.bits_per_pixel, format.scanline_pad
+            )?;
+        }
+        for screen in &self.screens {
+            writeln!(f)?;
+            writeln!(f, "screen #{}:", screen.number)?;
+            writeln!(
+                f,
+                "  dimensions:    {}x{} pixels ({}x{} millimeters)",
+                screen.width, screen.height, screen.width_mm, screen.height_mm
+            )?;
+            writeln!(f, "  root window id:    {:#x}", screen.root)?;
+            writeln!(f, "  depth of root window:    {} planes", screen.root_depth)?;
+            writeln!(f, "  number of visuals:    {}", screen.visuals.len())?;
+            writeln!(f, "  default visual id:  {:#x}", screen.root_visual)?;
+            for visual in &screen.visuals {
+                writeln!(
+                    f,
+                    "    {:#x}: {} depth {}, {} bits per rgb, {} colormap entries, masks {:#x} {:#x} {:#x}",
+                    visual.id,
+                    visual.class,
+                    visual.depth,
+                    visual.bits_per_rgb_value,
+                    visual.colormap_entries,
+                    visual.red_mask,
+                    visual.green_mask,
+                    visual.blue_mask
+                )?;
+            }
+        }
+        if !self.xv_adaptors.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Xv adaptors:")?;
+            for adaptor in &self.xv_adaptors {
+                writeln!(
+                    f,
+                    "  screen #{}: {} ({}), ports {:#x}-{:#x}",
+                    adaptor.screen,
+                    adaptor.name,
+                    adaptor.kinds.join(", "),
+                    adaptor.base_port,
+                    adaptor.base_port + adaptor.num_ports.saturating_sub(1) as u32
+                )?;
+                if !adaptor.image_formats.is_empty() {
+                    writeln!(f, "    image formats: {}", adaptor.image_formats.join(", "))?;
+                }
+            }
+        }
+        if !self.randr_outputs.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "RandR outputs:")?;
+            for output in &self.randr_outputs {
+                write!(
+                    f,
+                    "  screen #{}: {} {}",
+                    output.screen, output.name, output.connection
+                )?;
+                if output.mm_width != 0 || output.mm_height != 0 {
+                    write!(f, " ({}x{} millimeters)", output.mm_width, output.mm_height)?;
+                }
+                writeln!(f)?;
+                for mode in &output.modes {
+                    writeln!(
+                        f,
+                        "    {} {}x{} {}.{:02} Hz{}",
+                        mode.name,
+                        mode.width,
+                        mode.height,
+                        mode.refresh_millihertz / 1000,
+                        mode.refresh_millihertz % 1000 / 10,
+                        if mode.preferred { " (preferred)" } else { "" }
+                    )?;
+                }
+            }
+        }
+        if !self.render_formats.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "RENDER pict formats:")?;
+            for format in &self.render_formats {
+                if format.direct {
+                    writeln!(
+                        f,
+                        "  {:#x}: direct depth {}, masks r {:#x} g {:#x} b {:#x} a {:#x}",
+                        format.id,
+                        format.depth,
+                        format.red_mask,
+                        format.green_mask,
+                        format.blue_mask,
+                        format.alpha_mask
+                    )?;
+                } else {
+                    writeln!(f, "  {:#x}: indexed depth {}", format.id, format.depth)?;
+                }
+            }
+        }
+        if !self.errors.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "errors:")?;
+            for err in &self.errors {
+                writeln!(f, "  {}: {}", err.section, err.error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the summaries of the screens from the setup.
+fn screens(setup: &x::Setup) -> Vec<ScreenSummary> {
+    setup
+        .roots()
+        .enumerate()
+        .map(|(number, screen)| ScreenSummary {
+            number,
+            root: crate::Xid::resource_id(&screen.root()),
+            width: screen.width_in_pixels(),
+            height: screen.height_in_pixels(),
+            width_mm: screen.width_in_millimeters(),
+            height_mm: screen.height_in_millimeters(),
+            root_depth: screen.root_depth(),
+            root_visual: screen.root_visual(),
+            visuals: screen
+                .allowed_depths()
+                .flat_map(|depth| {
+                    let d = depth.depth();
+                    depth.visuals().iter().map(move |v| VisualSummary {
+                        id: v.visual_id(),
+                        depth: d,
+                        class: format!("{:?}", v.class()),
+                        bits_per_rgb_value: v.bits_per_rgb_value(),
+                        colormap_entries: v.colormap_entries(),
+                        red_mask: v.red_mask(),
+                        green_mask: v.green_mask(),
+                        blue_mask: v.blue_mask(),
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Prefetches the extension data of the extensions whose versions are reported,
+/// such that checking their presence doesn't cost a round trip each.
+#[allow(unused_variables)] // without extension features
+fn prefetch_versioned(conn: &Connection) {
+    #[cfg(feature = "composite")]
+    crate::composite::prefetch_extension_data(conn);
+    #[cfg(feature = "damage")]
+    crate::damage::prefetch_extension_data(conn);
+    #[cfg(feature = "dpms")]
+    crate::dpms::prefetch_extension_data(conn);
+    #[cfg(feature = "dri2")]
+    crate::dri2::prefetch_extension_data(conn);
+    #[cfg(feature = "dri3")]
+    crate::dri3::prefetch_extension_data(conn);
+    #[cfg(feature = "glx")]
+    crate::glx::prefetch_extension_data(conn);
+    #[cfg(feature = "present")]
+    crate::present::prefetch_extension_data(conn);
+    #[cfg(feature = "randr")]
+    crate::randr::prefetch_extension_data(conn);
+    #[cfg(feature = "record")]
+    crate::record::prefetch_extension_data(conn);
+    #[cfg(feature = "render")]
+    crate::render::prefetch_extension_data(conn);
+    #[cfg(feature = "res")]
+    crate::res::prefetch_extension_data(conn);
+    #[cfg(feature = "screensaver")]
+    crate::screensaver::prefetch_extension_data(conn);
+    #[cfg(feature = "shape")]
+    crate::shape::prefetch_extension_data(conn);
+    #[cfg(feature = "shm")]
+    crate::shm::prefetch_extension_data(conn);
+    #[cfg(feature = "xf86vidmode")]
+    crate::xf86vidmode::prefetch_extension_data(conn);
+    #[cfg(feature = "xfixes")]
+    crate::xfixes::prefetch_extension_data(conn);
+    #[cfg(feature = "xinerama")]
+    crate::xinerama::prefetch_extension_data(conn);
+    #[cfg(feature = "xtest")]
+    crate::xtest::prefetch_extension_data(conn);
+    #[cfg(feature = "xv")]
+    crate::xv::prefetch_extension_data(conn);
+    #[cfg(feature = "xvmc")]
+    crate::xvmc::prefetch_extension_data(conn);
+}
+
+type PendingVersion<'a> = (&'static str, Box<dyn FnOnce() -> Result<(u32, u32)> + 'a>);
+
+/// Sends the version requests of the extensions that are present, with the version
+/// of the bindings as client version.
+macro_rules! query_versions {
+    ($conn:ident, $pending:ident, $($feature:literal => $module:ident::$request:ident { $($major_field:ident, $minor_field:ident)? } -> $major:ident, $minor:ident;)*) => {$(
+        #[cfg(feature = $feature)]
+        if crate::$module::get_extension_data($conn).is_some() {
+            let cookie = $conn.send_request(&crate::$module::$request {
+                $(
+                    $major_field: crate::$module::MAJOR_VERSION as _,
+                    $minor_field: crate::$module::MINOR_VERSION as _,
+                )?
+            });
+            $pending.push((
+                crate::$module::XNAME,
+                Box::new(move || {
+                    let reply = $conn
+                        .wait_for_reply(cookie)
+                        .context(stringify!($request), || crate::$module::XNAME)?;
+                    Ok((reply.$major() as u32, reply.$minor() as u32))
+                }),
+            ));
+        }
+    )*};
+}
+
+/// Sends the version requests of the supported extensions that are present.
+///
+/// XInput is not part of the table: a client can negotiate its version only once.
+#[allow(unused_variables)] // without extension features
+fn query_versions(conn: &Connection) -> Vec<PendingVersion<'_>> {
+    #[allow(unused_mut)]
+    let mut pending: Vec<PendingVersion> = Vec::new();
+    query_versions! {
+        conn, pending,
+        "composite" => composite::QueryVersion { client_major_version, client_minor_version } -> major_version, minor_version;
+        "damage" => damage::QueryVersion { client_major_version, client_minor_version } -> major_version, minor_version;
+        "dpms" => dpms::GetVersion { client_major_version, client_minor_version } -> server_major_version, server_minor_version;
+        "dri2" => dri2::QueryVersion { major_version, minor_version } -> major_version, minor_version;
+        "dri3" => dri3::QueryVersion { major_version, minor_version } -> major_version, minor_version;
+        "glx" => glx::QueryVersion { major_version, minor_version } -> major_version, minor_version;
+        "present" => present::QueryVersion { major_version, minor_version } -> major_version, minor_version;
+        "randr" => randr::QueryVersion { major_version, minor_version } -> major_version, minor_version;
+        "record" => record::QueryVersion { major_version, minor_version } -> major_version, minor_version;
+        "render" => render::QueryVersion { client_major_version, client_minor_version } -> major_version, minor_version;
+        "res" => res::QueryVersion { client_major, client_minor } -> server_major, server_minor;
+        "screensaver" => screensaver::QueryVersion { client_major_version, client_minor_version } -> server_major_version, server_minor_version;
+        "shape" => shape::QueryVersion {} -> major_version, minor_version;
+        "shm" => shm::QueryVersion {} -> major_version, minor_version;
+        "xf86vidmode" => xf86vidmode::QueryVersion {} -> major_version, minor_version;
+        "xfixes" => xfixes::QueryVersion { client_major_version, client_minor_version } -> major_version, minor_version;
+        "xinerama" => xinerama::QueryVersion { major, minor } -> major, minor;
+        "xtest" => xtest::GetVersion { major_version, minor_version } -> major_version, minor_version;
+        "xv" => xv::QueryExtension {} -> major, minor;
+        "xvmc" => xvmc::QueryVersion {} -> major, minor;
+    }
+    pending
+}
+
+#[cfg(feature = "xv")]
+mod xv_section {
+    use super::{CapabilityReport, XvAdaptorSummary};
+    use crate::base::{Connection, ResultExt};
+    use crate::{x, xv};
+
+    pub(super) type Pending = Vec<(usize, xv::QueryAdaptorsCookie)>;
+
+    pub(super) fn send(conn: &Connection, roots: &[x::Window]) -> Pending {
+        if xv::get_extension_data(conn).is_none() {
+            return Vec::new();
+        }
+        roots
+            .iter()
+            .enumerate()
+            .map(|(screen, &window)| (screen, conn.send_request(&xv::QueryAdaptors { window })))
+            .collect()
+    }
+
+    pub(super) fn gather(conn: &Connection, pending: Pending, report: &mut CapabilityReport) {
+        let mut formats = Vec::new();
+        for (screen, cookie) in pending {
+            let reply = match conn
+                .wait_for_reply(cookie)
+                .context("QueryAdaptors", || format!("screen {}", screen))
+            {
+                Ok(reply) => reply,
+                Err(err) => {
+                    report.push_error(xv::XNAME, err);
+                    continue;
+                }
+            };
+            for info in reply.info() {
+                let ty = info.r#type();
+                let kinds = [
+                    (xv::Type::INPUT_MASK, "input"),
+                    (xv::Type::OUTPUT_MASK, "output"),
+                    (xv::Type::VIDEO_MASK, "video"),
+                    (xv::Type::STILL_MASK, "still"),
+                    (xv::Type::IMAGE_MASK, "image"),
+                ];
+                if ty.contains(xv::Type::IMAGE_MASK) {
+                    let port = info.base_id();
+                    let cookie = conn.send_request(&xv::ListImageFormats { port });
+                    formats.push((report.xv_adaptors.len(), port, cookie));
+                }
+                report.xv_adaptors.push(XvAdaptorSummary {
+                    screen,
+                    name: info.name().to_utf8().into_owned(),
+                    base_port: crate::Xid::resource_id(&info.base_id()),
+                    num_ports: info.num_ports(),
+                    kinds: kinds
+                        .iter()
+                        .filter(|(mask, _)| ty.contains(*mask))
+                        .map(|(_, kind)| kind.to_string())
+                        .collect(),
+                    image_formats: Vec::new(),
+                });
+            }
+        }
+        for (index, port, cookie) in formats {
+            match conn
+                .wait_for_reply(cookie)
+                .context("ListImageFormats", || format!("port {:?}", port))
+            {
+                Ok(reply) => {
+                    report.xv_adaptors[index].image_formats = reply
+                        .format()
+                        .iter()
+                        .map(|f| xv::fourcc_name(f.id()))
+                        .collect()
+                }
+                Err(err) => report.push_error(xv::XNAME, err),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "randr")]
+mod randr_section {
+    use super::{CapabilityReport, RandrModeSummary, RandrOutputSummary};
+    use crate::base::{Connection, ResultExt, Xid};
+    use crate::{randr, x};
+
+    pub(super) type Pending = Vec<(usize, randr::GetScreenResourcesCurrentCookie)>;
+
+    /// `GetScreenResourcesCurrent` needs RandR 1.3.
+    pub(super) fn send(conn: &Connection, roots: &[x::Window], version: (u32, u32)) -> Pending {
+        if version < (1, 3) {
+            return Vec::new();
+        }
+        roots
+            .iter()
+            .enumerate()
+            .map(|(screen, &window)| {
+                let cookie = conn.send_request(&randr::GetScreenResourcesCurrent { window });
+                (screen, cookie)
+            })
+            .collect()
+    }
+
+    fn mode_summary(info: &randr::ModeInfo, names: &[u8], offset: usize) -> RandrModeSummary {
+        let name = names
+            .get(offset..offset + info.name_len as usize)
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .unwrap_or_default();
+        RandrModeSummary {
+            name,
+            width: info.width,
+            height: info.height,
+            refresh_millihertz: (info.refresh_hz() * 1000.0).round() as u32,
+            preferred: false,
+        }
+    }
+
+    pub(super) fn gather(conn: &Connection, pending: Pending, report: &mut CapabilityReport) {
+        let mut outputs = Vec::new();
+        for (screen, cookie) in pending {
+            let reply = match conn
+                .wait_for_reply(cookie)
+                .context("GetScreenResourcesCurrent", || format!("screen {}", screen))
+            {
+                Ok(reply) => reply,
+                Err(err) => {
+                    report.push_error(randr::XNAME, err);
+                    continue;
+                }
+            };
+            let mut offset = 0;
+            let modes: Vec<_> = reply
+                .modes()
+                .iter()
+                .map(|info| {
+                    let summary = mode_summary(info, reply.names(), offset);
+                    offset += info.name_len as usize;
+                    (info.id, summary)
+                })
+                .collect();
+            for &output in reply.outputs() {
+                let cookie = conn.send_request(&randr::GetOutputInfo {
+                    output,
+                    config_timestamp: reply.config_timestamp(),
+                });
+                outputs.push((screen, output, cookie, modes.clone()));
+            }
+        }
+        for (screen, output, cookie, modes) in outputs {
+            let info = match conn.wait_for_reply(cookie).context("GetOutputInfo", || {
+                format!("output {:#x}", output.resource_id())
+            }) {
+                Ok(info) => info,
+                Err(err) => {
+                    report.push_error(randr::XNAME, err);
+                    continue;
+                }
+            };
+            let preferred = info.num_preferred() as usize;
+            report.randr_outputs.push(RandrOutputSummary {
+                screen,
+                name: String::from_utf8_lossy(info.name()).into_owned(),
+                connection: match info.connection() {
+                    randr::Connection::Connected => "connected",
+                    randr::Connection::Disconnected => "disconnected",
+                    randr::Connection::Unknown => "unknown",
+                }
+                .to_string(),
+                crtc: if info.crtc().is_none() {
+                    None
+                } else {
+                    Some(info.crtc().resource_id())
+                },
+                mm_width: info.mm_width(),
+                mm_height: info.mm_height(),
+                modes: info
+                    .modes()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, mode)| {
+                        let (_, summary) =
+                            modes.iter().find(|(id, _)| *id == mode.resource_id())?;
+                        Some(RandrModeSummary {
+                            preferred: i < preferred,
+                            ..summary.clone()
+                        })
+                    })
+                    .collect(),
+            });
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+mod render_section {
+    use super::{CapabilityReport, PictFormatSummary};
+    use crate::base::{Connection, ResultExt, Xid};
+    use crate::render;
+
+    pub(super) fn send(conn: &Connection) -> Option<render::QueryPictFormatsCookie> {
+        render::get_extension_data(conn).map(|_| conn.send_request(&render::QueryPictFormats {}))
+    }
+
+    pub(super) fn gather(
+        conn: &Connection,
+        cookie: Option<render::QueryPictFormatsCookie>,
+        report: &mut CapabilityReport,
+    ) {
+        let cookie = match cookie {
+            Some(cookie) => cookie,
+            None => return,
+        };
+        match conn
+            .wait_for_reply(cookie)
+            .context("QueryPictFormats", || render::XNAME)
+        {
+            Ok(reply) => {
+                report.render_formats = reply
+                    .formats()
+                    .iter()
+                    .map(|format| {
+                        let direct = format.direct();
+                        PictFormatSummary {
+                            id: format.id().resource_id(),
+                            depth: format.depth(),
+                            direct: format.r#type() == render::PictType::Direct,
+                            red_mask: direct.red_mask,
+                            green_mask: direct.green_mask,
+                            blue_mask: direct.blue_mask,
+                            alpha_mask: direct.alpha_mask,
+                        }
+                    })
+                    .collect()
+            }
+            Err(err) => report.push_error(render::XNAME, err),
+        }
+    }
+}
+
+fn list_extensions(conn: &Connection) -> Result<Vec<ServerExtension>> {
+    let cookie = conn.send_request(&x::ListExtensions {});
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("ListExtensions", String::new)?;
+    let names: Vec<String> = reply
+        .names()
+        .map(|name| name.name().to_utf8().into_owned())
+        .collect();
+    let cookies: Vec<_> = names
+        .iter()
+        .map(|name| {
+            conn.send_request(&x::QueryExtension {
+                name: name.as_bytes(),
+            })
+        })
+        .collect();
+
+    let mut extensions = Vec::with_capacity(names.len());
+    for (name, cookie) in names.into_iter().zip(cookies) {
+        let reply = conn
+            .wait_for_reply(cookie)
+            .context("QueryExtension", || name.clone())?;
+        if reply.present() {
+            extensions.push(ServerExtension {
+                name,
+                major_opcode: reply.major_opcode(),
+                first_event: reply.first_event(),
+                first_error: reply.first_error(),
+            });
+        }
+    }
+    extensions.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(extensions)
+}
+
+impl Connection {
+    /// Gathers the capabilities of the server: the extensions it advertises, the versions
+    /// of the extensions supported by this crate, the screens, visuals and pixmap formats,
+    /// and depending on the cargo features, the `XVideo` adaptors, the RandR outputs
+    /// and the RENDER picture formats.
+    ///
+    /// The requests are pipelined: all the requests that don't depend on a previous reply
+    /// are sent before waiting for the first reply.
+    /// A section that fails is recorded in [CapabilityReport::errors] and left empty,
+    /// without failing the other sections.
+    ///
+    /// The versions are negotiated with the versions of the bindings, as a client of the
+    /// crate would. XInput is not negotiated, because a client can do it only once.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> xcb::Result<()> {
+    /// let (conn, _) = xcb::Connection::connect(None)?;
+    /// println!("{}", conn.capability_report());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn capability_report(&self) -> CapabilityReport {
+        let setup = self.get_setup();
+        let max_len = self.get_maximum_request_length();
+        let mut report = CapabilityReport {
+            vendor: setup.vendor().to_utf8().into_owned(),
+            release_number: setup.release_number(),
+            protocol_major_version: setup.protocol_major_version(),
+            protocol_minor_version: setup.protocol_minor_version(),
+            maximum_request_length: max_len.saturating_mul(4),
+            extensions: Vec::new(),
+            versions: Vec::new(),
+            pixmap_formats: setup
+                .pixmap_formats()
+                .iter()
+                .map(|format| PixmapFormatSummary {
+                    depth: format.depth(),
+                    bits_per_pixel: format.bits_per_pixel(),
+                    scanline_pad: format.scanline_pad(),
+                })
+                .collect(),
+            screens: screens(setup),
+            xv_adaptors: Vec::new(),
+            randr_outputs: Vec::new(),
+            render_formats: Vec::new(),
+            errors: Vec::new(),
+        };
+        #[allow(unused_variables)]
+        let roots: Vec<x::Window> = setup.roots().map(|screen| screen.root()).collect();
+
+        // the extension data arrives with the reply of ListExtensions
+        prefetch_versioned(self);
+        match list_extensions(self) {
+            Ok(extensions) => report.extensions = extensions,
+            Err(err) => report.push_error("extensions", err),
+        }
+
+        let versions = query_versions(self);
+        #[cfg(feature = "xv")]
+        let xv = xv_section::send(self, &roots);
+        #[cfg(feature = "render")]
+        let render = render_section::send(self);
+        for (name, version) in versions {
+            match version() {
+                Ok((major, minor)) => {
+                    #[cfg(feature = "log")]
+                    crate::logging::extension_version(name, major, minor);
+                    report.versions.push(ExtensionVersion {
+                        name: name.to_string(),
+                        major,
+                        minor,
+                    })
+                }
+                Err(err) => report.push_error(name, err),
+            }
+        }
+
+        // the RandR requests depend on the negotiated version
+        #[cfg(feature = "randr")]
+        let randr = report
+            .version(crate::randr::XNAME)
+            .map(|version| randr_section::send(self, &roots, version))
+            .unwrap_or_default();
+        #[cfg(feature = "xv")]
+        xv_section::gather(self, xv, &mut report);
+        #[cfg(feature = "render")]
+        render_section::gather(self, render, &mut report);
+        #[cfg(feature = "randr")]
+        randr_section::gather(self, randr, &mut report);
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ne_bytes<T: Copy>(values: &[T], to_bytes: fn(T) -> Vec<u8>) -> Vec<u8> {
+        values.iter().flat_map(|&v| to_bytes(v)).collect()
+    }
+
+    /// A `ListExtensions` reply with `names`.
+    fn list_extensions_reply(seq: u16, names: &[&str]) -> Vec<u8> {
+        let mut body = vec![0u8; 24];
+        for name in names {
+            body.push(name.len() as u8);
+            body.extend_from_slice(name.as_bytes());
+        }
+        crate::mock::reply(seq, names.len() as u8, &body)
+    }
+
+    #[test]
+    fn test_capability_report_core() {
+        use crate::mock;
+
+        let conn = mock::connect(|req| match req.opcode {
+            98 => match mock::extension_name(req) {
+                b"SYNC" => vec![mock::query_extension_reply(req, 135, 83, 134)],
+                b"Generic Event Extension" => vec![mock::query_extension_reply(req, 128, 0, 0)],
+                _ => vec![mock::reply(req.seq, 0, &[])],
+            },
+            99 => vec![list_extensions_reply(
+                req.seq,
+                &["SYNC", "Generic Event Extension", "NOT-QUERYABLE"],
+            )],
+            _ => mock::default_response(req),
+        });
+
+        let report = conn.capability_report();
+        assert_eq!(report.errors, []);
+        assert_eq!(
+            (report.protocol_major_version, report.protocol_minor_version),
+            (11, 0)
+        );
+        assert_eq!(report.maximum_request_length, 0xffff * 4);
+        // sorted by name, without the extension that is not present
+        assert_eq!(
+            report.extensions,
+            [
+                ServerExtension {
+                    name: "Generic Event Extension".into(),
+                    major_opcode: 128,
+                    first_event: 0,
+                    first_error: 0,
+                },
+                ServerExtension {
+                    name: "SYNC".into(),
+                    major_opcode: 135,
+                    first_event: 83,
+                    first_error: 134,
+                },
+            ]
+        );
+        assert_eq!(report.versions, []);
+        assert_eq!(report.screens.len(), 1);
+        let screen = &report.screens[0];
+        assert_eq!(screen.root, mock::ROOT);
+        assert_eq!((screen.width, screen.height), (1920, 1080));
+        assert_eq!((screen.root_depth, screen.root_visual), (24, 0x21));
+
+        let dump = report.to_string();
+        assert!(dump.contains("number of extensions:    2\n"));
+        assert!(dump.contains("    SYNC (opcode 135)\n"));
+        assert!(dump.contains("  dimensions:    1920x1080 pixels (508x286 millimeters)\n"));
+        assert!(!dump.contains("errors:"));
+    }
+
+    #[test]
+    fn test_capability_report_list_extensions_error() {
+        use crate::mock;
+
+        let conn = mock::connect(|req| match req.opcode {
+            98 => vec![mock::reply(req.seq, 0, &[])],
+            // BadImplementation
+            99 => vec![mock::error(req.seq, 17, 0, 99)],
+            _ => mock::default_response(req),
+        });
+
+        let report = conn.capability_report();
+        assert_eq!(report.extensions, []);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].section, "extensions");
+        assert!(report.errors[0].error.contains("ListExtensions"));
+        // the other sections are gathered
+        assert_eq!(report.screens.len(), 1);
+        assert!(report.to_string().contains("errors:\n  extensions: "));
+    }
+
+    #[cfg(all(feature = "randr", feature = "render", feature = "xv"))]
+    #[test]
+    fn test_capability_report_extensions() {
+        use crate::mock;
+
+        const RANDR: u8 = 140;
+        const RENDER: u8 = 141;
+        const XV: u8 = 142;
+
+        let u16s = |v: &[u16]| ne_bytes(v, |v| v.to_ne_bytes().to_vec());
+        let u32s = |v: &[u32]| ne_bytes(v, |v| v.to_ne_bytes().to_vec());
+
+        let conn = mock::connect(move |req| match (req.opcode, req.data[1]) {
+            (98, _) => match mock::extension_name(req) {
+                b"RANDR" => vec![mock::query_extension_reply(req, RANDR, 89, 147)],
+                b"RENDER" => vec![mock::query_extension_reply(req, RENDER, 0, 142)],
+                b"XVideo" => vec![mock::query_extension_reply(req, XV, 80, 150)],
+                _ => vec![mock::reply(req.seq, 0, &[])],
+            },
+            (99, _) => vec![list_extensions_reply(
+                req.seq,
+                &["RANDR", "RENDER", "XVideo"],
+            )],
+            // randr::QueryVersion
+            (RANDR, 0) => vec![mock::reply(req.seq, 0, &u32s(&[1, 6]))],
+            // randr::GetScreenResourcesCurrent: 1 CRTC, 2 outputs and 2 modes
+            (RANDR, 25) => {
+                let mut body = u32s(&[1, 2]);
+                body.extend(u16s(&[1, 2, 2, 17]));
+                body.resize(24, 0);
+                body.extend(u32s(&[0x50, 0x60, 0x61]));
+                for (id, width, height, clock, htotal, vtotal, name_len) in [
+                    (
+                        0x70u32,
+                        1920u16,
+                        1080u16,
+                        148_500_000u32,
+                        2200u16,
+                        1125u16,
+                        9u16,
+                    ),
+                    (0x71, 1280, 720, 74_250_000, 1650, 750, 8),
+                ] {
+                    body.extend(u32s(&[id]));
+                    body.extend(u16s(&[width, height]));
+                    body.extend(u32s(&[clock]));
+                    body.extend(u16s(&[0, 0, htotal, 0, 0, 0, vtotal, name_len]));
+                    body.extend(u32s(&[0]));
+                }
+                body.extend_from_slice(b"1920x10801280x720");
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            // randr::GetOutputInfo: an error for the second output
+            (RANDR, 9) if req.u32_at(4) == 0x61 => vec![mock::error(req.seq, 17, 0, RANDR)],
+            (RANDR, 9) => {
+                let mut body = u32s(&[1, 0x50, 510, 290]);
+                body.extend_from_slice(&[0, 0]);
+                body.extend(u16s(&[1, 2, 1, 0, 6]));
+                body.extend(u32s(&[0x50, 0x70, 0x71]));
+                body.extend_from_slice(b"HDMI-1");
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            // render::QueryVersion
+            (RENDER, 0) => vec![mock::reply(req.seq, 0, &u32s(&[0, 11]))],
+            // render::QueryPictFormats: an indexed and a direct format
+            (RENDER, 1) => {
+                let mut body = u32s(&[2, 0, 0, 0, 0, 0]);
+                body.extend(u32s(&[0x30]));
+                body.extend_from_slice(&[0, 8, 0, 0]);
+                body.extend(u16s(&[0; 8]));
+                body.extend(u32s(&[0x20]));
+                body.extend(u32s(&[0x31]));
+                body.extend_from_slice(&[1, 32, 0, 0]);
+                body.extend(u16s(&[16, 0xff, 8, 0xff, 0, 0xff, 24, 0xff]));
+                body.extend(u32s(&[0]));
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            // xv::QueryExtension
+            (XV, 0) => vec![mock::reply(req.seq, 0, &u16s(&[2, 2]))],
+            // xv::QueryAdaptors: an image adaptor
+            (XV, 1) => {
+                let name = b"Textured Video";
+                let mut body = u16s(&[1]);
+                body.resize(24, 0);
+                body.extend(u32s(&[0x80]));
+                body.extend(u16s(&[name.len() as u16, 16, 1]));
+                body.extend_from_slice(&[0x11, 0]);
+                body.extend_from_slice(name);
+                body.resize(body.len() + crate::base::align_pad(name.len(), 4), 0);
+                body.extend(u32s(&[0x21]));
+                body.extend_from_slice(&[24, 0, 0, 0]);
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            // xv::ListImageFormats
+            (XV, 16) => {
+                let mut body = u32s(&[2]);
+                body.resize(24, 0);
+                for id in [crate::xv::FOURCC_YV12, crate::xv::FOURCC_YUY2] {
+                    let mut info = id.to_ne_bytes().to_vec();
+                    info.resize(128, 0);
+                    body.extend(info);
+                }
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            _ => mock::default_response(req),
+        });
+
+        let report = conn.capability_report();
+        assert_eq!(report.extensions.len(), 3);
+        assert_eq!(report.version("RANDR"), Some((1, 6)));
+        assert_eq!(report.version("RENDER"), Some((0, 11)));
+        assert_eq!(report.version("XVideo"), Some((2, 2)));
+        assert_eq!(report.version("MIT-SHM"), None);
+
+        assert_eq!(
+            report.xv_adaptors,
+            [XvAdaptorSummary {
+                screen: 0,
+                name: "Textured Video".into(),
+                base_port: 0x80,
+                num_ports: 16,
+                kinds: vec!["input".into(), "image".into()],
+                image_formats: vec!["YV12".into(), "YUY2".into()],
+            }]
+        );
+
+        // the failing output is reported as an error, without failing the other one
+        assert_eq!(report.randr_outputs.len(), 1);
+        let output = &report.randr_outputs[0];
+        assert_eq!(output.name, "HDMI-1");
+        assert_eq!(output.connection, "connected");
+        assert_eq!(output.crtc, Some(0x50));
+        assert_eq!(
+            output.modes,
+            [
+                RandrModeSummary {
+                    name: "1920x1080".into(),
+                    width: 1920,
+                    height: 1080,
+                    refresh_millihertz: 60_000,
+                    preferred: true,
+                },
+                RandrModeSummary {
+                    name: "1280x720".into(),
+                    width: 1280,
+                    height: 720,
+                    refresh_millihertz: 60_000,
+                    preferred: false,
+                },
+            ]
+        );
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].section, "RANDR");
+        assert!(report.errors[0].error.contains("output 0x61"));
+
+        assert_eq!(report.render_formats.len(), 2);
+        assert!(!report.render_formats[0].direct);
+        assert_eq!(report.render_formats[0].depth, 8);
+        assert_eq!(
+            report.render_formats[1],
+            PictFormatSummary {
+                id: 0x31,
+                depth: 32,
+                direct: true,
+                red_mask: 0xff,
+                green_mask: 0xff,
+                blue_mask: 0xff,
+                alpha_mask: 0xff,
+            }
+        );
+
+        let dump = report.to_string();
+        assert!(dump.contains("    RANDR (opcode 140, version 1.6)\n"));
+        assert!(dump.contains("  screen #0: Textured Video (input, image), ports 0x80-0x8f\n"));
+        assert!(dump.contains("    image formats: YV12, YUY2\n"));
+        assert!(dump.contains("  screen #0: HDMI-1 connected (510x290 millimeters)\n"));
+        assert!(dump.contains("    1920x1080 1920x1080 60.00 Hz (preferred)\n"));
+        assert!(dump.contains("  0x31: direct depth 32, masks r 0xff g 0xff b 0xff a 0xff\n"));
+    }
+}
@@ -0,0 +1,777 @@
+//! Application identification and
+//! [startup notification](https://specifications.freedesktop.org/startup-notification-spec/startup-notification-latest.txt).
+//!
+//! [stamp_window] sets the properties that identify the process of a top-level window.
+//!
+//! Launchers and launched applications exchange startup notification messages, such as
+//! `new: ID=launcher-1234 NAME="Text Editor" SCREEN=0`, as `ClientMessage` events sent
+//! to the root window. A message doesn't fit in one event: it is split in chunks of
+//! 20 bytes, the first sent with the type `_NET_STARTUP_INFO_BEGIN` and the following ones
+//! with `_NET_STARTUP_INFO`, and the last chunk contains the terminating nul byte.
+//! [StartupNotification] sends the messages and reassembles the received ones.
+//!
+//! The launcher passes the startup ID to the application in the `DESKTOP_STARTUP_ID`
+//! environment variable. The application takes it with [take_startup_id], sets it on its
+//! first window with [set_startup_id], and sends the `remove` message with
+//! [StartupNotification::send_remove] once the window is shown.
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::event::Event;
+use crate::x;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Length of the chunks of the messages, the data of a `ClientMessage` event of format 8.
+const CHUNK_LEN: usize = 20;
+
+const ATOM_NAMES: [&str; 6] = [
+    "_NET_STARTUP_INFO_BEGIN",
+    "_NET_STARTUP_INFO",
+    "_NET_STARTUP_ID",
+    "_NET_WM_PID",
+    "WM_CLIENT_LEADER",
+    "UTF8_STRING",
+];
+
+/// The atoms of the startup notification and of the identification of the windows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Atoms {
+    /// The type of the first chunk of a message.
+    pub info_begin: x::Atom,
+    /// The type of the following chunks of a message.
+    pub info: x::Atom,
+    /// `_NET_STARTUP_ID`, the property of the windows with their startup ID.
+    pub startup_id: x::Atom,
+    /// `_NET_WM_PID`, the property of the windows with the ID of their process.
+    pub wm_pid: x::Atom,
+    /// `WM_CLIENT_LEADER`, the property of the windows with the leader window of their client.
+    pub client_leader: x::Atom,
+    pub utf8_string: x::Atom,
+}
+
+impl Atoms {
+    /// Interns the atoms.
+    ///
+    /// All the `InternAtom` requests are pipelined.
+    pub fn intern(conn: &Connection) -> Result<Atoms> {
+        let cookies: Vec<_> = ATOM_NAMES
+            .iter()
+            .map(|name| {
+                conn.send_request(&x::InternAtom {
+                    only_if_exists: false,
+                    name: name.as_bytes(),
+                })
+            })
+            .collect();
+        let mut atoms = [x::ATOM_NONE; 6];
+        for ((atom, cookie), name) in atoms.iter_mut().zip(cookies).zip(ATOM_NAMES) {
+            *atom = conn
+                .wait_for_reply(cookie)
+                .context("InternAtom", || name)?
+                .atom();
+        }
+        let [info_begin, info, startup_id, wm_pid, client_leader, utf8_string] = atoms;
+        Ok(Atoms {
+            info_begin,
+            info,
+            startup_id,
+            wm_pid,
+            client_leader,
+            utf8_string,
+        })
+    }
+}
+
+/// The host name of the machine, as returned by `gethostname`.
+fn hostname() -> Option<Vec<u8>> {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if res != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    Some(buf[..len].to_vec())
+}
+
+/// Sets the properties that identify the process of the top-level `window`.
+///
+/// - `WM_CLIENT_MACHINE` is set to the host name.
+/// - `_NET_WM_PID` is set to the ID of the process.
+/// - `WM_CLIENT_LEADER` is set to `window` itself: the window is the leader of the windows
+///   of the client, that should have their `WM_CLIENT_LEADER` set to it.
+///
+/// The window managers use them to find the process of an unresponsive window.
+pub fn stamp_window(conn: &Connection, window: x::Window) -> Result<()> {
+    let atoms = Atoms::intern(conn)?;
+
+    let mut cookies = Vec::with_capacity(3);
+    if let Some(hostname) = hostname() {
+        let cookie = conn.send_request_checked(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window,
+            property: x::ATOM_WM_CLIENT_MACHINE,
+            r#type: x::ATOM_STRING,
+            data: &hostname,
+        });
+        cookies.push(("WM_CLIENT_MACHINE", cookie));
+    }
+    let cookie = conn.send_request_checked(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: atoms.wm_pid,
+        r#type: x::ATOM_CARDINAL,
+        data: &[std::process::id()],
+    });
+    cookies.push(("_NET_WM_PID", cookie));
+    let cookie = conn.send_request_checked(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: atoms.client_leader,
+        r#type: x::ATOM_WINDOW,
+        data: &[window],
+    });
+    cookies.push(("WM_CLIENT_LEADER", cookie));
+
+    for (name, cookie) in cookies {
+        conn.check_request(cookie).context("ChangeProperty", || {
+            format!("{} of window {:#x}", name, window.resource_id())
+        })?;
+    }
+    Ok(())
+}
+
+/// Takes the startup ID passed by the launcher in the `DESKTOP_STARTUP_ID` environment
+/// variable.
+///
+/// The variable is removed from the environment, so that it is not inherited by the child
+/// processes.
+pub fn take_startup_id() -> Option<String> {
+    let id = std::env::var("DESKTOP_STARTUP_ID").ok();
+    std::env::remove_var("DESKTOP_STARTUP_ID");
+    id.filter(|id| !id.is_empty())
+}
+
+/// Sets the `_NET_STARTUP_ID` property of the top-level `window` to `id`.
+///
+/// The window manager uses it to end the startup feedback when the window is mapped, and
+/// to apply the focus stealing prevention with the timestamp of the launch (the part
+/// of the ID after `_TIME`).
+pub fn set_startup_id(conn: &Connection, window: x::Window, id: &str) -> Result<()> {
+    let atoms = Atoms::intern(conn)?;
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: atoms.startup_id,
+        r#type: atoms.utf8_string,
+        data: id.as_bytes(),
+    })
+    .context("ChangeProperty", || {
+        format!("_NET_STARTUP_ID of window {:#x}", window.resource_id())
+    })
+}
+
+/// The kind of a startup notification message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    /// `new:`, a launch sequence starts.
+    New,
+    /// `change:`, the parameters of a launch sequence change.
+    Change,
+    /// `remove:`, a launch sequence ends.
+    Remove,
+}
+
+impl MessageKind {
+    /// The prefix of the messages, without the colon.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            MessageKind::New => "new",
+            MessageKind::Change => "change",
+            MessageKind::Remove => "remove",
+        }
+    }
+}
+
+/// A startup notification message: its kind and its `KEY=value` parameters.
+///
+/// The parameters keep the order in which they are added or parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageKind,
+    pub params: Vec<(String, String)>,
+}
+
+impl Message {
+    /// Builds a message of the launch sequence `id`.
+    pub fn new(kind: MessageKind, id: &str) -> Message {
+        Message {
+            kind,
+            params: vec![("ID".to_string(), id.to_string())],
+        }
+    }
+
+    /// Builds the `remove` message of the launch sequence `id`.
+    pub fn remove(id: &str) -> Message {
+        Message::new(MessageKind::Remove, id)
+    }
+
+    /// Adds the parameter `key` (e.g. `NAME`, `SCREEN`, `BIN`, `ICON`, `DESKTOP`,
+    /// `TIMESTAMP`, `WMCLASS`).
+    pub fn with(mut self, key: &str, value: &str) -> Message {
+        self.params.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// The value of the first parameter `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The ID of the launch sequence.
+    pub fn id(&self) -> Option<&str> {
+        self.get("ID")
+    }
+
+    /// Parses a message, without its terminating nul byte.
+    ///
+    /// The values can be quoted with double quotes to contain spaces. Quoted or not,
+    /// a backslash escapes the following character.
+    /// Returns `None` if the prefix is unknown or if a parameter has no `=`.
+    pub fn parse(message: &str) -> Option<Message> {
+        let colon = message.find(':')?;
+        let kind = match &message[..colon] {
+            "new" => MessageKind::New,
+            "change" => MessageKind::Change,
+            "remove" => MessageKind::Remove,
+            _ => return None,
+        };
+        let mut params = Vec::new();
+        let mut chars = message[colon + 1..].chars().peekable();
+        loop {
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+            if chars.peek().is_none() {
+                break;
+            }
+            let mut key = String::new();
+            loop {
+                match chars.next() {
+                    Some('=') if !key.is_empty() => break,
+                    Some(c) if c != ' ' && c != '=' => key.push(c),
+                    _ => return None,
+                }
+            }
+            let mut value = String::new();
+            let mut quoted = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => quoted = !quoted,
+                    ' ' if !quoted => break,
+                    c => value.push(c),
+                }
+            }
+            params.push((key, value));
+        }
+        Some(Message { kind, params })
+    }
+}
+
+impl fmt::Display for Message {
+    /// Formats the message, quoting the values that need it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.kind.prefix())?;
+        for (key, value) in &self.params {
+            write!(f, " {}=", key)?;
+            if !value.is_empty() && !value.contains(&[' ', '"', '\\'][..]) {
+                f.write_str(value)?;
+                continue;
+            }
+            f.write_str("\"")?;
+            for c in value.chars() {
+                if c == '"' || c == '\\' {
+                    f.write_str("\\")?;
+                }
+                write!(f, "{}", c)?;
+            }
+            f.write_str("\"")?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `message` and its terminating nul byte in chunks.
+fn chunks(message: &[u8]) -> Vec<[u8; CHUNK_LEN]> {
+    let mut bytes = message.to_vec();
+    bytes.push(0);
+    bytes
+        .chunks(CHUNK_LEN)
+        .map(|chunk| {
+            let mut data = [0u8; CHUNK_LEN];
+            data[..chunk.len()].copy_from_slice(chunk);
+            data
+        })
+        .collect()
+}
+
+/// Appends a chunk received from `window` to its partial message, or starts a new message
+/// if `begin` is set.
+///
+/// Returns the message, without the terminating nul byte, if the chunk completes it.
+/// The chunks that don't follow a first chunk are ignored.
+fn push_chunk(
+    partial: &mut HashMap<x::Window, Vec<u8>>,
+    window: x::Window,
+    begin: bool,
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    if begin {
+        partial.insert(window, Vec::new());
+    }
+    let message = partial.get_mut(&window)?;
+    match data.iter().position(|b| *b == 0) {
+        Some(end) => {
+            message.extend_from_slice(&data[..end]);
+            partial.remove(&window)
+        }
+        None => {
+            message.extend_from_slice(data);
+            None
+        }
+    }
+}
+
+/// Sends and receives startup notification messages on a screen.
+///
+/// To receive the messages, a launcher must select the `PropertyChange` events of the root
+/// window and call [StartupNotification::handle_event] with every event.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, screen_num) = xcb::Connection::connect(None)?;
+/// # let window: xcb::x::Window = conn.generate_id();
+/// use xcb::startup;
+///
+/// // in the launched application, once its first window is mapped
+/// if let Some(id) = startup::take_startup_id() {
+///     startup::set_startup_id(&conn, window, &id)?;
+///     startup::StartupNotification::new(&conn, screen_num)?.send_remove(&id)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct StartupNotification<'a> {
+    conn: &'a Connection,
+    atoms: Atoms,
+    root: x::Window,
+    partial: HashMap<x::Window, Vec<u8>>,
+}
+
+impl<'a> fmt::Debug for StartupNotification<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StartupNotification")
+            .field("atoms", &self.atoms)
+            .field("root", &self.root)
+            .field("partial", &self.partial)
+            .finish()
+    }
+}
+
+impl<'a> StartupNotification<'a> {
+    /// Interns the atoms of the protocol, to send and receive messages on `screen`.
+    pub fn new(conn: &'a Connection, screen: i32) -> Result<StartupNotification<'a>> {
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen as usize)
+            .unwrap_or_else(|| panic!("no screen {}", screen))
+            .root();
+        Ok(StartupNotification {
+            conn,
+            atoms: Atoms::intern(conn)?,
+            root,
+            partial: HashMap::new(),
+        })
+    }
+
+    /// The interned atoms.
+    pub fn atoms(&self) -> &Atoms {
+        &self.atoms
+    }
+
+    /// Sends `message` to the root window.
+    ///
+    /// As required by the specification, the chunks are sent on behalf of a temporary
+    /// window, that is destroyed after the message is sent.
+    pub fn send(&self, message: &Message) -> Result<()> {
+        let conn = self.conn;
+        let window: x::Window = conn.generate_id();
+        let text = message.to_string();
+        let detail = || format!("{} message {:?}", message.kind.prefix(), message.id());
+
+        conn.send_and_check_request(&x::CreateWindow {
+            depth: 0,
+            wid: window,
+            parent: self.root,
+            x: -100,
+            y: -100,
+            width: 1,
+            height: 1,
+            border_width: 0,
+            class: x::WindowClass::InputOnly,
+            visual: x::COPY_FROM_PARENT,
+            value_list: &[x::Cw::OverrideRedirect(true)],
+        })
+        .context("CreateWindow", detail)?;
+
+        let cookies: Vec<_> = chunks(text.as_bytes())
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| {
+                let r#type = if i == 0 {
+                    self.atoms.info_begin
+                } else {
+                    self.atoms.info
+                };
+                let event =
+                    x::ClientMessageEvent::new(window, r#type, x::ClientMessageData::Data8(data));
+                conn.send_request_checked(&x::SendEvent {
+                    propagate: false,
+                    destination: x::SendEventDest::Window(self.root),
+                    event_mask: x::EventMask::PROPERTY_CHANGE,
+                    event: &event,
+                })
+            })
+            .collect();
+        let sent = cookies
+            .into_iter()
+            .try_for_each(|cookie| conn.check_request(cookie).context("SendEvent", detail));
+        conn.send_and_check_request(&x::DestroyWindow { window })
+            .context("DestroyWindow", detail)?;
+        sent
+    }
+
+    /// Sends the `remove` message of the launch sequence `id`, to end the startup feedback
+    /// of the launcher.
+    pub fn send_remove(&self, id: &str) -> Result<()> {
+        self.send(&Message::remove(id))
+    }
+
+    /// Reassembles the messages from the `ClientMessage` events of the root window.
+    ///
+    /// Returns the message completed by `event`, if any. The messages that are not valid
+    /// UTF-8 or that fail to parse are dropped.
+    pub fn handle_event(&mut self, event: &Event) -> Option<Message> {
+        match event {
+            Event::X(x::Event::ClientMessage(ev)) => self.handle_client_message(ev),
+            _ => None,
+        }
+    }
+
+    /// Like [StartupNotification::handle_event], with a `ClientMessage` event.
+    pub fn handle_client_message(&mut self, ev: &x::ClientMessageEvent) -> Option<Message> {
+        let begin = ev.r#type() == self.atoms.info_begin;
+        if !begin && ev.r#type() != self.atoms.info {
+            return None;
+        }
+        let data = match ev.data() {
+            x::ClientMessageData::Data8(data) => data,
+            _ => return None,
+        };
+        let message = push_chunk(&mut self.partial, ev.window(), begin, &data)?;
+        Message::parse(std::str::from_utf8(&message).ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32) -> x::Window {
+        use crate::XidNew;
+        unsafe { x::Window::new(id) }
+    }
+
+    const ATOMS: [u32; 6] = [400, 401, 402, 403, 404, 405];
+
+    fn mock_connection(requests: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>) -> Connection {
+        use crate::mock;
+
+        mock::connect(move |req| match req.opcode {
+            // InternAtom
+            16 => {
+                let len = u16::from_ne_bytes([req.data[4], req.data[5]]) as usize;
+                let name = &req.data[8..8 + len];
+                let i = ATOM_NAMES
+                    .iter()
+                    .position(|n| n.as_bytes() == name)
+                    .unwrap();
+                let atom = ATOMS[i];
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            // SendEvent: the event is delivered back to the connection
+            25 => {
+                requests.lock().unwrap().push(req.data.clone());
+                let mut ev = req.data[12..44].to_vec();
+                ev[0] |= 0x80;
+                ev[2..4].copy_from_slice(&req.seq.to_ne_bytes());
+                vec![ev]
+            }
+            // CreateWindow, ChangeProperty, DestroyWindow
+            1 | 18 | 4 => {
+                requests.lock().unwrap().push(req.data.clone());
+                vec![]
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let msg = Message::parse(
+            "new: ID=foo-1234_TIME56789 NAME=\"Hello World\" SCREEN=0 BIN=hello ICON=hello",
+        )
+        .unwrap();
+        assert_eq!(msg.kind, MessageKind::New);
+        assert_eq!(msg.id(), Some("foo-1234_TIME56789"));
+        assert_eq!(msg.get("NAME"), Some("Hello World"));
+        assert_eq!(msg.get("SCREEN"), Some("0"));
+        assert_eq!(msg.get("BIN"), Some("hello"));
+        assert_eq!(msg.get("DESKTOP"), None);
+
+        let msg = Message::parse("remove: ID=foo-1234_TIME56789").unwrap();
+        assert_eq!(msg, Message::remove("foo-1234_TIME56789"));
+
+        // escapes, inside and outside quotes, and empty values
+        let msg =
+            Message::parse(r#"change:  NAME="say \"hi\"" BIN=a\ b DESCRIPTION= ICON="c:\\d"  "#)
+                .unwrap();
+        assert_eq!(msg.kind, MessageKind::Change);
+        assert_eq!(
+            msg.params,
+            [
+                ("NAME".to_string(), "say \"hi\"".to_string()),
+                ("BIN".to_string(), "a b".to_string()),
+                ("DESCRIPTION".to_string(), String::new()),
+                ("ICON".to_string(), "c:\\d".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            Message::parse("new:"),
+            Some(Message {
+                kind: MessageKind::New,
+                params: vec![],
+            })
+        );
+        assert_eq!(Message::parse("ID=foo"), None);
+        assert_eq!(Message::parse("delete: ID=foo"), None);
+        assert_eq!(Message::parse("new: ID"), None);
+        assert_eq!(Message::parse("new: =foo"), None);
+    }
+
+    #[test]
+    fn test_format_message() {
+        let msg = Message::new(MessageKind::New, "foo-1234_TIME56789")
+            .with("NAME", "Hello World")
+            .with("SCREEN", "0")
+            .with("DESCRIPTION", "")
+            .with("ICON", "say \"c:\\d\"");
+        let text = msg.to_string();
+        assert_eq!(
+            text,
+            r#"new: ID=foo-1234_TIME56789 NAME="Hello World" SCREEN=0 DESCRIPTION="" ICON="say \"c:\\d\"""#
+        );
+        assert_eq!(Message::parse(&text), Some(msg));
+    }
+
+    #[test]
+    fn test_chunks() {
+        // 19 bytes and the nul byte fill a single chunk
+        let msg = b"remove: ID=abcdefgh";
+        assert_eq!(msg.len(), 19);
+        let c = chunks(msg);
+        assert_eq!(c.len(), 1);
+        assert_eq!(&c[0][..19], msg);
+        assert_eq!(c[0][19], 0);
+
+        // the nul byte of a message of 20 bytes is alone in the second chunk
+        let c = chunks(b"remove: ID=abcdefghi");
+        assert_eq!(c.len(), 2);
+        assert_eq!(c[1], [0; 20]);
+
+        let msg = b"new: ID=launcher-42 NAME=\"Text Editor\" SCREEN=0";
+        let c = chunks(msg);
+        assert_eq!(c.len(), 3);
+        let mut partial = HashMap::new();
+        let w = window(0x0120_0001);
+        assert_eq!(push_chunk(&mut partial, w, true, &c[0]), None);
+        assert_eq!(push_chunk(&mut partial, w, false, &c[1]), None);
+        assert_eq!(
+            push_chunk(&mut partial, w, false, &c[2]).as_deref(),
+            Some(&msg[..])
+        );
+        assert!(partial.is_empty());
+    }
+
+    #[test]
+    fn test_reassembly() {
+        let a = window(0x0120_0001);
+        let b = window(0x0340_0001);
+        let msg_a = b"new: ID=a-1 NAME=\"Application A\" SCREEN=0";
+        let msg_b = b"change: ID=b-2 DESKTOP=3";
+        let chunks_a = chunks(msg_a);
+        let chunks_b = chunks(msg_b);
+        let mut partial = HashMap::new();
+
+        // a chunk without a first chunk is ignored
+        assert_eq!(push_chunk(&mut partial, a, false, &chunks_a[1]), None);
+        assert!(partial.is_empty());
+
+        // the messages of two windows are interleaved
+        assert_eq!(push_chunk(&mut partial, a, true, &chunks_a[0]), None);
+        assert_eq!(push_chunk(&mut partial, b, true, &chunks_b[0]), None);
+        assert_eq!(push_chunk(&mut partial, a, false, &chunks_a[1]), None);
+        assert_eq!(
+            push_chunk(&mut partial, b, false, &chunks_b[1]).as_deref(),
+            Some(&msg_b[..])
+        );
+        assert_eq!(
+            push_chunk(&mut partial, a, false, &chunks_a[2]).as_deref(),
+            Some(&msg_a[..])
+        );
+
+        // a first chunk drops the partial message of the window
+        assert_eq!(push_chunk(&mut partial, a, true, &chunks_a[0]), None);
+        assert_eq!(push_chunk(&mut partial, a, true, &chunks_b[0]), None);
+        assert_eq!(
+            push_chunk(&mut partial, a, false, &chunks_b[1]).as_deref(),
+            Some(&msg_b[..])
+        );
+
+        // the bytes after the nul byte are ignored
+        let mut data = [b'x'; 20];
+        data[..9].copy_from_slice(b"remove: \0");
+        assert_eq!(
+            push_chunk(&mut partial, a, true, &data).as_deref(),
+            Some(&b"remove: "[..])
+        );
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        use crate::mock;
+        use std::convert::TryInto;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(requests.clone());
+        let mut sn = StartupNotification::new(&conn, 0).unwrap();
+
+        let msg = Message::new(MessageKind::New, "launcher-42_TIME1000")
+            .with("NAME", "Text Editor")
+            .with("SCREEN", "0");
+        sn.send(&msg).unwrap();
+
+        {
+            let requests = requests.lock().unwrap();
+            // CreateWindow, 3 SendEvent, DestroyWindow
+            assert_eq!(requests.len(), 5);
+            let temp = requests[0][4..8].to_vec();
+            assert_eq!(requests[0][0], 1);
+            assert_eq!(requests[4][0], 4);
+            assert_eq!(requests[4][4..8], temp[..]);
+            for (i, req) in requests[1..4].iter().enumerate() {
+                assert_eq!(req[0], 25);
+                assert_eq!(
+                    u32::from_ne_bytes(req[4..8].try_into().unwrap()),
+                    mock::ROOT
+                );
+                let ev = &req[12..44];
+                assert_eq!(ev[0], 33); // ClientMessage
+                assert_eq!(ev[1], 8); // format
+                assert_eq!(ev[4..8], temp[..]);
+                let r#type = u32::from_ne_bytes(ev[8..12].try_into().unwrap());
+                assert_eq!(r#type, if i == 0 { ATOMS[0] } else { ATOMS[1] });
+            }
+        }
+
+        // the events are delivered back to the connection
+        let mut received = Vec::new();
+        while let Some(event) = conn.poll_for_queued_event().unwrap() {
+            received.extend(sn.handle_event(&event));
+        }
+        assert_eq!(received, [msg]);
+
+        sn.send_remove("launcher-42_TIME1000").unwrap();
+        let mut received = Vec::new();
+        while let Some(event) = conn.poll_for_queued_event().unwrap() {
+            received.extend(sn.handle_event(&event));
+        }
+        assert_eq!(received, [Message::remove("launcher-42_TIME1000")]);
+    }
+
+    #[test]
+    fn test_stamp_window() {
+        use std::convert::TryInto;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(requests.clone());
+        let w = window(0x0120_0001);
+
+        stamp_window(&conn, w).unwrap();
+        set_startup_id(&conn, w, "launcher-42_TIME1000").unwrap();
+
+        let u32_at = |req: &[u8], offset: usize| {
+            u32::from_ne_bytes(req[offset..offset + 4].try_into().unwrap())
+        };
+        let requests = requests.lock().unwrap();
+        // (property, type, format, data)
+        let props: Vec<_> = requests
+            .iter()
+            .map(|req| {
+                assert_eq!(req[0], 18);
+                assert_eq!(u32_at(req, 4), 0x0120_0001);
+                let len = u32_at(req, 20) as usize * req[16] as usize / 8;
+                (
+                    u32_at(req, 8),
+                    u32_at(req, 12),
+                    req[16],
+                    req[24..24 + len].to_vec(),
+                )
+            })
+            .collect();
+        let hostname = hostname().unwrap();
+        assert_eq!(
+            props,
+            [
+                (
+                    x::ATOM_WM_CLIENT_MACHINE.resource_id(),
+                    x::ATOM_STRING.resource_id(),
+                    8,
+                    hostname
+                ),
+                (
+                    ATOMS[3],
+                    x::ATOM_CARDINAL.resource_id(),
+                    32,
+                    std::process::id().to_ne_bytes().to_vec()
+                ),
+                (
+                    ATOMS[4],
+                    x::ATOM_WINDOW.resource_id(),
+                    32,
+                    0x0120_0001u32.to_ne_bytes().to_vec()
+                ),
+                (ATOMS[2], ATOMS[5], 8, b"launcher-42_TIME1000".to_vec()),
+            ]
+        );
+    }
+}
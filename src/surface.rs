@@ -0,0 +1,755 @@
+//! Off-screen drawing surfaces.
+//!
+//! [Offscreen] bundles the server objects needed to draw off-screen and to show the
+//! result on a window: a pixmap of the depth of a reference drawable, a graphics context
+//! and, with the `render` cargo feature and when the RENDER extension is active on the
+//! connection, a picture of the pixmap.
+
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+#[cfg(feature = "render")]
+use crate::ext::Extension;
+#[cfg(feature = "render")]
+use crate::render;
+
+/// An image in the `ZPixmap` format of the depth of an [Offscreen] surface.
+///
+/// Each row is [Offscreen::stride] bytes long and the pixels are in the image byte order
+/// of the server (see `x::Setup::image_byte_order`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: u16,
+    pub height: u16,
+    pub data: Vec<u8>,
+}
+
+/// A pixmap, a graphics context and a picture, created and freed together.
+///
+/// The pixmap has the depth of the drawable the surface is created like, such that it
+/// can be copied to it. The graphics context has default values, except that it
+/// generates no graphics exposures.
+///
+/// The picture is only created with the `render` cargo feature, if the RENDER extension
+/// is active on the connection and has a format for the depth of the pixmap.
+/// Its format is the one of the visual of the reference window if there is one, such
+/// that colors are interpreted as on the window.
+///
+/// All the resources are freed when the surface is dropped.
+pub struct Offscreen<'a> {
+    conn: &'a Connection,
+    like: x::Drawable,
+    depth: u8,
+    width: u16,
+    height: u16,
+    pixmap: x::Pixmap,
+    gc: x::Gcontext,
+    #[cfg(feature = "render")]
+    picture: Option<(render::Picture, render::Pictformat)>,
+}
+
+impl<'a> Offscreen<'a> {
+    /// Creates a surface of `width` by `height` pixels, of the depth of the drawable `like`.
+    ///
+    /// # Panics
+    /// Panics if `width` or `height` is zero.
+    pub fn new(
+        conn: &'a Connection,
+        like: x::Drawable,
+        width: u16,
+        height: u16,
+    ) -> Result<Offscreen<'a>> {
+        assert!(
+            width > 0 && height > 0,
+            "invalid surface size {}x{}",
+            width,
+            height
+        );
+
+        let geometry = conn.send_request(&x::GetGeometry { drawable: like });
+        // the visual of the window, to pick the format of the picture
+        #[cfg(feature = "render")]
+        let visual = match like {
+            x::Drawable::Window(window) if conn.extension_data(Extension::Render).is_some() => {
+                Some(conn.send_request(&x::GetWindowAttributes { window }))
+            }
+            _ => None,
+        };
+        let depth = conn
+            .wait_for_reply(geometry)
+            .context("GetGeometry", || format!("{:?}", like))?
+            .depth();
+
+        let pixmap = conn.generate_id();
+        let create_pixmap = conn.send_request_checked(&x::CreatePixmap {
+            depth,
+            pid: pixmap,
+            drawable: like,
+            width,
+            height,
+        });
+        let gc = conn.generate_id();
+        let create_gc = conn.send_request_checked(&x::CreateGc {
+            cid: gc,
+            drawable: x::Drawable::Pixmap(pixmap),
+            value_list: &[x::Gc::GraphicsExposures(false)],
+        });
+        conn.check_request(create_pixmap)
+            .context("CreatePixmap", || {
+                format!("depth {}, {}x{}", depth, width, height)
+            })?;
+        conn.check_request(create_gc)
+            .context("CreateGC", || format!("{:?}", pixmap))?;
+
+        let surface = Offscreen {
+            conn,
+            like,
+            depth,
+            width,
+            height,
+            pixmap,
+            gc,
+            #[cfg(feature = "render")]
+            picture: None,
+        };
+        #[cfg(feature = "render")]
+        let surface = if conn.extension_data(Extension::Render).is_some() {
+            let visual = match visual {
+                Some(cookie) => Some(
+                    conn.wait_for_reply(cookie)
+                        .context("GetWindowAttributes", || format!("{:?}", like))?
+                        .visual(),
+                ),
+                None => None,
+            };
+            surface.with_picture(visual)?
+        } else {
+            surface
+        };
+
+        Ok(surface)
+    }
+
+    /// The pixmap of the surface.
+    ///
+    /// The pixmap keeps its identifier when the surface is resized.
+    pub fn pixmap(&self) -> x::Pixmap {
+        self.pixmap
+    }
+
+    /// The graphics context of the surface.
+    pub fn gc(&self) -> x::Gcontext {
+        self.gc
+    }
+
+    /// The picture of the pixmap, if the surface has one.
+    ///
+    /// The picture keeps its identifier when the surface is resized.
+    #[cfg(feature = "render")]
+    pub fn picture(&self) -> Option<render::Picture> {
+        self.picture.map(|(picture, _)| picture)
+    }
+
+    /// The format of the picture, if the surface has one.
+    #[cfg(feature = "render")]
+    pub fn picture_format(&self) -> Option<render::Pictformat> {
+        self.picture.map(|(_, format)| format)
+    }
+
+    /// The depth of the pixmap, which is the one of the drawable the surface was created like.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// The width and height of the surface.
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /// The length in bytes of a row of `width` pixels of an [Image] of the surface.
+    ///
+    /// # Panics
+    /// Panics if the setup of the connection has no pixmap format of the depth of the surface.
+    pub fn stride(&self, width: u16) -> usize {
+        let format = self
+            .conn
+            .get_setup()
+            .pixmap_formats()
+            .iter()
+            .find(|f| f.depth() == self.depth)
+            .unwrap_or_else(|| panic!("no pixmap format of depth {}", self.depth));
+        stride(
+            width,
+            format.bits_per_pixel() as usize,
+            format.scanline_pad() as usize,
+        )
+    }
+
+    /// Resizes the surface to `width` by `height` pixels.
+    ///
+    /// The pixmap and the picture are recreated with the same identifiers, the content
+    /// of the surface is undefined afterwards. Nothing is done if the size is unchanged.
+    ///
+    /// # Panics
+    /// Panics if `width` or `height` is zero.
+    pub fn resize(&mut self, width: u16, height: u16) -> Result<()> {
+        assert!(
+            width > 0 && height > 0,
+            "invalid surface size {}x{}",
+            width,
+            height
+        );
+        if (width, height) == self.size() {
+            return Ok(());
+        }
+
+        #[cfg(feature = "render")]
+        if let Some((picture, _)) = self.picture {
+            self.conn.send_request(&render::FreePicture { picture });
+        }
+        self.conn.send_request(&x::FreePixmap {
+            pixmap: self.pixmap,
+        });
+        self.width = width;
+        self.height = height;
+        let cookie = self.conn.send_request_checked(&x::CreatePixmap {
+            depth: self.depth,
+            pid: self.pixmap,
+            drawable: self.like,
+            width,
+            height,
+        });
+        self.conn
+            .check_request(cookie)
+            .context("CreatePixmap", || {
+                format!("depth {}, {}x{}", self.depth, width, height)
+            })?;
+
+        #[cfg(feature = "render")]
+        self.create_picture()?;
+        Ok(())
+    }
+
+    /// Fills `rect` with `pixel`.
+    pub fn fill_rect(&self, pixel: u32, rect: x::Rectangle) {
+        self.conn.send_request(&x::ChangeGc {
+            gc: self.gc,
+            value_list: &[x::Gc::Foreground(pixel)],
+        });
+        self.conn.send_request(&x::PolyFillRectangle {
+            drawable: x::Drawable::Pixmap(self.pixmap),
+            gc: self.gc,
+            rectangles: &[rect],
+        });
+    }
+
+    /// Uploads `image` to the surface, with its top left corner at `dst`.
+    ///
+    /// Images that don't fit in a single request are uploaded in bands of rows.
+    ///
+    /// # Panics
+    /// Panics if `image.data` is shorter than `image.height` rows of
+    /// [`stride(image.width)`](Offscreen::stride) bytes.
+    pub fn put_image(&self, image: &Image, dst: x::Point) {
+        let stride = self.stride(image.width);
+        let len = stride * image.height as usize;
+        assert!(
+            image.data.len() >= len,
+            "image data of {} bytes is shorter than {} rows of {} bytes",
+            image.data.len(),
+            image.height,
+            stride
+        );
+        if len == 0 {
+            return;
+        }
+
+        let request = x::PutImage {
+            format: x::ImageFormat::ZPixmap,
+            drawable: x::Drawable::Pixmap(self.pixmap),
+            gc: self.gc,
+            width: image.width,
+            height: 0,
+            dst_x: dst.x,
+            dst_y: dst.y,
+            left_pad: 0,
+            depth: self.depth,
+            data: &[],
+        };
+        // the room for the data, without the 4 bytes of the extended length of BIG-REQUESTS
+        let max_len = (self.conn.get_maximum_request_length() as usize * 4)
+            .saturating_sub(request.request_len() + 4);
+        let band = (max_len / stride).clamp(1, image.height as usize);
+        for (i, rows) in image.data[..len].chunks(band * stride).enumerate() {
+            self.conn.send_request(&x::PutImage {
+                height: (rows.len() / stride) as u16,
+                dst_y: dst.y.wrapping_add((i * band) as i16),
+                data: rows,
+                ..request
+            });
+        }
+    }
+
+    /// Copies `src_rect` of the surface to `dst`, at `dst_pt`.
+    ///
+    /// `dst` must have the depth of the surface and be on the same screen.
+    pub fn copy_to(&self, dst: x::Drawable, src_rect: x::Rectangle, dst_pt: x::Point) {
+        self.conn.send_request(&x::CopyArea {
+            src_drawable: x::Drawable::Pixmap(self.pixmap),
+            dst_drawable: dst,
+            gc: self.gc,
+            src_x: src_rect.x,
+            src_y: src_rect.y,
+            dst_x: dst_pt.x,
+            dst_y: dst_pt.y,
+            width: src_rect.width,
+            height: src_rect.height,
+        });
+    }
+
+    /// Composites `src_rect` of the surface onto the picture `dst`, at `dst_pt`, with `op`.
+    ///
+    /// Unlike [Offscreen::copy_to], `dst` can have any format.
+    ///
+    /// # Panics
+    /// Panics if the surface has no picture (see [Offscreen::picture]).
+    #[cfg(feature = "render")]
+    pub fn composite_to(
+        &self,
+        dst: render::Picture,
+        op: render::PictOp,
+        src_rect: x::Rectangle,
+        dst_pt: x::Point,
+    ) {
+        let src = self.picture().expect("the surface has no picture");
+        self.conn.send_request(&render::Composite {
+            op,
+            src,
+            mask: render::Picture::none(),
+            dst,
+            src_x: src_rect.x,
+            src_y: src_rect.y,
+            mask_x: 0,
+            mask_y: 0,
+            dst_x: dst_pt.x,
+            dst_y: dst_pt.y,
+            width: src_rect.width,
+            height: src_rect.height,
+        });
+    }
+
+    /// Adds a picture to the surface, of the format of `visual` if there is one.
+    ///
+    /// The surface is freed on error.
+    #[cfg(feature = "render")]
+    fn with_picture(mut self, visual: Option<x::Visualid>) -> Result<Offscreen<'a>> {
+        let conn = self.conn;
+        let cookie = conn.send_request(&render::QueryPictFormats {});
+        let reply = conn
+            .wait_for_reply(cookie)
+            .context("QueryPictFormats", || render::XNAME)?;
+        let visual_format = visual.and_then(|visual| {
+            reply
+                .screens()
+                .flat_map(|screen| screen.depths())
+                .flat_map(|depth| depth.visuals().iter())
+                .find(|pv| pv.visual == visual)
+                .map(|pv| pv.format)
+        });
+        if let Some(format) = pick_format(reply.formats(), visual_format, self.depth) {
+            self.picture = Some((conn.generate_id(), format));
+            if let Err(err) = self.create_picture() {
+                // not to be freed on drop
+                self.picture = None;
+                return Err(err);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Creates the picture of the pixmap, if the surface has one.
+    #[cfg(feature = "render")]
+    fn create_picture(&self) -> Result<()> {
+        if let Some((picture, format)) = self.picture {
+            let cookie = self.conn.send_request_checked(&render::CreatePicture {
+                pid: picture,
+                drawable: x::Drawable::Pixmap(self.pixmap),
+                format,
+                value_list: &[],
+            });
+            self.conn
+                .check_request(cookie)
+                .context("CreatePicture", || format!("{:?}", self.pixmap))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> std::fmt::Debug for Offscreen<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("Offscreen");
+        s.field("like", &self.like)
+            .field("depth", &self.depth)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixmap", &self.pixmap)
+            .field("gc", &self.gc);
+        #[cfg(feature = "render")]
+        s.field("picture", &self.picture);
+        s.finish()
+    }
+}
+
+impl<'a> Drop for Offscreen<'a> {
+    fn drop(&mut self) {
+        #[cfg(feature = "render")]
+        if let Some((picture, _)) = self.picture {
+            self.conn.send_request(&render::FreePicture { picture });
+        }
+        self.conn.send_request(&x::FreeGc { gc: self.gc });
+        self.conn.send_request(&x::FreePixmap {
+            pixmap: self.pixmap,
+        });
+    }
+}
+
+/// The length in bytes of a row of `width` pixels of `bits_per_pixel`, padded to a
+/// multiple of `scanline_pad` bits.
+pub(crate) fn stride(width: u16, bits_per_pixel: usize, scanline_pad: usize) -> usize {
+    let bits = width as usize * bits_per_pixel;
+    bits.div_ceil(scanline_pad) * scanline_pad / 8
+}
+
+/// Picks the picture format of a pixmap of `depth`.
+///
+/// The format of the visual of the reference window, `visual_format`, is preferred.
+/// Otherwise the direct formats of `depth` are considered, preferring the ones whose
+/// channels span the whole depth (i.e. with alpha at depth 32, without at depth 24),
+/// then the ones with red in the high bits, as the visuals of most servers.
+#[cfg(feature = "render")]
+pub(crate) fn pick_format(
+    formats: &[render::Pictforminfo],
+    visual_format: Option<render::Pictformat>,
+    depth: u8,
+) -> Option<render::Pictformat> {
+    let candidates = || {
+        formats
+            .iter()
+            .filter(|f| f.r#type() == render::PictType::Direct && f.depth() == depth)
+    };
+    if let Some(format) = visual_format {
+        if candidates().any(|f| f.id() == format) {
+            return Some(format);
+        }
+    }
+    candidates()
+        .rev()
+        .max_by_key(|f| {
+            let direct = f.direct();
+            let bits: u32 = [
+                direct.red_mask,
+                direct.green_mask,
+                direct.blue_mask,
+                direct.alpha_mask,
+            ]
+            .iter()
+            .map(|mask| mask.count_ones())
+            .sum();
+            (bits == depth as u32, direct.red_shift > direct.blue_shift)
+        })
+        .map(|f| f.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stride() {
+        assert_eq!(stride(0, 32, 32), 0);
+        assert_eq!(stride(3, 32, 32), 12);
+        assert_eq!(stride(3, 24, 32), 12);
+        assert_eq!(stride(5, 24, 32), 16);
+        assert_eq!(stride(1, 1, 32), 4);
+        assert_eq!(stride(33, 1, 32), 8);
+        assert_eq!(stride(3, 8, 8), 3);
+        assert_eq!(stride(3, 16, 32), 8);
+    }
+
+    /// The tests that need the `render` cargo feature.
+    #[cfg(feature = "render")]
+    mod offscreen {
+        use super::*;
+        use crate::base::WiredOut;
+        use crate::mock;
+        use crate::XidNew;
+
+        use std::collections::HashSet;
+        use std::sync::{Arc, Mutex};
+
+        const RENDER_OPCODE: u8 = 139;
+        const ROOT_VISUAL: u32 = 0x21;
+
+        const XRGB: u32 = 0x30;
+        const ARGB: u32 = 0x31;
+        const A8: u32 = 0x32;
+        const XBGR: u32 = 0x33;
+
+        /// A direct format of `depth` with the channels `(shift, mask)` red, green, blue and alpha.
+        fn direct(id: u32, depth: u8, channels: [(u16, u16); 4]) -> render::Pictforminfo {
+            let [red, green, blue, alpha] = channels;
+            render::Pictforminfo::new(
+                unsafe { render::Pictformat::new(id) },
+                render::PictType::Direct,
+                depth,
+                render::Directformat {
+                    red_shift: red.0,
+                    red_mask: red.1,
+                    green_shift: green.0,
+                    green_mask: green.1,
+                    blue_shift: blue.0,
+                    blue_mask: blue.1,
+                    alpha_shift: alpha.0,
+                    alpha_mask: alpha.1,
+                },
+                x::Colormap::none(),
+            )
+        }
+
+        fn formats() -> Vec<render::Pictforminfo> {
+            vec![
+                direct(XBGR, 24, [(0, 0xff), (8, 0xff), (16, 0xff), (0, 0)]),
+                direct(A8, 8, [(0, 0), (0, 0), (0, 0), (0, 0xff)]),
+                direct(XRGB, 24, [(16, 0xff), (8, 0xff), (0, 0xff), (0, 0)]),
+                direct(ARGB, 32, [(16, 0xff), (8, 0xff), (0, 0xff), (24, 0xff)]),
+                // a depth 32 format without alpha
+                direct(0x34, 32, [(16, 0xff), (8, 0xff), (0, 0xff), (0, 0)]),
+            ]
+        }
+
+        fn format(id: u32) -> Option<render::Pictformat> {
+            Some(unsafe { render::Pictformat::new(id) })
+        }
+
+        #[test]
+        fn test_pick_format() {
+            let formats = formats();
+
+            // the format of the visual
+            assert_eq!(pick_format(&formats, format(XBGR), 24), format(XBGR));
+            // unless it is not of the depth
+            assert_eq!(pick_format(&formats, format(ARGB), 24), format(XRGB));
+
+            // red in the high bits
+            assert_eq!(pick_format(&formats, None, 24), format(XRGB));
+            // alpha at depth 32
+            assert_eq!(pick_format(&formats, None, 32), format(ARGB));
+            assert_eq!(pick_format(&formats, None, 8), format(A8));
+
+            // no format
+            assert_eq!(pick_format(&formats, None, 16), None);
+            assert_eq!(pick_format(&[], None, 24), None);
+        }
+
+        /// The resources created on the mock server.
+        #[derive(Debug, Default)]
+        struct Resources {
+            live: HashSet<u32>,
+            created: usize,
+            /// The format of the last created picture.
+            format: u32,
+            /// The height, destination ordinate and data length of the `PutImage` requests.
+            images: Vec<(u16, i16, usize)>,
+        }
+
+        /// The reply to `QueryPictFormats`: the formats of [formats], and one screen with
+        /// the root visual in `XRGB`.
+        fn query_pict_formats_reply(seq: u16) -> Vec<u8> {
+            let formats = formats();
+            let mut body = vec![0u8; 24];
+            body[0..4].copy_from_slice(&(formats.len() as u32).to_ne_bytes());
+            body[4..8].copy_from_slice(&1u32.to_ne_bytes()); // screens
+            body[8..12].copy_from_slice(&1u32.to_ne_bytes()); // depths
+            body[12..16].copy_from_slice(&1u32.to_ne_bytes()); // visuals
+            for f in &formats {
+                let mut buf = [0u8; 28];
+                f.serialize(&mut buf);
+                body.extend_from_slice(&buf);
+            }
+            // the screen: depths and fallback
+            body.extend_from_slice(&1u32.to_ne_bytes());
+            body.extend_from_slice(&XRGB.to_ne_bytes());
+            // the depth: depth, visuals
+            body.extend_from_slice(&[24, 0]);
+            body.extend_from_slice(&1u16.to_ne_bytes());
+            body.extend_from_slice(&[0; 4]);
+            body.extend_from_slice(&ROOT_VISUAL.to_ne_bytes());
+            body.extend_from_slice(&XRGB.to_ne_bytes());
+            mock::reply(seq, 0, &body)
+        }
+
+        /// Connects to a mock server with RENDER if `render`, on which the drawables of
+        /// depth 32 have an id above `0x1000`.
+        fn mock_connection(render: bool, resources: Arc<Mutex<Resources>>) -> Connection {
+            let handler = move |req: &mock::MockRequest| {
+                let mut res = resources.lock().unwrap();
+                let mut create = |id: u32| {
+                    assert!(res.live.insert(id), "0x{:x} is already in use", id);
+                    res.created += 1;
+                };
+                match req.opcode {
+                    // QueryExtension
+                    98 => match mock::extension_name(req) {
+                        b"RENDER" => vec![mock::query_extension_reply(req, RENDER_OPCODE, 0, 140)],
+                        _ => vec![mock::reply(req.seq, 0, &[])],
+                    },
+                    // GetWindowAttributes
+                    3 => {
+                        let mut body = vec![0u8; 36];
+                        body[0..4].copy_from_slice(&ROOT_VISUAL.to_ne_bytes());
+                        vec![mock::reply(req.seq, 0, &body)]
+                    }
+                    // GetGeometry
+                    14 => {
+                        let depth = if req.u32_at(4) > 0x1000 { 32 } else { 24 };
+                        vec![mock::reply(req.seq, depth, &[0; 24])]
+                    }
+                    // CreatePixmap, CreateGC
+                    53 | 55 => {
+                        create(req.u32_at(4));
+                        vec![]
+                    }
+                    // PutImage
+                    72 => {
+                        let height = u16::from_ne_bytes([req.data[14], req.data[15]]);
+                        let dst_y = i16::from_ne_bytes([req.data[18], req.data[19]]);
+                        res.images.push((height, dst_y, req.data.len() - 24));
+                        vec![]
+                    }
+                    // FreePixmap, FreeGC
+                    54 | 60 => {
+                        assert!(res.live.remove(&req.u32_at(4)));
+                        vec![]
+                    }
+                    RENDER_OPCODE => match req.data[1] {
+                        // QueryPictFormats
+                        1 => vec![query_pict_formats_reply(req.seq)],
+                        // CreatePicture
+                        4 => {
+                            create(req.u32_at(4));
+                            res.format = req.u32_at(12);
+                            vec![]
+                        }
+                        // FreePicture
+                        7 => {
+                            assert!(res.live.remove(&req.u32_at(4)));
+                            vec![]
+                        }
+                        _ => vec![],
+                    },
+                    _ => mock::default_response(req),
+                }
+            };
+            if render {
+                mock::connect_with_extensions(handler, &[crate::Extension::Render])
+            } else {
+                mock::connect(handler)
+            }
+        }
+
+        /// Waits for the server to process the requests sent so far.
+        fn sync(conn: &Connection) {
+            let cookie = conn.send_request(&x::GetInputFocus {});
+            conn.wait_for_reply(cookie).unwrap();
+        }
+
+        #[test]
+        fn test_offscreen_lifecycle() {
+            let resources = Arc::new(Mutex::new(Resources::default()));
+            let conn = mock_connection(true, resources.clone());
+            let window = x::Drawable::Window(unsafe { x::Window::new(0x100) });
+
+            let mut surface = Offscreen::new(&conn, window, 64, 32).unwrap();
+            assert_eq!(surface.depth(), 24);
+            assert_eq!(surface.size(), (64, 32));
+            assert_eq!(surface.picture_format(), format(XRGB));
+            let ids = (surface.pixmap(), surface.gc(), surface.picture().unwrap());
+            sync(&conn);
+            {
+                let res = resources.lock().unwrap();
+                assert_eq!(res.live.len(), 3);
+                assert_eq!(res.created, 3);
+                assert_eq!(res.format, XRGB);
+            }
+
+            // the pixmap and the picture are recreated, with the same ids
+            surface.resize(128, 32).unwrap();
+            surface.resize(128, 32).unwrap();
+            sync(&conn);
+            assert_eq!(
+                (surface.pixmap(), surface.gc(), surface.picture().unwrap()),
+                ids
+            );
+            assert_eq!(surface.size(), (128, 32));
+            {
+                let res = resources.lock().unwrap();
+                assert_eq!(res.live.len(), 3);
+                assert_eq!(res.created, 5);
+            }
+
+            drop(surface);
+            sync(&conn);
+            assert!(resources.lock().unwrap().live.is_empty());
+        }
+
+        #[test]
+        fn test_offscreen_depth() {
+            let resources = Arc::new(Mutex::new(Resources::default()));
+            let conn = mock_connection(true, resources.clone());
+
+            // a pixmap has no visual, its format is picked by depth
+            let pixmap = x::Drawable::Pixmap(unsafe { x::Pixmap::new(0x2000) });
+            let surface = Offscreen::new(&conn, pixmap, 16, 16).unwrap();
+            assert_eq!(surface.depth(), 32);
+            assert_eq!(surface.picture_format(), format(ARGB));
+            assert_eq!(surface.stride(3), 12);
+        }
+
+        #[test]
+        fn test_offscreen_put_image() {
+            let resources = Arc::new(Mutex::new(Resources::default()));
+            let conn = mock_connection(true, resources.clone());
+            let window = x::Drawable::Window(unsafe { x::Window::new(0x100) });
+            let surface = Offscreen::new(&conn, window, 256, 300).unwrap();
+
+            // rows of 1 KiB, 255 of which fit in a request of the mock server
+            let image = Image {
+                width: 256,
+                height: 300,
+                data: vec![0; 1024 * 300],
+            };
+            surface.put_image(&image, x::Point { x: 0, y: 10 });
+            sync(&conn);
+            assert_eq!(
+                resources.lock().unwrap().images,
+                vec![(255, 10, 255 * 1024), (45, 265, 45 * 1024)]
+            );
+        }
+
+        #[test]
+        fn test_offscreen_without_render() {
+            let resources = Arc::new(Mutex::new(Resources::default()));
+            let conn = mock_connection(false, resources.clone());
+            let window = x::Drawable::Window(unsafe { x::Window::new(0x100) });
+
+            let surface = Offscreen::new(&conn, window, 64, 32).unwrap();
+            assert_eq!(surface.picture(), None);
+            sync(&conn);
+            assert_eq!(resources.lock().unwrap().live.len(), 2);
+
+            drop(surface);
+            sync(&conn);
+            assert!(resources.lock().unwrap().live.is_empty());
+        }
+    }
+}
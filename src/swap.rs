@@ -0,0 +1,167 @@
+//! Scheduling of buffer swaps with DRI2 or Present.
+//!
+//! [Scheduler] is implemented by [Dri2Scheduler], which swaps the buffers of a DRI2 drawable
+//! with `dri2::SwapBuffers`, and by [PresentScheduler], which presents pixmaps with
+//! `present::Pixmap`. [autodetect] picks Present when the server supports it, such that a
+//! render loop doesn't depend on the driver.
+//!
+//! Both backends report the completed swaps as [SwapComplete]. The serial of a swap is
+//! the swap buffer count (SBC) of the drawable with DRI2, and a counter of the scheduler
+//! with Present. The server only sends the lower 32 bits of them in the completion events,
+//! the serials are widened back to 64 bits from the serials of the requested swaps.
+//!
+//! Accessible with the `dri2` or `present` cargo feature.
+
+use crate::base::{Connection, Result};
+use crate::event::Event;
+use crate::x;
+
+#[cfg(feature = "dri2")]
+use crate::base::{ResultExt, Xid};
+#[cfg(any(feature = "dri2", feature = "present"))]
+use crate::ext::Extension;
+
+#[cfg(feature = "dri2")]
+pub use crate::dri2::Dri2Scheduler;
+#[cfg(feature = "present")]
+pub use crate::present::PresentScheduler;
+
+/// A completed swap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SwapComplete {
+    /// The serial of the swap, as returned by [Scheduler::request_swap].
+    pub serial: u64,
+    /// The system time of the swap, in microseconds.
+    pub ust: u64,
+    /// The media stream counter (the vertical blank count) of the swap.
+    pub msc: u64,
+}
+
+/// The backend of a [Scheduler].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// `dri2::SwapBuffers`.
+    Dri2,
+    /// `present::Pixmap`.
+    Present,
+}
+
+/// Schedules the swaps of the buffers of a window and reports their completion.
+///
+/// The completions are received as events: [Scheduler::handle_event] is meant to be called
+/// from the event loop with every event.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # use xcb::x;
+/// let optional = [
+///     #[cfg(feature = "present")]
+///     xcb::Extension::Present,
+///     #[cfg(feature = "dri2")]
+///     xcb::Extension::Dri2,
+/// ];
+/// let (conn, _) = xcb::Connection::connect_with_extensions(None, &[], &optional)?;
+/// # let window: x::Window = conn.generate_id();
+/// # let pixmap: x::Pixmap = conn.generate_id();
+/// let mut scheduler = xcb::swap::autodetect(&conn, window)?.expect("no DRI2 nor Present");
+/// scheduler.set_pixmap(pixmap);
+/// let mut msc = 0;
+/// loop {
+///     // render the next frame
+///     scheduler.request_swap(msc + 1)?;
+///     while scheduler.pending() > 0 {
+///         let event = conn.wait_for_event()?;
+///         scheduler.handle_event(&event);
+///     }
+///     for complete in scheduler.poll_completions() {
+///         msc = complete.msc;
+///     }
+/// }
+/// # }
+/// ```
+pub trait Scheduler {
+    /// The backend of the scheduler.
+    fn backend(&self) -> Backend;
+
+    /// Sets the pixmap presented by the next swaps.
+    ///
+    /// Only the backends that present pixmaps use it. The others swap the buffers of the
+    /// window and ignore it.
+    fn set_pixmap(&mut self, _pixmap: x::Pixmap) {}
+
+    /// Schedules a swap at the media stream counter `target_msc`, or as soon as possible
+    /// if `target_msc` is already passed (e.g. `0`).
+    ///
+    /// Returns the serial of the swap.
+    fn request_swap(&mut self, target_msc: u64) -> Result<u64>;
+
+    /// Records the completion of a swap if `event` reports one, in which case `true`
+    /// is returned.
+    fn handle_event(&mut self, event: &Event) -> bool;
+
+    /// The number of requested swaps that are not completed yet.
+    fn pending(&self) -> usize;
+
+    /// Returns the completions recorded since the last call, in order.
+    fn poll_completions(&mut self) -> Vec<SwapComplete>;
+}
+
+/// Builds the scheduler of `window`, with Present if the server supports it, or DRI2.
+///
+/// The extensions must have been cached by the connection, for their events to be resolved:
+/// the connection must be opened with `Extension::Present` and `Extension::Dri2` as optional
+/// extensions. With DRI2, the DRI2 drawable of `window` is created.
+///
+/// Returns `None` if neither is supported.
+pub fn autodetect<'a>(
+    conn: &'a Connection,
+    window: x::Window,
+) -> Result<Option<Box<dyn Scheduler + 'a>>> {
+    #[cfg(feature = "present")]
+    {
+        if conn
+            .active_extensions()
+            .any(|ext| ext == Extension::Present)
+        {
+            return Ok(Some(Box::new(PresentScheduler::new(conn, window)?)));
+        }
+    }
+    #[cfg(feature = "dri2")]
+    {
+        if conn.active_extensions().any(|ext| ext == Extension::Dri2) {
+            let drawable = x::Drawable::Window(window);
+            conn.send_and_check_request(&crate::dri2::CreateDrawable { drawable })
+                .context("CreateDrawable", || {
+                    format!("for window {}", window.resource_id())
+                })?;
+            return Ok(Some(Box::new(Dri2Scheduler::new(conn, drawable))));
+        }
+    }
+    Ok(None)
+}
+
+/// Widens `serial`, the lower 32 bits of the serial of a swap, to the serial closest
+/// to `last`, the serial of the last requested swap.
+pub(crate) fn widen_serial(last: u64, serial: u32) -> u64 {
+    crate::base::widen(last.saturating_sub(0x8000_0000), serial as u64, 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widen_serial() {
+        assert_eq!(widen_serial(0, 0), 0);
+        assert_eq!(widen_serial(10, 8), 8);
+        assert_eq!(widen_serial(10, 10), 10);
+
+        // across the wrap around of the 32-bit serials
+        assert_eq!(widen_serial(0xffff_fffe, 0xffff_fffd), 0xffff_fffd);
+        assert_eq!(widen_serial(0x1_0000_0001, 0xffff_fffe), 0xffff_fffe);
+        assert_eq!(widen_serial(0x1_0000_0001, 0x0000_0001), 0x1_0000_0001);
+        assert_eq!(widen_serial(0x1_0000_0001, 0x0000_0000), 0x1_0000_0000);
+        assert_eq!(widen_serial(0x5_0000_0002, 0xffff_ffff), 0x4_ffff_ffff);
+    }
+}
@@ -134,6 +134,23 @@ fn test_dynbuf_from_data_panic() {
     unsafe { x::DepthBuf::from_data(data) };
 }
 
+#[test]
+fn test_iterator_len() {
+    use crate::mock;
+
+    // the mock server has a single screen, without depths
+    let conn = mock::connect(mock::default_response);
+    let mut roots = conn.get_setup().roots();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(roots.size_hint(), (1, Some(1)));
+
+    let screen = roots.next().unwrap();
+    assert_eq!(roots.len(), 0);
+    assert!(roots.next().is_none());
+    assert_eq!(screen.root(), unsafe { x::Window::new(mock::ROOT) });
+    assert_eq!(screen.allowed_depths().len(), 0);
+}
+
 #[test]
 fn test_cw_is_sorted_distinct() {
     assert!(x::Cw::is_sorted_distinct(&[
@@ -206,3 +223,1518 @@ fn test_event_new() {
     assert_eq!(ev.target(), target);
     assert_eq!(ev.property(), property);
 }
+
+#[test]
+fn test_input_event_new() {
+    use crate::Xid;
+
+    let root = unsafe { x::Window::new(0x100) };
+    let window = unsafe { x::Window::new(0x0120_0001) };
+    let state = x::KeyButMask::SHIFT | x::KeyButMask::BUTTON1;
+
+    let press = x::KeyPressEvent::new(
+        38,
+        1000,
+        root,
+        window,
+        x::Window::none(),
+        110,
+        220,
+        10,
+        20,
+        state,
+        true,
+    );
+    assert_eq!(press.response_type(), x::KeyPressEvent::NUMBER as u8);
+    assert_eq!(press.detail(), 38);
+    assert_eq!(press.time(), 1000);
+    assert_eq!(press.root(), root);
+    assert_eq!(press.event(), window);
+    assert_eq!(press.child(), x::Window::none());
+    assert_eq!((press.root_x(), press.root_y()), (110, 220));
+    assert_eq!((press.event_x(), press.event_y()), (10, 20));
+    assert_eq!(press.state(), state);
+    assert!(press.same_screen());
+
+    let release = x::KeyReleaseEvent::new_key_release(
+        38,
+        1001,
+        root,
+        window,
+        x::Window::none(),
+        110,
+        220,
+        10,
+        20,
+        state,
+        true,
+    );
+    assert_eq!(release.response_type(), 3);
+    assert_eq!(release.time(), 1001);
+
+    let release = x::ButtonReleaseEvent::new_button_release(
+        1,
+        1002,
+        root,
+        window,
+        window,
+        -5,
+        7,
+        -5,
+        7,
+        x::KeyButMask::BUTTON1,
+        false,
+    );
+    assert_eq!(release.response_type(), 5);
+    assert_eq!(release.detail(), 1);
+    assert_eq!(release.child(), window);
+    assert_eq!((release.event_x(), release.event_y()), (-5, 7));
+    assert!(!release.same_screen());
+
+    let motion = x::MotionNotifyEvent::new(
+        x::Motion::Normal,
+        1003,
+        root,
+        window,
+        x::Window::none(),
+        300,
+        400,
+        30,
+        40,
+        x::KeyButMask::empty(),
+        true,
+    );
+    assert_eq!(motion.response_type(), x::MotionNotifyEvent::NUMBER as u8);
+    assert_eq!(motion.detail(), x::Motion::Normal);
+    assert_eq!((motion.root_x(), motion.root_y()), (300, 400));
+
+    // the event sent with SendEvent
+    let mut wire = [0xffu8; 32];
+    assert_eq!(press.serialize(&mut wire), 32);
+    let mut expected = vec![2, 38, 0, 0];
+    expected.extend_from_slice(&1000u32.to_ne_bytes());
+    expected.extend_from_slice(&0x100u32.to_ne_bytes());
+    expected.extend_from_slice(&0x0120_0001u32.to_ne_bytes());
+    expected.extend_from_slice(&0u32.to_ne_bytes());
+    for coord in [110i16, 220, 10, 20] {
+        expected.extend_from_slice(&coord.to_ne_bytes());
+    }
+    expected.extend_from_slice(&(state.bits() as u16).to_ne_bytes());
+    expected.extend_from_slice(&[1, 0]);
+    assert_eq!(&wire[..], &expected[..]);
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_extension_event_new() {
+    use crate::xv;
+
+    let event_base = 90;
+    let drawable = x::Drawable::Window(unsafe { x::Window::new(0x0120_0001) });
+    let port = unsafe { xv::Port::new(0x44) };
+    let ev = xv::PortNotifyEvent::new(event_base, x::CURRENT_TIME, port, x::ATOM_STRING, -5);
+    assert_eq!(
+        ev.response_type(),
+        event_base + xv::PortNotifyEvent::NUMBER as u8
+    );
+
+    let ev = xv::VideoNotifyEvent::new(
+        event_base,
+        xv::VideoNotifyReason::Busy,
+        x::CURRENT_TIME,
+        drawable,
+        port,
+    );
+    assert_eq!(ev.response_type(), event_base);
+    assert_eq!(ev.sequence(), 0);
+    assert_eq!(ev.reason(), xv::VideoNotifyReason::Busy);
+    assert_eq!(ev.port(), port);
+
+    // the padding sent with SendEvent is initialized
+    let mut wire = [0xffu8; 32];
+    assert_eq!(ev.serialize(&mut wire), 32);
+    assert_eq!(wire[0], event_base);
+    assert_eq!(&wire[16..], &[0u8; 16]);
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_send_event_wire() {
+    use crate::{mock, xv, Extension, Xid};
+    use std::sync::{Arc, Mutex};
+
+    // the events received by the mock server in SendEvent requests
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let conn = {
+        let sent = sent.clone();
+        mock::connect_with_extensions(
+            move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+                25 => {
+                    sent.lock().unwrap().push(req.data[12..44].to_vec());
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            },
+            &[Extension::Xv],
+        )
+    };
+    let window = unsafe { x::Window::new(0x400) };
+
+    let message = x::ClientMessageEvent::new(
+        window,
+        x::ATOM_WM_NAME,
+        x::ClientMessageData::Data32([1, 2, 3, 4, 5]),
+    );
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(window),
+        event_mask: x::EventMask::NO_EVENT,
+        event: &message,
+    })
+    .unwrap();
+
+    let first_event = conn.extension_data(Extension::Xv).unwrap().first_event;
+    let port = unsafe { xv::Port::new(0x44) };
+    let notify = xv::PortNotifyEvent::new(first_event, x::CURRENT_TIME, port, x::ATOM_STRING, -5);
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(window),
+        event_mask: x::EventMask::NO_EVENT,
+        event: &notify,
+    })
+    .unwrap();
+
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+
+    let mut wire = vec![33, 32, 0, 0];
+    wire.extend_from_slice(&0x400u32.to_ne_bytes());
+    wire.extend_from_slice(&x::ATOM_WM_NAME.resource_id().to_ne_bytes());
+    for v in 1..=5u32 {
+        wire.extend_from_slice(&v.to_ne_bytes());
+    }
+    assert_eq!(sent[0], wire);
+
+    // the event code of the extension, and a null sequence
+    assert_eq!(sent[1][0], first_event + 1);
+    assert_eq!(&sent[1][2..4], &[0, 0]);
+    assert_eq!(&sent[1][8..12], &0x44u32.to_ne_bytes());
+    assert_eq!(&sent[1][16..20], &(-5i32).to_ne_bytes());
+    assert_eq!(&sent[1][20..], &[0u8; 12]);
+}
+
+#[test]
+#[cfg(feature = "xinput")]
+fn test_xi_grab_device_mask() {
+    use crate::{mock, xinput, Extension, Xid};
+    use std::sync::{Arc, Mutex};
+
+    // the mask words of the XIGrabDevice requests received by the mock server
+    let masks = Arc::new(Mutex::new(Vec::new()));
+    let conn = {
+        let masks = masks.clone();
+        mock::connect_with_extensions(
+            move |req| match req.opcode {
+                98 => vec![mock::query_extension_reply(req, 131, 0, 0)],
+                131 => {
+                    assert_eq!(req.data[1], 51);
+                    let mask_len = u16::from_ne_bytes([req.data[22], req.data[23]]) as usize;
+                    let words: Vec<u32> = (0..mask_len).map(|i| req.u32_at(24 + 4 * i)).collect();
+                    masks.lock().unwrap().push(words);
+                    vec![mock::reply(req.seq, 0, &[])]
+                }
+                _ => mock::default_response(req),
+            },
+            &[Extension::Input],
+        )
+    };
+    let window = unsafe { x::Window::new(mock::ROOT) };
+
+    let mask = xinput::XiEventMask::BUTTON_PRESS | xinput::XiEventMask::MOTION;
+    assert_eq!(mask.bits(), 0x50);
+    assert_eq!(xinput::XiEventMask::from_bits(0x50), Some(mask));
+
+    let cookie = conn.send_request(&xinput::XiGrabDevice {
+        window,
+        time: x::CURRENT_TIME,
+        cursor: x::Cursor::none(),
+        device: xinput::Device::AllMaster,
+        mode: x::GrabMode::Async,
+        paired_device_mode: x::GrabMode::Async,
+        owner_events: xinput::GrabOwner::NoOwner,
+        mask: &[mask],
+    });
+    let reply = conn.wait_for_reply(cookie).unwrap();
+    assert_eq!(reply.status(), x::GrabStatus::Success);
+
+    let masks = masks.lock().unwrap();
+    assert_eq!(*masks, [vec![0x50]]);
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_unknown_event_cast() {
+    use crate::base::Raw;
+    use crate::{mock, xv, UnknownEvent};
+
+    let conn = mock::connect_with_extensions(
+        |req| match req.opcode {
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            _ => mock::default_response(req),
+        },
+        &[crate::Extension::Xv],
+    );
+    let first_event = conn
+        .extension_data(crate::Extension::Xv)
+        .unwrap()
+        .first_event;
+    assert_eq!(first_event, 80);
+    assert!(conn.extension_data(crate::Extension::Shm).is_none());
+    assert_eq!(xv::VideoNotifyEvent::event_code(first_event), 80);
+    assert_eq!(xv::PortNotifyEvent::event_code(first_event), 81);
+    assert_eq!(x::KeyPressEvent::event_code(first_event), 2);
+
+    let port = unsafe { xv::Port::new(0x44) };
+    let ev = xv::PortNotifyEvent::new(first_event, x::CURRENT_TIME, port, x::ATOM_STRING, -5);
+    let ev = unsafe { UnknownEvent::from_raw(ev.into_raw()) };
+
+    // the event is given back if the response type doesn't match
+    let ev = ev
+        .cast_event::<xv::VideoNotifyEvent>(first_event)
+        .unwrap_err();
+    let ev = ev.cast_event::<x::KeyPressEvent>(first_event).unwrap_err();
+    let ev: xv::PortNotifyEvent = ev.cast_event(first_event).unwrap();
+    assert_eq!(ev.port(), port);
+    assert_eq!(ev.value(), -5);
+
+    // the events sent with SendEvent have the most significant bit of response_type set
+    let ev = unsafe { UnknownEvent::from_raw(ev.into_raw()) };
+    unsafe { (*ev.as_raw()).response_type |= 0x80 };
+    assert_eq!(ev.response_type(), 0x80 | 81);
+    assert!(ev.cast_event::<xv::PortNotifyEvent>(first_event).is_ok());
+}
+
+#[test]
+fn test_change_property_request_len() {
+    let window = unsafe { x::Window::new(12) };
+    let change = |data: &[u8]| {
+        x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window,
+            property: x::ATOM_WM_NAME,
+            r#type: x::ATOM_STRING,
+            data,
+        }
+        .request_len()
+    };
+    // 24 bytes of fixed part, and the data padded to 4 bytes
+    assert_eq!(change(b""), 24);
+    assert_eq!(change(b"a"), 28);
+    assert_eq!(change(b"abcd"), 28);
+    assert_eq!(change(b"abcde"), 32);
+
+    let request = x::ChangeProperty {
+        mode: x::PropMode::Append,
+        window,
+        property: x::ATOM_WM_ICON_SIZE,
+        r#type: x::ATOM_CARDINAL,
+        data: &[1u32, 2, 3],
+    };
+    assert_eq!(request.request_len(), 36);
+    let request = x::ChangeProperty {
+        mode: x::PropMode::Append,
+        window,
+        property: x::ATOM_WM_ICON_SIZE,
+        r#type: x::ATOM_CARDINAL,
+        data: &[1u16, 2, 3],
+    };
+    assert_eq!(request.request_len(), 32);
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_put_image_request_len() {
+    use crate::xv;
+
+    let data = [0u8; 9];
+    let put_image = |len: usize| {
+        xv::PutImage {
+            port: unsafe { xv::Port::new(1) },
+            drawable: x::Drawable::Window(unsafe { x::Window::new(12) }),
+            gc: unsafe { x::Gcontext::new(13) },
+            id: 0x32595559,
+            src_x: 0,
+            src_y: 0,
+            src_w: 2,
+            src_h: 2,
+            drw_x: 0,
+            drw_y: 0,
+            drw_w: 2,
+            drw_h: 2,
+            width: 2,
+            height: 2,
+            data: &data[..len],
+        }
+        .request_len()
+    };
+    // 40 bytes of fixed part, and the data padded to 4 bytes
+    assert_eq!(put_image(0), 40);
+    assert_eq!(put_image(1), 44);
+    assert_eq!(put_image(8), 48);
+    assert_eq!(put_image(9), 52);
+}
+
+#[test]
+#[cfg(feature = "dri2")]
+fn test_get_buffers_with_format_request_len() {
+    use crate::dri2;
+
+    let attachments = [
+        dri2::AttachFormat::new(dri2::Attachment::BufferBackLeft, 32),
+        dri2::AttachFormat::new(dri2::Attachment::BufferDepth, 24),
+        dri2::AttachFormat::new(dri2::Attachment::BufferStencil, 8),
+    ];
+    let get_buffers = |count: usize| {
+        dri2::GetBuffersWithFormat {
+            drawable: x::Drawable::Window(unsafe { x::Window::new(12) }),
+            count: count as u32,
+            attachments: &attachments[..count],
+        }
+        .request_len()
+    };
+    // 12 bytes of fixed part, and 8 bytes per attachment
+    assert_eq!(get_buffers(0), 12);
+    assert_eq!(get_buffers(1), 20);
+    assert_eq!(get_buffers(3), 36);
+}
+
+#[test]
+fn test_fits_in_request() {
+    use crate::mock;
+
+    // the mock server has a maximum request length of 0xffff, without BIG-REQUESTS
+    let conn = mock::connect(|req| match req.opcode {
+        98 => vec![mock::reply(req.seq, 0, &[])],
+        _ => mock::default_response(req),
+    });
+    assert!(conn.fits_in_request(0));
+    assert!(conn.fits_in_request(0xffff * 4));
+    assert!(!conn.fits_in_request(0xffff * 4 + 1));
+}
+
+#[test]
+fn test_maximum_request_length_big_requests() {
+    use crate::mock;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    const BIGREQ_OPCODE: u8 = 133;
+
+    // BIG-REQUESTS raises the maximum request length to 4 MiB
+    let enables = Arc::new(AtomicU32::new(0));
+    let conn = {
+        let enables = enables.clone();
+        mock::connect(move |req| match req.opcode {
+            98 => match mock::extension_name(req) {
+                b"BIG-REQUESTS" => vec![mock::query_extension_reply(req, BIGREQ_OPCODE, 0, 0)],
+                _ => vec![mock::reply(req.seq, 0, &[])],
+            },
+            // bigreq::Enable
+            BIGREQ_OPCODE => {
+                enables.fetch_add(1, Ordering::SeqCst);
+                vec![mock::reply(req.seq, 0, &(1u32 << 20).to_ne_bytes())]
+            }
+            _ => mock::default_response(req),
+        })
+    };
+
+    crate::bigreq::prefetch_extension_data(&conn);
+    conn.prefetch_maximum_request_length();
+    assert_eq!(conn.get_maximum_request_length(), 1 << 20);
+    assert_eq!(conn.get_maximum_request_length(), 1 << 20);
+    assert_eq!(enables.load(Ordering::SeqCst), 1);
+    // the extended length of the large requests is accounted for
+    assert!(conn.fits_in_request((1 << 22) - 4));
+    assert!(!conn.fits_in_request(1 << 22));
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_reply_error() {
+    use crate::{mock, xv, Error, ProtocolError};
+
+    // an invalid port: GrabPort is answered with a BadPort error instead of a reply
+    let conn = mock::connect_with_extensions(
+        |req| match req.opcode {
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            140 if req.data[1] == 3 => vec![mock::error(req.seq, 150, req.u32_at(4), 140)],
+            _ => mock::default_response(req),
+        },
+        &[crate::Extension::Xv],
+    );
+    let port = unsafe { xv::Port::new(42) };
+
+    let cookie = conn.send_request(&xv::GrabPort {
+        port,
+        time: x::CURRENT_TIME,
+    });
+    match conn.wait_for_reply(cookie) {
+        Err(Error::Protocol(ProtocolError::Xv(xv::Error::BadPort(err), _))) => {
+            assert_eq!(err.error_code(), 150);
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // unchecked, the error is delivered to the event loop
+    let cookie = conn.send_request_unchecked(&xv::GrabPort {
+        port,
+        time: x::CURRENT_TIME,
+    });
+    assert!(conn.wait_for_reply_unchecked(cookie).unwrap().is_none());
+    assert!(matches!(
+        conn.poll_for_event(),
+        Err(Error::Protocol(ProtocolError::Xv(xv::Error::BadPort(_), _)))
+    ));
+}
+
+#[test]
+fn test_checked_and_unchecked_errors() {
+    use crate::{mock, Error, ProtocolError};
+
+    // GetGeometry fails with BadDrawable and MapWindow with BadWindow
+    let conn = mock::connect(|req| match req.opcode {
+        14 => vec![mock::error(req.seq, 9, req.u32_at(4), 14)],
+        8 => vec![mock::error(req.seq, 3, req.u32_at(4), 8)],
+        _ => mock::default_response(req),
+    });
+    let window = unsafe { x::Window::new(0x400) };
+    let sync = || {
+        let cookie = conn.send_request(&x::GetInputFocus {});
+        conn.wait_for_reply(cookie).unwrap();
+    };
+
+    // checked request with reply: the error comes with the reply
+    let cookie = conn.send_request(&x::GetGeometry {
+        drawable: x::Drawable::Window(window),
+    });
+    assert!(matches!(
+        conn.wait_for_reply(cookie),
+        Err(Error::Protocol(ProtocolError::X(x::Error::Drawable(_), _)))
+    ));
+    sync();
+    assert!(conn.poll_for_event().unwrap().is_none());
+
+    // unchecked request with reply: the error goes to the event loop
+    let cookie = conn.send_request_unchecked(&x::GetGeometry {
+        drawable: x::Drawable::Window(window),
+    });
+    assert!(conn.wait_for_reply_unchecked(cookie).unwrap().is_none());
+    assert!(matches!(
+        conn.poll_for_event(),
+        Err(Error::Protocol(ProtocolError::X(x::Error::Drawable(_), _)))
+    ));
+
+    // checked void request: the error is returned by check_request
+    let cookie = conn.send_request_checked(&x::MapWindow { window });
+    assert!(matches!(
+        conn.check_request(cookie),
+        Err(Error::Protocol(ProtocolError::X(x::Error::Window(_), _)))
+    ));
+    sync();
+    assert!(conn.poll_for_event().unwrap().is_none());
+
+    // unchecked void request: the error goes to the event loop
+    conn.send_request(&x::MapWindow { window });
+    sync();
+    assert!(matches!(
+        conn.poll_for_event(),
+        Err(Error::Protocol(ProtocolError::X(x::Error::Window(_), _)))
+    ));
+}
+
+#[test]
+fn test_error_sequence() {
+    use crate::{mock, BaseError, BaseEvent, Cookie, Error, ProtocolError};
+
+    // MapWindow fails with BadWindow for 0x402, and generates MapNotify for the others
+    let conn = mock::connect(|req| match req.opcode {
+        8 if req.u32_at(4) == 0x402 => vec![mock::error(req.seq, 3, 0x402, 8)],
+        8 => {
+            let mut ev = vec![0u8; 32];
+            ev[0] = 19;
+            ev[2..4].copy_from_slice(&req.seq.to_ne_bytes());
+            ev[8..12].copy_from_slice(&req.data[4..8]);
+            vec![ev]
+        }
+        _ => mock::default_response(req),
+    });
+    let map = |window| {
+        conn.send_request(&x::MapWindow {
+            window: unsafe { x::Window::new(window) },
+        })
+    };
+
+    // the errors and the events of unchecked requests are matched with their cookie
+    let cookies = [map(0x401), map(0x402), map(0x403)];
+    conn.flush().unwrap();
+    let event = match conn.wait_for_event() {
+        Ok(crate::Event::X(x::Event::MapNotify(ev))) => ev,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    assert_eq!(event.full_sequence() as u64, cookies[0].sequence());
+    match conn.wait_for_event() {
+        Err(Error::Protocol(err)) => {
+            assert_eq!(err.full_sequence() as u64, cookies[1].sequence());
+            assert_eq!(err.sequence(), cookies[1].sequence() as u16);
+            match err {
+                ProtocolError::X(x::Error::Window(err), _) => {
+                    assert_eq!(err.full_sequence() as u64, cookies[1].sequence());
+                }
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+    assert!(conn.wait_for_event().is_ok());
+
+    // the events built by the client have no sequence number
+    let event = x::MapNotifyEvent::new(
+        unsafe { x::Window::new(mock::ROOT) },
+        unsafe { x::Window::new(0x401) },
+        false,
+    );
+    assert_eq!(event.full_sequence(), 0);
+}
+
+#[test]
+fn test_resolve_raw_error() {
+    use crate::ffi::{xcb_generic_error_t, xcb_wait_for_event};
+    use crate::{mock, Cookie, ProtocolError};
+
+    // MapWindow fails with BadWindow
+    let conn = mock::connect(|req| match req.opcode {
+        8 => vec![mock::error(req.seq, 3, req.u32_at(4), 8)],
+        _ => mock::default_response(req),
+    });
+    let cookie = conn.send_request(&x::MapWindow {
+        window: unsafe { x::Window::new(0x402) },
+    });
+    conn.flush().unwrap();
+
+    // the error of the unchecked request is returned by the event functions of XCB
+    let ev = unsafe { xcb_wait_for_event(conn.get_raw_conn()) };
+    assert!(!ev.is_null());
+    assert_eq!(unsafe { (*ev).response_type }, 0);
+    let err = unsafe { conn.resolve_error(&mut *(ev as *mut xcb_generic_error_t)) };
+    assert_eq!(err.full_sequence() as u64, cookie.sequence());
+    assert!(matches!(
+        err,
+        ProtocolError::X(x::Error::Window(_), Some("x::MapWindow"))
+    ));
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_extension_reply_errors() {
+    use crate::{mock, xv, Error, ProtocolError};
+
+    // xv::QueryImageAttributes fails with BadPort
+    let conn = mock::connect_with_extensions(
+        |req| match req.opcode {
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            140 if req.data[1] == 17 => {
+                let mut error = mock::error(req.seq, 150, req.u32_at(4), 140);
+                error[8] = 17; // minor opcode
+                vec![error]
+            }
+            _ => mock::default_response(req),
+        },
+        &[crate::Extension::Xv],
+    );
+    let request = xv::QueryImageAttributes {
+        port: unsafe { xv::Port::new(42) },
+        id: 0x3259_5559,
+        width: 640,
+        height: 480,
+    };
+
+    // checked: the error comes with the reply
+    let cookie = conn.send_request(&request);
+    match conn.wait_for_reply(cookie) {
+        Err(Error::Protocol(ProtocolError::Xv(xv::Error::BadPort(_), request))) => {
+            assert_eq!(request, Some("xv::QueryImageAttributes"));
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+    assert!(conn.poll_for_event().unwrap().is_none());
+
+    // unchecked: the error goes to the event loop
+    let cookie = conn.send_request_unchecked(&request);
+    assert!(conn.wait_for_reply_unchecked(cookie).unwrap().is_none());
+    assert!(matches!(
+        conn.poll_for_event(),
+        Err(Error::Protocol(ProtocolError::Xv(xv::Error::BadPort(_), _)))
+    ));
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_unknown_error() {
+    use crate::{mock, xv, Cookie, Error, ProtocolError};
+
+    // the errors of an extension that is not cached, above the errors of Xv
+    let conn = mock::connect_with_extensions(
+        |req| match req.opcode {
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            140 if req.data[1] == 3 => vec![mock::error(req.seq, 153, 42, 140)],
+            127 => vec![mock::error(req.seq, 200, 7, 127)],
+            _ => mock::default_response(req),
+        },
+        &[crate::Extension::Xv],
+    );
+
+    let cookie = conn.send_request(&xv::GrabPort {
+        port: unsafe { xv::Port::new(42) },
+        time: x::CURRENT_TIME,
+    });
+    let seq = cookie.sequence();
+    match conn.wait_for_reply(cookie) {
+        Err(Error::Protocol(ProtocolError::Unknown(err, _))) => {
+            assert_eq!(err.error_code(), 153);
+            assert_eq!(err.full_sequence() as u64, seq);
+            assert_eq!(err.resource_id(), 42);
+            assert_eq!(err.major_code(), 140);
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    let err = match conn.send_and_check_request(&x::NoOperation {}) {
+        Err(Error::Protocol(err)) => err,
+        res => panic!("unexpected result: {:?}", res),
+    };
+    match &err {
+        ProtocolError::Unknown(err, _) => {
+            assert_eq!(err.error_code(), 200);
+            assert_eq!(err.major_code(), 127);
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+    // it can be propagated as a `std::error::Error`
+    let err: Box<dyn std::error::Error> = Box::new(err);
+    assert!(err.to_string().contains("200"));
+}
+
+#[test]
+fn test_replies_are_freed() {
+    // the replies own the memory allocated by libxcb and free it when dropped
+    assert!(std::mem::needs_drop::<x::GetInputFocusReply>());
+    assert!(std::mem::needs_drop::<crate::bigreq::EnableReply>());
+    #[cfg(feature = "xv")]
+    assert!(std::mem::needs_drop::<crate::xv::QueryPortAttributesReply>());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "the lists of xproto::QueryTreeReply overflow its length")]
+fn test_reply_list_overflow() {
+    use crate::mock;
+
+    // QueryTree announces 100 children in a reply without data
+    let conn = mock::connect(|req| match req.opcode {
+        15 => {
+            let mut body = [0u8; 24];
+            body[8..10].copy_from_slice(&100u16.to_ne_bytes());
+            vec![mock::reply(req.seq, 0, &body)]
+        }
+        _ => mock::default_response(req),
+    });
+    let cookie = conn.send_request(&x::QueryTree {
+        window: unsafe { x::Window::new(mock::ROOT) },
+    });
+    let _ = conn.wait_for_reply(cookie);
+}
+
+#[test]
+fn test_reply_list_borrows() {
+    use crate::mock;
+    use base::Reply;
+
+    // GetImage reply of 8 bytes of image data
+    let conn = mock::connect(|req| match req.opcode {
+        73 => {
+            let mut body = vec![0u8; 24];
+            body.extend(0..8u8);
+            vec![mock::reply(req.seq, 24, &body)]
+        }
+        _ => mock::default_response(req),
+    });
+    let cookie = conn.send_request(&x::GetImage {
+        format: x::ImageFormat::ZPixmap,
+        drawable: x::Drawable::Window(unsafe { x::Window::new(mock::ROOT) }),
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 1,
+        plane_mask: !0,
+    });
+    let reply = conn.wait_for_reply(cookie).unwrap();
+    assert_eq!(reply.data(), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+    // the data is not copied: it is read in the buffer of the reply
+    let raw = unsafe { reply.into_raw() };
+    let reply = unsafe { x::GetImageReply::from_raw(raw) };
+    assert_eq!(reply.data().as_ptr(), unsafe { raw.add(32) });
+    assert_eq!(reply.data().as_ptr(), reply.data().as_ptr());
+}
+
+#[test]
+fn test_reply_clone() {
+    use crate::mock;
+
+    // GetImage reply of 8 bytes of image data
+    let conn = mock::connect(|req| match req.opcode {
+        73 => {
+            let mut body = vec![0u8; 24];
+            body.extend(0..8u8);
+            vec![mock::reply(req.seq, 24, &body)]
+        }
+        _ => mock::default_response(req),
+    });
+    let cookie = conn.send_request(&x::GetImage {
+        format: x::ImageFormat::ZPixmap,
+        drawable: x::Drawable::Window(unsafe { x::Window::new(mock::ROOT) }),
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 1,
+        plane_mask: !0,
+    });
+    let reply = conn.wait_for_reply(cookie).unwrap();
+
+    // the clone owns a copy of the reply, which outlives the original
+    let clone = reply.clone();
+    assert_ne!(clone.data().as_ptr(), reply.data().as_ptr());
+    drop(reply);
+    let data = std::thread::spawn(move || clone.data().to_vec())
+        .join()
+        .unwrap();
+    assert_eq!(data, [0, 1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_latin1_names() {
+    use crate::{mock, xv};
+
+    // QueryAdaptors reply with an adaptor named with a Latin-1 registered sign
+    const NAME: &[u8] = b"Intel\xae Textured Video";
+    let conn = mock::connect_with_extensions(
+        |req| match req.opcode {
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            140 if req.data[1] == 1 => {
+                let mut body = vec![0u8; 24];
+                body[0..2].copy_from_slice(&1u16.to_ne_bytes());
+                body.extend_from_slice(&0x40u32.to_ne_bytes());
+                body.extend_from_slice(&(NAME.len() as u16).to_ne_bytes());
+                body.extend_from_slice(&1u16.to_ne_bytes());
+                body.extend_from_slice(&0u16.to_ne_bytes());
+                body.extend_from_slice(&[0x13, 0]);
+                body.extend_from_slice(NAME);
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            _ => mock::default_response(req),
+        },
+        &[crate::Extension::Xv],
+    );
+    let cookie = conn.send_request(&xv::QueryAdaptors {
+        window: unsafe { x::Window::new(mock::ROOT) },
+    });
+    let reply = conn.wait_for_reply(cookie).unwrap();
+    let info = reply.info().next().unwrap();
+
+    let name = info.name();
+    assert_eq!(name.as_bytes(), NAME);
+    assert!(name.try_as_ascii().is_err());
+    assert_eq!(name.to_utf8(), "Intel\u{ae} Textured Video");
+    assert_eq!(name.to_string(), "Intel\u{ae} Textured Video");
+}
+
+#[test]
+fn test_connection_shut_down() {
+    use crate::{mock, ConnError, Error};
+
+    // the server closes the connection when it receives NoOperation
+    let conn = mock::connect(|req| match req.opcode {
+        127 => std::panic::resume_unwind(Box::new("closing")),
+        _ => mock::default_response(req),
+    });
+    assert_eq!(conn.has_error(), Ok(()));
+    let cookie = conn.send_request(&x::GetInputFocus {});
+    conn.wait_for_reply(cookie).unwrap();
+
+    conn.send_request(&x::NoOperation {});
+    // no error can be received for the checked request, the connection error is returned
+    let cookie = conn.send_request_checked(&x::MapWindow {
+        window: unsafe { x::Window::new(mock::ROOT) },
+    });
+    assert!(matches!(
+        conn.check_request(cookie),
+        Err(Error::Connection(ConnError::Connection))
+    ));
+    let cookie = conn.send_request(&x::GetInputFocus {});
+    assert!(matches!(
+        conn.wait_for_reply(cookie),
+        Err(Error::Connection(ConnError::Connection))
+    ));
+    assert_eq!(conn.has_error(), Err(ConnError::Connection));
+    assert_eq!(conn.flush(), Err(ConnError::Connection));
+}
+
+#[test]
+fn test_connect_error() {
+    use crate::{ConnError, Connection};
+
+    // the display name can't be parsed
+    assert_eq!(
+        Connection::connect(Some("not a display")).err(),
+        Some(ConnError::ClosedParseErr)
+    );
+    // no server listens on the socket
+    assert_eq!(
+        Connection::connect(Some("unix:65000")).err(),
+        Some(ConnError::Connection)
+    );
+}
+
+#[test]
+fn test_poisoned_connection() {
+    use crate::{mock, ConnError, Error};
+    use std::os::unix::io::AsRawFd;
+
+    let conn = mock::connect(mock::default_response);
+    assert_eq!(conn.take_error(), None);
+
+    // the socket is shut down under the connection
+    assert_eq!(
+        unsafe { libc::shutdown(conn.as_raw_fd(), libc::SHUT_RDWR) },
+        0
+    );
+    let cookie = conn.send_request(&x::GetInputFocus {});
+    assert!(matches!(
+        conn.wait_for_reply(cookie),
+        Err(Error::Connection(ConnError::Connection))
+    ));
+
+    // the following calls fail right away
+    let cookie = conn.send_request(&x::GetInputFocus {});
+    assert!(matches!(
+        conn.wait_for_reply(cookie),
+        Err(Error::Connection(ConnError::Connection))
+    ));
+    assert_eq!(conn.flush(), Err(ConnError::Connection));
+    assert!(matches!(
+        conn.send_and_check_request(&x::NoOperation {}),
+        Err(Error::Connection(ConnError::Connection))
+    ));
+
+    // the error is taken once, but the connection stays shut down
+    assert_eq!(conn.take_error(), Some(ConnError::Connection));
+    assert_eq!(conn.take_error(), None);
+    assert_eq!(conn.has_error(), Err(ConnError::Connection));
+}
+
+#[test]
+fn test_connect_to_fd_auth_info() {
+    use crate::{AuthInfo, BinaryAuthInfo, ConnError, Connection};
+    use std::io::{Read, Write};
+    use std::os::unix::io::IntoRawFd;
+    use std::os::unix::net::UnixStream;
+
+    // a server that refuses the connection, and sends back the authorization it received
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let server = std::thread::spawn(move || {
+        let mut req = [0u8; 12];
+        server.read_exact(&mut req).unwrap();
+        let name_len = u16::from_ne_bytes([req[6], req[7]]) as usize;
+        let data_len = u16::from_ne_bytes([req[8], req[9]]) as usize;
+        let mut name = vec![0u8; name_len + crate::base::align_pad(name_len, 4)];
+        let mut data = vec![0u8; data_len + crate::base::align_pad(data_len, 4)];
+        server.read_exact(&mut name).unwrap();
+        server.read_exact(&mut data).unwrap();
+        name.truncate(name_len);
+        data.truncate(data_len);
+
+        // failed, with a reason of 8 bytes
+        let mut buf = vec![0, 8];
+        buf.extend_from_slice(&11u16.to_ne_bytes());
+        buf.extend_from_slice(&0u16.to_ne_bytes());
+        buf.extend_from_slice(&2u16.to_ne_bytes());
+        buf.extend_from_slice(b"no auth!");
+        server.write_all(&buf).unwrap();
+        (name, data)
+    });
+
+    // the data is binary, with NUL bytes
+    let cookie = [0x3c, 0x00, 0xa1, 0x5e, 0x00, 0x00, 0xff, 0x7a, 0x91];
+    let auth_info = BinaryAuthInfo {
+        name: "MIT-MAGIC-COOKIE-1",
+        data: &cookie,
+    };
+    let res = Connection::connect_to_fd_with_binary_auth_info(
+        client.into_raw_fd(),
+        Some(auth_info),
+        &[],
+        &[],
+    );
+    assert_eq!(res.err(), Some(ConnError::Connection));
+
+    let (name, data) = server.join().unwrap();
+    assert_eq!(name, b"MIT-MAGIC-COOKIE-1");
+    assert_eq!(data, cookie);
+    // the string data of AuthInfo is passed as is
+    let auth_info = BinaryAuthInfo::from(AuthInfo {
+        name: "XDM-AUTHORIZATION-1",
+        data: "data",
+    });
+    assert_eq!(auth_info.name, "XDM-AUTHORIZATION-1");
+    assert_eq!(auth_info.data, b"data");
+}
+
+#[test]
+fn test_borrowed_raw_conn() {
+    use crate::{mock, Connection};
+
+    let conn = mock::connect(mock::default_response);
+    assert!(conn.owns_raw_conn());
+    let raw = conn.into_raw_conn();
+
+    // another library owns the connection, dropping the borrowed one keeps it open
+    for _ in 0..2 {
+        let conn = unsafe { Connection::from_borrowed_raw_conn(raw) };
+        assert!(!conn.owns_raw_conn());
+        let cookie = conn.send_request(&x::GetInputFocus {});
+        conn.wait_for_reply(cookie).unwrap();
+    }
+    assert_eq!(
+        unsafe { crate::ffi::base::xcb_connection_has_error(raw) },
+        0
+    );
+
+    // the ownership is taken back
+    let conn = unsafe { Connection::from_raw_conn(raw) };
+    assert!(conn.owns_raw_conn());
+    let cookie = conn.send_request(&x::GetInputFocus {});
+    conn.wait_for_reply(cookie).unwrap();
+}
+
+#[test]
+fn test_cookie_discard() {
+    use crate::{mock, Cookie};
+
+    const BAD_DRAWABLE: u8 = 9;
+
+    // GetGeometry fails
+    let conn = mock::connect(|req| match req.opcode {
+        14 => vec![mock::error(req.seq, BAD_DRAWABLE, req.u32_at(4), 14)],
+        _ => mock::default_response(req),
+    });
+    let drawable = x::Drawable::Window(unsafe { x::Window::new(0x200) });
+    let sync = || {
+        let cookie = conn.send_request(&x::GetInputFocus {});
+        conn.wait_for_reply(cookie).unwrap();
+    };
+
+    // the error of an unchecked request goes to the event loop, unless discarded
+    conn.send_request_unchecked(&x::GetGeometry { drawable });
+    sync();
+    assert!(conn.poll_for_event().is_err());
+
+    conn.send_request_unchecked(&x::GetGeometry { drawable })
+        .discard(&conn);
+    sync();
+    assert!(matches!(conn.poll_for_event(), Ok(None)));
+
+    // the replies are discarded as well
+    conn.send_request(&x::GetInputFocus {}).discard(&conn);
+    conn.send_request(&x::GetGeometry { drawable })
+        .discard(&conn);
+    sync();
+    assert!(matches!(conn.poll_for_event(), Ok(None)));
+}
+
+#[test]
+#[cfg(feature = "xv")]
+fn test_get_extension_data() {
+    use crate::{mock, Extension};
+
+    // XVideo is present, MIT-SHM is not
+    let conn = mock::connect(|req| match req.opcode {
+        98 => match mock::extension_name(req) {
+            b"XVideo" => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            _ => vec![mock::reply(req.seq, 0, &[])],
+        },
+        _ => mock::default_response(req),
+    });
+    assert!(conn.extension_data(Extension::Xv).is_none());
+
+    conn.prefetch_extension_data(Extension::Xv);
+    conn.prefetch_extension_data(Extension::Shm);
+    let data = conn.get_extension_data(Extension::Xv).unwrap();
+    assert_eq!(data.ext, Extension::Xv);
+    assert_eq!(
+        (data.major_opcode, data.first_event, data.first_error),
+        (140, 80, 150)
+    );
+    assert!(conn.get_extension_data(Extension::Shm).is_none());
+    // the same data as the function of the extension module
+    assert_eq!(
+        crate::xv::get_extension_data(&conn).map(|data| data.first_event),
+        Some(80)
+    );
+}
+
+#[test]
+fn test_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<crate::Connection>();
+    assert_send_sync::<crate::Event>();
+    assert_send_sync::<crate::Error>();
+    assert_send_sync::<crate::VoidCookieChecked>();
+    assert_send_sync::<x::GetInputFocusCookie>();
+    assert_send_sync::<x::GetInputFocusReply>();
+    assert_send_sync::<crate::bigreq::EnableReply>();
+}
+
+#[test]
+fn test_event_thread() {
+    use crate::{bigreq, mock, Event};
+    use std::sync::Arc;
+
+    const BIGREQ_OPCODE: u8 = 133;
+    const EVENTS: u32 = 50;
+
+    // SendEvent echoes its event back to the client
+    let conn = Arc::new(mock::connect(|req| match req.opcode {
+        98 => match mock::extension_name(req) {
+            b"BIG-REQUESTS" => vec![mock::query_extension_reply(req, BIGREQ_OPCODE, 0, 0)],
+            _ => vec![mock::reply(req.seq, 0, &[])],
+        },
+        BIGREQ_OPCODE => vec![mock::reply(req.seq, 0, &(1u32 << 20).to_ne_bytes())],
+        25 => {
+            let mut ev = req.data[12..44].to_vec();
+            ev[0] |= 0x80;
+            ev[2..4].copy_from_slice(&req.seq.to_ne_bytes());
+            vec![ev]
+        }
+        _ => mock::default_response(req),
+    }));
+
+    // the reader blocks in wait_for_event while the main thread makes round trips
+    let reader = {
+        let conn = conn.clone();
+        std::thread::spawn(move || {
+            (0..EVENTS)
+                .map(|_| match conn.wait_for_event().unwrap() {
+                    Event::X(x::Event::ClientMessage(ev)) => match ev.data() {
+                        x::ClientMessageData::Data32(data) => data[0],
+                        data => panic!("unexpected data {:?}", data),
+                    },
+                    ev => panic!("unexpected event {:?}", ev),
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let window = unsafe { x::Window::new(0x200) };
+    for i in 0..EVENTS {
+        let cookie = conn.send_request(&bigreq::Enable {});
+        assert_eq!(
+            conn.wait_for_reply(cookie)
+                .unwrap()
+                .maximum_request_length(),
+            1 << 20
+        );
+        let event = x::ClientMessageEvent::new(
+            window,
+            x::ATOM_NONE,
+            x::ClientMessageData::Data32([i, 0, 0, 0, 0]),
+        );
+        conn.send_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(window),
+            event_mask: x::EventMask::NO_EVENT,
+            event: &event,
+        });
+        conn.flush().unwrap();
+    }
+
+    assert_eq!(reader.join().unwrap(), (0..EVENTS).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_wait_for_event_timeout() {
+    use crate::{mock, Event};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // SendEvent echoes its event back to the client
+    let conn = Arc::new(mock::connect(|req| match req.opcode {
+        25 => {
+            let mut ev = req.data[12..44].to_vec();
+            ev[0] |= 0x80;
+            ev[2..4].copy_from_slice(&req.seq.to_ne_bytes());
+            vec![ev]
+        }
+        _ => mock::default_response(req),
+    }));
+    let window = unsafe { x::Window::new(0x200) };
+    let send_event = move |conn: &crate::Connection, n: u32| {
+        let event = x::ClientMessageEvent::new(
+            window,
+            x::ATOM_NONE,
+            x::ClientMessageData::Data32([n, 0, 0, 0, 0]),
+        );
+        conn.send_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(window),
+            event_mask: x::EventMask::NO_EVENT,
+            event: &event,
+        });
+    };
+    let number = |event: Option<Event>| match event {
+        Some(Event::X(x::Event::ClientMessage(ev))) => match ev.data() {
+            x::ClientMessageData::Data32(data) => data[0],
+            data => panic!("unexpected data {:?}", data),
+        },
+        ev => panic!("unexpected event {:?}", ev),
+    };
+    let long = Duration::from_secs(10);
+
+    // nothing to read
+    let start = Instant::now();
+    assert!(conn
+        .wait_for_event_timeout(Duration::from_millis(50))
+        .unwrap()
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(conn
+        .wait_for_event_timeout(Duration::ZERO)
+        .unwrap()
+        .is_none());
+
+    // the request is flushed
+    send_event(&conn, 1);
+    assert_eq!(number(conn.wait_for_event_timeout(long).unwrap()), 1);
+
+    // the events read while waiting for a reply are queued
+    send_event(&conn, 2);
+    send_event(&conn, 3);
+    let cookie = conn.send_request(&x::GetInputFocus {});
+    conn.wait_for_reply(cookie).unwrap();
+    assert_eq!(
+        number(conn.wait_for_event_timeout(Duration::ZERO).unwrap()),
+        2
+    );
+    assert_eq!(
+        number(conn.wait_for_event_timeout(Duration::ZERO).unwrap()),
+        3
+    );
+
+    // the event sent by another thread while waiting, and a timeout that overflows
+    let sender = {
+        let conn = conn.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            send_event(&conn, 4);
+            conn.flush().unwrap();
+        })
+    };
+    assert_eq!(number(conn.wait_for_event_timeout(long).unwrap()), 4);
+    sender.join().unwrap();
+    send_event(&conn, 5);
+    assert_eq!(
+        number(conn.wait_for_event_timeout(Duration::MAX).unwrap()),
+        5
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use crate::Lat1String;
+    use serde::de::value::{Error, MapDeserializer, StrDeserializer};
+    use serde::de::{DeserializeOwned, IntoDeserializer};
+    use serde::{Deserialize, Serialize};
+
+    fn assert_serde<T: Serialize + DeserializeOwned>() {}
+    assert_serde::<x::Rectangle>();
+    assert_serde::<x::Point>();
+    assert_serde::<x::Window>();
+    assert_serde::<x::EventMask>();
+    assert_serde::<x::ClientMessageData>();
+    assert_serde::<x::KeyPressEvent>();
+    assert_serde::<x::ClientMessageEvent>();
+    #[cfg(feature = "xv")]
+    assert_serde::<crate::xv::Rational>();
+    #[cfg(feature = "dri2")]
+    assert_serde::<crate::dri2::AttachFormat>();
+
+    // events are deserialized through their constructor, which ignores the extra fields
+    let fields = [
+        ("response_type", 29u32),
+        ("sequence", 12),
+        ("time", 1234),
+        ("owner", 0x200),
+        ("selection", 1),
+    ];
+    let de: MapDeserializer<_, Error> = MapDeserializer::new(fields.iter().copied());
+    let ev = x::SelectionClearEvent::deserialize(de).unwrap();
+    assert_eq!(ev.response_type(), 29);
+    assert_eq!(ev.sequence(), 0);
+    assert_eq!(ev.time(), 1234);
+    assert_eq!(ev.owner(), unsafe { x::Window::new(0x200) });
+    assert_eq!(ev.selection(), x::ATOM_PRIMARY);
+
+    // Latin-1 strings are serialized as UTF-8
+    let de: StrDeserializer<Error> = "café".into_deserializer();
+    let s = Lat1String::deserialize(de).unwrap();
+    assert_eq!(s.as_bytes(), b"caf\xe9");
+    let de: StrDeserializer<Error> = "€".into_deserializer();
+    assert!(Lat1String::deserialize(de).is_err());
+}
+
+#[test]
+fn test_put_image_chunked() {
+    use crate::mock;
+    use std::sync::{Arc, Mutex};
+    use x::{Drawable, Gcontext, ImageFormat, PutImage, Window};
+
+    // the height, dst_y and data of the PutImage requests
+    let puts = Arc::new(Mutex::new(Vec::new()));
+    let conn = {
+        let puts = puts.clone();
+        mock::connect(move |req| match req.opcode {
+            // QueryExtension: BIG-REQUESTS is not available
+            98 => vec![mock::reply(req.seq, 0, &[])],
+            // PutImage
+            72 => {
+                let height = u16::from_ne_bytes([req.data[14], req.data[15]]);
+                let dst_y = i16::from_ne_bytes([req.data[18], req.data[19]]);
+                puts.lock()
+                    .unwrap()
+                    .push((height, dst_y, req.data[24..].to_vec()));
+                vec![]
+            }
+            _ => mock::default_response(req),
+        })
+    };
+    let drawable = Drawable::Window(unsafe { Window::new(mock::ROOT) });
+    let gc = unsafe { Gcontext::new(0x200001) };
+
+    // the mock server accepts requests of 256 KiB: 4 bands of 16 KiB rows
+    let (width, height) = (4096u16, 60u16);
+    let data: Vec<u8> = (0..width as usize * 4 * height as usize)
+        .map(|i| (i / (width as usize * 4)) as u8)
+        .collect();
+    let request = PutImage {
+        format: ImageFormat::ZPixmap,
+        drawable,
+        gc,
+        width,
+        height,
+        dst_x: 10,
+        dst_y: 20,
+        left_pad: 0,
+        depth: 24,
+        data: &data,
+    };
+    assert!(!conn.fits_in_request(request.request_len()));
+    x::put_image_chunked(&conn, &request).unwrap();
+    {
+        let puts = puts.lock().unwrap();
+        let heights: Vec<_> = puts.iter().map(|p| (p.0, p.1)).collect();
+        assert_eq!(heights, [(15, 20), (15, 35), (15, 50), (15, 65)]);
+        let sent: Vec<u8> = puts.iter().flat_map(|p| p.2.clone()).collect();
+        assert_eq!(sent, data);
+    }
+    puts.lock().unwrap().clear();
+
+    // the rows of the 8 planes of XyPixmap are split alike: bands of 4 rows of 8 KiB planes
+    let (width, height, planes) = (65504u16, 6usize, 8usize);
+    let stride = width as usize / 8;
+    let data: Vec<u8> = (0..planes * height)
+        .flat_map(|row| vec![row as u8; stride])
+        .collect();
+    x::put_image_chunked(
+        &conn,
+        &PutImage {
+            format: ImageFormat::XyPixmap,
+            width,
+            height: height as u16,
+            depth: planes as u8,
+            data: &data,
+            ..request
+        },
+    )
+    .unwrap();
+    let band = |y0: usize, y1: usize| -> Vec<u8> {
+        (0..planes)
+            .flat_map(|plane| (y0..y1).map(move |y| plane * height + y))
+            .flat_map(|row| vec![row as u8; stride])
+            .collect()
+    };
+    assert_eq!(
+        *puts.lock().unwrap(),
+        [(4, 20, band(0, 4)), (2, 24, band(4, 6))]
+    );
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn test_put_image_chunked_dst_y_range() {
+    use crate::mock;
+
+    // a single band does not fit: the second one starts below i16::MAX
+    let conn = mock::connect(|req| match req.opcode {
+        // QueryExtension: BIG-REQUESTS is not available
+        98 => vec![mock::reply(req.seq, 0, &[])],
+        _ => mock::default_response(req),
+    });
+    let (width, height) = (4096u16, 60u16);
+    let data = vec![0u8; width as usize * 4 * height as usize];
+    let _ = x::put_image_chunked(
+        &conn,
+        &x::PutImage {
+            format: x::ImageFormat::ZPixmap,
+            drawable: x::Drawable::Window(unsafe { x::Window::new(mock::ROOT) }),
+            gc: unsafe { x::Gcontext::new(0x200001) },
+            width,
+            height,
+            dst_x: 0,
+            dst_y: i16::MAX - 10,
+            left_pad: 0,
+            depth: 24,
+            data: &data,
+        },
+    );
+}
+
+#[test]
+fn test_parse_display() {
+    use crate::base::{parse_display, DisplayInfo};
+
+    let info = |protocol: Option<&str>, host: Option<&str>, display, screen| {
+        Some(DisplayInfo {
+            host: host.map(String::from),
+            protocol: protocol.map(String::from),
+            display,
+            screen,
+        })
+    };
+
+    assert_eq!(parse_display(":0"), info(None, None, 0, None));
+    assert_eq!(parse_display(":1.2"), info(None, None, 1, Some(2)));
+    assert_eq!(
+        parse_display("hostname:1.2"),
+        info(None, Some("hostname"), 1, Some(2))
+    );
+    assert_eq!(parse_display("unix:0"), info(None, Some("unix"), 0, None));
+    assert_eq!(parse_display("unix/:0"), info(Some("unix"), None, 0, None));
+    assert_eq!(
+        parse_display("tcp/example.org:10.0"),
+        info(Some("tcp"), Some("example.org"), 10, Some(0))
+    );
+    assert_eq!(
+        parse_display("inet6/[::1]:3"),
+        info(Some("inet6"), Some("::1"), 3, None)
+    );
+    assert_eq!(
+        parse_display("[fe80::1]:0.1"),
+        info(None, Some("fe80::1"), 0, Some(1))
+    );
+    // unbracketed IPv6: the last colon separates the display
+    assert_eq!(parse_display("::1:0"), info(None, Some("::1"), 0, None));
+    assert_eq!(parse_display("[]:0"), info(None, None, 0, None));
+
+    for name in [
+        "hostname", "host:", ":", ":x", ":0.", ":0.x", ":0.1.2", ":.1", ":-1", ":+1", ":65536",
+        "unix/", "host:0 ",
+    ] {
+        assert_eq!(parse_display(name), None, "{:?}", name);
+    }
+}
+
+#[test]
+fn test_display_info() {
+    use crate::base::parse_display;
+
+    for (name, local) in [
+        (":0", true),
+        ("unix:0", true),
+        ("unix/:0", true),
+        ("unix/host:0", true),
+        ("host:0.1", false),
+        ("tcp/:0", false),
+        ("tcp/unix:0", false),
+        ("[::1]:2", false),
+    ] {
+        let info = parse_display(name).unwrap();
+        assert_eq!(info.is_local(), local, "{}", name);
+        // formatted back to the same string
+        assert_eq!(info.to_string(), name);
+        assert_eq!(parse_display(&info.to_string()), Some(info));
+    }
+    assert_eq!(
+        parse_display("unix:7").unwrap().socket_path(),
+        Some("/tmp/.X11-unix/X7".into())
+    );
+    assert_eq!(parse_display("host:7").unwrap().socket_path(), None);
+    assert_eq!(
+        parse_display("[::1]:0").unwrap().host.as_deref(),
+        Some("::1")
+    );
+}
+
+#[test]
+fn test_widen() {
+    use crate::base::widen;
+
+    assert_eq!(widen(0, 5, 16), 5);
+    assert_eq!(widen(0xfffe, 0xffff, 16), 0xffff);
+    assert_eq!(widen(0xfffe, 0x0001, 16), 0x1_0001);
+    assert_eq!(widen(0x1_0001, 0x0001, 16), 0x1_0001);
+    assert_eq!(widen(0x3_fff0, 0x0010, 16), 0x4_0010);
+    assert_eq!(widen(0xffff_ffff, 0, 32), 0x1_0000_0000);
+}
+
+#[test]
+fn test_widen_sequence16() {
+    use crate::base::widen_sequence16;
+
+    // before the first wrap
+    assert_eq!(widen_sequence16(0, 0), 0);
+    assert_eq!(widen_sequence16(10, 5), 5);
+    assert_eq!(widen_sequence16(10, 20), 20);
+
+    // just before and after 0xffff
+    assert_eq!(widen_sequence16(0xfffe, 0xfffd), 0xfffd);
+    assert_eq!(widen_sequence16(0xfffe, 0xffff), 0xffff);
+    assert_eq!(widen_sequence16(0xfffe, 0x0000), 0x1_0000);
+    assert_eq!(widen_sequence16(0xfffe, 0x0001), 0x1_0001);
+    assert_eq!(widen_sequence16(0x1_0001, 0xffff), 0xffff);
+    assert_eq!(widen_sequence16(0x1_0001, 0x0000), 0x1_0000);
+    assert_eq!(widen_sequence16(0x1_0001, 0x0002), 0x1_0002);
+
+    // limits of the window around the last sequence number
+    assert_eq!(widen_sequence16(0x1_0000, 0x8000), 0x8000);
+    assert_eq!(widen_sequence16(0x1_0000, 0x7fff), 0x1_7fff);
+
+    // after several wraps
+    assert_eq!(widen_sequence16(0x3_fff0, 0x0010), 0x4_0010);
+    assert_eq!(widen_sequence16(0x4_0010, 0xfff0), 0x3_fff0);
+    assert_eq!(widen_sequence16(0x12_3456, 0x3456), 0x12_3456);
+    assert_eq!(widen_sequence16(0xffff_ffff, 0x0000), 0x1_0000_0000);
+    assert_eq!(widen_sequence16(0x1_0000_0000, 0xffff), 0xffff_ffff);
+}
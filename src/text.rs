@@ -0,0 +1,401 @@
+//! Measurement of text with the core fonts.
+//!
+//! These helpers are meant for tools that need text metrics but do not render text
+//! with the Render extension. A [FontCache] opens the fonts the first time they are used,
+//! and closes them when it is dropped.
+//!
+//! Characters are indexed by their Unicode code point, which matches the encoding of
+//! the ISO 8859-1 (`fixed`) and ISO 10646 fonts.
+
+use crate::base::{Connection, Result, ResultExt};
+use crate::x;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// The metrics of an open font, as returned by `QueryFont`.
+#[derive(Debug)]
+pub(crate) struct FontMetrics {
+    font: x::Font,
+    min_byte1: u8,
+    max_byte1: u8,
+    min_char_or_byte2: u16,
+    max_char_or_byte2: u16,
+    default_char: u16,
+    max_bounds: x::Charinfo,
+    /// Empty if all the characters have the metrics of `max_bounds`.
+    char_infos: Vec<x::Charinfo>,
+}
+
+fn is_nonexistent(info: &x::Charinfo) -> bool {
+    info.left_side_bearing == 0
+        && info.right_side_bearing == 0
+        && info.character_width == 0
+        && info.ascent == 0
+        && info.descent == 0
+}
+
+impl FontMetrics {
+    fn new(font: x::Font, reply: &x::QueryFontReply) -> FontMetrics {
+        FontMetrics {
+            font,
+            min_byte1: reply.min_byte1(),
+            max_byte1: reply.max_byte1(),
+            min_char_or_byte2: reply.min_char_or_byte2(),
+            max_char_or_byte2: reply.max_char_or_byte2(),
+            default_char: reply.default_char(),
+            max_bounds: reply.max_bounds(),
+            char_infos: reply.char_infos().to_vec(),
+        }
+    }
+
+    /// Returns the metrics of the character `c`, or `None` if the font does not have it.
+    ///
+    /// Fonts with `min_byte1 == max_byte1 == 0` are indexed linearly by the character
+    /// code. The others are matrices indexed by the high byte (`byte1`) and the low byte
+    /// (`byte2`) of the character code.
+    fn char_info(&self, c: u32) -> Option<x::Charinfo> {
+        let (min2, max2) = (self.min_char_or_byte2 as u32, self.max_char_or_byte2 as u32);
+        let index = if self.min_byte1 == 0 && self.max_byte1 == 0 {
+            if c < min2 || c > max2 {
+                return None;
+            }
+            c - min2
+        } else {
+            if c > 0xffff {
+                return None;
+            }
+            let (byte1, byte2) = (c >> 8, c & 0xff);
+            let (min1, max1) = (self.min_byte1 as u32, self.max_byte1 as u32);
+            if byte1 < min1 || byte1 > max1 || byte2 < min2 || byte2 > max2 {
+                return None;
+            }
+            (byte1 - min1) * (max2 - min2 + 1) + (byte2 - min2)
+        };
+
+        let info = if self.char_infos.is_empty() {
+            self.max_bounds
+        } else {
+            *self.char_infos.get(index as usize)?
+        };
+        if is_nonexistent(&info) {
+            None
+        } else {
+            Some(info)
+        }
+    }
+
+    /// Returns the metrics of the character `c`, falling back to the default character.
+    /// Returns `None` if both are missing, in which case `c` is not drawn.
+    fn glyph(&self, c: char) -> Option<x::Charinfo> {
+        self.char_info(c as u32)
+            .or_else(|| self.char_info(self.default_char as u32))
+    }
+
+    fn measure(&self, text: &str) -> (i32, i16, i16) {
+        text.chars().filter_map(|c| self.glyph(c)).fold(
+            (0, 0, 0),
+            |(width, ascent, descent), info| {
+                (
+                    width + info.character_width as i32,
+                    ascent.max(info.ascent),
+                    descent.max(info.descent),
+                )
+            },
+        )
+    }
+
+    /// The characters of `text` in the encoding of `QueryTextExtents`.
+    fn char2b(&self, text: &str) -> Vec<x::Char2b> {
+        text.chars()
+            .map(|c| {
+                let c = u16::try_from(c as u32).unwrap_or(self.default_char);
+                x::Char2b {
+                    byte1: (c >> 8) as u8,
+                    byte2: c as u8,
+                }
+            })
+            .collect()
+    }
+}
+
+fn open_font(conn: &Connection, font_name: &str) -> Result<FontMetrics> {
+    let font = conn.generate_id();
+    let open = conn.send_request_checked(&x::OpenFont {
+        fid: font,
+        name: font_name.as_bytes(),
+    });
+    let query = conn.send_request(&x::QueryFont {
+        font: x::Fontable::Font(font),
+    });
+    conn.check_request(open)
+        .context("OpenFont", || font_name.to_string())?;
+    let reply = conn
+        .wait_for_reply(query)
+        .context("QueryFont", || font_name.to_string())?;
+    Ok(FontMetrics::new(font, &reply))
+}
+
+/// The core fonts opened to measure text, by name.
+///
+/// The fonts are opened the first time they are used, and closed when the cache is dropped.
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// let (conn, _) = xcb::Connection::connect(None)?;
+/// let mut fonts = xcb::text::FontCache::new(&conn);
+/// let (width, ascent, descent) = fonts.measure("fixed", "Hello, world!")?;
+/// println!("{}x{}", width, ascent + descent);
+/// # Ok(())
+/// # }
+/// ```
+pub struct FontCache<'a> {
+    conn: &'a Connection,
+    fonts: HashMap<String, FontMetrics>,
+}
+
+impl<'a> FontCache<'a> {
+    /// Creates an empty cache of the fonts of `conn`.
+    pub fn new(conn: &'a Connection) -> FontCache<'a> {
+        FontCache {
+            conn,
+            fonts: HashMap::new(),
+        }
+    }
+
+    fn font(&mut self, font_name: &str) -> Result<&FontMetrics> {
+        if !self.fonts.contains_key(font_name) {
+            let metrics = open_font(self.conn, font_name)?;
+            self.fonts.insert(font_name.to_string(), metrics);
+        }
+        Ok(&self.fonts[font_name])
+    }
+
+    /// Measures `text` drawn with the core font `font_name`.
+    ///
+    /// Returns the width (the sum of the widths of the characters), and the maximum ascent
+    /// and descent of the characters, like the overall metrics of `QueryTextExtents`.
+    /// Characters missing from the font are measured as the default character of the font,
+    /// or ignored if the font has no default character.
+    ///
+    /// The metrics are computed locally: only the first measurement with a font costs
+    /// a round trip to the server. See [FontCache::measure_exact] to let the server
+    /// compute them.
+    pub fn measure(&mut self, font_name: &str, text: &str) -> Result<(i32, i16, i16)> {
+        Ok(self.font(font_name)?.measure(text))
+    }
+
+    /// Measures `text` drawn with the core font `font_name` with `QueryTextExtents`.
+    ///
+    /// Same as [FontCache::measure], except that the metrics are computed by the server,
+    /// which costs a round trip per call. Characters that do not fit in 16 bits are measured
+    /// as the default character of the font.
+    pub fn measure_exact(&mut self, font_name: &str, text: &str) -> Result<(i32, i16, i16)> {
+        let metrics = self.font(font_name)?;
+        let (font, string) = (metrics.font, metrics.char2b(text));
+        let cookie = self.conn.send_request(&x::QueryTextExtents {
+            font: x::Fontable::Font(font),
+            string: &string,
+        });
+        let reply = self
+            .conn
+            .wait_for_reply(cookie)
+            .context("QueryTextExtents", || font_name.to_string())?;
+        Ok((
+            reply.overall_width(),
+            reply.overall_ascent(),
+            reply.overall_descent(),
+        ))
+    }
+}
+
+impl<'a> std::fmt::Debug for FontCache<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FontCache")
+            .field("fonts", &self.fonts.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<'a> Drop for FontCache<'a> {
+    fn drop(&mut self) {
+        for metrics in self.fonts.values() {
+            self.conn.send_request(&x::CloseFont { font: metrics.font });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn char_info(width: i16, ascent: i16, descent: i16) -> x::Charinfo {
+        x::Charinfo {
+            left_side_bearing: 0,
+            right_side_bearing: width,
+            character_width: width,
+            ascent,
+            descent,
+            attributes: 0,
+        }
+    }
+
+    /// The metrics of the `fixed` font of the X.Org server
+    /// (`-misc-fixed-medium-r-semicondensed--13-120-75-75-c-60-iso8859-1`), with the
+    /// C1 controls (0x80..0xa0) nonexistent, as in most ISO 8859-1 fonts.
+    fn fixed_metrics(char_infos: bool) -> FontMetrics {
+        use crate::XidNew;
+
+        let char_infos = if char_infos {
+            (0..256)
+                .map(|c| {
+                    if (0x80..0xa0).contains(&c) {
+                        char_info(0, 0, 0)
+                    } else {
+                        char_info(6, 11, 2)
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        FontMetrics {
+            font: unsafe { x::Font::new(0x200001) },
+            min_byte1: 0,
+            max_byte1: 0,
+            min_char_or_byte2: 0,
+            max_char_or_byte2: 255,
+            default_char: 0,
+            max_bounds: char_info(6, 11, 2),
+            char_infos,
+        }
+    }
+
+    #[test]
+    fn test_measure_fixed() {
+        let fixed = fixed_metrics(true);
+        assert_eq!(fixed.measure(""), (0, 0, 0));
+        assert_eq!(fixed.measure("Hello"), (30, 11, 2));
+        assert_eq!(fixed.measure("é"), (6, 11, 2));
+        // nonexistent and out of range characters are measured as the default character
+        assert_eq!(fixed.measure("\u{85}"), (6, 11, 2));
+        assert_eq!(fixed.measure("a\u{2603}b"), (18, 11, 2));
+
+        // without default character, missing characters are ignored
+        let fixed = FontMetrics {
+            default_char: 0x80,
+            ..fixed_metrics(true)
+        };
+        assert_eq!(fixed.measure("a\u{85}\u{2603}b"), (12, 11, 2));
+
+        // no per-character metrics: all have the metrics of max_bounds
+        let fixed = fixed_metrics(false);
+        assert_eq!(fixed.measure("Hello"), (30, 11, 2));
+        assert_eq!(fixed.measure("\u{2603}"), (6, 11, 2));
+    }
+
+    #[test]
+    fn test_measure_matrix() {
+        use crate::XidNew;
+
+        // rows 0x20 and 0x21, columns 0x40 to 0x4f
+        let mut char_infos: Vec<_> = (0..32).map(|i| char_info(i + 1, i, 1)).collect();
+        char_infos[3] = char_info(0, 0, 0);
+        let font = FontMetrics {
+            font: unsafe { x::Font::new(0x200001) },
+            min_byte1: 0x20,
+            max_byte1: 0x21,
+            min_char_or_byte2: 0x40,
+            max_char_or_byte2: 0x4f,
+            default_char: 0x2041,
+            max_bounds: char_info(32, 31, 1),
+            char_infos,
+        };
+
+        assert_eq!(font.char_info(0x2040).unwrap().character_width, 1);
+        assert_eq!(font.char_info(0x204f).unwrap().character_width, 16);
+        assert_eq!(font.char_info(0x2140).unwrap().character_width, 17);
+        assert_eq!(font.char_info(0x214f).unwrap().character_width, 32);
+        assert!(font.char_info(0x2043).is_none());
+        // byte2 out of range
+        assert!(font.char_info(0x2050).is_none());
+        assert!(font.char_info(0x213f).is_none());
+        // byte1 out of range
+        assert!(font.char_info(0x0041).is_none());
+        assert!(font.char_info(0x2240).is_none());
+        assert!(font.char_info(0x1_2040).is_none());
+
+        assert_eq!(font.measure("\u{2045}\u{2140}"), (6 + 17, 16, 1));
+        // missing characters fall back to the default character (width 2)
+        assert_eq!(font.measure("\u{2043}A\u{1F600}"), (6, 1, 1));
+
+        let string = font.char2b("\u{2145}A\u{1F600}");
+        let string: Vec<_> = string.iter().map(|c| (c.byte1, c.byte2)).collect();
+        assert_eq!(string, [(0x21, 0x45), (0, 0x41), (0x20, 0x41)]);
+    }
+
+    #[test]
+    fn test_font_cache() {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        // the opened and closed fonts
+        let fonts = Arc::new(Mutex::new((Vec::new(), Vec::new())));
+        let conn = {
+            let fonts = fonts.clone();
+            mock::connect(move |req| match req.opcode {
+                // OpenFont
+                45 => {
+                    let len = u16::from_ne_bytes([req.data[8], req.data[9]]) as usize;
+                    assert_eq!(&req.data[12..12 + len], b"fixed");
+                    fonts.lock().unwrap().0.push(req.u32_at(4));
+                    vec![]
+                }
+                // CloseFont
+                46 => {
+                    fonts.lock().unwrap().1.push(req.u32_at(4));
+                    vec![]
+                }
+                // QueryFont: 6x13 without per-character metrics
+                47 => {
+                    let mut body = vec![0u8; 52];
+                    let max_bounds = [0i16, 6, 6, 11, 2];
+                    for (i, v) in max_bounds.iter().enumerate() {
+                        body[16 + 2 * i..18 + 2 * i].copy_from_slice(&v.to_ne_bytes());
+                    }
+                    body[34..36].copy_from_slice(&255u16.to_ne_bytes());
+                    body[44..46].copy_from_slice(&11i16.to_ne_bytes());
+                    body[46..48].copy_from_slice(&2i16.to_ne_bytes());
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                // QueryTextExtents
+                48 => {
+                    let mut body = vec![0u8; 24];
+                    body[4..6].copy_from_slice(&11i16.to_ne_bytes());
+                    body[6..8].copy_from_slice(&2i16.to_ne_bytes());
+                    body[8..12].copy_from_slice(&42i32.to_ne_bytes());
+                    vec![mock::reply(req.seq, 0, &body)]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+
+        let mut cache = FontCache::new(&conn);
+        assert_eq!(cache.measure("fixed", "Hello").unwrap(), (30, 11, 2));
+        assert_eq!(cache.measure("fixed", "Hi").unwrap(), (12, 11, 2));
+        assert_eq!(cache.measure_exact("fixed", "Hello").unwrap(), (42, 11, 2));
+
+        let opened = fonts.lock().unwrap().0.clone();
+        assert_eq!(opened.len(), 1);
+        drop(cache);
+        conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+            .unwrap();
+        assert_eq!(fonts.lock().unwrap().1, opened);
+
+        // the fonts of a cache are independent of the other caches
+        let mut cache = FontCache::new(&conn);
+        cache.measure("fixed", "Hello").unwrap();
+        assert_eq!(fonts.lock().unwrap().0.len(), 2);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::base::{widen, Connection, Cookie, SequenceNumber, VoidCookie};
+use crate::error::ProtocolError;
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// Maximum number of requests tracked at once by [Connection::track_unchecked].
+///
+/// When the limit is reached, the oldest tracked request is forgotten.
+pub const MAX_TRACKED_UNCHECKED: usize = 4096;
+
+/// An error of a request tracked with [Connection::track_unchecked].
+#[derive(Debug)]
+pub struct TaggedError {
+    /// The tag given to [Connection::track_unchecked].
+    pub tag: u64,
+    pub error: ProtocolError,
+}
+
+/// Mapping of the sequence numbers of tracked unchecked requests to their tag.
+///
+/// libxcb sequence numbers (of cookies and in the `full_sequence` field of events and errors)
+/// are 32-bit and wrap around. They are widened to 64-bit such that the tracked requests can be
+/// ordered and collected once the server has processed them.
+pub(crate) struct UncheckedTracker {
+    bits: u32,
+    last_sent: SequenceNumber,
+    last_read: SequenceNumber,
+    tags: BTreeMap<SequenceNumber, u64>,
+    errors: VecDeque<TaggedError>,
+}
+
+impl Default for UncheckedTracker {
+    fn default() -> Self {
+        Self::with_bits(32)
+    }
+}
+
+impl UncheckedTracker {
+    fn with_bits(bits: u32) -> Self {
+        UncheckedTracker {
+            bits,
+            last_sent: 0,
+            last_read: 0,
+            tags: BTreeMap::new(),
+            errors: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    fn track(&mut self, seq: SequenceNumber, tag: u64) {
+        let seq = widen(self.last_sent, seq, self.bits);
+        self.last_sent = seq;
+        if seq < self.last_read {
+            // the request was already processed
+            return;
+        }
+        self.tags.insert(seq, tag);
+        if self.tags.len() > MAX_TRACKED_UNCHECKED {
+            let oldest = *self.tags.keys().next().unwrap();
+            self.tags.remove(&oldest);
+        }
+    }
+
+    /// Records that the server has processed the requests up to `seq`, and forgets
+    /// the tracked requests before `seq`: they can't fail anymore.
+    pub(crate) fn observe(&mut self, seq: SequenceNumber) {
+        let seq = widen(self.last_read, seq, self.bits);
+        self.last_read = seq;
+        self.tags = self.tags.split_off(&seq);
+    }
+
+    /// The last sequence number observed in an event or an error.
+    pub(crate) fn last_read(&self) -> SequenceNumber {
+        self.last_read
+    }
+
+    /// Returns the tag of the request `seq` that failed, if it is tracked.
+    pub(crate) fn take(&mut self, seq: SequenceNumber) -> Option<u64> {
+        self.observe(seq);
+        self.tags.remove(&self.last_read)
+    }
+
+    pub(crate) fn push_error(&mut self, error: TaggedError) {
+        self.errors.push_back(error);
+    }
+}
+
+impl Connection {
+    /// Tracks the unchecked request of `cookie` with `tag`.
+    ///
+    /// If the request fails, the error is not returned by [Connection::wait_for_event] or
+    /// [Connection::poll_for_event]. It is instead queued as a [TaggedError], to be retrieved
+    /// with [Connection::poll_for_error]. This allows a dispatcher to route the errors of
+    /// unchecked requests back to the subsystem that sent them.
+    ///
+    /// At most [MAX_TRACKED_UNCHECKED] requests are tracked at once. Requests are forgotten
+    /// as soon as an event or an error of a later request is received.
+    pub fn track_unchecked(&self, cookie: &VoidCookie, tag: u64) {
+        self.unchecked_tracker().track(cookie.sequence(), tag);
+    }
+
+    /// Returns the next error of a request tracked with [Connection::track_unchecked].
+    ///
+    /// The errors are collected while events are read with [Connection::wait_for_event],
+    /// [Connection::poll_for_event] or [Connection::poll_for_queued_event].
+    /// This function does not read from the connection.
+    pub fn poll_for_error(&self) -> Option<TaggedError> {
+        self.unchecked_tracker().errors.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_wrap() {
+        let mut tracker = UncheckedTracker::with_bits(16);
+        tracker.observe(0xfff0);
+        tracker.track(0xfffe, 1);
+        tracker.track(0xffff, 2);
+        tracker.track(0x0001, 3);
+        assert_eq!(
+            tracker.tags.keys().copied().collect::<Vec<_>>(),
+            [0xfffe, 0xffff, 0x1_0001]
+        );
+
+        // an event of request 0xffff: 0xfffe can't fail anymore
+        tracker.observe(0xffff);
+        assert_eq!(tracker.tags.len(), 2);
+
+        // the error of request 0x0001 after the wrap, and not of 0x0001 before
+        assert_eq!(tracker.take(0x0000), None);
+        assert_eq!(tracker.tags.len(), 1);
+        assert_eq!(tracker.take(0x0001), Some(3));
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.take(0x0001), None);
+    }
+
+    #[test]
+    fn test_tracker_multi_wrap() {
+        let mut tracker = UncheckedTracker::with_bits(16);
+        for i in 0..5u64 {
+            let seq = i * 0x8000 + 0x10;
+            tracker.track(seq & 0xffff, i);
+            tracker.observe((seq - 1) & 0xffff);
+            assert_eq!(tracker.take(seq & 0xffff), Some(i));
+        }
+        assert_eq!(tracker.last_read, 4 * 0x8000 + 0x10);
+
+        // a request tracked after the server processed it is not tracked
+        tracker.observe(0x20);
+        tracker.track(0x18, 42);
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_tracker_bounded() {
+        let mut tracker = UncheckedTracker::default();
+        for seq in 1..=MAX_TRACKED_UNCHECKED as u64 + 1 {
+            tracker.track(seq, seq);
+        }
+        assert_eq!(tracker.tags.len(), MAX_TRACKED_UNCHECKED);
+        assert_eq!(tracker.take(1), None);
+        assert_eq!(tracker.take(2), Some(2));
+    }
+
+    #[test]
+    fn test_track_unchecked() {
+        use crate::mock;
+        use crate::x;
+        use crate::XidNew;
+
+        // MapWindow fails with BadWindow
+        let conn = mock::connect(|req| match req.opcode {
+            8 => vec![mock::error(req.seq, 3, req.u32_at(4), 8)],
+            _ => mock::default_response(req),
+        });
+        let window = unsafe { x::Window::new(0x400001) };
+
+        let tracked = conn.send_request(&x::MapWindow { window });
+        conn.track_unchecked(&tracked, 7);
+        conn.send_request(&x::MapWindow { window });
+        conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))
+            .unwrap();
+
+        // the untracked error is returned as usual
+        assert!(matches!(
+            conn.poll_for_event(),
+            Err(crate::Error::Protocol(ProtocolError::X(
+                x::Error::Window(_),
+                _
+            )))
+        ));
+        assert!(matches!(conn.poll_for_event(), Ok(None)));
+
+        let tagged = conn.poll_for_error().unwrap();
+        assert_eq!(tagged.tag, 7);
+        assert!(matches!(
+            tagged.error,
+            ProtocolError::X(x::Error::Window(_), _)
+        ));
+        assert!(conn.poll_for_error().is_none());
+    }
+}
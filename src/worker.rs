@@ -0,0 +1,270 @@
+use crate::base::{Connection, Result, ResultExt};
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// A main connection and a secondary connection to the same display.
+///
+/// The secondary connection is meant to run requests that block for a long time
+/// (e.g. `dri2::WaitMsc` or the `record::EnableContext` reply stream) so that the event loop
+/// of the main connection never stalls. See also [BlockingWorker].
+///
+/// Resource XIDs are scoped to the server, so an XID created on one connection can be used
+/// with the other one. However, some states are attached to the connection that set
+/// them and not shared:
+///  - grabs (e.g. `x::GrabPointer`) are released when the connection that made them closes,
+///  - event selections are per connection: events selected with `x::ChangeWindowAttributes`
+///    on the main connection are not delivered to the secondary connection and vice versa,
+///  - the two connections are not synchronized: a request sent on one connection is not
+///    guaranteed to be processed before a request sent later on the other one, unless the first
+///    connection waited for a reply (or checked a request) in between.
+pub struct ConnectionPair {
+    main: Connection,
+    secondary: Connection,
+}
+
+impl ConnectionPair {
+    /// Builds a pair from `main` and a new connection to the same display.
+    pub fn new(main: Connection) -> Result<ConnectionPair> {
+        let secondary = Self::clone_display(&main)?;
+        Ok(ConnectionPair { main, secondary })
+    }
+
+    /// Opens a new connection to the display of `conn`.
+    ///
+    /// The new connection uses the same authentication info as `conn` and caches the data
+    /// of the same extensions.
+    ///
+    /// Returns [ConnError::ClosedParseErr](crate::ConnError::ClosedParseErr), with a context
+    /// telling so, if `conn` was not opened by name (see [Connection::display_name]).
+    pub fn clone_display(conn: &Connection) -> Result<Connection> {
+        conn.reconnect()
+            .context("connect", || match conn.display_name() {
+                Some(name) => format!("to display {:?}", name),
+                None => "to the display of a connection opened without display name".to_string(),
+            })
+    }
+
+    /// The main connection.
+    pub fn main(&self) -> &Connection {
+        &self.main
+    }
+
+    /// The secondary connection.
+    pub fn secondary(&self) -> &Connection {
+        &self.secondary
+    }
+
+    /// Consumes the pair, moving the secondary connection to a [BlockingWorker].
+    pub fn into_worker(self) -> (Connection, BlockingWorker) {
+        (self.main, BlockingWorker::new(self.secondary))
+    }
+}
+
+type Job = Box<dyn FnOnce(&Connection) + Send>;
+
+/// A thread that owns a connection and runs blocking requests on it.
+///
+/// Closures submitted with [BlockingWorker::submit] are run in order on the worker thread.
+/// The same caveats as for [ConnectionPair] apply regarding the state shared between
+/// the connection of the worker and other connections.
+///
+/// The worker thread is stopped when the `BlockingWorker` is dropped, after all
+/// the closures already submitted have completed.
+///
+/// # Example
+/// ```no_run
+/// # #[cfg(not(feature = "dri2"))]
+/// # fn main() {}
+/// # #[cfg(feature = "dri2")]
+/// # fn main() -> xcb::Result<()> {
+/// # use xcb::{dri2, x};
+/// let (conn, _) = xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Dri2], &[])?;
+/// let worker = xcb::BlockingWorker::for_display_of(&conn)?;
+///
+/// let drawable: x::Window = conn.generate_id();
+/// // [...] create and map the window
+///
+/// // wait for the next vblank without blocking the main connection
+/// let vblank = worker.submit(move |conn| {
+///     let cookie = conn.send_request(&dri2::WaitMsc {
+///         drawable: x::Drawable::Window(drawable),
+///         target_msc_hi: 0,
+///         target_msc_lo: 0,
+///         divisor_hi: 0,
+///         divisor_lo: 1,
+///         remainder_hi: 0,
+///         remainder_lo: 0,
+///     });
+///     let reply = conn.wait_for_reply(cookie)?;
+///     Ok(reply.msc_lo())
+/// });
+///
+/// loop {
+///     if let Ok(msc) = vblank.try_recv() {
+///         println!("vblank {}", msc?);
+///         break;
+///     }
+///     while let Some(event) = conn.poll_for_event()? {
+///         // [...] handle events
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct BlockingWorker {
+    conn: Arc<Connection>,
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BlockingWorker {
+    /// Starts a worker thread that owns `conn`.
+    pub fn new(conn: Connection) -> BlockingWorker {
+        let conn = Arc::new(conn);
+        let (jobs, rx) = mpsc::channel::<Job>();
+
+        let thread = {
+            let conn = conn.clone();
+            thread::spawn(move || {
+                for job in rx {
+                    // a panicking job disconnects its receiver, the next jobs still run
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&conn)));
+                }
+            })
+        };
+
+        BlockingWorker {
+            conn,
+            jobs: Some(jobs),
+            thread: Some(thread),
+        }
+    }
+
+    /// Starts a worker thread with a new connection to the display of `conn`.
+    ///
+    /// See [ConnectionPair::clone_display].
+    pub fn for_display_of(conn: &Connection) -> Result<BlockingWorker> {
+        Ok(Self::new(ConnectionPair::clone_display(conn)?))
+    }
+
+    /// The connection of the worker.
+    ///
+    /// It can be used from any thread, for example to generate XIDs or send requests
+    /// that must be ordered with the ones of the worker.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Runs `f` with the connection of the worker on the worker thread.
+    ///
+    /// The result of `f` is sent through the returned receiver.
+    /// If `f` panics, the panic is caught on the worker thread, which keeps running the
+    /// closures submitted after it, and the receiver is disconnected without a result.
+    pub fn submit<T, F>(&self, f: F) -> Receiver<Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let job: Job = Box::new(move |conn| {
+            // the receiver may have been dropped if the result is not needed
+            let _ = tx.send(f(conn));
+        });
+        // the worker thread runs until the worker is dropped
+        let _ = self.jobs.as_ref().unwrap().send(job);
+        rx
+    }
+}
+
+impl Drop for BlockingWorker {
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clone_display_unknown() {
+        use crate::base::ConnError;
+
+        let conn = crate::mock::connect(crate::mock::default_response);
+        assert!(conn.display_name().is_none());
+        match ConnectionPair::clone_display(&conn) {
+            Err(err) => {
+                assert!(matches!(
+                    err.root_cause(),
+                    crate::Error::Connection(ConnError::ClosedParseErr)
+                ));
+                let ctx = err.chain().next().unwrap();
+                assert_eq!(ctx.operation, "connect");
+                assert!(ctx.detail.contains("without display name"));
+            }
+            Ok(_) => panic!("expected ConnError::ClosedParseErr"),
+        }
+    }
+
+    #[test]
+    fn test_blocking_worker() {
+        use crate::mock;
+        use crate::x;
+        use std::sync::Mutex;
+
+        // The worker server holds the reply of its first GetInputFocus until `release` is signaled,
+        // similarly to a server that would wait for the next vblank to reply to WaitMsc.
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let worker = BlockingWorker::new(mock::connect(move |req| {
+            if req.opcode == 43 && req.seq == 1 {
+                released.lock().unwrap().recv().unwrap();
+            }
+            mock::default_response(req)
+        }));
+
+        let blocking = worker.submit(|conn| {
+            let cookie = conn.send_request(&x::GetInputFocus {});
+            conn.wait_for_reply(cookie)?;
+            Ok(42)
+        });
+
+        // the main connection keeps answering while the worker is blocked
+        let main = mock::connect(mock::default_response);
+        for _ in 0..10 {
+            let cookie = main.send_request(&x::GetInputFocus {});
+            main.wait_for_reply(cookie).unwrap();
+            assert!(blocking.try_recv().is_err());
+        }
+
+        release.send(()).unwrap();
+        assert_eq!(blocking.recv().unwrap().unwrap(), 42);
+
+        // jobs are run in order
+        let a = worker.submit(|_| Ok(1));
+        let b = worker.submit(|conn| {
+            conn.wait_for_reply(conn.send_request(&x::GetInputFocus {}))?;
+            Ok(2)
+        });
+        assert_eq!(a.recv().unwrap().unwrap(), 1);
+        assert_eq!(b.recv().unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_blocking_worker_panic() {
+        let worker = BlockingWorker::new(crate::mock::connect(crate::mock::default_response));
+
+        let panicked = worker.submit(|_| -> Result<()> { panic!("job panicked") });
+        assert!(panicked.recv().is_err());
+
+        // the worker keeps running the next jobs
+        let next = worker.submit(|_| Ok(1));
+        assert_eq!(next.recv().unwrap().unwrap(), 1);
+    }
+}
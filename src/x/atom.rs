@@ -0,0 +1,176 @@
+//! Interning of many atoms in a single round trip.
+
+use super::{Atom, InternAtom, ATOM_NONE};
+use crate::base::{Connection, Result, ResultExt};
+
+use std::collections::HashMap;
+
+/// Interns the atoms of `names`, in the order of `names`.
+///
+/// All the `InternAtom` requests are sent before waiting for the first reply, so the
+/// atoms are interned in a single round trip. If `only_if_exists` is `true`, the atoms
+/// that don't exist on the server are returned as `ATOM_NONE`.
+///
+/// See also [atoms_struct](crate::atoms_struct) for atoms known at compile time.
+pub fn intern_atoms(conn: &Connection, names: &[&str], only_if_exists: bool) -> Result<Vec<Atom>> {
+    let cookies: Vec<_> = names
+        .iter()
+        .map(|name| {
+            conn.send_request(&InternAtom {
+                only_if_exists,
+                name: name.as_bytes(),
+            })
+        })
+        .collect();
+    names
+        .iter()
+        .zip(cookies)
+        .map(|(name, cookie)| {
+            Ok(conn
+                .wait_for_reply(cookie)
+                .context("InternAtom", || name.to_string())?
+                .atom())
+        })
+        .collect()
+}
+
+/// A cache of interned atoms, by name.
+///
+/// The atoms are interned by batches with [AtomCache::intern], which only requests the
+/// names that are not cached yet. An atom is valid until the server resets, so a cache
+/// must not outlive the connection it was filled with.
+#[derive(Clone, Debug, Default)]
+pub struct AtomCache {
+    atoms: HashMap<String, Atom>,
+}
+
+impl AtomCache {
+    /// Creates an empty cache.
+    pub fn new() -> AtomCache {
+        AtomCache::default()
+    }
+
+    /// Interns the atoms of `names` that are not cached yet, in a single round trip, and
+    /// returns the atoms of all `names` in their order.
+    ///
+    /// If `only_if_exists` is `true`, the atoms that don't exist on the server are returned
+    /// as `ATOM_NONE` and are not cached: they are requested again by the next calls,
+    /// as another client may create them in the meantime.
+    pub fn intern(
+        &mut self,
+        conn: &Connection,
+        names: &[&str],
+        only_if_exists: bool,
+    ) -> Result<Vec<Atom>> {
+        let mut missing: Vec<&str> = Vec::new();
+        for name in names {
+            if !self.atoms.contains_key(*name) && !missing.contains(name) {
+                missing.push(name);
+            }
+        }
+
+        let atoms = intern_atoms(conn, &missing, only_if_exists)?;
+        for (name, atom) in missing.into_iter().zip(atoms) {
+            if atom != ATOM_NONE {
+                self.atoms.insert(name.to_string(), atom);
+            }
+        }
+        Ok(names
+            .iter()
+            .map(|name| self.get(name).unwrap_or(ATOM_NONE))
+            .collect())
+    }
+
+    /// The cached atom of `name`, if any.
+    pub fn get(&self, name: &str) -> Option<Atom> {
+        self.atoms.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atom_cache() {
+        use crate::mock;
+        use crate::Xid;
+        use std::convert::TryInto;
+        use std::sync::{Arc, Mutex};
+
+        // the names of the InternAtom requests, and the atoms existing on the server
+        let requested = Arc::new(Mutex::new(Vec::<String>::new()));
+        let existing = Arc::new(Mutex::new(vec!["WM_PROTOCOLS".to_string()]));
+        let conn = {
+            let requested = requested.clone();
+            let existing = existing.clone();
+            mock::connect(move |req| match req.opcode {
+                16 => {
+                    let only_if_exists = req.data[1] != 0;
+                    let len = u16::from_ne_bytes(req.data[4..6].try_into().unwrap()) as usize;
+                    let name = std::str::from_utf8(&req.data[8..8 + len]).unwrap();
+                    requested.lock().unwrap().push(name.to_string());
+
+                    let mut existing = existing.lock().unwrap();
+                    let index = match existing.iter().position(|a| a == name) {
+                        Some(index) => Some(index),
+                        None if only_if_exists => None,
+                        None => {
+                            existing.push(name.to_string());
+                            Some(existing.len() - 1)
+                        }
+                    };
+                    let atom = index.map_or(0, |index| 300 + index as u32);
+                    vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let ids = |atoms: Vec<Atom>| atoms.iter().map(|a| a.resource_id()).collect::<Vec<_>>();
+
+        assert_eq!(
+            ids(intern_atoms(&conn, &["_NET_WM_NAME", "WM_PROTOCOLS"], false).unwrap()),
+            [301, 300]
+        );
+        assert!(intern_atoms(&conn, &[], false).unwrap().is_empty());
+        requested.lock().unwrap().clear();
+
+        let mut cache = AtomCache::new();
+        assert_eq!(
+            ids(cache
+                .intern(
+                    &conn,
+                    &["WM_PROTOCOLS", "_NET_WM_PID", "WM_PROTOCOLS"],
+                    true
+                )
+                .unwrap()),
+            [300, 0, 300]
+        );
+        assert_eq!(
+            cache.get("WM_PROTOCOLS").map(|a| a.resource_id()),
+            Some(300)
+        );
+        assert_eq!(cache.get("_NET_WM_PID"), None);
+
+        // the cached atoms are not requested again, the missing ones are
+        assert_eq!(
+            ids(cache
+                .intern(
+                    &conn,
+                    &["_NET_WM_PID", "WM_PROTOCOLS", "_NET_WM_NAME"],
+                    false
+                )
+                .unwrap()),
+            [302, 300, 301]
+        );
+        assert_eq!(
+            *requested.lock().unwrap(),
+            ["WM_PROTOCOLS", "_NET_WM_PID", "_NET_WM_PID", "_NET_WM_NAME"]
+        );
+        requested.lock().unwrap().clear();
+        cache
+            .intern(&conn, &["_NET_WM_NAME", "_NET_WM_PID"], true)
+            .unwrap();
+        assert!(requested.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! Colormap helpers for the clients of the PseudoColor and other dynamic visuals.
+
+use super::{
+    ChangeWindowAttributes, Colormap, ColormapNotifyEvent, ColormapState, Cw,
+    ListInstalledColormaps, Window,
+};
+use crate::base::{Connection, Result, ResultExt, Xid};
+
+/// What a `ColormapNotify` event reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColormapChange {
+    /// The colormap attribute of the window was changed to `colormap`, or freed if
+    /// `colormap` is `COLORMAP_NONE`. `installed` tells whether `colormap` is installed.
+    Attribute { colormap: Colormap, installed: bool },
+    /// The colormap of the window was installed.
+    Installed(Colormap),
+    /// The colormap of the window was uninstalled.
+    Uninstalled(Colormap),
+}
+
+impl ColormapNotifyEvent {
+    /// What the event reports, from its `new` flag and its state.
+    pub fn change(&self) -> ColormapChange {
+        let colormap = self.colormap();
+        match (self.new_(), self.state()) {
+            (true, state) => ColormapChange::Attribute {
+                colormap,
+                installed: state == ColormapState::Installed,
+            },
+            (false, ColormapState::Installed) => ColormapChange::Installed(colormap),
+            (false, ColormapState::Uninstalled) => ColormapChange::Uninstalled(colormap),
+        }
+    }
+}
+
+/// Lists the colormaps installed on the screen of `window`.
+pub fn list_installed_colormaps(conn: &Connection, window: Window) -> Result<Vec<Colormap>> {
+    let cookie = conn.send_request(&ListInstalledColormaps { window });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("ListInstalledColormaps", || {
+            format!("for window {:#x}", window.resource_id())
+        })?;
+    Ok(reply.cmaps().to_vec())
+}
+
+/// Sets the colormap attribute of `window`.
+///
+/// The window manager installs the colormap of the top-level windows when they have the
+/// focus. A top-level window whose subwindows have other colormaps lists them in its
+/// `WM_COLORMAP_WINDOWS` property (see [crate::icccm::set_wm_colormap_windows]).
+pub fn set_window_colormap(conn: &Connection, window: Window, colormap: Colormap) -> Result<()> {
+    conn.send_and_check_request(&ChangeWindowAttributes {
+        window,
+        value_list: &[Cw::Colormap(colormap)],
+    })
+    .context("ChangeWindowAttributes", || {
+        format!(
+            "colormap {:#x} of window {:#x}",
+            colormap.resource_id(),
+            window.resource_id()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colormaps() {
+        use crate::mock;
+        use crate::x::{Event, COLORMAP_NONE};
+        use crate::XidNew;
+
+        // ColormapNotify event, sent after the request of sequence `seq`
+        fn colormap_notify(seq: u16, colormap: u32, new: bool, state: ColormapState) -> Vec<u8> {
+            let mut ev = vec![32, 0];
+            ev.extend_from_slice(&seq.to_ne_bytes());
+            ev.extend_from_slice(&0x200u32.to_ne_bytes());
+            ev.extend_from_slice(&colormap.to_ne_bytes());
+            ev.extend_from_slice(&[new as u8, state as u8]);
+            ev.resize(32, 0);
+            ev
+        }
+
+        let conn = mock::connect(move |req| match req.opcode {
+            // ChangeWindowAttributes: the colormap is installed by the window manager
+            2 => {
+                assert_eq!(req.u32_at(4), 0x200);
+                assert_eq!(req.u32_at(8), 0x2000); // CW_COLORMAP
+                let colormap = req.u32_at(12);
+                vec![
+                    colormap_notify(req.seq, colormap, true, ColormapState::Uninstalled),
+                    colormap_notify(req.seq, colormap, false, ColormapState::Installed),
+                    colormap_notify(req.seq, colormap, false, ColormapState::Uninstalled),
+                    colormap_notify(req.seq, 0, true, ColormapState::Uninstalled),
+                ]
+            }
+            // ListInstalledColormaps
+            83 => {
+                let mut body = vec![0u8; 24];
+                body[0..2].copy_from_slice(&2u16.to_ne_bytes());
+                body.extend_from_slice(&0x20u32.to_ne_bytes());
+                body.extend_from_slice(&0x300u32.to_ne_bytes());
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            _ => mock::default_response(req),
+        });
+        let window = unsafe { Window::new(0x200) };
+        let colormap = unsafe { Colormap::new(0x300) };
+
+        assert_eq!(
+            list_installed_colormaps(&conn, window).unwrap(),
+            [unsafe { Colormap::new(0x20) }, colormap]
+        );
+
+        set_window_colormap(&conn, window, colormap).unwrap();
+        let expected = [
+            ColormapChange::Attribute {
+                colormap,
+                installed: false,
+            },
+            ColormapChange::Installed(colormap),
+            ColormapChange::Uninstalled(colormap),
+            ColormapChange::Attribute {
+                colormap: COLORMAP_NONE,
+                installed: false,
+            },
+        ];
+        for change in expected {
+            match conn.wait_for_event().unwrap() {
+                crate::Event::X(Event::ColormapNotify(ev)) => {
+                    assert_eq!(ev.window(), window);
+                    assert_eq!(ev.change(), change);
+                }
+                ev => panic!("unexpected event {:?}", ev),
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Graphics context creation helpers.
+
+use super::window::set_value;
+use super::{
+    ArcMode, CapStyle, ChangeGc, CreateGc, Drawable, FillRule, FillStyle, Font, Gc, Gcontext, Gx,
+    JoinStyle, LineStyle, Pixmap, SubwindowMode,
+};
+use crate::base::{Connection, Result, ResultExt, Xid};
+
+/// The values of a graphics context, for [create_gc] and [change_gc].
+///
+/// The setters can be called in any order: the values are kept in the order of the [Gc]
+/// enum, which the value lists of `CreateGC` and `ChangeGC` require, and a value set twice
+/// replaces the previous one.
+///
+/// # Example
+/// ```no_run
+/// # use xcb::x;
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, screen_num) = xcb::Connection::connect(None)?;
+/// # let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+/// let values = x::GcValues::new()
+///     .graphics_exposures(false)
+///     .line_width(2)
+///     .foreground(screen.black_pixel());
+/// let gc = x::create_gc(&conn, x::Drawable::Window(screen.root()), &values)?;
+/// x::change_gc(&conn, gc, &x::GcValues::new().foreground(screen.white_pixel()))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcValues {
+    values: Vec<Gc>,
+}
+
+impl GcValues {
+    /// No value: the defaults of `CreateGC`, or the current values for
+    /// `ChangeGC`.
+    pub fn new() -> GcValues {
+        GcValues::default()
+    }
+
+    /// Sets `value`, replacing the previous value of the same component.
+    pub fn with(mut self, value: Gc) -> GcValues {
+        set_value(&mut self.values, value);
+        self
+    }
+
+    pub fn function(self, function: Gx) -> GcValues {
+        self.with(Gc::Function(function))
+    }
+
+    pub fn plane_mask(self, plane_mask: u32) -> GcValues {
+        self.with(Gc::PlaneMask(plane_mask))
+    }
+
+    pub fn foreground(self, pixel: u32) -> GcValues {
+        self.with(Gc::Foreground(pixel))
+    }
+
+    pub fn background(self, pixel: u32) -> GcValues {
+        self.with(Gc::Background(pixel))
+    }
+
+    /// The width of the lines in pixels, `0` for the fast "thin" lines.
+    pub fn line_width(self, width: u16) -> GcValues {
+        self.with(Gc::LineWidth(width as u32))
+    }
+
+    pub fn line_style(self, style: LineStyle) -> GcValues {
+        self.with(Gc::LineStyle(style))
+    }
+
+    pub fn cap_style(self, style: CapStyle) -> GcValues {
+        self.with(Gc::CapStyle(style))
+    }
+
+    pub fn join_style(self, style: JoinStyle) -> GcValues {
+        self.with(Gc::JoinStyle(style))
+    }
+
+    pub fn fill_style(self, style: FillStyle) -> GcValues {
+        self.with(Gc::FillStyle(style))
+    }
+
+    pub fn fill_rule(self, rule: FillRule) -> GcValues {
+        self.with(Gc::FillRule(rule))
+    }
+
+    pub fn tile(self, pixmap: Pixmap) -> GcValues {
+        self.with(Gc::Tile(pixmap))
+    }
+
+    pub fn stipple(self, pixmap: Pixmap) -> GcValues {
+        self.with(Gc::Stipple(pixmap))
+    }
+
+    /// The origin of the tile or the stipple, relative to the origin of the destination.
+    pub fn tile_stipple_origin(self, x: i16, y: i16) -> GcValues {
+        self.with(Gc::TileStippleOriginX(x as i32))
+            .with(Gc::TileStippleOriginY(y as i32))
+    }
+
+    pub fn font(self, font: Font) -> GcValues {
+        self.with(Gc::Font(font))
+    }
+
+    pub fn subwindow_mode(self, mode: SubwindowMode) -> GcValues {
+        self.with(Gc::SubwindowMode(mode))
+    }
+
+    /// Whether `CopyArea` and `CopyPlane` generate `GraphicsExposure` and `NoExposure`
+    /// events. The default is `true`.
+    pub fn graphics_exposures(self, enabled: bool) -> GcValues {
+        self.with(Gc::GraphicsExposures(enabled))
+    }
+
+    /// The origin of the clip mask, relative to the origin of the destination.
+    pub fn clip_origin(self, x: i16, y: i16) -> GcValues {
+        self.with(Gc::ClipOriginX(x as i32))
+            .with(Gc::ClipOriginY(y as i32))
+    }
+
+    /// The clip mask, a pixmap of depth 1, or `Pixmap::none()` to draw everywhere.
+    pub fn clip_mask(self, pixmap: Pixmap) -> GcValues {
+        self.with(Gc::ClipMask(pixmap))
+    }
+
+    pub fn dash_offset(self, offset: u16) -> GcValues {
+        self.with(Gc::DashOffset(offset as u32))
+    }
+
+    /// Dashes of `length` pixels, on and off. Uneven dash lists are set with `SetDashes`.
+    pub fn dashes(self, length: u8) -> GcValues {
+        self.with(Gc::DashList(length as u32))
+    }
+
+    pub fn arc_mode(self, mode: ArcMode) -> GcValues {
+        self.with(Gc::ArcMode(mode))
+    }
+
+    /// The value list of `CreateGC` and `ChangeGC`.
+    pub fn value_list(&self) -> &[Gc] {
+        &self.values
+    }
+}
+
+/// Creates a graphics context usable with the drawables of the root and the depth of
+/// `drawable`, and returns it.
+pub fn create_gc(conn: &Connection, drawable: Drawable, values: &GcValues) -> Result<Gcontext> {
+    let gc = conn.generate_id();
+    conn.send_and_check_request(&CreateGc {
+        cid: gc,
+        drawable,
+        value_list: values.value_list(),
+    })
+    .context("CreateGC", || {
+        format!("for drawable {:#x}", drawable.resource_id())
+    })?;
+    Ok(gc)
+}
+
+/// Changes the `values` of `gc`, the others are unchanged.
+pub fn change_gc(conn: &Connection, gc: Gcontext, values: &GcValues) -> Result<()> {
+    conn.send_and_check_request(&ChangeGc {
+        gc,
+        value_list: values.value_list(),
+    })
+    .context("ChangeGC", || format!("of gc {:#x}", gc.resource_id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gc_values() {
+        use crate::mock;
+        use crate::x::Window;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let values = GcValues::new()
+            .graphics_exposures(false)
+            .line_width(3)
+            .background(0x00ff_ffff)
+            .foreground(0)
+            .cap_style(CapStyle::Round)
+            .function(Gx::Xor)
+            .line_width(2);
+        assert_eq!(
+            values.value_list(),
+            [
+                Gc::Function(Gx::Xor),
+                Gc::Foreground(0),
+                Gc::Background(0x00ff_ffff),
+                Gc::LineWidth(2),
+                Gc::CapStyle(CapStyle::Round),
+                Gc::GraphicsExposures(false),
+            ]
+        );
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let requests = requests.clone();
+            mock::connect(move |req| match req.opcode {
+                55 | 56 => {
+                    requests.lock().unwrap().push(req.data.to_vec());
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let root = unsafe { Window::new(mock::ROOT) };
+        let gc = create_gc(&conn, Drawable::Window(root), &values).unwrap();
+        change_gc(&conn, gc, &GcValues::new().clip_origin(-1, 2)).unwrap();
+
+        let requests = requests.lock().unwrap();
+        let u32_at = |request: &[u8], off: usize| {
+            u32::from_ne_bytes([
+                request[off],
+                request[off + 1],
+                request[off + 2],
+                request[off + 3],
+            ])
+        };
+
+        let create = &requests[0];
+        assert_eq!(create[0], 55);
+        assert_eq!(u32_at(create, 4), gc.resource_id());
+        assert_eq!(u32_at(create, 8), mock::ROOT);
+        // function, foreground, background, line width, cap style, graphics exposures
+        assert_eq!(u32_at(create, 12), 0x1_005d);
+        let values: Vec<u32> = (0..6).map(|i| u32_at(create, 16 + 4 * i)).collect();
+        assert_eq!(values, [6, 0, 0x00ff_ffff, 2, 2, 0]);
+
+        let change = &requests[1];
+        assert_eq!(change[0], 56);
+        assert_eq!(u32_at(change, 4), gc.resource_id());
+        assert_eq!(u32_at(change, 8), 0x6_0000);
+        assert_eq!(u32_at(change, 12), -1i32 as u32);
+        assert_eq!(u32_at(change, 16), 2);
+    }
+}
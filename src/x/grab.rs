@@ -0,0 +1,394 @@
+//! Passive grabs of keys and buttons.
+
+use super::{
+    ButtonIndex, Cursor, Error, EventMask, GrabButton, GrabKey, GrabMode, Keycode, ModMask,
+    UngrabButton, UngrabKey, Window,
+};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::error::ProtocolError;
+
+/// The key of a passive grab.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// Any key (`AnyKey`).
+    Any,
+    /// The key of a keycode.
+    Code(Keycode),
+}
+
+impl Key {
+    /// The keycode sent to the server, `0` for [Key::Any].
+    pub fn keycode(self) -> Keycode {
+        match self {
+            Key::Any => 0,
+            Key::Code(code) => code,
+        }
+    }
+}
+
+/// The modifiers of a passive grab.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mods {
+    /// Any combination of modifiers, including none (`AnyModifier`).
+    Any,
+    /// Exactly the modifiers of the mask.
+    Mask(ModMask),
+}
+
+impl Mods {
+    /// The mask sent to the server, `ModMask::ANY` for [Mods::Any].
+    pub fn mask(self) -> ModMask {
+        match self {
+            Mods::Any => ModMask::ANY,
+            Mods::Mask(mask) => mask,
+        }
+    }
+}
+
+/// The modes of a passive grab, once it is activated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GrabOptions {
+    /// Whether the events are reported normally to the windows of the client, rather
+    /// than only to the grab window.
+    pub owner_events: bool,
+    pub pointer_mode: GrabMode,
+    pub keyboard_mode: GrabMode,
+}
+
+impl Default for GrabOptions {
+    /// The events are only reported to the grab window, and are not frozen.
+    fn default() -> GrabOptions {
+        GrabOptions {
+            owner_events: false,
+            pointer_mode: GrabMode::Async,
+            keyboard_mode: GrabMode::Async,
+        }
+    }
+}
+
+/// Every modifier mask that can be grabbed, that is every combination of Shift, Lock,
+/// Control and Mod1 to Mod5.
+fn mod_combinations() -> impl Iterator<Item = ModMask> {
+    (0..=0xffu32).map(ModMask::from_bits_truncate)
+}
+
+fn grab_key_request(window: Window, key: Key, mods: ModMask, options: GrabOptions) -> GrabKey {
+    GrabKey {
+        owner_events: options.owner_events,
+        grab_window: window,
+        modifiers: mods,
+        key: key.keycode(),
+        pointer_mode: options.pointer_mode,
+        keyboard_mode: options.keyboard_mode,
+    }
+}
+
+/// Grabs `key` pressed with `mods` on `window`.
+///
+/// Fails with `x::Error::Access` if another client grabs one of the combinations.
+/// With [Key::Any] or [Mods::Any], nothing is grabbed in that case: see
+/// [grab_key_checked] to find out the conflicting combinations.
+pub fn grab_key(
+    conn: &Connection,
+    window: Window,
+    key: Key,
+    mods: Mods,
+    options: GrabOptions,
+) -> Result<()> {
+    conn.send_and_check_request(&grab_key_request(window, key, mods.mask(), options))
+        .context("GrabKey", || {
+            format!(
+                "{:?} with {:?} on window {:#x}",
+                key,
+                mods,
+                window.resource_id()
+            )
+        })
+}
+
+/// Grabs `key` pressed with `mods` on `window`, as much as the grabs of the other
+/// clients allow.
+///
+/// If the grab fails with `x::Error::Access` and `mods` is [Mods::Any], the modifier
+/// combinations are grabbed one by one. Returns the combinations that are grabbed by
+/// another client, and thus not by this one. An empty list means that everything is
+/// grabbed.
+pub fn grab_key_checked(
+    conn: &Connection,
+    window: Window,
+    key: Key,
+    mods: Mods,
+    options: GrabOptions,
+) -> Result<Vec<ModMask>> {
+    match grab_key(conn, window, key, mods, options) {
+        Ok(()) => return Ok(Vec::new()),
+        Err(err) if !is_access_error(&err) => return Err(err),
+        Err(_) if mods != Mods::Any => return Ok(vec![mods.mask()]),
+        Err(_) => {}
+    }
+
+    let cookies: Vec<_> = mod_combinations()
+        .map(|mask| {
+            let request = grab_key_request(window, key, mask, options);
+            (mask, conn.send_request_checked(&request))
+        })
+        .collect();
+    let mut conflicts = Vec::new();
+    for (mask, cookie) in cookies {
+        match conn.check_request(cookie) {
+            Ok(()) => {}
+            Err(crate::Error::Protocol(ProtocolError::X(Error::Access(_), _))) => {
+                conflicts.push(mask)
+            }
+            Err(err) => {
+                return Err(err).context("GrabKey", || {
+                    format!(
+                        "{:?} with {:?} on window {:#x}",
+                        key,
+                        mask,
+                        window.resource_id()
+                    )
+                })
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Releases the grab of `key` pressed with `mods` on `window`.
+///
+/// [Key::Any] and [Mods::Any] release every grab they match, whichever way they were
+/// established.
+pub fn ungrab_key(conn: &Connection, window: Window, key: Key, mods: Mods) -> Result<()> {
+    conn.send_and_check_request(&UngrabKey {
+        key: key.keycode(),
+        grab_window: window,
+        modifiers: mods.mask(),
+    })
+    .context("UngrabKey", || {
+        format!(
+            "{:?} with {:?} on window {:#x}",
+            key,
+            mods,
+            window.resource_id()
+        )
+    })
+}
+
+/// Grabs `button` pressed with `mods` on `window`, and reports the pointer events of
+/// `event_mask` while the grab is active.
+///
+/// Fails with `x::Error::Access` if another client grabs one of the combinations.
+pub fn grab_button(
+    conn: &Connection,
+    window: Window,
+    button: ButtonIndex,
+    mods: Mods,
+    event_mask: EventMask,
+    options: GrabOptions,
+) -> Result<()> {
+    conn.send_and_check_request(&GrabButton {
+        owner_events: options.owner_events,
+        grab_window: window,
+        event_mask,
+        pointer_mode: options.pointer_mode,
+        keyboard_mode: options.keyboard_mode,
+        confine_to: Window::none(),
+        cursor: Cursor::none(),
+        button,
+        modifiers: mods.mask(),
+    })
+    .context("GrabButton", || {
+        format!(
+            "{:?} with {:?} on window {:#x}",
+            button,
+            mods,
+            window.resource_id()
+        )
+    })
+}
+
+/// Releases the grab of `button` pressed with `mods` on `window`.
+pub fn ungrab_button(
+    conn: &Connection,
+    window: Window,
+    button: ButtonIndex,
+    mods: Mods,
+) -> Result<()> {
+    conn.send_and_check_request(&UngrabButton {
+        button,
+        grab_window: window,
+        modifiers: mods.mask(),
+    })
+    .context("UngrabButton", || {
+        format!(
+            "{:?} with {:?} on window {:#x}",
+            button,
+            mods,
+            window.resource_id()
+        )
+    })
+}
+
+fn is_access_error(err: &crate::Error) -> bool {
+    matches!(
+        err.root_cause(),
+        crate::Error::Protocol(ProtocolError::X(Error::Access(_), _))
+    )
+}
+
+impl Connection {
+    /// Releases every key grab of the client on `window`.
+    pub fn ungrab_all_keys(&self, window: Window) -> Result<()> {
+        ungrab_key(self, window, Key::Any, Mods::Any)
+    }
+
+    /// Releases every button grab of the client on `window`.
+    pub fn ungrab_all_buttons(&self, window: Window) -> Result<()> {
+        ungrab_button(self, window, ButtonIndex::Any, Mods::Any)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::base::XidNew;
+
+    type Grabs = std::sync::Arc<std::sync::Mutex<Vec<(u8, u16)>>>;
+
+    /// Connects to a mock server that tracks the key grabs of the client, and rejects with
+    /// `BadAccess` the ones that overlap the grabs of another client, `foreign`.
+    fn connect(foreign: Grabs, own: Grabs) -> Connection {
+        use crate::mock;
+
+        fn overlap(a: (u8, u16), b: (u8, u16)) -> bool {
+            (a.0 == 0 || b.0 == 0 || a.0 == b.0) && (a.1 == 0x8000 || b.1 == 0x8000 || a.1 == b.1)
+        }
+
+        mock::connect(move |req| {
+            let mods = || u16::from_ne_bytes([req.data[8], req.data[9]]);
+            match req.opcode {
+                // GrabKey
+                33 => {
+                    let grab = (req.data[10], mods());
+                    if foreign.lock().unwrap().iter().any(|g| overlap(*g, grab)) {
+                        return vec![mock::error(req.seq, 10, 0, 33)];
+                    }
+                    own.lock().unwrap().push(grab);
+                    vec![]
+                }
+                // UngrabKey
+                34 => {
+                    let grab = (req.data[1], mods());
+                    own.lock().unwrap().retain(|g| !overlap(*g, grab));
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            }
+        })
+    }
+
+    #[test]
+    fn test_key_mods_values() {
+        assert_eq!(Key::Any.keycode(), 0);
+        assert_eq!(Key::Code(38).keycode(), 38);
+        assert_eq!(Mods::Any.mask().bits(), 1 << 15);
+        assert_eq!(Mods::Mask(ModMask::CONTROL).mask(), ModMask::CONTROL);
+        assert_eq!(mod_combinations().count(), 256);
+    }
+
+    #[test]
+    fn test_grab_key_conflicts() {
+        use std::sync::{Arc, Mutex};
+
+        let control = ModMask::CONTROL.bits() as u16;
+        let shift_mod1 = (ModMask::SHIFT | ModMask::N1).bits() as u16;
+        let foreign = Arc::new(Mutex::new(vec![(38, control), (38, shift_mod1), (40, 0)]));
+        let own: Grabs = Arc::new(Mutex::new(Vec::new()));
+        let conn = connect(foreign.clone(), own.clone());
+        let window = unsafe { Window::new(0x0120_0001) };
+        let options = GrabOptions::default();
+
+        // the blanket grab is refused as a whole
+        let err = grab_key(&conn, window, Key::Code(38), Mods::Any, options).unwrap_err();
+        assert!(is_access_error(&err));
+        assert!(own.lock().unwrap().is_empty());
+
+        // the checked variant grabs the other combinations, and reports the conflicting ones
+        let conflicts = grab_key_checked(&conn, window, Key::Code(38), Mods::Any, options).unwrap();
+        assert_eq!(conflicts, [ModMask::CONTROL, ModMask::SHIFT | ModMask::N1]);
+        assert_eq!(own.lock().unwrap().len(), 254);
+
+        // a specific combination is reported as is
+        let conflicts = grab_key_checked(
+            &conn,
+            window,
+            Key::Code(40),
+            Mods::Mask(ModMask::empty()),
+            options,
+        )
+        .unwrap();
+        assert_eq!(conflicts, [ModMask::empty()]);
+        let conflicts = grab_key_checked(
+            &conn,
+            window,
+            Key::Code(40),
+            Mods::Mask(ModMask::LOCK),
+            options,
+        )
+        .unwrap();
+        assert!(conflicts.is_empty());
+
+        // the blanket ungrab releases everything
+        conn.ungrab_all_keys(window).unwrap();
+        assert!(own.lock().unwrap().is_empty());
+
+        // once the other client released its grabs, the blanket grab succeeds
+        foreign.lock().unwrap().clear();
+        grab_key(&conn, window, Key::Any, Mods::Any, options).unwrap();
+        assert_eq!(*own.lock().unwrap(), [(0, 0x8000)]);
+        ungrab_key(&conn, window, Key::Code(38), Mods::Mask(ModMask::CONTROL)).unwrap();
+        assert!(own.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ungrab_all_buttons() {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let requests = requests.clone();
+            mock::connect(move |req| {
+                if req.opcode == 28 || req.opcode == 29 {
+                    requests.lock().unwrap().push(req.data.clone());
+                }
+                mock::default_response(req)
+            })
+        };
+        let window = unsafe { Window::new(0x0120_0001) };
+
+        grab_button(
+            &conn,
+            window,
+            ButtonIndex::N1,
+            Mods::Mask(ModMask::N4),
+            EventMask::BUTTON_PRESS,
+            GrabOptions::default(),
+        )
+        .unwrap();
+        conn.ungrab_all_buttons(window).unwrap();
+
+        let requests = requests.lock().unwrap();
+        // GrabButton: the button and the modifiers
+        assert_eq!(requests[0][20], 1);
+        assert_eq!(
+            &requests[0][22..24],
+            &(ModMask::N4.bits() as u16).to_ne_bytes()
+        );
+        // UngrabButton: AnyButton and AnyModifier
+        assert_eq!(requests[1][1], 0);
+        assert_eq!(&requests[1][8..10], &0x8000u16.to_ne_bytes());
+    }
+}
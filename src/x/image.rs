@@ -0,0 +1,112 @@
+//! Upload of images larger than the maximum request length.
+
+use super::{ImageFormat, PutImage};
+use crate::base::{Connection, Result, ResultExt};
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+/// Sends the image of `request` in as many `PutImage` requests as needed to fit in the
+/// maximum request length of the server, and checks them.
+///
+/// The image is split in bands of rows, sent at the `dst_y` of their first row. `data`
+/// holds the rows of the image with the padding of the server: the scanline pad of the
+/// pixmap format of `depth` for `ImageFormat::ZPixmap`, and the bitmap scanline pad for
+/// the other formats, whose `left_pad` is accounted for. The planes of
+/// `ImageFormat::XyPixmap` are split alike.
+///
+/// The maximum request length is the one of BIG-REQUESTS if the extension is enabled,
+/// which [Connection::get_maximum_request_length] does when it is available.
+///
+/// # Panics
+/// Panics if `depth` is not the depth of a pixmap format of the server for
+/// `ImageFormat::ZPixmap`, if `data` is shorter than the image, if a single row does
+/// not fit in a request, or if the `dst_y` of a band exceeds `i16::MAX`, the largest
+/// coordinate of the protocol. Nothing is sent in these cases.
+pub fn put_image_chunked(conn: &Connection, request: &PutImage) -> Result<()> {
+    let setup = conn.get_setup();
+    let (bits_per_row, scanline_pad, planes) = match request.format {
+        ImageFormat::ZPixmap => {
+            let format = setup
+                .pixmap_formats()
+                .iter()
+                .find(|f| f.depth() == request.depth)
+                .unwrap_or_else(|| panic!("no pixmap format of depth {}", request.depth));
+            let bits = request.width as usize * format.bits_per_pixel() as usize;
+            (bits, format.scanline_pad() as usize, 1)
+        }
+        format => {
+            let bits = request.width as usize + request.left_pad as usize;
+            let planes = match format {
+                ImageFormat::XyPixmap => request.depth as usize,
+                _ => 1,
+            };
+            (bits, setup.bitmap_format_scanline_pad() as usize, planes)
+        }
+    };
+    let stride = bits_per_row.div_ceil(scanline_pad) * scanline_pad / 8;
+    let height = request.height as usize;
+    let plane_size = stride * height;
+    assert!(
+        request.data.len() >= plane_size * planes,
+        "image data of {} bytes, {} expected",
+        request.data.len(),
+        plane_size * planes
+    );
+
+    // the room for the data, without the 4 bytes of the extended length of BIG-REQUESTS
+    let header_len = PutImage {
+        data: &[],
+        ..*request
+    }
+    .request_len();
+    let max_data_len =
+        (conn.get_maximum_request_length() as usize * 4).saturating_sub(header_len + 4);
+    let rows = max_data_len / (stride * planes).max(1);
+    assert!(
+        rows > 0,
+        "a row of {} bytes does not fit in a request",
+        stride * planes
+    );
+
+    // the bands are checked before sending anything
+    let bands: Vec<(usize, usize, i16)> = (0..height)
+        .step_by(rows)
+        .map(|y0| {
+            let dst_y = i32::from(request.dst_y) + y0 as i32;
+            let dst_y = i16::try_from(dst_y).unwrap_or_else(|_| {
+                panic!("dst_y {} of the band at row {} out of range", dst_y, y0)
+            });
+            (y0, (y0 + rows).min(height), dst_y)
+        })
+        .collect();
+
+    let mut cookies = Vec::with_capacity(bands.len());
+    for (y0, y1, dst_y) in bands {
+        let data = if planes == 1 {
+            Cow::Borrowed(&request.data[y0 * stride..y1 * stride])
+        } else {
+            let planes = request.data.chunks_exact(plane_size).take(planes);
+            Cow::Owned(
+                planes
+                    .flat_map(|plane| &plane[y0 * stride..y1 * stride])
+                    .copied()
+                    .collect(),
+            )
+        };
+        let band = PutImage {
+            height: (y1 - y0) as u16,
+            dst_y,
+            data: &data,
+            ..*request
+        };
+        debug_assert!(conn.fits_in_request(band.request_len()));
+        cookies.push(conn.send_request_checked(&band));
+    }
+    for cookie in cookies {
+        conn.check_request(cookie).context("PutImage", || {
+            format!("image of {}x{}", request.width, request.height)
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,233 @@
+//! Composition of the pixels of `TrueColor` and `DirectColor` visuals.
+
+use super::Visualtype;
+
+/// A color channel of a pixel: `width` bits starting at bit `shift`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Channel {
+    pub shift: u32,
+    pub width: u32,
+}
+
+impl Channel {
+    /// The channel of `mask`, which should be a contiguous run of bits.
+    ///
+    /// If it is not, the channel is the lowest run of bits of `mask`.
+    pub fn from_mask(mask: u32) -> Channel {
+        if mask == 0 {
+            return Channel { shift: 0, width: 0 };
+        }
+        let shift = mask.trailing_zeros();
+        Channel {
+            shift,
+            width: (mask >> shift).trailing_ones(),
+        }
+    }
+
+    /// The mask of the channel.
+    pub fn mask(&self) -> u32 {
+        (self.max() << self.shift) as u32
+    }
+
+    /// The maximum value of the channel.
+    fn max(&self) -> u64 {
+        (1u64 << self.width) - 1
+    }
+
+    /// Scales the 16 bits `value` to the channel, rounded to the nearest, and shifts it
+    /// in place.
+    pub fn encode(&self, value: u16) -> u32 {
+        let max = self.max();
+        let scaled = (value as u64 * max + 0x7fff) / 0xffff;
+        (scaled << self.shift) as u32
+    }
+
+    /// Extracts the channel from `pixel`, and scales it to 16 bits.
+    ///
+    /// The value is scaled by replicating its bits, such that the maximum value of
+    /// the channel is `0xffff`.
+    pub fn decode(&self, pixel: u32) -> u16 {
+        if self.width == 0 {
+            return 0;
+        }
+        let value = (pixel as u64 >> self.shift) & self.max();
+        if self.width >= 16 {
+            return (value >> (self.width - 16)) as u16;
+        }
+        let mut replicated = 0u64;
+        let mut bits = 0;
+        while bits < 16 {
+            replicated = replicated << self.width | value;
+            bits += self.width;
+        }
+        (replicated >> (bits - 16)) as u16
+    }
+}
+
+/// The layout of the red, green and blue channels in a pixel.
+///
+/// The masks of `TrueColor` and `DirectColor` visuals are contiguous, such that each
+/// channel is a run of bits. Other visuals have empty masks, which give empty channels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PixelFormat {
+    pub red: Channel,
+    pub green: Channel,
+    pub blue: Channel,
+}
+
+impl PixelFormat {
+    /// The format of the masks.
+    pub fn from_masks(red_mask: u32, green_mask: u32, blue_mask: u32) -> PixelFormat {
+        PixelFormat {
+            red: Channel::from_mask(red_mask),
+            green: Channel::from_mask(green_mask),
+            blue: Channel::from_mask(blue_mask),
+        }
+    }
+
+    /// The format of the pixels of `visual`.
+    pub fn from_visual(visual: &Visualtype) -> PixelFormat {
+        PixelFormat::from_masks(visual.red_mask(), visual.green_mask(), visual.blue_mask())
+    }
+
+    /// Composes the pixel of the 16 bits channel values.
+    pub fn encode(&self, r: u16, g: u16, b: u16) -> u32 {
+        self.red.encode(r) | self.green.encode(g) | self.blue.encode(b)
+    }
+
+    /// Decomposes `pixel` into 16 bits channel values.
+    pub fn decode(&self, pixel: u32) -> (u16, u16, u16) {
+        (
+            self.red.decode(pixel),
+            self.green.decode(pixel),
+            self.blue.decode(pixel),
+        )
+    }
+}
+
+impl Visualtype {
+    /// Composes the pixel of the 16 bits channel values, without colormap.
+    ///
+    /// See [PixelFormat] for the visuals it is relevant for.
+    pub fn encode_pixel(&self, r: u16, g: u16, b: u16) -> u32 {
+        PixelFormat::from_visual(self).encode(r, g, b)
+    }
+
+    /// Decomposes `pixel` into 16 bits channel values, without colormap.
+    ///
+    /// See [PixelFormat] for the visuals it is relevant for.
+    pub fn decode_pixel(&self, pixel: u32) -> (u16, u16, u16) {
+        PixelFormat::from_visual(self).decode(pixel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_from_mask() {
+        assert_eq!(
+            Channel::from_mask(0x00ff_0000),
+            Channel {
+                shift: 16,
+                width: 8
+            }
+        );
+        assert_eq!(
+            Channel::from_mask(0xf800),
+            Channel {
+                shift: 11,
+                width: 5
+            }
+        );
+        assert_eq!(
+            Channel::from_mask(0x3ff0_0000),
+            Channel {
+                shift: 20,
+                width: 10
+            }
+        );
+        assert_eq!(Channel::from_mask(0), Channel { shift: 0, width: 0 });
+        assert_eq!(Channel::from_mask(0xffff_ffff).mask(), 0xffff_ffff);
+        // not contiguous: the lowest run
+        assert_eq!(Channel::from_mask(0x0f0f).mask(), 0x000f);
+    }
+
+    #[test]
+    fn test_pixel_format_layouts() {
+        use crate::x::VisualClass;
+
+        let layouts = [
+            // 888
+            (0x00ff_0000, 0x0000_ff00, 0x0000_00ff),
+            // 565
+            (0xf800, 0x07e0, 0x001f),
+            // 555
+            (0x7c00, 0x03e0, 0x001f),
+            // BGR 888
+            (0x0000_00ff, 0x0000_ff00, 0x00ff_0000),
+        ];
+        for (red_mask, green_mask, blue_mask) in layouts {
+            let visual = Visualtype::new(
+                0x21,
+                VisualClass::TrueColor,
+                8,
+                256,
+                red_mask,
+                green_mask,
+                blue_mask,
+            );
+            let format = PixelFormat::from_visual(&visual);
+
+            // the extremes
+            assert_eq!(
+                visual.encode_pixel(0xffff, 0xffff, 0xffff),
+                red_mask | green_mask | blue_mask
+            );
+            assert_eq!(visual.encode_pixel(0xffff, 0, 0), red_mask);
+            assert_eq!(visual.encode_pixel(0, 0, 0xffff), blue_mask);
+            assert_eq!(visual.decode_pixel(green_mask), (0, 0xffff, 0));
+            assert_eq!(visual.decode_pixel(0), (0, 0, 0));
+
+            for channel in [format.red, format.green, format.blue] {
+                // every value of the channel is decoded and encoded back exactly
+                for value in 0..=channel.max() as u32 {
+                    let pixel = value << channel.shift;
+                    assert_eq!(channel.encode(channel.decode(pixel)), pixel);
+                }
+                // every 16 bits value is encoded within the quantization error
+                let step = 0xffff as f64 / channel.max() as f64;
+                for value in 0..=0xffffu16 {
+                    let decoded = channel.decode(channel.encode(value));
+                    let error = (decoded as f64 - value as f64).abs();
+                    assert!(
+                        error <= step / 2.0 + 1.0,
+                        "{:?}: {} decoded as {}",
+                        channel,
+                        value,
+                        decoded
+                    );
+                }
+            }
+
+            // the channels don't overlap
+            let pixel = visual.encode_pixel(0x8000, 0x4000, 0xc000);
+            let (r, g, b) = visual.decode_pixel(pixel);
+            assert_eq!(format.encode(r, g, b), pixel);
+            assert_eq!(format.decode(pixel), (r, g, b));
+        }
+    }
+
+    #[test]
+    fn test_bit_replication() {
+        let five = Channel::from_mask(0x1f);
+        assert_eq!(five.decode(0b10000), 0b1000_0100_0010_0001);
+        assert_eq!(five.decode(0b00001), 0b0000_1000_0100_0010);
+        let six = Channel::from_mask(0x3f);
+        assert_eq!(six.decode(0b100000), 0b1000_0010_0000_1000);
+        let ten = Channel::from_mask(0x3ff);
+        assert_eq!(ten.decode(0x3ff), 0xffff);
+        assert_eq!(ten.decode(0x200), 0x8020);
+    }
+}
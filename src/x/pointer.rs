@@ -0,0 +1,309 @@
+//! Pointer button mapping.
+
+use super::{ButtonPressEvent, Event, GetPointerMapping, Mapping, MappingNotifyEvent};
+use crate::base::{Connection, Result, ResultExt};
+
+/// The role of a logical pointer button.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LogicalButton {
+    /// Button 1, the left button of a right-handed mouse.
+    Primary,
+    /// Button 2.
+    Middle,
+    /// Button 3, the right button of a right-handed mouse.
+    Secondary,
+    /// Button 4.
+    ScrollUp,
+    /// Button 5.
+    ScrollDown,
+    /// Button 6.
+    ScrollLeft,
+    /// Button 7.
+    ScrollRight,
+    /// Any other logical button.
+    Other(u8),
+    /// The physical button is disabled by the mapping.
+    Disabled,
+}
+
+impl LogicalButton {
+    /// The role of the logical button number `button`.
+    pub fn from_logical(button: u8) -> LogicalButton {
+        match button {
+            0 => LogicalButton::Disabled,
+            1 => LogicalButton::Primary,
+            2 => LogicalButton::Middle,
+            3 => LogicalButton::Secondary,
+            4 => LogicalButton::ScrollUp,
+            5 => LogicalButton::ScrollDown,
+            6 => LogicalButton::ScrollLeft,
+            7 => LogicalButton::ScrollRight,
+            n => LogicalButton::Other(n),
+        }
+    }
+
+    /// Whether the button is one of the scroll buttons 4 to 7.
+    ///
+    /// The scroll buttons can be remapped like the other buttons, although in practice
+    /// they never are.
+    pub fn is_scroll(&self) -> bool {
+        matches!(
+            self,
+            LogicalButton::ScrollUp
+                | LogicalButton::ScrollDown
+                | LogicalButton::ScrollLeft
+                | LogicalButton::ScrollRight
+        )
+    }
+}
+
+/// The pointer button mapping, as set with `x::SetPointerMapping`.
+///
+/// Element `i` of the map is the logical button of the physical button `i + 1`.
+/// Left-handed users typically swap the buttons 1 and 3.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PointerMapping {
+    map: Vec<u8>,
+}
+
+impl PointerMapping {
+    /// Builds a mapping from the map of `x::GetPointerMapping` or `x::SetPointerMapping`.
+    pub fn new(map: &[u8]) -> PointerMapping {
+        PointerMapping { map: map.to_vec() }
+    }
+
+    /// The identity mapping of `buttons` physical buttons.
+    pub fn identity(buttons: u8) -> PointerMapping {
+        PointerMapping {
+            map: (1..=buttons).collect(),
+        }
+    }
+
+    /// The map, indexed by the physical button minus one.
+    pub fn map(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// The logical button of the physical button `physical_detail`.
+    ///
+    /// Buttons beyond the map are not mapped.
+    pub fn logical_button(&self, physical_detail: u8) -> LogicalButton {
+        let logical = match physical_detail {
+            0 => 0,
+            n => self.map.get(n as usize - 1).copied().unwrap_or(n),
+        };
+        LogicalButton::from_logical(logical)
+    }
+
+    /// The physical buttons mapped to `button`.
+    pub fn physical_buttons(&self, button: LogicalButton) -> impl Iterator<Item = u8> + '_ {
+        (1..=self.map.len() as u8).filter(move |b| self.logical_button(*b) == button)
+    }
+}
+
+impl ButtonPressEvent {
+    /// The role of the button of the event, considering `detail` as a physical button
+    /// translated by `mapping`.
+    ///
+    /// Note that the server applies the pointer mapping before it reports the core
+    /// events: the translation is for events built from physical button numbers, such as
+    /// the events replayed from a recording or from a device that bypasses the mapping.
+    /// The role of the `detail` of a core event is given by [LogicalButton::from_logical].
+    pub fn logical_button(&self, mapping: &PointerMapping) -> LogicalButton {
+        mapping.logical_button(self.detail())
+    }
+}
+
+/// The pointer button mapping of the server.
+///
+/// The mapping is fetched with `x::GetPointerMapping` by [PointerMappingCache::new], and
+/// again by [PointerMappingCache::refresh] after a `MappingNotify` event.
+/// `MappingNotify` events are always sent to all clients, they don't need to be selected.
+pub struct PointerMappingCache<'c> {
+    conn: &'c Connection,
+    mapping: PointerMapping,
+}
+
+impl<'c> PointerMappingCache<'c> {
+    /// Fetches the pointer mapping of the server.
+    pub fn new(conn: &'c Connection) -> Result<PointerMappingCache<'c>> {
+        Ok(PointerMappingCache {
+            conn,
+            mapping: fetch_pointer_mapping(conn)?,
+        })
+    }
+
+    /// The cached mapping.
+    pub fn mapping(&self) -> &PointerMapping {
+        &self.mapping
+    }
+
+    /// Fetches the mapping again if `event` notifies a change of the pointer mapping,
+    /// and returns whether it did.
+    pub fn refresh(&mut self, event: &MappingNotifyEvent) -> Result<bool> {
+        match event.request() {
+            Mapping::Pointer => {
+                self.mapping = fetch_pointer_mapping(self.conn)?;
+                Ok(true)
+            }
+            Mapping::Keyboard | Mapping::Modifier => Ok(false),
+        }
+    }
+
+    /// As [PointerMappingCache::refresh], if `event` is a `MappingNotify` event.
+    pub fn handle_event(&mut self, event: &crate::Event) -> Result<bool> {
+        match event {
+            crate::Event::X(Event::MappingNotify(ev)) => self.refresh(ev),
+            _ => Ok(false),
+        }
+    }
+}
+
+impl<'c> std::fmt::Debug for PointerMappingCache<'c> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PointerMappingCache")
+            .field("mapping", &self.mapping)
+            .finish()
+    }
+}
+
+fn fetch_pointer_mapping(conn: &Connection) -> Result<PointerMapping> {
+    let cookie = conn.send_request(&GetPointerMapping {});
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("GetPointerMapping", String::new)?;
+    Ok(PointerMapping::new(reply.map()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logical_button() {
+        let identity = PointerMapping::identity(7);
+        assert_eq!(identity.logical_button(1), LogicalButton::Primary);
+        assert_eq!(identity.logical_button(2), LogicalButton::Middle);
+        assert_eq!(identity.logical_button(3), LogicalButton::Secondary);
+        assert_eq!(identity.logical_button(4), LogicalButton::ScrollUp);
+        assert_eq!(identity.logical_button(5), LogicalButton::ScrollDown);
+        assert_eq!(identity.logical_button(6), LogicalButton::ScrollLeft);
+        assert_eq!(identity.logical_button(7), LogicalButton::ScrollRight);
+        // beyond the map
+        assert_eq!(identity.logical_button(9), LogicalButton::Other(9));
+        assert_eq!(identity.logical_button(0), LogicalButton::Disabled);
+
+        let left_handed = PointerMapping::new(&[3, 2, 1, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(left_handed.logical_button(1), LogicalButton::Secondary);
+        assert_eq!(left_handed.logical_button(3), LogicalButton::Primary);
+        assert_eq!(left_handed.logical_button(2), LogicalButton::Middle);
+        assert_eq!(left_handed.logical_button(4), LogicalButton::ScrollUp);
+        assert_eq!(left_handed.logical_button(8), LogicalButton::Other(8));
+        assert_eq!(
+            left_handed
+                .physical_buttons(LogicalButton::Primary)
+                .collect::<Vec<_>>(),
+            [3]
+        );
+
+        // the scroll buttons can be remapped, and buttons disabled
+        let odd = PointerMapping::new(&[1, 0, 3, 5, 4, 7, 6]);
+        assert_eq!(odd.logical_button(2), LogicalButton::Disabled);
+        assert_eq!(odd.logical_button(4), LogicalButton::ScrollDown);
+        assert_eq!(odd.logical_button(5), LogicalButton::ScrollUp);
+        assert_eq!(odd.logical_button(6), LogicalButton::ScrollRight);
+        assert_eq!(odd.logical_button(7), LogicalButton::ScrollLeft);
+        assert!(odd.logical_button(7).is_scroll());
+        assert!(!odd.logical_button(1).is_scroll());
+    }
+
+    #[test]
+    fn test_button_event_logical_button() {
+        use crate::x::{KeyButMask, Window};
+        use crate::XidNew;
+
+        let ev = |detail| unsafe {
+            ButtonPressEvent::new(
+                detail,
+                0,
+                Window::new(0x100),
+                Window::new(0x200),
+                Window::new(0),
+                10,
+                10,
+                1,
+                1,
+                KeyButMask::empty(),
+                true,
+            )
+        };
+        let left_handed = PointerMapping::new(&[3, 2, 1]);
+        assert_eq!(ev(1).logical_button(&left_handed), LogicalButton::Secondary);
+        assert_eq!(ev(3).logical_button(&left_handed), LogicalButton::Primary);
+        assert_eq!(
+            ev(5).logical_button(&left_handed),
+            LogicalButton::ScrollDown
+        );
+    }
+
+    #[test]
+    fn test_pointer_mapping_cache() {
+        use crate::mock;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        // MappingNotify event, sent after the request of sequence `seq`
+        fn mapping_notify(seq: u16, request: Mapping) -> Vec<u8> {
+            let mut ev = vec![34, 0];
+            ev.extend_from_slice(&seq.to_ne_bytes());
+            ev.extend_from_slice(&[request as u8, 8, 1]);
+            ev.resize(32, 0);
+            ev
+        }
+
+        let requests = Arc::new(AtomicU32::new(0));
+        let conn = {
+            let requests = requests.clone();
+            mock::connect(move |req| match req.opcode {
+                // SetPointerMapping: notify a keyboard then a pointer mapping change
+                116 => vec![
+                    mapping_notify(req.seq, Mapping::Keyboard),
+                    mapping_notify(req.seq, Mapping::Pointer),
+                    mock::reply(req.seq, 0, &[]),
+                ],
+                // GetPointerMapping: identity then left-handed
+                117 => {
+                    let map: &[u8] = if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                        &[1, 2, 3, 4, 5]
+                    } else {
+                        &[3, 2, 1, 4, 5]
+                    };
+                    let mut body = vec![0u8; 24];
+                    body.extend_from_slice(map);
+                    vec![mock::reply(req.seq, map.len() as u8, &body)]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+
+        let mut cache = PointerMappingCache::new(&conn).unwrap();
+        assert_eq!(cache.mapping().logical_button(1), LogicalButton::Primary);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let cookie = conn.send_request(&crate::x::SetPointerMapping {
+            map: &[3, 2, 1, 4, 5],
+        });
+        conn.wait_for_reply(cookie).unwrap();
+
+        // the keyboard notification doesn't refresh the mapping
+        let event = conn.poll_for_queued_event().unwrap().unwrap();
+        assert!(!cache.handle_event(&event).unwrap());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let event = conn.poll_for_queued_event().unwrap().unwrap();
+        assert!(cache.handle_event(&event).unwrap());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.mapping().logical_button(1), LogicalButton::Secondary);
+        assert_eq!(cache.mapping().logical_button(3), LogicalButton::Primary);
+    }
+}
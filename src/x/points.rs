@@ -0,0 +1,247 @@
+//! Helpers for the requests that take a list of points.
+
+use super::{CoordMode, Drawable, FillPoly, Gcontext, Point, PolyLine, PolyPoint, PolyShape};
+
+/// A list of points with its coordinate mode.
+///
+/// Drawing long paths with relative coordinates does not change the size of the request,
+/// but the small deltas compress much better when the connection is compressed or
+/// tunneled (e.g. over SSH).
+#[derive(Copy, Clone, Debug)]
+pub enum Points<'a> {
+    /// Coordinates relative to the origin of the drawable ([CoordMode::Origin]).
+    Absolute(&'a [Point]),
+    /// Coordinates after the first one are relative to the previous point ([CoordMode::Previous]).
+    Relative(&'a [Point]),
+}
+
+impl<'a> Points<'a> {
+    /// The coordinate mode to use in the request.
+    pub fn coordinate_mode(&self) -> CoordMode {
+        match self {
+            Points::Absolute(_) => CoordMode::Origin,
+            Points::Relative(_) => CoordMode::Previous,
+        }
+    }
+
+    /// The points as sent in the request.
+    pub fn as_slice(&self) -> &'a [Point] {
+        match self {
+            Points::Absolute(points) => points,
+            Points::Relative(points) => points,
+        }
+    }
+
+    /// Converts absolute points to points relative to the previous one.
+    ///
+    /// The first point is kept unchanged.
+    /// Computations wrap around like they do in the server, so the conversion
+    /// always round-trips with [Points::absolute_from_relative].
+    pub fn relative_from_absolute(points: &[Point]) -> Vec<Point> {
+        let mut prev = Point { x: 0, y: 0 };
+        points
+            .iter()
+            .map(|p| {
+                let rel = Point {
+                    x: p.x.wrapping_sub(prev.x),
+                    y: p.y.wrapping_sub(prev.y),
+                };
+                prev = *p;
+                rel
+            })
+            .collect()
+    }
+
+    /// Converts points relative to the previous one to absolute points.
+    pub fn absolute_from_relative(points: &[Point]) -> Vec<Point> {
+        let mut cur = Point { x: 0, y: 0 };
+        points
+            .iter()
+            .map(|p| {
+                cur = Point {
+                    x: cur.x.wrapping_add(p.x),
+                    y: cur.y.wrapping_add(p.y),
+                };
+                cur
+            })
+            .collect()
+    }
+
+    /// Checks whether the polygon described by `points` is convex.
+    ///
+    /// Degenerate polygons (less than 3 points, or all points aligned) are convex.
+    pub fn is_convex(&self) -> bool {
+        let owned;
+        let points = match self {
+            Points::Absolute(points) => *points,
+            Points::Relative(points) => {
+                owned = Self::absolute_from_relative(points);
+                &owned
+            }
+        };
+        let n = points.len();
+        if n < 3 {
+            return true;
+        }
+
+        let mut sign = 0i64;
+        let mut turns = 0f64;
+        for i in 0..n {
+            let (a, b, c) = (points[i], points[(i + 1) % n], points[(i + 2) % n]);
+            let (abx, aby) = (b.x as i64 - a.x as i64, b.y as i64 - a.y as i64);
+            let (bcx, bcy) = (c.x as i64 - b.x as i64, c.y as i64 - b.y as i64);
+            let cross = abx * bcy - aby * bcx;
+            if cross != 0 {
+                if sign != 0 && cross.signum() != sign {
+                    return false;
+                }
+                sign = cross.signum();
+            }
+            let dot = abx * bcx + aby * bcy;
+            turns += (cross as f64).atan2(dot as f64);
+        }
+        // a self-intersecting polygon (e.g. a star) turns more than once
+        sign == 0 || turns.abs() < 3.0 * std::f64::consts::PI
+    }
+}
+
+impl<'a> PolyPoint<'a> {
+    /// Builds a `PolyPoint` request whose coordinate mode matches `points`.
+    pub fn with_points(drawable: Drawable, gc: Gcontext, points: Points<'a>) -> PolyPoint<'a> {
+        PolyPoint {
+            coordinate_mode: points.coordinate_mode(),
+            drawable,
+            gc,
+            points: points.as_slice(),
+        }
+    }
+}
+
+impl<'a> PolyLine<'a> {
+    /// Builds a `PolyLine` request whose coordinate mode matches `points`.
+    pub fn with_points(drawable: Drawable, gc: Gcontext, points: Points<'a>) -> PolyLine<'a> {
+        PolyLine {
+            coordinate_mode: points.coordinate_mode(),
+            drawable,
+            gc,
+            points: points.as_slice(),
+        }
+    }
+}
+
+impl<'a> FillPoly<'a> {
+    /// Builds a `FillPoly` request whose coordinate mode matches `points`.
+    ///
+    /// `shape` is a hint that allows the server to use a faster algorithm.
+    /// The server does not check the hint: if [PolyShape::Convex] (or [PolyShape::Nonconvex])
+    /// is given for a polygon that is not convex (or that is self-intersecting), the server
+    /// does not return an error, but the fill result is undefined.
+    /// Use [PolyShape::Complex] if unsure, or check with [Points::is_convex].
+    ///
+    /// # Panics
+    /// In debug builds, panics if `shape` is [PolyShape::Convex] and the polygon is not convex.
+    pub fn with_points(
+        drawable: Drawable,
+        gc: Gcontext,
+        shape: PolyShape,
+        points: Points<'a>,
+    ) -> FillPoly<'a> {
+        debug_assert!(
+            shape != PolyShape::Convex || points.is_convex(),
+            "polygon declared convex is not convex"
+        );
+        FillPoly {
+            drawable,
+            gc,
+            shape,
+            coordinate_mode: points.coordinate_mode(),
+            points: points.as_slice(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pts(coords: &[(i16, i16)]) -> Vec<Point> {
+        coords.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    fn coords(points: &[Point]) -> Vec<(i16, i16)> {
+        points.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn test_relative_from_absolute() {
+        let abs = pts(&[(10, 10), (20, 15), (20, 5), (-3, 0)]);
+        let rel = Points::relative_from_absolute(&abs);
+        assert_eq!(coords(&rel), [(10, 10), (10, 5), (0, -10), (-23, -5)]);
+        assert_eq!(coords(&Points::absolute_from_relative(&rel)), coords(&abs));
+
+        // wrapping deltas round-trip
+        let abs = pts(&[(i16::MIN, i16::MAX), (i16::MAX, i16::MIN), (0, 0)]);
+        let rel = Points::relative_from_absolute(&abs);
+        assert_eq!(coords(&Points::absolute_from_relative(&rel)), coords(&abs));
+
+        assert!(Points::relative_from_absolute(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_is_convex() {
+        let square = pts(&[(0, 0), (10, 0), (10, 10), (0, 10)]);
+        assert!(Points::Absolute(&square).is_convex());
+        let rel = Points::relative_from_absolute(&square);
+        assert!(Points::Relative(&rel).is_convex());
+
+        // concave "arrow"
+        let arrow = pts(&[(0, 0), (10, 5), (0, 10), (3, 5)]);
+        assert!(!Points::Absolute(&arrow).is_convex());
+
+        // self-intersecting star: every turn is in the same direction
+        let star = pts(&[(0, -10), (6, 8), (-9, -3), (9, -3), (-6, 8)]);
+        assert!(!Points::Absolute(&star).is_convex());
+
+        // degenerate
+        assert!(Points::Absolute(&pts(&[(0, 0), (1, 1)])).is_convex());
+        assert!(Points::Absolute(&pts(&[(0, 0), (1, 1), (2, 2)])).is_convex());
+    }
+
+    #[test]
+    fn test_with_points() {
+        use crate::XidNew;
+
+        let window = unsafe { crate::x::Window::new(1) };
+        let gc = unsafe { Gcontext::new(2) };
+        let rel = Points::relative_from_absolute(&pts(&[(0, 0), (10, 0), (10, 10)]));
+
+        let req = PolyLine::with_points(Drawable::Window(window), gc, Points::Relative(&rel));
+        assert_eq!(req.coordinate_mode, CoordMode::Previous);
+        assert_eq!(coords(req.points), coords(&rel));
+
+        let req = FillPoly::with_points(
+            Drawable::Window(window),
+            gc,
+            PolyShape::Convex,
+            Points::Relative(&rel),
+        );
+        assert_eq!(req.coordinate_mode, CoordMode::Previous);
+    }
+
+    #[test]
+    #[should_panic(expected = "polygon declared convex is not convex")]
+    #[cfg(debug_assertions)]
+    fn test_fill_poly_convex_misuse() {
+        use crate::XidNew;
+
+        let window = unsafe { crate::x::Window::new(1) };
+        let gc = unsafe { Gcontext::new(2) };
+        let arrow = pts(&[(0, 0), (10, 5), (0, 10), (3, 5)]);
+        FillPoly::with_points(
+            Drawable::Window(window),
+            gc,
+            PolyShape::Convex,
+            Points::Absolute(&arrow),
+        );
+    }
+}
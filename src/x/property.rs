@@ -0,0 +1,538 @@
+//! Reading of whole properties, and writing of string properties.
+
+use super::{
+    Atom, ChangeProperty, Error, GetProperty, InternAtom, PropMode, Window, ATOM_ANY, ATOM_NONE,
+    ATOM_STRING,
+};
+use crate::base::{Connection, PropertyError, Result, ResultExt, Xid};
+use crate::error::ProtocolError;
+use crate::lat1_str::Lat1Str;
+
+use std::convert::TryInto;
+
+/// Property length requested by the first `GetProperty` of [get_full_property], in 32-bit
+/// units. The rest of a longer property is requested at once by a second request.
+const FIRST_PROP_CHUNK: u32 = 0x10000;
+
+/// A property fetched by [get_full_property].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PropertyValue {
+    /// The property is not set.
+    Missing,
+    /// The property is set with another type than the requested one.
+    ///
+    /// The server does not return the value in that case.
+    TypeMismatch {
+        actual_type: Atom,
+        actual_format: u8,
+    },
+    /// The value of the property, of the requested type (or of any type for `ATOM_ANY`).
+    ///
+    /// `data` holds the items of `format` bits in native byte order.
+    Value {
+        r#type: Atom,
+        format: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl PropertyValue {
+    /// The bytes of the value, with the items in native byte order, whatever the format.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            PropertyValue::Value { data, .. } => Some(data),
+            _ => None,
+        }
+    }
+
+    /// The 16-bit items of the value, if it is a value of format 16.
+    pub fn data16(&self) -> Option<Vec<u16>> {
+        match self {
+            PropertyValue::Value {
+                format: 16, data, ..
+            } => Some(
+                data.chunks_exact(2)
+                    .map(|item| u16::from_ne_bytes(item.try_into().unwrap()))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// The 32-bit items of the value, if it is a value of format 32.
+    pub fn data32(&self) -> Option<Vec<u32>> {
+        match self {
+            PropertyValue::Value {
+                format: 32, data, ..
+            } => Some(
+                data.chunks_exact(4)
+                    .map(|item| u32::from_ne_bytes(item.try_into().unwrap()))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// The 32-bit items of the value for the getters of `property`.
+    ///
+    /// Returns `None` if the property is not set, and a [PropertyError] if it is set
+    /// with another type or format.
+    pub(crate) fn expect_data32(&self, property: &'static str) -> Result<Option<Vec<u32>>> {
+        if self.expect_format(32, property)? {
+            Ok(self.data32())
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The bytes of the value for the getters of `property`, as
+    /// [expect_data32](PropertyValue::expect_data32) for the format 8.
+    pub(crate) fn expect_data8(&self, property: &'static str) -> Result<Option<&[u8]>> {
+        if self.expect_format(8, property)? {
+            Ok(self.bytes())
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether the property is set, with the format `expected`.
+    fn expect_format(&self, expected: u8, property: &'static str) -> Result<bool> {
+        let (actual_type, actual_format) = match *self {
+            PropertyValue::Missing => return Ok(false),
+            PropertyValue::Value { format, .. } if format == expected => return Ok(true),
+            PropertyValue::TypeMismatch {
+                actual_type,
+                actual_format,
+            } => (actual_type, actual_format),
+            PropertyValue::Value { r#type, format, .. } => (r#type, format),
+        };
+        Err(PropertyError {
+            property,
+            actual_type,
+            actual_format,
+        }
+        .into())
+    }
+}
+
+/// Fetches the whole value of `property` on `window`, of type `r#type`.
+///
+/// A long property is fetched with a second `GetProperty` request for the rest of its
+/// value. If the property is replaced with another type or format, or shortened, between
+/// the requests, it is fetched again from the start, and if it is deleted,
+/// [PropertyValue::Missing] is returned. Unlike a bare
+/// `GetProperty` reply, which is empty both when the property is not set and when it is
+/// set with another type, the result tells the cases apart.
+pub fn get_full_property(
+    conn: &Connection,
+    window: Window,
+    property: Atom,
+    r#type: Atom,
+) -> Result<PropertyValue> {
+    let detail = || {
+        format!(
+            "property {:#x} of window {:#x}",
+            property.resource_id(),
+            window.resource_id()
+        )
+    };
+
+    let mut data = Vec::new();
+    // the type and format of the first reply
+    let mut first = None;
+    let mut long_length = FIRST_PROP_CHUNK;
+    loop {
+        let cookie = conn.send_request(&GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type,
+            long_offset: (data.len() / 4) as u32,
+            long_length,
+        });
+        let reply = match conn.wait_for_reply(cookie) {
+            // the property was shortened below the offset since the previous request
+            Err(crate::Error::Protocol(ProtocolError::X(Error::Value(_), _)))
+                if first.is_some() =>
+            {
+                data.clear();
+                first = None;
+                long_length = FIRST_PROP_CHUNK;
+                continue;
+            }
+            res => res.context("GetProperty", detail)?,
+        };
+        if reply.r#type() == ATOM_NONE {
+            return Ok(PropertyValue::Missing);
+        }
+        if r#type != ATOM_ANY && reply.r#type() != r#type {
+            return Ok(PropertyValue::TypeMismatch {
+                actual_type: reply.r#type(),
+                actual_format: reply.format(),
+            });
+        }
+        match first {
+            None => first = Some((reply.r#type(), reply.format())),
+            // the property was replaced since the previous request
+            Some(prev) if prev != (reply.r#type(), reply.format()) => {
+                data.clear();
+                first = None;
+                long_length = FIRST_PROP_CHUNK;
+                continue;
+            }
+            Some(_) => {}
+        }
+        let len = data.len();
+        match reply.format() {
+            8 => data.extend_from_slice(reply.value::<u8>()),
+            16 => data.extend(reply.value::<u16>().iter().flat_map(|v| v.to_ne_bytes())),
+            32 => data.extend(reply.value::<u32>().iter().flat_map(|v| v.to_ne_bytes())),
+            _ => {}
+        }
+        if reply.bytes_after() == 0 || data.len() == len {
+            return Ok(PropertyValue::Value {
+                r#type: reply.r#type(),
+                format: reply.format(),
+                data,
+            });
+        }
+        long_length = reply.bytes_after().div_ceil(4).max(1);
+    }
+}
+
+/// A string value of a property, whose encoding gives the type of the property.
+#[derive(Copy, Clone, Debug)]
+pub enum PropertyString<'a> {
+    /// A Latin-1 string, of type `STRING` (e.g. for `WM_NAME`).
+    Latin1(&'a Lat1Str),
+    /// A UTF-8 string, of type `UTF8_STRING` (e.g. for `_NET_WM_NAME`).
+    Utf8(&'a str),
+}
+
+/// Sets `property` of `window` to the string `value`, or prepends or appends `value` to it,
+/// depending on `mode`.
+///
+/// The type of the property is `STRING` or `UTF8_STRING`, depending on the encoding of
+/// `value`. The other properties are set with [ChangeProperty], which infers the format
+/// from the type of the items of its `data`:
+///
+/// ```no_run
+/// # use xcb::x;
+/// # fn set_pid(conn: &xcb::Connection, window: x::Window, net_wm_pid: x::Atom) -> xcb::Result<()> {
+/// conn.send_and_check_request(&x::ChangeProperty {
+///     mode: x::PropMode::Replace,
+///     window,
+///     property: net_wm_pid,
+///     r#type: x::ATOM_CARDINAL,
+///     data: &[std::process::id()],
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn change_string_property(
+    conn: &Connection,
+    mode: PropMode,
+    window: Window,
+    property: Atom,
+    value: PropertyString,
+) -> Result<()> {
+    let (r#type, data) = match value {
+        PropertyString::Latin1(value) => (ATOM_STRING, value.as_bytes()),
+        PropertyString::Utf8(value) => {
+            let cookie = conn.send_request(&InternAtom {
+                only_if_exists: false,
+                name: b"UTF8_STRING",
+            });
+            let utf8_string = conn
+                .wait_for_reply(cookie)
+                .context("InternAtom", || "UTF8_STRING")?
+                .atom();
+            (utf8_string, value.as_bytes())
+        }
+    };
+    conn.send_and_check_request(&ChangeProperty {
+        mode,
+        window,
+        property,
+        r#type,
+        data,
+    })
+    .context("ChangeProperty", || {
+        format!(
+            "property {:#x} of window {:#x}",
+            property.resource_id(),
+            window.resource_id()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_connection(r#type: Atom, format: u8, value: Vec<u8>) -> Connection {
+        let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        mock_changing_property(vec![(r#type, format, value)], requests)
+    }
+
+    #[test]
+    fn test_full_property_type_mismatch() {
+        use crate::x::{ATOM_ATOM, ATOM_CARDINAL, ATOM_WM_NAME};
+        use crate::XidNew;
+
+        let window = unsafe { Window::new(0x200001) };
+        let value: Vec<u8> = [1u32, 2, 3].iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let conn = mock_connection(ATOM_CARDINAL, 32, value);
+
+        let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_CARDINAL).unwrap();
+        assert_eq!(prop.data32(), Some(vec![1, 2, 3]));
+        let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_ANY).unwrap();
+        assert_eq!(prop.data32(), Some(vec![1, 2, 3]));
+
+        let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_ATOM).unwrap();
+        assert_eq!(
+            prop,
+            PropertyValue::TypeMismatch {
+                actual_type: ATOM_CARDINAL,
+                actual_format: 32,
+            }
+        );
+        assert_eq!(prop.data32(), None);
+        let err = prop.expect_data32("WM_NAME").unwrap_err();
+        match err {
+            crate::Error::Property(err) => {
+                assert_eq!(
+                    err,
+                    PropertyError {
+                        property: "WM_NAME",
+                        actual_type: ATOM_CARDINAL,
+                        actual_format: 32,
+                    }
+                );
+                assert!(err.to_string().contains("WM_NAME"));
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        let conn = mock_connection(ATOM_NONE, 0, vec![]);
+        let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_ATOM).unwrap();
+        assert_eq!(prop, PropertyValue::Missing);
+        assert!(prop.expect_data32("WM_NAME").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_full_property_chunks() {
+        use crate::x::{ATOM_STRING, ATOM_WM_NAME};
+        use crate::XidNew;
+
+        let window = unsafe { Window::new(0x200001) };
+        // longer than a chunk, and not a multiple of 4 bytes
+        let value: Vec<u8> = (0..FIRST_PROP_CHUNK as usize * 4 + 7)
+            .map(|i| i as u8)
+            .collect();
+        let conn = mock_connection(ATOM_STRING, 8, value.clone());
+
+        let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_STRING).unwrap();
+        assert_eq!(
+            prop,
+            PropertyValue::Value {
+                r#type: ATOM_STRING,
+                format: 8,
+                data: value,
+            }
+        );
+        // not of format 32
+        assert_eq!(prop.data32(), None);
+        assert!(prop.expect_data32("WM_NAME").is_err());
+    }
+
+    /// A connection to a server where the property is in the state of `states` of the index of
+    /// the `GetProperty` request, or in the last state. `requests` counts the requests.
+    fn mock_changing_property(
+        states: Vec<(Atom, u8, Vec<u8>)>,
+        requests: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    ) -> Connection {
+        use crate::mock;
+        use std::sync::atomic::Ordering;
+
+        mock::connect(move |req| match req.opcode {
+            // GetProperty
+            20 => {
+                let index = requests.fetch_add(1, Ordering::SeqCst);
+                let (r#type, format, value) = &states[index.min(states.len() - 1)];
+                let (r#type, format) = (*r#type, *format);
+                let requested = req.u32_at(12);
+                let mut body = vec![0u8; 24];
+                body[0..4].copy_from_slice(&r#type.resource_id().to_ne_bytes());
+                if r#type == ATOM_NONE {
+                    return vec![mock::reply(req.seq, 0, &body)];
+                }
+                if requested != 0 && requested != r#type.resource_id() {
+                    // the server returns the size of the property, but no value
+                    body[4..8].copy_from_slice(&(value.len() as u32).to_ne_bytes());
+                    return vec![mock::reply(req.seq, format, &body)];
+                }
+                let offset = req.u32_at(16) as usize * 4;
+                if offset > value.len() {
+                    const BAD_VALUE: u8 = 2;
+                    return vec![mock::error(req.seq, BAD_VALUE, req.u32_at(16), 20)];
+                }
+                let len = (req.u32_at(20) as usize * 4).min(value.len() - offset);
+                let after = value.len() - offset - len;
+                body[4..8].copy_from_slice(&(after as u32).to_ne_bytes());
+                let items = len / (format.max(8) as usize / 8);
+                body[8..12].copy_from_slice(&(items as u32).to_ne_bytes());
+                body.extend_from_slice(&value[offset..offset + len]);
+                vec![mock::reply(req.seq, format, &body)]
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    #[test]
+    fn test_full_property_second_request() {
+        use crate::x::{ATOM_CARDINAL, ATOM_WM_NAME};
+        use crate::XidNew;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let window = unsafe { Window::new(0x200001) };
+        // several times longer than the first request
+        let value: Vec<u16> = (0..FIRST_PROP_CHUNK * 5).map(|i| i as u16).collect();
+        let bytes: Vec<u8> = value.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let conn =
+            mock_changing_property(vec![(ATOM_CARDINAL, 16, bytes.clone())], requests.clone());
+
+        let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_CARDINAL).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(prop.bytes(), Some(&bytes[..]));
+        assert_eq!(prop.data16(), Some(value));
+        assert_eq!(prop.data32(), None);
+        assert_eq!(PropertyValue::Missing.bytes(), None);
+    }
+
+    #[test]
+    fn test_full_property_changed() {
+        use crate::x::{ATOM_ANY, ATOM_CARDINAL, ATOM_STRING, ATOM_WM_NAME};
+        use crate::XidNew;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let window = unsafe { Window::new(0x200001) };
+        let long = vec![1u8; FIRST_PROP_CHUNK as usize * 4 + 8];
+        let short = vec![2u8; 8];
+        let get = |states| {
+            let requests = Arc::new(AtomicUsize::new(0));
+            let conn = mock_changing_property(states, requests.clone());
+            let prop = get_full_property(&conn, window, ATOM_WM_NAME, ATOM_ANY).unwrap();
+            (prop, requests.load(Ordering::SeqCst))
+        };
+
+        // deleted after the first request
+        assert_eq!(
+            get(vec![(ATOM_STRING, 8, long.clone()), (ATOM_NONE, 0, vec![])]),
+            (PropertyValue::Missing, 2)
+        );
+
+        // shortened below the offset of the second request: fetched again
+        let expected = PropertyValue::Value {
+            r#type: ATOM_STRING,
+            format: 8,
+            data: short.clone(),
+        };
+        assert_eq!(
+            get(vec![
+                (ATOM_STRING, 8, long.clone()),
+                (ATOM_STRING, 8, short.clone())
+            ]),
+            (expected, 3)
+        );
+
+        // replaced with another type: fetched again
+        let expected = PropertyValue::Value {
+            r#type: ATOM_CARDINAL,
+            format: 32,
+            data: short.clone(),
+        };
+        assert_eq!(
+            get(vec![
+                (ATOM_STRING, 8, long.clone()),
+                (ATOM_CARDINAL, 32, long.clone()),
+                (ATOM_CARDINAL, 32, short.clone())
+            ]),
+            (expected, 3)
+        );
+    }
+
+    #[test]
+    fn test_change_string_property() {
+        use crate::mock;
+        use crate::x::ATOM_WM_NAME;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        const UTF8_STRING: u32 = 301;
+
+        // mode, type, format and data of the ChangeProperty requests
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let changes = changes.clone();
+            mock::connect(move |req| match req.opcode {
+                16 => {
+                    assert_eq!(&req.data[8..19], b"UTF8_STRING");
+                    vec![mock::reply(req.seq, 0, &UTF8_STRING.to_ne_bytes())]
+                }
+                18 => {
+                    assert_eq!(req.u32_at(4), 0x200001);
+                    assert_eq!(req.u32_at(8), ATOM_WM_NAME.resource_id());
+                    let len = req.u32_at(20) as usize;
+                    changes.lock().unwrap().push((
+                        req.data[1],
+                        req.u32_at(12),
+                        req.data[16],
+                        req.data[24..24 + len].to_vec(),
+                    ));
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let window = unsafe { Window::new(0x200001) };
+
+        change_string_property(
+            &conn,
+            PropMode::Replace,
+            window,
+            ATOM_WM_NAME,
+            PropertyString::Latin1(Lat1Str::from_bytes(b"caf\xe9")),
+        )
+        .unwrap();
+        change_string_property(
+            &conn,
+            PropMode::Append,
+            window,
+            ATOM_WM_NAME,
+            PropertyString::Utf8("caf\u{e9} \u{2615}"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [
+                (
+                    PropMode::Replace as u8,
+                    ATOM_STRING.resource_id(),
+                    8,
+                    b"caf\xe9".to_vec()
+                ),
+                (
+                    PropMode::Append as u8,
+                    UTF8_STRING,
+                    8,
+                    "caf\u{e9} \u{2615}".as_bytes().to_vec()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,777 @@
+//! Window creation, introspection and save-set helpers.
+
+use super::{
+    Atom, BackingStore, ChangeSaveSet, ChangeWindowAttributes, Colormap, ConfigWindow,
+    ConfigureWindow, CreateWindow, Cursor, Cw, EventMask, GetGeometry, GetProperty,
+    GetWindowAttributes, Gravity, InternAtom, MapState, Pixmap, Rectangle, ReparentWindow, SetMode,
+    StackMode, Visualid, Window, WindowClass, ATOM_ANY, ATOM_NONE, ATOM_WM_CLASS, ATOM_WM_NAME,
+    COPY_FROM_PARENT,
+};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::lat1_str::Lat1Str;
+
+/// Maximum property length requested by [window_summary], in 32-bit units.
+const MAX_PROP_LEN: u32 = 1024;
+
+/// The content of the `WM_CLASS` property.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WmClass {
+    /// The instance name (the first string).
+    pub instance: String,
+    /// The class name (the second string).
+    pub class: String,
+}
+
+impl WmClass {
+    /// Parses the raw value of a `WM_CLASS` property.
+    ///
+    /// `WM_CLASS` should contain two consecutive NUL-terminated Latin-1 strings, but some
+    /// clients omit the final NUL, or set only one string. The missing strings are empty.
+    /// Returns `None` if `data` is empty.
+    pub fn parse(data: &[u8]) -> Option<WmClass> {
+        if data.is_empty() {
+            return None;
+        }
+        let mut strings = data
+            .split(|b| *b == 0)
+            .map(|s| Lat1Str::from_bytes(s).to_utf8().into_owned());
+        let instance = strings.next().unwrap_or_default();
+        let class = strings.next().unwrap_or_default();
+        Some(WmClass { instance, class })
+    }
+}
+
+/// A summary of the state of a window, as returned by [window_summary].
+#[derive(Clone, Debug)]
+pub struct WindowSummary {
+    pub window: Window,
+    pub class: WindowClass,
+    pub map_state: MapState,
+    pub override_redirect: bool,
+    pub backing_store: BackingStore,
+    pub backing_planes: u32,
+    pub backing_pixel: u32,
+    pub save_under: bool,
+    pub root: Window,
+    pub depth: u8,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub border_width: u16,
+    /// The `WM_CLASS` property, if set.
+    pub wm_class: Option<WmClass>,
+    /// The `WM_NAME` property, if set and of type `STRING`.
+    pub wm_name: Option<String>,
+    /// The `_NET_WM_NAME` property, if set.
+    pub net_wm_name: Option<String>,
+}
+
+/// Fetches the attributes, the geometry, and the names of `window`.
+///
+/// All the requests are pipelined, which makes this function significantly faster
+/// than fetching each piece one after the other.
+/// It is mainly intended for debugging tools and task bars.
+///
+/// Errors are returned with the name of the failed request as context (see [crate::Error::chain]).
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, screen_num) = xcb::Connection::connect(None)?;
+/// # let window = conn.get_setup().roots().nth(screen_num as usize).unwrap().root();
+/// let summary = xcb::x::window_summary(&conn, window)?;
+/// if let Some(wm_class) = &summary.wm_class {
+///     println!("{}: {}x{}", wm_class.class, summary.width, summary.height);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn window_summary(conn: &Connection, window: Window) -> Result<WindowSummary> {
+    let detail = || format!("for window {:#x}", window.resource_id());
+    let get_property = |property: Atom| {
+        conn.send_request(&GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type: ATOM_ANY,
+            long_offset: 0,
+            long_length: MAX_PROP_LEN,
+        })
+    };
+
+    let attrs = conn.send_request(&GetWindowAttributes { window });
+    let geom = conn.send_request(&GetGeometry {
+        drawable: super::Drawable::Window(window),
+    });
+    let net_wm_name_atom = conn.send_request(&InternAtom {
+        only_if_exists: true,
+        name: b"_NET_WM_NAME",
+    });
+    let wm_class = get_property(ATOM_WM_CLASS);
+    let wm_name = get_property(ATOM_WM_NAME);
+
+    let net_wm_name_atom = conn
+        .wait_for_reply(net_wm_name_atom)
+        .context("InternAtom", || "_NET_WM_NAME")?
+        .atom();
+    let net_wm_name = if net_wm_name_atom != ATOM_NONE {
+        Some(get_property(net_wm_name_atom))
+    } else {
+        None
+    };
+
+    let attrs = conn
+        .wait_for_reply(attrs)
+        .context("GetWindowAttributes", detail)?;
+    let geom = conn.wait_for_reply(geom).context("GetGeometry", detail)?;
+
+    let wm_class = conn
+        .wait_for_reply(wm_class)
+        .context("GetProperty(WM_CLASS)", detail)?;
+    let wm_class = WmClass::parse(wm_class.value());
+
+    let wm_name = conn
+        .wait_for_reply(wm_name)
+        .context("GetProperty(WM_NAME)", detail)?;
+    let wm_name = if wm_name.format() == 8 && wm_name.r#type() == super::ATOM_STRING {
+        Some(Lat1Str::from_bytes(wm_name.value()).to_utf8().into_owned())
+    } else {
+        None
+    };
+
+    let net_wm_name = if let Some(cookie) = net_wm_name {
+        let reply = conn
+            .wait_for_reply(cookie)
+            .context("GetProperty(_NET_WM_NAME)", detail)?;
+        if reply.format() == 8 && reply.r#type() != ATOM_NONE {
+            Some(String::from_utf8_lossy(reply.value()).into_owned())
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    Ok(WindowSummary {
+        window,
+        class: attrs.class(),
+        map_state: attrs.map_state(),
+        override_redirect: attrs.override_redirect(),
+        backing_store: attrs.backing_store(),
+        backing_planes: attrs.backing_planes(),
+        backing_pixel: attrs.backing_pixel(),
+        save_under: attrs.save_under(),
+        root: geom.root(),
+        depth: geom.depth(),
+        x: geom.x(),
+        y: geom.y(),
+        width: geom.width(),
+        height: geom.height(),
+        border_width: geom.border_width(),
+        wm_class,
+        wm_name,
+        net_wm_name,
+    })
+}
+
+/// Adds `window` to the save-set of the client (`SetMode::Insert`), or removes it
+/// (`SetMode::Delete`).
+///
+/// When the connection of the client closes, the windows of its save-set that are inferiors
+/// of windows it created are reparented to their closest ancestor that survives, and mapped.
+/// This is how window managers and embedders (see [crate::embed::Embedder]) make sure
+/// that the windows of other clients are not destroyed with theirs if they crash.
+///
+/// `window` must not be created by the client itself.
+pub fn change_save_set(conn: &Connection, mode: SetMode, window: Window) -> Result<()> {
+    conn.send_and_check_request(&ChangeSaveSet { mode, window })
+        .context("ChangeSaveSet", || {
+            format!("for window {:#x}", window.resource_id())
+        })
+}
+
+/// Reparents `window` into `parent`, at position (`x`, `y`) relative to the origin of `parent`.
+///
+/// The position is the one of the outer corner of the border of `window`. A mapped window
+/// is unmapped before it is reparented, and mapped again afterwards. See
+/// [crate::reparent::safe_reparent] for a version that reports the resulting events.
+pub fn reparent_window(
+    conn: &Connection,
+    window: Window,
+    parent: Window,
+    x: i16,
+    y: i16,
+) -> Result<()> {
+    conn.send_and_check_request(&ReparentWindow {
+        window,
+        parent,
+        x,
+        y,
+    })
+    .context("ReparentWindow", || {
+        format!(
+            "of window {:#x} into {:#x}",
+            window.resource_id(),
+            parent.resource_id()
+        )
+    })
+}
+
+/// Sets `value` in the sorted value list `values`, replacing the value of the same variant.
+///
+/// The value list enums are ordered by variant first, in the order of their mask bits.
+pub(super) fn set_value<T: Ord>(values: &mut Vec<T>, value: T) {
+    let variant = std::mem::discriminant(&value);
+    values.retain(|v| std::mem::discriminant(v) != variant);
+    let index = values.partition_point(|v| *v < value);
+    values.insert(index, value);
+}
+
+/// The attributes of a window, for [create_window] and [change_window_attributes].
+///
+/// The setters can be called in any order: the values are kept in the order of the [Cw]
+/// enum, which the value lists of `CreateWindow` and `ChangeWindowAttributes` require,
+/// and a value set twice replaces the previous one.
+///
+/// # Example
+/// ```no_run
+/// # use xcb::x;
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, screen_num) = xcb::Connection::connect(None)?;
+/// # let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+/// let attributes = x::WindowAttributes::new()
+///     .event_mask(x::EventMask::EXPOSURE | x::EventMask::KEY_PRESS)
+///     .back_pixel(screen.white_pixel());
+/// let window = x::create_window(
+///     &conn,
+///     screen.root(),
+///     x::Rectangle { x: 0, y: 0, width: 300, height: 200 },
+///     0,
+///     x::WindowClass::InputOutput,
+///     None,
+///     &attributes,
+/// )?;
+/// conn.send_request(&x::CreateWindow {
+///     depth: x::COPY_FROM_PARENT as u8,
+///     wid: conn.generate_id(),
+///     parent: window,
+///     x: 10,
+///     y: 10,
+///     width: 100,
+///     height: 100,
+///     border_width: 1,
+///     class: x::WindowClass::InputOutput,
+///     visual: screen.root_visual(),
+///     value_list: attributes.value_list(),
+/// });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowAttributes {
+    values: Vec<Cw>,
+}
+
+impl WindowAttributes {
+    /// Attributes without value: the defaults of `CreateWindow`, or the current values for
+    /// `ChangeWindowAttributes`.
+    pub fn new() -> WindowAttributes {
+        WindowAttributes::default()
+    }
+
+    /// Sets `value`, replacing the previous value of the same attribute.
+    pub fn with(mut self, value: Cw) -> WindowAttributes {
+        set_value(&mut self.values, value);
+        self
+    }
+
+    pub fn back_pixmap(self, pixmap: Pixmap) -> WindowAttributes {
+        self.with(Cw::BackPixmap(pixmap))
+    }
+
+    pub fn back_pixel(self, pixel: u32) -> WindowAttributes {
+        self.with(Cw::BackPixel(pixel))
+    }
+
+    pub fn border_pixmap(self, pixmap: Pixmap) -> WindowAttributes {
+        self.with(Cw::BorderPixmap(pixmap))
+    }
+
+    pub fn border_pixel(self, pixel: u32) -> WindowAttributes {
+        self.with(Cw::BorderPixel(pixel))
+    }
+
+    pub fn bit_gravity(self, gravity: Gravity) -> WindowAttributes {
+        self.with(Cw::BitGravity(gravity))
+    }
+
+    pub fn win_gravity(self, gravity: Gravity) -> WindowAttributes {
+        self.with(Cw::WinGravity(gravity))
+    }
+
+    pub fn backing_store(self, backing_store: BackingStore) -> WindowAttributes {
+        self.with(Cw::BackingStore(backing_store))
+    }
+
+    pub fn backing_planes(self, planes: u32) -> WindowAttributes {
+        self.with(Cw::BackingPlanes(planes))
+    }
+
+    pub fn backing_pixel(self, pixel: u32) -> WindowAttributes {
+        self.with(Cw::BackingPixel(pixel))
+    }
+
+    pub fn override_redirect(self, override_redirect: bool) -> WindowAttributes {
+        self.with(Cw::OverrideRedirect(override_redirect))
+    }
+
+    pub fn save_under(self, save_under: bool) -> WindowAttributes {
+        self.with(Cw::SaveUnder(save_under))
+    }
+
+    pub fn event_mask(self, mask: EventMask) -> WindowAttributes {
+        self.with(Cw::EventMask(mask))
+    }
+
+    pub fn dont_propagate(self, mask: EventMask) -> WindowAttributes {
+        self.with(Cw::DontPropagate(mask))
+    }
+
+    pub fn colormap(self, colormap: Colormap) -> WindowAttributes {
+        self.with(Cw::Colormap(colormap))
+    }
+
+    pub fn cursor(self, cursor: Cursor) -> WindowAttributes {
+        self.with(Cw::Cursor(cursor))
+    }
+
+    /// The value list of `CreateWindow` or `ChangeWindowAttributes`.
+    pub fn value_list(&self) -> &[Cw] {
+        &self.values
+    }
+}
+
+/// Creates a window, child of `parent`, with `geometry` relative to the inside of the
+/// border of `parent`, and returns it. The window is not mapped.
+///
+/// `visual` is the depth and the visual of the window, `None` to copy them from `parent`
+/// (and for `WindowClass::InputOnly` windows).
+pub fn create_window(
+    conn: &Connection,
+    parent: Window,
+    geometry: Rectangle,
+    border_width: u16,
+    class: WindowClass,
+    visual: Option<(u8, Visualid)>,
+    attributes: &WindowAttributes,
+) -> Result<Window> {
+    let (depth, visual) = visual.unwrap_or((COPY_FROM_PARENT as u8, COPY_FROM_PARENT));
+    let window = conn.generate_id();
+    conn.send_and_check_request(&CreateWindow {
+        depth,
+        wid: window,
+        parent,
+        x: geometry.x,
+        y: geometry.y,
+        width: geometry.width,
+        height: geometry.height,
+        border_width,
+        class,
+        visual,
+        value_list: attributes.value_list(),
+    })
+    .context("CreateWindow", || {
+        format!(
+            "{}x{}+{}+{} in window {:#x}",
+            geometry.width,
+            geometry.height,
+            geometry.x,
+            geometry.y,
+            parent.resource_id()
+        )
+    })?;
+    Ok(window)
+}
+
+/// Changes the `attributes` of `window`, the others are unchanged.
+pub fn change_window_attributes(
+    conn: &Connection,
+    window: Window,
+    attributes: &WindowAttributes,
+) -> Result<()> {
+    conn.send_and_check_request(&ChangeWindowAttributes {
+        window,
+        value_list: attributes.value_list(),
+    })
+    .context("ChangeWindowAttributes", || {
+        format!("of window {:#x}", window.resource_id())
+    })
+}
+
+/// The geometry and stacking changes of a window, for [configure_window].
+///
+/// As [WindowAttributes], the setters can be called in any order, and a value set twice
+/// replaces the previous one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigureValues {
+    values: Vec<ConfigWindow>,
+}
+
+impl ConfigureValues {
+    /// Values that change nothing.
+    pub fn new() -> ConfigureValues {
+        ConfigureValues::default()
+    }
+
+    /// Sets `value`, replacing the previous value of the same field.
+    pub fn with(mut self, value: ConfigWindow) -> ConfigureValues {
+        set_value(&mut self.values, value);
+        self
+    }
+
+    /// The position of the outer corner of the border, relative to the origin of the parent.
+    pub fn x(self, x: i32) -> ConfigureValues {
+        self.with(ConfigWindow::X(x))
+    }
+
+    pub fn y(self, y: i32) -> ConfigureValues {
+        self.with(ConfigWindow::Y(y))
+    }
+
+    /// The inside width, without the border.
+    pub fn width(self, width: u32) -> ConfigureValues {
+        self.with(ConfigWindow::Width(width))
+    }
+
+    pub fn height(self, height: u32) -> ConfigureValues {
+        self.with(ConfigWindow::Height(height))
+    }
+
+    pub fn border_width(self, border_width: u32) -> ConfigureValues {
+        self.with(ConfigWindow::BorderWidth(border_width))
+    }
+
+    /// The sibling the window is stacked relative to, with [ConfigureValues::stack_mode].
+    pub fn sibling(self, sibling: Window) -> ConfigureValues {
+        self.with(ConfigWindow::Sibling(sibling))
+    }
+
+    pub fn stack_mode(self, stack_mode: StackMode) -> ConfigureValues {
+        self.with(ConfigWindow::StackMode(stack_mode))
+    }
+
+    /// The value list of `ConfigureWindow`.
+    pub fn value_list(&self) -> &[ConfigWindow] {
+        &self.values
+    }
+}
+
+/// Changes the geometry or the stacking of `window`.
+///
+/// A window manager that redirects the substructure of the parent receives a
+/// `ConfigureRequest` instead, and decides of the change.
+pub fn configure_window(conn: &Connection, window: Window, values: &ConfigureValues) -> Result<()> {
+    conn.send_and_check_request(&ConfigureWindow {
+        window,
+        value_list: values.value_list(),
+    })
+    .context("ConfigureWindow", || {
+        format!("of window {:#x}", window.resource_id())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wm_class_parse() {
+        let wm_class = |instance: &str, class: &str| {
+            Some(WmClass {
+                instance: instance.to_string(),
+                class: class.to_string(),
+            })
+        };
+
+        assert_eq!(
+            WmClass::parse(b"xterm\0XTerm\0"),
+            wm_class("xterm", "XTerm")
+        );
+        // missing trailing NUL
+        assert_eq!(WmClass::parse(b"xterm\0XTerm"), wm_class("xterm", "XTerm"));
+        // a single string, with or without NUL
+        assert_eq!(WmClass::parse(b"xterm\0"), wm_class("xterm", ""));
+        assert_eq!(WmClass::parse(b"xterm"), wm_class("xterm", ""));
+        // empty instance
+        assert_eq!(WmClass::parse(b"\0XTerm\0"), wm_class("", "XTerm"));
+        // extra strings are ignored
+        assert_eq!(WmClass::parse(b"a\0b\0c\0"), wm_class("a", "b"));
+        // Latin-1
+        assert_eq!(
+            WmClass::parse(b"caf\xe9\0Caf\xe9\0"),
+            wm_class("café", "Café")
+        );
+        assert_eq!(WmClass::parse(b""), None);
+    }
+
+    #[test]
+    fn test_window_summary() {
+        use crate::mock::{self, MockRequest};
+        use crate::XidNew;
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        const NET_WM_NAME: u32 = 300;
+        const UTF8_STRING: u32 = 301;
+
+        fn property_reply(req: &MockRequest, r#type: u32, value: &[u8]) -> Vec<u8> {
+            let mut body = vec![0u8; 24];
+            body[0..4].copy_from_slice(&r#type.to_ne_bytes());
+            body[8..12].copy_from_slice(&(value.len() as u32).to_ne_bytes());
+            body.extend_from_slice(value);
+            mock::reply(req.seq, 8, &body)
+        }
+
+        // The server holds the replies until the 5 initial requests are received:
+        // window_summary would never return if the requests were not pipelined.
+        let mut pending = Vec::new();
+        let conn = mock::connect(move |req| {
+            let resp = match req.opcode {
+                // GetWindowAttributes
+                3 => {
+                    let mut body = vec![0u8; 36];
+                    body[4..6].copy_from_slice(&1u16.to_ne_bytes()); // InputOutput
+                    body[16] = 1; // save_under
+                    body[18] = 2; // Viewable
+                    mock::reply(req.seq, 2, &body) // backing store: Always
+                }
+                // GetGeometry
+                14 => {
+                    let mut body = vec![0u8; 24];
+                    body[0..4].copy_from_slice(&0x100u32.to_ne_bytes());
+                    body[4..6].copy_from_slice(&(-10i16).to_ne_bytes());
+                    body[6..8].copy_from_slice(&20i16.to_ne_bytes());
+                    body[8..10].copy_from_slice(&640u16.to_ne_bytes());
+                    body[10..12].copy_from_slice(&480u16.to_ne_bytes());
+                    body[12..14].copy_from_slice(&1u16.to_ne_bytes());
+                    mock::reply(req.seq, 24, &body)
+                }
+                // InternAtom
+                16 => mock::reply(req.seq, 0, &NET_WM_NAME.to_ne_bytes()),
+                // GetProperty
+                20 => match req.u32_at(8) {
+                    67 => property_reply(req, 31, b"xterm\0XTerm"),
+                    39 => property_reply(req, 31, b"caf\xe9"),
+                    NET_WM_NAME => property_reply(req, UTF8_STRING, "café ☕".as_bytes()),
+                    _ => property_reply(req, 0, b""),
+                },
+                _ => return mock::default_response(req),
+            };
+            if req.seq < 5 {
+                pending.push(resp);
+                vec![]
+            } else {
+                pending.push(resp);
+                std::mem::take(&mut pending)
+            }
+        });
+
+        let window = unsafe { Window::new(0x200) };
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            tx.send(window_summary(&conn, window).unwrap()).unwrap();
+        });
+        let summary = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("window_summary requests are not pipelined");
+
+        assert_eq!(summary.window, window);
+        assert_eq!(summary.class, WindowClass::InputOutput);
+        assert_eq!(summary.map_state, MapState::Viewable);
+        assert_eq!(summary.backing_store, BackingStore::Always);
+        assert!(summary.save_under);
+        assert!(!summary.override_redirect);
+        assert_eq!(summary.root.resource_id(), 0x100);
+        assert_eq!(summary.depth, 24);
+        assert_eq!((summary.x, summary.y), (-10, 20));
+        assert_eq!((summary.width, summary.height), (640, 480));
+        assert_eq!(summary.border_width, 1);
+        assert_eq!(
+            summary.wm_class,
+            Some(WmClass {
+                instance: "xterm".to_string(),
+                class: "XTerm".to_string(),
+            })
+        );
+        assert_eq!(summary.wm_name.as_deref(), Some("café"));
+        assert_eq!(summary.net_wm_name.as_deref(), Some("café ☕"));
+    }
+
+    #[test]
+    fn test_window_summary_error_context() {
+        use crate::{mock, Error, ErrorContext, ProtocolError};
+
+        // GetGeometry fails with BadDrawable
+        let conn = mock::connect(|req| match req.opcode {
+            14 => vec![mock::error(req.seq, 9, req.u32_at(4), 14)],
+            3 => vec![mock::reply(req.seq, 0, &[0; 36])],
+            16 => vec![mock::reply(req.seq, 0, &[])],
+            20 => vec![mock::reply(req.seq, 8, &[])],
+            _ => mock::default_response(req),
+        });
+
+        let window = unsafe { <Window as crate::XidNew>::new(0x400001) };
+        let err = window_summary(&conn, window).unwrap_err();
+
+        let chain: Vec<&ErrorContext> = err.chain().collect();
+        assert_eq!(
+            chain,
+            [&ErrorContext {
+                operation: "GetGeometry",
+                detail: "for window 0x400001".to_string(),
+            }]
+        );
+        assert!(matches!(
+            err.root_cause(),
+            Error::Protocol(ProtocolError::X(crate::x::Error::Drawable(_), _))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "GetGeometry for window 0x400001\ncaused by: xcb protocol error"
+        );
+    }
+
+    #[test]
+    fn test_window_attributes() {
+        use crate::mock;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let cursor = unsafe { Cursor::new(0x300) };
+        // set out of order, and twice for the event mask
+        let attributes = WindowAttributes::new()
+            .cursor(cursor)
+            .event_mask(EventMask::KEY_PRESS)
+            .override_redirect(true)
+            .back_pixel(0xff_ffff)
+            .event_mask(EventMask::EXPOSURE)
+            .bit_gravity(Gravity::NorthWest);
+        assert_eq!(
+            attributes.value_list(),
+            [
+                Cw::BackPixel(0xff_ffff),
+                Cw::BitGravity(Gravity::NorthWest),
+                Cw::OverrideRedirect(true),
+                Cw::EventMask(EventMask::EXPOSURE),
+                Cw::Cursor(cursor),
+            ]
+        );
+
+        // the value mask and the value lists of CreateWindow and ChangeWindowAttributes
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let requests = requests.clone();
+            mock::connect(move |req| match req.opcode {
+                1 | 2 => {
+                    requests.lock().unwrap().push(req.data.to_vec());
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let geometry = Rectangle {
+            x: 10,
+            y: 20,
+            width: 300,
+            height: 200,
+        };
+        let parent = unsafe { Window::new(mock::ROOT) };
+        let window = create_window(
+            &conn,
+            parent,
+            geometry,
+            1,
+            WindowClass::InputOutput,
+            None,
+            &attributes,
+        )
+        .unwrap();
+        change_window_attributes(&conn, window, &WindowAttributes::new().save_under(true)).unwrap();
+
+        let requests = requests.lock().unwrap();
+        let u32_at = |data: &[u8], off: usize| {
+            u32::from_ne_bytes([data[off], data[off + 1], data[off + 2], data[off + 3]])
+        };
+        let create = &requests[0];
+        assert_eq!(create[1], 0); // depth
+        assert_eq!(u32_at(create, 4), window.resource_id());
+        assert_eq!(u32_at(create, 8), mock::ROOT);
+        assert_eq!(u32_at(create, 24), 0); // visual
+                                           // back pixel, bit gravity, override redirect, event mask, cursor
+        assert_eq!(u32_at(create, 28), 0x0000_4a12);
+        let values: Vec<u32> = (0..5).map(|i| u32_at(create, 32 + 4 * i)).collect();
+        assert_eq!(values, [0xff_ffff, 1, 1, 0x8000, 0x300]);
+
+        let change = &requests[1];
+        assert_eq!(u32_at(change, 4), window.resource_id());
+        assert_eq!(u32_at(change, 8), 0x0000_0400); // save under
+        assert_eq!(u32_at(change, 12), 1);
+    }
+
+    #[test]
+    fn test_configure_values() {
+        use crate::mock;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let sibling = unsafe { Window::new(0x300) };
+        let values = ConfigureValues::new()
+            .stack_mode(StackMode::Below)
+            .sibling(sibling)
+            .height(200)
+            .x(-10)
+            .width(100)
+            .x(-20);
+        assert_eq!(
+            values.value_list(),
+            [
+                ConfigWindow::X(-20),
+                ConfigWindow::Width(100),
+                ConfigWindow::Height(200),
+                ConfigWindow::Sibling(sibling),
+                ConfigWindow::StackMode(StackMode::Below),
+            ]
+        );
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = {
+            let requests = requests.clone();
+            mock::connect(move |req| match req.opcode {
+                12 => {
+                    requests.lock().unwrap().push(req.data.to_vec());
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+        let window = unsafe { Window::new(0x200001) };
+        configure_window(&conn, window, &values).unwrap();
+
+        let requests = requests.lock().unwrap();
+        let request = &requests[0];
+        let u32_at = |off: usize| {
+            u32::from_ne_bytes([
+                request[off],
+                request[off + 1],
+                request[off + 2],
+                request[off + 3],
+            ])
+        };
+        assert_eq!(u32_at(4), 0x200001);
+        // x, width, height, sibling, stack mode
+        assert_eq!(u16::from_ne_bytes([request[8], request[9]]), 0x6d);
+        let values: Vec<u32> = (0..5).map(|i| u32_at(12 + 4 * i)).collect();
+        assert_eq!(values, [-20i32 as u32, 100, 200, 0x300, 1]);
+    }
+}
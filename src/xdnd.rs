@@ -0,0 +1,1662 @@
+//! Groundwork of the [XDND](https://www.freedesktop.org/wiki/Specifications/XDND/)
+//! drag-and-drop protocol.
+//!
+//! This module provides the client messages of the protocol ([Enter], [Position], [Status],
+//! [Leave], [Drop] and [Finished]), the search of the drop target under the pointer
+//! ([find_target]), and [DragSource] that drives the protocol on the source side,
+//! from the button press to the drop or the cancellation.
+//!
+//! The data is not transferred by the client messages: the source owns the `XdndSelection`
+//! selection during the drag, and the target converts it with `ConvertSelection` once
+//! the data is dropped, like any other selection.
+
+use crate::base::{Connection, Result, ResultExt, Xid, XidNew};
+use crate::x;
+
+/// The version of the protocol implemented by this module.
+pub const XDND_VERSION: u32 = 5;
+
+/// The oldest version of the protocol supported by this module.
+const XDND_MIN_VERSION: u32 = 3;
+
+const ATOM_NAMES: [&str; 10] = [
+    "XdndAware",
+    "XdndEnter",
+    "XdndPosition",
+    "XdndStatus",
+    "XdndLeave",
+    "XdndDrop",
+    "XdndFinished",
+    "XdndSelection",
+    "XdndTypeList",
+    "XdndActionCopy",
+];
+
+/// The atoms of the protocol.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Atoms {
+    /// `XdndAware`, the property of the windows that accept drops, set to the protocol version.
+    pub aware: x::Atom,
+    pub enter: x::Atom,
+    pub position: x::Atom,
+    pub status: x::Atom,
+    pub leave: x::Atom,
+    pub drop: x::Atom,
+    pub finished: x::Atom,
+    /// `XdndSelection`, the selection that transfers the data.
+    pub selection: x::Atom,
+    /// `XdndTypeList`, the property of the source window with all the types of the data.
+    pub type_list: x::Atom,
+    /// `XdndActionCopy`, the default action.
+    pub action_copy: x::Atom,
+}
+
+impl Atoms {
+    /// Interns the atoms of the protocol.
+    ///
+    /// All the `InternAtom` requests are pipelined.
+    pub fn intern(conn: &Connection) -> Result<Atoms> {
+        let cookies: Vec<_> = ATOM_NAMES
+            .iter()
+            .map(|name| {
+                conn.send_request(&x::InternAtom {
+                    only_if_exists: false,
+                    name: name.as_bytes(),
+                })
+            })
+            .collect();
+        let mut atoms = [x::ATOM_NONE; 10];
+        for ((atom, cookie), name) in atoms.iter_mut().zip(cookies).zip(ATOM_NAMES) {
+            *atom = conn
+                .wait_for_reply(cookie)
+                .context("InternAtom", || name)?
+                .atom();
+        }
+        let [aware, enter, position, status, leave, drop, finished, selection, type_list, action_copy] =
+            atoms;
+        Ok(Atoms {
+            aware,
+            enter,
+            position,
+            status,
+            leave,
+            drop,
+            finished,
+            selection,
+            type_list,
+            action_copy,
+        })
+    }
+
+    /// Parses `ev` if it is a message of the protocol.
+    pub fn parse(&self, ev: &x::ClientMessageEvent) -> Option<Message> {
+        let data = ev.data();
+        let r#type = ev.r#type();
+        if r#type == self.enter {
+            Enter::from_data(&data).map(Message::Enter)
+        } else if r#type == self.position {
+            Position::from_data(&data).map(Message::Position)
+        } else if r#type == self.status {
+            Status::from_data(&data).map(Message::Status)
+        } else if r#type == self.leave {
+            Leave::from_data(&data).map(Message::Leave)
+        } else if r#type == self.drop {
+            Drop::from_data(&data).map(Message::Drop)
+        } else if r#type == self.finished {
+            Finished::from_data(&data).map(Message::Finished)
+        } else {
+            None
+        }
+    }
+
+    /// Builds the `ClientMessage` event of `message`, to be sent to `window`.
+    pub fn client_message(&self, window: x::Window, message: &Message) -> x::ClientMessageEvent {
+        let (r#type, data) = match message {
+            Message::Enter(msg) => (self.enter, msg.to_data()),
+            Message::Position(msg) => (self.position, msg.to_data()),
+            Message::Status(msg) => (self.status, msg.to_data()),
+            Message::Leave(msg) => (self.leave, msg.to_data()),
+            Message::Drop(msg) => (self.drop, msg.to_data()),
+            Message::Finished(msg) => (self.finished, msg.to_data()),
+        };
+        x::ClientMessageEvent::new(window, r#type, data)
+    }
+}
+
+fn data32(data: &x::ClientMessageData) -> Option<&[u32; 5]> {
+    match data {
+        x::ClientMessageData::Data32(data) => Some(data),
+        _ => None,
+    }
+}
+
+fn window(id: u32) -> x::Window {
+    unsafe { x::Window::new(id) }
+}
+
+fn atom(id: u32) -> x::Atom {
+    unsafe { x::Atom::new(id) }
+}
+
+/// Packs a pair of 16-bit values in the high and low halves of a 32-bit value.
+fn pack(high: u16, low: u16) -> u32 {
+    (high as u32) << 16 | low as u32
+}
+
+/// `XdndEnter`, sent by the source when the pointer enters a target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Enter {
+    pub source: x::Window,
+    /// The version of the protocol used for the drag.
+    pub version: u32,
+    /// The first three types of the data.
+    pub types: Vec<x::Atom>,
+    /// Whether the data has more than three types, listed in the `XdndTypeList` property
+    /// of the source window.
+    pub more_types: bool,
+}
+
+impl Enter {
+    pub fn to_data(&self) -> x::ClientMessageData {
+        let mut data = [
+            self.source.resource_id(),
+            self.version << 24 | self.more_types as u32,
+            0,
+            0,
+            0,
+        ];
+        for (d, t) in data[2..].iter_mut().zip(&self.types) {
+            *d = t.resource_id();
+        }
+        x::ClientMessageData::Data32(data)
+    }
+
+    pub fn from_data(data: &x::ClientMessageData) -> Option<Enter> {
+        let data = data32(data)?;
+        Some(Enter {
+            source: window(data[0]),
+            version: data[1] >> 24,
+            types: data[2..]
+                .iter()
+                .filter(|&&t| t != 0)
+                .map(|&t| atom(t))
+                .collect(),
+            more_types: data[1] & 1 != 0,
+        })
+    }
+}
+
+/// `XdndPosition`, sent by the source when the pointer moves over the target.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub source: x::Window,
+    /// The pointer position, relative to the root window.
+    pub root_x: i16,
+    pub root_y: i16,
+    pub time: x::Timestamp,
+    /// The action requested by the source.
+    pub action: x::Atom,
+}
+
+impl Position {
+    pub fn to_data(&self) -> x::ClientMessageData {
+        x::ClientMessageData::Data32([
+            self.source.resource_id(),
+            0,
+            pack(self.root_x as u16, self.root_y as u16),
+            self.time,
+            self.action.resource_id(),
+        ])
+    }
+
+    pub fn from_data(data: &x::ClientMessageData) -> Option<Position> {
+        let data = data32(data)?;
+        Some(Position {
+            source: window(data[0]),
+            root_x: (data[2] >> 16) as i16,
+            root_y: data[2] as i16,
+            time: data[3],
+            action: atom(data[4]),
+        })
+    }
+}
+
+/// `XdndStatus`, sent by the target in response to each [Position].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub target: x::Window,
+    /// Whether the target accepts the drop at the current position.
+    pub accept: bool,
+    /// Whether the target wants [Position] messages while the pointer is in `rect`.
+    pub want_position: bool,
+    /// The rectangle, relative to the root window, where the status doesn't change.
+    /// Empty if the status may change anywhere.
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    /// The action accepted by the target.
+    pub action: x::Atom,
+}
+
+impl Status {
+    pub fn to_data(&self) -> x::ClientMessageData {
+        x::ClientMessageData::Data32([
+            self.target.resource_id(),
+            self.accept as u32 | (self.want_position as u32) << 1,
+            pack(self.x as u16, self.y as u16),
+            pack(self.width, self.height),
+            self.action.resource_id(),
+        ])
+    }
+
+    pub fn from_data(data: &x::ClientMessageData) -> Option<Status> {
+        let data = data32(data)?;
+        Some(Status {
+            target: window(data[0]),
+            accept: data[1] & 1 != 0,
+            want_position: data[1] & 2 != 0,
+            x: (data[2] >> 16) as i16,
+            y: data[2] as i16,
+            width: (data[3] >> 16) as u16,
+            height: data[3] as u16,
+            action: atom(data[4]),
+        })
+    }
+}
+
+/// `XdndLeave`, sent by the source when the pointer leaves the target or the drag is cancelled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Leave {
+    pub source: x::Window,
+}
+
+impl Leave {
+    pub fn to_data(&self) -> x::ClientMessageData {
+        x::ClientMessageData::Data32([self.source.resource_id(), 0, 0, 0, 0])
+    }
+
+    pub fn from_data(data: &x::ClientMessageData) -> Option<Leave> {
+        let data = data32(data)?;
+        Some(Leave {
+            source: window(data[0]),
+        })
+    }
+}
+
+/// `XdndDrop`, sent by the source when the data is dropped on the target.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Drop {
+    pub source: x::Window,
+    /// The time of the drop, to be used to convert the `XdndSelection` selection.
+    pub time: x::Timestamp,
+}
+
+impl Drop {
+    pub fn to_data(&self) -> x::ClientMessageData {
+        x::ClientMessageData::Data32([self.source.resource_id(), 0, self.time, 0, 0])
+    }
+
+    pub fn from_data(data: &x::ClientMessageData) -> Option<Drop> {
+        let data = data32(data)?;
+        Some(Drop {
+            source: window(data[0]),
+            time: data[2],
+        })
+    }
+}
+
+/// `XdndFinished`, sent by the target when it is done with the dropped data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Finished {
+    pub target: x::Window,
+    /// Whether the target performed the action (version 5).
+    pub success: bool,
+    /// The action performed by the target (version 5).
+    pub action: x::Atom,
+}
+
+impl Finished {
+    pub fn to_data(&self) -> x::ClientMessageData {
+        x::ClientMessageData::Data32([
+            self.target.resource_id(),
+            self.success as u32,
+            self.action.resource_id(),
+            0,
+            0,
+        ])
+    }
+
+    pub fn from_data(data: &x::ClientMessageData) -> Option<Finished> {
+        let data = data32(data)?;
+        Some(Finished {
+            target: window(data[0]),
+            success: data[1] & 1 != 0,
+            action: atom(data[2]),
+        })
+    }
+}
+
+/// A message of the protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Enter(Enter),
+    Position(Position),
+    Status(Status),
+    Leave(Leave),
+    Drop(Drop),
+    Finished(Finished),
+}
+
+impl Message {
+    /// The name of the message type (e.g. `"XdndEnter"`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::Enter(_) => "XdndEnter",
+            Message::Position(_) => "XdndPosition",
+            Message::Status(_) => "XdndStatus",
+            Message::Leave(_) => "XdndLeave",
+            Message::Drop(_) => "XdndDrop",
+            Message::Finished(_) => "XdndFinished",
+        }
+    }
+}
+
+/// A window that accepts drops.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    /// The window with the `XdndAware` property.
+    pub window: x::Window,
+    /// The version of the protocol to use with the target: the lowest of the version
+    /// of the target and [XDND_VERSION].
+    pub version: u32,
+}
+
+/// Returns the version of the protocol in the `XdndAware` property of `window`, if any.
+fn xdnd_aware(conn: &Connection, atoms: &Atoms, window: x::Window) -> Result<Option<u32>> {
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window,
+        property: atoms.aware,
+        r#type: x::ATOM_ATOM,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let reply = conn.wait_for_reply(cookie).context("GetProperty", || {
+        format!("XdndAware of window {:#x}", window.resource_id())
+    })?;
+    if reply.r#type() != x::ATOM_ATOM || reply.format() != 32 {
+        return Ok(None);
+    }
+    Ok(reply.value::<u32>().first().copied())
+}
+
+/// Returns the topmost viewable child of `window` that contains (`x`, `y`),
+/// other than `skip`.
+fn topmost_child_at(
+    conn: &Connection,
+    window: x::Window,
+    x: i16,
+    y: i16,
+    skip: x::Window,
+) -> Result<x::Window> {
+    let detail = || format!("for window {:#x}", window.resource_id());
+    let cookie = conn.send_request(&x::QueryTree { window });
+    let tree = conn.wait_for_reply(cookie).context("QueryTree", detail)?;
+
+    // children are listed in stacking order, bottom-most first
+    let cookies: Vec<_> = tree
+        .children()
+        .iter()
+        .rev()
+        .filter(|&&child| child != skip)
+        .map(|&child| {
+            let attributes = conn.send_request(&x::GetWindowAttributes { window: child });
+            let geometry = conn.send_request(&x::GetGeometry {
+                drawable: x::Drawable::Window(child),
+            });
+            (child, attributes, geometry)
+        })
+        .collect();
+    let mut replies = Vec::with_capacity(cookies.len());
+    for (child, attributes, geometry) in cookies {
+        let detail = || format!("for window {:#x}", child.resource_id());
+        let attributes = conn
+            .wait_for_reply(attributes)
+            .context("GetWindowAttributes", detail)?;
+        let geometry = conn
+            .wait_for_reply(geometry)
+            .context("GetGeometry", detail)?;
+        replies.push((child, attributes, geometry));
+    }
+
+    let (x, y) = (x as i32, y as i32);
+    for (child, attributes, geometry) in replies {
+        if attributes.map_state() != x::MapState::Viewable {
+            continue;
+        }
+        let border = 2 * geometry.border_width() as i32;
+        let (left, top) = (geometry.x() as i32, geometry.y() as i32);
+        let (right, bottom) = (
+            left + geometry.width() as i32 + border,
+            top + geometry.height() as i32 + border,
+        );
+        if x >= left && x < right && y >= top && y < bottom {
+            return Ok(child);
+        }
+    }
+    Ok(x::WINDOW_NONE)
+}
+
+/// Returns the child of `window` under (`root_x`, `root_y`), other than `skip`.
+fn child_at(
+    conn: &Connection,
+    root: x::Window,
+    window: x::Window,
+    root_x: i16,
+    root_y: i16,
+    skip: x::Window,
+) -> Result<x::Window> {
+    let cookie = conn.send_request(&x::TranslateCoordinates {
+        src_window: root,
+        dst_window: window,
+        src_x: root_x,
+        src_y: root_y,
+    });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("TranslateCoordinates", || {
+            format!("to window {:#x}", window.resource_id())
+        })?;
+    if skip.is_none() || reply.child() != skip {
+        return Ok(reply.child());
+    }
+    // the point is over the skipped window, look for the window beneath it
+    topmost_child_at(conn, window, reply.dst_x(), reply.dst_y(), skip)
+}
+
+/// Finds the window that accepts drops under (`root_x`, `root_y`), relative to `root`.
+///
+/// The window tree is descended with `TranslateCoordinates` until a window with
+/// the `XdndAware` property is found. `skip` is ignored by the search, and the windows
+/// beneath it are searched instead: it is typically the window that shows the dragged item
+/// under the pointer (or `x::WINDOW_NONE`).
+///
+/// Returns `None` if there is no such window or if its version of the protocol is too old.
+/// The `XdndProxy` property is not supported.
+pub fn find_target(
+    conn: &Connection,
+    atoms: &Atoms,
+    root: x::Window,
+    root_x: i16,
+    root_y: i16,
+    skip: x::Window,
+) -> Result<Option<Target>> {
+    let mut window = root;
+    loop {
+        let child = child_at(conn, root, window, root_x, root_y, skip)?;
+        if child.is_none() {
+            return Ok(None);
+        }
+        if let Some(version) = xdnd_aware(conn, atoms, child)? {
+            if version < XDND_MIN_VERSION {
+                return Ok(None);
+            }
+            return Ok(Some(Target {
+                window: child,
+                version: version.min(XDND_VERSION),
+            }));
+        }
+        window = child;
+    }
+}
+
+/// The cursors shown during a drag, depending on the status of the target.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DragCursors {
+    /// Shown when there is no target, or when the target rejects the drop.
+    pub reject: x::Cursor,
+    /// Shown when the target accepts the drop.
+    pub accept: x::Cursor,
+}
+
+/// The state of a [DragSource].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DragState {
+    /// No drag was started.
+    Idle,
+    /// The pointer is grabbed, and the targets under the pointer are notified.
+    Dragging,
+    /// The data was dropped on the target, which converts the `XdndSelection` selection.
+    Dropped,
+    /// The target is done with the data.
+    Finished {
+        /// Whether the target performed the action.
+        success: bool,
+        /// The action performed by the target.
+        action: x::Atom,
+    },
+    /// The button was released out of a target accepting the drop,
+    /// or [DragSource::cancel] was called.
+    Cancelled,
+}
+
+const DRAG_EVENT_MASK: x::EventMask =
+    x::EventMask::BUTTON_RELEASE.union(x::EventMask::POINTER_MOTION);
+
+/// Drives the source side of the protocol.
+///
+/// [DragSource::begin] is called with the button press that starts the drag: it grabs
+/// the pointer and acquires the `XdndSelection` selection. [DragSource::handle_event] is then
+/// meant to be called from the event loop with every event: it sends the messages
+/// to the targets under the pointer, switches the cursor of the grab with the status
+/// of the target, and drops the data when the button is released over a target that
+/// accepts it.
+///
+/// The `SelectionRequest` events of the `XdndSelection` selection are not handled:
+/// the data is transferred by the selection code of the application.
+pub struct DragSource<'a> {
+    conn: &'a Connection,
+    atoms: Atoms,
+    window: x::Window,
+    icon: x::Window,
+    types: Vec<x::Atom>,
+    action: x::Atom,
+    cursors: DragCursors,
+    cursor: x::Cursor,
+    root: x::Window,
+    state: DragState,
+    target: Option<Target>,
+    accepted: bool,
+    // A `Position` message was sent, and its `Status` is not received yet.
+    waiting_status: bool,
+    // The last position not sent while waiting for the status.
+    pending: Option<(i16, i16, x::Timestamp)>,
+}
+
+impl<'a> std::fmt::Debug for DragSource<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DragSource")
+            .field("window", &self.window)
+            .field("types", &self.types)
+            .field("state", &self.state)
+            .field("target", &self.target)
+            .field("accepted", &self.accepted)
+            .finish()
+    }
+}
+
+impl<'a> DragSource<'a> {
+    /// Creates a drag of data of `types` from `window`.
+    ///
+    /// The requested action is `XdndActionCopy`.
+    pub fn new(
+        conn: &'a Connection,
+        atoms: Atoms,
+        window: x::Window,
+        types: &[x::Atom],
+        cursors: DragCursors,
+    ) -> DragSource<'a> {
+        DragSource {
+            conn,
+            atoms,
+            window,
+            icon: x::WINDOW_NONE,
+            types: types.to_vec(),
+            action: atoms.action_copy,
+            cursors,
+            cursor: x::CURSOR_NONE,
+            root: x::WINDOW_NONE,
+            state: DragState::Idle,
+            target: None,
+            accepted: false,
+            waiting_status: false,
+            pending: None,
+        }
+    }
+
+    /// Sets the window that shows the dragged item under the pointer, ignored by the search
+    /// of the targets.
+    pub fn set_icon_window(&mut self, icon: x::Window) {
+        self.icon = icon;
+    }
+
+    /// Sets the action requested to the targets (e.g. `XdndActionMove`).
+    pub fn set_action(&mut self, action: x::Atom) {
+        self.action = action;
+    }
+
+    /// The source window.
+    pub fn window(&self) -> x::Window {
+        self.window
+    }
+
+    /// The state of the drag.
+    pub fn state(&self) -> DragState {
+        self.state
+    }
+
+    /// The target under the pointer, or where the data was dropped.
+    pub fn target(&self) -> Option<Target> {
+        self.target
+    }
+
+    /// Whether the target accepts the drop, according to its last status.
+    pub fn accepted(&self) -> bool {
+        self.accepted
+    }
+
+    /// Starts the drag from the button press `press`.
+    ///
+    /// The `XdndTypeList` property is set if there are more than three types,
+    /// the `XdndSelection` selection is acquired and the pointer is grabbed.
+    /// Returns `false` if the pointer could not be grabbed.
+    pub fn begin(&mut self, press: &x::ButtonPressEvent) -> Result<bool> {
+        let conn = self.conn;
+        let window = self.window;
+        let detail = || format!("for window {:#x}", window.resource_id());
+
+        if self.types.len() > 3 {
+            conn.send_and_check_request(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window,
+                property: self.atoms.type_list,
+                r#type: x::ATOM_ATOM,
+                data: &self.types,
+            })
+            .context("ChangeProperty", || {
+                format!("XdndTypeList of window {:#x}", window.resource_id())
+            })?;
+        }
+        conn.send_and_check_request(&x::SetSelectionOwner {
+            owner: window,
+            selection: self.atoms.selection,
+            time: press.time(),
+        })
+        .context("SetSelectionOwner", detail)?;
+
+        let cookie = conn.send_request(&x::GrabPointer {
+            owner_events: false,
+            grab_window: window,
+            event_mask: DRAG_EVENT_MASK,
+            pointer_mode: x::GrabMode::Async,
+            keyboard_mode: x::GrabMode::Async,
+            confine_to: x::WINDOW_NONE,
+            cursor: self.cursors.reject,
+            time: press.time(),
+        });
+        let reply = conn.wait_for_reply(cookie).context("GrabPointer", detail)?;
+        if reply.status() != x::GrabStatus::Success {
+            return Ok(false);
+        }
+
+        self.cursor = self.cursors.reject;
+        self.root = press.root();
+        self.state = DragState::Dragging;
+        self.target = None;
+        self.accepted = false;
+        self.waiting_status = false;
+        self.pending = None;
+        Ok(true)
+    }
+
+    /// Drives the drag with `event`.
+    ///
+    /// Returns `true` if the event was consumed by the drag: the pointer events during the drag,
+    /// and the messages of the targets.
+    pub fn handle_event(&mut self, event: &crate::Event) -> Result<bool> {
+        match event {
+            crate::Event::X(x::Event::MotionNotify(ev)) if self.state == DragState::Dragging => {
+                self.motion(ev.root_x(), ev.root_y(), ev.time())?;
+                Ok(true)
+            }
+            crate::Event::X(x::Event::ButtonRelease(ev)) if self.state == DragState::Dragging => {
+                self.release(ev.time())?;
+                Ok(true)
+            }
+            crate::Event::X(x::Event::ClientMessage(ev)) if ev.window() == self.window => {
+                match self.atoms.parse(ev) {
+                    Some(Message::Status(status)) => {
+                        self.status(&status)?;
+                        Ok(true)
+                    }
+                    Some(Message::Finished(finished)) => {
+                        self.finished(&finished);
+                        Ok(true)
+                    }
+                    _ => Ok(false),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Cancels the drag: the target is left and the pointer is released.
+    ///
+    /// Does nothing if the drag is not in progress.
+    pub fn cancel(&mut self) -> Result<()> {
+        if self.state != DragState::Dragging {
+            return Ok(());
+        }
+        self.ungrab(x::CURRENT_TIME)?;
+        self.state = DragState::Cancelled;
+        if let Some(target) = self.target {
+            self.send(
+                target.window,
+                &Message::Leave(Leave {
+                    source: self.window,
+                }),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, window: x::Window, message: &Message) -> Result<()> {
+        let event = self.atoms.client_message(window, message);
+        self.conn
+            .send_and_check_request(&x::SendEvent {
+                propagate: false,
+                destination: x::SendEventDest::Window(window),
+                event_mask: x::EventMask::NO_EVENT,
+                event: &event,
+            })
+            .context("SendEvent", || {
+                format!("{} to window {:#x}", message.name(), window.resource_id())
+            })
+    }
+
+    fn send_position(
+        &mut self,
+        target: x::Window,
+        root_x: i16,
+        root_y: i16,
+        time: x::Timestamp,
+    ) -> Result<()> {
+        self.waiting_status = true;
+        self.send(
+            target,
+            &Message::Position(Position {
+                source: self.window,
+                root_x,
+                root_y,
+                time,
+                action: self.action,
+            }),
+        )
+    }
+
+    fn set_cursor(&mut self, cursor: x::Cursor) -> Result<()> {
+        if cursor == self.cursor {
+            return Ok(());
+        }
+        self.cursor = cursor;
+        self.conn
+            .send_and_check_request(&x::ChangeActivePointerGrab {
+                cursor,
+                time: x::CURRENT_TIME,
+                event_mask: DRAG_EVENT_MASK,
+            })
+            .context("ChangeActivePointerGrab", || {
+                format!("to cursor {:#x}", cursor.resource_id())
+            })
+    }
+
+    fn ungrab(&self, time: x::Timestamp) -> Result<()> {
+        self.conn
+            .send_and_check_request(&x::UngrabPointer { time })
+            .context("UngrabPointer", || {
+                format!("for window {:#x}", self.window.resource_id())
+            })
+    }
+
+    fn motion(&mut self, root_x: i16, root_y: i16, time: x::Timestamp) -> Result<()> {
+        let target = find_target(self.conn, &self.atoms, self.root, root_x, root_y, self.icon)?;
+        // our own windows are not targets
+        let target = target.filter(|t| t.window != self.window);
+
+        if target.map(|t| t.window) != self.target.map(|t| t.window) {
+            if let Some(old) = self.target.take() {
+                self.send(
+                    old.window,
+                    &Message::Leave(Leave {
+                        source: self.window,
+                    }),
+                )?;
+            }
+            self.accepted = false;
+            self.waiting_status = false;
+            self.pending = None;
+            self.set_cursor(self.cursors.reject)?;
+            if let Some(new) = target {
+                self.send(
+                    new.window,
+                    &Message::Enter(Enter {
+                        source: self.window,
+                        version: new.version,
+                        types: self.types.iter().take(3).copied().collect(),
+                        more_types: self.types.len() > 3,
+                    }),
+                )?;
+            }
+            self.target = target;
+        }
+
+        match self.target {
+            // the target is not flooded: the last position is sent with the status
+            Some(_) if self.waiting_status => {
+                self.pending = Some((root_x, root_y, time));
+                Ok(())
+            }
+            Some(target) => self.send_position(target.window, root_x, root_y, time),
+            None => Ok(()),
+        }
+    }
+
+    fn status(&mut self, status: &Status) -> Result<()> {
+        let target = match self.target {
+            Some(target) if target.window == status.target => target,
+            // stale status of a target that was left
+            _ => return Ok(()),
+        };
+        if self.state != DragState::Dragging {
+            return Ok(());
+        }
+        self.waiting_status = false;
+        self.accepted = status.accept;
+        let cursor = if status.accept {
+            self.cursors.accept
+        } else {
+            self.cursors.reject
+        };
+        self.set_cursor(cursor)?;
+        if let Some((root_x, root_y, time)) = self.pending.take() {
+            self.send_position(target.window, root_x, root_y, time)?;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, time: x::Timestamp) -> Result<()> {
+        self.ungrab(time)?;
+        match self.target {
+            Some(target) if self.accepted => {
+                self.state = DragState::Dropped;
+                self.send(
+                    target.window,
+                    &Message::Drop(Drop {
+                        source: self.window,
+                        time,
+                    }),
+                )
+            }
+            Some(target) => {
+                self.state = DragState::Cancelled;
+                self.send(
+                    target.window,
+                    &Message::Leave(Leave {
+                        source: self.window,
+                    }),
+                )
+            }
+            None => {
+                self.state = DragState::Cancelled;
+                Ok(())
+            }
+        }
+    }
+
+    fn finished(&mut self, finished: &Finished) {
+        match self.target {
+            Some(target)
+                if target.window == finished.target && self.state == DragState::Dropped =>
+            {
+                self.state = DragState::Finished {
+                    // the success and the action are only reported by version 5
+                    success: finished.success || target.version < 5,
+                    action: if target.version < 5 {
+                        self.action
+                    } else {
+                        finished.action
+                    },
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: u32 = 0x200;
+
+    /// The icon of the dragged item, always under the pointer.
+    const ICON: u32 = 0x300;
+
+    /// The frame of the window manager around `TARGET`, at (100, 100) in the root window.
+    const FRAME: u32 = 0x400;
+
+    /// The target window, at (0, 20) in `FRAME`.
+    const TARGET: u32 = 0x500;
+
+    /// A display shared by the mock servers of the source (client 0) and the target (client 1).
+    #[derive(Default)]
+    struct MockDisplay {
+        atoms: Vec<Vec<u8>>,
+        /// The events to deliver to each client with the responses to its next request.
+        events: [Vec<Vec<u8>>; 2],
+        /// The properties: type, format and value, by window and property.
+        properties: std::collections::HashMap<(u32, u32), (u32, u8, Vec<u8>)>,
+        selection_owner: u32,
+        /// The opcode and the data of the pointer requests of the source.
+        pointer_requests: Vec<(u8, Vec<u8>)>,
+    }
+
+    fn client_of(window: u32) -> usize {
+        if window == TARGET {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn mock_request(
+        display: &std::sync::Mutex<MockDisplay>,
+        client: usize,
+        req: &crate::mock::MockRequest,
+    ) -> Vec<Vec<u8>> {
+        use crate::mock;
+        use std::convert::TryInto;
+
+        let mut display = display.lock().unwrap();
+        let mut responses = std::mem::take(&mut display.events[client]);
+        for ev in &mut responses {
+            ev[2..4].copy_from_slice(&req.seq.to_ne_bytes());
+        }
+        let i16_at =
+            |offset: usize| i16::from_ne_bytes(req.data[offset..offset + 2].try_into().unwrap());
+        let mut reply = |data: u8, body: &[u8]| responses.push(mock::reply(req.seq, data, body));
+
+        match req.opcode {
+            // GetWindowAttributes: viewable
+            3 => {
+                let mut body = vec![0u8; 36];
+                body[18] = 2;
+                reply(0, &body);
+            }
+            // GetGeometry
+            14 => {
+                let mut body = mock::ROOT.to_ne_bytes().to_vec();
+                let geometry = match req.u32_at(4) {
+                    FRAME => [100i16, 100, 400, 300, 0],
+                    ICON => [0, 0, 32, 32, 0],
+                    _ => [0, 20, 400, 280, 0],
+                };
+                for v in geometry {
+                    body.extend_from_slice(&v.to_ne_bytes());
+                }
+                reply(24, &body);
+            }
+            // QueryTree of the root window
+            15 => {
+                assert_eq!(req.u32_at(4), mock::ROOT);
+                let mut body = mock::ROOT.to_ne_bytes().to_vec();
+                body.extend_from_slice(&0u32.to_ne_bytes());
+                body.extend_from_slice(&2u16.to_ne_bytes());
+                body.resize(24, 0);
+                body.extend_from_slice(&FRAME.to_ne_bytes());
+                body.extend_from_slice(&ICON.to_ne_bytes());
+                reply(0, &body);
+            }
+            // InternAtom
+            16 => {
+                let len = u16::from_ne_bytes([req.data[4], req.data[5]]) as usize;
+                let name = req.data[8..8 + len].to_vec();
+                let index = match display.atoms.iter().position(|n| *n == name) {
+                    Some(index) => index,
+                    None => {
+                        display.atoms.push(name);
+                        display.atoms.len() - 1
+                    }
+                };
+                let atom = 1000 + index as u32;
+                responses.push(mock::reply(req.seq, 0, &atom.to_ne_bytes()));
+            }
+            // ChangeProperty
+            18 => {
+                let format = req.data[16];
+                let len = req.u32_at(20) as usize * format as usize / 8;
+                let value = (req.u32_at(12), format, req.data[24..24 + len].to_vec());
+                display
+                    .properties
+                    .insert((req.u32_at(4), req.u32_at(8)), value);
+            }
+            // GetProperty
+            20 => {
+                let (r#type, format, value) = display
+                    .properties
+                    .get(&(req.u32_at(4), req.u32_at(8)))
+                    .cloned()
+                    .unwrap_or_default();
+                let mut body = vec![0u8; 24];
+                body[0..4].copy_from_slice(&r#type.to_ne_bytes());
+                let len = if format == 0 {
+                    0
+                } else {
+                    value.len() * 8 / format as usize
+                };
+                body[8..12].copy_from_slice(&(len as u32).to_ne_bytes());
+                body.extend_from_slice(&value);
+                responses.push(mock::reply(req.seq, format, &body));
+            }
+            // SetSelectionOwner
+            22 => display.selection_owner = req.u32_at(4),
+            // ConvertSelection: SelectionRequest to the owner
+            24 => {
+                let mut ev = vec![30, 0, 0, 0];
+                ev.extend_from_slice(&req.data[20..24]); // time
+                ev.extend_from_slice(&display.selection_owner.to_ne_bytes());
+                ev.extend_from_slice(&req.data[4..20]); // requestor, selection, target, property
+                ev.resize(32, 0);
+                let owner = client_of(display.selection_owner);
+                display.events[owner].push(ev);
+            }
+            // SendEvent
+            25 => {
+                let mut ev = req.data[12..44].to_vec();
+                ev[0] |= 0x80;
+                display.events[client_of(req.u32_at(4))].push(ev);
+            }
+            // GrabPointer
+            26 => {
+                display
+                    .pointer_requests
+                    .push((req.opcode, req.data.clone()));
+                responses.push(mock::reply(req.seq, 0, &[]));
+            }
+            // UngrabPointer, ChangeActivePointerGrab
+            27 | 30 => display
+                .pointer_requests
+                .push((req.opcode, req.data.clone())),
+            // TranslateCoordinates
+            40 => {
+                let (x, y) = (i16_at(12), i16_at(14));
+                let inside_target = (100..500).contains(&x) && (120..400).contains(&y);
+                let (child, dst_x, dst_y) = match req.u32_at(8) {
+                    mock::ROOT => (ICON, x, y),
+                    FRAME if inside_target => (TARGET, x - 100, y - 100),
+                    FRAME => (0, x - 100, y - 100),
+                    _ => (0, x - 100, y - 120),
+                };
+                let mut body = child.to_ne_bytes().to_vec();
+                body.extend_from_slice(&dst_x.to_ne_bytes());
+                body.extend_from_slice(&dst_y.to_ne_bytes());
+                reply(1, &body);
+            }
+            _ => responses.extend(mock::default_response(req)),
+        }
+        responses
+    }
+
+    /// Connects the source and the target to a [MockDisplay].
+    fn mock_display() -> (
+        Connection,
+        Connection,
+        std::sync::Arc<std::sync::Mutex<MockDisplay>>,
+    ) {
+        use crate::mock;
+        use std::sync::{Arc, Mutex};
+
+        let display = Arc::new(Mutex::new(MockDisplay::default()));
+        let source = {
+            let display = display.clone();
+            mock::connect(move |req| mock_request(&display, 0, req))
+        };
+        let target = {
+            let display = display.clone();
+            mock::connect(move |req| mock_request(&display, 1, req))
+        };
+        (source, target, display)
+    }
+
+    /// Returns the next event of `conn`, after a round trip to get the pending events.
+    fn next_event(conn: &Connection) -> crate::Event {
+        if let Some(ev) = conn.poll_for_queued_event().unwrap() {
+            return ev;
+        }
+        conn.send_and_check_request(&x::NoOperation {}).unwrap();
+        conn.poll_for_queued_event().unwrap().expect("no event")
+    }
+
+    fn motion(time: x::Timestamp, root_x: i16, root_y: i16) -> crate::Event {
+        crate::Event::X(x::Event::MotionNotify(x::MotionNotifyEvent::new(
+            x::Motion::Normal,
+            time,
+            window(crate::mock::ROOT),
+            window(SOURCE),
+            x::WINDOW_NONE,
+            root_x,
+            root_y,
+            root_x,
+            root_y,
+            x::KeyButMask::BUTTON1,
+            true,
+        )))
+    }
+
+    fn button(time: x::Timestamp, root_x: i16, root_y: i16) -> x::ButtonPressEvent {
+        x::ButtonPressEvent::new(
+            1,
+            time,
+            window(crate::mock::ROOT),
+            window(SOURCE),
+            x::WINDOW_NONE,
+            root_x,
+            root_y,
+            root_x,
+            root_y,
+            x::KeyButMask::empty(),
+            true,
+        )
+    }
+
+    #[test]
+    fn test_message_layout() {
+        let source = window(0x0120_0003);
+        let target = window(0x0340_0001);
+        let data = |msg: &Message| match msg {
+            Message::Enter(msg) => msg.to_data(),
+            Message::Position(msg) => msg.to_data(),
+            Message::Status(msg) => msg.to_data(),
+            Message::Leave(msg) => msg.to_data(),
+            Message::Drop(msg) => msg.to_data(),
+            Message::Finished(msg) => msg.to_data(),
+        };
+
+        // XdndEnter: version in the high byte of data.l[1], more-than-3-types in bit 0
+        let enter = Enter {
+            source,
+            version: 5,
+            types: vec![atom(300), atom(301)],
+            more_types: false,
+        };
+        assert_eq!(
+            data32(&enter.to_data()),
+            Some(&[0x0120_0003, 0x0500_0000, 300, 301, 0])
+        );
+        let enter_more = Enter {
+            types: vec![atom(300), atom(301), atom(302)],
+            more_types: true,
+            ..enter.clone()
+        };
+        assert_eq!(
+            data32(&enter_more.to_data()),
+            Some(&[0x0120_0003, 0x0500_0001, 300, 301, 302])
+        );
+
+        // XdndPosition: root coordinates packed as (x << 16) | y
+        let position = Position {
+            source,
+            root_x: 100,
+            root_y: 200,
+            time: 4000,
+            action: atom(310),
+        };
+        assert_eq!(
+            data32(&position.to_data()),
+            Some(&[0x0120_0003, 0, 0x0064_00c8, 4000, 310])
+        );
+
+        // XdndStatus: accept in bit 0, want-position in bit 1, then the no-motion rectangle
+        let status = Status {
+            target,
+            accept: true,
+            want_position: true,
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+            action: atom(310),
+        };
+        assert_eq!(
+            data32(&status.to_data()),
+            Some(&[0x0340_0001, 3, 0x000a_0014, 0x001e_0028, 310])
+        );
+        let reject = Status {
+            accept: false,
+            want_position: false,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            action: x::ATOM_NONE,
+            ..status
+        };
+        assert_eq!(data32(&reject.to_data()), Some(&[0x0340_0001, 0, 0, 0, 0]));
+
+        assert_eq!(
+            data32(&Leave { source }.to_data()),
+            Some(&[0x0120_0003, 0, 0, 0, 0])
+        );
+        // XdndDrop: timestamp in data.l[2]
+        assert_eq!(
+            data32(&Drop { source, time: 4242 }.to_data()),
+            Some(&[0x0120_0003, 0, 4242, 0, 0])
+        );
+        // XdndFinished: success in bit 0 of data.l[1], action in data.l[2]
+        let finished = Finished {
+            target,
+            success: true,
+            action: atom(310),
+        };
+        assert_eq!(
+            data32(&finished.to_data()),
+            Some(&[0x0340_0001, 1, 310, 0, 0])
+        );
+
+        // round trips
+        for msg in [
+            Message::Enter(enter),
+            Message::Enter(enter_more),
+            Message::Position(position),
+            Message::Position(Position {
+                root_x: -5,
+                root_y: -1,
+                ..position
+            }),
+            Message::Status(status),
+            Message::Status(reject),
+            Message::Leave(Leave { source }),
+            Message::Drop(Drop { source, time: 4242 }),
+            Message::Finished(finished),
+        ] {
+            let parsed = match &msg {
+                Message::Enter(_) => Enter::from_data(&data(&msg)).map(Message::Enter),
+                Message::Position(_) => Position::from_data(&data(&msg)).map(Message::Position),
+                Message::Status(_) => Status::from_data(&data(&msg)).map(Message::Status),
+                Message::Leave(_) => Leave::from_data(&data(&msg)).map(Message::Leave),
+                Message::Drop(_) => Drop::from_data(&data(&msg)).map(Message::Drop),
+                Message::Finished(_) => Finished::from_data(&data(&msg)).map(Message::Finished),
+            };
+            assert_eq!(parsed.as_ref(), Some(&msg), "{}", msg.name());
+        }
+
+        // only the 32-bit format
+        assert_eq!(
+            Leave::from_data(&x::ClientMessageData::Data8([0; 20])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_client_message() {
+        let atoms = Atoms {
+            aware: atom(1000),
+            enter: atom(1001),
+            position: atom(1002),
+            status: atom(1003),
+            leave: atom(1004),
+            drop: atom(1005),
+            finished: atom(1006),
+            selection: atom(1007),
+            type_list: atom(1008),
+            action_copy: atom(1009),
+        };
+        let msg = Message::Drop(Drop {
+            source: window(SOURCE),
+            time: 12,
+        });
+        let ev = atoms.client_message(window(TARGET), &msg);
+        assert_eq!(ev.window(), window(TARGET));
+        assert_eq!(ev.r#type(), atoms.drop);
+        assert_eq!(atoms.parse(&ev), Some(msg));
+
+        let ev = x::ClientMessageEvent::new(
+            window(TARGET),
+            atom(2000),
+            x::ClientMessageData::Data32([SOURCE, 0, 0, 0, 0]),
+        );
+        assert_eq!(atoms.parse(&ev), None);
+    }
+
+    #[test]
+    fn test_find_target() {
+        let (conn, _, display) = mock_display();
+        let atoms = Atoms::intern(&conn).unwrap();
+        let root = window(crate::mock::ROOT);
+
+        // not aware yet
+        assert_eq!(
+            find_target(&conn, &atoms, root, 200, 200, window(ICON)).unwrap(),
+            None
+        );
+
+        display.lock().unwrap().properties.insert(
+            (TARGET, atoms.aware.resource_id()),
+            (x::ATOM_ATOM.resource_id(), 32, 4u32.to_ne_bytes().to_vec()),
+        );
+        // the icon is skipped, and the frame is descended
+        assert_eq!(
+            find_target(&conn, &atoms, root, 200, 200, window(ICON)).unwrap(),
+            Some(Target {
+                window: window(TARGET),
+                version: 4
+            })
+        );
+        // in the frame, out of the target
+        assert_eq!(
+            find_target(&conn, &atoms, root, 200, 110, window(ICON)).unwrap(),
+            None
+        );
+        // out of the frame
+        assert_eq!(
+            find_target(&conn, &atoms, root, 50, 50, window(ICON)).unwrap(),
+            None
+        );
+        // the icon is not skipped
+        assert_eq!(
+            find_target(&conn, &atoms, root, 200, 200, x::WINDOW_NONE).unwrap(),
+            None
+        );
+
+        // too old
+        display.lock().unwrap().properties.insert(
+            (TARGET, atoms.aware.resource_id()),
+            (x::ATOM_ATOM.resource_id(), 32, 2u32.to_ne_bytes().to_vec()),
+        );
+        assert_eq!(
+            find_target(&conn, &atoms, root, 200, 200, window(ICON)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_drop_text_plain() {
+        let (src, dst, display) = mock_display();
+        let cursors = DragCursors {
+            reject: unsafe { x::Cursor::new(0x600) },
+            accept: unsafe { x::Cursor::new(0x601) },
+        };
+        let cursor_of = |req: &[u8]| u32::from_ne_bytes([req[4], req[5], req[6], req[7]]);
+
+        // the target advertises the protocol
+        let dst_atoms = Atoms::intern(&dst).unwrap();
+        dst.send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: window(TARGET),
+            property: dst_atoms.aware,
+            r#type: x::ATOM_ATOM,
+            data: &[XDND_VERSION],
+        })
+        .unwrap();
+
+        let atoms = Atoms::intern(&src).unwrap();
+        assert_eq!(atoms, dst_atoms);
+        let text_plain = {
+            let cookie = src.send_request(&x::InternAtom {
+                only_if_exists: false,
+                name: b"text/plain",
+            });
+            src.wait_for_reply(cookie).unwrap().atom()
+        };
+
+        let mut drag = DragSource::new(&src, atoms, window(SOURCE), &[text_plain], cursors);
+        drag.set_icon_window(window(ICON));
+        assert_eq!(drag.state(), DragState::Idle);
+        assert!(drag.begin(&button(10, 50, 50)).unwrap());
+        assert_eq!(drag.state(), DragState::Dragging);
+        assert_eq!(display.lock().unwrap().selection_owner, SOURCE);
+        {
+            let display = display.lock().unwrap();
+            let (opcode, grab) = &display.pointer_requests[0];
+            assert_eq!(*opcode, 26);
+            assert_eq!(
+                u32::from_ne_bytes([grab[16], grab[17], grab[18], grab[19]]),
+                0x600
+            );
+        }
+
+        // over the desktop: no target
+        assert!(drag.handle_event(&motion(11, 50, 50)).unwrap());
+        assert_eq!(drag.target(), None);
+
+        // over the target: XdndEnter, then XdndPosition
+        assert!(drag.handle_event(&motion(12, 200, 200)).unwrap());
+        assert_eq!(drag.target().map(|t| t.window), Some(window(TARGET)));
+        let ev = match next_event(&dst) {
+            crate::Event::X(x::Event::ClientMessage(ev)) => ev,
+            ev => panic!("unexpected event {:?}", ev),
+        };
+        assert_eq!(
+            dst_atoms.parse(&ev),
+            Some(Message::Enter(Enter {
+                source: window(SOURCE),
+                version: XDND_VERSION,
+                types: vec![text_plain],
+                more_types: false,
+            }))
+        );
+        let ev = match next_event(&dst) {
+            crate::Event::X(x::Event::ClientMessage(ev)) => ev,
+            ev => panic!("unexpected event {:?}", ev),
+        };
+        let position = match dst_atoms.parse(&ev) {
+            Some(Message::Position(position)) => position,
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert_eq!((position.root_x, position.root_y), (200, 200));
+        assert_eq!(position.action, dst_atoms.action_copy);
+
+        // the position is not sent until the status is received
+        assert!(drag.handle_event(&motion(13, 210, 210)).unwrap());
+
+        let send = |conn: &Connection, to: u32, msg: Message| {
+            let event = dst_atoms.client_message(window(to), &msg);
+            conn.send_and_check_request(&x::SendEvent {
+                propagate: false,
+                destination: x::SendEventDest::Window(window(to)),
+                event_mask: x::EventMask::NO_EVENT,
+                event: &event,
+            })
+            .unwrap();
+        };
+        send(
+            &dst,
+            SOURCE,
+            Message::Status(Status {
+                target: window(TARGET),
+                accept: true,
+                want_position: true,
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+                action: dst_atoms.action_copy,
+            }),
+        );
+
+        // the cursor changes, and the pending position is sent
+        let ev = next_event(&src);
+        assert!(drag.handle_event(&ev).unwrap());
+        assert!(drag.accepted());
+        {
+            let display = display.lock().unwrap();
+            let (opcode, change) = display.pointer_requests.last().unwrap();
+            assert_eq!(*opcode, 30);
+            assert_eq!(cursor_of(change), 0x601);
+        }
+        let ev = match next_event(&dst) {
+            crate::Event::X(x::Event::ClientMessage(ev)) => ev,
+            ev => panic!("unexpected event {:?}", ev),
+        };
+        match dst_atoms.parse(&ev) {
+            Some(Message::Position(position)) => {
+                assert_eq!((position.root_x, position.root_y), (210, 210));
+                assert_eq!(position.time, 13);
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+
+        // drop
+        let release = crate::Event::X(x::Event::ButtonRelease(button(14, 210, 210)));
+        assert!(drag.handle_event(&release).unwrap());
+        assert_eq!(drag.state(), DragState::Dropped);
+        assert_eq!(
+            display.lock().unwrap().pointer_requests.last().unwrap().0,
+            27
+        );
+        let ev = match next_event(&dst) {
+            crate::Event::X(x::Event::ClientMessage(ev)) => ev,
+            ev => panic!("unexpected event {:?}", ev),
+        };
+        let time = match dst_atoms.parse(&ev) {
+            Some(Message::Drop(drop)) => drop.time,
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert_eq!(time, 14);
+
+        // the target converts the selection, answered by the selection code of the source
+        dst.send_and_check_request(&x::ConvertSelection {
+            requestor: window(TARGET),
+            selection: dst_atoms.selection,
+            target: text_plain,
+            property: dst_atoms.selection,
+            time,
+        })
+        .unwrap();
+        let request = match next_event(&src) {
+            crate::Event::X(x::Event::SelectionRequest(ev)) => ev,
+            ev => panic!("unexpected event {:?}", ev),
+        };
+        assert!(!drag
+            .handle_event(&crate::Event::X(x::Event::SelectionRequest(
+                x::SelectionRequestEvent::new(
+                    request.time(),
+                    request.owner(),
+                    request.requestor(),
+                    request.selection(),
+                    request.target(),
+                    request.property(),
+                )
+            )))
+            .unwrap());
+        assert_eq!(request.owner(), window(SOURCE));
+        assert_eq!(request.target(), text_plain);
+        src.send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: request.requestor(),
+            property: request.property(),
+            r#type: text_plain,
+            data: b"hello",
+        })
+        .unwrap();
+        let notify = x::SelectionNotifyEvent::new(
+            request.time(),
+            request.requestor(),
+            request.selection(),
+            request.target(),
+            request.property(),
+        );
+        src.send_and_check_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(request.requestor()),
+            event_mask: x::EventMask::NO_EVENT,
+            event: &notify,
+        })
+        .unwrap();
+
+        let notify = match next_event(&dst) {
+            crate::Event::X(x::Event::SelectionNotify(ev)) => ev,
+            ev => panic!("unexpected event {:?}", ev),
+        };
+        let cookie = dst.send_request(&x::GetProperty {
+            delete: true,
+            window: window(TARGET),
+            property: notify.property(),
+            r#type: x::ATOM_ANY,
+            long_offset: 0,
+            long_length: 1024,
+        });
+        let reply = dst.wait_for_reply(cookie).unwrap();
+        assert_eq!(reply.r#type(), text_plain);
+        assert_eq!(reply.value::<u8>(), b"hello");
+
+        send(
+            &dst,
+            SOURCE,
+            Message::Finished(Finished {
+                target: window(TARGET),
+                success: true,
+                action: dst_atoms.action_copy,
+            }),
+        );
+        let ev = next_event(&src);
+        assert!(drag.handle_event(&ev).unwrap());
+        assert_eq!(
+            drag.state(),
+            DragState::Finished {
+                success: true,
+                action: atoms.action_copy
+            }
+        );
+    }
+
+    #[test]
+    fn test_drag_cancelled() {
+        let (src, dst, display) = mock_display();
+        let atoms = Atoms::intern(&src).unwrap();
+        display.lock().unwrap().properties.insert(
+            (TARGET, atoms.aware.resource_id()),
+            (x::ATOM_ATOM.resource_id(), 32, 5u32.to_ne_bytes().to_vec()),
+        );
+        let cursors = DragCursors {
+            reject: unsafe { x::Cursor::new(0x600) },
+            accept: unsafe { x::Cursor::new(0x601) },
+        };
+        let types: Vec<_> = (0..4).map(|i| atom(2000 + i)).collect();
+
+        let mut drag = DragSource::new(&src, atoms, window(SOURCE), &types, cursors);
+        drag.set_icon_window(window(ICON));
+        assert!(drag.begin(&button(10, 50, 50)).unwrap());
+        // more than three types: XdndTypeList
+        assert_eq!(
+            display
+                .lock()
+                .unwrap()
+                .properties
+                .get(&(SOURCE, atoms.type_list.resource_id()))
+                .map(|p| p.2.len()),
+            Some(16)
+        );
+
+        assert!(drag.handle_event(&motion(11, 200, 200)).unwrap());
+        match next_event(&dst) {
+            crate::Event::X(x::Event::ClientMessage(ev)) => match atoms.parse(&ev) {
+                Some(Message::Enter(enter)) => {
+                    assert_eq!(enter.types, types[..3]);
+                    assert!(enter.more_types);
+                }
+                msg => panic!("unexpected message {:?}", msg),
+            },
+            ev => panic!("unexpected event {:?}", ev),
+        }
+
+        // released before any status: the target is left
+        let release = crate::Event::X(x::Event::ButtonRelease(button(12, 200, 200)));
+        assert!(drag.handle_event(&release).unwrap());
+        assert_eq!(drag.state(), DragState::Cancelled);
+        // XdndPosition, then XdndLeave
+        next_event(&dst);
+        match next_event(&dst) {
+            crate::Event::X(x::Event::ClientMessage(ev)) => {
+                assert!(matches!(atoms.parse(&ev), Some(Message::Leave(_))));
+            }
+            ev => panic!("unexpected event {:?}", ev),
+        }
+
+        // not dragging anymore
+        assert!(!drag.handle_event(&motion(13, 50, 50)).unwrap());
+        drag.cancel().unwrap();
+        assert_eq!(drag.state(), DragState::Cancelled);
+    }
+}
@@ -0,0 +1,78 @@
+//! Conversions between the XInput fixed-point types and `f64`.
+
+use super::{Fp1616, Fp3232};
+
+const FP1616_ONE: f64 = 65536.0;
+const FP3232_ONE: f64 = 4294967296.0;
+
+/// Converts a 16.16 fixed-point value to `f64`.
+pub fn fp1616_to_f64(val: Fp1616) -> f64 {
+    val as f64 / FP1616_ONE
+}
+
+/// Converts a `f64` to a 16.16 fixed-point value.
+///
+/// The value is rounded to the nearest representable value and saturates
+/// at the bounds of the type.
+pub fn f64_to_fp1616(val: f64) -> Fp1616 {
+    (val * FP1616_ONE).round() as Fp1616
+}
+
+impl Fp3232 {
+    /// Converts this 32.32 fixed-point value to `f64`.
+    pub fn to_f64(self) -> f64 {
+        self.integral as f64 + self.frac as f64 / FP3232_ONE
+    }
+
+    /// Builds a 32.32 fixed-point value from a `f64`.
+    ///
+    /// As in the protocol, `frac` is always a positive fraction added to
+    /// `integral`, so `-1.5` is encoded as `integral: -2, frac: 0x8000_0000`.
+    pub fn from_f64(val: f64) -> Fp3232 {
+        let integral = val.floor();
+        let frac = ((val - integral) * FP3232_ONE).round();
+        if frac >= FP3232_ONE {
+            Fp3232 {
+                integral: integral as i32 + 1,
+                frac: 0,
+            }
+        } else {
+            Fp3232 {
+                integral: integral as i32,
+                frac: frac as u32,
+            }
+        }
+    }
+}
+
+impl From<Fp3232> for f64 {
+    fn from(val: Fp3232) -> f64 {
+        val.to_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fp1616() {
+        assert_eq!(fp1616_to_f64(0x0001_8000), 1.5);
+        assert_eq!(fp1616_to_f64(-0x0001_8000), -1.5);
+        assert_eq!(f64_to_fp1616(-1.5), -0x0001_8000);
+        assert_eq!(f64_to_fp1616(fp1616_to_f64(-12345)), -12345);
+    }
+
+    #[test]
+    fn test_fp3232() {
+        let val = Fp3232::from_f64(-1.5);
+        assert_eq!(val.integral, -2);
+        assert_eq!(val.frac, 0x8000_0000);
+        assert_eq!(val.to_f64(), -1.5);
+
+        let val = Fp3232::from_f64(3.25);
+        assert_eq!(val.integral, 3);
+        assert_eq!(val.frac, 0x4000_0000);
+        assert_eq!(f64::from(val), 3.25);
+    }
+}
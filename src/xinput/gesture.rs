@@ -0,0 +1,263 @@
+//! Helpers for the touchpad gesture events introduced in XInput 2.4.
+
+use super::{
+    fp1616_to_f64, Event, GesturePinchBeginEvent, GesturePinchEventFlags, GestureSwipeBeginEvent,
+    GestureSwipeEventFlags, XiEventMask,
+};
+
+impl XiEventMask {
+    /// Selects `GestureSwipeEnd` events.
+    ///
+    /// `GestureSwipeEnd` is bit 32 of the XI2 event mask, which does not fit in the first mask
+    /// word. This flag must therefore be placed in the *second* word of the mask:
+    /// ```no_run
+    /// # use xcb::xinput;
+    /// # fn f(device: xinput::Device) {
+    /// let mask = xinput::EventMaskBuf::new(
+    ///     device,
+    ///     &[
+    ///         xinput::XiEventMask::GESTURE_SWIPE_BEGIN | xinput::XiEventMask::GESTURE_SWIPE_UPDATE,
+    ///         xinput::XiEventMask::GESTURE_SWIPE_END_WORD1,
+    ///     ],
+    /// );
+    /// # }
+    /// ```
+    pub const GESTURE_SWIPE_END_WORD1: XiEventMask = unsafe { XiEventMask::from_bits_unchecked(1) };
+}
+
+impl GesturePinchBeginEvent {
+    /// The motion delta of the gesture center, in screen coordinates.
+    pub fn delta_f64(&self) -> (f64, f64) {
+        (fp1616_to_f64(self.delta_x()), fp1616_to_f64(self.delta_y()))
+    }
+
+    /// The motion delta of the gesture center, without pointer acceleration.
+    pub fn delta_unaccel_f64(&self) -> (f64, f64) {
+        (
+            fp1616_to_f64(self.delta_unaccel_x()),
+            fp1616_to_f64(self.delta_unaccel_y()),
+        )
+    }
+
+    /// The absolute scale of the pinch, relative to the beginning of the gesture.
+    pub fn scale_f64(&self) -> f64 {
+        fp1616_to_f64(self.scale())
+    }
+
+    /// The rotation delta of the pinch since the previous event, in degrees.
+    pub fn delta_angle_f64(&self) -> f64 {
+        fp1616_to_f64(self.delta_angle())
+    }
+
+    /// Whether the gesture was cancelled (only meaningful for `GesturePinchEnd`).
+    pub fn is_cancelled(&self) -> bool {
+        self.flags()
+            .contains(GesturePinchEventFlags::GESTURE_PINCH_CANCELLED)
+    }
+}
+
+impl GestureSwipeBeginEvent {
+    /// The motion delta of the gesture center, in screen coordinates.
+    pub fn delta_f64(&self) -> (f64, f64) {
+        (fp1616_to_f64(self.delta_x()), fp1616_to_f64(self.delta_y()))
+    }
+
+    /// The motion delta of the gesture center, without pointer acceleration.
+    pub fn delta_unaccel_f64(&self) -> (f64, f64) {
+        (
+            fp1616_to_f64(self.delta_unaccel_x()),
+            fp1616_to_f64(self.delta_unaccel_y()),
+        )
+    }
+
+    /// Whether the gesture was cancelled (only meaningful for `GestureSwipeEnd`).
+    pub fn is_cancelled(&self) -> bool {
+        self.flags()
+            .contains(GestureSwipeEventFlags::GESTURE_SWIPE_CANCELLED)
+    }
+}
+
+/// The aggregated state of a pinch gesture.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PinchGesture {
+    /// The number of fingers of the gesture.
+    pub fingers: u32,
+    /// The cumulative scale since the beginning of the gesture.
+    pub scale: f64,
+    /// The cumulative rotation since the beginning of the gesture, in degrees.
+    pub rotation: f64,
+    /// The cumulative translation of the gesture center.
+    pub translation: (f64, f64),
+    /// Whether the gesture was cancelled.
+    pub cancelled: bool,
+}
+
+/// Aggregates a sequence of pinch events into a [PinchGesture].
+///
+/// Feed all received XInput events to [GestureRecognizer::handle_event]. The complete gesture
+/// is returned when the `GesturePinchEnd` event is received.
+#[derive(Debug, Default)]
+pub struct GestureRecognizer {
+    current: Option<PinchGesture>,
+}
+
+impl GestureRecognizer {
+    pub fn new() -> GestureRecognizer {
+        GestureRecognizer { current: None }
+    }
+
+    /// The pinch gesture in progress, if any.
+    pub fn current(&self) -> Option<&PinchGesture> {
+        self.current.as_ref()
+    }
+
+    /// Processes `event` and returns the complete gesture if `event` ended a pinch.
+    ///
+    /// Events that are not pinch events are ignored.
+    pub fn handle_event(&mut self, event: &Event) -> Option<PinchGesture> {
+        match event {
+            Event::GesturePinchBegin(ev) => {
+                let mut gesture = PinchGesture {
+                    fingers: ev.detail(),
+                    scale: 1.0,
+                    rotation: 0.0,
+                    translation: (0.0, 0.0),
+                    cancelled: false,
+                };
+                Self::accumulate(&mut gesture, ev);
+                self.current = Some(gesture);
+                None
+            }
+            Event::GesturePinchUpdate(ev) => {
+                if let Some(gesture) = self.current.as_mut() {
+                    Self::accumulate(gesture, ev);
+                }
+                None
+            }
+            Event::GesturePinchEnd(ev) => {
+                let mut gesture = self.current.take()?;
+                Self::accumulate(&mut gesture, ev);
+                gesture.cancelled = ev.is_cancelled();
+                Some(gesture)
+            }
+            _ => None,
+        }
+    }
+
+    fn accumulate(gesture: &mut PinchGesture, ev: &GesturePinchBeginEvent) {
+        let (dx, dy) = ev.delta_f64();
+        gesture.scale = ev.scale_f64();
+        gesture.rotation += ev.delta_angle_f64();
+        gesture.translation.0 += dx;
+        gesture.translation.1 += dy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pinch_event_data(
+        evtype: u16,
+        delta: (i32, i32),
+        scale: i32,
+        angle: i32,
+        flags: u32,
+    ) -> Vec<u8> {
+        let mut data = vec![0u8; 104];
+        data[0] = 35; // GenericEvent
+        data[4..8].copy_from_slice(&18u32.to_ne_bytes());
+        data[8..10].copy_from_slice(&evtype.to_ne_bytes());
+        data[10..12].copy_from_slice(&2u16.to_ne_bytes());
+        data[16..20].copy_from_slice(&2u32.to_ne_bytes()); // detail: 2 fingers
+        data[52..56].copy_from_slice(&delta.0.to_ne_bytes());
+        data[56..60].copy_from_slice(&delta.1.to_ne_bytes());
+        data[68..72].copy_from_slice(&scale.to_ne_bytes());
+        data[72..76].copy_from_slice(&angle.to_ne_bytes());
+        data[100..104].copy_from_slice(&flags.to_ne_bytes());
+        data
+    }
+
+    fn pinch_event(evtype: u16, delta: (i32, i32), scale: i32, angle: i32, flags: u32) -> Event {
+        use crate::base::WiredIn;
+
+        let data = pinch_event_data(evtype, delta, scale, angle, flags);
+        let mut offset = 0;
+        let ev = unsafe { GesturePinchBeginEvent::unserialize(data.as_ptr(), (), &mut offset) };
+        assert_eq!(offset, data.len());
+        match evtype {
+            27 => Event::GesturePinchBegin(ev),
+            28 => Event::GesturePinchUpdate(ev),
+            29 => Event::GesturePinchEnd(ev),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_pinch_event_accessors() {
+        let ev = match pinch_event(
+            28,
+            (-0x0001_8000, 0x0000_4000),
+            0x0002_0000,
+            -0x0000_8000,
+            0,
+        ) {
+            Event::GesturePinchUpdate(ev) => ev,
+            _ => unreachable!(),
+        };
+        assert_eq!(ev.detail(), 2);
+        assert_eq!(ev.delta_f64(), (-1.5, 0.25));
+        assert_eq!(ev.scale_f64(), 2.0);
+        assert_eq!(ev.delta_angle_f64(), -0.5);
+        assert!(!ev.is_cancelled());
+    }
+
+    #[test]
+    fn test_gesture_recognizer() {
+        let mut recognizer = GestureRecognizer::new();
+        let one = 0x0001_0000;
+
+        assert!(recognizer
+            .handle_event(&pinch_event(27, (0, 0), one, 0, 0))
+            .is_none());
+        assert!(recognizer
+            .handle_event(&pinch_event(28, (-one, 2 * one), 3 * one / 2, 10 * one, 0))
+            .is_none());
+        assert!(recognizer
+            .handle_event(&pinch_event(28, (-one, 0), 2 * one, -4 * one, 0))
+            .is_none());
+        assert_eq!(recognizer.current().unwrap().scale, 2.0);
+
+        let gesture = recognizer
+            .handle_event(&pinch_event(29, (0, 0), 5 * one / 2, 0, 0))
+            .unwrap();
+        assert_eq!(
+            gesture,
+            PinchGesture {
+                fingers: 2,
+                scale: 2.5,
+                rotation: 6.0,
+                translation: (-2.0, 2.0),
+                cancelled: false,
+            }
+        );
+        assert!(recognizer.current().is_none());
+    }
+
+    #[test]
+    fn test_gesture_recognizer_cancelled() {
+        let mut recognizer = GestureRecognizer::new();
+        let one = 0x0001_0000;
+
+        // an end without begin is ignored
+        assert!(recognizer
+            .handle_event(&pinch_event(29, (0, 0), one, 0, 1))
+            .is_none());
+
+        recognizer.handle_event(&pinch_event(27, (0, 0), one, 0, 0));
+        let gesture = recognizer
+            .handle_event(&pinch_event(29, (0, 0), one, 0, 1))
+            .unwrap();
+        assert!(gesture.cancelled);
+    }
+}
@@ -0,0 +1,318 @@
+//! Replay of recorded core input with `FakeInput`.
+
+use super::FakeInput;
+use crate::base::{Connection, Result, ResultExt};
+use crate::event::Event;
+use crate::x;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The core event types injected by `FakeInput`.
+const KEY_PRESS: u8 = 2;
+const KEY_RELEASE: u8 = 3;
+const BUTTON_PRESS: u8 = 4;
+const BUTTON_RELEASE: u8 = 5;
+const MOTION_NOTIFY: u8 = 6;
+
+/// An input action of a [Step].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Action {
+    KeyPress(x::Keycode),
+    KeyRelease(x::Keycode),
+    ButtonPress(x::Button),
+    ButtonRelease(x::Button),
+    /// Moves the pointer to the root coordinates `x` and `y`.
+    Motion {
+        x: i16,
+        y: i16,
+    },
+}
+
+/// An action of a [Script], and when it is performed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Step {
+    /// The offset from the start of the script, in milliseconds.
+    pub offset_ms: u32,
+    pub action: Action,
+}
+
+/// A sequence of timed input actions, replayed with `FakeInput`.
+///
+/// With the `serde` cargo feature, the script is serializable.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Script {
+    /// The steps, in the order of their offsets.
+    pub steps: Vec<Step>,
+    /// The size in pixels of the screen the script was recorded on.
+    ///
+    /// When set, the coordinates of the motions are scaled to the size of the screen
+    /// the script is replayed on. When `None`, they are replayed as is.
+    pub screen_size: Option<(u16, u16)>,
+}
+
+impl Script {
+    /// Builds a script from recorded core input events.
+    ///
+    /// The key, button and motion events are kept, the others are ignored. The steps are
+    /// paced by the server timestamps of the events, relative to the first kept one.
+    /// The screen size is not known from the events: set [Script::screen_size] to
+    /// scale the motions on replay.
+    pub fn from_events(events: &[Event]) -> Script {
+        let recorded: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::X(x::Event::KeyPress(ev)) => {
+                    Some((ev.time(), Action::KeyPress(ev.detail())))
+                }
+                Event::X(x::Event::KeyRelease(ev)) => {
+                    Some((ev.time(), Action::KeyRelease(ev.detail())))
+                }
+                Event::X(x::Event::ButtonPress(ev)) => {
+                    Some((ev.time(), Action::ButtonPress(ev.detail())))
+                }
+                Event::X(x::Event::ButtonRelease(ev)) => {
+                    Some((ev.time(), Action::ButtonRelease(ev.detail())))
+                }
+                Event::X(x::Event::MotionNotify(ev)) => Some((
+                    ev.time(),
+                    Action::Motion {
+                        x: ev.root_x(),
+                        y: ev.root_y(),
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+        let times: Vec<_> = recorded.iter().map(|(time, _)| *time).collect();
+        Script {
+            steps: timestamp_offsets(&times)
+                .into_iter()
+                .zip(recorded)
+                .map(|(offset_ms, (_, action))| Step { offset_ms, action })
+                .collect(),
+            screen_size: None,
+        }
+    }
+
+    /// The duration of the script at normal speed.
+    pub fn duration(&self) -> Duration {
+        let offset_ms = self.steps.last().map_or(0, |step| step.offset_ms);
+        Duration::from_millis(offset_ms as u64)
+    }
+
+    /// Replays the script on the screen of the pointer, blocking until the last step.
+    ///
+    /// The offsets are divided by `speed`: `2.0` replays twice as fast. Each step is
+    /// checked before sleeping until the next one.
+    ///
+    /// # Panics
+    /// Panics if `speed` is not strictly positive.
+    pub fn replay(&self, conn: &Connection, speed: f32) -> Result<()> {
+        assert!(speed > 0.0, "invalid replay speed {}", speed);
+
+        let setup = conn.get_setup();
+        let first_root = setup.roots().next().expect("no screen").root();
+        let cookie = conn.send_request(&x::QueryPointer { window: first_root });
+        let root = conn
+            .wait_for_reply(cookie)
+            .context("QueryPointer", || "of the first screen")?
+            .root();
+        let screen = setup
+            .roots()
+            .find(|screen| screen.root() == root)
+            .expect("the pointer is on an unknown screen");
+        let size = (screen.width_in_pixels(), screen.height_in_pixels());
+
+        let start = Instant::now();
+        for (i, step) in self.steps.iter().enumerate() {
+            let due =
+                start + Duration::from_secs_f64(step.offset_ms as f64 / 1000.0 / speed as f64);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+
+            let (r#type, detail, (root_x, root_y)) = match step.action {
+                Action::KeyPress(key) => (KEY_PRESS, key, (0, 0)),
+                Action::KeyRelease(key) => (KEY_RELEASE, key, (0, 0)),
+                Action::ButtonPress(button) => (BUTTON_PRESS, button, (0, 0)),
+                Action::ButtonRelease(button) => (BUTTON_RELEASE, button, (0, 0)),
+                Action::Motion { x, y } => {
+                    let pos = match self.screen_size {
+                        Some((width, height)) => {
+                            (remap(x, width, size.0), remap(y, height, size.1))
+                        }
+                        None => (x, y),
+                    };
+                    // detail 0 is an absolute motion
+                    (MOTION_NOTIFY, 0, pos)
+                }
+            };
+            conn.send_and_check_request(&FakeInput {
+                r#type,
+                detail,
+                time: x::CURRENT_TIME,
+                root,
+                root_x,
+                root_y,
+                deviceid: 0,
+            })
+            .context("FakeInput", || format!("step {} ({:?})", i, step.action))?;
+        }
+        Ok(())
+    }
+}
+
+/// The offsets in milliseconds of the server timestamps `times` relative to the first.
+///
+/// The timestamps wrap around after about 49.7 days, so the difference between
+/// consecutive timestamps is computed modulo 2^32. A timestamp older than the previous
+/// one (a difference of more than 2^31) gives the same offset as the previous one.
+fn timestamp_offsets(times: &[x::Timestamp]) -> Vec<u32> {
+    let mut offset = 0u32;
+    let mut prev = match times.first() {
+        Some(time) => *time,
+        None => return Vec::new(),
+    };
+    times
+        .iter()
+        .map(|time| {
+            let delta = time.wrapping_sub(prev) as i32;
+            if delta > 0 {
+                offset = offset.saturating_add(delta as u32);
+                prev = *time;
+            }
+            offset
+        })
+        .collect()
+}
+
+/// Scales the coordinate `pos` of a screen dimension of `from` pixels to one of `to`
+/// pixels, such that the first and last pixels are kept.
+fn remap(pos: i16, from: u16, to: u16) -> i16 {
+    if from == to || from <= 1 || to == 0 {
+        return pos;
+    }
+    let scaled = pos as f64 * (to - 1) as f64 / (from - 1) as f64;
+    scaled.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_offsets() {
+        assert_eq!(timestamp_offsets(&[]), Vec::<u32>::new());
+        assert_eq!(timestamp_offsets(&[1000]), vec![0]);
+        assert_eq!(
+            timestamp_offsets(&[1000, 1000, 1016, 1500]),
+            vec![0, 0, 16, 500]
+        );
+
+        // the server time wraps around
+        assert_eq!(
+            timestamp_offsets(&[u32::MAX - 10, u32::MAX, 5, 100]),
+            vec![0, 10, 16, 111]
+        );
+
+        // an out of order timestamp does not go back in time, nor forward by ~49 days
+        assert_eq!(
+            timestamp_offsets(&[1000, 1100, 1050, 1200]),
+            vec![0, 100, 100, 200]
+        );
+        assert_eq!(timestamp_offsets(&[5, u32::MAX, 20]), vec![0, 0, 15]);
+    }
+
+    #[test]
+    fn test_remap() {
+        // same size
+        assert_eq!(remap(123, 1920, 1920), 123);
+
+        // the edges are kept
+        assert_eq!(remap(0, 1920, 3840), 0);
+        assert_eq!(remap(1919, 1920, 3840), 3839);
+        assert_eq!(remap(1919, 1920, 1280), 1279);
+        assert_eq!(remap(1079, 1080, 720), 719);
+
+        // rounded to the nearest pixel
+        assert_eq!(remap(960, 1920, 1280), 640);
+        assert_eq!(remap(1, 1920, 3840), 2);
+
+        // off screen positions are scaled too
+        assert_eq!(remap(-10, 1001, 2001), -20);
+
+        // degenerate sizes
+        assert_eq!(remap(5, 1, 100), 5);
+        assert_eq!(remap(5, 100, 0), 5);
+    }
+
+    #[test]
+    fn test_script_from_events() {
+        use crate::base::Xid;
+
+        let root = x::Window::none();
+        let key = |time, detail| {
+            Event::X(x::Event::KeyPress(x::KeyPressEvent::new(
+                detail,
+                time,
+                root,
+                root,
+                x::Window::none(),
+                0,
+                0,
+                0,
+                0,
+                x::KeyButMask::empty(),
+                true,
+            )))
+        };
+        let motion = |time, root_x, root_y| {
+            Event::X(x::Event::MotionNotify(x::MotionNotifyEvent::new(
+                x::Motion::Normal,
+                time,
+                root,
+                root,
+                x::Window::none(),
+                root_x,
+                root_y,
+                0,
+                0,
+                x::KeyButMask::empty(),
+                true,
+            )))
+        };
+        let events = [
+            Event::X(x::Event::FocusIn(x::FocusInEvent::new(
+                x::NotifyDetail::Nonlinear,
+                root,
+                x::NotifyMode::Normal,
+            ))),
+            motion(u32::MAX - 4, 10, 20),
+            key(10, 38),
+        ];
+
+        let script = Script::from_events(&events);
+        assert_eq!(
+            script,
+            Script {
+                steps: vec![
+                    Step {
+                        offset_ms: 0,
+                        action: Action::Motion { x: 10, y: 20 },
+                    },
+                    Step {
+                        offset_ms: 15,
+                        action: Action::KeyPress(38),
+                    },
+                ],
+                screen_size: None,
+            }
+        );
+        assert_eq!(script.duration(), Duration::from_millis(15));
+    }
+}
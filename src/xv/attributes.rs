@@ -0,0 +1,277 @@
+//! Decoding of the port attributes notifications.
+
+use super::{AttributeFlag, AttributeInfo, Port, PortNotifyEvent, QueryPortAttributes};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+use std::collections::hash_map::{Entry, HashMap};
+
+impl PortNotifyEvent {
+    /// Fetches the name of the attribute that changed.
+    ///
+    /// This issues a `GetAtomName` request and waits for the reply.
+    /// Use a [PortAttributeMonitor] to classify events without round trip.
+    pub fn attribute_name(&self, conn: &Connection) -> Result<String> {
+        atom_name(conn, self.attribute())
+    }
+}
+
+/// The name of an attribute, without the terminating NUL.
+pub(super) fn attribute_info_name(attr: &AttributeInfo) -> &[u8] {
+    // the size of the name includes the terminating NUL
+    let name = attr.name().as_bytes();
+    name.split(|b| *b == 0).next().unwrap_or_default()
+}
+
+fn atom_name(conn: &Connection, atom: x::Atom) -> Result<String> {
+    let cookie = conn.send_request(&x::GetAtomName { atom });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("GetAtomName", || format!("for atom {}", atom.resource_id()))?;
+    Ok(reply.name().to_utf8().into_owned())
+}
+
+/// An attribute of a port, as returned by `QueryPortAttributes`.
+#[derive(Clone, Debug)]
+pub struct PortAttribute {
+    /// The atom that identifies the attribute in the notifications.
+    pub atom: x::Atom,
+    /// The attribute name (e.g. `"XV_BRIGHTNESS"`).
+    pub name: String,
+    pub flags: AttributeFlag,
+    pub min: i32,
+    pub max: i32,
+}
+
+/// Classifies the [PortNotifyEvent] of a port by attribute name.
+///
+/// The atoms of all the attributes of the port are resolved when the monitor is built,
+/// such that [PortAttributeMonitor::classify] does not need any round trip to the server.
+///
+/// # Example
+/// ```no_run
+/// # use xcb::xv;
+/// # fn main() -> xcb::Result<()> {
+/// # let (conn, _) = xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Xv], &[])?;
+/// # let port: xv::Port = unsafe { xcb::XidNew::new(0) };
+/// let mut monitor = xv::PortAttributeMonitor::new(&conn, port)?;
+/// conn.send_request(&xv::SelectPortNotify { port, onoff: true });
+/// loop {
+///     if let xcb::Event::Xv(xv::Event::PortNotify(ev)) = conn.wait_for_event()? {
+///         if let Some(("XV_BRIGHTNESS", value)) = monitor.classify_or_fetch(&conn, &ev)? {
+///             println!("brightness is now {}", value);
+///         }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PortAttributeMonitor {
+    port: Port,
+    attributes: Vec<PortAttribute>,
+    names: HashMap<x::Atom, String>,
+}
+
+impl PortAttributeMonitor {
+    /// Queries the attributes of `port` and resolves their atoms.
+    ///
+    /// All the `InternAtom` requests are pipelined.
+    pub fn new(conn: &Connection, port: Port) -> Result<PortAttributeMonitor> {
+        let cookie = conn.send_request(&QueryPortAttributes { port });
+        let reply = conn
+            .wait_for_reply(cookie)
+            .context("QueryPortAttributes", || {
+                format!("for port {}", port.resource_id())
+            })?;
+
+        let cookies: Vec<_> = reply
+            .attributes()
+            .map(|attr| {
+                let name = attribute_info_name(attr);
+                let cookie = conn.send_request(&x::InternAtom {
+                    only_if_exists: false,
+                    name,
+                });
+                (cookie, attr)
+            })
+            .collect();
+
+        let mut attributes = Vec::with_capacity(cookies.len());
+        for (cookie, attr) in cookies {
+            let name = crate::Lat1Str::from_bytes(attribute_info_name(attr))
+                .to_utf8()
+                .into_owned();
+            let atom = conn
+                .wait_for_reply(cookie)
+                .context("InternAtom", || name.clone())?
+                .atom();
+            attributes.push(PortAttribute {
+                atom,
+                name,
+                flags: attr.flags(),
+                min: attr.min(),
+                max: attr.max(),
+            });
+        }
+
+        Ok(Self::with_attributes(port, attributes))
+    }
+
+    /// Builds a monitor with already resolved attributes.
+    pub fn with_attributes(port: Port, attributes: Vec<PortAttribute>) -> PortAttributeMonitor {
+        let names = attributes
+            .iter()
+            .map(|attr| (attr.atom, attr.name.clone()))
+            .collect();
+        PortAttributeMonitor {
+            port,
+            attributes,
+            names,
+        }
+    }
+
+    /// The monitored port.
+    pub fn port(&self) -> Port {
+        self.port
+    }
+
+    /// The attributes of the monitored port.
+    pub fn attributes(&self) -> &[PortAttribute] {
+        &self.attributes
+    }
+
+    /// Returns the attribute name and the new value notified by `ev`.
+    ///
+    /// Returns `None` if `ev` is for another port or if the attribute atom is unknown.
+    pub fn classify(&self, ev: &PortNotifyEvent) -> Option<(&str, i32)> {
+        if ev.port() != self.port {
+            return None;
+        }
+        self.names
+            .get(&ev.attribute())
+            .map(|name| (name.as_str(), ev.value()))
+    }
+
+    /// Same as [PortAttributeMonitor::classify], but fetches the name of unknown attribute atoms.
+    ///
+    /// The fetched name is cached for the next events.
+    pub fn classify_or_fetch(
+        &mut self,
+        conn: &Connection,
+        ev: &PortNotifyEvent,
+    ) -> Result<Option<(&str, i32)>> {
+        if ev.port() != self.port {
+            return Ok(None);
+        }
+        let name = match self.names.entry(ev.attribute()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(atom_name(conn, ev.attribute())?),
+        };
+        Ok(Some((name.as_str(), ev.value())))
+    }
+}
+
+#[cfg(test)]
+pub(super) fn port_attributes_reply(seq: u16, names: &[&str]) -> Vec<u8> {
+    let mut body = vec![0u8; 24];
+    body[0..4].copy_from_slice(&(names.len() as u32).to_ne_bytes());
+    for (i, name) in names.iter().enumerate() {
+        let size = name.len() + 1;
+        body.extend_from_slice(&3u32.to_ne_bytes()); // gettable | settable
+        body.extend_from_slice(&(-(i as i32) * 100).to_ne_bytes());
+        body.extend_from_slice(&(i as i32 * 100).to_ne_bytes());
+        body.extend_from_slice(&(size as u32).to_ne_bytes());
+        body.extend_from_slice(name.as_bytes());
+        body.push(0);
+        body.resize(body.len() + crate::base::align_pad(size, 4), 0);
+    }
+    crate::mock::reply(seq, 0, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_connection(names: &'static [&'static str]) -> Connection {
+        use crate::mock;
+        use std::convert::TryInto;
+
+        mock::connect(move |req| match req.opcode {
+            // QueryExtension
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            // xv::QueryPortAttributes
+            140 if req.data[1] == 15 => vec![port_attributes_reply(req.seq, names)],
+            // InternAtom: the atom is 1000 + the index of the name
+            16 => {
+                let len = u16::from_ne_bytes(req.data[4..6].try_into().unwrap()) as usize;
+                let name = std::str::from_utf8(&req.data[8..8 + len]).unwrap();
+                let atom = 1000 + names.iter().position(|n| *n == name).unwrap() as u32;
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            // GetAtomName
+            17 => {
+                let name = format!("ATOM_{}", req.u32_at(4));
+                let mut body = vec![0u8; 24];
+                body[0..2].copy_from_slice(&(name.len() as u16).to_ne_bytes());
+                body.extend_from_slice(name.as_bytes());
+                vec![mock::reply(req.seq, 0, &body)]
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    #[test]
+    fn test_port_attribute_monitor() {
+        use crate::XidNew;
+
+        let conn = mock_connection(&["XV_BRIGHTNESS", "XV_CONTRAST", "XV_COLORKEY"]);
+        let port = unsafe { Port::new(42) };
+        let other_port = unsafe { Port::new(43) };
+        let atom = |id| unsafe { x::Atom::new(id) };
+
+        let mut monitor = PortAttributeMonitor::new(&conn, port).unwrap();
+        assert_eq!(monitor.attributes().len(), 3);
+        assert_eq!(monitor.attributes()[1].name, "XV_CONTRAST");
+        assert_eq!(monitor.attributes()[1].min, -100);
+        assert_eq!(monitor.attributes()[1].max, 100);
+
+        let ev = PortNotifyEvent::new(80, 0, port, atom(1000), -12);
+        assert_eq!(monitor.classify(&ev), Some(("XV_BRIGHTNESS", -12)));
+        let ev = PortNotifyEvent::new(80, 0, port, atom(1002), 0x00ff00);
+        assert_eq!(monitor.classify(&ev), Some(("XV_COLORKEY", 0x00ff00)));
+
+        // other port
+        let ev = PortNotifyEvent::new(80, 0, other_port, atom(1000), 1);
+        assert_eq!(monitor.classify(&ev), None);
+        assert_eq!(monitor.classify_or_fetch(&conn, &ev).unwrap(), None);
+
+        // stale cache: the atom is fetched and inserted
+        let ev = PortNotifyEvent::new(80, 0, port, atom(2000), 7);
+        assert_eq!(monitor.classify(&ev), None);
+        assert_eq!(
+            monitor.classify_or_fetch(&conn, &ev).unwrap(),
+            Some(("ATOM_2000", 7))
+        );
+        assert_eq!(monitor.classify(&ev), Some(("ATOM_2000", 7)));
+        assert_eq!(ev.attribute_name(&conn).unwrap(), "ATOM_2000");
+    }
+
+    #[test]
+    fn test_port_attribute_monitor_seeded() {
+        use crate::XidNew;
+
+        let port = unsafe { Port::new(42) };
+        let monitor = PortAttributeMonitor::with_attributes(
+            port,
+            vec![PortAttribute {
+                atom: unsafe { x::Atom::new(77) },
+                name: "XV_HUE".to_string(),
+                flags: AttributeFlag::GETTABLE | AttributeFlag::SETTABLE,
+                min: -1000,
+                max: 1000,
+            }],
+        );
+        let ev = PortNotifyEvent::new(80, 0, port, unsafe { x::Atom::new(77) }, -1000);
+        assert_eq!(monitor.classify(&ev), Some(("XV_HUE", -1000)));
+    }
+}
@@ -0,0 +1,274 @@
+//! Management of the colorkey of the overlay adaptors.
+//!
+//! The video of an overlay adaptor only shows through the pixels of the destination
+//! that have the colorkey value. The drawable must therefore be filled with the colorkey,
+//! unless the adaptor paints it itself (`XV_AUTOPAINT_COLORKEY`).
+//! Texture adaptors have no colorkey.
+
+use super::attributes::attribute_info_name;
+use super::{
+    AttributeFlag, GetPortAttribute, Port, QueryPortAttributes, QueryPortAttributesReply,
+    SetPortAttribute,
+};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+use std::fmt;
+
+const XV_COLORKEY: &str = "XV_COLORKEY";
+const XV_AUTOPAINT_COLORKEY: &str = "XV_AUTOPAINT_COLORKEY";
+
+/// Error returned by [paint_colorkey].
+#[derive(Debug)]
+pub enum ColorkeyError {
+    /// The port has no `XV_COLORKEY` attribute (e.g. a texture adaptor).
+    NotAnOverlayAdaptor {
+        /// The port without colorkey.
+        port: Port,
+    },
+    /// A request failed.
+    Xcb(crate::Error),
+}
+
+impl fmt::Display for ColorkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorkeyError::NotAnOverlayAdaptor { port } => write!(
+                f,
+                "port {} is not an overlay adaptor (no {} attribute)",
+                port.resource_id(),
+                XV_COLORKEY
+            ),
+            ColorkeyError::Xcb(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ColorkeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ColorkeyError::Xcb(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<crate::Error> for ColorkeyError {
+    fn from(err: crate::Error) -> ColorkeyError {
+        ColorkeyError::Xcb(err)
+    }
+}
+
+/// Whether the attributes of `reply` have an attribute `name` with all the `flags`.
+fn has_attribute(reply: &QueryPortAttributesReply, name: &str, flags: AttributeFlag) -> bool {
+    reply
+        .attributes()
+        .any(|attr| attribute_info_name(attr) == name.as_bytes() && attr.flags().contains(flags))
+}
+
+/// Resolves the atom of the attribute `name` of `port` if it has all the `flags`.
+fn probe_attribute(
+    conn: &Connection,
+    port: Port,
+    name: &str,
+    flags: AttributeFlag,
+) -> Result<Option<x::Atom>> {
+    let cookie = conn.send_request(&QueryPortAttributes { port });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("QueryPortAttributes", || {
+            format!("for port {}", port.resource_id())
+        })?;
+    if !has_attribute(&reply, name, flags) {
+        return Ok(None);
+    }
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name: name.as_bytes(),
+    });
+    let atom = conn
+        .wait_for_reply(cookie)
+        .context("InternAtom", || name.to_string())?
+        .atom();
+    Ok(if atom.is_none() { None } else { Some(atom) })
+}
+
+/// Fetches the value of the attribute `name` of `port`.
+///
+/// Returns `None` if the port has no gettable attribute `name`.
+pub fn get_port_attribute_by_name(
+    conn: &Connection,
+    port: Port,
+    name: &str,
+) -> Result<Option<i32>> {
+    let attribute = match probe_attribute(conn, port, name, AttributeFlag::GETTABLE)? {
+        Some(attribute) => attribute,
+        None => return Ok(None),
+    };
+    let cookie = conn.send_request(&GetPortAttribute { port, attribute });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .context("GetPortAttribute", || {
+            format!("{} of port {}", name, port.resource_id())
+        })?;
+    Ok(Some(reply.value()))
+}
+
+/// Sets the value of the attribute `name` of `port`.
+///
+/// Returns `false` if the port has no settable attribute `name`.
+pub fn set_port_attribute_by_name(
+    conn: &Connection,
+    port: Port,
+    name: &str,
+    value: i32,
+) -> Result<bool> {
+    let attribute = match probe_attribute(conn, port, name, AttributeFlag::SETTABLE)? {
+        Some(attribute) => attribute,
+        None => return Ok(false),
+    };
+    conn.send_and_check_request(&SetPortAttribute {
+        port,
+        attribute,
+        value,
+    })
+    .context("SetPortAttribute", || {
+        format!("{} of port {}", name, port.resource_id())
+    })?;
+    Ok(true)
+}
+
+/// Fetches the colorkey pixel of `port`.
+///
+/// Returns `None` if the port has no `XV_COLORKEY` attribute, which is the case
+/// of the texture adaptors.
+pub fn colorkey(conn: &Connection, port: Port) -> Result<Option<u32>> {
+    Ok(get_port_attribute_by_name(conn, port, XV_COLORKEY)?.map(|key| key as u32))
+}
+
+/// Enables or disables the painting of the colorkey by the adaptor of `port`.
+///
+/// Returns `false` if the port has no `XV_AUTOPAINT_COLORKEY` attribute.
+/// The colorkey must then be painted by the client, e.g. with [paint_colorkey].
+pub fn set_autopaint_colorkey(conn: &Connection, port: Port, autopaint: bool) -> Result<bool> {
+    set_port_attribute_by_name(conn, port, XV_AUTOPAINT_COLORKEY, autopaint as i32)
+}
+
+/// Fills `rect` of `drawable` with the colorkey of `port`.
+///
+/// The foreground of `gc` is set to the colorkey.
+/// Returns [ColorkeyError::NotAnOverlayAdaptor] if the port has no colorkey.
+pub fn paint_colorkey(
+    conn: &Connection,
+    port: Port,
+    drawable: x::Drawable,
+    gc: x::Gcontext,
+    rect: x::Rectangle,
+) -> std::result::Result<(), ColorkeyError> {
+    let key = colorkey(conn, port)?.ok_or(ColorkeyError::NotAnOverlayAdaptor { port })?;
+    let change_gc = conn.send_request_checked(&x::ChangeGc {
+        gc,
+        value_list: &[x::Gc::Foreground(key)],
+    });
+    let fill = conn.send_request_checked(&x::PolyFillRectangle {
+        drawable,
+        gc,
+        rectangles: &[rect],
+    });
+    conn.check_request(change_gc).context("ChangeGC", || {
+        format!("foreground of gc {}", gc.resource_id())
+    })?;
+    conn.check_request(fill).context("PolyFillRectangle", || {
+        format!("colorkey of port {}", port.resource_id())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_connection(names: &'static [&'static str], key: u32) -> Connection {
+        use crate::mock;
+        use std::convert::TryInto;
+
+        mock::connect(move |req| match req.opcode {
+            // QueryExtension
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            // xv::QueryPortAttributes
+            140 if req.data[1] == 15 => {
+                vec![crate::xv::attributes::port_attributes_reply(req.seq, names)]
+            }
+            // xv::GetPortAttribute of XV_COLORKEY
+            140 if req.data[1] == 14 => {
+                assert_eq!(req.u32_at(8), 1000);
+                vec![mock::reply(req.seq, 0, &key.to_ne_bytes())]
+            }
+            // InternAtom: the atom is 1000 + the index of the name, or None
+            16 => {
+                let len = u16::from_ne_bytes(req.data[4..6].try_into().unwrap()) as usize;
+                let name = std::str::from_utf8(&req.data[8..8 + len]).unwrap();
+                let atom = names
+                    .iter()
+                    .position(|n| *n == name)
+                    .map_or(0, |i| 1000 + i as u32);
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            _ => mock::default_response(req),
+        })
+    }
+
+    #[test]
+    fn test_colorkey_overlay() {
+        use crate::XidNew;
+
+        let conn = mock_connection(
+            &["XV_COLORKEY", "XV_AUTOPAINT_COLORKEY", "XV_BRIGHTNESS"],
+            0x0101fe,
+        );
+        let port = unsafe { Port::new(42) };
+
+        assert_eq!(colorkey(&conn, port).unwrap(), Some(0x0101fe));
+        assert!(set_autopaint_colorkey(&conn, port, true).unwrap());
+        assert_eq!(
+            get_port_attribute_by_name(&conn, port, "XV_HUE").unwrap(),
+            None
+        );
+
+        let window = unsafe { x::Window::new(12) };
+        let gc = unsafe { x::Gcontext::new(13) };
+        let rect = x::Rectangle {
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 48,
+        };
+        assert!(paint_colorkey(&conn, port, x::Drawable::Window(window), gc, rect).is_ok());
+    }
+
+    #[test]
+    fn test_colorkey_texture() {
+        use crate::XidNew;
+
+        let conn = mock_connection(&["XV_BRIGHTNESS", "XV_SYNC_TO_VBLANK"], 0);
+        let port = unsafe { Port::new(42) };
+
+        assert_eq!(colorkey(&conn, port).unwrap(), None);
+        assert!(!set_autopaint_colorkey(&conn, port, true).unwrap());
+
+        let window = unsafe { x::Window::new(12) };
+        let gc = unsafe { x::Gcontext::new(13) };
+        let rect = x::Rectangle {
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 48,
+        };
+        let err = paint_colorkey(&conn, port, x::Drawable::Window(window), gc, rect).unwrap_err();
+        assert!(matches!(err, ColorkeyError::NotAnOverlayAdaptor { port: p } if p == port));
+        assert_eq!(
+            err.to_string(),
+            "port 42 is not an overlay adaptor (no XV_COLORKEY attribute)"
+        );
+    }
+}
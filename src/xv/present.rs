@@ -0,0 +1,1156 @@
+//! Presentation of YUV video frames on a port, with a software conversion fallback.
+
+use super::upload::{bands, put_image_len, OwnedSegment, UploadStrategy};
+use super::{ListImageFormats, Port, PutImage, QueryImageAttributes, ShmPutImage};
+use crate::base::{Connection, Result, ResultExt};
+use crate::{shm, x};
+
+use std::fmt;
+
+/// Builds a FourCC code, as used in the `id` of the `XVideo` image formats.
+pub const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+/// Planar 4:2:0 YUV, with the Y, U and V planes in this order.
+pub const FOURCC_I420: u32 = fourcc(b"I420");
+/// Planar 4:2:0 YUV, with the Y, V and U planes in this order.
+pub const FOURCC_YV12: u32 = fourcc(b"YV12");
+/// Packed 4:2:2 YUV, with the bytes ordered as Y0 U Y1 V.
+pub const FOURCC_YUY2: u32 = fourcc(b"YUY2");
+
+/// A plane of a [VideoFrame].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plane {
+    /// The rows of the plane.
+    pub data: Vec<u8>,
+    /// The number of bytes from the start of a row to the start of the next one.
+    pub stride: usize,
+}
+
+/// A video frame, as produced by a decoder.
+///
+/// The planes are in the order of the FourCC format (e.g. Y, U, V for I420).
+/// Each plane is laid out with its own stride: the frame is packed into the layout that
+/// the server expects (`xv::QueryImageAttributes`) when it is presented.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VideoFrame {
+    /// The FourCC code of the format of the frame.
+    pub fourcc: u32,
+    /// The width of the frame, in pixels.
+    pub width: u16,
+    /// The height of the frame, in pixels.
+    pub height: u16,
+    /// The planes of the frame.
+    pub planes: Vec<Plane>,
+}
+
+impl VideoFrame {
+    /// Checks that the frame has the planes that its format needs, with enough data.
+    ///
+    /// Formats other than [FOURCC_I420], [FOURCC_YV12] and [FOURCC_YUY2] are passed
+    /// as-is to the server and always considered complete.
+    pub fn is_complete(&self) -> bool {
+        let geometry = plane_geometry(self.fourcc, self.width as usize, self.height as usize);
+        match geometry {
+            Some(geometry) => {
+                self.planes.len() >= geometry.len()
+                    && geometry
+                        .iter()
+                        .zip(&self.planes)
+                        .all(|(&(row_len, rows), plane)| {
+                            rows == 0
+                                || plane.stride >= row_len
+                                    && plane.data.len() >= (rows - 1) * plane.stride + row_len
+                        })
+            }
+            None => true,
+        }
+    }
+}
+
+/// The size in bytes of a row and the number of rows of each plane of the known formats.
+fn plane_geometry(fourcc: u32, width: usize, height: usize) -> Option<Vec<(usize, usize)>> {
+    let chroma = (width.div_ceil(2), height.div_ceil(2));
+    match fourcc {
+        FOURCC_I420 | FOURCC_YV12 => Some(vec![(width, height), chroma, chroma]),
+        FOURCC_YUY2 => Some(vec![(width.div_ceil(2) * 4, height)]),
+        _ => None,
+    }
+}
+
+/// A software conversion from the format of a frame to a format accepted by a port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Conversion {
+    /// The format is accepted as-is.
+    None,
+    /// I420 to YV12 or back: the U and V planes are swapped.
+    SwapChroma,
+    /// I420 or YV12 to YUY2: the planes are interleaved and the chroma is upsampled
+    /// vertically.
+    PlanarToYuy2,
+}
+
+/// Selects how to present frames of format `fourcc` on a port accepting the formats `supported`.
+///
+/// Returns the format to send and the conversion to it, or `None` if there is no
+/// conversion path. Formats are preferred in this order: `fourcc` itself, the planar format
+/// with swapped chroma planes, then YUY2.
+pub fn select_conversion(fourcc: u32, supported: &[u32]) -> Option<(u32, Conversion)> {
+    if supported.contains(&fourcc) {
+        return Some((fourcc, Conversion::None));
+    }
+    let swapped = match fourcc {
+        FOURCC_I420 => FOURCC_YV12,
+        FOURCC_YV12 => FOURCC_I420,
+        _ => return None,
+    };
+    if supported.contains(&swapped) {
+        Some((swapped, Conversion::SwapChroma))
+    } else if supported.contains(&FOURCC_YUY2) {
+        Some((FOURCC_YUY2, Conversion::PlanarToYuy2))
+    } else {
+        None
+    }
+}
+
+/// Converts an I420 frame to YV12, or a YV12 frame to I420.
+///
+/// # Panics
+/// Panics if the frame is neither I420 nor YV12, or has less than 3 planes.
+pub fn swap_chroma(mut frame: VideoFrame) -> VideoFrame {
+    frame.fourcc = match frame.fourcc {
+        FOURCC_I420 => FOURCC_YV12,
+        FOURCC_YV12 => FOURCC_I420,
+        _ => panic!("swap_chroma: not a I420 or YV12 frame"),
+    };
+    frame.planes.swap(1, 2);
+    frame
+}
+
+/// Converts an I420 or YV12 frame to YUY2.
+///
+/// Each chroma row is used for the two rows of luma that it covers. For an odd width,
+/// the last luma sample is duplicated to complete the last pair.
+///
+/// # Panics
+/// Panics if the frame is neither I420 nor YV12, or is not [complete](VideoFrame::is_complete).
+pub fn planar_to_yuy2(frame: &VideoFrame) -> VideoFrame {
+    let (u, v) = match frame.fourcc {
+        FOURCC_I420 => (1, 2),
+        FOURCC_YV12 => (2, 1),
+        _ => panic!("planar_to_yuy2: not a I420 or YV12 frame"),
+    };
+    assert!(frame.is_complete(), "planar_to_yuy2: incomplete frame");
+
+    let width = frame.width as usize;
+    let pairs = width.div_ceil(2);
+    let stride = pairs * 4;
+    let (y_plane, u_plane, v_plane) = (&frame.planes[0], &frame.planes[u], &frame.planes[v]);
+
+    let mut data = Vec::with_capacity(stride * frame.height as usize);
+    for row in 0..frame.height as usize {
+        let luma = &y_plane.data[row * y_plane.stride..][..width];
+        let cb = &u_plane.data[row / 2 * u_plane.stride..][..pairs];
+        let cr = &v_plane.data[row / 2 * v_plane.stride..][..pairs];
+        for i in 0..pairs {
+            let y0 = luma[2 * i];
+            let y1 = *luma.get(2 * i + 1).unwrap_or(&y0);
+            data.extend_from_slice(&[y0, cb[i], y1, cr[i]]);
+        }
+    }
+
+    VideoFrame {
+        fourcc: FOURCC_YUY2,
+        width: frame.width,
+        height: frame.height,
+        planes: vec![Plane { data, stride }],
+    }
+}
+
+/// The rows `y0..y1` of `frame`, of a format with known planes, `y0` being even.
+fn frame_band(frame: &VideoFrame, y0: usize, y1: usize) -> VideoFrame {
+    let height = frame.height as usize;
+    let geometry = plane_geometry(frame.fourcc, frame.width as usize, height)
+        .expect("frame_band: unknown planes");
+    let planes = geometry
+        .iter()
+        .zip(&frame.planes)
+        .map(|(&(row_len, rows), plane)| {
+            // the chroma planes of the planar formats are subsampled vertically
+            let (start, end) = if rows == height {
+                (y0, y1)
+            } else {
+                (y0 / 2, y1.div_ceil(2))
+            };
+            let data = &plane.data[start * plane.stride..(end - 1) * plane.stride + row_len];
+            Plane {
+                data: data.to_vec(),
+                stride: plane.stride,
+            }
+        })
+        .collect();
+    VideoFrame {
+        fourcc: frame.fourcc,
+        width: frame.width,
+        height: (y1 - y0) as u16,
+        planes,
+    }
+}
+
+/// The layout of an image in the data of `PutImage`, as returned by `QueryImageAttributes`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ImageLayout {
+    fourcc: u32,
+    width: u16,
+    height: u16,
+    data_size: usize,
+    pitches: Vec<usize>,
+    offsets: Vec<usize>,
+}
+
+impl ImageLayout {
+    /// Copies the planes of `frame` at the offsets and pitches of the layout.
+    fn pack(&self, frame: &VideoFrame) -> Vec<u8> {
+        let mut data = vec![0u8; self.data_size];
+        for (p, plane) in frame.planes.iter().enumerate().take(self.pitches.len()) {
+            let (start, pitch) = (self.offsets[p], self.pitches[p]);
+            if pitch == 0 || start >= self.data_size {
+                continue;
+            }
+            let end = self
+                .offsets
+                .iter()
+                .copied()
+                .filter(|&off| off > start)
+                .min()
+                .unwrap_or(self.data_size)
+                .min(self.data_size);
+            let row_len = plane.stride.min(pitch);
+            for row in 0..(end - start) / pitch {
+                let src = row * plane.stride;
+                if src >= plane.data.len() {
+                    break;
+                }
+                let len = row_len.min(plane.data.len() - src);
+                let dst = start + row * pitch;
+                data[dst..dst + len].copy_from_slice(&plane.data[src..src + len]);
+            }
+        }
+        data
+    }
+}
+
+/// A shared memory segment attached by the server and mapped by this client.
+///
+/// See `shm::Attach` and `shm::AttachFd` to attach a segment.
+#[derive(Debug)]
+pub struct SharedSegment {
+    seg: shm::Seg,
+    pub(super) addr: *mut u8,
+    size: usize,
+}
+
+impl SharedSegment {
+    /// Describes the segment `seg`, mapped at `addr` with `size` bytes.
+    ///
+    /// # Safety
+    /// `addr` must be valid for writes of `size` bytes, for as long as the segment is used
+    /// by a [Presenter], and must not be accessed concurrently.
+    pub unsafe fn new(seg: shm::Seg, addr: *mut u8, size: usize) -> SharedSegment {
+        SharedSegment { seg, addr, size }
+    }
+
+    /// The segment, as attached by the server.
+    pub fn seg(&self) -> shm::Seg {
+        self.seg
+    }
+
+    /// The size in bytes of the segment.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Error returned by [Presenter::present_frame].
+#[derive(Debug)]
+pub enum PresentError {
+    /// The port can't display the format of the frame, even after conversion.
+    UnsupportedFormat {
+        /// The FourCC code of the frame.
+        fourcc: u32,
+        /// The FourCC codes of the formats accepted by the port.
+        supported: Vec<u32>,
+    },
+    /// The frame misses planes or data for its format.
+    IncompleteFrame,
+    /// The frame doesn't fit in a `PutImage` request, and there is no shared segment
+    /// large enough for it.
+    TooLarge {
+        /// The length of the `PutImage` request, in bytes.
+        request_len: usize,
+    },
+    /// A request failed.
+    Xcb(crate::Error),
+}
+
+pub(crate) fn fourcc_name(code: u32) -> String {
+    code.to_le_bytes()
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() { b as char } else { '?' })
+        .collect()
+}
+
+impl fmt::Display for PresentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresentError::UnsupportedFormat { fourcc, supported } => {
+                let supported: Vec<_> = supported.iter().map(|&id| fourcc_name(id)).collect();
+                write!(
+                    f,
+                    "unsupported video format {} (the port accepts {})",
+                    fourcc_name(*fourcc),
+                    supported.join(", ")
+                )
+            }
+            PresentError::IncompleteFrame => f.write_str("incomplete video frame"),
+            PresentError::TooLarge { request_len } => write!(
+                f,
+                "video frame too large for a PutImage request ({} bytes)",
+                request_len
+            ),
+            PresentError::Xcb(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PresentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PresentError::Xcb(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<crate::Error> for PresentError {
+    fn from(err: crate::Error) -> PresentError {
+        PresentError::Xcb(err)
+    }
+}
+
+/// The number of image layouts cached by a [Presenter].
+const MAX_LAYOUTS: usize = 3;
+
+/// Presents video frames on a drawable through an `XVideo` port.
+///
+/// The image formats of the port are listed once at creation. Frames in a format accepted
+/// by the port are sent as-is, other frames are converted in software when possible
+/// (see [select_conversion]).
+///
+/// Frames are sent with `xv::PutImage`, or with `xv::ShmPutImage` when a shared segment
+/// large enough for the image is configured with [Presenter::set_shared_segment].
+/// Otherwise, they are uploaded following the [UploadStrategy] of the presenter, which
+/// can be detected with [UploadStrategy::detect]: with [UploadStrategy::Shm], the presenter
+/// creates and attaches its own shared segment.
+///
+/// # Example
+/// ```no_run
+/// # use xcb::{x, xv};
+/// # fn decode() -> xv::VideoFrame { unimplemented!() }
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let (conn, _) = xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Xv], &[])?;
+/// # let port: xv::Port = unimplemented!();
+/// # let window: x::Window = unimplemented!();
+/// let mut presenter = xv::Presenter::new(&conn, port, x::Drawable::Window(window))?;
+/// presenter.set_strategy(xv::UploadStrategy::detect(&conn, 1920 * 1080 * 2)?);
+/// loop {
+///     presenter.present_frame(decode())?;
+/// }
+/// # }
+/// ```
+pub struct Presenter<'a> {
+    conn: &'a Connection,
+    port: Port,
+    drawable: x::Drawable,
+    gc: x::Gcontext,
+    formats: Vec<u32>,
+    /// The layouts of the last images, the full frames and their bands.
+    layouts: Vec<ImageLayout>,
+    shm: Option<SharedSegment>,
+    strategy: UploadStrategy,
+    owned_shm: Option<OwnedSegment>,
+    destination: Option<x::Rectangle>,
+}
+
+impl<'a> fmt::Debug for Presenter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Presenter")
+            .field("port", &self.port)
+            .field("drawable", &self.drawable)
+            .field("formats", &self.formats)
+            .field("shm", &self.shm)
+            .field("strategy", &self.strategy)
+            .field("owned_shm", &self.owned_shm)
+            .field("destination", &self.destination)
+            .finish()
+    }
+}
+
+impl<'a> Presenter<'a> {
+    /// Creates a presenter of frames on `drawable` through `port`.
+    ///
+    /// Lists the image formats of the port and creates a graphics context for `drawable`,
+    /// freed when the presenter is dropped. The upload strategy is [UploadStrategy::Socket].
+    pub fn new(conn: &'a Connection, port: Port, drawable: x::Drawable) -> Result<Presenter<'a>> {
+        let formats = conn.send_request(&ListImageFormats { port });
+        let gc = conn.generate_id();
+        let create_gc = conn.send_request_checked(&x::CreateGc {
+            cid: gc,
+            drawable,
+            value_list: &[],
+        });
+        let formats = conn
+            .wait_for_reply(formats)
+            .context("ListImageFormats", || format!("port {:?}", port))?;
+        conn.check_request(create_gc)
+            .context("CreateGC", || format!("drawable {:?}", drawable))?;
+
+        Ok(Presenter {
+            conn,
+            port,
+            drawable,
+            gc,
+            formats: formats.format().iter().map(|f| f.id()).collect(),
+            layouts: Vec::new(),
+            shm: None,
+            strategy: UploadStrategy::Socket,
+            owned_shm: None,
+            destination: None,
+        })
+    }
+
+    /// The port of the presenter.
+    pub fn port(&self) -> Port {
+        self.port
+    }
+
+    /// The drawable on which the frames are presented.
+    pub fn drawable(&self) -> x::Drawable {
+        self.drawable
+    }
+
+    /// The FourCC codes of the image formats accepted by the port.
+    pub fn formats(&self) -> &[u32] {
+        &self.formats
+    }
+
+    /// Sets the shared segment through which the frames are sent, or `None` to send them
+    /// in the requests.
+    ///
+    /// Frames that don't fit in the segment are sent in the requests.
+    pub fn set_shared_segment(&mut self, shm: Option<SharedSegment>) {
+        self.shm = shm;
+    }
+
+    /// The strategy followed to upload the frames.
+    pub fn strategy(&self) -> UploadStrategy {
+        self.strategy
+    }
+
+    /// Sets the strategy followed to upload the frames that don't fit in the shared segment
+    /// set with [Presenter::set_shared_segment], if any.
+    ///
+    /// With [UploadStrategy::Shm], the presenter creates a shared segment large enough
+    /// for the frames, and falls back to [UploadStrategy::SocketChunked] if it can't.
+    /// With [UploadStrategy::SocketChunked], the frames that don't fit in a request are
+    /// sent in bands of rows, scaled separately to the destination.
+    pub fn set_strategy(&mut self, strategy: UploadStrategy) {
+        self.strategy = strategy;
+        if strategy != UploadStrategy::Shm {
+            if let Some(owned) = self.owned_shm.take() {
+                owned.free(self.conn);
+            }
+        }
+    }
+
+    /// Sets the area of the drawable in which the frames are scaled, or `None` to present
+    /// them unscaled at the origin of the drawable.
+    pub fn set_destination(&mut self, destination: Option<x::Rectangle>) {
+        self.destination = destination;
+    }
+
+    /// Presents `frame`, converting it first if the port doesn't accept its format.
+    pub fn present_frame(&mut self, frame: VideoFrame) -> std::result::Result<(), PresentError> {
+        let (fourcc, conversion) =
+            select_conversion(frame.fourcc, &self.formats).ok_or_else(|| {
+                PresentError::UnsupportedFormat {
+                    fourcc: frame.fourcc,
+                    supported: self.formats.clone(),
+                }
+            })?;
+        if !frame.is_complete() {
+            return Err(PresentError::IncompleteFrame);
+        }
+        let frame = match conversion {
+            Conversion::None => frame,
+            Conversion::SwapChroma => swap_chroma(frame),
+            Conversion::PlanarToYuy2 => planar_to_yuy2(&frame),
+        };
+        debug_assert_eq!(frame.fourcc, fourcc);
+
+        let layout = self.layout(&frame)?.clone();
+        let (width, height) = (frame.width, frame.height);
+        let dst = self.destination.unwrap_or(x::Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+
+        if let Some((shmseg, addr)) = self.segment(layout.data_size)? {
+            let data = layout.pack(&frame);
+            // SAFETY: guaranteed by SharedSegment::new, the segment is large enough
+            unsafe {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), addr, data.len());
+            }
+            let cookie = self.conn.send_request_checked(&ShmPutImage {
+                port: self.port,
+                drawable: self.drawable,
+                gc: self.gc,
+                shmseg,
+                id: fourcc,
+                offset: 0,
+                src_x: 0,
+                src_y: 0,
+                src_w: width,
+                src_h: height,
+                drw_x: dst.x,
+                drw_y: dst.y,
+                drw_w: dst.width,
+                drw_h: dst.height,
+                width,
+                height,
+                send_event: 0,
+            });
+            // the check also ensures that the server is done reading the shared segment
+            // before the next frame overwrites it
+            self.conn
+                .check_request(cookie)
+                .context("ShmPutImage", || fourcc_name(fourcc))?;
+            return Ok(());
+        }
+
+        if self.strategy == UploadStrategy::SocketChunked
+            && !self.conn.fits_in_request(put_image_len(layout.data_size))
+            && plane_geometry(fourcc, width as usize, height as usize).is_some()
+        {
+            return self.put_bands(&frame, &layout, dst);
+        }
+        let data = layout.pack(&frame);
+        let cookie = self.put_image(fourcc, width, height, dst, &data)?;
+        self.conn
+            .check_request(cookie)
+            .context("PutImage", || fourcc_name(fourcc))?;
+        Ok(())
+    }
+
+    /// The shared segment and its address through which an image of `size` bytes is sent,
+    /// if any.
+    fn segment(&mut self, size: usize) -> Result<Option<(shm::Seg, *mut u8)>> {
+        if let Some(shm) = self.shm.as_ref().filter(|shm| shm.size >= size) {
+            return Ok(Some((shm.seg, shm.addr)));
+        }
+        if self.strategy != UploadStrategy::Shm {
+            return Ok(None);
+        }
+        if self
+            .owned_shm
+            .as_ref()
+            .is_some_and(|o| o.segment.size < size)
+        {
+            self.owned_shm.take().unwrap().free(self.conn);
+        }
+        if self.owned_shm.is_none() {
+            self.owned_shm = OwnedSegment::new(self.conn, size)?;
+            if self.owned_shm.is_none() {
+                // the memory can't be shared after all
+                self.strategy = UploadStrategy::SocketChunked;
+            }
+        }
+        Ok(self
+            .owned_shm
+            .as_ref()
+            .map(|o| (o.segment.seg, o.segment.addr)))
+    }
+
+    /// Sends the image `data` of `width` by `height` pixels in a `PutImage` request,
+    /// scaled to `dst`.
+    fn put_image(
+        &self,
+        fourcc: u32,
+        width: u16,
+        height: u16,
+        dst: x::Rectangle,
+        data: &[u8],
+    ) -> std::result::Result<crate::VoidCookieChecked, PresentError> {
+        let request = PutImage {
+            port: self.port,
+            drawable: self.drawable,
+            gc: self.gc,
+            id: fourcc,
+            src_x: 0,
+            src_y: 0,
+            src_w: width,
+            src_h: height,
+            drw_x: dst.x,
+            drw_y: dst.y,
+            drw_w: dst.width,
+            drw_h: dst.height,
+            width,
+            height,
+            data,
+        };
+        // libxcb would shut the connection down
+        let request_len = request.request_len();
+        if !self.conn.fits_in_request(request_len) {
+            return Err(PresentError::TooLarge { request_len });
+        }
+        Ok(self.conn.send_request_checked(&request))
+    }
+
+    /// Sends `frame`, of image `layout`, in bands of rows that fit in `PutImage` requests.
+    fn put_bands(
+        &mut self,
+        frame: &VideoFrame,
+        layout: &ImageLayout,
+        dst: x::Rectangle,
+    ) -> std::result::Result<(), PresentError> {
+        let height = frame.height as usize;
+        // the room for the data, without the 4 bytes of the extended length of BIG-REQUESTS
+        let max_len = (self.conn.get_maximum_request_length() as usize * 4)
+            .saturating_sub(put_image_len(0) + 4);
+        let row_size = layout.data_size.div_ceil(height);
+        let bands = bands(height, row_size, max_len).ok_or(PresentError::TooLarge {
+            request_len: put_image_len(2 * row_size),
+        })?;
+
+        // the bands are scaled to the rows of the destination they cover
+        let dst_y = |y: usize| dst.y as i32 + (y * dst.height as usize / height) as i32;
+        let mut cookies = Vec::with_capacity(bands.len());
+        for (y0, y1) in bands {
+            let band = frame_band(frame, y0, y1);
+            let data = self.layout(&band)?.pack(&band);
+            let rect = x::Rectangle {
+                x: dst.x,
+                y: dst_y(y0) as i16,
+                width: dst.width,
+                height: (dst_y(y1) - dst_y(y0)) as u16,
+            };
+            cookies.push(self.put_image(frame.fourcc, frame.width, band.height, rect, &data)?);
+        }
+        for cookie in cookies {
+            self.conn
+                .check_request(cookie)
+                .context("PutImage", || fourcc_name(frame.fourcc))?;
+        }
+        Ok(())
+    }
+
+    /// The layout of the image of `frame`, queried once per format and size.
+    ///
+    /// The layouts of the full frames and of their first and last bands are kept.
+    fn layout(&mut self, frame: &VideoFrame) -> Result<&ImageLayout> {
+        let cached = self.layouts.iter().position(|l| {
+            (l.fourcc, l.width, l.height) == (frame.fourcc, frame.width, frame.height)
+        });
+        if cached.is_none() {
+            let cookie = self.conn.send_request(&QueryImageAttributes {
+                port: self.port,
+                id: frame.fourcc,
+                width: frame.width,
+                height: frame.height,
+            });
+            let reply = self
+                .conn
+                .wait_for_reply(cookie)
+                .context("QueryImageAttributes", || fourcc_name(frame.fourcc))?;
+            if self.layouts.len() == MAX_LAYOUTS {
+                self.layouts.remove(0);
+            }
+            self.layouts.push(ImageLayout {
+                fourcc: frame.fourcc,
+                width: frame.width,
+                height: frame.height,
+                data_size: reply.data_size() as usize,
+                pitches: reply.pitches().iter().map(|&p| p as usize).collect(),
+                offsets: reply.offsets().iter().map(|&o| o as usize).collect(),
+            });
+        }
+        Ok(&self.layouts[cached.unwrap_or(self.layouts.len() - 1)])
+    }
+}
+
+impl<'a> Drop for Presenter<'a> {
+    fn drop(&mut self) {
+        if let Some(owned) = self.owned_shm.take() {
+            owned.free(self.conn);
+        }
+        self.conn.send_request(&x::FreeGc { gc: self.gc });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fourcc() {
+        assert_eq!(FOURCC_I420, 0x3032_3449);
+        assert_eq!(FOURCC_YV12, 0x3231_5659);
+        assert_eq!(FOURCC_YUY2, 0x3259_5559);
+        assert_eq!(fourcc_name(FOURCC_YUY2), "YUY2");
+        assert_eq!(fourcc_name(3), "????");
+    }
+
+    #[test]
+    fn test_frame_is_complete() {
+        let mut frame = planar_frame(FOURCC_I420, 3, 3);
+        assert!(frame.is_complete());
+        frame.planes[2].data.pop();
+        assert!(!frame.is_complete());
+        frame.planes.pop();
+        assert!(!frame.is_complete());
+
+        let mut frame = planar_frame(FOURCC_I420, 4, 2);
+        frame.planes[0].stride = 3;
+        assert!(!frame.is_complete());
+
+        // unknown formats are not checked
+        frame.fourcc = fourcc(b"RGB3");
+        assert!(frame.is_complete());
+    }
+
+    #[test]
+    fn test_select_conversion() {
+        use Conversion::*;
+
+        let all = [FOURCC_YUY2, FOURCC_YV12, FOURCC_I420];
+        assert_eq!(
+            select_conversion(FOURCC_I420, &all),
+            Some((FOURCC_I420, None))
+        );
+        assert_eq!(
+            select_conversion(FOURCC_YV12, &all),
+            Some((FOURCC_YV12, None))
+        );
+        assert_eq!(
+            select_conversion(FOURCC_YUY2, &all),
+            Some((FOURCC_YUY2, None))
+        );
+
+        let planar = [FOURCC_YV12];
+        assert_eq!(
+            select_conversion(FOURCC_I420, &planar),
+            Some((FOURCC_YV12, SwapChroma))
+        );
+        assert_eq!(
+            select_conversion(FOURCC_YV12, &planar),
+            Some((FOURCC_YV12, None))
+        );
+        assert_eq!(select_conversion(FOURCC_YUY2, &planar), Option::None);
+
+        // the chroma swap is preferred over interleaving
+        let both = [FOURCC_YUY2, FOURCC_I420];
+        assert_eq!(
+            select_conversion(FOURCC_YV12, &both),
+            Some((FOURCC_I420, SwapChroma))
+        );
+
+        let packed = [FOURCC_YUY2, fourcc(b"UYVY")];
+        assert_eq!(
+            select_conversion(FOURCC_I420, &packed),
+            Some((FOURCC_YUY2, PlanarToYuy2))
+        );
+        assert_eq!(
+            select_conversion(FOURCC_YV12, &packed),
+            Some((FOURCC_YUY2, PlanarToYuy2))
+        );
+        assert_eq!(select_conversion(fourcc(b"NV12"), &packed), Option::None);
+        assert_eq!(select_conversion(FOURCC_I420, &[]), Option::None);
+    }
+
+    #[test]
+    fn test_swap_chroma() {
+        let i420 = planar_frame(FOURCC_I420, 4, 2);
+        let yv12 = swap_chroma(i420.clone());
+        assert_eq!(yv12, planar_frame(FOURCC_YV12, 4, 2));
+        assert_eq!(swap_chroma(yv12), i420);
+    }
+
+    #[test]
+    fn test_planar_to_yuy2() {
+        let expected = vec![
+            10, 100, 11, 200, 12, 101, 13, 201, //
+            14, 100, 15, 200, 16, 101, 17, 201, //
+        ];
+        for fourcc in [FOURCC_I420, FOURCC_YV12] {
+            let yuy2 = planar_to_yuy2(&planar_frame(fourcc, 4, 2));
+            assert_eq!(yuy2.fourcc, FOURCC_YUY2);
+            assert_eq!((yuy2.width, yuy2.height), (4, 2));
+            assert_eq!(yuy2.planes.len(), 1);
+            assert_eq!(yuy2.planes[0].stride, 8);
+            assert_eq!(yuy2.planes[0].data, expected);
+        }
+
+        // odd size: the last luma sample is duplicated, the last chroma row covers one row
+        let yuy2 = planar_to_yuy2(&planar_frame(FOURCC_I420, 3, 3));
+        assert_eq!(yuy2.planes[0].stride, 8);
+        assert_eq!(
+            yuy2.planes[0].data,
+            vec![
+                10, 100, 11, 200, 12, 101, 12, 201, //
+                13, 100, 14, 200, 15, 101, 15, 201, //
+                16, 102, 17, 202, 18, 103, 18, 203, //
+            ]
+        );
+        assert!(yuy2.is_complete());
+
+        // strides larger than the rows
+        let mut frame = planar_frame(FOURCC_I420, 2, 2);
+        frame.planes[0] = Plane {
+            data: vec![1, 2, 0, 0, 3, 4],
+            stride: 4,
+        };
+        let yuy2 = planar_to_yuy2(&frame);
+        assert_eq!(yuy2.planes[0].data, vec![1, 100, 2, 200, 3, 100, 4, 200]);
+    }
+
+    #[test]
+    fn test_layout_pack() {
+        let layout = ImageLayout {
+            fourcc: FOURCC_I420,
+            width: 2,
+            height: 2,
+            data_size: 16,
+            pitches: vec![4, 2, 2],
+            offsets: vec![0, 8, 12],
+        };
+        let data = layout.pack(&planar_frame(FOURCC_I420, 2, 2));
+        assert_eq!(
+            data,
+            vec![10, 11, 0, 0, 12, 13, 0, 0, 100, 0, 0, 0, 200, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_presenter() {
+        use crate::mock;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        let images = Arc::new(Mutex::new(Vec::new()));
+        let queries = Arc::new(Mutex::new(0));
+        let conn = {
+            let images = images.clone();
+            let queries = queries.clone();
+            mock::connect(move |req| match req.opcode {
+                // QueryExtension
+                98 => match mock::extension_name(req) {
+                    b"XVideo" => vec![mock::query_extension_reply(req, 140, 80, 150)],
+                    _ => vec![mock::reply(req.seq, 0, &[])],
+                },
+                // xv::ListImageFormats
+                140 if req.data[1] == 16 => vec![image_formats_reply(req.seq, &[FOURCC_YUY2])],
+                // xv::QueryImageAttributes
+                140 if req.data[1] == 17 => {
+                    *queries.lock().unwrap() += 1;
+                    let width = u16::from_ne_bytes([req.data[12], req.data[13]]);
+                    let height = u16::from_ne_bytes([req.data[14], req.data[15]]);
+                    vec![image_attributes_reply(req.seq, width, height)]
+                }
+                // xv::PutImage
+                140 if req.data[1] == 18 => {
+                    images.lock().unwrap().push((
+                        req.u32_at(16),
+                        req.data[20..40].to_vec(),
+                        req.data[40..].to_vec(),
+                    ));
+                    vec![]
+                }
+                _ => mock::default_response(req),
+            })
+        };
+
+        let port = unsafe { Port::new(42) };
+        let window = x::Drawable::Window(unsafe { x::Window::new(0x200) });
+        let mut presenter = Presenter::new(&conn, port, window).unwrap();
+        assert_eq!(presenter.formats(), [FOURCC_YUY2]);
+
+        presenter
+            .present_frame(planar_frame(FOURCC_I420, 2, 2))
+            .unwrap();
+        presenter
+            .present_frame(planar_frame(FOURCC_YV12, 2, 2))
+            .unwrap();
+        assert_eq!(*queries.lock().unwrap(), 1);
+
+        let images = images.lock().unwrap();
+        assert_eq!(images.len(), 2);
+        for (id, geometry, data) in images.iter() {
+            assert_eq!(*id, FOURCC_YUY2);
+            // src 2x2 at 0,0, drawn unscaled at 0,0, image of 2x2
+            let expected: Vec<u8> = [0u16, 0, 2, 2, 0, 0, 2, 2, 2, 2]
+                .iter()
+                .flat_map(|v| v.to_ne_bytes().to_vec())
+                .collect();
+            assert_eq!(geometry, &expected);
+            // rows of 5 bytes, padded to 4 bytes in the request
+            assert_eq!(data[..10], [10, 100, 11, 200, 0, 12, 100, 13, 200, 0]);
+            assert_eq!(data.len(), 12);
+        }
+        drop(images);
+
+        let err = presenter
+            .present_frame(VideoFrame {
+                fourcc: fourcc(b"NV12"),
+                width: 2,
+                height: 2,
+                planes: vec![],
+            })
+            .unwrap_err();
+        match &err {
+            PresentError::UnsupportedFormat { fourcc, supported } => {
+                assert_eq!(fourcc_name(*fourcc), "NV12");
+                assert_eq!(supported, &[FOURCC_YUY2]);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!(
+            err.to_string(),
+            "unsupported video format NV12 (the port accepts YUY2)"
+        );
+
+        let mut frame = planar_frame(FOURCC_I420, 2, 2);
+        frame.planes.pop();
+        assert!(matches!(
+            presenter.present_frame(frame),
+            Err(PresentError::IncompleteFrame)
+        ));
+
+        // rows of 513 bytes, beyond the maximum request length of the mock server
+        assert!(matches!(
+            presenter.present_frame(planar_frame(FOURCC_I420, 256, 512)),
+            Err(PresentError::TooLarge {
+                request_len: 262696
+            })
+        ));
+    }
+
+    fn planar_frame(fourcc: u32, width: u16, height: u16) -> VideoFrame {
+        // luma samples are 10 + index, chroma samples 100 + index (U) and 200 + index (V)
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        let plane = |base: usize, len: usize, stride: usize| Plane {
+            data: (0..len).map(|i| (base + i) as u8).collect(),
+            stride,
+        };
+        let y = plane(10, w * h, w);
+        let u = plane(100, cw * ch, cw);
+        let v = plane(200, cw * ch, cw);
+        let planes = if fourcc == FOURCC_YV12 {
+            vec![y, v, u]
+        } else {
+            vec![y, u, v]
+        };
+        VideoFrame {
+            fourcc,
+            width,
+            height,
+            planes,
+        }
+    }
+
+    fn image_formats_reply(seq: u16, formats: &[u32]) -> Vec<u8> {
+        let mut body = vec![0u8; 24];
+        body[0..4].copy_from_slice(&(formats.len() as u32).to_ne_bytes());
+        for id in formats {
+            let mut info = [0u8; 128];
+            info[0..4].copy_from_slice(&id.to_ne_bytes());
+            body.extend_from_slice(&info);
+        }
+        crate::mock::reply(seq, 0, &body)
+    }
+
+    fn image_attributes_reply(seq: u16, width: u16, height: u16) -> Vec<u8> {
+        // a layout with one byte more than needed at the end of each row
+        let (w, h) = (width as u32, height as u32);
+        let pitches = [w * 2 + 1];
+        let data_size = pitches[0] * h;
+        let mut body = vec![0u8; 24];
+        body[0..4].copy_from_slice(&1u32.to_ne_bytes());
+        body[4..8].copy_from_slice(&data_size.to_ne_bytes());
+        body[8..10].copy_from_slice(&width.to_ne_bytes());
+        body[10..12].copy_from_slice(&height.to_ne_bytes());
+        body.extend_from_slice(&pitches[0].to_ne_bytes());
+        body.extend_from_slice(&0u32.to_ne_bytes());
+        crate::mock::reply(seq, 0, &body)
+    }
+
+    #[test]
+    fn test_presenter_strategies() {
+        use crate::ext::Extension;
+        use crate::mock;
+        use crate::XidNew;
+        use std::sync::{Arc, Mutex};
+
+        const SHM_OPCODE: u8 = 141;
+        const BAD_ACCESS: u8 = 10;
+
+        #[derive(Debug, PartialEq)]
+        enum Request {
+            PutImage { drw_y: i16, drw_h: u16, height: u16 },
+            ShmPutImage(u32),
+            Attach(u32),
+            Detach(u32),
+        }
+
+        let presenter_conn = |attach_error: Option<u8>, requests: Arc<Mutex<Vec<Request>>>| {
+            let handler = move |req: &mock::MockRequest| {
+                let u16_at = |at: usize| u16::from_ne_bytes([req.data[at], req.data[at + 1]]);
+                let mut requests = requests.lock().unwrap();
+                match req.opcode {
+                    // QueryExtension
+                    98 => match mock::extension_name(req) {
+                        b"XVideo" => vec![mock::query_extension_reply(req, 140, 80, 150)],
+                        b"MIT-SHM" => vec![mock::query_extension_reply(req, SHM_OPCODE, 90, 160)],
+                        _ => vec![mock::reply(req.seq, 0, &[])],
+                    },
+                    // xv::ListImageFormats
+                    140 if req.data[1] == 16 => vec![image_formats_reply(req.seq, &[FOURCC_YUY2])],
+                    // xv::QueryImageAttributes
+                    140 if req.data[1] == 17 => {
+                        vec![image_attributes_reply(req.seq, u16_at(12), u16_at(14))]
+                    }
+                    // xv::PutImage
+                    140 if req.data[1] == 18 => {
+                        requests.push(Request::PutImage {
+                            drw_y: u16_at(30) as i16,
+                            drw_h: u16_at(34),
+                            height: u16_at(38),
+                        });
+                        vec![]
+                    }
+                    // xv::ShmPutImage
+                    140 if req.data[1] == 19 => {
+                        requests.push(Request::ShmPutImage(req.u32_at(16)));
+                        vec![]
+                    }
+                    // shm::Attach
+                    SHM_OPCODE if req.data[1] == 1 => match attach_error {
+                        Some(code) => vec![mock::error(req.seq, code, req.u32_at(8), SHM_OPCODE)],
+                        None => {
+                            requests.push(Request::Attach(req.u32_at(4)));
+                            vec![]
+                        }
+                    },
+                    // shm::Detach
+                    SHM_OPCODE if req.data[1] == 2 => {
+                        requests.push(Request::Detach(req.u32_at(4)));
+                        vec![]
+                    }
+                    _ => mock::default_response(req),
+                }
+            };
+            mock::connect_with_extensions(handler, &[Extension::Xv, Extension::Shm])
+        };
+        let port = unsafe { Port::new(42) };
+        let window = x::Drawable::Window(unsafe { x::Window::new(0x200) });
+
+        // the presenter attaches its own segment, once for frames of the same size
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = presenter_conn(None, requests.clone());
+        let mut presenter = Presenter::new(&conn, port, window).unwrap();
+        presenter.set_strategy(UploadStrategy::Shm);
+        for _ in 0..2 {
+            presenter
+                .present_frame(planar_frame(FOURCC_I420, 256, 512))
+                .unwrap();
+        }
+        assert_eq!(presenter.strategy(), UploadStrategy::Shm);
+        drop(presenter);
+        // waits for the server to process the detachment
+        let cookie = conn.send_request(&x::GetInputFocus {});
+        conn.wait_for_reply(cookie).unwrap();
+        {
+            let requests = requests.lock().unwrap();
+            let seg = match requests[0] {
+                Request::Attach(seg) => seg,
+                ref req => panic!("unexpected request {:?}", req),
+            };
+            assert_eq!(
+                requests[1..],
+                [
+                    Request::ShmPutImage(seg),
+                    Request::ShmPutImage(seg),
+                    Request::Detach(seg)
+                ]
+            );
+        }
+
+        // a remote server refuses the segment: the frames that don't fit are sent in bands
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let conn = presenter_conn(Some(BAD_ACCESS), requests.clone());
+        let mut presenter = Presenter::new(&conn, port, window).unwrap();
+        presenter.set_strategy(UploadStrategy::Shm);
+        presenter
+            .present_frame(planar_frame(FOURCC_I420, 2, 2))
+            .unwrap();
+        assert_eq!(presenter.strategy(), UploadStrategy::SocketChunked);
+        // rows of 513 bytes, 510 of which fit in a request of the mock server
+        presenter
+            .present_frame(planar_frame(FOURCC_I420, 256, 512))
+            .unwrap();
+        presenter.set_destination(Some(x::Rectangle {
+            x: 0,
+            y: 10,
+            width: 128,
+            height: 256,
+        }));
+        presenter
+            .present_frame(planar_frame(FOURCC_I420, 256, 512))
+            .unwrap();
+        drop(presenter);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                Request::PutImage {
+                    drw_y: 0,
+                    drw_h: 2,
+                    height: 2
+                },
+                Request::PutImage {
+                    drw_y: 0,
+                    drw_h: 510,
+                    height: 510
+                },
+                Request::PutImage {
+                    drw_y: 510,
+                    drw_h: 2,
+                    height: 2
+                },
+                Request::PutImage {
+                    drw_y: 10,
+                    drw_h: 255,
+                    height: 510
+                },
+                Request::PutImage {
+                    drw_y: 265,
+                    drw_h: 1,
+                    height: 2
+                },
+            ]
+        );
+        assert_eq!(conn.has_error(), Ok(()));
+    }
+}
@@ -0,0 +1,437 @@
+//! Statistics of a video stream, from the video notifications of a port.
+
+use super::{
+    AttributeFlag, Event, GetPortAttribute, Port, PortAttributeMonitor, VideoNotifyEvent,
+    VideoNotifyReason,
+};
+use crate::base::{Connection, Result, ResultExt, Xid};
+use crate::x;
+
+use std::time::{Duration, Instant};
+
+/// The state of a video stream, as last notified by the server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamState {
+    /// No notification was received yet.
+    Idle,
+    /// The video is playing.
+    Started,
+    /// The video was stopped by the client.
+    Stopped,
+    /// The port is grabbed by another client.
+    Busy,
+    /// The video was preempted by another client.
+    Preempted,
+    /// The video was stopped because of a hardware error.
+    HardError,
+}
+
+impl From<VideoNotifyReason> for StreamState {
+    fn from(reason: VideoNotifyReason) -> StreamState {
+        match reason {
+            VideoNotifyReason::Started => StreamState::Started,
+            VideoNotifyReason::Stopped => StreamState::Stopped,
+            VideoNotifyReason::Busy => StreamState::Busy,
+            VideoNotifyReason::Preempted => StreamState::Preempted,
+            VideoNotifyReason::HardError => StreamState::HardError,
+        }
+    }
+}
+
+type HardErrorCallback = Box<dyn FnMut(&VideoNotifyEvent) + Send>;
+
+/// Tracks the state of the video stream of a port on a drawable.
+///
+/// [StreamStats::handle_event] is meant to be called from the event loop with every event,
+/// after video notifications were selected with `xv::SelectVideoNotify`.
+///
+/// The server reports the transitions in order, but clients that merge the events
+/// of several connections may receive them out of order. The notifications are ordered
+/// by their timestamp: a notification older than the current state is counted
+/// as [StreamStats::out_of_order] but doesn't change the state.
+///
+/// Optionally, port attributes such as `XV_SYNC_TO_VBLANK` or driver specific frame counters
+/// can be polled periodically with [StreamStats::enable_polling] and [StreamStats::poll_if_due].
+pub struct StreamStats {
+    port: Port,
+    drawable: x::Drawable,
+    state: StreamState,
+    last_time: Option<x::Timestamp>,
+    starts: u32,
+    stops: u32,
+    busy: u32,
+    preemptions: u32,
+    hard_errors: u32,
+    out_of_order: u32,
+    on_hard_error: Option<HardErrorCallback>,
+    polled: Vec<(x::Atom, String, Option<i32>)>,
+    poll_interval: Duration,
+    last_poll: Option<Instant>,
+}
+
+impl std::fmt::Debug for StreamStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamStats")
+            .field("port", &self.port)
+            .field("drawable", &self.drawable)
+            .field("state", &self.state)
+            .field("starts", &self.starts)
+            .field("stops", &self.stops)
+            .field("busy", &self.busy)
+            .field("preemptions", &self.preemptions)
+            .field("hard_errors", &self.hard_errors)
+            .field("out_of_order", &self.out_of_order)
+            .finish()
+    }
+}
+
+impl StreamStats {
+    /// Tracks the video stream of `port` on `drawable`.
+    pub fn new(port: Port, drawable: x::Drawable) -> StreamStats {
+        StreamStats {
+            port,
+            drawable,
+            state: StreamState::Idle,
+            last_time: None,
+            starts: 0,
+            stops: 0,
+            busy: 0,
+            preemptions: 0,
+            hard_errors: 0,
+            out_of_order: 0,
+            on_hard_error: None,
+            polled: Vec::new(),
+            poll_interval: Duration::from_secs(1),
+            last_poll: None,
+        }
+    }
+
+    /// Sets a callback called when a `HardError` notification is received.
+    pub fn on_hard_error<F>(&mut self, f: F)
+    where
+        F: FnMut(&VideoNotifyEvent) + Send + 'static,
+    {
+        self.on_hard_error = Some(Box::new(f));
+    }
+
+    /// Updates the statistics with `event`.
+    ///
+    /// Returns `true` if `event` is a video notification of the tracked port and drawable.
+    pub fn handle_event(&mut self, event: &crate::Event) -> bool {
+        match event {
+            crate::Event::Xv(Event::VideoNotify(ev)) => self.handle_video_notify(ev),
+            _ => false,
+        }
+    }
+
+    /// Updates the statistics with `ev`.
+    ///
+    /// Returns `true` if `ev` is for the tracked port and drawable.
+    pub fn handle_video_notify(&mut self, ev: &VideoNotifyEvent) -> bool {
+        if ev.port() != self.port || ev.drawable().resource_id() != self.drawable.resource_id() {
+            return false;
+        }
+
+        let time = ev.time();
+        if let Some(last) = self.last_time {
+            // timestamps wrap around every 49.7 days
+            if (time.wrapping_sub(last) as i32) < 0 {
+                self.out_of_order += 1;
+                return true;
+            }
+        }
+        self.last_time = Some(time);
+
+        let state = StreamState::from(ev.reason());
+        if state != self.state {
+            match state {
+                StreamState::Started => self.starts += 1,
+                StreamState::Stopped if self.state != StreamState::Idle => self.stops += 1,
+                StreamState::Busy => self.busy += 1,
+                StreamState::Preempted => self.preemptions += 1,
+                _ => {}
+            }
+        }
+        if state == StreamState::HardError {
+            self.hard_errors += 1;
+            if let Some(f) = self.on_hard_error.as_mut() {
+                f(ev);
+            }
+        }
+        self.state = state;
+        true
+    }
+
+    /// The tracked port.
+    pub fn port(&self) -> Port {
+        self.port
+    }
+
+    /// The tracked drawable.
+    pub fn drawable(&self) -> x::Drawable {
+        self.drawable
+    }
+
+    /// The current state of the stream.
+    pub fn state(&self) -> StreamState {
+        self.state
+    }
+
+    /// The number of times the stream was started.
+    pub fn starts(&self) -> u32 {
+        self.starts
+    }
+
+    /// The number of times the stream was stopped after it was started.
+    ///
+    /// A `Stopped` notification received before any other is not counted.
+    pub fn stops(&self) -> u32 {
+        self.stops
+    }
+
+    /// The number of times the port was reported busy.
+    pub fn busy(&self) -> u32 {
+        self.busy
+    }
+
+    /// The number of times the stream was preempted by another client.
+    pub fn preemptions(&self) -> u32 {
+        self.preemptions
+    }
+
+    /// The number of hardware errors.
+    pub fn hard_errors(&self) -> u32 {
+        self.hard_errors
+    }
+
+    /// The number of notifications received out of order, that were ignored.
+    pub fn out_of_order(&self) -> u32 {
+        self.out_of_order
+    }
+
+    /// Enables the polling of the port attributes named `names` every `interval`.
+    ///
+    /// The attributes of the port are discovered with `xv::QueryPortAttributes`:
+    /// the names that are not gettable attributes of the port are ignored.
+    /// Returns the names of the attributes that will be polled.
+    pub fn enable_polling(
+        &mut self,
+        conn: &Connection,
+        names: &[&str],
+        interval: Duration,
+    ) -> Result<Vec<String>> {
+        let monitor = PortAttributeMonitor::new(conn, self.port)?;
+        self.polled = monitor
+            .attributes()
+            .iter()
+            .filter(|attr| {
+                attr.flags.contains(AttributeFlag::GETTABLE) && names.contains(&&*attr.name)
+            })
+            .map(|attr| (attr.atom, attr.name.clone(), None))
+            .collect();
+        self.poll_interval = interval;
+        self.last_poll = None;
+        Ok(self
+            .polled
+            .iter()
+            .map(|(_, name, _)| name.clone())
+            .collect())
+    }
+
+    /// Polls the attributes enabled with [StreamStats::enable_polling] if the polling interval
+    /// has elapsed since the last poll.
+    ///
+    /// All the `GetPortAttribute` requests are pipelined.
+    /// Returns `true` if the attributes were polled.
+    pub fn poll_if_due(&mut self, conn: &Connection) -> Result<bool> {
+        if self.polled.is_empty() {
+            return Ok(false);
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_poll {
+            if now.duration_since(last) < self.poll_interval {
+                return Ok(false);
+            }
+        }
+        self.last_poll = Some(now);
+
+        let port = self.port;
+        let cookies: Vec<_> = self
+            .polled
+            .iter()
+            .map(|(attribute, _, _)| {
+                conn.send_request(&GetPortAttribute {
+                    port,
+                    attribute: *attribute,
+                })
+            })
+            .collect();
+        for ((_, name, value), cookie) in self.polled.iter_mut().zip(cookies) {
+            let reply = conn
+                .wait_for_reply(cookie)
+                .context("GetPortAttribute", || {
+                    format!("{} of port {}", name, port.resource_id())
+                })?;
+            *value = Some(reply.value());
+        }
+        Ok(true)
+    }
+
+    /// The last polled value of the attribute `name`.
+    pub fn attribute(&self, name: &str) -> Option<i32> {
+        self.polled
+            .iter()
+            .find(|(_, n, _)| n == name)
+            .and_then(|(_, _, value)| *value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notify(
+        reason: VideoNotifyReason,
+        time: x::Timestamp,
+        drawable: u32,
+        port: u32,
+    ) -> crate::Event {
+        use crate::XidNew;
+
+        let drawable = x::Drawable::Window(unsafe { x::Window::new(drawable) });
+        let port = unsafe { Port::new(port) };
+        crate::Event::Xv(Event::VideoNotify(VideoNotifyEvent::new(
+            80, reason, time, drawable, port,
+        )))
+    }
+
+    fn new_stats() -> StreamStats {
+        use crate::XidNew;
+
+        StreamStats::new(
+            unsafe { Port::new(42) },
+            x::Drawable::Window(unsafe { x::Window::new(0x200) }),
+        )
+    }
+
+    #[test]
+    fn test_stream_stats_transitions() {
+        use std::sync::{Arc, Mutex};
+        use VideoNotifyReason::*;
+
+        let mut stats = new_stats();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        {
+            let errors = errors.clone();
+            stats.on_hard_error(move |ev| errors.lock().unwrap().push(ev.time()));
+        }
+        assert_eq!(stats.state(), StreamState::Idle);
+
+        for (reason, time) in [
+            (Started, 10),
+            (Started, 11),
+            (Preempted, 20),
+            (Started, 30),
+            (Busy, 35),
+            (Started, 40),
+            (HardError, 50),
+            (Stopped, 60),
+        ] {
+            assert!(stats.handle_event(&notify(reason, time, 0x200, 42)));
+        }
+        assert_eq!(stats.state(), StreamState::Stopped);
+        assert_eq!(stats.starts(), 3);
+        assert_eq!(stats.preemptions(), 1);
+        assert_eq!(stats.busy(), 1);
+        assert_eq!(stats.hard_errors(), 1);
+        assert_eq!(stats.stops(), 1);
+        assert_eq!(*errors.lock().unwrap(), [50]);
+
+        // other port or drawable
+        assert!(!stats.handle_event(&notify(Started, 70, 0x200, 43)));
+        assert!(!stats.handle_event(&notify(Started, 70, 0x300, 42)));
+        assert_eq!(stats.state(), StreamState::Stopped);
+    }
+
+    #[test]
+    fn test_stream_stats_out_of_order() {
+        use VideoNotifyReason::*;
+
+        // Stopped received before the (older) Started
+        let mut stats = new_stats();
+        assert!(stats.handle_event(&notify(Stopped, 20, 0x200, 42)));
+        assert!(stats.handle_event(&notify(Started, 10, 0x200, 42)));
+        assert_eq!(stats.state(), StreamState::Stopped);
+        assert_eq!(stats.starts(), 0);
+        assert_eq!(stats.stops(), 0);
+        assert_eq!(stats.out_of_order(), 1);
+
+        // Stopped before any Started, in order
+        let mut stats = new_stats();
+        stats.handle_event(&notify(Stopped, 10, 0x200, 42));
+        stats.handle_event(&notify(Started, 20, 0x200, 42));
+        assert_eq!(stats.state(), StreamState::Started);
+        assert_eq!(stats.starts(), 1);
+        assert_eq!(stats.stops(), 0);
+        assert_eq!(stats.out_of_order(), 0);
+
+        // timestamp wrap around
+        let mut stats = new_stats();
+        stats.handle_event(&notify(Started, u32::MAX - 5, 0x200, 42));
+        stats.handle_event(&notify(Preempted, 5, 0x200, 42));
+        assert_eq!(stats.state(), StreamState::Preempted);
+        assert_eq!(stats.out_of_order(), 0);
+    }
+
+    #[test]
+    fn test_stream_stats_polling() {
+        use crate::mock;
+        use crate::XidNew;
+        use std::convert::TryInto;
+
+        const NAMES: [&str; 3] = ["XV_BRIGHTNESS", "XV_SYNC_TO_VBLANK", "XV_FRAME_COUNT"];
+
+        let conn = mock::connect(|req| match req.opcode {
+            98 => vec![mock::query_extension_reply(req, 140, 80, 150)],
+            // xv::QueryPortAttributes: XV_FRAME_COUNT is not available
+            140 if req.data[1] == 15 => vec![crate::xv::attributes::port_attributes_reply(
+                req.seq,
+                &NAMES[..2],
+            )],
+            // xv::GetPortAttribute: the value is the atom
+            140 if req.data[1] == 14 => {
+                let atom = req.u32_at(8) as i32;
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            16 => {
+                let len = u16::from_ne_bytes(req.data[4..6].try_into().unwrap()) as usize;
+                let name = std::str::from_utf8(&req.data[8..8 + len]).unwrap();
+                let atom = 1000 + NAMES.iter().position(|n| *n == name).unwrap() as u32;
+                vec![mock::reply(req.seq, 0, &atom.to_ne_bytes())]
+            }
+            _ => mock::default_response(req),
+        });
+
+        let mut stats = StreamStats::new(
+            unsafe { Port::new(42) },
+            x::Drawable::Window(unsafe { x::Window::new(0x200) }),
+        );
+        assert!(!stats.poll_if_due(&conn).unwrap());
+
+        let polled = stats
+            .enable_polling(
+                &conn,
+                &["XV_SYNC_TO_VBLANK", "XV_FRAME_COUNT"],
+                Duration::from_secs(3600),
+            )
+            .unwrap();
+        assert_eq!(polled, ["XV_SYNC_TO_VBLANK"]);
+        assert_eq!(stats.attribute("XV_SYNC_TO_VBLANK"), None);
+
+        assert!(stats.poll_if_due(&conn).unwrap());
+        assert_eq!(stats.attribute("XV_SYNC_TO_VBLANK"), Some(1001));
+        assert_eq!(stats.attribute("XV_BRIGHTNESS"), None);
+        // the interval has not elapsed
+        assert!(!stats.poll_if_due(&conn).unwrap());
+    }
+}
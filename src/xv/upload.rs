@@ -0,0 +1,296 @@
+//! Selection of the path through which the frames of a [Presenter](super::Presenter)
+//! are uploaded to the server.
+
+use super::{Port, PutImage, SharedSegment};
+use crate::base::{align_pad, Connection, Result, ResultExt};
+use crate::error::ProtocolError;
+use crate::ext::Extension;
+use crate::{shm, x, Xid};
+
+use std::ptr;
+
+/// The length of a `xv::PutImage` request of `data_len` bytes of image data.
+pub(crate) fn put_image_len(data_len: usize) -> usize {
+    let header_len = PutImage {
+        port: Port::none(),
+        drawable: x::Drawable::None,
+        gc: x::Gcontext::none(),
+        id: 0,
+        src_x: 0,
+        src_y: 0,
+        src_w: 0,
+        src_h: 0,
+        drw_x: 0,
+        drw_y: 0,
+        drw_w: 0,
+        drw_h: 0,
+        width: 0,
+        height: 0,
+        data: &[],
+    }
+    .request_len();
+    header_len + data_len + align_pad(data_len, 4)
+}
+
+/// The size of the segment attached to probe the locality of the server.
+const PROBE_SIZE: usize = 4096;
+
+/// How the frames are uploaded to the server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UploadStrategy {
+    /// In a shared memory segment, with `xv::ShmPutImage`.
+    ///
+    /// The server and the client share memory: the image data does not go through the socket.
+    Shm,
+    /// In a single `xv::PutImage` request.
+    Socket,
+    /// In several `xv::PutImage` requests of bands of rows, the frames being too large
+    /// for a single request.
+    SocketChunked,
+}
+
+impl UploadStrategy {
+    /// Detects the fastest way to upload frames of `frame_size` bytes.
+    ///
+    /// [UploadStrategy::Shm] is returned if the `MIT-SHM` extension is active on the
+    /// connection and the server can attach a segment of this client, which tells that
+    /// it runs on the same machine. The locality is probed by attaching a small segment
+    /// with a checked request: a remote server fails it, typically with `BadAccess`.
+    /// The probe segment is detached and removed in any case.
+    ///
+    /// Otherwise, [UploadStrategy::Socket] is returned if the frames fit in a `PutImage`
+    /// request, and [UploadStrategy::SocketChunked] if they don't.
+    pub fn detect(conn: &Connection, frame_size: usize) -> Result<UploadStrategy> {
+        if conn.extension_data(Extension::Shm).is_some() && probe_shm(conn)? {
+            return Ok(UploadStrategy::Shm);
+        }
+        if conn.fits_in_request(put_image_len(frame_size)) {
+            Ok(UploadStrategy::Socket)
+        } else {
+            Ok(UploadStrategy::SocketChunked)
+        }
+    }
+}
+
+/// Whether the server can attach a segment created by this client.
+///
+/// Only a connection error is returned as an error: the failure of the system calls
+/// or of the attachment tells that the memory can't be shared.
+fn probe_shm(conn: &Connection) -> Result<bool> {
+    // SAFETY: plain system calls, the segment is only used by the server
+    let shmid = unsafe { libc::shmget(libc::IPC_PRIVATE, PROBE_SIZE, libc::IPC_CREAT | 0o600) };
+    if shmid < 0 {
+        return Ok(false);
+    }
+    let shmseg = conn.generate_id();
+    let res = conn.send_and_check_request(&shm::Attach {
+        shmseg,
+        shmid: shmid as u32,
+        read_only: true,
+    });
+    // the segment is removed once detached by the server, or right away if not attached
+    // SAFETY: `shmid` is a segment created above
+    unsafe {
+        libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+    }
+    match res {
+        Ok(()) => {
+            conn.send_and_check_request(&shm::Detach { shmseg })
+                .context("Detach", || "probe segment")?;
+            Ok(true)
+        }
+        Err(crate::Error::Protocol(ProtocolError::X(..))) => Ok(false),
+        Err(err) => Err(err).context("Attach", || "probe segment"),
+    }
+}
+
+/// A shared segment created, mapped and attached by a [Presenter](super::Presenter).
+#[derive(Debug)]
+pub(crate) struct OwnedSegment {
+    pub(crate) segment: SharedSegment,
+}
+
+impl OwnedSegment {
+    /// Creates a segment of `size` bytes and attaches it to the server.
+    ///
+    /// Returns `None` if the segment can't be created or mapped by this client, or if the
+    /// server fails to attach it.
+    pub(crate) fn new(conn: &Connection, size: usize) -> Result<Option<OwnedSegment>> {
+        // SAFETY: plain system calls, the mapping is checked before use
+        let (shmid, addr) = unsafe {
+            let shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if shmid < 0 {
+                return Ok(None);
+            }
+            let addr = libc::shmat(shmid, ptr::null(), 0);
+            if addr as isize == -1 {
+                libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+                return Ok(None);
+            }
+            (shmid, addr as *mut u8)
+        };
+
+        let seg = conn.generate_id();
+        let res = conn.send_and_check_request(&shm::Attach {
+            shmseg: seg,
+            shmid: shmid as u32,
+            read_only: true,
+        });
+        // SAFETY: `shmid` is the segment created above, still mapped at `addr` on success
+        unsafe {
+            libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+            if res.is_err() {
+                libc::shmdt(addr as *const _);
+            }
+        }
+        match res {
+            Ok(()) => {}
+            Err(crate::Error::Protocol(ProtocolError::X(..))) => return Ok(None),
+            Err(err) => return Err(err).context("Attach", || format!("segment of {} bytes", size)),
+        }
+
+        Ok(Some(OwnedSegment {
+            // SAFETY: `addr` is mapped with `size` bytes until `free`
+            segment: unsafe { SharedSegment::new(seg, addr, size) },
+        }))
+    }
+
+    /// Detaches the segment from the server and unmaps it.
+    pub(crate) fn free(self, conn: &Connection) {
+        conn.send_request(&shm::Detach {
+            shmseg: self.segment.seg(),
+        });
+        // the server no longer reads the segment: each frame is checked
+        // SAFETY: the address returned by `shmat` in `new`
+        unsafe {
+            libc::shmdt(self.segment.addr as *const _);
+        }
+    }
+}
+
+/// The rows of the bands of a frame of `height` rows with `row_size` bytes of image
+/// data per row, such that each band fits in `max_len` bytes.
+///
+/// The bands have an even number of rows, for the vertically subsampled chroma planes,
+/// except the last one if `height` is odd. Returns `None` if two rows don't fit.
+pub(crate) fn bands(height: usize, row_size: usize, max_len: usize) -> Option<Vec<(usize, usize)>> {
+    let rows = max_len / row_size.max(1) / 2 * 2;
+    if rows == 0 {
+        return None;
+    }
+    Some(
+        (0..height)
+            .step_by(rows)
+            .map(|y| (y, (y + rows).min(height)))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bands() {
+        assert_eq!(bands(4, 10, 100), Some(vec![(0, 4)]));
+        assert_eq!(bands(25, 10, 100), Some(vec![(0, 10), (10, 20), (20, 25)]));
+        // an even number of rows
+        assert_eq!(bands(10, 10, 59), Some(vec![(0, 4), (4, 8), (8, 10)]));
+        assert_eq!(bands(10, 10, 19), None);
+        assert_eq!(bands(0, 10, 100), Some(vec![]));
+    }
+
+    fn mock_connection(
+        shm: bool,
+        attach_error: Option<u8>,
+        segments: std::sync::Arc<std::sync::Mutex<Vec<(u32, bool)>>>,
+    ) -> Connection {
+        use crate::mock;
+
+        const SHM_OPCODE: u8 = 141;
+
+        let handler = move |req: &mock::MockRequest| match req.opcode {
+            // QueryExtension
+            98 => match mock::extension_name(req) {
+                b"MIT-SHM" if shm => vec![mock::query_extension_reply(req, SHM_OPCODE, 90, 160)],
+                b"XVideo" => vec![mock::query_extension_reply(req, 140, 80, 150)],
+                _ => vec![mock::reply(req.seq, 0, &[])],
+            },
+            // shm::Attach
+            SHM_OPCODE if req.data[1] == 1 => match attach_error {
+                Some(code) => vec![mock::error(req.seq, code, req.u32_at(8), SHM_OPCODE)],
+                None => {
+                    segments.lock().unwrap().push((req.u32_at(4), true));
+                    vec![]
+                }
+            },
+            // shm::Detach
+            SHM_OPCODE if req.data[1] == 2 => {
+                let mut segments = segments.lock().unwrap();
+                let seg = segments
+                    .iter_mut()
+                    .find(|(seg, attached)| *seg == req.u32_at(4) && *attached)
+                    .expect("detach of an unknown segment");
+                seg.1 = false;
+                vec![]
+            }
+            _ => mock::default_response(req),
+        };
+        let extensions: &[Extension] = if shm {
+            &[Extension::Xv, Extension::Shm]
+        } else {
+            &[Extension::Xv]
+        };
+        mock::connect_with_extensions(handler, extensions)
+    }
+
+    #[test]
+    fn test_detect_local() {
+        use std::sync::{Arc, Mutex};
+
+        let segments = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(true, None, segments.clone());
+        assert_eq!(
+            UploadStrategy::detect(&conn, 1 << 20).unwrap(),
+            UploadStrategy::Shm
+        );
+        // the probe segment is detached
+        let segments = segments.lock().unwrap();
+        assert_eq!(segments.len(), 1);
+        assert!(!segments[0].1);
+    }
+
+    #[test]
+    fn test_detect_remote() {
+        use std::sync::{Arc, Mutex};
+
+        const BAD_ACCESS: u8 = 10;
+        const BAD_VALUE: u8 = 2;
+
+        for error in [BAD_ACCESS, BAD_VALUE] {
+            let segments = Arc::new(Mutex::new(Vec::new()));
+            let conn = mock_connection(true, Some(error), segments.clone());
+            // the mock server accepts requests of 256 KiB
+            assert_eq!(
+                UploadStrategy::detect(&conn, 1000).unwrap(),
+                UploadStrategy::Socket
+            );
+            assert_eq!(
+                UploadStrategy::detect(&conn, 1 << 20).unwrap(),
+                UploadStrategy::SocketChunked
+            );
+            assert!(segments.lock().unwrap().is_empty());
+            // the failed probes do not break the connection
+            assert_eq!(conn.has_error(), Ok(()));
+        }
+
+        // without MIT-SHM
+        let segments = Arc::new(Mutex::new(Vec::new()));
+        let conn = mock_connection(false, None, segments.clone());
+        assert_eq!(
+            UploadStrategy::detect(&conn, 1000).unwrap(),
+            UploadStrategy::Socket
+        );
+        assert!(segments.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Connection setup, resource creation and request checking.
+
+use xcb::{x, Cookie, Extension};
+
+fn create_window() -> xcb::Result<()> {
+    let (conn, screen_num) = xcb::Connection::connect(None)?;
+    let setup = conn.get_setup();
+    let screen = setup.roots().nth(screen_num as usize).unwrap();
+
+    let window: x::Window = conn.generate_id();
+    let cookie = conn.send_request_checked(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: screen.root(),
+        x: 0,
+        y: 0,
+        width: 150,
+        height: 150,
+        border_width: 0,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[
+            x::Cw::BackPixel(screen.white_pixel()),
+            x::Cw::EventMask(x::EventMask::EXPOSURE | x::EventMask::KEY_PRESS),
+        ],
+    });
+    conn.check_request(cookie)?;
+    conn.send_request(&x::MapWindow { window });
+    conn.flush()?;
+
+    let cookie = conn.send_request(&x::GetGeometry {
+        drawable: x::Drawable::Window(window),
+    });
+    let geometry = conn.wait_for_reply(cookie)?;
+    let _: (i16, i16, u16, u16) = (
+        geometry.x(),
+        geometry.y(),
+        geometry.width(),
+        geometry.height(),
+    );
+
+    // a speculative request
+    conn.send_request(&x::GetInputFocus {}).discard(&conn);
+    Ok(())
+}
+
+fn extensions() -> xcb::Result<()> {
+    let (conn, _) = xcb::Connection::connect_with_extensions(
+        None,
+        &[Extension::BigRequests],
+        &[Extension::XcMisc],
+    )?;
+    let _: bool = conn.active_extensions().any(|ext| ext == Extension::XcMisc);
+    let _: Option<u8> = conn
+        .extension_data(Extension::BigRequests)
+        .map(|data| data.major_opcode);
+    let _: Option<xcb::ConnError> = conn.take_error();
+    Ok(())
+}
+
+fn raw_connection() -> xcb::Result<()> {
+    let (conn, _) = xcb::Connection::connect(None)?;
+    let raw = conn.into_raw_conn();
+    // SAFETY: `raw` is a valid connection, disconnected below
+    let borrowed = unsafe { xcb::Connection::from_borrowed_raw_conn(raw) };
+    let _: bool = borrowed.owns_raw_conn();
+    drop(borrowed);
+    // SAFETY: `raw` is still connected
+    drop(unsafe { xcb::Connection::from_raw_conn(raw) });
+    Ok(())
+}
+
+#[cfg(feature = "xlib_xcb")]
+fn xlib_connection(dpy: *mut x11::xlib::Display) {
+    // SAFETY: `dpy` is a valid display, closed by the caller
+    let conn = unsafe { xcb::Connection::from_borrowed_xlib_display(dpy) };
+    assert!(!conn.owns_raw_conn());
+    conn.set_event_queue_owner(xcb::EventQueueOwner::Xcb);
+}
+
+#[test]
+fn connection_api() {
+    let _: fn() -> xcb::Result<()> = create_window;
+    let _: fn() -> xcb::Result<()> = extensions;
+    let _: fn() -> xcb::Result<()> = raw_connection;
+    #[cfg(feature = "xlib_xcb")]
+    let _: fn(*mut x11::xlib::Display) = xlib_connection;
+    let _: fn(&xcb::Connection) -> u32 = xcb::Connection::get_maximum_request_length;
+    let _: fn(&xcb::Connection, usize) -> bool = xcb::Connection::fits_in_request;
+    let _: unsafe fn(&xcb::Connection, &mut xcb::ffi::xcb_generic_error_t) -> xcb::ProtocolError =
+        xcb::Connection::resolve_error;
+    let _: fn(&xcb::Connection, std::time::Duration) -> xcb::Result<Option<xcb::Event>> =
+        xcb::Connection::wait_for_event_timeout;
+}
@@ -0,0 +1,42 @@
+//! Swaps of the buffers of a window with DRI2, and buffers of explicit formats.
+
+use xcb::swap::Scheduler;
+use xcb::{dri2, x};
+
+fn swap(conn: &xcb::Connection, window: x::Window) -> xcb::Result<()> {
+    let mut scheduler = dri2::Dri2Scheduler::new(conn, x::Drawable::Window(window));
+    let _: xcb::swap::Backend = scheduler.backend();
+    let _: u64 = scheduler.request_swap(1)?;
+    while scheduler.pending() > 0 {
+        let event = conn.wait_for_event()?;
+        let _: bool = scheduler.handle_event(&event);
+    }
+    for complete in scheduler.poll_completions() {
+        let _: (u64, u64, u64) = (complete.serial, complete.ust, complete.msc);
+    }
+    Ok(())
+}
+
+fn back_buffer(conn: &xcb::Connection, window: x::Window) -> xcb::Result<Option<u32>> {
+    let attachments = [
+        dri2::AttachFormat::new(dri2::Attachment::BufferBackLeft, 32),
+        dri2::AttachFormat::new(dri2::Attachment::BufferDepth, 24),
+    ];
+    let cookie = conn.send_request(&dri2::GetBuffersWithFormat {
+        drawable: x::Drawable::Window(window),
+        count: attachments.len() as u32,
+        attachments: &attachments,
+    });
+    let reply = conn.wait_for_reply(cookie)?;
+    Ok(reply
+        .buffers()
+        .iter()
+        .find(|buf| buf.attachment() == dri2::Attachment::BufferBackLeft)
+        .map(|buf| buf.name()))
+}
+
+#[test]
+fn dri2_api() {
+    let _: fn(&xcb::Connection, x::Window) -> xcb::Result<()> = swap;
+    let _: fn(&xcb::Connection, x::Window) -> xcb::Result<Option<u32>> = back_buffer;
+}
@@ -0,0 +1,42 @@
+//! Event dispatch.
+
+use xcb::x;
+
+fn event_loop(conn: &xcb::Connection) -> xcb::Result<()> {
+    loop {
+        match conn.wait_for_event()? {
+            xcb::Event::X(x::Event::Expose(ev)) => {
+                let _: (x::Window, u16) = (ev.window(), ev.count());
+            }
+            xcb::Event::X(x::Event::KeyPress(ev)) => {
+                let _: (x::Keycode, x::KeyButMask) = (ev.detail(), ev.state());
+            }
+            xcb::Event::X(x::Event::ClientMessage(ev)) => {
+                if let x::ClientMessageData::Data32([atom, ..]) = ev.data() {
+                    let _: u32 = atom;
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+        while let Some(_event) = conn.poll_for_event()? {}
+    }
+}
+
+fn send_event(conn: &xcb::Connection, window: x::Window, atom: x::Atom) -> xcb::Result<()> {
+    let event =
+        x::ClientMessageEvent::new(window, atom, x::ClientMessageData::Data32([0, 0, 0, 0, 0]));
+    conn.send_and_check_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(window),
+        event_mask: x::EventMask::NO_EVENT,
+        event: &event,
+    })?;
+    Ok(())
+}
+
+#[test]
+fn event_api() {
+    let _: fn(&xcb::Connection) -> xcb::Result<()> = event_loop;
+    let _: fn(&xcb::Connection, x::Window, x::Atom) -> xcb::Result<()> = send_event;
+}
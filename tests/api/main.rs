@@ -0,0 +1,16 @@
+//! Public API stability test.
+//!
+//! The modules of this test are small programs written against the documented public
+//! API. They need an X server and are only compiled: a breaking change of a signature they
+//! use makes this test fail to build, instead of silently breaking downstream code.
+//!
+//! The misuses that the API prevents on purpose are checked by the `compile_fail`
+//! examples of `tests/api/compile_fail.md`, which run with the doctests of the crate.
+
+mod connection;
+#[cfg(feature = "dri2")]
+mod dri2;
+mod event;
+mod property;
+#[cfg(feature = "xv")]
+mod xv;
@@ -0,0 +1,40 @@
+//! Property helpers.
+
+use xcb::{icccm, x};
+
+fn window_properties(conn: &xcb::Connection, window: x::Window) -> xcb::Result<()> {
+    match x::get_full_property(conn, window, x::ATOM_WM_NAME, x::ATOM_STRING)? {
+        x::PropertyValue::Value { data, .. } => {
+            let _: Vec<u8> = data;
+        }
+        x::PropertyValue::TypeMismatch {
+            actual_type,
+            actual_format,
+        } => {
+            let _: (x::Atom, u8) = (actual_type, actual_format);
+        }
+        x::PropertyValue::Missing => {}
+    }
+
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: x::ATOM_WM_NAME,
+        r#type: x::ATOM_STRING,
+        data: b"title",
+    })?;
+
+    let hints: Option<icccm::WmHints> = icccm::get_wm_hints(conn, window)?;
+    icccm::set_wm_hints(conn, window, &hints.unwrap_or_default())?;
+    let _: Option<icccm::WmState> = icccm::get_wm_state(conn, window)?;
+    let _: x::WindowSummary = x::window_summary(conn, window)?;
+    Ok(())
+}
+
+#[test]
+fn property_api() {
+    let _: fn(&xcb::Connection, x::Window) -> xcb::Result<()> = window_properties;
+    let _: fn(&[u8]) -> Option<x::WmClass> = x::WmClass::parse;
+    let _: fn(&xcb::Connection, x::Window, &[x::Window]) -> xcb::Result<()> =
+        icccm::set_wm_colormap_windows;
+}
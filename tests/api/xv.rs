@@ -0,0 +1,47 @@
+//! Walk of the XVideo adaptors and presentation of frames.
+
+use xcb::{x, xv};
+
+fn adaptors(conn: &xcb::Connection, window: x::Window) -> xcb::Result<Vec<xv::Port>> {
+    let cookie = conn.send_request(&xv::QueryAdaptors { window });
+    let reply = conn.wait_for_reply(cookie)?;
+    let mut ports = Vec::new();
+    for adaptor in reply.info() {
+        let _: &xcb::Lat1Str = adaptor.name();
+        let _: &[xv::Format] = adaptor.formats();
+        if adaptor.r#type().contains(xv::Type::IMAGE_MASK) {
+            ports.push(adaptor.base_id());
+        }
+    }
+    Ok(ports)
+}
+
+fn count_adaptors(reply: &xv::QueryAdaptorsReply) -> std::thread::JoinHandle<usize> {
+    // the clone owns a copy of the reply
+    let reply = reply.clone();
+    std::thread::spawn(move || reply.info().count())
+}
+
+fn present(
+    conn: &xcb::Connection,
+    port: xv::Port,
+    window: x::Window,
+    frame: xv::VideoFrame,
+) -> Result<(), xv::PresentError> {
+    let mut presenter = xv::Presenter::new(conn, port, x::Drawable::Window(window))?;
+    presenter.set_strategy(xv::UploadStrategy::detect(conn, 1920 * 1080 * 2)?);
+    let _: &[u32] = presenter.formats();
+    presenter.present_frame(frame)
+}
+
+#[test]
+fn xv_api() {
+    let _: fn(&xcb::Connection, x::Window) -> xcb::Result<Vec<xv::Port>> = adaptors;
+    let _: fn(&xv::QueryAdaptorsReply) -> std::thread::JoinHandle<usize> = count_adaptors;
+    let _: fn(
+        &xcb::Connection,
+        xv::Port,
+        x::Window,
+        xv::VideoFrame,
+    ) -> Result<(), xv::PresentError> = present;
+}
@@ -0,0 +1,43 @@
+//! Compile smoke test of the feature profiles.
+//!
+//! Each profile is checked by building this test with its features, e.g.:
+//! - `cargo test --no-default-features --test features`
+//! - `cargo test --no-default-features --features xv --test features`
+
+#[test]
+fn core_profile() {
+    // the core protocol and the mandatory extensions are always there
+    let _: fn(&xcb::Connection) -> u32 = xcb::Connection::get_maximum_request_length;
+    let _ = xcb::x::ATOM_ANY;
+    let _ = xcb::Extension::BigRequests;
+    let _ = xcb::Extension::XcMisc;
+    let _ = xcb::bigreq::Enable {};
+    let _ = xcb::xc_misc::GetXidRange {};
+}
+
+#[cfg(feature = "shm")]
+#[test]
+fn shm_profile() {
+    let _ = xcb::Extension::Shm;
+    let _ = xcb::shm::QueryVersion {};
+}
+
+#[cfg(feature = "xv")]
+#[test]
+fn xv_profile() {
+    // XVideo depends on MIT-SHM
+    let _ = xcb::Extension::Xv;
+    let _ = xcb::Extension::Shm;
+    let _ = xcb::xv::QueryExtension {};
+    let _ = std::mem::size_of::<xcb::xv::ShmPutImage>();
+}
+
+#[cfg(feature = "dri2")]
+#[test]
+fn dri2_profile() {
+    let _ = xcb::Extension::Dri2;
+    let _ = xcb::dri2::QueryVersion {
+        major_version: 1,
+        minor_version: 4,
+    };
+}
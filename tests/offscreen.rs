@@ -0,0 +1,115 @@
+//! Creates, resizes and drops `surface::Offscreen` surfaces on the X server of `DISPLAY`,
+//! and checks with the X-Resource extension that no server resource is leaked.
+//!
+//! The test is skipped if `DISPLAY` is not set or if the server does not support
+//! RENDER or X-Resource.
+
+#![cfg(all(feature = "render", feature = "res"))]
+
+use std::collections::BTreeMap;
+use xcb::{render, res, surface, x, Extension};
+
+/// Returns the number of resources of the client of `conn`, by type name.
+fn resource_counts(conn: &xcb::Connection) -> xcb::Result<BTreeMap<String, u32>> {
+    let xid = conn.get_setup().resource_id_base();
+    let cookie = conn.send_request(&res::QueryClientResources { xid });
+    let reply = conn.wait_for_reply(cookie)?;
+
+    let mut counts = BTreeMap::new();
+    for ty in reply.types() {
+        let cookie = conn.send_request(&x::GetAtomName {
+            atom: ty.resource_type,
+        });
+        let name = conn.wait_for_reply(cookie)?.name().to_string();
+        counts.insert(name, ty.count);
+    }
+    Ok(counts)
+}
+
+#[test]
+fn offscreen_resources() -> xcb::Result<()> {
+    if std::env::var_os("DISPLAY").is_none() {
+        eprintln!("DISPLAY is not set, skipping");
+        return Ok(());
+    }
+    let (conn, screen_num) =
+        xcb::Connection::connect_with_extensions(None, &[], &[Extension::Render, Extension::Res])?;
+    if conn.extension_data(Extension::Render).is_none()
+        || conn.extension_data(Extension::Res).is_none()
+    {
+        eprintln!("the server does not support RENDER or X-Resource, skipping");
+        return Ok(());
+    }
+    let cookie = conn.send_request(&res::QueryVersion {
+        client_major: 1,
+        client_minor: 2,
+    });
+    conn.wait_for_reply(cookie)?;
+    let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+    let root = x::Drawable::Window(screen.root());
+
+    let before = resource_counts(&conn)?;
+
+    let mut surface = surface::Offscreen::new(&conn, root, 64, 64)?;
+    assert_eq!(surface.depth(), screen.root_depth());
+    assert!(surface.picture().is_some());
+    surface.fill_rect(
+        screen.white_pixel(),
+        x::Rectangle {
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 64,
+        },
+    );
+    let created = resource_counts(&conn)?;
+    for (ty, count) in [("PIXMAP", 1), ("GC", 1), ("PICTURE", 1)] {
+        let before = before.get(ty).copied().unwrap_or(0);
+        assert_eq!(
+            created.get(ty).copied().unwrap_or(0),
+            before + count,
+            "{}",
+            ty
+        );
+    }
+
+    for size in [128, 32, 300] {
+        surface.resize(size, size)?;
+        assert_eq!(
+            resource_counts(&conn)?,
+            created,
+            "after resizing to {}",
+            size
+        );
+    }
+
+    // a surface of the depth of a pixmap, and composited onto the first one
+    let pixmap: x::Pixmap = conn.generate_id();
+    conn.send_and_check_request(&x::CreatePixmap {
+        depth: 32,
+        pid: pixmap,
+        drawable: root,
+        width: 1,
+        height: 1,
+    })?;
+    let argb = surface::Offscreen::new(&conn, x::Drawable::Pixmap(pixmap), 16, 16)?;
+    conn.send_and_check_request(&x::FreePixmap { pixmap })?;
+    assert_eq!(argb.depth(), 32);
+    argb.composite_to(
+        surface.picture().unwrap(),
+        render::PictOp::Over,
+        x::Rectangle {
+            x: 0,
+            y: 0,
+            width: 16,
+            height: 16,
+        },
+        x::Point { x: 8, y: 8 },
+    );
+    drop(argb);
+
+    drop(surface);
+    assert_eq!(resource_counts(&conn)?, before);
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Checks the RENDER capabilities of the X server of `DISPLAY` against its version.
+//!
+//! The test is skipped if `DISPLAY` is not set.
+
+#![cfg(feature = "render")]
+
+use xcb::{render, Extension};
+
+#[test]
+fn render_capabilities() -> xcb::Result<()> {
+    if std::env::var_os("DISPLAY").is_none() {
+        eprintln!("DISPLAY is not set, skipping");
+        return Ok(());
+    }
+    let (conn, screen_num) =
+        xcb::Connection::connect_with_extensions(None, &[], &[Extension::Render])?;
+    if conn.extension_data(Extension::Render).is_none() {
+        eprintln!("the server does not support RENDER, skipping");
+        return Ok(());
+    }
+    let render = render::Handle::new(&conn, screen_num)?;
+    let version = render.version();
+    eprintln!("RENDER {}.{}", version.0, version.1);
+
+    // the operators of a group are all supported or all unsupported
+    let groups = [
+        [render::PictOp::Clear, render::PictOp::Saturate],
+        [render::PictOp::DisjointClear, render::PictOp::ConjointXor],
+        [render::PictOp::Multiply, render::PictOp::HslLuminosity],
+    ];
+    for [first, last] in groups {
+        assert_eq!(render.supports_op(first), render.supports_op(last));
+        assert_eq!(
+            render.supports_op(first),
+            version >= first.required_version()
+        );
+    }
+    // a version supports the features of the previous ones
+    assert!(render.supports_op(render::PictOp::Over));
+    if render.supports_op(render::PictOp::Multiply) {
+        assert!(render.supports_gradients());
+        assert!(render.supports_op(render::PictOp::ConjointOver));
+    }
+    if render.supports_gradients() {
+        // every server supporting filters has these
+        assert!(render.supports_filters(&["nearest", "bilinear"])?);
+    }
+
+    let color = render::Color {
+        red: 0,
+        green: 0x8000,
+        blue: 0xffff,
+        alpha: 0xffff,
+    };
+    match render.create_solid_fill(color) {
+        Ok(picture) => {
+            assert!(render.supports_gradients());
+            conn.send_and_check_request(&render::FreePicture { picture })?;
+        }
+        Err(xcb::Error::Unsupported(_)) => assert!(!render.supports_gradients()),
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
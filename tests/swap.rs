@@ -0,0 +1,76 @@
+//! Swaps the buffers of a window with the backend supported by the X server of `DISPLAY`.
+//!
+//! The test is skipped if `DISPLAY` is not set.
+
+#![cfg(any(feature = "dri2", feature = "present"))]
+
+use xcb::{swap, x, Extension};
+
+#[test]
+fn swap_autodetect() -> xcb::Result<()> {
+    if std::env::var_os("DISPLAY").is_none() {
+        eprintln!("DISPLAY is not set, skipping");
+        return Ok(());
+    }
+    let optional = [
+        #[cfg(feature = "present")]
+        Extension::Present,
+        #[cfg(feature = "dri2")]
+        Extension::Dri2,
+    ];
+    let (conn, screen_num) = xcb::Connection::connect_with_extensions(None, &[], &optional)?;
+    let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+
+    let window: x::Window = conn.generate_id();
+    conn.send_and_check_request(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: screen.root(),
+        x: 0,
+        y: 0,
+        width: 64,
+        height: 64,
+        border_width: 0,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[],
+    })?;
+    let pixmap: x::Pixmap = conn.generate_id();
+    conn.send_and_check_request(&x::CreatePixmap {
+        depth: screen.root_depth(),
+        pid: pixmap,
+        drawable: x::Drawable::Window(window),
+        width: 64,
+        height: 64,
+    })?;
+    conn.send_and_check_request(&x::MapWindow { window })?;
+
+    let mut scheduler = match swap::autodetect(&conn, window)? {
+        Some(scheduler) => scheduler,
+        None => {
+            eprintln!("the server supports neither DRI2 nor Present, skipping");
+            return Ok(());
+        }
+    };
+    eprintln!("swapping with {:?}", scheduler.backend());
+    scheduler.set_pixmap(pixmap);
+
+    let mut serials = Vec::new();
+    for _ in 0..3 {
+        serials.push(scheduler.request_swap(0)?);
+    }
+    let mut completions = Vec::new();
+    while scheduler.pending() > 0 {
+        let event = conn.wait_for_event()?;
+        scheduler.handle_event(&event);
+        completions.extend(scheduler.poll_completions());
+    }
+
+    assert!(serials.windows(2).all(|s| s[0] < s[1]));
+    let last = completions.last().unwrap();
+    assert_eq!(last.serial, *serials.last().unwrap());
+    assert!(completions
+        .windows(2)
+        .all(|c| c[0].serial < c[1].serial && c[0].msc <= c[1].msc));
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Records input generated with XTEST on a counter window, and replays it with
+//! `xtest::Script` on the X server of `DISPLAY`.
+//!
+//! The test is skipped if `DISPLAY` is not set or if the server does not support XTEST.
+
+#![cfg(feature = "xtest")]
+
+use std::time::{Duration, Instant};
+use xcb::{x, xtest, Extension};
+
+/// Collects the input events of the window until `count` button releases are received,
+/// or a timeout.
+fn record(conn: &xcb::Connection, count: usize) -> xcb::Result<Vec<xcb::Event>> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut events = Vec::new();
+    let mut releases = 0;
+    while releases < count && Instant::now() < deadline {
+        match conn.poll_for_event()? {
+            Some(event) => match event {
+                xcb::Event::X(x::Event::ButtonRelease(_)) => {
+                    releases += 1;
+                    events.push(event);
+                }
+                xcb::Event::X(x::Event::ButtonPress(_))
+                | xcb::Event::X(x::Event::MotionNotify(_)) => events.push(event),
+                _ => {}
+            },
+            None => std::thread::sleep(Duration::from_millis(5)),
+        }
+    }
+    Ok(events)
+}
+
+/// The button presses of `events`, with their root coordinates.
+fn clicks(events: &[xcb::Event]) -> Vec<(u8, i16, i16)> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            xcb::Event::X(x::Event::ButtonPress(ev)) => {
+                Some((ev.detail(), ev.root_x(), ev.root_y()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn xtest_replay() -> xcb::Result<()> {
+    if std::env::var_os("DISPLAY").is_none() {
+        eprintln!("DISPLAY is not set, skipping");
+        return Ok(());
+    }
+    let (conn, screen_num) =
+        xcb::Connection::connect_with_extensions(None, &[], &[Extension::Test])?;
+    if conn.extension_data(Extension::Test).is_none() {
+        eprintln!("the server does not support XTEST, skipping");
+        return Ok(());
+    }
+    let screen = conn.get_setup().roots().nth(screen_num as usize).unwrap();
+    let root = screen.root();
+
+    // the counter window, not managed by a window manager to keep it at the origin
+    let window: x::Window = conn.generate_id();
+    conn.send_and_check_request(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: root,
+        x: 0,
+        y: 0,
+        width: 100,
+        height: 100,
+        border_width: 0,
+        class: x::WindowClass::InputOutput,
+        visual: screen.root_visual(),
+        value_list: &[
+            x::Cw::OverrideRedirect(true),
+            x::Cw::EventMask(
+                x::EventMask::BUTTON_PRESS
+                    | x::EventMask::BUTTON_RELEASE
+                    | x::EventMask::POINTER_MOTION,
+            ),
+        ],
+    })?;
+    conn.send_and_check_request(&x::MapWindow { window })?;
+
+    let fake = |r#type, detail, root_x, root_y| {
+        conn.send_and_check_request(&xtest::FakeInput {
+            r#type,
+            detail,
+            time: x::CURRENT_TIME,
+            root,
+            root_x,
+            root_y,
+            deviceid: 0,
+        })
+    };
+    for (button, pos) in [(1, (20, 30)), (3, (60, 70))] {
+        fake(6, 0, pos.0, pos.1)?;
+        fake(4, button, 0, 0)?;
+        std::thread::sleep(Duration::from_millis(20));
+        fake(5, button, 0, 0)?;
+    }
+    let recorded = record(&conn, 2)?;
+    assert_eq!(clicks(&recorded), vec![(1, 20, 30), (3, 60, 70)]);
+
+    let mut script = xtest::Script::from_events(&recorded);
+    assert!(script.duration() >= Duration::from_millis(40));
+    script.screen_size = Some((screen.width_in_pixels(), screen.height_in_pixels()));
+
+    // move away, and replay twice as fast
+    fake(6, 0, 90, 90)?;
+    script.replay(&conn, 2.0)?;
+    let replayed = record(&conn, 2)?;
+    assert_eq!(clicks(&replayed), clicks(&recorded));
+
+    Ok(())
+}